@@ -0,0 +1,70 @@
+
+/**
+ * A fixed-size dense bit set, used by the dataflow
+ * analyses to hold sets of numbered definitions.
+ */
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct BitSet {
+    words: Vec<u64>,
+    size: u32
+}
+
+impl BitSet {
+    pub(crate) fn new(size: u32) -> BitSet {
+        let nwords = (size as usize).div_ceil(64);
+        BitSet { words: vec![0; nwords], size }
+    }
+
+    pub(crate) fn insert(&mut self, idx: u32) -> bool {
+        debug_assert!(idx < self.size);
+        let (w, b) = ((idx / 64) as usize, idx % 64);
+        let had = (self.words[w] >> b) & 1 == 1;
+        self.words[w] |= 1 << b;
+        !had
+    }
+    pub(crate) fn remove(&mut self, idx: u32) -> bool {
+        debug_assert!(idx < self.size);
+        let (w, b) = ((idx / 64) as usize, idx % 64);
+        let had = (self.words[w] >> b) & 1 == 1;
+        self.words[w] &= !(1 << b);
+        had
+    }
+    pub(crate) fn contains(&self, idx: u32) -> bool {
+        debug_assert!(idx < self.size);
+        let (w, b) = ((idx / 64) as usize, idx % 64);
+        (self.words[w] >> b) & 1 == 1
+    }
+
+    // Union `other` into this set, returning whether
+    // this set changed.
+    pub(crate) fn union_with(&mut self, other: &BitSet) -> bool {
+        debug_assert!(self.size == other.size);
+        let mut changed = false;
+        for (w, o) in self.words.iter_mut().zip(&other.words) {
+            let nw = *w | *o;
+            changed |= nw != *w;
+            *w = nw;
+        }
+        changed
+    }
+    pub(crate) fn subtract(&mut self, other: &BitSet) {
+        debug_assert!(self.size == other.size);
+        for (w, o) in self.words.iter_mut().zip(&other.words) {
+            *w &= !*o;
+        }
+    }
+
+    pub(crate) fn count(&self) -> u32 {
+        self.words.iter().map(|w| w.count_ones()).sum()
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item=u32> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &w)| {
+            (0 .. 64_u32).filter(move |b| (w >> b) & 1 == 1)
+                         .map(move |b| (i as u32) * 64 + b)
+        })
+    }
+}
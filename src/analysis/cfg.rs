@@ -0,0 +1,51 @@
+
+use crate::block::BlockId;
+use crate::graph::Graph;
+
+/**
+ * The control flow graph of a finished graph, with
+ * successor and predecessor lists indexed by block id.
+ *
+ * Edges are kept with multiplicity: a branch with both
+ * targets naming the same block contributes two edges,
+ * each carrying its own phi arguments.
+ */
+pub struct Cfg {
+    succs: Vec<Vec<BlockId>>,
+    preds: Vec<Vec<BlockId>>,
+    rpo: Vec<BlockId>
+}
+
+impl Cfg {
+    pub(crate) fn compute(graph: &Graph) -> Cfg {
+        let nblocks = graph.block_store().total_blocks();
+        let mut succs = vec![Vec::new(); nblocks];
+        let mut preds = vec![Vec::new(); nblocks];
+        let mut rpo = Vec::with_capacity(nblocks);
+
+        for block in graph.block_store().iter_rpo_blocks() {
+            rpo.push(block.id());
+            let end = graph.block_end(block);
+            for target in end.targets_iter() {
+                let to = target.block_id();
+                succs[block.id().as_u32() as usize].push(to);
+                preds[to.as_u32() as usize].push(block.id());
+            }
+        }
+
+        Cfg { succs, preds, rpo }
+    }
+
+    pub fn num_blocks(&self) -> usize { self.succs.len() }
+
+    pub fn succs(&self, block: BlockId) -> &[BlockId] {
+        &self.succs[block.as_u32() as usize]
+    }
+    pub fn preds(&self, block: BlockId) -> &[BlockId] {
+        &self.preds[block.as_u32() as usize]
+    }
+
+    // The blocks in reverse post order (the order
+    // in which they were specified).
+    pub fn rpo(&self) -> &[BlockId] { &self.rpo }
+}
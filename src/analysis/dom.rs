@@ -0,0 +1,174 @@
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::analysis::Cfg;
+
+/**
+ * The dominator tree of a graph, computed with the
+ * iterative algorithm of Cooper, Harvey and Kennedy.
+ *
+ * The numbering used is a depth-first reverse post
+ * order from the start block, rather than the
+ * specification order, so it stays correct for graphs
+ * that were not produced by the builder.  Blocks not
+ * reachable from the start block have no dominators.
+ */
+pub struct DomTree {
+    // Immediate dominator of each block, by block id.
+    // The start block is its own immediate dominator.
+    idom: Vec<Option<BlockId>>,
+
+    // Depth-first RPO number of each reachable block.
+    number: Vec<u32>
+}
+
+impl DomTree {
+    const UNREACHED: u32 = u32::MAX;
+
+    pub(crate) fn compute(graph: &Graph, cfg: &Cfg) -> DomTree {
+        let nblocks = cfg.num_blocks();
+        let start = graph.block_store().start_block_id();
+        let rpo = depth_first_rpo(cfg, start);
+
+        let mut number = vec![Self::UNREACHED; nblocks];
+        for (i, b) in rpo.iter().enumerate() {
+            number[b.as_u32() as usize] = i as u32;
+        }
+
+        let mut idom: Vec<Option<BlockId>> = vec![None; nblocks];
+        idom[start.as_u32() as usize] = Some(start);
+
+        let intersect = |idom: &[Option<BlockId>],
+                         mut a: BlockId, mut b: BlockId| {
+            let num = |x: BlockId| number[x.as_u32() as usize];
+            while a != b {
+                while num(a) > num(b) {
+                    a = idom[a.as_u32() as usize].unwrap();
+                }
+                while num(b) > num(a) {
+                    b = idom[b.as_u32() as usize].unwrap();
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in rpo.iter().skip(1) {
+                let mut new_idom: Option<BlockId> = None;
+                for &p in cfg.preds(b) {
+                    if idom[p.as_u32() as usize].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                      None => p,
+                      Some(cur) => intersect(&idom, p, cur)
+                    });
+                }
+                let bi = b.as_u32() as usize;
+                if new_idom.is_some() && idom[bi] != new_idom {
+                    idom[bi] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        DomTree { idom, number }
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.number[block.as_u32() as usize] != Self::UNREACHED
+    }
+
+    // The depth-first RPO number of a reachable block.
+    pub(crate) fn rpo_number(&self, block: BlockId) -> Option<u32> {
+        Some(self.number[block.as_u32() as usize])
+          .filter(|&n| n != Self::UNREACHED)
+    }
+
+    // The immediate dominator of a block.  None for the
+    // start block and for unreachable blocks.
+    pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+        let bi = block.as_u32() as usize;
+        self.idom[bi].filter(|&d| d != block)
+    }
+
+    // Whether `a` dominates `b`.  Every reachable block
+    // dominates itself; unreachable blocks dominate and
+    // are dominated by nothing.
+    pub fn dominates(&self, a: BlockId, b: BlockId) -> bool {
+        if !self.is_reachable(a) || !self.is_reachable(b) {
+            return false;
+        }
+        let mut cur = b;
+        loop {
+            if cur == a {
+                return true;
+            }
+            match self.idom(cur) {
+              Some(d) => cur = d,
+              None => return false
+            }
+        }
+    }
+
+    // Whether the instruction `a` dominates the
+    // instruction `b`: its block dominates b's, and
+    // within one block it comes no later in the stream.
+    // Every instruction of a reachable block dominates
+    // itself.
+    pub fn dominates_def(&self, graph: &Graph, a: InstrId, b: InstrId)
+      -> bool
+    {
+        match (graph.block_of_instr(a), graph.block_of_instr(b)) {
+          (Some(a_block), Some(b_block)) => {
+            self.instr_dominates(a_block, a, b_block, b)
+          }
+          _ => false
+        }
+    }
+
+    // As `dominates_def`, with the blocks of both
+    // instructions already known.
+    pub(crate) fn instr_dominates(&self, a_block: BlockId, a: InstrId,
+                                  b_block: BlockId, b: InstrId)
+      -> bool
+    {
+        if a_block == b_block {
+            self.is_reachable(a_block) && a <= b
+        } else {
+            self.dominates(a_block, b_block)
+        }
+    }
+}
+
+// Number the blocks reachable from `start` in reverse
+// post order of a depth-first walk.
+fn depth_first_rpo(cfg: &Cfg, start: BlockId) -> Vec<BlockId> {
+    let mut visited = vec![false; cfg.num_blocks()];
+    let mut post = Vec::new();
+
+    // Each stack entry is a block and the index of the
+    // next successor to visit.
+    let mut stack = vec![(start, 0)];
+    visited[start.as_u32() as usize] = true;
+    while let Some(&mut (b, ref mut next)) = stack.last_mut() {
+        let succs = cfg.succs(b);
+        if *next < succs.len() {
+            let s = succs[*next];
+            *next += 1;
+            if !visited[s.as_u32() as usize] {
+                visited[s.as_u32() as usize] = true;
+                stack.push((s, 0));
+            }
+        } else {
+            post.push(b);
+            stack.pop();
+        }
+    }
+
+    post.reverse();
+    post
+}
@@ -0,0 +1,174 @@
+
+use std::collections::HashMap;
+
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::Op;
+use crate::ir_types::IrTypeId;
+
+/**
+ * How far an allocation-like value gets out of the
+ * graph: not at all; only into the snapshots of side
+ * exits, which must materialize it when they are
+ * taken; or all the way, by being stored to memory or
+ * returned.  A pointer passed to a call escapes
+ * as though stored.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+pub enum Escape { None, OnExit, Stored, Returned }
+
+/**
+ * Escape analysis of the graph's allocation-like
+ * values.
+ *
+ * Pointers (`PtrInt` values) that are not derived from
+ * other pointers are the sites: params, such as stack
+ * slots handed in by the embedder, and any op that
+ * makes a new pointer.  A pointer derived from others,
+ * by a phi, a check, a copy, arithmetic, or the
+ * address of a member, may point into any of their
+ * sites.  A site escapes with the furthest escaping
+ * use of a pointer into it.  Comparing a pointer, or
+ * loading or storing through it, lets nothing escape.
+ *
+ * A site that does not escape can be scalarized, and
+ * one that escapes only on exit need only be kept in
+ * the snapshots of the exits that capture it.
+ */
+pub struct EscapeInfo {
+    // Each site and how it escapes, in stream order.
+    sites: Vec<(InstrId, Escape)>,
+
+    // The sites each pointer may point into, in stream
+    // order.
+    points_to: HashMap<InstrId, Vec<InstrId>>
+}
+
+impl EscapeInfo {
+    pub(crate) fn compute(graph: &Graph) -> EscapeInfo {
+        let store = graph.block_store();
+
+        // The pointers, and the phis of each block.
+        let mut pointers = Vec::new();
+        let mut phis = vec![Vec::new(); store.total_blocks()];
+        for block in store.iter_rpo_blocks() {
+            let bi = block.id().as_u32() as usize;
+            for (i, info) in graph.block_instrs(block).enumerate() {
+                let id = info.defn().instr_id();
+                if (i as u32) < block.num_phis() {
+                    phis[bi].push(id);
+                }
+                let op = info.op();
+                if op.defines_value()
+                   && op.out_type() == Some(IrTypeId::PtrInt)
+                {
+                    pointers.push(id);
+                }
+            }
+        }
+        pointers.sort_unstable();
+        let is_pointer = |id: InstrId| {
+            pointers.binary_search(&id).is_ok()
+        };
+
+        let mut sites = Vec::new();
+        // The pointers derived from each pointer.
+        let mut derived: HashMap<InstrId, Vec<InstrId>> =
+          HashMap::new();
+        // The furthest escaping direct use of each pointer.
+        let mut uses: HashMap<InstrId, Escape> = HashMap::new();
+        let mut escape_by = |value: InstrId, escape: Escape| {
+            let e = uses.entry(value).or_insert(Escape::None);
+            *e = (*e).max(escape);
+        };
+
+        for block in store.iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                let id = info.defn().instr_id();
+                let inputs: Vec<InstrId> =
+                  info.inputs_iter().map(|d| d.instr_id()).collect();
+                let mut derive_from = |from: &[InstrId]| {
+                    for &p in from.iter().filter(|&&p| is_pointer(p)) {
+                        derived.entry(p).or_default().push(id);
+                    }
+                };
+                match info.op() {
+                  Op::Phi(_) => {}
+                  Op::NullCheck(_) | Op::Copy(_) => {
+                    derive_from(&inputs)
+                  }
+                  Op::PtrAdd(_) => derive_from(&inputs[.. 1]),
+                  Op::BoundsCheck(_) | Op::Bini(_)
+                    if is_pointer(id) => derive_from(&inputs),
+                  Op::Ret(_) => {
+                    inputs.iter().for_each(|&v| {
+                        escape_by(v, Escape::Returned)
+                    })
+                  }
+                  Op::Store(_) => escape_by(inputs[1], Escape::Stored),
+                  Op::Call(_) => {
+                    inputs.iter().for_each(|&v| {
+                        escape_by(v, Escape::Stored)
+                    })
+                  }
+                  _ if is_pointer(id) => sites.push(id),
+                  _ => {}
+                }
+
+                for target in info.targets_iter() {
+                    let bi = target.block_id().as_u32() as usize;
+                    let args = target.phi_args().map(|d| d.instr_id());
+                    for (&phi, arg) in phis[bi].iter().zip(args) {
+                        if is_pointer(arg) {
+                            derived.entry(arg).or_default().push(phi);
+                        }
+                    }
+                }
+
+                if let Some(snapshot) = graph.snapshot(id) {
+                    for &(_, value) in snapshot.entries() {
+                        escape_by(value, Escape::OnExit);
+                    }
+                }
+            }
+        }
+
+        // Walk the pointers derived from each site.
+        let mut points_to: HashMap<InstrId, Vec<InstrId>> =
+          HashMap::new();
+        let mut escapes = Vec::with_capacity(sites.len());
+        for &site in &sites {
+            let mut escape = Escape::None;
+            let mut stack = vec![site];
+            while let Some(p) = stack.pop() {
+                let into = points_to.entry(p).or_default();
+                if into.last() == Some(&site) {
+                    continue;
+                }
+                into.push(site);
+                if let Some(&e) = uses.get(&p) {
+                    escape = escape.max(e);
+                }
+                if let Some(next) = derived.get(&p) {
+                    stack.extend(next.iter().cloned());
+                }
+            }
+            escapes.push((site, escape));
+        }
+        EscapeInfo { sites: escapes, points_to }
+    }
+
+    pub fn sites(&self) -> &[(InstrId, Escape)] { &self.sites }
+
+    // How the site escapes, or None if it is not a site.
+    pub fn escape(&self, site: InstrId) -> Option<Escape> {
+        self.sites.binary_search_by_key(&site, |e| e.0)
+          .ok().map(|i| self.sites[i].1)
+    }
+
+    // The sites a pointer may point into.
+    pub fn points_to(&self, pointer: InstrId) -> &[InstrId] {
+        self.points_to.get(&pointer).map_or(&[], |s| &s[..])
+    }
+}
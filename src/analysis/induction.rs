@@ -0,0 +1,283 @@
+
+use std::collections::HashMap;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::{ Op, ConstOp, BiniKind };
+use crate::analysis::{ Cfg, DomTree, LoopInfo, Loop };
+use crate::ir_types::IrTypeId;
+
+/**
+ * A basic induction variable: a phi of a loop header
+ * that takes any value on entry to the loop, and the
+ * phi plus the same constant step over every back
+ * edge.
+ */
+pub struct BasicIv {
+    phi: InstrId,
+    header: BlockId,
+    ty: IrTypeId,
+    step: u64,
+
+    // The edges into the header, as the block ending
+    // in each, the index of the target and the value
+    // passed: from outside the loop, and the back edges.
+    entries: Vec<(BlockId, u32, InstrId)>,
+    latches: Vec<(BlockId, u32, InstrId)>
+}
+
+impl BasicIv {
+    pub fn phi(&self) -> InstrId { self.phi }
+    pub fn header(&self) -> BlockId { self.header }
+    pub fn ty(&self) -> IrTypeId { self.ty }
+
+    // The step, wrapped to the width of the type.
+    pub fn step(&self) -> u64 { self.step }
+
+    pub fn entries(&self) -> &[(BlockId, u32, InstrId)] {
+        &self.entries
+    }
+    pub fn latches(&self) -> &[(BlockId, u32, InstrId)] {
+        &self.latches
+    }
+}
+
+/**
+ * A derived induction variable: a value of the loop
+ * computed, wherever it is computed, as a basic
+ * induction variable times a constant scale, plus
+ * an optional loop-invariant base and a constant
+ * offset, wrapping at the width of the type.
+ */
+pub struct DerivedIv {
+    value: InstrId,
+    basic: InstrId,
+    scale: u64,
+    base: Option<InstrId>,
+    offset: u64
+}
+
+impl DerivedIv {
+    pub fn value(&self) -> InstrId { self.value }
+
+    // The phi of the basic induction variable.
+    pub fn basic(&self) -> InstrId { self.basic }
+
+    pub fn scale(&self) -> u64 { self.scale }
+    pub fn base(&self) -> Option<InstrId> { self.base }
+    pub fn offset(&self) -> u64 { self.offset }
+}
+
+/**
+ * The induction variables of the graph's loops.
+ *
+ * Only `Int32` and `Int64` values are induction
+ * variables.  Derived variables are found in RPO
+ * through `add` and `sub` of constants, `add` of a
+ * value defined outside the loop that dominates its
+ * header, and `mul` by constants; the increments of
+ * a basic variable are derived variables with a scale
+ * of 1.  Loops entered or continued from unreachable
+ * blocks have none.
+ */
+pub struct InductionVars {
+    basic: Vec<BasicIv>,
+    derived: Vec<DerivedIv>
+}
+
+// The affine form of a derived variable.
+#[derive(Clone, Copy)]
+struct Affine {
+    basic: InstrId,
+    scale: u64,
+    base: Option<InstrId>,
+    offset: u64
+}
+
+pub(crate) fn wrap(ty: IrTypeId, v: u64) -> u64 {
+    match ty {
+      IrTypeId::Int32 => v & u32::MAX as u64,
+      _ => v
+    }
+}
+
+fn const_int(op: &Op) -> Option<u64> {
+    match *op {
+      Op::Const(ConstOp::Int32(i)) => Some(i as u64),
+      Op::Const(ConstOp::Int64(i)) => Some(i),
+      _ => None
+    }
+}
+
+impl InductionVars {
+    pub(crate) fn compute(graph: &Graph, cfg: &Cfg, dom: &DomTree,
+                          loops: &LoopInfo)
+      -> InductionVars
+    {
+        let store = graph.block_store();
+
+        // The block and op of each instruction, and the
+        // edges into each block.
+        let mut blocks: HashMap<InstrId, BlockId> = HashMap::new();
+        let mut ops: HashMap<InstrId, (Op, Vec<InstrId>)> =
+          HashMap::new();
+        let mut edges: Vec<Vec<(BlockId, u32, Vec<InstrId>)>> =
+          vec![Vec::new(); cfg.num_blocks()];
+        for block in store.iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                let id = info.defn().instr_id();
+                blocks.insert(id, block.id());
+                let inputs =
+                  info.inputs_iter().map(|d| d.instr_id()).collect();
+                ops.insert(id, (info.op().clone(), inputs));
+                for (i, target) in info.targets_iter().enumerate() {
+                    let args = target.phi_args()
+                                     .map(|d| d.instr_id()).collect();
+                    edges[target.block_id().as_u32() as usize]
+                      .push((block.id(), i as u32, args));
+                }
+            }
+        }
+        let konst = |v: InstrId| const_int(&ops[&v].0);
+
+        let mut basic = Vec::new();
+        let mut derived = Vec::new();
+        for lp in loops.loops() {
+            let header = lp.header();
+            let into = &edges[header.as_u32() as usize];
+            if into.iter().any(|e| !dom.is_reachable(e.0)) {
+                continue;
+            }
+
+            let mut affine: HashMap<InstrId, Affine> = HashMap::new();
+            let head = graph.block(header);
+            let phis = graph.block_instrs(head)
+                            .take(head.num_phis() as usize);
+            for (j, info) in phis.enumerate() {
+                let phi = info.defn().instr_id();
+                let ty = match info.op().out_type() {
+                  Some(ty @ IrTypeId::Int32)
+                    | Some(ty @ IrTypeId::Int64) => ty,
+                  _ => continue
+                };
+                let (entries, latches): (Vec<_>, Vec<_>) =
+                  into.iter().map(|(b, i, args)| (*b, *i, args[j]))
+                      .partition(|e| !lp.contains(e.0));
+                let steps: Vec<Option<u64>> = latches.iter().map(|e| {
+                    let (op, inputs) = &ops[&e.2];
+                    let kind = match op {
+                      Op::Bini(op) => op.kind(),
+                      _ => return None
+                    };
+                    match (kind, inputs[0] == phi, inputs[1] == phi) {
+                      (BiniKind::Add, true, _) => konst(inputs[1]),
+                      (BiniKind::Add, _, true) => konst(inputs[0]),
+                      (BiniKind::Sub, true, _) => {
+                        konst(inputs[1]).map(|c| c.wrapping_neg())
+                      }
+                      _ => None
+                    }.map(|c| wrap(ty, c))
+                }).collect();
+                let step = match steps.first() {
+                  Some(&Some(step)) if step != 0 => step,
+                  _ => continue
+                };
+                if entries.is_empty()
+                   || steps.iter().any(|&s| s != Some(step))
+                {
+                    continue;
+                }
+                affine.insert(phi, Affine {
+                    basic: phi, scale: 1, base: None, offset: 0
+                });
+                basic.push(BasicIv {
+                    phi, header, ty, step, entries, latches
+                });
+            }
+            if affine.is_empty() {
+                continue;
+            }
+
+            let invariant = |v: InstrId| {
+                let b = blocks[&v];
+                !lp.contains(b) && dom.dominates(b, header)
+            };
+            Self::derive(graph, lp, &ops, &konst, &invariant,
+                         &mut affine, &mut derived);
+        }
+        derived.sort_unstable_by_key(|d: &DerivedIv| d.value);
+        InductionVars { basic, derived }
+    }
+
+    // Find the derived variables of a loop, from the
+    // affine forms of its basic variables.
+    fn derive(graph: &Graph, lp: &Loop,
+              ops: &HashMap<InstrId, (Op, Vec<InstrId>)>,
+              konst: &dyn Fn(InstrId) -> Option<u64>,
+              invariant: &dyn Fn(InstrId) -> bool,
+              affine: &mut HashMap<InstrId, Affine>,
+              derived: &mut Vec<DerivedIv>)
+    {
+        for &b in lp.blocks() {
+            for info in graph.block_instrs(graph.block(b)) {
+                let id = info.defn().instr_id();
+                let (op, inputs) = &ops[&id];
+                let (kind, ty) = match op {
+                  Op::Bini(op) => (op.kind(), op.tyid()),
+                  _ => continue
+                };
+                let (x, y) = (inputs[0], inputs[1]);
+                let forms = (affine.get(&x), affine.get(&y));
+                let (a, other) = match forms {
+                  (Some(&a), None) => (a, y),
+                  (None, Some(&a)) if kind != BiniKind::Sub => (a, x),
+                  _ => continue
+                };
+                let form = match (kind, konst(other)) {
+                  (BiniKind::Add, Some(c)) => Affine {
+                      offset: wrap(ty, a.offset.wrapping_add(c)), ..a
+                  },
+                  (BiniKind::Sub, Some(c)) => Affine {
+                      offset: wrap(ty, a.offset.wrapping_sub(c)), ..a
+                  },
+                  (BiniKind::Add, None)
+                    if a.base.is_none() && invariant(other) => Affine {
+                      base: Some(other), ..a
+                  },
+                  (BiniKind::Mul, Some(c)) if a.base.is_none() => {
+                    Affine {
+                        scale: wrap(ty, a.scale.wrapping_mul(c)),
+                        offset: wrap(ty, a.offset.wrapping_mul(c)),
+                        ..a
+                    }
+                  }
+                  _ => continue
+                };
+                affine.insert(id, form);
+                derived.push(DerivedIv {
+                    value: id,
+                    basic: form.basic,
+                    scale: form.scale,
+                    base: form.base,
+                    offset: form.offset
+                });
+            }
+        }
+    }
+
+    pub fn basic(&self) -> &[BasicIv] { &self.basic }
+
+    // The derived variables, in stream order.
+    pub fn derived(&self) -> &[DerivedIv] { &self.derived }
+
+    // The basic variable of a header phi, if it is one.
+    pub fn basic_of(&self, phi: InstrId) -> Option<&BasicIv> {
+        self.basic.iter().find(|iv| iv.phi == phi)
+    }
+
+    // A derived variable of a value, if it is one.
+    pub fn derived_of(&self, value: InstrId) -> Option<&DerivedIv> {
+        self.derived.iter().find(|d| d.value == value)
+    }
+}
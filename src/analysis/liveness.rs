@@ -0,0 +1,143 @@
+
+use crate::block::{ Block, BlockId };
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::analysis::{ BitSet, Cfg };
+
+/**
+ * Block-level liveness of definitions.
+ *
+ * Every value-defining instruction is given a dense
+ * index (in stream order) and the live-in and live-out
+ * sets of each block are kept as bit sets over those
+ * indices.
+ *
+ * Phi definitions are considered defined at the head
+ * of their block, and phi arguments are considered used
+ * at the exit of the predecessor passing them, so they
+ * are live-out of the predecessor but not live-in of the
+ * target block.
+ */
+pub struct Liveness {
+    // The defining instruction of each dense index.
+    defs: Vec<InstrId>,
+
+    // Live sets, indexed by block id.
+    live_in: Vec<BitSet>,
+    live_out: Vec<BitSet>
+}
+
+impl Liveness {
+    pub(crate) fn compute(graph: &Graph, cfg: &Cfg) -> Liveness {
+        let mut defs = Vec::new();
+        for block in graph.block_store().iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                if info.op().defines_value() {
+                    defs.push(info.defn().instr_id());
+                }
+            }
+        }
+        defs.sort_unstable();
+
+        let ndefs = defs.len() as u32;
+        let nblocks = cfg.num_blocks();
+        let empty = BitSet::new(ndefs);
+        let mut gen = vec![empty.clone(); nblocks];
+        let mut kill = vec![empty.clone(); nblocks];
+        let mut phi_uses = vec![empty.clone(); nblocks];
+
+        let index_of = |id: InstrId| -> u32 {
+            defs.binary_search(&id)
+                .expect("Use of non-value instruction.") as u32
+        };
+
+        for block in graph.block_store().iter_rpo_blocks() {
+            let bi = block.id().as_u32() as usize;
+            for info in graph.block_instrs(block) {
+                for inp in info.inputs_iter() {
+                    let idx = index_of(inp.instr_id());
+                    if !kill[bi].contains(idx) {
+                        gen[bi].insert(idx);
+                    }
+                }
+                for target in info.targets_iter() {
+                    for arg in target.phi_args() {
+                        phi_uses[bi].insert(index_of(arg.instr_id()));
+                    }
+                }
+                if info.op().defines_value() {
+                    kill[bi].insert(index_of(info.defn().instr_id()));
+                }
+            }
+        }
+
+        let mut live_in = vec![empty.clone(); nblocks];
+        let mut live_out = phi_uses;
+
+        // Iterate to a fixed point, visiting blocks in
+        // post order so successors are usually settled
+        // before their predecessors.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &b in cfg.rpo().iter().rev() {
+                let bi = b.as_u32() as usize;
+                for &s in cfg.succs(b) {
+                    let si = s.as_u32() as usize;
+                    let succ_in = live_in[si].clone();
+                    live_out[bi].union_with(&succ_in);
+                }
+                let mut new_in = live_out[bi].clone();
+                new_in.subtract(&kill[bi]);
+                new_in.union_with(&gen[bi]);
+                if new_in != live_in[bi] {
+                    live_in[bi] = new_in;
+                    changed = true;
+                }
+            }
+        }
+
+        Liveness { defs, live_in, live_out }
+    }
+
+    /** The number of value definitions in the graph. */
+    pub fn num_defs(&self) -> u32 { self.defs.len() as u32 }
+
+    pub fn live_in(&self, block: BlockId)
+      -> impl Iterator<Item=InstrId> + '_
+    {
+        let set = &self.live_in[block.as_u32() as usize];
+        set.iter().map(move |i| self.defs[i as usize])
+    }
+    pub fn live_out(&self, block: BlockId)
+      -> impl Iterator<Item=InstrId> + '_
+    {
+        let set = &self.live_out[block.as_u32() as usize];
+        set.iter().map(move |i| self.defs[i as usize])
+    }
+
+    pub fn is_live_in(&self, block: BlockId, def: InstrId) -> bool {
+        self.def_index(def).is_some_and(|i| {
+            self.live_in[block.as_u32() as usize].contains(i)
+        })
+    }
+    pub fn is_live_out(&self, block: BlockId, def: InstrId) -> bool {
+        self.def_index(def).is_some_and(|i| {
+            self.live_out[block.as_u32() as usize].contains(i)
+        })
+    }
+
+    // The dense index for a definition, if it is one.
+    pub(crate) fn def_index(&self, def: InstrId) -> Option<u32> {
+        self.defs.binary_search(&def).ok().map(|i| i as u32)
+    }
+    pub(crate) fn def_at(&self, idx: u32) -> InstrId {
+        self.defs[idx as usize]
+    }
+    pub(crate) fn live_out_set(&self, block: &Block) -> &BitSet {
+        &self.live_out[block.id().as_u32() as usize]
+    }
+    pub(crate) fn live_in_set(&self, block: &Block) -> &BitSet {
+        &self.live_in[block.id().as_u32() as usize]
+    }
+}
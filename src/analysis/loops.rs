@@ -0,0 +1,124 @@
+
+use crate::block::BlockId;
+use crate::graph::Graph;
+use crate::analysis::Cfg;
+
+/**
+ * A natural loop: a loop header together with every
+ * block that can reach one of the header's back edges
+ * without passing through the header.
+ */
+pub struct Loop {
+    header: BlockId,
+
+    // Blocks in the loop (including the header),
+    // in RPO.
+    blocks: Vec<BlockId>,
+
+    // Index of the innermost enclosing loop.
+    parent: Option<usize>,
+
+    // Nesting depth, with outermost loops at 1.
+    depth: u32
+}
+
+impl Loop {
+    pub fn header(&self) -> BlockId { self.header }
+    pub fn blocks(&self) -> &[BlockId] { &self.blocks }
+    pub fn depth(&self) -> u32 { self.depth }
+    pub fn contains(&self, block: BlockId) -> bool {
+        self.blocks.contains(&block)
+    }
+}
+
+/**
+ * The loop nest of a graph.
+ *
+ * Back edges are recognized as edges whose target was
+ * entered no later than their source.  The builder only
+ * permits these to target loop headers.
+ */
+pub struct LoopInfo {
+    // Loops in RPO order of their headers, so outer
+    // loops always precede the loops nested in them.
+    loops: Vec<Loop>,
+
+    // The innermost loop containing each block,
+    // indexed by block id.
+    innermost: Vec<Option<usize>>
+}
+
+impl LoopInfo {
+    pub(crate) fn compute(graph: &Graph, cfg: &Cfg) -> LoopInfo {
+        let nblocks = cfg.num_blocks();
+        let order = |b: BlockId| graph.block(b).order();
+
+        let mut loops: Vec<Loop> = Vec::new();
+        for &header in cfg.rpo() {
+            let latches: Vec<BlockId> =
+              cfg.preds(header).iter().cloned()
+                 .filter(|&p| order(p) >= order(header))
+                 .collect();
+            if latches.is_empty() {
+                continue;
+            }
+            debug_assert!(graph.block(header).is_loop());
+
+            // Walk backwards from the latches, stopping
+            // at the header.
+            let mut in_loop = vec![false; nblocks];
+            in_loop[header.as_u32() as usize] = true;
+            let mut stack = latches;
+            while let Some(b) = stack.pop() {
+                let bi = b.as_u32() as usize;
+                if in_loop[bi] {
+                    continue;
+                }
+                in_loop[bi] = true;
+                stack.extend_from_slice(cfg.preds(b));
+            }
+
+            let blocks = cfg.rpo().iter().cloned()
+                            .filter(|b| in_loop[b.as_u32() as usize])
+                            .collect();
+            loops.push(Loop {
+                header, blocks, parent: None, depth: 1
+            });
+        }
+
+        // Loops are ordered by header RPO, so the
+        // innermost loop containing a block is the last
+        // one listing it.
+        let mut innermost = vec![None; nblocks];
+        for i in 0 .. loops.len() {
+            let parent = innermost[loops[i].header.as_u32() as usize];
+            if let Some(p) = parent {
+                loops[i].parent = Some(p);
+                loops[i].depth = loops[p].depth + 1;
+            }
+            for b in &loops[i].blocks {
+                innermost[b.as_u32() as usize] = Some(i);
+            }
+        }
+
+        LoopInfo { loops, innermost }
+    }
+
+    pub fn loops(&self) -> &[Loop] { &self.loops }
+
+    pub fn parent(&self, lp: &Loop) -> Option<&Loop> {
+        lp.parent.map(|p| &self.loops[p])
+    }
+
+    // The innermost loop containing a block.
+    pub fn loop_of(&self, block: BlockId) -> Option<&Loop> {
+        self.innermost[block.as_u32() as usize]
+            .map(|i| &self.loops[i])
+    }
+
+    // The loop nesting depth of a block, with 0
+    // meaning the block is in no loop.
+    pub fn depth_of(&self, block: BlockId) -> u32 {
+        self.loop_of(block).map_or(0, |l| l.depth)
+    }
+}
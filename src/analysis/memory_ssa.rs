@@ -0,0 +1,177 @@
+
+use std::collections::HashMap;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::Op;
+use crate::analysis::{ Cfg, DomTree };
+
+/**
+ * A state of memory, as MemorySSA names them: memory
+ * as it is on entry to the graph, as a write left it,
+ * or as it is at the start of a block where the states
+ * of its predecessors meet.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash)]
+pub enum MemoryAccess {
+    LiveOnEntry,
+    Def(InstrId),
+    Phi(BlockId)
+}
+
+/**
+ * A MemorySSA overlay of the graph's memory accesses.
+ *
+ * The writes are the stores, and the patch points
+ * and calls, whose code may write anything.  Each load
+ * uses, and each write follows, the nearest state of
+ * memory that reaches it, and memory phis are placed
+ * at the iterated dominance frontier of the blocks
+ * holding writes.  With no alias analysis of the
+ * pointers, the write that clobbers a load is the
+ * state it uses.
+ *
+ * Instructions of unreachable blocks have no accesses.
+ */
+pub struct MemorySsa {
+    // The state each load and write follows.
+    defining: HashMap<InstrId, MemoryAccess>,
+
+    // The memory phis, with the state arriving over
+    // each reachable predecessor edge, in `Cfg::preds`
+    // order.
+    phis: HashMap<BlockId, Vec<(BlockId, MemoryAccess)>>
+}
+
+// The dominance frontier of each block, by block id:
+// the blocks where its dominance ends.
+fn dominance_frontiers(cfg: &Cfg, dom: &DomTree)
+  -> Vec<Vec<BlockId>>
+{
+    let mut frontiers = vec![Vec::new(); cfg.num_blocks()];
+    for &b in cfg.rpo() {
+        let preds = cfg.preds(b);
+        if preds.len() < 2 || !dom.is_reachable(b) {
+            continue;
+        }
+        for &p in preds.iter().filter(|&&p| dom.is_reachable(p)) {
+            let mut runner = p;
+            while Some(runner) != dom.idom(b) {
+                let ri = runner.as_u32() as usize;
+                if !frontiers[ri].contains(&b) {
+                    frontiers[ri].push(b);
+                }
+                match dom.idom(runner) {
+                  Some(d) => runner = d,
+                  None => break
+                }
+            }
+        }
+    }
+    frontiers
+}
+
+impl MemorySsa {
+    pub(crate) fn compute(graph: &Graph, cfg: &Cfg, dom: &DomTree)
+      -> MemorySsa
+    {
+        let reachable = || {
+            cfg.rpo().iter().cloned()
+               .filter(|&b| dom.is_reachable(b))
+        };
+
+        // The blocks holding writes.
+        let mut def_blocks = Vec::new();
+        for b in reachable() {
+            let block = graph.block(b);
+            if graph.block_instrs(block).any(|info| {
+                matches!(info.op(),
+                         Op::Store(_) | Op::PatchPoint(_) | Op::Call(_))
+            }) {
+                def_blocks.push(b);
+            }
+        }
+
+        // Place phis at the iterated dominance frontier
+        // of the blocks holding writes.
+        let frontiers = dominance_frontiers(cfg, dom);
+        let mut has_phi = vec![false; cfg.num_blocks()];
+        let mut work = def_blocks;
+        while let Some(b) = work.pop() {
+            for &f in &frontiers[b.as_u32() as usize] {
+                let fi = f.as_u32() as usize;
+                if !has_phi[fi] {
+                    has_phi[fi] = true;
+                    work.push(f);
+                }
+            }
+        }
+
+        // Name the states in RPO, where each block's
+        // immediate dominator comes before it.
+        let mut defining = HashMap::new();
+        let mut block_out = vec![MemoryAccess::LiveOnEntry;
+                                 cfg.num_blocks()];
+        for b in reachable() {
+            let mut state = if has_phi[b.as_u32() as usize] {
+                MemoryAccess::Phi(b)
+            } else {
+                match dom.idom(b) {
+                  Some(d) => block_out[d.as_u32() as usize],
+                  None => MemoryAccess::LiveOnEntry
+                }
+            };
+            for info in graph.block_instrs(graph.block(b)) {
+                let id = info.defn().instr_id();
+                match info.op() {
+                  Op::Load(_) => {
+                    defining.insert(id, state);
+                  }
+                  Op::Store(_) | Op::PatchPoint(_) | Op::Call(_) => {
+                    defining.insert(id, state);
+                    state = MemoryAccess::Def(id);
+                  }
+                  _ => {}
+                }
+            }
+            block_out[b.as_u32() as usize] = state;
+        }
+
+        let mut phis = HashMap::new();
+        for b in reachable() {
+            if !has_phi[b.as_u32() as usize] {
+                continue;
+            }
+            let incoming = cfg.preds(b).iter().cloned()
+              .filter(|&p| dom.is_reachable(p))
+              .map(|p| (p, block_out[p.as_u32() as usize]))
+              .collect();
+            phis.insert(b, incoming);
+        }
+        MemorySsa { defining, phis }
+    }
+
+    // The state of memory a load reads, or a write
+    // writes over.
+    pub fn defining_access(&self, instr: InstrId)
+      -> Option<MemoryAccess>
+    {
+        self.defining.get(&instr).cloned()
+    }
+
+    // The nearest write, or memory phi, that may have
+    // written the bytes a load reads.
+    pub fn clobber(&self, load: InstrId) -> Option<MemoryAccess> {
+        self.defining_access(load)
+    }
+
+    // The states arriving at a block's memory phi, by
+    // predecessor, if it has one.
+    pub fn phi(&self, block: BlockId)
+      -> Option<&[(BlockId, MemoryAccess)]>
+    {
+        self.phis.get(&block).map(|p| &p[..])
+    }
+}
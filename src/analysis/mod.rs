@@ -0,0 +1,31 @@
+
+mod bitset;
+pub(crate) use self::bitset::BitSet;
+
+mod cfg;
+pub use self::cfg::Cfg;
+
+mod dom;
+pub use self::dom::DomTree;
+
+mod escape;
+pub use self::escape::{ EscapeInfo, Escape };
+
+mod induction;
+pub use self::induction::{ InductionVars, BasicIv, DerivedIv };
+pub(crate) use self::induction::wrap;
+
+mod liveness;
+pub use self::liveness::Liveness;
+
+mod loops;
+pub use self::loops::{ LoopInfo, Loop };
+
+mod memory_ssa;
+pub use self::memory_ssa::{ MemorySsa, MemoryAccess };
+
+mod pressure;
+pub use self::pressure::RegPressure;
+
+mod slp;
+pub use self::slp::{ SlpPlan, SlpPack };
@@ -0,0 +1,108 @@
+
+use crate::block::{ Block, BlockId };
+use crate::graph::Graph;
+use crate::analysis::{ Liveness, LoopInfo };
+
+/**
+ * Register pressure estimates: the maximum number of
+ * simultaneously live values at any point within each
+ * block, and within each loop.
+ *
+ * This counts values of every type alike, and assumes
+ * stream order within blocks.
+ */
+pub struct RegPressure {
+    // Maximum pressure of each block, by block id.
+    block_max: Vec<u32>,
+
+    // Maximum pressure over the blocks of each loop,
+    // keyed by loop header.
+    loop_max: Vec<(BlockId, u32)>
+}
+
+impl RegPressure {
+    pub(crate) fn compute(graph: &Graph,
+        liveness: &Liveness,
+        loops: &LoopInfo)
+      -> RegPressure
+    {
+        let nblocks = graph.block_store().total_blocks();
+        let mut block_max = vec![0; nblocks];
+        for block in graph.block_store().iter_rpo_blocks() {
+            block_max[block.id().as_u32() as usize] =
+              block_pressure(graph, liveness, block);
+        }
+
+        let loop_max = loops.loops().iter().map(|lp| {
+            let max = lp.blocks().iter()
+                        .map(|b| block_max[b.as_u32() as usize])
+                        .max()
+                        .unwrap_or(0);
+            (lp.header(), max)
+        }).collect();
+
+        RegPressure { block_max, loop_max }
+    }
+
+    pub fn block_max(&self, block: BlockId) -> u32 {
+        self.block_max[block.as_u32() as usize]
+    }
+
+    // The maximum pressure within the loop headed by
+    // the given block, if it heads a loop.
+    pub fn loop_max(&self, header: BlockId) -> Option<u32> {
+        self.loop_max.iter()
+            .find(|&&(h, _)| h == header)
+            .map(|&(_, max)| max)
+    }
+
+    pub fn loops(&self) -> impl Iterator<Item=(BlockId, u32)> + '_ {
+        self.loop_max.iter().cloned()
+    }
+
+    /** The maximum pressure anywhere in the graph. */
+    pub fn max(&self) -> u32 {
+        self.block_max.iter().cloned().max().unwrap_or(0)
+    }
+}
+
+// Walk a block backwards from its live-out set,
+// tracking the live set size at each instruction.
+fn block_pressure(graph: &Graph, liveness: &Liveness, block: &Block)
+  -> u32
+{
+    let mut steps: Vec<(Option<u32>, Vec<u32>)> = Vec::new();
+    for info in graph.block_instrs(block) {
+        let def = if info.op().defines_value() {
+            liveness.def_index(info.defn().instr_id())
+        } else {
+            None
+        };
+        let uses = info.inputs_iter()
+                       .filter_map(|d| liveness.def_index(d.instr_id()))
+                       .collect();
+        steps.push((def, uses));
+    }
+
+    let mut live = liveness.live_out_set(block).clone();
+    let mut max = live.count();
+    for (def, uses) in steps.iter().rev() {
+        // A definition occupies a register at its own
+        // instruction even if it is never used.
+        if let Some(d) = *def {
+            live.insert(d);
+            max = max.max(live.count());
+            live.remove(d);
+        }
+        for &u in uses {
+            live.insert(u);
+        }
+        max = max.max(live.count());
+    }
+
+    debug_assert!({
+        live.subtract(liveness.live_in_set(block));
+        live.is_empty()
+    });
+    max
+}
@@ -0,0 +1,351 @@
+
+use std::collections::HashMap;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::{ Op, BiniKind, CmpKind };
+use crate::analysis::{ BitSet, DomTree };
+use crate::ir_types::IrTypeId;
+
+// The width of the vector registers packs are sized
+// for.
+const VECTOR_BITS: u32 = 128;
+
+/**
+ * A pack of isomorphic scalar instructions of one
+ * block, which a vector instruction placed at its last
+ * lane could compute together.
+ */
+pub struct SlpPack {
+    block: BlockId,
+    lanes: Vec<InstrId>,
+    benefit: i32
+}
+
+impl SlpPack {
+    pub fn block(&self) -> BlockId { self.block }
+
+    // The instructions of each lane, in stream order.
+    pub fn lanes(&self) -> &[InstrId] { &self.lanes }
+
+    // The instructions the pack saves, net of the
+    // lanes inserted and extracted around it.
+    pub fn benefit(&self) -> i32 { self.benefit }
+}
+
+/**
+ * A straight-line (SLP) vectorization plan: the packs
+ * of the graph's blocks that pay for themselves.
+ *
+ * Packs are seeded in stream order from `bini` and
+ * `cmp` instructions of the same kind and type, up to
+ * as many lanes as fit a 128-bit vector, that neither
+ * depend on each other nor have uses before the pack's
+ * last lane.  The cost model counts one instruction
+ * per scalar lane and per vector instruction, one to
+ * broadcast an operand the same in every lane, one per
+ * lane to insert an operand not computed by a pack
+ * with the same lanes, and one per lane to extract a
+ * value used other than by such a pack.  Packs are
+ * dropped one at a time while the others save more
+ * without one, so a pack that costs more than it
+ * saves may be kept for the packs it feeds.
+ *
+ * The graph has no vector types yet for a vectorizer
+ * to bundle packs into, so the plan only describes
+ * them.
+ */
+pub struct SlpPlan {
+    packs: Vec<SlpPack>
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PackKey {
+    Bini(BiniKind, IrTypeId),
+    Cmp(CmpKind, IrTypeId)
+}
+
+impl PackKey {
+    fn of(op: &Op) -> Option<PackKey> {
+        match op {
+          Op::Bini(op) => Some(PackKey::Bini(op.kind(), op.tyid())),
+          Op::Cmp(op) => Some(PackKey::Cmp(op.kind(), op.tyid())),
+          _ => None
+        }
+    }
+
+    fn max_lanes(self) -> usize {
+        let ty = match self {
+          PackKey::Bini(_, ty) | PackKey::Cmp(_, ty) => ty
+        };
+        match ty {
+          IrTypeId::Bool => 0,
+          ty => (VECTOR_BITS / (ty.mem_size() * 8)) as usize
+        }
+    }
+}
+
+// The instructions of a block, with what packing
+// needs of each.
+struct BlockInstr {
+    id: InstrId,
+    key: Option<PackKey>,
+
+    // The in-block instructions it depends on, and
+    // the position of its first in-block use.
+    deps: BitSet,
+    first_use: usize
+}
+
+// Seed the packs of a block.
+fn seed_block(instrs: &[BlockInstr]) -> Vec<Vec<usize>> {
+    let mut packs = Vec::new();
+    let mut keys: Vec<PackKey> = Vec::new();
+    for instr in instrs {
+        match instr.key {
+          Some(key) if !keys.contains(&key) => keys.push(key),
+          _ => {}
+        }
+    }
+    for key in keys {
+        let max = key.max_lanes();
+        let mut pack: Vec<usize> = Vec::new();
+        for (x, instr) in instrs.iter().enumerate() {
+            if instr.key != Some(key) {
+                continue;
+            }
+            let joins = pack.len() < max && pack.iter().all(|&l| {
+                !instr.deps.contains(l as u32)
+                  && instrs[l].first_use > x
+            });
+            if !joins {
+                if pack.len() >= 2 {
+                    packs.push(pack);
+                }
+                pack = Vec::new();
+            }
+            pack.push(x);
+        }
+        if pack.len() >= 2 {
+            packs.push(pack);
+        }
+    }
+    packs
+}
+
+type Users = HashMap<InstrId, Vec<(InstrId, Option<usize>)>>;
+
+// The packs being costed, and which are still kept.
+struct Packing<'a> {
+    packs: &'a [(BlockId, Vec<InstrId>)],
+    operands: &'a HashMap<InstrId, Vec<InstrId>>,
+    users: &'a Users,
+    lane_of: HashMap<InstrId, (usize, usize)>,
+    live: Vec<bool>
+}
+
+impl<'a> Packing<'a> {
+    fn new(packs: &'a [(BlockId, Vec<InstrId>)],
+           operands: &'a HashMap<InstrId, Vec<InstrId>>,
+           users: &'a Users)
+      -> Packing<'a>
+    {
+        let mut lane_of = HashMap::new();
+        for (p, (_, lanes)) in packs.iter().enumerate() {
+            for (i, &id) in lanes.iter().enumerate() {
+                lane_of.insert(id, (p, i));
+            }
+        }
+        let live = vec![true; packs.len()];
+        Packing { packs, operands, users, lane_of, live }
+    }
+
+    // The pack and lane of a value, among the packs
+    // kept other than `dropped`.
+    fn lane(&self, id: InstrId, dropped: Option<usize>)
+      -> Option<(usize, usize)>
+    {
+        self.lane_of.get(&id).cloned().filter(|&(p, _)| {
+            self.live[p] && Some(p) != dropped
+        })
+    }
+
+    // The pack computing an operand of a pack in lane
+    // order, if one does.
+    fn packed_operand(&self, p: usize, k: usize,
+                      dropped: Option<usize>)
+      -> Option<usize>
+    {
+        let lanes = &self.packs[p].1;
+        let (q, _) = self.lane(self.operands[&lanes[0]][k], dropped) ?;
+        let exact = self.packs[q].1.len() == lanes.len()
+          && lanes.iter().enumerate().all(|(i, l)| {
+              self.lane(self.operands[l][k], dropped) == Some((q, i))
+          });
+        if exact { Some(q) } else { None }
+    }
+
+    // The instructions a pack saves, with `dropped`
+    // dropped.
+    fn benefit(&self, p: usize, dropped: Option<usize>) -> i32 {
+        let lanes = &self.packs[p].1;
+        let n = lanes.len() as i32;
+        let mut benefit = n - 1;
+        for k in 0 .. self.operands[&lanes[0]].len() {
+            if self.packed_operand(p, k, dropped).is_some() {
+                continue;
+            }
+            let first = self.operands[&lanes[0]][k];
+            let same =
+              lanes.iter().all(|l| self.operands[l][k] == first);
+            benefit -= if same { 1 } else { n };
+        }
+        for lane in lanes {
+            let extracted = self.users.get(lane).into_iter().flatten()
+              .any(|&(u, k)| match (self.lane(u, dropped), k) {
+                (Some((q, _)), Some(k)) => {
+                  self.packed_operand(q, k, dropped) != Some(p)
+                }
+                _ => true
+              });
+            if extracted {
+                benefit -= 1;
+            }
+        }
+        benefit
+    }
+
+    // The kept packs computing operands of a pack's
+    // lanes, or using them.
+    fn neighbors(&self, p: usize) -> Vec<usize> {
+        let mut neighbors = Vec::new();
+        for lane in &self.packs[p].1 {
+            let uses = self.users.get(lane).into_iter().flatten()
+                           .map(|u| u.0);
+            for v in self.operands[lane].iter().cloned().chain(uses) {
+                match self.lane(v, None) {
+                  Some((q, _)) if q != p && !neighbors.contains(&q) => {
+                    neighbors.push(q)
+                  }
+                  _ => {}
+                }
+            }
+        }
+        neighbors
+    }
+}
+
+impl SlpPlan {
+    pub(crate) fn compute(graph: &Graph, dom: &DomTree) -> SlpPlan {
+        // The users of each value, with the operand
+        // position of each use by an instruction, or
+        // None for a phi argument or snapshot entry.
+        let mut users: Users = HashMap::new();
+        for block in graph.block_store().iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                let id = info.defn().instr_id();
+                for (k, d) in info.inputs_iter().enumerate() {
+                    users.entry(d.instr_id()).or_default()
+                         .push((id, Some(k)));
+                }
+                let args = info.targets_iter()
+                               .flat_map(|t| t.phi_args())
+                               .map(|d| d.instr_id());
+                let entries = graph.snapshot(id).into_iter()
+                                   .flat_map(|s| s.entries())
+                                   .map(|e| e.1);
+                for v in args.chain(entries) {
+                    users.entry(v).or_default().push((id, None));
+                }
+            }
+        }
+
+        let mut packs: Vec<(BlockId, Vec<InstrId>)> = Vec::new();
+        for block in graph.block_store().iter_rpo_blocks() {
+            if !dom.is_reachable(block.id()) {
+                continue;
+            }
+            let mut instrs: Vec<BlockInstr> = Vec::new();
+            let mut pos: HashMap<InstrId, usize> = HashMap::new();
+            let n = graph.block_instrs(block).count() as u32;
+            for info in graph.block_instrs(block) {
+                let id = info.defn().instr_id();
+                let operands: Vec<InstrId> =
+                  info.inputs_iter().map(|d| d.instr_id()).collect();
+                let mut deps = BitSet::new(n);
+                for v in &operands {
+                    if let Some(&p) = pos.get(v) {
+                        deps.insert(p as u32);
+                        let earlier = instrs[p].deps.clone();
+                        deps.union_with(&earlier);
+                    }
+                }
+                pos.insert(id, instrs.len());
+                instrs.push(BlockInstr {
+                    id, key: PackKey::of(info.op()), deps,
+                    first_use: usize::MAX
+                });
+            }
+            for instr in &mut instrs {
+                let first = users.get(&instr.id).into_iter().flatten()
+                  .filter_map(|u| pos.get(&u.0).cloned()).min();
+                instr.first_use = first.unwrap_or(usize::MAX);
+            }
+            for pack in seed_block(&instrs) {
+                let lanes =
+                  pack.iter().map(|&l| instrs[l].id).collect();
+                packs.push((block.id(), lanes));
+            }
+        }
+
+        // Drop packs one at a time while dropping one
+        // saves more than keeping it, and all of them if
+        // what is left saves nothing.
+        let operands: HashMap<InstrId, Vec<InstrId>> =
+          graph.block_store().iter_rpo_blocks()
+               .flat_map(|b| graph.block_instrs(b))
+               .map(|info| (info.defn().instr_id(),
+                            info.inputs_iter().map(|d| d.instr_id())
+                                .collect()))
+               .collect();
+        let mut packing = Packing::new(&packs, &operands, &users);
+        loop {
+            let live: Vec<usize> = (0 .. packs.len())
+              .filter(|&p| packing.live[p]).collect();
+            let benefits: HashMap<usize, i32> = live.iter()
+              .map(|&p| (p, packing.benefit(p, None))).collect();
+            let dropped = live.iter().map(|&i| {
+                let delta: i32 = packing.neighbors(i).iter()
+                  .map(|&j| packing.benefit(j, Some(i)) - benefits[&j])
+                  .sum();
+                (delta - benefits[&i], i)
+            }).max_by_key(|&(delta, i)| (delta, std::cmp::Reverse(i)));
+            match dropped {
+              Some((delta, i)) if delta > 0 => packing.live[i] = false,
+              _ => break
+            }
+        }
+        let kept: Vec<(usize, i32)> = (0 .. packs.len())
+          .filter(|&p| packing.live[p])
+          .map(|p| (p, packing.benefit(p, None))).collect();
+        if kept.iter().map(|k| k.1).sum::<i32>() <= 0 {
+            return SlpPlan { packs: Vec::new() };
+        }
+
+        let mut packs: Vec<Option<(BlockId, Vec<InstrId>)>> =
+          packs.into_iter().map(Some).collect();
+        let packs = kept.into_iter().map(|(p, benefit)| {
+            let (block, lanes) = packs[p].take().unwrap();
+            SlpPack { block, lanes, benefit }
+        }).collect();
+        SlpPlan { packs }
+    }
+
+    pub fn packs(&self) -> &[SlpPack] { &self.packs }
+
+    // The instructions saved by all the packs.
+    pub fn benefit(&self) -> i32 {
+        self.packs.iter().map(|p| p.benefit).sum()
+    }
+}
@@ -0,0 +1,105 @@
+
+use std::mem;
+
+/**
+ * A vector stored as a list of chunks.  Chunked, every
+ * chunk holds a fixed power-of-two number of elements
+ * and is allocated at its full size, so growing the
+ * vector never moves elements already pushed, and a
+ * very large vector never needs one large allocation.
+ * Contiguous, there is a single chunk which grows as a
+ * plain `Vec` does.
+ */
+pub(crate) struct ChunkedVec<T> {
+    chunks: Vec<Vec<T>>,
+
+    // The log2 of the elements in a full chunk.
+    shift: u32,
+
+    len: usize
+}
+
+impl<T> ChunkedVec<T> {
+    // A single chunk, growing like a `Vec`.
+    pub(crate) fn contiguous(cap: usize) -> ChunkedVec<T> {
+        ChunkedVec {
+            chunks: vec![Vec::with_capacity(cap)],
+            shift: usize::BITS - 1,
+            len: 0
+        }
+    }
+
+    // Chunks of `1 << shift` elements each.
+    pub(crate) fn chunked(shift: u32) -> ChunkedVec<T> {
+        debug_assert!(shift < usize::BITS - 1);
+        ChunkedVec { chunks: Vec::new(), shift, len: 0 }
+    }
+
+    pub(crate) fn is_chunked(&self) -> bool {
+        self.shift < usize::BITS - 1
+    }
+
+    pub(crate) fn len(&self) -> usize { self.len }
+    pub(crate) fn is_empty(&self) -> bool { self.len == 0 }
+
+    fn mask(&self) -> usize { (1_usize << self.shift) - 1 }
+
+    pub(crate) fn push(&mut self, value: T) {
+        if self.len >> self.shift == self.chunks.len() {
+            let cap = 1_usize << self.shift;
+            self.chunks.push(Vec::with_capacity(cap));
+        }
+        self.chunks.last_mut().unwrap().push(value);
+        self.len += 1;
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<&T> {
+        if index < self.len {
+            Some(unsafe { self.get_unchecked(index) })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) unsafe fn get_unchecked(&self, index: usize) -> &T {
+        debug_assert!(index < self.len);
+        self.chunks.get_unchecked(index >> self.shift)
+                   .get_unchecked(index & self.mask())
+    }
+
+    pub(crate) unsafe fn get_unchecked_mut(&mut self, index: usize)
+      -> &mut T
+    {
+        debug_assert!(index < self.len);
+        let mask = self.mask();
+        self.chunks.get_unchecked_mut(index >> self.shift)
+                   .get_unchecked_mut(index & mask)
+    }
+
+    pub(crate) fn last(&self) -> Option<&T> {
+        self.chunks.last().and_then(|chunk| chunk.last())
+    }
+
+    // The bytes allocated for elements, used or not.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        let elems: usize =
+          self.chunks.iter().map(|chunk| chunk.capacity()).sum();
+        elems * mem::size_of::<T>()
+          + self.chunks.capacity() * mem::size_of::<Vec<T>>()
+    }
+
+    // Release any capacity beyond the elements held.
+    // Only the last chunk can be partly used.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        if let Some(chunk) = self.chunks.last_mut() {
+            chunk.shrink_to_fit();
+        }
+        self.chunks.shrink_to_fit();
+    }
+
+    pub(crate) fn iter(&self)
+      -> impl Iterator<Item=&T> + Clone
+    {
+        self.chunks.iter().flat_map(|chunk| chunk.iter())
+    }
+}
@@ -0,0 +1,235 @@
+
+use std::ops::Range;
+
+use crate::block::BlockId;
+use crate::decode::{ ByteReader, DecodeError, decode_op };
+use crate::instr::{ InstrId, InstrInfo, InstrPosn, OperandEncoding };
+use crate::graph::Graph;
+use crate::ops::{ Op, Opcode };
+
+/*
+ * A batch holds decoded instructions in flat buffers:
+ * each record names a run of `operands`, holding its
+ * inputs, and a run of `targets`, each of which in
+ * turn names the run of `operands` holding its phi
+ * arguments.  Decoding a block into a batch that is
+ * reused takes no allocation once the buffers have
+ * grown to fit, where decoding instructions one at a
+ * time allocates for the operands of each.
+ */
+
+#[derive(Clone)]
+struct BatchRecord {
+    id: InstrId,
+    op: Op,
+    inputs: (u32, u32),
+    targets: (u32, u32)
+}
+
+#[derive(Clone, Copy)]
+struct BatchTarget {
+    block: BlockId,
+    args: (u32, u32)
+}
+
+/**
+ * A reusable buffer of decoded instructions, filled a
+ * block or byte range at a time.
+ */
+#[derive(Clone, Default)]
+pub struct DecodeBatch {
+    records: Vec<BatchRecord>,
+    operands: Vec<InstrId>,
+    targets: Vec<BatchTarget>
+}
+
+/** An instruction in a `DecodeBatch`. */
+#[derive(Clone, Copy)]
+pub struct BatchInstr<'a> {
+    batch: &'a DecodeBatch,
+    record: &'a BatchRecord
+}
+
+impl DecodeBatch {
+    pub fn new() -> DecodeBatch {
+        DecodeBatch::default()
+    }
+
+    pub fn len(&self) -> usize { self.records.len() }
+    pub fn is_empty(&self) -> bool { self.records.is_empty() }
+
+    // Drop the decoded instructions, keeping the
+    // buffers for the next fill.
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.operands.clear();
+        self.targets.clear();
+    }
+
+    pub fn get(&self, index: usize) -> Option<BatchInstr<'_>> {
+        self.records.get(index).map(|record| {
+            BatchInstr { batch: self, record }
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=BatchInstr<'_>> {
+        self.records.iter().map(move |record| {
+            BatchInstr { batch: self, record }
+        })
+    }
+
+    /**
+     * Decode the instructions in `range` of an untrusted
+     * stream with the checks of `decode_instrs`,
+     * replacing the batch's contents.  The range must
+     * start and end at instruction boundaries; an
+     * instruction running past its end fails as
+     * truncated.  Ids and error offsets are relative to
+     * the whole of `bytes`.  Pooled constants are
+     * resolved in `pool`.  On error, the batch holds the
+     * instructions before the one that failed.
+     */
+    pub fn decode_range(&mut self, bytes: &[u8], range: Range<usize>,
+                        encoding: OperandEncoding, pool: &[u64])
+      -> Result<(), DecodeError>
+    {
+        self.clear();
+        let end = range.end.min(bytes.len());
+        let mut reader = ByteReader::at(&bytes[.. end], range.start)
+                           .with_pool(pool);
+        while !reader.at_end() {
+            self.decode_next(&mut reader, encoding) ?;
+        }
+        Ok(())
+    }
+
+    fn decode_next(&mut self, reader: &mut ByteReader,
+                   encoding: OperandEncoding)
+      -> Result<(), DecodeError>
+    {
+        let offset = reader.offset();
+        if offset >= (InstrPosn::INVALID_VALUE as usize) {
+            return Err(DecodeError::Overflow { offset });
+        }
+        let id = InstrId::from_offset(offset);
+        let op = decode_op(reader) ?;
+
+        let inputs_start = self.operands.len();
+        for _ in 0 .. op.num_inputs() {
+            self.operands.push(reader.read_operand(id, encoding) ?);
+        }
+        let inputs = self.run_from(inputs_start, self.operands.len());
+
+        let targets_start = self.targets.len();
+        for _ in 0 .. op.num_targets().unwrap_or(0) {
+            let block = BlockId::new(reader.read_field_u32(encoding) ?);
+            let nargs_offset = reader.offset();
+            let nargs = reader.read_field_u32(encoding) ? as usize;
+
+            // As in `DecodedInstr::decode`, a count beyond
+            // the remaining bytes is rejected before
+            // reserving for it.
+            if nargs > reader.remaining() {
+                return Err(DecodeError::Truncated {
+                    offset: nargs_offset
+                });
+            }
+            let args_start = self.operands.len();
+            self.operands.reserve(nargs);
+            for _ in 0 .. nargs {
+                self.operands.push(reader.read_operand(id, encoding) ?);
+            }
+            let args = self.run_from(args_start, self.operands.len());
+            self.targets.push(BatchTarget { block, args });
+        }
+        let targets = self.run_from(targets_start, self.targets.len());
+
+        self.records.push(BatchRecord { id, op, inputs, targets });
+        Ok(())
+    }
+
+    // Add an instruction of a finished graph, whose
+    // bytes are known to be well formed.
+    fn push_info(&mut self, info: &InstrInfo) {
+        let inputs_start = self.operands.len();
+        self.operands.extend(info.inputs_iter()
+                                 .map(|d| d.instr_id()));
+        let inputs = self.run_from(inputs_start, self.operands.len());
+
+        let targets_start = self.targets.len();
+        for target in info.targets_iter() {
+            let args_start = self.operands.len();
+            self.operands.extend(target.phi_args()
+                                       .map(|d| d.instr_id()));
+            let args = self.run_from(args_start, self.operands.len());
+            self.targets.push(BatchTarget {
+                block: target.block_id(), args
+            });
+        }
+        let targets = self.run_from(targets_start, self.targets.len());
+
+        self.records.push(BatchRecord {
+            id: info.defn().instr_id(),
+            op: info.op().clone(),
+            inputs, targets
+        });
+    }
+
+    fn run_from(&self, start: usize, end: usize) -> (u32, u32) {
+        (start as u32, (end - start) as u32)
+    }
+
+    fn operand_run(&self, (start, len): (u32, u32)) -> &[InstrId] {
+        &self.operands[start as usize .. (start + len) as usize]
+    }
+}
+
+impl<'a> BatchInstr<'a> {
+    pub fn id(&self) -> InstrId { self.record.id }
+    pub fn opcode(&self) -> Opcode { self.record.op.opcode() }
+
+    pub fn inputs(&self) -> &'a [InstrId] {
+        self.batch.operand_run(self.record.inputs)
+    }
+
+    // The target blocks of an end instruction, each with
+    // the phi arguments passed to it.
+    pub fn targets(&self)
+      -> impl ExactSizeIterator<Item=(BlockId, &'a [InstrId])>
+    {
+        let batch = self.batch;
+        let (start, len) = self.record.targets;
+        batch.targets[start as usize .. (start + len) as usize]
+          .iter()
+          .map(move |t| (t.block, batch.operand_run(t.args)))
+    }
+}
+
+impl Graph {
+    /**
+     * Decode the instructions of a block into a batch,
+     * replacing its contents.
+     */
+    pub fn decode_block(&self, block: BlockId,
+                        batch: &mut DecodeBatch)
+    {
+        batch.clear();
+        for info in self.block_instrs(self.block(block)) {
+            batch.push_info(&info);
+        }
+    }
+
+    /**
+     * Decode every block into a batch in RPO, which is
+     * the order of the instruction stream, replacing
+     * its contents.
+     */
+    pub fn decode_all(&self, batch: &mut DecodeBatch) {
+        batch.clear();
+        for block in self.block_store().iter_rpo_blocks() {
+            for info in self.block_instrs(block) {
+                batch.push_info(&info);
+            }
+        }
+    }
+}
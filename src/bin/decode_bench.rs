@@ -0,0 +1,52 @@
+
+extern crate cog_ir;
+
+use std::time::Instant;
+
+use cog_ir::api::{
+    DecodeBatch, GraphGen, GraphGenConfig, OperandEncoding
+};
+
+// Time re-decoding the operands of generated graphs,
+// which is what analyses spend on leb128.  Pass a
+// round count to change how long each encoding runs.
+fn main() {
+    let rounds = std::env::args().nth(1)
+                     .and_then(|s| s.parse().ok())
+                     .unwrap_or(200);
+    let encodings = [
+        OperandEncoding::Absolute,
+        OperandEncoding::Delta,
+        OperandEncoding::Fixed
+    ];
+    for &encoding in &encodings {
+        let config = GraphGenConfig {
+            max_depth: 5, max_items: 6, encoding,
+            ..Default::default()
+        };
+        let graphs: Vec<_> = (0 .. 16).map(|seed| {
+            GraphGen::with_config(seed, config.clone()).generate()
+        }).collect();
+        let bytes: usize =
+          graphs.iter().map(|g| g.instr_bytes().len()).sum();
+        let mut batch = DecodeBatch::new();
+        let mut operands = 0;
+
+        let start = Instant::now();
+        for _ in 0 .. rounds {
+            for graph in &graphs {
+                graph.decode_all(&mut batch);
+                for instr in batch.iter() {
+                    operands += instr.inputs().len();
+                    for (_, args) in instr.targets() {
+                        operands += args.len();
+                    }
+                }
+            }
+        }
+        let per_round = start.elapsed() / rounds;
+        println!("{:?}: {} bytes, {} operands, {:?}/round",
+                 encoding, bytes, operands / (rounds as usize),
+                 per_round);
+    }
+}
@@ -0,0 +1,57 @@
+
+extern crate cog_ir;
+
+use std::fs;
+use std::process;
+
+use cog_ir::api::{ Graph, OperandEncoding, disasm };
+
+const USAGE: &str = "usage: disasm FILE\n       \
+                     disasm --raw [absolute|delta|fixed] FILE";
+
+// Disassemble a serialized graph, or with `--raw` the
+// bare instruction stream in a file, whose operands
+// are absolute offsets unless another encoding is
+// named.  A graph that fails to load is disassembled
+// as a raw stream of absolute operands.
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (raw, encoding, path) = match args.as_slice() {
+      [path] => (false, OperandEncoding::Absolute, path),
+      [flag, path] if flag == "--raw" => {
+        (true, OperandEncoding::Absolute, path)
+      }
+      [flag, name, path] if flag == "--raw" => {
+        let encoding = match name.as_str() {
+          "absolute" => OperandEncoding::Absolute,
+          "delta" => OperandEncoding::Delta,
+          "fixed" => OperandEncoding::Fixed,
+          _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+          }
+        };
+        (true, encoding, path)
+      }
+      _ => {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+      }
+    };
+
+    let bytes = fs::read(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path, err);
+        process::exit(1);
+    });
+    if raw {
+        print!("{}", disasm(&bytes, encoding, &[]));
+        return;
+    }
+    match Graph::from_bytes(&bytes) {
+      Ok(graph) => print!("{}", graph.disasm()),
+      Err(err) => {
+        eprintln!("{}: {}; disassembling as a raw stream", path, err);
+        print!("{}", disasm(&bytes, encoding, &[]));
+      }
+    }
+}
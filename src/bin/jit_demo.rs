@@ -0,0 +1,49 @@
+
+extern crate cog_ir;
+
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+
+use cog_ir::api::{ build, Int64Ty };
+
+// Build a loop summing the integers below its param,
+// compile it, and run it.
+fn main() {
+    env_logger::builder()
+        .default_format_timestamp(false)
+        .default_format_module_path(false)
+        .init();
+
+    let graph = build(|bs| {
+        let head = bs.decl_loop_head(2);
+        let n = bs.emit_param::<Int64Ty>(0);
+        let zero = bs.emit_const_int64(0);
+        bs.jump(head, &[zero.untyped_defn(), zero.untyped_defn()]);
+
+        bs.def_loop(head, |bs| {
+            let body = bs.decl_plain_block(0);
+            let done = bs.decl_plain_block(0);
+            let i = bs.emit_phi::<Int64Ty>();
+            let sum = bs.emit_phi::<Int64Ty>();
+            let more = bs.emit_lt(i, n);
+            bs.branch(more, body, &[], done, &[]);
+
+            bs.def_block(body);
+            let one = bs.emit_const_int64(1);
+            let next_i = bs.emit_add(i, one);
+            let next_sum = bs.emit_add(sum, i);
+            bs.jump(head, &[next_i.untyped_defn(),
+                            next_sum.untyped_defn()]);
+
+            bs.def_block(done);
+            bs.ret(sum);
+        });
+    }).into_graph();
+
+    let func = graph.compile_x86_64().unwrap();
+    info!("Compiled to {} bytes.", func.code_size());
+    for &n in &[0, 10, 1000, 1_000_000] {
+        println!("sum below {} = {:?}", n, func.call(&[n]));
+    }
+}
@@ -105,11 +105,7 @@ fn main() {
 
     graph(builder, |gs| {
         gs.debug_print_cur_instr();
-        let mut i = 0_u32;
-        loop {
-            let defn = match gs.next_defn() {
-              Some(defn) => defn, None => { break; }
-            };
+        while let Some(defn) = gs.next_defn() {
             debug!("i0: {}", defn);
             gs.debug_print_cur_instr();
         }
@@ -3,6 +3,7 @@ use std::fmt;
 use std::marker::PhantomData;
 
 use crate::instr::{ InstrId, InstrPosn };
+use crate::arena::ChunkedVec;
 
 /**
  * A block-id identifies a block by declaration id.
@@ -15,10 +16,11 @@ use crate::instr::{ InstrId, InstrPosn };
  * but have their control flow nested within.
  */
 #[derive(Clone, Copy, Debug)]
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BlockId(u32);
 impl BlockId {
-    pub(crate) fn as_u32(&self) -> u32 { self.0 }
+    pub(crate) fn new(val: u32) -> BlockId { BlockId(val) }
+    pub fn as_u32(&self) -> u32 { self.0 }
 }
 impl fmt::Display for BlockId {
     fn fmt(&self, f: &mut fmt::Formatter)
@@ -41,8 +43,8 @@ impl<'a> BlockRef<'a> {
     pub(crate) fn id(&self) -> BlockId { self.0 }
 }
 
-impl<'a> Into<BlockId> for BlockRef<'a> {
-    fn into(self) -> BlockId { self.0 }
+impl<'a> From<BlockRef<'a>> for BlockId {
+    fn from(block: BlockRef<'a>) -> BlockId { block.0 }
 }
 
 /**
@@ -59,6 +61,11 @@ pub struct Block {
     // The state of the block.
     state: BlockState,
 
+    // Whether the block is a rarely taken path, such as
+    // a slow path or bailout, to be kept out of the way
+    // of the hot code.
+    cold: bool,
+
     // The number of incoming edges to this block.
     // Incremented as edges are added.
     // For non-loop-entry blocks, this field is
@@ -66,7 +73,7 @@ pub struct Block {
     input_edges: u32,
 
     // The numbering of the block in specification order
-    // (RPO).  Only set when the block is entered.
+    // (RPO).  Only set when the RPO index is built.
     order: u32,
 
     // The index of the first instruction.  Only set
@@ -80,7 +87,9 @@ pub struct Block {
 }
 enum BlockVariant {
     Plain { num_phis: u32 },
+    #[allow(dead_code)]
     Loop { num_phis: u32, loop_no: u16 },
+    #[allow(dead_code)]
     Start { start_no: u16 }
 }
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -92,36 +101,30 @@ impl Block {
         Block {
           id, variant,
           state: BlockState::Declared,
-          input_edges: 0, order: u32::max_value(),
+          cold: false,
+          input_edges: 0, order: u32::MAX,
           first_instr: InstrId::invalid(),
           last_instr: InstrId::invalid()
         }
     }
-    fn new_plain(id: BlockId, num_phis: u32) -> Block {
-        Self::new(id, BlockVariant::Plain { num_phis })
-    }
-    fn new_start(id: BlockId, start_no: u16) -> Block {
-        Self::new(id, BlockVariant::Start { start_no })
-    }
-    fn new_loop(id: BlockId, num_phis: u32, loop_no: u16)
-      -> Block
-    {
-        let bv = BlockVariant::Loop { num_phis, loop_no };
-        Self::new(id, bv)
-    }
     pub(crate) fn id(&self) -> BlockId { self.id }
 
     pub(crate) fn num_phis(&self) -> u32 {
         match self.variant {
           BlockVariant::Plain { num_phis }
             => num_phis,
-          BlockVariant::Loop { num_phis, loop_no: _ }
+          BlockVariant::Loop { num_phis, .. }
             => num_phis,
-          BlockVariant::Start { start_no }
+          BlockVariant::Start { .. }
             => 0
         }
     }
 
+    pub(crate) fn order(&self) -> u32 {
+        debug_assert!(self.order != u32::MAX);
+        self.order
+    }
+
     pub(crate) fn first_instr(&self) -> InstrId {
         self.first_instr
     }
@@ -130,20 +133,14 @@ impl Block {
     }
 
     pub fn is_start(&self) -> bool {
-        match self.variant {
-            BlockVariant::Start{ start_no: _ }
-              => true,
-            _ => false
-        }
+        matches!(self.variant, BlockVariant::Start { .. })
     }
     pub fn is_loop(&self) -> bool {
-        match self.variant {
-            BlockVariant::Loop{ num_phis: _, loop_no: _}
-              => true,
-            _ => false
-        }
+        matches!(self.variant, BlockVariant::Loop { .. })
     }
 
+    pub fn is_cold(&self) -> bool { self.cold }
+
     pub fn input_edges(&self) -> u32 { self.input_edges }
     pub fn has_entered(&self) -> bool {
         self.state >= BlockState::Entered
@@ -158,14 +155,16 @@ impl Block {
     pub(crate) fn incr_input_edges(&mut self) {
         self.input_edges += 1;
     }
-    fn set_entered(&mut self,
-      order: u32, first_instr: InstrId)
-    {
+    pub(crate) fn decr_input_edges(&mut self) {
+        debug_assert!(self.input_edges > 0);
+        self.input_edges -= 1;
+    }
+    fn set_entered(&mut self, first_instr: InstrId) {
         debug_assert!(! self.has_entered());
         self.state = BlockState::Entered;
         self.first_instr = first_instr;
-        self.order = order;
     }
+    #[allow(dead_code)]
     fn set_add_instr(&mut self, last_instr: InstrId) {
         debug_assert!(self.has_entered());
         debug_assert!(! self.has_finished());
@@ -184,14 +183,29 @@ impl Block {
     }
 }
 
+/**
+ * How a block store holds its blocks and RPO index.
+ * Contiguous storage keeps each in one vector that
+ * grows by reallocation.  Chunked storage keeps them in
+ * fixed-size chunks that are never moved, for very
+ * large graphs.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum BlockStorage { Contiguous, Chunked }
+
 /**
  * A store of all the blocks in a graph.  A vector
  * stores all block information in global declaration
  * order, and a secondary RPO index of the first vector.
+ * The RPO index is only built once the graph is
+ * finished: blocks are laid out in the instruction
+ * stream in the order they are entered, so it is the
+ * order of their first instructions.
  */
 pub struct BlockStore {
-    decl_blocks: Vec<Block>,
-    rpo_index: Vec<BlockId>,
+    decl_blocks: ChunkedVec<Block>,
+    rpo_index: ChunkedVec<BlockId>,
     cur_block_id: BlockId,
     num_starts: u16,
     num_loops: u16,
@@ -199,19 +213,31 @@ pub struct BlockStore {
 }
 
 impl BlockStore {
-    const INSTR_BYTES_CAP: usize = 256;
-    const INSTR_POSNS_CAP: usize = 256;
     const DECL_BLOCKS_CAP: usize = 8;
-    const RPO_INDEX_CAP: usize = 8;
 
-    const MAX_DECL_BLOCKS: u32 = 0xf_ffff;
-    const MAX_INSTR_BYTES: u32 = 0xff_ffff;
+    // The log2 of the blocks in a chunk of chunked
+    // storage.
+    const CHUNK_SHIFT: u32 = 10;
+
+    pub(crate) const MAX_DECL_BLOCKS: u32 = 0xf_ffff;
+    pub(crate) const MAX_LOOPS: u16 = u16::MAX;
+    pub(crate) const MAX_STARTS: u16 = u16::MAX;
 
     pub fn new() -> BlockStore {
-        let decl_blocks =
-          Vec::with_capacity(Self::DECL_BLOCKS_CAP);
-        let rpo_index =
-          Vec::with_capacity(Self::RPO_INDEX_CAP);
+        BlockStore::with_storage(BlockStorage::Contiguous)
+    }
+
+    pub(crate) fn with_storage(storage: BlockStorage) -> BlockStore {
+        let (decl_blocks, rpo_index) = match storage {
+          BlockStorage::Contiguous => (
+            ChunkedVec::contiguous(Self::DECL_BLOCKS_CAP),
+            ChunkedVec::contiguous(0)
+          ),
+          BlockStorage::Chunked => (
+            ChunkedVec::chunked(Self::CHUNK_SHIFT),
+            ChunkedVec::chunked(Self::CHUNK_SHIFT)
+          )
+        };
 
         let cur_block_id = BlockId(0);
 
@@ -223,30 +249,49 @@ impl BlockStore {
 
         // Declare a start block and enter it
         // immediately.
-        let first_id = bs.decl_start_block();
+        let first_id = bs.decl_start_block().unwrap();
         let first_ins = InstrId::new(InstrPosn::new(0));
         unsafe { bs.enter_block(first_id, first_ins) };
 
         bs
     }
 
+    pub(crate) fn storage(&self) -> BlockStorage {
+        if self.decl_blocks.is_chunked() {
+            BlockStorage::Chunked
+        } else {
+            BlockStorage::Contiguous
+        }
+    }
+
+    pub(crate) fn block_table_bytes(&self) -> usize {
+        self.decl_blocks.heap_bytes()
+    }
+    pub(crate) fn rpo_index_bytes(&self) -> usize {
+        self.rpo_index.heap_bytes()
+    }
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.decl_blocks.shrink_to_fit();
+        self.rpo_index.shrink_to_fit();
+    }
+
     pub(crate) fn start_block_id(&self) -> BlockId {
-        debug_assert!(self.decl_blocks.len() > 0);
+        debug_assert!(!self.decl_blocks.is_empty());
         BlockId(0)
     }
+    #[allow(dead_code)]
     pub(crate) unsafe fn last_rpo_block(&self)
       -> BlockId
     {
         debug_assert!(self.rpo_index.len()
                         == self.decl_blocks.len());
-        debug_assert!(self.rpo_index.len() > 0);
-        let last_block_id =
-            *self.rpo_index.get_unchecked(
-              self.rpo_index.len() - 1);
+        debug_assert!(!self.rpo_index.is_empty());
+        let last_block_id = *self.rpo_index.last().unwrap();
         debug_assert!(
           self.get_block(last_block_id).has_finished());
         last_block_id
     }
+    #[allow(dead_code)]
     pub(crate) unsafe fn next_rpo_block(
         &self, block_id: BlockId)
       -> Option<BlockId>
@@ -261,6 +306,7 @@ impl BlockStore {
           self.get_block(next_block_id).has_finished());
         Some(next_block_id)
     }
+    #[allow(dead_code)]
     pub(crate) unsafe fn prior_rpo_block(
         &self, block_id: BlockId)
       -> Option<BlockId>
@@ -281,17 +327,21 @@ impl BlockStore {
         }
     }
 
-    // Declare a new block and get an index for it.
-    fn decl_block(&mut self, bv: BlockVariant) -> BlockId {
+    // Declare a new block and get an index for it, or
+    // None if the store holds as many as it may.
+    fn decl_block(&mut self, bv: BlockVariant) -> Option<BlockId> {
         let len = self.decl_blocks.len() as u32;
         if len >= Self::MAX_DECL_BLOCKS {
-            panic!("Too many declared blocks.");
+            return None;
         }
         let id = BlockId(len);
         self.decl_blocks.push(Block::new(id, bv));
-        id
+        Some(id)
     }
 
+    pub(crate) fn num_loops(&self) -> u16 { self.num_loops }
+    pub(crate) fn num_starts(&self) -> u16 { self.num_starts }
+
     pub(crate) fn total_blocks(&self) -> usize {
         self.decl_blocks.len()
     }
@@ -300,46 +350,95 @@ impl BlockStore {
     {
         self.decl_blocks.iter()
     }
+    // The ids of the blocks in specification order.
+    pub(crate) fn rpo_len(&self) -> usize {
+        self.rpo_index.len()
+    }
+    pub(crate) fn iter_rpo_ids(&self)
+      -> impl Iterator<Item=BlockId> + '_
+    {
+        self.rpo_index.iter().copied()
+    }
+    pub(crate) fn iter_rpo_blocks(&self)
+      -> impl Iterator<Item=&Block>
+    {
+        self.rpo_index.iter().map(move |&id| unsafe {
+            self.get_block(id)
+        })
+    }
+
+    // The block whose instruction range holds `id`.
+    // Blocks are laid out in RPO, so this is a binary
+    // search of the RPO index by first instruction.
+    pub(crate) fn block_containing(&self, id: InstrId)
+      -> Option<BlockId>
+    {
+        let rpo_block = |ord: usize| unsafe {
+            self.get_block(*self.rpo_index.get(ord).unwrap())
+        };
+        let (mut lo, mut hi) = (0, self.rpo_index.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if rpo_block(mid).first_instr <= id {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        let block = rpo_block(lo.checked_sub(1) ?);
+        if block.has_finished() && id <= block.last_instr {
+            Some(block.id)
+        } else {
+            None
+        }
+    }
 
     pub(crate) fn decl_plain_block(
         &mut self, num_phis: u32)
-      -> BlockId
+      -> Option<BlockId>
     {
         let id = self.decl_block(
-          BlockVariant::Plain { num_phis });
+          BlockVariant::Plain { num_phis }) ?;
 
         // Update `total_phis` to reflect the phis in
         // this block.
         self.total_phis += num_phis;
 
-        id
+        Some(id)
     }
-    pub(crate) fn decl_start_block(&mut self) -> BlockId {
+    pub(crate) fn decl_start_block(&mut self)
+      -> Option<BlockId>
+    {
         // Assign a new start block number.
         let start_no = self.num_starts;
+        if start_no == Self::MAX_STARTS {
+            return None;
+        }
         let id = self.decl_block(
-          BlockVariant::Start { start_no });
+          BlockVariant::Start { start_no }) ?;
         self.num_starts += 1;
-        id
+        Some(id)
     }
     pub(crate) fn decl_loop_head(&mut self, num_phis: u32)
-      -> BlockId
+      -> Option<BlockId>
     {
         // Assign a new loop number.
         let loop_no = self.num_loops;
 
         // Restrict loop_no from being 0xffff, because
         // that's the sentinel "uninitialized" value.
-        assert!(loop_no < u16::max_value());
+        if loop_no == Self::MAX_LOOPS {
+            return None;
+        }
 
         let id = self.decl_block(
-          BlockVariant::Loop { num_phis, loop_no });
+          BlockVariant::Loop { num_phis, loop_no }) ?;
         self.num_loops += 1;
 
         // Update total phis to include this block's phis.
         self.total_phis += num_phis;
 
-        id
+        Some(id)
     }
 
     pub(crate) unsafe fn get_block(&self, id: BlockId)
@@ -363,22 +462,38 @@ impl BlockStore {
     pub(crate) unsafe fn enter_block(
         &mut self, id: BlockId, first_ins: InstrId)
     {
-        // Compute global ordering of block.
-        let order: u32 = self.rpo_index.len() as u32;
-
         // Mark new block as entered.
-        self.get_mut_block(id)
-            .set_entered(order, first_ins);
-
-        // Add the id of the block to the RPO vec.
-        debug_assert!(! self.rpo_index.contains(&id));
-        self.rpo_index.push(id);
+        self.get_mut_block(id).set_entered(first_ins);
 
         // Set the current block.
         self.cur_block_id = id;
 
-        debug!("Enter block id={} first_ins={} ord={}",
-               id.as_u32(), first_ins, order);
+        debug!("Enter block id={} first_ins={}",
+               id.as_u32(), first_ins);
+    }
+
+    // Build the RPO index from the entered blocks, and
+    // number each block by its position in it.
+    pub(crate) fn build_rpo_index(&mut self) {
+        let mut entries: Vec<(InstrId, BlockId)> =
+          self.decl_blocks.iter()
+              .filter(|block| block.has_entered())
+              .map(|block| (block.first_instr, block.id))
+              .collect();
+        entries.sort_unstable();
+
+        self.rpo_index = match self.storage() {
+          BlockStorage::Contiguous => {
+            ChunkedVec::contiguous(entries.len())
+          }
+          BlockStorage::Chunked => {
+            ChunkedVec::chunked(Self::CHUNK_SHIFT)
+          }
+        };
+        for (order, &(_, id)) in entries.iter().enumerate() {
+            unsafe { self.get_mut_block(id).order = order as u32 };
+            self.rpo_index.push(id);
+        }
     }
 
     // Finish specifying a block.
@@ -396,4 +511,32 @@ impl BlockStore {
         // Update the block state.
         self.get_mut_block(id).set_loop_complete();
     }
+
+    // Count the edges into each block, as given by
+    // their targets, for a store rebuilt without them.
+    pub(crate) fn count_input_edges(&mut self,
+                                    targets: &[BlockId])
+    {
+        for &target in targets {
+            assert!((target.0 as usize) < self.decl_blocks.len());
+            unsafe { self.get_mut_block(target).incr_input_edges() };
+        }
+    }
+
+    // Move an edge's input from one block to another.
+    pub(crate) fn move_input_edge(&mut self, from: BlockId,
+                                  to: BlockId)
+    {
+        assert!((from.0 as usize) < self.decl_blocks.len()
+                  && (to.0 as usize) < self.decl_blocks.len());
+        unsafe {
+            self.get_mut_block(from).decr_input_edges();
+            self.get_mut_block(to).incr_input_edges();
+        }
+    }
+
+    pub(crate) fn mark_cold(&mut self, id: BlockId) {
+        assert!((id.0 as usize) < self.decl_blocks.len());
+        unsafe { self.get_mut_block(id).cold = true; }
+    }
 }
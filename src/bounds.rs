@@ -0,0 +1,271 @@
+
+use std::collections::HashMap;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::changes::Change;
+use crate::ops::{ Op, ConstOp, BiniKind, BoundsCheckOp, Operation };
+use crate::ir_types::IrTypeId;
+
+/*
+ * Bounds check elimination.  A check is redundant, and
+ * is elided in place, when either
+ *
+ * - range analysis proves its index below its length.
+ *   The unsigned range of each value is found in stream
+ *   order, from constants, from `and`, `or` and `xor`,
+ *   from `add`, `sub` and `mul` that cannot wrap, and
+ *   from earlier checks, whose values are below their
+ *   lengths.  Phis and params may hold anything; or
+ *
+ * - a check of the same index against the same length
+ *   dominates it, where the value of a check is taken
+ *   as the index it checks.
+ *
+ * An elided check only gives back its index, and the
+ * ids of the graph's instructions stay as they were.
+ */
+
+/** What `Graph::eliminate_bounds_checks` found. */
+#[derive(Clone, Copy, Debug, Default)]
+#[derive(PartialEq, Eq)]
+pub struct BoundsCheckStats {
+    checks: u32,
+    by_range: u32,
+    by_dominance: u32
+}
+
+impl BoundsCheckStats {
+    // The checks in reachable blocks, elided or not.
+    pub fn checks(&self) -> u32 { self.checks }
+
+    // The checks elided by range analysis, and by a
+    // dominating check.
+    pub fn by_range(&self) -> u32 { self.by_range }
+    pub fn by_dominance(&self) -> u32 { self.by_dominance }
+
+    pub fn elided(&self) -> u32 { self.by_range + self.by_dominance }
+}
+
+// An inclusive range of unsigned values.
+#[derive(Clone, Copy, Debug)]
+struct Range { lo: u64, hi: u64 }
+
+impl Range {
+    fn exact(v: u64) -> Range { Range { lo: v, hi: v } }
+
+    // Every value of the type.
+    fn full(ty: IrTypeId) -> Range {
+        let hi = match ty {
+          IrTypeId::Bool => 1,
+          IrTypeId::Int32 => u32::MAX as u64,
+          IrTypeId::Int64 | IrTypeId::PtrInt => u64::MAX
+        };
+        Range { lo: 0, hi }
+    }
+
+    // The range, if it holds no values the type cannot.
+    fn within(self, ty: IrTypeId) -> Option<Range> {
+        if self.hi <= Range::full(ty).hi { Some(self) } else { None }
+    }
+}
+
+fn const_bits(op: &ConstOp) -> u64 {
+    match *op {
+      ConstOp::Bool(b) => b as u64,
+      ConstOp::Int32(i) => i as u64,
+      ConstOp::Int64(i) | ConstOp::PtrInt(i) => i
+    }
+}
+
+// The smallest all-ones value covering `v`.
+fn smear(v: u64) -> u64 {
+    if v == 0 { 0 } else { u64::MAX >> v.leading_zeros() }
+}
+
+fn bini_range(kind: BiniKind, ty: IrTypeId, a: Range, b: Range)
+  -> Range
+{
+    let range = match kind {
+      BiniKind::And => Some(Range { lo: 0, hi: a.hi.min(b.hi) }),
+      BiniKind::Or => {
+        Some(Range { lo: a.lo.max(b.lo), hi: smear(a.hi | b.hi) })
+      }
+      BiniKind::Xor => Some(Range { lo: 0, hi: smear(a.hi | b.hi) }),
+      BiniKind::Add => a.hi.checked_add(b.hi).map(|hi| {
+          Range { lo: a.lo + b.lo, hi }
+      }),
+      BiniKind::Sub if a.lo >= b.hi => {
+        Some(Range { lo: a.lo - b.hi, hi: a.hi - b.lo })
+      }
+      BiniKind::Sub => None,
+      BiniKind::Mul => a.hi.checked_mul(b.hi).map(|hi| {
+          Range { lo: a.lo * b.lo, hi }
+      })
+    };
+    range.and_then(|r| r.within(ty)).unwrap_or(Range::full(ty))
+}
+
+// The checks of each index and length, with their
+// blocks.
+type CheckTable = HashMap<(InstrId, InstrId), Vec<(BlockId, InstrId)>>;
+
+// How a check came to be redundant.
+enum Proof { Range, Dominance }
+
+impl Graph {
+    /**
+     * Elide the bounds checks proven redundant by range
+     * analysis or by a dominating check of the same
+     * index and length.  Checks in unreachable blocks
+     * are left alone.
+     */
+    pub fn eliminate_bounds_checks(&mut self) -> BoundsCheckStats {
+        let dom = self.dominators();
+
+        let mut ranges: HashMap<InstrId, Range> = HashMap::new();
+        // The index each check's value stands for.
+        let mut roots: HashMap<InstrId, InstrId> = HashMap::new();
+        // The checks of each index and length so far.
+        let mut seen: CheckTable = HashMap::new();
+        let mut stats = BoundsCheckStats::default();
+        let mut redundant = Vec::new();
+
+        for block in self.block_store().iter_rpo_blocks() {
+            if !dom.is_reachable(block.id()) {
+                continue;
+            }
+            for info in self.block_instrs(block) {
+                let id = info.defn().instr_id();
+                let inputs: Vec<InstrId> =
+                  info.inputs_iter().map(|d| d.instr_id()).collect();
+                let range_of = |v: InstrId, ty| {
+                    ranges.get(&v).cloned().unwrap_or(Range::full(ty))
+                };
+                let range = match info.op() {
+                  Op::Const(op) => Range::exact(const_bits(op)),
+                  Op::Cmp(_) => Range::full(IrTypeId::Bool),
+                  Op::Bini(op) => {
+                    let ty = op.tyid();
+                    bini_range(op.kind(), ty, range_of(inputs[0], ty),
+                               range_of(inputs[1], ty))
+                  }
+                  Op::BoundsCheck(check) => {
+                    let ty = check.tyid();
+                    let (index, len) = (inputs[0], inputs[1]);
+                    let (ri, rl) = (range_of(index, ty),
+                                    range_of(len, ty));
+                    let root = roots.get(&index).cloned()
+                                    .unwrap_or(index);
+                    let earlier = seen.entry((root, len)).or_default();
+                    let proof = if ri.hi < rl.lo {
+                        Some(Proof::Range)
+                    } else if earlier.iter().any(|&(b, c)| {
+                        dom.instr_dominates(b, c, block.id(), id)
+                    }) {
+                        Some(Proof::Dominance)
+                    } else {
+                        None
+                    };
+                    earlier.push((block.id(), id));
+                    roots.insert(id, root);
+
+                    stats.checks += 1;
+                    if !check.elided() {
+                        match proof {
+                          Some(Proof::Range) => stats.by_range += 1,
+                          Some(Proof::Dominance) => {
+                            stats.by_dominance += 1
+                          }
+                          None => {}
+                        }
+                        if proof.is_some() {
+                            redundant.push((id, ty));
+                        }
+                    }
+                    let hi = ri.hi.min(rl.hi.saturating_sub(1));
+                    Range { lo: ri.lo.min(hi), hi }
+                  }
+                  op => match op.out_type() {
+                    Some(ty) if op.defines_value() => Range::full(ty),
+                    _ => continue
+                  }
+                };
+                ranges.insert(id, range);
+            }
+        }
+
+        for (id, ty) in redundant {
+            let elided = BoundsCheckOp::new(ty, true).op();
+            self.record(Change::Payload { instr: id });
+            self.instr_store_mut().rewrite_payload(id, &elided);
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::Graph;
+
+    #[test]
+    fn elides_checks_proven_or_dominated() {
+        let mut graph = Graph::parse_ir("\
+block0:
+  %0 = param.i32 0
+  %1 = param.i32 1
+  %2 = boundscheck.i32 %0, %1
+  %3 = param.bool 2
+  %4 = const.i32 3
+  %5 = const.i32 10
+  %6 = boundscheck.i32 %4, %5
+  branch %3, block1, block2
+block1:
+  %7 = boundscheck.i32 %2, %1
+  %8 = boundscheck.i32 %1, %0
+  jump block3
+block2:
+  jump block3
+block3:
+  %9 = boundscheck.i32 %1, %0
+  ret.i32 %9
+").unwrap();
+        let stats = graph.eliminate_bounds_checks();
+        assert_eq!((stats.checks(), stats.by_range(), stats.by_dominance()),
+                   (5, 1, 1));
+
+        // The check in block1 does not dominate block3,
+        // so the check there stays.
+        let text = graph.print_ir();
+        assert!(text.contains("%6 = boundscheck.i32.elided %4, %5"));
+        assert!(text.contains("%7 = boundscheck.i32.elided %2, %1"));
+        assert!(text.contains("%8 = boundscheck.i32 %1, %0"));
+        assert!(text.contains("%9 = boundscheck.i32 %1, %0"));
+        assert!(graph.verify().is_ok());
+    }
+
+    #[test]
+    fn wrapping_add_proves_no_bound() {
+        // The sum wraps to 1, but its range is not known,
+        // so the check is not elided; the sum that cannot
+        // wrap is below the length.
+        let mut graph = Graph::parse_ir("\
+block0:
+  %0 = const.i32 4294967295
+  %1 = const.i32 2
+  %2 = add.i32 %0, %1
+  %3 = const.i32 10
+  %4 = boundscheck.i32 %2, %3
+  %5 = add.i32 %1, %1
+  %6 = boundscheck.i32 %5, %3
+  ret.i32 %6
+").unwrap();
+        let stats = graph.eliminate_bounds_checks();
+        assert_eq!((stats.checks(), stats.by_range()), (2, 1));
+        let text = graph.print_ir();
+        assert!(text.contains("%4 = boundscheck.i32 %2, %3"));
+        assert!(text.contains("%6 = boundscheck.i32.elided %5, %3"));
+        assert!(graph.verify().is_ok());
+    }
+}
@@ -1,23 +1,117 @@
 
+use std::error::Error;
+use std::fmt;
 use std::mem;
 
-use crate::block::{ Block, BlockId, BlockRef, BlockStore };
-use crate::ops::Operation;
-use crate::instr::{ InstrId, InstrStore };
+use crate::block::{
+    Block, BlockId, BlockRef, BlockStore, BlockStorage
+};
+use crate::ops::{ Operation, Op };
+use crate::instr::{
+    InstrId, InstrStore, EncodeOp, OperandEncoding, StreamGrowth
+};
 use crate::defn::{ Defn, TypedDefn };
-use crate::graph::Graph;
+use crate::graph::{ Graph, MemoryUsage };
+use crate::lint::{ self, BuildLint };
+use crate::snapshot::Snapshot;
 
 use crate::ops::{
     NopOp, PhiOp,
     ConstOp,
     CmpOp, CmpKind,
     BiniOp, BiniKind,
-    RetOp, JumpOp, BranchOp
+    RetOp, JumpOp, BranchOp,
+    ParamOp, GuardOp, ExitOp, PatchPointOp, BoundsCheckOp,
+    NullCheckOp, LoadOp, StoreOp, PtrAddOp, CopyOp
 };
 use crate::ir_types::{
-    IrOutputType, IrType,
-    BoolTy, Int32Ty, Int64Ty,
+    IrType, IrTypeId, IrInputTypeId,
+    IrPtrType, BoolTy, Int32Ty, Int64Ty, PtrIntTy, PtrTy
 };
+use crate::type_desc::{ TypeTable, DescId };
+use crate::target::TargetDesc;
+
+/** A limit of the builder's stores. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum BuildLimit {
+    // Declared blocks.
+    Blocks,
+
+    // Loop headers.
+    Loops,
+
+    // Start blocks.
+    Starts,
+
+    // Bytes of instruction stream.
+    InstrBytes,
+
+    // Bytes of a fixed slab of instruction stream, of
+    // the given size.
+    InstrSlab(usize),
+
+    // Instructions.
+    Instrs
+}
+
+impl BuildLimit {
+    // The most the limit allows.
+    pub fn max(&self) -> usize {
+        match self {
+          BuildLimit::Blocks => BlockStore::MAX_DECL_BLOCKS as usize,
+          BuildLimit::Loops => BlockStore::MAX_LOOPS as usize,
+          BuildLimit::Starts => BlockStore::MAX_STARTS as usize,
+          BuildLimit::InstrBytes => InstrStore::MAX_INSTR_BYTES,
+          BuildLimit::InstrSlab(size) => *size,
+          BuildLimit::Instrs => u32::MAX as usize
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+          BuildLimit::Blocks => "block",
+          BuildLimit::Loops => "loop",
+          BuildLimit::Starts => "start block",
+          BuildLimit::InstrBytes => "instruction byte",
+          BuildLimit::InstrSlab(_) => "instruction slab byte",
+          BuildLimit::Instrs => "instruction"
+        }
+    }
+}
+
+/**
+ * Why a build failed.  Once a build fails, the rest
+ * of the build function runs without effect, handed
+ * placeholder blocks and values.
+ */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum BuildError {
+    // A store outgrew a limit: which limit, the size
+    // of the store before the emission, and the
+    // emission that would have exceeded it.
+    LimitExceeded {
+        limit: BuildLimit,
+        size: usize,
+        emission: String
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match self {
+          BuildError::LimitExceeded { limit, size, emission } => {
+            write!(f, "{} limit of {} exceeded at {} by {}",
+                   limit.name(), limit.max(), size, emission)
+          }
+        }
+    }
+}
+
+impl Error for BuildError {}
 
 pub struct Builder {
     // The instruction store.
@@ -34,32 +128,166 @@ pub struct Builder {
     // returning to the parent graph, and
     // the segments within this vector themselves are
     // tracked by subgraph_decls.
-    subgraph_decls: Vec<BlockId>
+    subgraph_decls: Vec<BlockId>,
+
+    // When enabled, the type of every value defined so
+    // far, in instruction order, used to check operand
+    // types as each instruction is emitted.
+    shadow_types: Option<Vec<(InstrId, IrTypeId)>>,
+
+    // The snapshots attached to guards and exits so
+    // far, in instruction order.
+    snapshots: Vec<(InstrId, Snapshot)>,
+
+    // The machine the graph is built for.
+    target: TargetDesc,
+
+    // The error that failed the build, if any.
+    error: Option<BuildError>
 }
 
 impl Builder {
     const SUBGRAPH_DECLS_CAP: usize = 8;
 
     pub fn new() -> Builder {
+        Builder::with_encoding(OperandEncoding::Absolute)
+    }
+
+    // A builder writing operands in the given encoding.
+    pub fn with_encoding(encoding: OperandEncoding) -> Builder {
+        Builder::with_storage(encoding, BlockStorage::Contiguous)
+    }
+
+    // A builder writing operands in the given encoding,
+    // holding its blocks in the given storage.
+    pub fn with_storage(encoding: OperandEncoding,
+                        storage: BlockStorage)
+      -> Builder
+    {
         debug!("SizeOf(Block) = {}",
                mem::size_of::<Block>());
 
-        let instr_store = InstrStore::new();
-        let block_store = BlockStore::new();
+        let instr_store = InstrStore::new(encoding);
+        let block_store = BlockStore::with_storage(storage);
         let subgraph_decls =
           Vec::with_capacity(Self::SUBGRAPH_DECLS_CAP);
 
-        Builder { instr_store, block_store, subgraph_decls }
+        Builder {
+            instr_store, block_store, subgraph_decls,
+            shadow_types: None,
+            snapshots: Vec::new(),
+            target: TargetDesc::default(),
+            error: None
+        }
+    }
+
+    pub fn into_graph(self) -> Graph {
+        let mut graph = Graph::new(self.instr_store, self.block_store);
+        graph.set_snapshots(self.snapshots);
+        graph.set_target(self.target);
+        graph
     }
 
-    pub(crate) fn into_graph(self) -> Graph {
-        Graph::new(self.instr_store, self.block_store)
+    // Finish the graph with its instruction side-index
+    // built.
+    pub fn into_graph_indexed(self) -> Graph {
+        let mut graph = self.into_graph();
+        graph.build_instr_index();
+        graph
+    }
+
+    // Finish the graph, also reporting phis that never
+    // receive arguments and blocks that are never
+    // targeted.
+    pub fn into_graph_linted(self) -> (Graph, Vec<BuildLint>) {
+        let graph = self.into_graph();
+        let lints = lint::build_lints(&graph);
+        (graph, lints)
     }
 
     pub(crate) fn build<F>(f: F) -> Builder
         where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        Self::build_impl(Builder::new(), f)
+    }
+
+    // Build, giving an error rather than panicking if
+    // a limit is exceeded.
+    pub(crate) fn try_build<F>(f: F) -> Result<Builder, BuildError>
+        where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        Self::try_build_impl(Builder::new(), f)
+    }
+
+    pub(crate) fn try_build_with<F>(encoding: OperandEncoding,
+                                    storage: BlockStorage, f: F)
+      -> Result<Builder, BuildError>
+        where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        let builder = Builder::with_storage(encoding, storage);
+        Self::try_build_impl(builder, f)
+    }
+
+    pub(crate) fn build_encoded<F>(encoding: OperandEncoding, f: F)
+      -> Builder
+        where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        Self::build_impl(Builder::with_encoding(encoding), f)
+    }
+
+    pub(crate) fn build_with<F>(encoding: OperandEncoding,
+                                storage: BlockStorage, f: F)
+      -> Builder
+        where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        Self::build_impl(Builder::with_storage(encoding, storage), f)
+    }
+
+    // Build for the given target rather than the
+    // default.
+    pub(crate) fn build_for<F>(target: TargetDesc, f: F) -> Builder
+        where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        let mut builder = Builder::new();
+        builder.target = target;
+        Self::build_impl(builder, f)
+    }
+
+    // Build with the given encoding, and the
+    // instruction stream allocated and grown as `growth`
+    // says.
+    pub(crate) fn try_build_grown<F>(encoding: OperandEncoding,
+                                     growth: StreamGrowth, f: F)
+      -> Result<Builder, BuildError>
+        where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        let mut builder = Builder::with_encoding(encoding);
+        builder.instr_store = InstrStore::with_growth(encoding, growth);
+        Self::try_build_impl(builder, f)
+    }
+
+    // Build with shadow type tracking, so that operands
+    // of the wrong type (e.g. through a bad
+    // `TypedDefn::cast`) panic at the emission site.
+    pub(crate) fn build_checked<F>(f: F) -> Builder
+        where F: for<'x> FnOnce (&mut BuildSession<'x>)
     {
         let mut builder = Builder::new();
+        builder.shadow_types = Some(Vec::new());
+        Self::build_impl(builder, f)
+    }
+
+    fn build_impl<F>(builder: Builder, f: F) -> Builder
+        where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        Self::try_build_impl(builder, f)
+          .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn try_build_impl<F>(mut builder: Builder, f: F)
+      -> Result<Builder, BuildError>
+        where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
         let start_block = builder.block_store
                                  .start_block_id();
 
@@ -69,6 +297,10 @@ impl Builder {
                             /* emitted_phis = */ 0);
         f(&mut sess);
 
+        if let Some(err) = builder.error.take() {
+            return Err(err);
+        }
+
         // At the end of the session, all blocks must
         // be completely specified.
         for bl_ref in builder.block_store.iter_blocks() {
@@ -83,7 +315,25 @@ impl Builder {
             }
         }
 
-        builder
+        Ok(builder)
+    }
+
+    // The heap bytes the builder holds so far.  Its
+    // side tables are the constant pool, the queue of
+    // declared blocks and any shadow types.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let pool = self.instr_store.const_pool().heap_bytes();
+        let decls = self.subgraph_decls.capacity()
+                      * mem::size_of::<BlockId>();
+        let shadow = self.shadow_types.as_ref().map_or(0, |t| {
+            t.capacity() * mem::size_of::<(InstrId, IrTypeId)>()
+        });
+        MemoryUsage {
+            instr_stream: self.instr_store.heap_bytes(),
+            block_table: self.block_store.block_table_bytes(),
+            rpo_index: self.block_store.rpo_index_bytes(),
+            side_tables: pool + decls + shadow
+        }
     }
 
     pub fn dump_stats(&self, name: &'static str) {
@@ -94,6 +344,10 @@ impl Builder {
     }
 }
 
+impl Default for Builder {
+    fn default() -> Builder { Builder::new() }
+}
+
 /**
  * A graph build is parameterized around the lifetime
  * of a build session.
@@ -181,10 +435,92 @@ impl<'bs> BuildSession<'bs> {
           == self.subgraph_decls_len()
     }
 
+    pub(crate) fn cur_block_id(&self) -> BlockId {
+        self.cur_block.id()
+    }
+
     fn get_cur_block(&self) -> &Block {
         self.get_block(self.cur_block)
     }
 
+    // Whether the build has failed.  The rest of a
+    // failed session runs without effect.
+    pub(crate) fn failed(&self) -> bool {
+        self.builder.error.is_some()
+    }
+
+    pub(crate) fn instr_store(&self) -> &InstrStore {
+        &self.builder.instr_store
+    }
+
+    // The machine the graph is built for.
+    pub fn target(&self) -> TargetDesc {
+        self.builder.target
+    }
+
+    pub(crate) fn attach_snapshot(&mut self, instr: InstrId,
+                                  snapshot: Snapshot)
+    {
+        if !self.failed() {
+            self.builder.snapshots.push((instr, snapshot));
+        }
+    }
+
+    // Stand-ins handed out once the build has failed.
+    fn placeholder_block() -> BlockRef<'bs> {
+        BlockRef::new(BlockId::new(0))
+    }
+    fn placeholder_instr() -> InstrId {
+        InstrId::from_offset(0)
+    }
+
+    fn fail(&mut self, limit: BuildLimit, size: usize,
+            emission: String)
+    {
+        debug_assert!(!self.failed());
+        debug!("Build failed: {} limit at {} by {}",
+               limit.name(), size, emission);
+        self.builder.error = Some(BuildError::LimitExceeded {
+            limit, size, emission
+        });
+    }
+
+    // Fail on a declaration the block store refused,
+    // blaming the block limit or the count given.
+    fn decl_refused(&mut self, count: (BuildLimit, usize),
+                    emission: String)
+      -> BlockRef<'bs>
+    {
+        let total = self.builder.block_store.total_blocks();
+        let (limit, size) =
+          if total >= BuildLimit::Blocks.max() {
+              (BuildLimit::Blocks, total)
+          } else {
+              count
+          };
+        self.fail(limit, size, emission);
+        Self::placeholder_block()
+    }
+
+    // Fail on an instruction the instruction store
+    // refused, given the stream length before it.
+    fn emit_refused<OP: EncodeOp>(&mut self, op: &OP, size: usize)
+      -> InstrId
+    {
+        let num_instrs = self.builder.instr_store.num_instrs();
+        if num_instrs as usize >= BuildLimit::Instrs.max() {
+            self.fail(BuildLimit::Instrs, num_instrs as usize,
+                      op.to_string());
+        } else {
+            let limit = match self.builder.instr_store.slab_size() {
+              Some(slab) => BuildLimit::InstrSlab(slab),
+              None => BuildLimit::InstrBytes
+            };
+            self.fail(limit, size, op.to_string());
+        }
+        Self::placeholder_instr()
+    }
+
     fn next_spec_block(&self) -> BlockRef<'bs> {
         let idx = self.subgraph_cur_end();
         debug_assert!(idx < self.subgraph_decls_len());
@@ -201,20 +537,65 @@ impl<'bs> BuildSession<'bs> {
     pub fn decl_plain_block(&mut self, num_phis: u32)
       -> BlockRef<'bs>
     {
-        let id = self.builder.block_store
-                     .decl_plain_block(num_phis);
+        if self.failed() {
+            return Self::placeholder_block();
+        }
+        let id = match self.builder.block_store
+                           .decl_plain_block(num_phis)
+        {
+          Some(id) => id,
+          None => {
+            let total = self.builder.block_store.total_blocks();
+            return self.decl_refused(
+              (BuildLimit::Blocks, total),
+              format!("decl_plain_block({})", num_phis));
+          }
+        };
         self.builder.subgraph_decls.push(id);
         debug!("Decl plain block phis={} id={}",
                num_phis, id.as_u32());
         BlockRef::new(id)
     }
 
+    /**
+     * Declare a new cold block, for a rarely taken path
+     * such as a slow path or bailout.  Block layout
+     * places cold blocks after the others, and trace
+     * extraction leaves the trace rather than enter
+     * them.
+     */
+    pub fn decl_cold_block(&mut self, num_phis: u32)
+      -> BlockRef<'bs>
+    {
+        let block = self.decl_plain_block(num_phis);
+        self.mark_cold(block);
+        block
+    }
+
+    // Mark a declared block as cold.
+    pub fn mark_cold(&mut self, block: BlockRef<'bs>) {
+        if self.failed() {
+            return;
+        }
+        self.builder.block_store.mark_cold(block.id());
+    }
+
     // Declare a start block.
     pub fn decl_start_block(&mut self)
       -> BlockRef<'bs>
     {
-        let id = self.builder.block_store
-                     .decl_start_block();
+        if self.failed() {
+            return Self::placeholder_block();
+        }
+        let id = match self.builder.block_store.decl_start_block() {
+          Some(id) => id,
+          None => {
+            let starts = self.builder.block_store.num_starts();
+            return self.decl_refused(
+              (BuildLimit::Starts, starts as usize),
+              "decl_start_block()".to_string());
+          }
+        };
         self.builder.subgraph_decls.push(id);
         BlockRef::new(id)
     }
@@ -223,8 +604,20 @@ impl<'bs> BuildSession<'bs> {
     pub fn decl_loop_head(&mut self, num_phis: u32)
       -> BlockRef<'bs>
     {
-        let id = self.builder.block_store
-                     .decl_loop_head(num_phis);
+        if self.failed() {
+            return Self::placeholder_block();
+        }
+        let id = match self.builder.block_store
+                           .decl_loop_head(num_phis)
+        {
+          Some(id) => id,
+          None => {
+            let loops = self.builder.block_store.num_loops();
+            return self.decl_refused(
+              (BuildLimit::Loops, loops as usize),
+              format!("decl_loop_head({})", num_phis));
+          }
+        };
         self.builder.subgraph_decls.push(id);
         BlockRef::new(id)
     }
@@ -257,6 +650,10 @@ impl<'bs> BuildSession<'bs> {
     // Enter the next block.  The current block
     // must have been finished with a block-end instr.
     pub fn def_block(&mut self, block: BlockRef<'bs>) {
+        if self.failed() {
+            return;
+        }
+
         // Ensure that the block being entered is
         // not a loop block.  Loops must be
         // defined with `def_loop`.
@@ -277,11 +674,19 @@ impl<'bs> BuildSession<'bs> {
         let (new_block_id, r) = {
             let mut sub_sess: BuildSession<'cs> =
               BuildSession::new(
-                &mut self.builder,
+                self.builder,
                 cur_block,
                 emitted_phis);
             let r = f(&mut sub_sess);
-            sub_sess.assert_complete();
+            if !sub_sess.failed() {
+                sub_sess.assert_complete();
+            }
+
+            // Every block the sub-session declared has
+            // been entered, so pop them off the queue to
+            // leave this session's segment at its end.
+            let start = sub_sess.subgraph_start as usize;
+            sub_sess.builder.subgraph_decls.truncate(start);
 
             (sub_sess.cur_block.id(), r)
         };
@@ -318,6 +723,10 @@ impl<'bs> BuildSession<'bs> {
         loop_block: BlockRef<'bs>, f: F) -> R
       where F: FnOnce (&mut BuildSession<'cs>) -> R
     {
+        if self.failed() {
+            return self.def_subgraph(f);
+        }
+
         // Ensure that the block being entered is a loop.
         assert!(self.get_block(loop_block).is_loop());
 
@@ -327,6 +736,9 @@ impl<'bs> BuildSession<'bs> {
         // Immediately enter a subgraph.
         self.def_subgraph(move |cs| {
             let result = f(cs);
+            if cs.failed() {
+                return result;
+            }
 
             // When defining a loop subgraph, the entire
             // subgraph must be complete by the return
@@ -350,41 +762,79 @@ impl<'bs> BuildSession<'bs> {
         })
     }
 
+    // With shadow types enabled, check each operand
+    // against the type its operation expects.
+    fn shadow_check<OP: EncodeOp>(&self, op: &OP, operands: &[Defn]) {
+        let types = match self.builder.shadow_types {
+          Some(ref types) => types,
+          None => return
+        };
+        for (i, operand) in operands.iter().enumerate() {
+            let id = operand.instr_id();
+            let found = match types.binary_search_by_key(&id, |e| e.0) {
+              Ok(j) => types[j].1,
+              Err(_) => panic!("{}: operand {} {} defines no value",
+                               op, i, id)
+            };
+            let expected = op.encode_operand_type(i as u32);
+            if let IrInputTypeId::Specific(expected) = expected {
+                assert!(found == expected,
+                        "{}: operand {} {} is {}, expected {}",
+                        op, i, id, found.as_str(), expected.as_str());
+            }
+        }
+    }
+
+    fn shadow_record<OP: EncodeOp>(&mut self, id: InstrId, op: &OP) {
+        if let Some(ref mut types) = self.builder.shadow_types {
+            if let Some(ty) = op.encode_out_type() {
+                types.push((id, ty));
+            }
+        }
+    }
+
     fn emit_instr_impl<'cs: 'bs, OP>(&mut self,
         op: OP, operands: &[Defn<'cs>])
-      -> Option<InstrId>
-      where OP: Operation
+      -> InstrId
+      where OP: EncodeOp
     {
-        debug_assert!(! OP::terminal());
+        if self.failed() {
+            return Self::placeholder_instr();
+        }
+        debug_assert!(! op.encode_terminal());
         assert!(! self.get_cur_block().has_finished());
+        self.shadow_check(&op, operands);
 
         // Add the instruction to the instr store.
+        let size = self.builder.instr_store.instr_bytes_len();
         let instr_id =
-          self.builder.instr_store.emit_instr(
-            &op, operands) ?;
+          match self.builder.instr_store.emit_instr(&op, operands) {
+            Some(id) => id,
+            None => return self.emit_refused(&op, size)
+          };
+        self.shadow_record(instr_id, &op);
 
         // No changes need to be made to the block store.
 
-        Some(instr_id)
+        instr_id
     }
 
     fn emit_defn<'cs: 'bs, OP, T>(&mut self,
         op: OP, operands: &[Defn<'cs>])
-      -> Option<TypedDefn<'bs, T>>
+      -> TypedDefn<'bs, T>
       where OP: Operation,
             T: IrType
     {
         debug_assert!(op.out_type() == Some(T::ID));
-        let ins = self.emit_instr_impl(op, operands) ?;
-        Some(TypedDefn::new(ins))
+        TypedDefn::new(self.emit_instr_impl(op, operands))
     }
 
     fn emit_nodef<'cs: 'bs, OP>(&mut self,
         op: OP, operands: &[Defn<'cs>])
-      -> Option<InstrId>
+      -> InstrId
       where OP: Operation
     {
-        debug_assert!(op.out_type() == None);
+        debug_assert!(op.out_type().is_none());
         self.emit_instr_impl(op, operands)
     }
 
@@ -392,43 +842,92 @@ impl<'bs> BuildSession<'bs> {
         op: OP,
         operands: &[Defn<'cs>],
         targets: &[(BlockRef<'cs>, &[Defn<'cs>])])
-      -> Option<InstrId>
-      where OP: Operation
+      -> InstrId
+      where OP: EncodeOp
     {
-        debug_assert!(OP::terminal());
+        if self.failed() {
+            return Self::placeholder_instr();
+        }
+        debug_assert!(op.encode_terminal());
         assert!(! self.get_cur_block().has_finished());
+        self.shadow_check(&op, operands);
 
         // Add the instruction to the instr store.
-        let instr_id =
-          self.builder.instr_store.emit_end(
-            &op, operands, targets) ?;
+        let size = self.builder.instr_store.instr_bytes_len();
+        let instr_id = match self.builder.instr_store
+                                 .emit_end(&op, operands, targets)
+        {
+          Some(id) => id,
+          None => return self.emit_refused(&op, size)
+        };
 
-        // Mark the block as finished.
+        // Mark the block as finished, and count its edges
+        // into their targets.
         unsafe {
-            self.builder.block_store.finish_block(
-              self.cur_block.id(), instr_id);
+            let store = &mut self.builder.block_store;
+            store.finish_block(self.cur_block.id(), instr_id);
+            for (target, _) in targets {
+                store.get_mut_block(target.id()).incr_input_edges();
+            }
         }
 
-        Some(instr_id)
+        instr_id
+    }
+
+    // Emit a dynamically described non-terminal
+    // instruction, as when copying instructions out
+    // of an existing graph.
+    pub(crate) fn emit_op<'cs: 'bs>(&mut self,
+        op: &Op, operands: &[Defn<'cs>])
+      -> Defn<'bs>
+    {
+        debug_assert!(op.num_inputs() as usize == operands.len());
+        if let (Op::Phi(_), false) = (op, self.failed()) {
+            debug_assert!(self.emitted_phis
+                            < self.get_cur_block().num_phis());
+            self.emitted_phis += 1;
+        }
+        Defn::new(self.emit_instr_impl(op.clone(), operands))
+    }
+
+    // Emit a dynamically described block-end
+    // instruction.
+    pub(crate) fn emit_op_end<'cs: 'bs>(&mut self,
+        op: &Op,
+        operands: &[Defn<'cs>],
+        targets: &[(BlockRef<'cs>, &[Defn<'cs>])])
+      -> InstrId
+    {
+        debug_assert!(op.num_inputs() as usize == operands.len());
+        self.emit_end(op.clone(), operands, targets)
     }
 
     pub fn emit_nop(&mut self) {
-        self.emit_nodef(NopOp::new(), &[]).unwrap();
+        self.emit_nodef(NopOp::new(), &[]);
     }
     pub fn emit_const_bool(&mut self, b: bool)
       -> TypedDefn<'bs, BoolTy>
     {
-        self.emit_defn(ConstOp::new_bool(b), &[]).unwrap()
+        self.emit_defn(ConstOp::new_bool(b), &[])
     }
     pub fn emit_const_int32(&mut self, i: u32)
       -> TypedDefn<'bs, Int32Ty>
     {
-        self.emit_defn(ConstOp::new_int32(i), &[]).unwrap()
+        self.emit_defn(ConstOp::new_int32(i), &[])
     }
     pub fn emit_const_int64(&mut self, i: u64)
       -> TypedDefn<'bs, Int64Ty>
     {
-        self.emit_defn(ConstOp::new_int64(i), &[]).unwrap()
+        self.emit_defn(ConstOp::new_int64(i), &[])
+    }
+
+    // Emit a constant pointer, such as null.  The
+    // verifier rejects values wider than the target's
+    // pointers.
+    pub fn emit_const_ptr(&mut self, p: u64)
+      -> TypedDefn<'bs, PtrIntTy>
+    {
+        self.emit_defn(ConstOp::new_ptr_int(p), &[])
     }
 
     pub fn emit_cmp<'cs: 'bs, T: IrType>(&mut self,
@@ -440,7 +939,7 @@ impl<'bs> BuildSession<'bs> {
         let lhs = lhs.untyped_defn();
         let rhs = rhs.untyped_defn();
         self.emit_defn(
-          CmpOp::new(kind, T::ID), &[lhs, rhs]).unwrap()
+          CmpOp::new(kind, T::ID), &[lhs, rhs])
     }
     pub fn emit_lt<'cs: 'bs, T: IrType>(&mut self,
         lhs: TypedDefn<'cs, T>,
@@ -494,7 +993,7 @@ impl<'bs> BuildSession<'bs> {
         let lhs = lhs.untyped_defn();
         let rhs = rhs.untyped_defn();
         self.emit_defn(
-          BiniOp::new(kind, T::ID), &[lhs, rhs]).unwrap()
+          BiniOp::new(kind, T::ID), &[lhs, rhs])
     }
     pub fn emit_add<'cs: 'bs, T: IrType>(&mut self,
         lhs: TypedDefn<'cs, T>,
@@ -542,11 +1041,136 @@ impl<'bs> BuildSession<'bs> {
     pub fn emit_phi<T: IrType>(&mut self)
       -> TypedDefn<'bs, T>
     {
+        if self.failed() {
+            return TypedDefn::new(Self::placeholder_instr());
+        }
         assert!(! self.get_cur_block().has_finished());
         debug_assert!(self.emitted_phis
                         < self.get_cur_block().num_phis());
         self.emitted_phis += 1;
-        self.emit_defn(PhiOp::new(T::ID), &[]).unwrap()
+        self.emit_defn(PhiOp::new(T::ID), &[])
+    }
+
+    // Emit an incoming parameter.  Params belong at the
+    // head of the start block.
+    pub fn emit_param<T: IrType>(&mut self, index: u32)
+      -> TypedDefn<'bs, T>
+    {
+        debug_assert!(self.failed() || self.get_cur_block().is_start());
+        self.emit_defn(ParamOp::new(index, T::ID), &[])
+    }
+
+    // Emit a guard that side-exits unless `cond` has
+    // the expected value.
+    pub fn emit_guard<'cs: 'bs>(&mut self,
+        cond: TypedDefn<'cs, BoolTy>, expected: bool)
+    {
+        self.emit_nodef(GuardOp::new(expected),
+                        &[cond.untyped_defn()]);
+    }
+
+    // Emit a check that `index` is below `length`, as
+    // unsigned integers, giving back the index, with a
+    // side exit when it is not.
+    pub fn emit_bounds_check<'cs: 'bs, T: IrType>(&mut self,
+        index: TypedDefn<'cs, T>, length: TypedDefn<'cs, T>)
+      -> TypedDefn<'bs, T>
+    {
+        self.emit_defn(BoundsCheckOp::new(T::ID, false),
+                       &[index.untyped_defn(), length.untyped_defn()])
+    }
+
+    // Emit a check that a pointer is not null, giving
+    // it back, with a side exit when it is.
+    pub fn emit_null_check<'cs: 'bs, P: IrPtrType>(&mut self,
+        ptr: TypedDefn<'cs, P>)
+      -> TypedDefn<'bs, P>
+    {
+        self.emit_defn(NullCheckOp::new(false), &[ptr.untyped_defn()])
+    }
+
+    // Emit a load of a value of any type from the
+    // memory an untyped pointer points to.
+    pub fn emit_load<'cs: 'bs, T: IrType>(&mut self,
+        ptr: TypedDefn<'cs, PtrIntTy>)
+      -> TypedDefn<'bs, T>
+    {
+        self.emit_defn(LoadOp::new(T::ID), &[ptr.untyped_defn()])
+    }
+
+    // Emit a store of a value of any type to the
+    // memory an untyped pointer points to.
+    pub fn emit_store<'cs: 'bs, T: IrType>(&mut self,
+        ptr: TypedDefn<'cs, PtrIntTy>, val: TypedDefn<'cs, T>)
+    {
+        self.emit_nodef(StoreOp::new(T::ID),
+                        &[ptr.untyped_defn(), val.untyped_defn()]);
+    }
+
+    // Emit a load through a typed pointer, of the type
+    // it points to.
+    pub fn emit_load_from<'cs: 'bs, T: IrType>(&mut self,
+        ptr: TypedDefn<'cs, PtrTy<T>>)
+      -> TypedDefn<'bs, T>
+    {
+        self.emit_load(ptr.untyped_ptr())
+    }
+
+    // Emit a store through a typed pointer, of a value
+    // of the type it points to.
+    pub fn emit_store_to<'cs: 'bs, T: IrType>(&mut self,
+        ptr: TypedDefn<'cs, PtrTy<T>>, val: TypedDefn<'cs, T>)
+    {
+        self.emit_store(ptr.untyped_ptr(), val);
+    }
+
+    // Emit the address of member `member` of the
+    // aggregate `index` places past the one `ptr` points
+    // to, laid out as descriptor `desc` of `types`.
+    // Panics if the descriptor has no such member, or
+    // the table is laid out for another target.
+    pub fn emit_ptr_add<'cs: 'bs, P: IrPtrType>(&mut self,
+        types: &TypeTable, desc: DescId, member: u32,
+        ptr: TypedDefn<'cs, P>, index: TypedDefn<'cs, Int64Ty>)
+      -> TypedDefn<'bs, PtrIntTy>
+    {
+        assert!(types.target() == self.builder.target,
+                "type table laid out for another target");
+        let layout = types.desc(desc).and_then(|d| {
+            d.member(member).map(|(offset, _)| (offset, d.stride()))
+        });
+        let (offset, stride) = layout.unwrap_or_else(|| {
+            panic!("descriptor {} has no member {}",
+                   desc.as_u32(), member)
+        });
+        self.emit_defn(PtrAddOp::new(desc.as_u32(), member, offset,
+                                     stride),
+                       &[ptr.untyped_defn(), index.untyped_defn()])
+    }
+
+    // Emit a copy of a value.
+    pub fn emit_copy<'cs: 'bs, T: IrType>(&mut self,
+        val: TypedDefn<'cs, T>)
+      -> TypedDefn<'bs, T>
+    {
+        self.emit_defn(CopyOp::new(T::ID), &[val.untyped_defn()])
+    }
+
+    // Emit a guard whose side exit resumes the frontend
+    // in the frame state of `snapshot`.
+    pub fn emit_guard_with<'cs: 'bs>(&mut self,
+        cond: TypedDefn<'cs, BoolTy>, expected: bool,
+        snapshot: Snapshot)
+    {
+        let id = self.emit_nodef(GuardOp::new(expected),
+                                 &[cond.untyped_defn()]);
+        self.attach_snapshot(id, snapshot);
+    }
+
+    // Reserve `size` bytes of space to be patched later,
+    // under the client-chosen `id`.
+    pub fn emit_patch_point(&mut self, id: u32, size: u32) {
+        self.emit_nodef(PatchPointOp::new(id, size), &[]);
     }
 
     pub fn ret<'cs: 'bs, T: IrType>(&mut self,
@@ -554,14 +1178,28 @@ impl<'bs> BuildSession<'bs> {
     {
         self.emit_end(
           RetOp::new(T::ID), &[val.untyped_defn()],
-          /* targets = */ &[]).unwrap();
+          /* targets = */ &[]);
+    }
+
+    // End the block with a side exit from the graph.
+    pub fn exit(&mut self) {
+        self.emit_end(ExitOp::new(), &[],
+          /* targets = */ &[]);
+    }
+
+    // End the block with a side exit that resumes the
+    // frontend in the frame state of `snapshot`.
+    pub fn exit_with(&mut self, snapshot: Snapshot) {
+        let id = self.emit_end(ExitOp::new(), &[],
+          /* targets = */ &[]);
+        self.attach_snapshot(id, snapshot);
     }
 
     pub fn jump<'cs: 'bs>(&mut self,
         target: BlockRef<'cs>, phis: &[Defn<'cs>])
     {
         self.emit_end(JumpOp::new(), &[],
-          /* targets = */ &[(target, phis)]).unwrap();
+          /* targets = */ &[(target, phis)]);
     }
 
     pub fn branch<'cs: 'bs>(&mut self,
@@ -577,6 +1215,6 @@ impl<'bs> BuildSession<'bs> {
         self.emit_end(BranchOp::new(), &[bit],
           /* targets = */ &[
             (if_true, true_phis),
-            (if_false, false_phis)]).unwrap();
+            (if_false, false_phis)]);
     }
 }
@@ -0,0 +1,141 @@
+
+use std::error::Error;
+use std::fmt;
+
+use crate::block::BlockId;
+use crate::instr::{ InstrId, InstrStore };
+use crate::graph::Graph;
+use crate::decode::decode_instrs_pooled;
+use crate::serialize::{ LoadError, write_const_pool };
+
+/*
+ * The canonical encoding of a graph is the one
+ * `to_bytes` writes.  The loader already rejects
+ * overlong leb128 values, unknown opcodes, types and
+ * kinds, and trailing bytes, but accepts a few other
+ * spellings of the same graph, such as a boolean
+ * payload byte other than 0 or 1.  Bytes that load
+ * are certified by writing the loaded graph out again,
+ * re-encoding every instruction from its decoded form,
+ * and comparing.
+ */
+
+/**
+ * A reason serialized bytes are not in the canonical
+ * encoding.
+ */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum CanonicalError {
+    // The bytes do not load at all.
+    Load(LoadError),
+
+    // The bytes load, but differ from the canonical
+    // encoding of the same graph from this offset on.
+    // A compressed container is never canonical.
+    NotCanonical { offset: usize }
+}
+
+impl CanonicalError {
+    // The offset of the deviation, where it is known.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+          CanonicalError::Load(err) => err.offset(),
+          CanonicalError::NotCanonical { offset } => Some(*offset)
+        }
+    }
+}
+
+impl fmt::Display for CanonicalError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match self {
+          CanonicalError::Load(err) => write!(f, "{}", err),
+          CanonicalError::NotCanonical { offset } =>
+            write!(f, "non-canonical encoding at offset {}", offset)
+        }
+    }
+}
+
+impl Error for CanonicalError {}
+
+impl Graph {
+    /**
+     * Check that serialized bytes are exactly what
+     * `to_bytes` writes for the graph they hold, giving
+     * the offset of the first byte that is not.
+     */
+    pub fn validate_canonical(bytes: &[u8])
+      -> Result<(), CanonicalError>
+    {
+        let graph = Graph::from_bytes(bytes)
+                          .map_err(CanonicalError::Load) ?;
+        if !bytes.starts_with(b"CGIR") {
+            return Err(CanonicalError::NotCanonical { offset: 0 });
+        }
+
+        // The stream is copied verbatim, so this finds
+        // deviations in the header and block table.
+        let expected = graph.to_bytes();
+        if let Some(offset) = first_difference(bytes, &expected) {
+            return Err(CanonicalError::NotCanonical { offset });
+        }
+
+        // The constant pool is likewise copied, so it is
+        // checked against the pool the re-encoded stream
+        // builds, which holds exactly the constants worth
+        // pooling in order of first use.
+        let stream = graph.instr_bytes();
+        let stream_start = bytes.len() - 4 - stream.len();
+        let (canonical, pool) = canonical_stream(&graph);
+        let mut found = Vec::new();
+        let mut expected = Vec::new();
+        write_const_pool(graph.const_pool(), &mut found);
+        write_const_pool(&pool, &mut expected);
+        let pool_start = stream_start - found.len();
+        if let Some(offset) = first_difference(&found, &expected) {
+            return Err(CanonicalError::NotCanonical {
+                offset: pool_start + offset
+            });
+        }
+        if let Some(offset) = first_difference(stream, &canonical) {
+            return Err(CanonicalError::NotCanonical {
+                offset: stream_start + offset
+            });
+        }
+        Ok(())
+    }
+}
+
+fn first_difference(a: &[u8], b: &[u8]) -> Option<usize> {
+    match a.iter().zip(b).position(|(x, y)| x != y) {
+      Some(i) => Some(i),
+      None if a.len() != b.len() => Some(a.len().min(b.len())),
+      None => None
+    }
+}
+
+// Re-encode the graph's instruction stream from its
+// decoded instructions, giving the stream and the
+// constant pool it refers to.
+fn canonical_stream(graph: &Graph) -> (Vec<u8>, Vec<u64>) {
+    let encoding = graph.operand_encoding();
+    let decoded = decode_instrs_pooled(graph.instr_bytes(), encoding,
+                                       graph.const_pool())
+      .expect("loaded graph stream decodes");
+    let mut store = InstrStore::new(encoding);
+    for instr in &decoded {
+        let op = instr.op();
+        if !op.terminal() {
+            store.emit_instr(op, instr.inputs());
+            continue;
+        }
+        let targets: Vec<(BlockId, &[InstrId])> =
+          instr.targets().iter()
+               .map(|(block, args)| (*block, &args[..])).collect();
+        store.emit_end(op, instr.inputs(), &targets);
+    }
+    let pool = store.const_pool().values().to_vec();
+    (store.instr_bytes().to_vec(), pool)
+}
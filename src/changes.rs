@@ -0,0 +1,137 @@
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::def_use::UseSite;
+
+/*
+ * The change log.  Each edit a graph makes in place is
+ * logged, naming the instruction or block it touched,
+ * and the graph drops or updates the analyses it caches
+ * that the edit stales: an operand moves its use in the
+ * def-use index and drops liveness, an edge drops every
+ * analysis of the control flow, and a payload,
+ * snapshot or patch rewrite drops none.
+ *
+ * An edit that rebuilds the graph changes every id, so
+ * the log is cleared and a new epoch begun.  Analyses
+ * kept outside the graph subscribe by taking a mark,
+ * and later asking for the changes since it: none
+ * once the epoch has passed, when they must be
+ * computed anew.
+ */
+
+/** An edit made to a graph in place. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum Change {
+    // The use of `old` at a site of `user` now uses
+    // `new`.
+    Operand {
+        user: InstrId, site: UseSite, old: InstrId, new: InstrId
+    },
+
+    // The payload of an instruction was rewritten,
+    // keeping its opcode and operands.
+    Payload { instr: InstrId },
+
+    // The edge at an index of the end of a block was
+    // retargeted, or passes other arguments.
+    Edge { from: BlockId, index: u32 },
+
+    // The snapshot attached to an instruction names
+    // other values.
+    Snapshot { instr: InstrId },
+
+    // The space of a patch point instruction was
+    // written.
+    Patch { instr: InstrId }
+}
+
+/** A point in a graph's change log. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct ChangeMark {
+    epoch: u32,
+    len: usize
+}
+
+// The changes made to a graph in place since it was
+// last rebuilt.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ChangeLog {
+    epoch: u32,
+    changes: Vec<Change>
+}
+
+impl ChangeLog {
+    pub(crate) fn push(&mut self, change: Change) {
+        self.changes.push(change);
+    }
+
+    // Clear the log, its ids being gone.
+    pub(crate) fn rebuilt(&mut self) {
+        self.epoch += 1;
+        self.changes.clear();
+    }
+
+    pub(crate) fn mark(&self) -> ChangeMark {
+        ChangeMark { epoch: self.epoch, len: self.changes.len() }
+    }
+
+    pub(crate) fn since(&self, mark: ChangeMark) -> Option<&[Change]> {
+        if mark.epoch != self.epoch {
+            return None;
+        }
+        Some(&self.changes[mark.len ..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::BlockStorage;
+    use crate::instr::OperandEncoding;
+    use crate::def_use::UseSite;
+    use crate::text::parse_with;
+    use super::Change;
+
+    const DIAMOND: &str = "\
+block0:
+  %0 = const.i32 1
+  %1 = const.i32 2
+  %2 = lt.i32 %0, %1
+  branch %2, block1, block2
+block1:
+  jump block3(%0)
+block2:
+  jump block3(%1)
+block3(%3: i32):
+  ret.i32 %3
+";
+
+    #[test]
+    fn logs_in_place_edits_until_a_rebuild() {
+        let mut graph = parse_with(DIAMOND, OperandEncoding::Fixed,
+                                   BlockStorage::Contiguous);
+        let blocks: Vec<_> =
+          graph.rpo_block_views().map(|b| b.id()).collect();
+        let start = graph.block_view(blocks[0]).unwrap();
+        let one = start.instrs().next().unwrap().id();
+        let two = start.instrs().nth(1).unwrap().id();
+        let end = graph.block_view(blocks[2]).unwrap().end().id();
+
+        let mark = graph.change_mark();
+        assert_eq!(graph.changes_since(mark), Some(&[][..]));
+        assert!(!graph.set_phi_arg(blocks[2], 0, 0, one));
+        let site = UseSite::PhiArg { target: 0, index: 0 };
+        assert_eq!(graph.changes_since(mark), Some(&[
+            Change::Operand { user: end, site, old: two, new: one }
+        ][..]));
+
+        // Dropping block1 rebuilds the graph, ending the
+        // epoch the mark was taken in.
+        assert!(graph.retarget(blocks[0], 0, blocks[3], &[one]));
+        assert_eq!(graph.changes_since(mark), None);
+        let mark = graph.change_mark();
+        assert_eq!(graph.changes_since(mark), Some(&[][..]));
+        assert!(graph.verify().is_ok());
+    }
+}
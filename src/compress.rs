@@ -0,0 +1,123 @@
+
+use crate::decode::{ ByteReader, DecodeError };
+use crate::serialize::LoadError;
+use crate::leb128;
+
+/*
+ * A small LZ77 compressor for serialized graphs.  The
+ * compressed form is a sequence of (literals, match)
+ * pairs:
+ *
+ *   lit_len         leb128
+ *   literals        lit_len bytes
+ *   match_len       leb128, the length less MIN_MATCH
+ *   match_dist      leb128, back from the output end
+ *
+ * and ends after the literals of the pair that brings
+ * the output to its declared length.  Matches are
+ * found through a hash of the next MIN_MATCH bytes,
+ * keeping the last position of each hash.
+ */
+
+const MIN_MATCH: usize = 4;
+const MAX_DIST: usize = 1 << 16;
+const HASH_BITS: u32 = 14;
+
+fn hash(bytes: &[u8]) -> usize {
+    let mut word = [0_u8; MIN_MATCH];
+    word.copy_from_slice(&bytes[.. MIN_MATCH]);
+    let v = u32::from_le_bytes(word).wrapping_mul(0x9E37_79B1);
+    (v >> (32 - HASH_BITS)) as usize
+}
+
+pub(crate) fn compress(input: &[u8], out: &mut Vec<u8>) {
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut lit_start = 0;
+    let mut pos = 0;
+    while pos + MIN_MATCH <= input.len() {
+        let h = hash(&input[pos ..]);
+        let cand = table[h];
+        table[h] = pos;
+        let found = cand != usize::MAX
+          && pos - cand <= MAX_DIST
+          && input[cand .. cand + MIN_MATCH]
+               == input[pos .. pos + MIN_MATCH];
+        if !found {
+            pos += 1;
+            continue;
+        }
+
+        let mut len = MIN_MATCH;
+        while pos + len < input.len()
+          && input[cand + len] == input[pos + len]
+        {
+            len += 1;
+        }
+        emit_literals(&input[lit_start .. pos], out);
+        leb128::write_leb128u((len - MIN_MATCH) as u64, out);
+        leb128::write_leb128u((pos - cand) as u64, out);
+        pos += len;
+        lit_start = pos;
+    }
+    emit_literals(&input[lit_start ..], out);
+}
+
+fn emit_literals(literals: &[u8], out: &mut Vec<u8>) {
+    leb128::write_leb128u(literals.len() as u64, out);
+    out.extend_from_slice(literals);
+}
+
+// Expand compressed bytes, from untrusted input, to
+// exactly `len` bytes.  Offsets in errors are relative
+// to `bytes`.
+pub(crate) fn expand(bytes: &[u8], len: usize)
+  -> Result<Vec<u8>, LoadError>
+{
+    // Each literal takes a byte of input, and a match
+    // can only copy what is already output, so the
+    // output is not reserved beyond what the input can
+    // plausibly give.
+    let mut out = Vec::with_capacity(len.min(bytes.len() * 4));
+    let mut reader = ByteReader::new(bytes);
+    loop {
+        let offset = reader.offset();
+        let lit_len = reader.read_leb128u() ?;
+        if lit_len > ((len - out.len()) as u64) {
+            return Err(LoadError::BadCompression { offset });
+        }
+        if lit_len > (reader.remaining() as u64) {
+            return Err(DecodeError::Truncated { offset }.into());
+        }
+        let start = reader.offset();
+        let end = start + lit_len as usize;
+        out.extend_from_slice(&bytes[start .. end]);
+        reader = ByteReader::at(bytes, end);
+        if out.len() == len {
+            break;
+        }
+
+        let offset = reader.offset();
+        let match_len = reader.read_leb128u() ?;
+        let dist = reader.read_leb128u() ? as usize;
+        let room = (len - out.len()) as u64;
+        if match_len.saturating_add(MIN_MATCH as u64) > room
+          || dist == 0 || dist > out.len()
+        {
+            return Err(LoadError::BadCompression { offset });
+        }
+        let match_len = match_len as usize + MIN_MATCH;
+
+        // Matches may overlap their own output.
+        let from = out.len() - dist;
+        for i in 0 .. match_len {
+            let b = out[from + i];
+            out.push(b);
+        }
+    }
+    if !reader.at_end() {
+        return Err(LoadError::TrailingBytes {
+            offset: reader.offset()
+        });
+    }
+    Ok(out)
+}
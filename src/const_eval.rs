@@ -0,0 +1,217 @@
+
+use std::collections::{ HashMap, HashSet };
+
+use crate::instr::{ InstrId, InstrStore };
+use crate::defn::Defn;
+use crate::graph::Graph;
+use crate::builder::BuildSession;
+use crate::ops::{ Op, ConstOp, CmpKind, BiniKind };
+use crate::ir_types::IrTypeId;
+
+/**
+ * The value of a constant expression.  Int32 values
+ * wrap at 32 bits, and compare as signed integers, as
+ * do Int64 values; `false` orders before `true`.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash)]
+pub enum ConstValue {
+    Bool(bool),
+    Int32(u32),
+    Int64(u64),
+    PtrInt(u64)
+}
+
+impl ConstValue {
+    pub fn ty(&self) -> IrTypeId {
+        match self {
+          ConstValue::Bool(_) => IrTypeId::Bool,
+          ConstValue::Int32(_) => IrTypeId::Int32,
+          ConstValue::Int64(_) => IrTypeId::Int64,
+          ConstValue::PtrInt(_) => IrTypeId::PtrInt
+        }
+    }
+
+    // The value's bits, zero-extended.
+    pub fn as_u64(&self) -> u64 {
+        match *self {
+          ConstValue::Bool(b) => b as u64,
+          ConstValue::Int32(i) => i as u64,
+          ConstValue::Int64(i) | ConstValue::PtrInt(i) => i
+        }
+    }
+
+    fn of(op: &ConstOp) -> ConstValue {
+        match *op {
+          ConstOp::Bool(b) => ConstValue::Bool(b),
+          ConstOp::Int32(i) => ConstValue::Int32(i),
+          ConstOp::Int64(i) => ConstValue::Int64(i),
+          ConstOp::PtrInt(p) => ConstValue::PtrInt(p)
+        }
+    }
+
+    pub(crate) fn to_op(self) -> ConstOp {
+        match self {
+          ConstValue::Bool(b) => ConstOp::new_bool(b),
+          ConstValue::Int32(i) => ConstOp::new_int32(i),
+          ConstValue::Int64(i) => ConstOp::new_int64(i),
+          ConstValue::PtrInt(p) => ConstOp::new_ptr_int(p)
+        }
+    }
+
+    // The value as a signed integer, for comparison.
+    fn signed(&self) -> i64 {
+        match *self {
+          ConstValue::Bool(b) => b as i64,
+          ConstValue::Int32(i) => (i as i32) as i64,
+          ConstValue::Int64(i) | ConstValue::PtrInt(i) => i as i64
+        }
+    }
+
+    // A value of the given type from wrapping bits.
+    fn from_bits(ty: IrTypeId, bits: u64) -> Option<ConstValue> {
+        match ty {
+          IrTypeId::Bool => Some(ConstValue::Bool(bits & 1 != 0)),
+          IrTypeId::Int32 => Some(ConstValue::Int32(bits as u32)),
+          IrTypeId::Int64 => Some(ConstValue::Int64(bits)),
+          IrTypeId::PtrInt => None
+        }
+    }
+}
+
+fn eval_cmp(kind: CmpKind, lhs: ConstValue, rhs: ConstValue) -> bool {
+    let (a, b) = (lhs.signed(), rhs.signed());
+    match kind {
+      CmpKind::Lt => a < b, CmpKind::Gt => a > b,
+      CmpKind::Le => a <= b, CmpKind::Ge => a >= b,
+      CmpKind::Eq => a == b, CmpKind::Ne => a != b
+    }
+}
+
+fn eval_bini(kind: BiniKind, lhs: ConstValue, rhs: ConstValue) -> u64 {
+    let (a, b) = (lhs.as_u64(), rhs.as_u64());
+    match kind {
+      BiniKind::Add => a.wrapping_add(b),
+      BiniKind::Sub => a.wrapping_sub(b),
+      BiniKind::Mul => a.wrapping_mul(b),
+      BiniKind::And => a & b,
+      BiniKind::Or => a | b,
+      BiniKind::Xor => a ^ b
+    }
+}
+
+// Fold an operation over the values of its inputs,
+// which must be of the operation's type.
+fn fold(op: &Op, args: &[ConstValue]) -> Option<ConstValue> {
+    match op {
+      Op::Const(op) => Some(ConstValue::of(op)),
+      Op::Cmp(op) => {
+        if args.iter().any(|a| a.ty() != op.tyid()) {
+            return None;
+        }
+        Some(ConstValue::Bool(eval_cmp(op.kind(), args[0], args[1])))
+      }
+      Op::Bini(op) => {
+        if args.iter().any(|a| a.ty() != op.tyid()) {
+            return None;
+        }
+        let bits = eval_bini(op.kind(), args[0], args[1]);
+        ConstValue::from_bits(op.tyid(), bits)
+      }
+      Op::Copy(op) if args[0].ty() == op.tyid() => Some(args[0]),
+      _ => None
+    }
+}
+
+// Whether a value computed by the op can be constant:
+// it computes purely from its inputs, with no phi,
+// param or effect feeding in.
+fn foldable(op: &Op) -> bool {
+    matches!(op, Op::Const(_) | Op::Cmp(_) | Op::Bini(_)
+                   | Op::Copy(_))
+}
+
+/*
+ * Evaluate the computation tree under an instruction,
+ * walking it without recursion so that long chains
+ * cannot overflow the stack, and folding each shared
+ * subtree once.  Instructions are read with full
+ * checks, as ids may come from anywhere.
+ */
+pub(crate) fn eval_const(store: &InstrStore, root: InstrId)
+  -> Option<ConstValue>
+{
+    let mut values: HashMap<InstrId, ConstValue> = HashMap::new();
+    let mut expanded: HashSet<InstrId> = HashSet::new();
+    let mut stack = vec![root];
+    while let Some(&id) = stack.last() {
+        if values.contains_key(&id) {
+            stack.pop();
+            continue;
+        }
+        let info = store.try_read_instr_info(id).ok() ?;
+        if !foldable(info.op()) {
+            return None;
+        }
+        let inputs: Vec<InstrId> =
+          info.inputs_iter().map(|d| d.instr_id()).collect();
+        let pending: Vec<InstrId> =
+          inputs.iter().cloned()
+                .filter(|i| !values.contains_key(i))
+                .collect();
+        if !pending.is_empty() {
+            // An input still being evaluated below this
+            // one is a cycle, which no verified graph has.
+            if !expanded.insert(id)
+              || pending.iter().any(|i| expanded.contains(i))
+            {
+                return None;
+            }
+            stack.extend(pending);
+            continue;
+        }
+        let args: Vec<ConstValue> =
+          inputs.iter().map(|i| values[i]).collect();
+        values.insert(id, fold(info.op(), &args) ?);
+        stack.pop();
+    }
+    values.get(&root).cloned()
+}
+
+impl Graph {
+    /**
+     * Evaluate a definition, given as a `Defn` or the
+     * id of its instruction, whose computation tree is
+     * pure and rooted only in constants.  Gives None if
+     * anything else, such as a phi or param, feeds into
+     * it.
+     */
+    pub fn eval_const<D>(&self, defn: D) -> Option<ConstValue>
+      where D: Into<InstrId>
+    {
+        eval_const(self.instr_store(), defn.into())
+    }
+}
+
+impl<'bs> BuildSession<'bs> {
+    /**
+     * Evaluate a definition emitted so far, as with
+     * `Graph::eval_const`, so that constant expressions
+     * can be folded as they are built.
+     */
+    pub fn eval_const<'cs: 'bs>(&self, defn: Defn<'cs>)
+      -> Option<ConstValue>
+    {
+        if self.failed() {
+            return None;
+        }
+        eval_const(self.instr_store(), defn.instr_id())
+    }
+
+    // Emit the constant instruction for a value.
+    pub fn emit_const_value(&mut self, value: ConstValue)
+      -> Defn<'bs>
+    {
+        self.emit_op(&Op::Const(value.to_op()), &[])
+    }
+}
@@ -0,0 +1,67 @@
+
+use std::collections::HashMap;
+use std::mem;
+
+use crate::leb128;
+
+/**
+ * The large Int64 constants of an instruction stream.
+ * A constant whose value takes more than
+ * `MIN_POOLED_BYTES` of leb128 is written once into
+ * the pool, in order of first use, and each `ConstOp`
+ * of it refers to its index instead.
+ */
+#[derive(Clone, Default)]
+pub(crate) struct ConstPool {
+    values: Vec<u64>,
+    index: HashMap<u64, u32>
+}
+
+impl ConstPool {
+    // Values taking at least this many bytes are worth
+    // an index of at most three.
+    pub(crate) const MIN_POOLED_BYTES: usize = 4;
+
+    pub(crate) fn new() -> ConstPool {
+        ConstPool::default()
+    }
+
+    // Adopt the values of a pool read back from bytes
+    // that have been checked to be distinct.
+    pub(crate) fn from_values(values: Vec<u64>) -> ConstPool {
+        let index = values.iter().enumerate()
+                          .map(|(i, &v)| (v, i as u32))
+                          .collect();
+        ConstPool { values, index }
+    }
+
+    pub(crate) fn worth_pooling(value: u64) -> bool {
+        leb128::leb128u_len(value) >= Self::MIN_POOLED_BYTES
+    }
+
+    pub(crate) fn values(&self) -> &[u64] { &self.values }
+
+    pub(crate) fn index_of(&self, value: u64) -> Option<u32> {
+        self.index.get(&value).copied()
+    }
+
+    // The index of a value, adding it if it is new.
+    pub(crate) fn intern(&mut self, value: u64) -> u32 {
+        let next = self.values.len() as u32;
+        let values = &mut self.values;
+        *self.index.entry(value).or_insert_with(|| {
+            values.push(value);
+            next
+        })
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        let entry = mem::size_of::<(u64, u32)>();
+        self.values.capacity() * mem::size_of::<u64>()
+          + self.index.capacity() * entry
+    }
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.values.shrink_to_fit();
+        self.index.shrink_to_fit();
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::Arc;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::view::{ BlockView, InstrView };
+
+/**
+ * An owned cursor over a shared graph, positioned at an
+ * instruction of a block.  Unlike a `GraphSession` it
+ * borrows nothing, so it can be kept in a struct, moved
+ * between threads or across an await, and cloned to
+ * keep a position.  As an iterator it yields the
+ * instructions from its position on, block by block in
+ * specification order (RPO).
+ */
+#[derive(Clone)]
+pub struct GraphCursor {
+    // The underlying graph.
+    graph: Arc<Graph>,
+
+    // Current block being read.
+    block: BlockId,
+
+    // Current instruction being read, or none once the
+    // cursor has moved past the last block.
+    instr: Option<InstrId>
+}
+
+impl GraphCursor {
+    /** A cursor at the first instruction of the start block. */
+    pub fn new(graph: Arc<Graph>) -> GraphCursor {
+        let block = graph.start_block_id();
+        let instr = Some(graph.block(block).first_instr());
+        GraphCursor { graph, block, instr }
+    }
+
+    pub fn graph(&self) -> &Arc<Graph> { &self.graph }
+    pub fn into_graph(self) -> Arc<Graph> { self.graph }
+
+    // The current block.
+    pub fn block(&self) -> BlockId { self.block }
+
+    // The current instruction, or None past the last
+    // block.
+    pub fn instr(&self) -> Option<InstrId> { self.instr }
+
+    pub fn block_view(&self) -> BlockView<'_> {
+        BlockView::new(&self.graph, self.graph.block(self.block))
+    }
+    pub fn instr_view(&self) -> Option<InstrView<'_>> {
+        Some(InstrView::new(self.graph.instr(self.instr ?)))
+    }
+
+    /** Move to the first instruction of a block. */
+    pub fn seek_block(&mut self, block: BlockId) {
+        assert!(block.as_u32() < self.graph.num_blocks(),
+                "no block {}", block);
+        self.block = block;
+        self.instr = Some(self.graph.block(block).first_instr());
+    }
+
+    /**
+     * Move to the next instruction of the block,
+     * returning it.  At the block's end instruction,
+     * None is returned and the cursor stays.
+     */
+    pub fn next_instr(&mut self) -> Option<InstrId> {
+        let info = self.graph.instr(self.instr ?);
+        let next = info.next_defn() ?.instr_id();
+        self.instr = Some(next);
+        Some(next)
+    }
+
+    /**
+     * Move to the first instruction of the next block
+     * in RPO, returning the block.  Past the last
+     * block, None is returned and the cursor has no
+     * instruction.
+     */
+    pub fn next_block(&mut self) -> Option<BlockId> {
+        let order = self.graph.block(self.block).order() as usize;
+        let next = self.graph.block_store().iter_rpo_ids()
+                       .nth(order + 1);
+        match next {
+          Some(block) => self.seek_block(block),
+          None => self.instr = None
+        }
+        next
+    }
+}
+
+impl Iterator for GraphCursor {
+    type Item = (BlockId, InstrId);
+    fn next(&mut self) -> Option<(BlockId, InstrId)> {
+        let at = (self.block, self.instr ?);
+        if self.next_instr().is_none() {
+            self.next_block();
+        }
+        Some(at)
+    }
+}
@@ -0,0 +1,565 @@
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use crate::block::BlockId;
+use crate::instr::{ InstrId, InstrPosn, OffsetRepr, OperandEncoding };
+use crate::ops::{
+    Opcode, Operation, SpecializeOpcode, Op, DynOperation
+};
+use crate::ir_types::IrTypeId;
+use crate::leb128::{ self, Leb128Error };
+
+/**
+ * A problem found while decoding untrusted bytes,
+ * with the offset of the item that could not be
+ * decoded.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum DecodeError {
+    // The bytes ended in the middle of an item.
+    Truncated { offset: usize },
+
+    // A byte names no opcode.
+    BadOpcode { offset: usize, code: u8 },
+
+    // A byte names no IR type, or a type the
+    // operation does not accept.
+    BadType { offset: usize, code: u8 },
+
+    // A byte names no kind of the operation (e.g. an
+    // unknown comparison).
+    BadKind { offset: usize, code: u8 },
+
+    // A leb128 value does not fit the field it encodes.
+    Overflow { offset: usize },
+
+    // A leb128 value is not in its shortest encoding.
+    Overlong { offset: usize },
+
+    // A pooled constant names no entry of the
+    // constant pool.
+    BadPoolIndex { offset: usize, index: u32 }
+}
+
+impl DecodeError {
+    pub fn offset(&self) -> usize {
+        match *self {
+          DecodeError::Truncated { offset } => offset,
+          DecodeError::BadOpcode { offset, .. } => offset,
+          DecodeError::BadType { offset, .. } => offset,
+          DecodeError::BadKind { offset, .. } => offset,
+          DecodeError::Overflow { offset } => offset,
+          DecodeError::Overlong { offset } => offset,
+          DecodeError::BadPoolIndex { offset, .. } => offset
+        }
+    }
+
+    // The same error, with its offset moved by `base`,
+    // for bytes decoded out of a larger buffer.
+    pub(crate) fn offset_by(self, base: usize) -> DecodeError {
+        match self {
+          DecodeError::Truncated { offset } =>
+            DecodeError::Truncated { offset: base + offset },
+          DecodeError::BadOpcode { offset, code } =>
+            DecodeError::BadOpcode { offset: base + offset, code },
+          DecodeError::BadType { offset, code } =>
+            DecodeError::BadType { offset: base + offset, code },
+          DecodeError::BadKind { offset, code } =>
+            DecodeError::BadKind { offset: base + offset, code },
+          DecodeError::Overflow { offset } =>
+            DecodeError::Overflow { offset: base + offset },
+          DecodeError::Overlong { offset } =>
+            DecodeError::Overlong { offset: base + offset },
+          DecodeError::BadPoolIndex { offset, index } =>
+            DecodeError::BadPoolIndex { offset: base + offset, index }
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match *self {
+          DecodeError::Truncated { offset } =>
+            write!(f, "truncated at offset {}", offset),
+          DecodeError::BadOpcode { offset, code } =>
+            write!(f, "bad opcode {} at offset {}", code, offset),
+          DecodeError::BadType { offset, code } =>
+            write!(f, "bad type {} at offset {}", code, offset),
+          DecodeError::BadKind { offset, code } =>
+            write!(f, "bad kind {} at offset {}", code, offset),
+          DecodeError::Overflow { offset } =>
+            write!(f, "value overflow at offset {}", offset),
+          DecodeError::Overlong { offset } =>
+            write!(f, "overlong value at offset {}", offset),
+          DecodeError::BadPoolIndex { offset, index } =>
+            write!(f, "bad constant pool index {} at offset {}",
+                   index, offset)
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/**
+ * A problem found by `Graph::validate_stream`, with
+ * the offset of the first instruction at which the
+ * stream is inconsistent.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum StreamError {
+    // An instruction failed to decode, or the last one
+    // ran past the end of the stream.
+    Decode(DecodeError),
+
+    // An operand or phi argument names an instruction
+    // at or after its user.
+    ForwardOperand { offset: usize, operand: InstrId },
+
+    // An operand or phi argument names an offset that
+    // does not start an instruction.
+    NotAnInstr { offset: usize, operand: InstrId },
+
+    // The stream holds a different number of
+    // instructions than were emitted to it.
+    InstrCount { expected: u32, found: u32 }
+}
+
+impl StreamError {
+    pub fn offset(&self) -> usize {
+        match *self {
+          StreamError::Decode(err) => err.offset(),
+          StreamError::ForwardOperand { offset, .. } => offset,
+          StreamError::NotAnInstr { offset, .. } => offset,
+          StreamError::InstrCount { .. } => 0
+        }
+    }
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match *self {
+          StreamError::Decode(err) => write!(f, "{}", err),
+          StreamError::ForwardOperand { offset, operand } =>
+            write!(f, "forward operand {} at offset {}",
+                   operand.offset(), offset),
+          StreamError::NotAnInstr { offset, operand } =>
+            write!(f, "operand {} at offset {} is not an instruction",
+                   operand.offset(), offset),
+          StreamError::InstrCount { expected, found } =>
+            write!(f, "stream has {} instructions, expected {}",
+                   found, expected)
+        }
+    }
+}
+
+impl Error for StreamError {}
+
+/**
+ * A bounds-checked cursor over untrusted bytes.  Pooled
+ * constants are resolved in the reader's constant
+ * pool, which is empty unless one is given.
+ */
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    pool: &'a [u64]
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> ByteReader<'a> {
+        ByteReader { bytes, pos: 0, pool: &[] }
+    }
+    pub(crate) fn at(bytes: &'a [u8], pos: usize)
+      -> ByteReader<'a>
+    {
+        ByteReader { bytes, pos, pool: &[] }
+    }
+    pub(crate) fn with_pool(self, pool: &'a [u64])
+      -> ByteReader<'a>
+    {
+        ByteReader { pool, ..self }
+    }
+
+    pub(crate) fn offset(&self) -> usize { self.pos }
+    pub(crate) fn at_end(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+    pub(crate) fn remaining(&self) -> usize {
+        self.bytes.len().saturating_sub(self.pos)
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        let b = *self.bytes.get(self.pos).ok_or(
+          DecodeError::Truncated { offset: self.pos }) ?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    // Read `len` raw bytes.
+    pub(crate) fn read_bytes(&mut self, len: usize)
+      -> Result<&'a [u8], DecodeError>
+    {
+        let end = self.pos.checked_add(len)
+          .filter(|&end| end <= self.bytes.len())
+          .ok_or(DecodeError::Truncated { offset: self.pos }) ?;
+        let bytes = &self.bytes[self.pos .. end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn read_leb128u(&mut self)
+      -> Result<u64, DecodeError>
+    {
+        let offset = self.pos;
+        let rest = self.bytes.get(offset ..).unwrap_or(&[]);
+        let (nb, v) = leb128::read_leb128u_checked(rest)
+          .map_err(|err| match err {
+              Leb128Error::Truncated =>
+                DecodeError::Truncated { offset },
+              Leb128Error::Overlong =>
+                DecodeError::Overlong { offset },
+              Leb128Error::Overflow =>
+                DecodeError::Overflow { offset }
+          }) ?;
+        self.pos += nb;
+        Ok(v)
+    }
+
+    // Read a leb128 value that must fit in 32 bits.
+    pub(crate) fn read_leb128u32(&mut self)
+      -> Result<u32, DecodeError>
+    {
+        let offset = self.pos;
+        let v = self.read_leb128u() ?;
+        if v > (u32::MAX as u64) {
+            return Err(DecodeError::Overflow { offset });
+        }
+        Ok(v as u32)
+    }
+
+    // Read a leb128 value that must fit in an offset.
+    pub(crate) fn read_leb128_offset(&mut self)
+      -> Result<OffsetRepr, DecodeError>
+    {
+        let offset = self.pos;
+        let v = self.read_leb128u() ?;
+        OffsetRepr::try_from(v)
+          .map_err(|_| DecodeError::Overflow { offset })
+    }
+
+    // Read a constant pool index, giving the value of
+    // the entry it names.
+    pub(crate) fn read_pooled(&mut self)
+      -> Result<u64, DecodeError>
+    {
+        let offset = self.pos;
+        let index = self.read_leb128u32() ?;
+        self.pool.get(index as usize).copied()
+          .ok_or(DecodeError::BadPoolIndex { offset, index })
+    }
+
+    pub(crate) fn read_type_id(&mut self)
+      -> Result<IrTypeId, DecodeError>
+    {
+        let offset = self.pos;
+        IrTypeId::try_from(self.read_u8() ?)
+          .map_err(|code| DecodeError::BadType { offset, code })
+    }
+
+    // Read an instruction id, which must not be the
+    // reserved invalid position.
+    pub(crate) fn read_instr_id(&mut self)
+      -> Result<InstrId, DecodeError>
+    {
+        let offset = self.pos;
+        let v = self.read_leb128_offset() ?;
+        if v == InstrPosn::INVALID_VALUE {
+            return Err(DecodeError::Overflow { offset });
+        }
+        Ok(InstrId::new(InstrPosn::new(v)))
+    }
+
+    // Read an operand or target field written in the
+    // given encoding.
+    pub(crate) fn read_field(&mut self, encoding: OperandEncoding)
+      -> Result<OffsetRepr, DecodeError>
+    {
+        if encoding != OperandEncoding::Fixed {
+            return self.read_leb128_offset();
+        }
+        let n = OperandEncoding::FIXED_BYTES;
+        if self.remaining() < n {
+            return Err(DecodeError::Truncated { offset: self.pos });
+        }
+        let mut slot = [0_u8; OperandEncoding::FIXED_BYTES];
+        slot.copy_from_slice(&self.bytes[self.pos .. self.pos + n]);
+        self.pos += n;
+        Ok(OffsetRepr::from_le_bytes(slot))
+    }
+
+    // Read a target field that must fit in 32 bits.
+    // Unless offsets are wide, every field does.
+    #[allow(clippy::useless_conversion)]
+    pub(crate) fn read_field_u32(&mut self, encoding: OperandEncoding)
+      -> Result<u32, DecodeError>
+    {
+        let offset = self.pos;
+        let v = self.read_field(encoding) ?;
+        u32::try_from(v).map_err(|_| DecodeError::Overflow { offset })
+    }
+
+    // Read an operand of the instruction `user`, written
+    // in the given encoding.
+    pub(crate) fn read_operand(&mut self, user: InstrId,
+                               encoding: OperandEncoding)
+      -> Result<InstrId, DecodeError>
+    {
+        let offset = self.pos;
+        let v = encoding.decode(user, self.read_field(encoding) ?);
+        if v == InstrPosn::INVALID_VALUE {
+            return Err(DecodeError::Overflow { offset });
+        }
+        Ok(InstrId::new(InstrPosn::new(v)))
+    }
+}
+
+/**
+ * An instruction decoded from untrusted bytes, with
+ * its operands and targets owned.
+ */
+#[derive(Clone)]
+pub struct DecodedInstr {
+    id: InstrId,
+    op: Op,
+    inputs: Vec<InstrId>,
+    targets: Vec<(BlockId, Vec<InstrId>)>,
+
+    // The offsets ending the opcode and payload, and
+    // the operands, and the instruction.
+    header_end: usize,
+    operands_end: usize,
+    end: usize
+}
+
+impl DecodedInstr {
+    pub fn id(&self) -> InstrId { self.id }
+    pub fn opcode(&self) -> Opcode { self.op.opcode() }
+    pub fn inputs(&self) -> &[InstrId] { &self.inputs }
+
+    // The target blocks of an end instruction, each with
+    // the phi arguments passed to it.
+    pub fn targets(&self) -> &[(BlockId, Vec<InstrId>)] {
+        &self.targets
+    }
+
+    // The instruction's operation, with its immediates.
+    pub fn operation(&self) -> &dyn DynOperation { self.op.as_dyn() }
+
+    pub(crate) fn op(&self) -> &Op { &self.op }
+
+    // The heap bytes of the instruction, as held in a
+    // table of decoded instructions.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        let id_bytes = mem::size_of::<InstrId>();
+        let target_bytes = mem::size_of::<(BlockId, Vec<InstrId>)>();
+        mem::size_of::<DecodedInstr>()
+          + self.inputs.capacity() * id_bytes
+          + self.targets.capacity() * target_bytes
+          + self.targets.iter()
+                .map(|(_, args)| args.capacity() * id_bytes)
+                .sum::<usize>()
+    }
+
+    // The offsets ending the instruction's opcode and
+    // payload, its operands, and its targets.
+    pub(crate) fn byte_layout(&self) -> (usize, usize, usize) {
+        (self.header_end, self.operands_end, self.end)
+    }
+
+    // Decode the instruction at the reader's offset,
+    // with operands in the given encoding.
+    pub(crate) fn decode(reader: &mut ByteReader,
+                         encoding: OperandEncoding)
+      -> Result<DecodedInstr, DecodeError>
+    {
+        let offset = reader.offset();
+        if offset >= (InstrPosn::INVALID_VALUE as usize) {
+            return Err(DecodeError::Overflow { offset });
+        }
+        let id = InstrId::from_offset(offset);
+        let op = decode_op(reader) ?;
+        let header_end = reader.offset();
+
+        let mut inputs = Vec::new();
+        for _ in 0 .. op.num_inputs() {
+            inputs.push(reader.read_operand(id, encoding) ?);
+        }
+        let operands_end = reader.offset();
+
+        let mut targets = Vec::new();
+        for _ in 0 .. op.num_targets().unwrap_or(0) {
+            let block = BlockId::new(reader.read_field_u32(encoding) ?);
+            let nargs_offset = reader.offset();
+            let nargs = reader.read_field_u32(encoding) ? as usize;
+
+            // Every argument takes at least a byte, so a
+            // count beyond the remaining bytes can be
+            // rejected before allocating for it.
+            if nargs > reader.remaining() {
+                return Err(DecodeError::Truncated {
+                    offset: nargs_offset
+                });
+            }
+            let mut args = Vec::with_capacity(nargs);
+            for _ in 0 .. nargs {
+                args.push(reader.read_operand(id, encoding) ?);
+            }
+            targets.push((block, args));
+        }
+
+        Ok(DecodedInstr {
+            id, op, inputs, targets, header_end, operands_end,
+            end: reader.offset()
+        })
+    }
+}
+
+impl fmt::Display for DecodedInstr {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "{} {}(", self.id, self.op)?;
+        for (i, inp) in self.inputs.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", inp.offset())?;
+        }
+        write!(f, ")")?;
+        for (block, args) in &self.targets {
+            write!(f, " -> {}{:?}", block.as_u32(),
+                   args.iter().map(|a| a.offset())
+                       .collect::<Vec<_>>())?;
+        }
+        Ok(())
+    }
+}
+
+// Decode an opcode and the payload of its operation.
+pub(crate) fn decode_op(reader: &mut ByteReader)
+  -> Result<Op, DecodeError>
+{
+    let offset = reader.offset();
+    let opcode = Opcode::try_from(reader.read_u8() ?)
+      .map_err(|code| DecodeError::BadOpcode { offset, code }) ?;
+    opcode.specialize(DecodeSpec { reader })
+}
+
+struct DecodeSpec<'r, 'a> {
+    reader: &'r mut ByteReader<'a>
+}
+impl<'r, 'a> SpecializeOpcode<Result<Op, DecodeError>>
+  for DecodeSpec<'r, 'a>
+{
+    fn op<OP: Operation>(self) -> Result<Op, DecodeError> {
+        OP::decode(self.reader).map(|op| op.op())
+    }
+}
+
+/**
+ * Walks a raw instruction stream, outside of any
+ * graph, decoding each instruction with the checks of
+ * `decode_instrs`.  After an instruction fails to
+ * decode, the error is yielded and the walk ends.
+ */
+pub struct InstrDecoder<'a> {
+    reader: ByteReader<'a>,
+    encoding: OperandEncoding,
+    failed: bool
+}
+
+impl<'a> InstrDecoder<'a> {
+    // A decoder reading operands as absolute offsets.
+    pub fn new(bytes: &'a [u8]) -> InstrDecoder<'a> {
+        InstrDecoder::with_encoding(bytes, OperandEncoding::Absolute)
+    }
+
+    pub fn with_encoding(bytes: &'a [u8], encoding: OperandEncoding)
+      -> InstrDecoder<'a>
+    {
+        InstrDecoder {
+            reader: ByteReader::new(bytes),
+            encoding,
+            failed: false
+        }
+    }
+
+    // Resolve pooled constants in the given constant
+    // pool, such as that of `Graph::const_pool`.
+    pub fn with_pool(self, pool: &'a [u64]) -> InstrDecoder<'a> {
+        InstrDecoder { reader: self.reader.with_pool(pool), ..self }
+    }
+
+    // The offset of the next instruction to decode.
+    pub fn offset(&self) -> usize { self.reader.offset() }
+}
+
+impl<'a> Iterator for InstrDecoder<'a> {
+    type Item = Result<DecodedInstr, DecodeError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.at_end() {
+            return None;
+        }
+        let result = DecodedInstr::decode(&mut self.reader,
+                                          self.encoding);
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
+
+/**
+ * Decode a whole instruction stream from untrusted
+ * bytes, checking every opcode, payload, operand and
+ * target encoding.  This does not check that operands
+ * name real instructions; that is the verifier's job.
+ * Operands are read as absolute offsets.
+ */
+pub fn decode_instrs(bytes: &[u8])
+  -> Result<Vec<DecodedInstr>, DecodeError>
+{
+    decode_instrs_with(bytes, OperandEncoding::Absolute)
+}
+
+/**
+ * Decode a whole instruction stream as `decode_instrs`
+ * does, with operands in the given encoding.  Decoded
+ * operands are always absolute ids.
+ */
+pub fn decode_instrs_with(bytes: &[u8], encoding: OperandEncoding)
+  -> Result<Vec<DecodedInstr>, DecodeError>
+{
+    decode_instrs_pooled(bytes, encoding, &[])
+}
+
+/**
+ * Decode a whole instruction stream as
+ * `decode_instrs_with` does, resolving pooled
+ * constants in the given constant pool.  A constant
+ * naming no entry of the pool fails to decode.
+ */
+pub fn decode_instrs_pooled(bytes: &[u8], encoding: OperandEncoding,
+                            pool: &[u64])
+  -> Result<Vec<DecodedInstr>, DecodeError>
+{
+    InstrDecoder::with_encoding(bytes, encoding).with_pool(pool)
+                                                .collect()
+}
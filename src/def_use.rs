@@ -0,0 +1,121 @@
+use std::mem;
+
+use crate::instr::InstrId;
+use crate::graph::Graph;
+
+/**
+ * Where an instruction uses a value: as the operand at
+ * an index, or as the argument at an index passed to a
+ * phi of the target at an index of an end instruction.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum UseSite {
+    Operand(u32),
+    PhiArg { target: u32, index: u32 }
+}
+
+/** A use of a value by an instruction. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Use {
+    user: InstrId,
+    site: UseSite
+}
+
+impl Use {
+    pub fn user(&self) -> InstrId { self.user }
+    pub fn site(&self) -> UseSite { self.site }
+}
+
+/**
+ * The def-use index of a finished graph: every use of
+ * every value by an operand or phi argument, grouped
+ * by the value used, and within a value ordered by
+ * user and site.  Finding the uses of a value takes a
+ * binary search.  Uses by deopt snapshots are not
+ * instruction uses, and are not indexed.
+ */
+#[derive(Clone, Debug)]
+pub struct DefUses {
+    uses: Vec<(InstrId, Use)>
+}
+
+impl DefUses {
+    pub(crate) fn compute(graph: &Graph) -> DefUses {
+        let mut uses = Vec::new();
+        for block in graph.block_store().iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                let user = info.defn().instr_id();
+                for (i, def) in info.inputs_iter().enumerate() {
+                    let site = UseSite::Operand(i as u32);
+                    uses.push((def.instr_id(), Use { user, site }));
+                }
+                for (t, target) in info.targets_iter().enumerate() {
+                    for (i, def) in target.phi_args().enumerate() {
+                        let site = UseSite::PhiArg {
+                            target: t as u32, index: i as u32
+                        };
+                        uses.push((def.instr_id(), Use { user, site }));
+                    }
+                }
+            }
+        }
+        uses.sort_unstable();
+        DefUses { uses }
+    }
+
+    // Move the use at a site of `user` from `old` to
+    // `new`, keeping the uses sorted.
+    pub(crate) fn move_use(&mut self, user: InstrId, site: UseSite,
+                           old: InstrId, new: InstrId)
+    {
+        let u = Use { user, site };
+        if let Ok(i) = self.uses.binary_search(&(old, u)) {
+            self.uses.remove(i);
+        }
+        let i = self.uses.partition_point(|e| *e < (new, u));
+        self.uses.insert(i, (new, u));
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.uses.capacity() * mem::size_of::<(InstrId, Use)>()
+    }
+
+    // The number of uses of all values.
+    pub fn len(&self) -> usize { self.uses.len() }
+    pub fn is_empty(&self) -> bool { self.uses.is_empty() }
+
+    // The uses of the value defined by `def`, ordered
+    // by user and site.
+    pub fn uses(&self, def: InstrId) -> Uses<'_> {
+        let start = self.uses.partition_point(|u| u.0 < def);
+        let end = start + self.uses[start ..]
+                            .partition_point(|u| u.0 == def);
+        Uses { uses: self.uses[start .. end].iter() }
+    }
+
+    // Whether the value defined by `def` is used.
+    pub fn is_used(&self, def: InstrId) -> bool {
+        self.uses(def).next().is_some()
+    }
+}
+
+/** Iterates the uses of a value. */
+#[derive(Clone)]
+pub struct Uses<'a> {
+    uses: std::slice::Iter<'a, (InstrId, Use)>
+}
+
+impl<'a> Iterator for Uses<'a> {
+    type Item = Use;
+    fn next(&mut self) -> Option<Use> {
+        self.uses.next().map(|&(_, u)| u)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.uses.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for Uses<'a> {}
@@ -2,9 +2,8 @@
 use std::fmt;
 use std::marker::PhantomData;
 
-use crate::instr::{ InstrId, InstrPosn };
-use crate::ir_types::{ IrType, IrOutputType };
-use crate::leb128;
+use crate::instr::InstrId;
+use crate::ir_types::{ IrType, IrOutputType, PtrIntTy, PtrTy };
 
 /** A definition (just a reference to an instruction). */
 #[derive(Clone, Copy, Debug)]
@@ -18,14 +17,14 @@ impl<'a> Defn<'a> {
     pub fn instr_id(&self) -> InstrId { self.0 }
     pub fn as_u32(&self) -> u32 { self.0.as_u32() }
 }
-impl<'a> Into<InstrId> for Defn<'a> {
-    fn into(self) -> InstrId { self.0 }
+impl<'a> From<Defn<'a>> for InstrId {
+    fn from(defn: Defn<'a>) -> InstrId { defn.0 }
 }
 impl<'a> fmt::Display for Defn<'a> {
     fn fmt(&self, f: &mut fmt::Formatter)
       -> Result<(), fmt::Error>
     {
-        write!(f, "Def@{}", self.as_u32())
+        write!(f, "Def@{}", self.0.offset())
     }
 }
 
@@ -37,9 +36,7 @@ pub struct TypedDefn<'a, T: IrOutputType>
 impl<'a, T: IrOutputType> Clone
   for TypedDefn<'a, T>
 {
-    fn clone(&self) -> Self {
-        TypedDefn(self.0, Default::default())
-    }
+    fn clone(&self) -> Self { *self }
 }
 impl<'a, T: IrOutputType> Copy for TypedDefn<'a, T> {}
 
@@ -64,3 +61,18 @@ impl<'a, T> TypedDefn<'a, T>
         TypedDefn(self.0, Default::default())
     }
 }
+
+impl<'a, T: IrType> TypedDefn<'a, PtrTy<T>> {
+    // The pointer, as an untyped pointer.
+    pub fn untyped_ptr(&self) -> TypedDefn<'a, PtrIntTy> {
+        TypedDefn(self.0, Default::default())
+    }
+}
+
+impl<'a> TypedDefn<'a, PtrIntTy> {
+    // The pointer, as a pointer to values of type `T`,
+    // which the caller asserts it is.
+    pub fn points_to<T: IrType>(&self) -> TypedDefn<'a, PtrTy<T>> {
+        TypedDefn(self.0, Default::default())
+    }
+}
@@ -0,0 +1,427 @@
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::text::{ mnemonic, instr_lines };
+
+/*
+ * Graphs are diffed in two steps.  Blocks are aligned
+ * first, in RPO: blocks of the same kind holding the
+ * same sequence of operations are matched as a longest
+ * common subsequence, and the unmatched blocks between
+ * two matches are paired in order, with any left over
+ * inserted or removed.
+ *
+ * The instructions of each aligned pair are then
+ * matched the same way, in RPO order of the pairs.  Two
+ * instructions match when their operations are the
+ * same and each of their operands is the matched or
+ * changed counterpart of the other's, or the value at
+ * the same position in the block.  Since every operand
+ * is defined before its use in RPO, a value pairing
+ * found in one block carries to its uses after it, and
+ * an instruction that changes does not make its users
+ * change with it.
+ */
+
+/** The differences between two graphs, by aligned block. */
+#[derive(Clone, Debug)]
+pub struct GraphDiff {
+    blocks: Vec<BlockDiff>
+}
+
+/**
+ * A block of the old graph aligned with one of the new,
+ * named by their RPO positions.  An inserted block has
+ * no old position, and a removed block no new one.
+ */
+#[derive(Clone, Debug)]
+pub struct BlockDiff {
+    old: Option<u32>,
+    new: Option<u32>,
+
+    // The kind of each block, as prefixed to its name.
+    old_kind: &'static str,
+    new_kind: &'static str,
+
+    instrs: Vec<InstrDiff>
+}
+
+/**
+ * An instruction of an aligned block pair, with its text
+ * as `print_ir` prints it in its graph.
+ */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum InstrDiff {
+    Same { old: InstrId, new: InstrId, text: String },
+    Removed { old: InstrId, text: String },
+    Inserted { new: InstrId, text: String },
+    Changed {
+        old: InstrId, new: InstrId,
+        old_text: String, new_text: String
+    }
+}
+
+/**
+ * Diff two graphs, aligning the blocks and instructions
+ * of `new` with those of `old`.  Both graphs should
+ * pass `verify`.
+ */
+pub fn diff(old: &Graph, new: &Graph) -> GraphDiff {
+    Differ::new(old, new).diff()
+}
+
+impl GraphDiff {
+    pub fn blocks(&self) -> &[BlockDiff] { &self.blocks }
+
+    // Whether the graphs print the same.
+    pub fn is_empty(&self) -> bool {
+        self.blocks.iter().all(|b| !b.is_changed())
+    }
+}
+
+impl BlockDiff {
+    pub fn old_block(&self) -> Option<u32> { self.old }
+    pub fn new_block(&self) -> Option<u32> { self.new }
+    pub fn instrs(&self) -> &[InstrDiff] { &self.instrs }
+
+    pub fn is_changed(&self) -> bool {
+        self.old.is_none() || self.new.is_none()
+          || self.old_kind != self.new_kind
+          || self.instrs.iter().any(|i| !i.is_same())
+    }
+}
+
+impl InstrDiff {
+    pub fn is_same(&self) -> bool {
+        matches!(self, InstrDiff::Same { .. })
+    }
+}
+
+/*
+ * A diff prints each changed block under a line naming
+ * the aligned blocks, with the headers of both when
+ * they differ, and its instructions marked as in a
+ * unified diff.  Unchanged instructions are printed as
+ * they are in the new graph:
+ *
+ *   @@ block2 -> block2 @@
+ *      %4 = const.i32 1
+ *   -  %5 = add.i32 %2, %4
+ *   +  %5 = sub.i32 %2, %4
+ *      jump block1(%5)
+ */
+impl fmt::Display for GraphDiff {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        for block in self.blocks.iter().filter(|b| b.is_changed()) {
+            write!(f, "{}", block)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for BlockDiff {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        let name = |pos: Option<u32>| match pos {
+            Some(pos) => format!("block{}", pos),
+            None => "-".to_string()
+        };
+        writeln!(f, "@@ {} -> {} @@", name(self.old), name(self.new))?;
+        if let (Some(old), Some(new)) = (self.old, self.new) {
+            if self.old_kind != self.new_kind {
+                writeln!(f, "-{}block{}", self.old_kind, old)?;
+                writeln!(f, "+{}block{}", self.new_kind, new)?;
+            }
+        }
+        for instr in &self.instrs {
+            match instr {
+              InstrDiff::Same { text, .. } =>
+                writeln!(f, "   {}", text)?,
+              InstrDiff::Removed { text, .. } =>
+                writeln!(f, "-  {}", text)?,
+              InstrDiff::Inserted { text, .. } =>
+                writeln!(f, "+  {}", text)?,
+              InstrDiff::Changed { old_text, new_text, .. } => {
+                writeln!(f, "-  {}", old_text)?;
+                writeln!(f, "+  {}", new_text)?;
+              }
+            }
+        }
+        Ok(())
+    }
+}
+
+// An instruction, as compared by the differ.
+struct Item {
+    id: InstrId,
+    op: String,
+    defines_value: bool,
+    inputs: Vec<InstrId>,
+    targets: Vec<(u32, Vec<InstrId>)>
+}
+
+// A block, as compared by the differ.
+struct BlockItems {
+    kind: &'static str,
+    items: Vec<Item>,
+
+    // The position of each instruction in the block.
+    index: HashMap<InstrId, usize>
+}
+
+// The blocks of a graph in RPO, and the text of each of
+// its instructions.
+struct Side {
+    blocks: Vec<BlockItems>,
+    lines: HashMap<InstrId, String>
+}
+
+struct Differ {
+    old: Side,
+    new: Side,
+
+    // The new counterpart of each paired old value.
+    values: HashMap<InstrId, InstrId>,
+
+    // The new counterpart of each aligned old block, by
+    // RPO position.
+    block_map: Vec<Option<u32>>
+}
+
+// A pairing of two sequences, as found by `align`.
+enum Step {
+    Old(usize),
+    New(usize),
+    Pair(usize, usize)
+}
+
+impl Side {
+    fn new(graph: &Graph) -> Side {
+        let store = graph.block_store();
+        let mut rpo_pos = vec![u32::MAX; store.total_blocks()];
+        for (pos, block) in store.iter_rpo_blocks().enumerate() {
+            rpo_pos[block.id().as_u32() as usize] = pos as u32;
+        }
+
+        let blocks = store.iter_rpo_blocks().enumerate()
+                          .map(|(pos, block)| {
+            let kind = if block.is_loop() {
+                "loop "
+            } else if block.is_start() && pos > 0 {
+                "start "
+            } else {
+                ""
+            };
+            let items = graph.block_instrs(block).map(|info| Item {
+                id: info.defn().instr_id(),
+                op: mnemonic(info.op()),
+                defines_value: info.op().defines_value(),
+                inputs: info.inputs_iter().map(|d| d.instr_id())
+                            .collect(),
+                targets: info.targets_iter().map(|t| {
+                    let pos = rpo_pos[t.block_id().as_u32() as usize];
+                    (pos, t.phi_args().map(|d| d.instr_id()).collect())
+                }).collect()
+            }).collect::<Vec<_>>();
+            let index = items.iter().enumerate()
+                             .map(|(i, item)| (item.id, i)).collect();
+            BlockItems { kind, items, index }
+        }).collect();
+        Side { blocks, lines: instr_lines(graph) }
+    }
+}
+
+// Align two sequences: a longest common subsequence of
+// the elements `same` matches, with the unmatched
+// elements between two matches paired in order where
+// `pair` allows.
+fn align<S, P>(n: usize, m: usize, same: S, pair: P) -> Vec<Step>
+  where S: Fn(usize, usize) -> bool,
+        P: Fn(usize, usize) -> bool
+{
+    // lens[i][j] is the length of a longest common
+    // subsequence of the suffixes from i and j.
+    let mut lens = vec![vec![0_u32; m + 1]; n + 1];
+    for i in (0 .. n).rev() {
+        for j in (0 .. m).rev() {
+            lens[i][j] = if same(i, j) {
+                lens[i + 1][j + 1] + 1
+            } else {
+                lens[i + 1][j].max(lens[i][j + 1])
+            };
+        }
+    }
+
+    let mut steps = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    let (mut gap_i, mut gap_j) = (0, 0);
+    let flush = |steps: &mut Vec<Step>, i: usize, j: usize,
+                     gap_i: &mut usize, gap_j: &mut usize|
+    {
+        while *gap_i < i && *gap_j < j && pair(*gap_i, *gap_j) {
+            steps.push(Step::Pair(*gap_i, *gap_j));
+            *gap_i += 1;
+            *gap_j += 1;
+        }
+        steps.extend((*gap_i .. i).map(Step::Old));
+        steps.extend((*gap_j .. j).map(Step::New));
+    };
+    while i < n && j < m {
+        if same(i, j) && lens[i][j] == lens[i + 1][j + 1] + 1 {
+            flush(&mut steps, i, j, &mut gap_i, &mut gap_j);
+            steps.push(Step::Pair(i, j));
+            i += 1;
+            j += 1;
+            gap_i = i;
+            gap_j = j;
+        } else if lens[i + 1][j] >= lens[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    flush(&mut steps, n, m, &mut gap_i, &mut gap_j);
+    steps
+}
+
+impl Differ {
+    fn new(old: &Graph, new: &Graph) -> Differ {
+        let old = Side::new(old);
+        let new = Side::new(new);
+        let block_map = vec![None; old.blocks.len()];
+        Differ { old, new, values: HashMap::new(), block_map }
+    }
+
+    fn diff(mut self) -> GraphDiff {
+        let (old, new) = (&self.old.blocks, &self.new.blocks);
+        let same_block = |i: usize, j: usize| {
+            let (a, b) = (&old[i], &new[j]);
+            a.kind == b.kind
+              && a.items.len() == b.items.len()
+              && a.items.iter().zip(&b.items).all(|(x, y)| x.op == y.op)
+        };
+        let steps =
+          align(old.len(), new.len(), same_block, |_, _| true);
+
+        for step in &steps {
+            if let Step::Pair(i, j) = *step {
+                self.block_map[i] = Some(j as u32);
+            }
+        }
+        let blocks = steps.iter().map(|step| match *step {
+          Step::Old(i) => self.removed_block(i),
+          Step::New(j) => self.inserted_block(j),
+          Step::Pair(i, j) => self.diff_blocks(i, j)
+        }).collect();
+        GraphDiff { blocks }
+    }
+
+    fn removed_block(&self, i: usize) -> BlockDiff {
+        let block = &self.old.blocks[i];
+        BlockDiff {
+            old: Some(i as u32),
+            new: None,
+            old_kind: block.kind,
+            new_kind: block.kind,
+            instrs: block.items.iter().map(|item| InstrDiff::Removed {
+                old: item.id,
+                text: self.old.lines[&item.id].clone()
+            }).collect()
+        }
+    }
+
+    fn inserted_block(&self, j: usize) -> BlockDiff {
+        let block = &self.new.blocks[j];
+        BlockDiff {
+            old: None,
+            new: Some(j as u32),
+            old_kind: block.kind,
+            new_kind: block.kind,
+            instrs: block.items.iter().map(|item| InstrDiff::Inserted {
+                new: item.id,
+                text: self.new.lines[&item.id].clone()
+            }).collect()
+        }
+    }
+
+    fn diff_blocks(&mut self, i: usize, j: usize) -> BlockDiff {
+        let (a, b) = (&self.old.blocks[i], &self.new.blocks[j]);
+        let steps = {
+            let same = |x: usize, y: usize| {
+                self.same_instr(a, b, &a.items[x], &b.items[y])
+            };
+            let pair = |x: usize, y: usize| {
+                a.items[x].defines_value == b.items[y].defines_value
+            };
+            align(a.items.len(), b.items.len(), same, pair)
+        };
+
+        let mut instrs = Vec::with_capacity(steps.len());
+        for step in steps {
+            let diff = match step {
+              Step::Old(x) => {
+                let old = a.items[x].id;
+                let text = self.old.lines[&old].clone();
+                InstrDiff::Removed { old, text }
+              }
+              Step::New(y) => {
+                let new = b.items[y].id;
+                let text = self.new.lines[&new].clone();
+                InstrDiff::Inserted { new, text }
+              }
+              Step::Pair(x, y) => {
+                let (old, new) = (a.items[x].id, b.items[y].id);
+                if a.items[x].defines_value {
+                    self.values.insert(old, new);
+                }
+                let old_text = self.old.lines[&old].clone();
+                let new_text = self.new.lines[&new].clone();
+                if self.same_instr(a, b, &a.items[x], &b.items[y]) {
+                    InstrDiff::Same { old, new, text: new_text }
+                } else {
+                    InstrDiff::Changed { old, new, old_text, new_text }
+                }
+              }
+            };
+            instrs.push(diff);
+        }
+        BlockDiff {
+            old: Some(i as u32),
+            new: Some(j as u32),
+            old_kind: a.kind,
+            new_kind: b.kind,
+            instrs
+        }
+    }
+
+    fn same_instr(&self, a: &BlockItems, b: &BlockItems,
+                  x: &Item, y: &Item)
+      -> bool
+    {
+        let same_value = |u: &InstrId, v: &InstrId| {
+            if let Some(w) = self.values.get(u) {
+                return w == v;
+            }
+            let local_u = a.index.get(u);
+            local_u.is_some() && local_u == b.index.get(v)
+        };
+        let same_values = |us: &[InstrId], vs: &[InstrId]| {
+            us.len() == vs.len()
+              && us.iter().zip(vs).all(|(u, v)| same_value(u, v))
+        };
+        x.op == y.op
+          && same_values(&x.inputs, &y.inputs)
+          && x.targets.len() == y.targets.len()
+          && x.targets.iter().zip(&y.targets).all(|(s, t)| {
+                 self.block_map[s.0 as usize] == Some(t.0)
+                   && same_values(&s.1, &t.1)
+             })
+    }
+}
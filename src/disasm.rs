@@ -0,0 +1,142 @@
+
+use std::fmt::Write;
+
+use crate::instr::OperandEncoding;
+use crate::graph::Graph;
+use crate::decode::{ ByteReader, DecodedInstr };
+use crate::text::mnemonic;
+
+/*
+ * The disassembler prints an instruction stream as
+ * annotated hex, one instruction to a line:
+ *
+ *   000011  06 01 01 | 0c 0c            add.i32 @c, @c
+ *   000022  0a | | 04 03 1f 00 0c       jump -> b4(@1f, @0, @c)
+ *
+ * giving the instruction's offset, then its bytes in
+ * three groups, the opcode with its payload, the
+ * operands and the target lists, and then what they
+ * decode to, with operands and phi arguments as the
+ * absolute offsets they name, in hex, and targets as
+ * block ids.  Instructions of more than `ROW_BYTES`
+ * bytes continue on the lines after.
+ *
+ * The stream is decoded with the checks of
+ * `decode_instrs`, and so can be any bytes: where an
+ * instruction fails to decode, the error is printed
+ * and the rest of the stream is dumped as plain hex,
+ * which is where an encoder and decoder that disagree
+ * show up.
+ */
+
+// The bytes shown on each line.
+const ROW_BYTES: usize = 8;
+
+// The width of the hex column, with the separators
+// of the three groups.
+const HEX_WIDTH: usize = ROW_BYTES * 3 + 4;
+
+// The decoding of an instruction.
+fn annotation(instr: &DecodedInstr) -> String {
+    let mut out = mnemonic(instr.op());
+    let mut sep = if out.contains(' ') { ", " } else { " " };
+    for input in instr.inputs() {
+        write!(out, "{}@{:x}", sep, input.offset()).unwrap();
+        sep = ", ";
+    }
+    for (i, (block, args)) in instr.targets().iter().enumerate() {
+        out.push_str(if i == 0 { " -> " } else { ", " });
+        write!(out, "b{}(", block.as_u32()).unwrap();
+        for (j, arg) in args.iter().enumerate() {
+            if j > 0 {
+                out.push_str(", ");
+            }
+            write!(out, "@{:x}", arg.offset()).unwrap();
+        }
+        out.push(')');
+    }
+    out
+}
+
+// Print bytes and their separators as rows of the hex
+// column, the first row followed by `note`.
+fn hex_rows(out: &mut String, start: usize, groups: &[&[u8]],
+            note: &str)
+{
+    let mut rows: Vec<String> = vec![String::new()];
+    let mut in_row = 0;
+    for (g, group) in groups.iter().enumerate() {
+        if g > 0 && groups[g ..].iter().any(|r| !r.is_empty()) {
+            rows.last_mut().unwrap().push_str("| ");
+        }
+        for byte in group.iter() {
+            if in_row == ROW_BYTES {
+                rows.push(String::new());
+                in_row = 0;
+            }
+            let row = rows.last_mut().unwrap();
+            write!(row, "{:02x} ", byte).unwrap();
+            in_row += 1;
+        }
+    }
+    for (i, row) in rows.iter().enumerate() {
+        if i == 0 {
+            write!(out, "{:06x}  ", start).unwrap();
+        } else {
+            out.push_str("        ");
+        }
+        if i == 0 && !note.is_empty() {
+            writeln!(out, "{:width$}{}", row, note,
+                     width = HEX_WIDTH).unwrap();
+        } else {
+            writeln!(out, "{}", row.trim_end()).unwrap();
+        }
+    }
+}
+
+/**
+ * Disassemble a raw instruction stream with operands
+ * in the given encoding, resolving pooled constants in
+ * the given constant pool.
+ */
+pub fn disasm(bytes: &[u8], encoding: OperandEncoding, pool: &[u64])
+  -> String
+{
+    let mut out = String::new();
+    let mut reader = ByteReader::new(bytes).with_pool(pool);
+    while !reader.at_end() {
+        let start = reader.offset();
+        match DecodedInstr::decode(&mut reader, encoding) {
+          Ok(instr) => {
+            let (header, operands, end) = instr.byte_layout();
+            let groups = [
+                &bytes[start .. header],
+                &bytes[header .. operands],
+                &bytes[operands .. end]
+            ];
+            hex_rows(&mut out, start, &groups, &annotation(&instr));
+          }
+          Err(err) => {
+            writeln!(out, "{:06x}  error: {}", start, err).unwrap();
+            for row in (start .. bytes.len()).step_by(ROW_BYTES) {
+                let end = (row + ROW_BYTES).min(bytes.len());
+                hex_rows(&mut out, row, &[&bytes[row .. end]], "");
+            }
+            break;
+          }
+        }
+    }
+    out
+}
+
+impl Graph {
+    /**
+     * Disassemble the graph's instruction stream, in
+     * the graph's operand encoding and with its
+     * constant pool.
+     */
+    pub fn disasm(&self) -> String {
+        disasm(self.instr_bytes(), self.operand_encoding(),
+               self.const_pool())
+    }
+}
@@ -0,0 +1,460 @@
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::view::BlockView;
+use crate::ops::{ CmpKind, BiniKind };
+use crate::ir_types::IrTypeId;
+use crate::regalloc::{ Allocation, Location, TargetRegs, PhysReg };
+use crate::lower::{
+    LoweringBackend, LowerOp, LowerInstr, LowerTarget, Operand,
+    phi_locations, edge_moves
+};
+use crate::ebpf::{ EbpfInsn, EbpfProgram, EbpfError };
+use crate::patch::PatchPoint;
+
+/*
+ * Each instruction loads its operands into the scratch
+ * registers `r0` and `r1`, computes into `r0`, and
+ * stores that to its own location.  The stack below
+ * the frame pointer `r10` holds the context pointer,
+ * the spill slots, and the slots edge moves pass
+ * values through:
+ *
+ *   r10 - 8                context
+ *   r10 - 16 - 8K          spill slot K
+ *   after the spills       one slot per phi moved
+ *
+ * Since eBPF has no push or pop, an edge stores all of
+ * its phi arguments to the move slots before loading
+ * any of them into the target's phi locations.
+ */
+
+const STACK_LIMIT: u32 = 512;
+const CTX_SLOT: i16 = -8;
+const FIRST_SPILL: i16 = -16;
+
+const R0: u8 = 0;
+const R1: u8 = 1;
+const R10: u8 = 10;
+
+// Instruction classes, and the fields of opcodes.
+const LD: u8 = 0x00;
+const LDX: u8 = 0x01;
+const STX: u8 = 0x03;
+const ALU: u8 = 0x04;
+const JMP: u8 = 0x05;
+const JMP32: u8 = 0x06;
+const ALU64: u8 = 0x07;
+
+const K: u8 = 0x00;
+const X: u8 = 0x08;
+const W: u8 = 0x00;
+const B: u8 = 0x10;
+const DW: u8 = 0x18;
+const IMM: u8 = 0x00;
+const MEM: u8 = 0x60;
+
+const ADD: u8 = 0x00;
+const SUB: u8 = 0x10;
+const MUL: u8 = 0x20;
+const OR: u8 = 0x40;
+const AND: u8 = 0x50;
+const XOR: u8 = 0xA0;
+const MOV: u8 = 0xB0;
+
+const JA: u8 = 0x00;
+const JEQ: u8 = 0x10;
+const JNE: u8 = 0x50;
+const JSGT: u8 = 0x60;
+const JSGE: u8 = 0x70;
+const EXIT: u8 = 0x90;
+const JLT: u8 = 0xA0;
+const JSLT: u8 = 0xC0;
+const JSLE: u8 = 0xD0;
+
+/**
+ * The registers values are allocated to when lowering
+ * to eBPF, `r2` to `r9`, in one class holding values
+ * of every type.
+ */
+pub fn target_regs() -> TargetRegs {
+    let mut target = TargetRegs::new();
+    let regs: Vec<PhysReg> = (2 .. 10).map(|r| {
+        target.add_reg(&format!("r{}", r))
+    }).collect();
+    let gpr = target.add_class("gpr", &regs);
+    for &ty in &[IrTypeId::Bool, IrTypeId::Int32,
+                 IrTypeId::Int64, IrTypeId::PtrInt]
+    {
+        target.set_type_class(ty, gpr);
+    }
+    target
+}
+
+fn reg(reg: PhysReg) -> u8 {
+    reg.as_u16() as u8 + 2
+}
+
+fn spill_off(slot: u32) -> i32 {
+    (FIRST_SPILL as i32) - 8 * (slot as i32)
+}
+
+// The size field of a memory access of the type.
+fn size_field(ty: IrTypeId) -> u8 {
+    match ty.mem_size() {
+      1 => B,
+      4 => W,
+      _ => DW
+    }
+}
+
+fn jmp_op(kind: CmpKind) -> u8 {
+    match kind {
+      CmpKind::Lt => JSLT, CmpKind::Gt => JSGT,
+      CmpKind::Le => JSLE, CmpKind::Ge => JSGE,
+      CmpKind::Eq => JEQ, CmpKind::Ne => JNE
+    }
+}
+
+fn alu_op(kind: BiniKind) -> u8 {
+    match kind {
+      BiniKind::Add => ADD, BiniKind::Sub => SUB,
+      BiniKind::Mul => MUL, BiniKind::And => AND,
+      BiniKind::Or => OR, BiniKind::Xor => XOR
+    }
+}
+
+// Int32 and Bool values are held zero-extended, which
+// the 32-bit ALU and jump classes keep them.
+fn is_wide(ty: IrTypeId) -> bool {
+    ty == IrTypeId::Int64 || ty == IrTypeId::PtrInt
+}
+
+pub(crate) struct EbpfBackend<'a> {
+    alloc: &'a Allocation,
+    insns: Vec<EbpfInsn>,
+
+    // Instruction index of each block, and the
+    // locations of its phis, by block id.
+    block_starts: Vec<Option<usize>>,
+    phi_locations: Vec<Vec<Option<Location>>>,
+
+    // Jumps to patch once every block is placed.
+    fixups: Vec<(usize, BlockId)>,
+
+    // The first move slot, below the spill slots.
+    move_base: i32,
+    stack_bytes: u32,
+
+    exits: Vec<InstrId>,
+    num_params: usize,
+    patch_points: Vec<PatchPoint>
+}
+
+impl<'a> EbpfBackend<'a> {
+    pub(crate) fn new(alloc: &'a Allocation) -> EbpfBackend<'a> {
+        EbpfBackend {
+            alloc,
+            insns: Vec::new(),
+            block_starts: Vec::new(),
+            phi_locations: Vec::new(),
+            fixups: Vec::new(),
+            move_base: 0,
+            stack_bytes: 0,
+            exits: Vec::new(),
+            num_params: 0,
+            patch_points: Vec::new()
+        }
+    }
+
+    fn emit(&mut self, code: u8, dst: u8, src: u8, off: i16, imm: i32) {
+        self.insns.push(EbpfInsn::new(code, dst, src, off, imm));
+    }
+
+    fn mov_reg(&mut self, dst: u8, src: u8) {
+        if dst != src {
+            self.emit(ALU64 | MOV | X, dst, src, 0, 0);
+        }
+    }
+
+    // Load a constant, taking two slots only for values
+    // that do not fit in 32 bits.
+    fn mov_imm(&mut self, dst: u8, v: u64) {
+        if v <= (i32::MAX as u64) {
+            self.emit(ALU64 | MOV | K, dst, 0, 0, v as i32);
+        } else if v <= (u32::MAX as u64) {
+            self.emit(ALU | MOV | K, dst, 0, 0, v as u32 as i32);
+        } else {
+            self.emit(LD | IMM | DW, dst, 0, 0, v as u32 as i32);
+            self.emit(0, 0, 0, 0, (v >> 32) as u32 as i32);
+        }
+    }
+
+    fn load(&mut self, dst: u8, base: u8, off: i32) {
+        self.emit(LDX | MEM | DW, dst, base, off as i16, 0);
+    }
+    fn store(&mut self, base: u8, off: i32, src: u8) {
+        self.emit(STX | MEM | DW, base, src, off as i16, 0);
+    }
+
+    fn load_operand(&mut self, dst: u8, operand: &Operand) {
+        let konst = operand.konst().and_then(|k| k.const_bits());
+        if let Some(bits) = konst {
+            self.mov_imm(dst, bits);
+            return;
+        }
+        match operand.location() {
+          Some(Location::Reg(r)) => self.mov_reg(dst, reg(r)),
+          Some(Location::Spill(s)) => {
+            self.load(dst, R10, spill_off(s.as_u32()));
+          }
+          None => panic!("Operand {:?} has no location.",
+                         operand.value())
+        }
+    }
+
+    // Store `r0` to the location of a value.  Values
+    // the allocator gave no location are never used.
+    fn store_result(&mut self, location: Option<Location>) {
+        match location {
+          Some(Location::Reg(r)) => self.mov_reg(reg(r), R0),
+          Some(Location::Spill(s)) => {
+            self.store(R10, spill_off(s.as_u32()), R0);
+          }
+          None => {}
+        }
+    }
+
+    fn jump_to(&mut self, block: BlockId) {
+        self.fixups.push((self.insns.len(), block));
+        self.emit(JMP | JA, 0, 0, 0, 0);
+    }
+
+    fn side_exit(&mut self, instr: InstrId) {
+        self.exits.push(instr);
+        self.mov_imm(R0, self.exits.len() as u64);
+        self.emit(JMP | EXIT, 0, 0, 0, 0);
+    }
+
+    fn emit_edge_moves(&mut self, target: &LowerTarget) {
+        let block = target.block().as_u32() as usize;
+        let moves = edge_moves(target, &self.phi_locations[block]);
+        for (i, (arg, _)) in moves.iter().enumerate() {
+            self.load_operand(R0, arg);
+            self.store(R10, self.move_base - 8 * (i as i32), R0);
+        }
+        for (i, &(_, dst)) in moves.iter().enumerate() {
+            let from = self.move_base - 8 * (i as i32);
+            match dst {
+              Location::Reg(r) => self.load(reg(r), R10, from),
+              Location::Spill(s) => {
+                self.load(R0, R10, from);
+                self.store(R10, spill_off(s.as_u32()), R0);
+              }
+            }
+        }
+    }
+
+    fn lower_branch(&mut self, instr: &LowerInstr) {
+        self.load_operand(R0, &instr.operands()[0]);
+        let (t, f) = (&instr.targets()[0], &instr.targets()[1]);
+        if t.args().is_empty() && f.args().is_empty() {
+            self.fixups.push((self.insns.len(), t.block()));
+            self.emit(JMP | JNE | K, R0, 0, 0, 0);
+            self.jump_to(f.block());
+            return;
+        }
+        let skip = self.insns.len();
+        self.emit(JMP | JEQ | K, R0, 0, 0, 0);
+        self.emit_edge_moves(t);
+        self.jump_to(t.block());
+        let off = self.insns.len() - skip - 1;
+        self.insns[skip].set_off(off as i16);
+        self.emit_edge_moves(f);
+        self.jump_to(f.block());
+    }
+}
+
+impl<'a> LoweringBackend for EbpfBackend<'a> {
+    type Output = Result<EbpfProgram, EbpfError>;
+
+    fn begin(&mut self, graph: &Graph) {
+        let nblocks = graph.block_store().total_blocks();
+        self.block_starts = vec![None; nblocks];
+        self.phi_locations = phi_locations(graph, self.alloc);
+
+        let max_phis = self.phi_locations.iter()
+                           .map(|p| p.len() as u32)
+                           .max().unwrap_or(0);
+        let spills = self.alloc.num_spill_slots();
+        self.move_base = spill_off(spills);
+        self.stack_bytes = 8 + 8 * (spills + max_phis);
+
+        self.store(R10, CTX_SLOT as i32, R1);
+    }
+
+    fn begin_block(&mut self, block: &BlockView) {
+        let start = self.insns.len();
+        self.block_starts[block.id().as_u32() as usize] = Some(start);
+    }
+
+    fn lower_instr(&mut self, instr: &LowerInstr) {
+        let operands = instr.operands();
+        match instr.op() {
+          LowerOp::Nop | LowerOp::Phi { .. } => {}
+          LowerOp::Param { index, ty } => {
+            // Truncate the argument to the param's type,
+            // with any nonzero value a true Bool.
+            self.num_params = self.num_params.max(index as usize + 1);
+            self.load(R1, R10, CTX_SLOT as i32);
+            self.load(R0, R1, 8 + 8 * (index as i32));
+            match ty {
+              IrTypeId::Int32 => self.emit(ALU | MOV | X, R0, R0, 0, 0),
+              IrTypeId::Bool => {
+                self.emit(JMP | JEQ | K, R0, 0, 1, 0);
+                self.mov_imm(R0, 1);
+              }
+              _ => {}
+            }
+            self.store_result(instr.location());
+          }
+          op @ LowerOp::ConstBool(_)
+          | op @ LowerOp::ConstInt32(_)
+          | op @ LowerOp::ConstInt64(_)
+          | op @ LowerOp::ConstPtrInt(_) => {
+            if instr.location().is_some() {
+                self.mov_imm(R0, op.const_bits().unwrap());
+                self.store_result(instr.location());
+            }
+          }
+          LowerOp::Cmp { kind, ty } => {
+            // r0 = lhs; r1 = rhs;
+            // if r0 <kind> r1 goto +2; r0 = 0; goto +1;
+            // r0 = 1
+            self.load_operand(R0, &operands[0]);
+            self.load_operand(R1, &operands[1]);
+            let class = if is_wide(ty) { JMP } else { JMP32 };
+            self.emit(class | jmp_op(kind) | X, R0, R1, 2, 0);
+            self.mov_imm(R0, 0);
+            self.emit(JMP | JA, 0, 0, 1, 0);
+            self.mov_imm(R0, 1);
+            self.store_result(instr.location());
+          }
+          LowerOp::Bini { kind, ty } => {
+            self.load_operand(R0, &operands[0]);
+            self.load_operand(R1, &operands[1]);
+            let class = if is_wide(ty) { ALU64 } else { ALU };
+            self.emit(class | alu_op(kind) | X, R0, R1, 0, 0);
+            if ty == IrTypeId::Bool {
+                self.emit(ALU | AND | K, R0, 0, 0, 1);
+            }
+            self.store_result(instr.location());
+          }
+          LowerOp::Guard { expected } => {
+            // Skip the side exit when the guard holds.
+            self.load_operand(R0, &operands[0]);
+            let pass = if expected { JNE } else { JEQ };
+            self.emit(JMP | pass | K, R0, 0, 2, 0);
+            self.side_exit(instr.id());
+          }
+          LowerOp::Ret { .. } => {
+            self.load_operand(R0, &operands[0]);
+            self.load(R1, R10, CTX_SLOT as i32);
+            self.store(R1, 0, R0);
+            self.mov_imm(R0, 0);
+            self.emit(JMP | EXIT, 0, 0, 0, 0);
+          }
+          LowerOp::Branch => self.lower_branch(instr),
+          LowerOp::Jump => {
+            let target = &instr.targets()[0];
+            self.emit_edge_moves(target);
+            self.jump_to(target.block());
+          }
+          LowerOp::Exit => self.side_exit(instr.id()),
+          LowerOp::PatchPoint { id, size } => {
+            // Whole instructions of `ja +0`, covering at
+            // least the space asked for.
+            let insns = (size as usize).div_ceil(8);
+            let offset = 8 * self.insns.len();
+            for _ in 0 .. insns {
+                self.emit(JMP | JA, 0, 0, 0, 0);
+            }
+            self.patch_points.push(PatchPoint::new(
+              id, instr.id(), offset, 8 * insns as u32));
+          }
+          LowerOp::BoundsCheck { ty, elided } => {
+            // Skip the side exit when the index is below
+            // the length, unsigned.
+            self.load_operand(R0, &operands[0]);
+            if !elided {
+                self.load_operand(R1, &operands[1]);
+                let class = if is_wide(ty) { JMP } else { JMP32 };
+                self.emit(class | JLT | X, R0, R1, 2, 0);
+                self.side_exit(instr.id());
+            }
+            self.store_result(instr.location());
+          }
+          LowerOp::NullCheck { elided } => {
+            // Skip the side exit when the pointer is not
+            // null.
+            self.load_operand(R0, &operands[0]);
+            if !elided {
+                self.emit(JMP | JNE | K, R0, 0, 2, 0);
+                self.side_exit(instr.id());
+            }
+            self.store_result(instr.location());
+          }
+          LowerOp::Load { ty } => {
+            self.load_operand(R1, &operands[0]);
+            self.emit(LDX | MEM | size_field(ty), R0, R1, 0, 0);
+            self.store_result(instr.location());
+          }
+          LowerOp::Store { ty } => {
+            self.load_operand(R1, &operands[0]);
+            self.load_operand(R0, &operands[1]);
+            self.emit(STX | MEM | size_field(ty), R1, R0, 0, 0);
+          }
+          LowerOp::PtrAdd { offset, stride } => {
+            self.load_operand(R0, &operands[1]);
+            self.mov_imm(R1, stride as u64);
+            self.emit(ALU64 | MUL | X, R0, R1, 0, 0);
+            self.load_operand(R1, &operands[0]);
+            self.emit(ALU64 | ADD | X, R0, R1, 0, 0);
+            self.mov_imm(R1, offset as u64);
+            self.emit(ALU64 | ADD | X, R0, R1, 0, 0);
+            self.store_result(instr.location());
+          }
+          LowerOp::Copy { .. } => {
+            self.load_operand(R0, &operands[0]);
+            self.store_result(instr.location());
+          }
+          LowerOp::Call { .. } => {
+            unreachable!("calls are rejected before lowering")
+          }
+        }
+    }
+
+    fn finish(mut self) -> Result<EbpfProgram, EbpfError> {
+        if self.stack_bytes > STACK_LIMIT {
+            return Err(EbpfError::StackTooLarge {
+                bytes: self.stack_bytes
+            });
+        }
+        for &(from, block) in &self.fixups {
+            let to = self.block_starts[block.as_u32() as usize]
+                         .expect("Jump to a block with no code.");
+            let off = (to as i64) - (from as i64) - 1;
+            if off < (i16::MIN as i64) || off > (i16::MAX as i64) {
+                return Err(EbpfError::JumpTooFar { from });
+            }
+            self.insns[from].set_off(off as i16);
+        }
+        Ok(EbpfProgram {
+            insns: self.insns,
+            num_params: self.num_params,
+            exits: self.exits,
+            patch_points: self.patch_points
+        })
+    }
+}
@@ -0,0 +1,181 @@
+
+use std::error::Error;
+use std::fmt;
+
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::patch::PatchPoint;
+use crate::verify::VerifyError;
+use crate::target::TargetDesc;
+use crate::regalloc::{ linear_scan, assign_spill_slots };
+
+mod backend;
+pub use self::backend::target_regs;
+use self::backend::EbpfBackend;
+
+/** An error lowering a graph to eBPF. */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum EbpfError {
+    // The graph does not pass `verify`.
+    Verify(Vec<VerifyError>),
+
+    // The spill slots and edge moves need more stack
+    // than the 512 bytes eBPF allows.
+    StackTooLarge { bytes: u32 },
+
+    // A jump at the given instruction index is too far
+    // for its 16-bit offset.
+    JumpTooFar { from: usize },
+
+    // The graph is built for a target other than a
+    // 64-bit little-endian one.
+    Target(TargetDesc),
+
+    // The graph calls another graph at the given
+    // instruction, which a single program cannot.
+    Call { instr: InstrId }
+}
+
+impl fmt::Display for EbpfError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match self {
+          EbpfError::Verify(errs) => {
+            write!(f, "{} verify errors", errs.len())?;
+            if let Some(err) = errs.first() {
+                write!(f, ", first: {}", err)?;
+            }
+            Ok(())
+          }
+          EbpfError::StackTooLarge { bytes } =>
+            write!(f, "needs {} bytes of stack, over 512", bytes),
+          EbpfError::JumpTooFar { from } =>
+            write!(f, "jump at insn {} out of range", from),
+          EbpfError::Target(target) =>
+            write!(f, "cannot lower for target {}", target),
+          EbpfError::Call { instr } =>
+            write!(f, "cannot lower the call at {}", instr)
+        }
+    }
+}
+
+impl Error for EbpfError {}
+
+/**
+ * An eBPF instruction: an opcode, destination and
+ * source registers, a signed offset, and a signed
+ * immediate.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct EbpfInsn {
+    code: u8,
+    regs: u8,
+    off: i16,
+    imm: i32
+}
+
+impl EbpfInsn {
+    pub(crate) fn new(code: u8, dst: u8, src: u8, off: i16, imm: i32)
+      -> EbpfInsn
+    {
+        debug_assert!(dst < 16 && src < 16);
+        EbpfInsn { code, regs: (src << 4) | dst, off, imm }
+    }
+
+    pub fn code(&self) -> u8 { self.code }
+    pub fn dst(&self) -> u8 { self.regs & 0xF }
+    pub fn src(&self) -> u8 { self.regs >> 4 }
+    pub fn off(&self) -> i16 { self.off }
+    pub fn imm(&self) -> i32 { self.imm }
+
+    pub(crate) fn set_off(&mut self, off: i16) { self.off = off; }
+
+    // The instruction in the kernel's little-endian
+    // `struct bpf_insn` layout.
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0_u8; 8];
+        bytes[0] = self.code;
+        bytes[1] = self.regs;
+        bytes[2 .. 4].copy_from_slice(&self.off.to_le_bytes());
+        bytes[4 .. 8].copy_from_slice(&self.imm.to_le_bytes());
+        bytes
+    }
+}
+
+/**
+ * A graph lowered to an eBPF program.
+ *
+ * The program takes the context pointer in `r1`,
+ * pointing at a `u64` result followed by a `u64` for
+ * each param.  It gives 0 in `r0` after storing a
+ * returned value into the result, or K + 1 on leaving
+ * through the Kth of `exits`.
+ */
+#[derive(Clone, Debug)]
+pub struct EbpfProgram {
+    insns: Vec<EbpfInsn>,
+    num_params: usize,
+    exits: Vec<InstrId>,
+    patch_points: Vec<PatchPoint>
+}
+
+impl EbpfProgram {
+    pub fn insns(&self) -> &[EbpfInsn] { &self.insns }
+
+    // The number of params the graph reads, counting
+    // indices up to the highest it reads.
+    pub fn num_params(&self) -> usize { self.num_params }
+
+    // The guards and exits, in the order of the side
+    // exits they leave by.
+    pub fn exits(&self) -> &[InstrId] { &self.exits }
+
+    // The space each patch point reserves, as `ja +0`
+    // instructions, with offsets into `to_bytes`.
+    pub fn patch_points(&self) -> &[PatchPoint] {
+        &self.patch_points
+    }
+
+    /** The program's bytes, as loaded with `bpf(2)`. */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.insns.iter().flat_map(|i| i.to_bytes()).collect()
+    }
+}
+
+impl Graph {
+    /**
+     * Lower the graph to an eBPF program: values
+     * coalesced by `coalesce_copies` are allocated to
+     * `r2` to `r9` by `linear_scan`, with
+     * spilled values sharing slots by
+     * `assign_spill_slots`, and phis resolved by moves
+     * through the stack on each edge.  Loops lower to
+     * backward jumps, which need a kernel with bounded
+     * loop support.  The graph must be built for a
+     * 64-bit little-endian target.
+     */
+    pub fn lower_ebpf(&self) -> Result<EbpfProgram, EbpfError> {
+        if !self.target().is_64_little() {
+            return Err(EbpfError::Target(self.target()));
+        }
+        self.verify().map_err(EbpfError::Verify) ?;
+        if let Some(instr) = self.first_call() {
+            return Err(EbpfError::Call { instr });
+        }
+        let liveness = self.liveness();
+        let intervals = self.live_intervals(liveness);
+        let classes = self.coalesce_copies(&intervals);
+        let alloc = linear_scan(classes.intervals(), &target_regs())
+                      .expect("target_regs has a class for every type");
+        let alloc = assign_spill_slots(classes.intervals(), &alloc);
+        let alloc = classes.expand(&alloc);
+        let backend = EbpfBackend::new(&alloc);
+        let program = self.lower(backend, Some(&alloc)) ?;
+        debug!("Lowered to {} eBPF insns, {} spill slots",
+               program.insns.len(), alloc.num_spill_slots());
+        Ok(program)
+    }
+}
@@ -0,0 +1,127 @@
+
+use std::fmt;
+
+use crate::ops::Opcode;
+use crate::graph::Graph;
+
+/**
+ * The bytes spent on the instructions of one opcode:
+ * on opcode bytes and payloads, on operand references,
+ * and on the target lists of end instructions, which
+ * hold target block ids, argument counts and phi
+ * arguments.
+ */
+#[derive(Clone, Copy, Debug, Default)]
+#[derive(PartialEq, Eq)]
+pub struct OpcodeBytes {
+    count: u32,
+    op_bytes: usize,
+    operand_bytes: usize,
+    target_bytes: usize
+}
+
+impl OpcodeBytes {
+    pub fn count(&self) -> u32 { self.count }
+    pub fn op_bytes(&self) -> usize { self.op_bytes }
+    pub fn operand_bytes(&self) -> usize { self.operand_bytes }
+    pub fn target_bytes(&self) -> usize { self.target_bytes }
+
+    pub fn total(&self) -> usize {
+        self.op_bytes + self.operand_bytes + self.target_bytes
+    }
+
+    fn add(&mut self, other: &OpcodeBytes) {
+        self.count += other.count;
+        self.op_bytes += other.op_bytes;
+        self.operand_bytes += other.operand_bytes;
+        self.target_bytes += other.target_bytes;
+    }
+}
+
+/**
+ * Where the bytes of a graph's instruction stream go,
+ * broken down by opcode.
+ */
+#[derive(Clone, Debug)]
+pub struct EncodingStats {
+    // The bytes of each opcode, indexed by opcode
+    // byte less `Opcode::MIN`.
+    by_opcode: Vec<OpcodeBytes>
+}
+
+impl EncodingStats {
+    pub(crate) fn compute(graph: &Graph) -> EncodingStats {
+        let min = Opcode::MIN.into_u8() as usize;
+        let len = Opcode::MAX.into_u8() as usize - min + 1;
+        let mut by_opcode = vec![OpcodeBytes::default(); len];
+
+        // Each instruction runs up to the next in the
+        // stream, and the last up to the stream's end.
+        let mut instrs = Vec::new();
+        for block in graph.block_store().iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                let start = info.defn().instr_id().offset();
+                instrs.push((start, info.op().opcode(),
+                             info.header_len(), info.operands_end()));
+            }
+        }
+        let stream_len = graph.instr_bytes().len();
+        for (i, &(start, opcode, header, operands_end)) in
+          instrs.iter().enumerate()
+        {
+            let end = instrs.get(i + 1).map_or(stream_len, |n| n.0);
+            let entry = &mut by_opcode[opcode.into_u8() as usize - min];
+            entry.count += 1;
+            entry.op_bytes += header;
+            entry.operand_bytes += operands_end - header;
+            entry.target_bytes += (end - start) - operands_end;
+        }
+        EncodingStats { by_opcode }
+    }
+
+    // The bytes of the given opcode.
+    pub fn opcode(&self, opcode: Opcode) -> OpcodeBytes {
+        let min = Opcode::MIN.into_u8();
+        self.by_opcode[(opcode.into_u8() - min) as usize]
+    }
+
+    // The opcodes the graph uses, with their bytes.
+    pub fn iter(&self)
+      -> impl Iterator<Item=(Opcode, OpcodeBytes)> + '_
+    {
+        let min = Opcode::MIN.into_u8();
+        self.by_opcode.iter().enumerate()
+            .filter(|(_, bytes)| bytes.count > 0)
+            .map(move |(i, bytes)| {
+                let opcode = unsafe { Opcode::from_u8(min + i as u8) };
+                (opcode, *bytes)
+            })
+    }
+
+    // The bytes of every opcode together.
+    pub fn totals(&self) -> OpcodeBytes {
+        let mut totals = OpcodeBytes::default();
+        for bytes in &self.by_opcode {
+            totals.add(bytes);
+        }
+        totals
+    }
+}
+
+impl fmt::Display for EncodingStats {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        writeln!(f, "{:<8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+                 "opcode", "count", "op", "operands", "targets",
+                 "total")?;
+        let rows = self.iter().map(|(op, b)| (format!("{:?}", op), b));
+        let totals = ("total".to_string(), self.totals());
+        for (name, b) in rows.chain(Some(totals)) {
+            writeln!(f, "{:<8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+                     name, b.count, b.op_bytes, b.operand_bytes,
+                     b.target_bytes, b.total())?;
+        }
+        Ok(())
+    }
+}
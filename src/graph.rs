@@ -1,13 +1,101 @@
 
+use std::fmt::Write;
 use std::mem;
+use std::panic::RefUnwindSafe;
+use std::sync::OnceLock;
 
-use crate::block::{ Block, BlockId, BlockRef, BlockStore };
+use crate::block::{
+    Block, BlockId, BlockRef, BlockStore, BlockStorage
+};
 use crate::ops::{ Op };
 use crate::instr::{
-    InstrId, InstrStore, InstrInfo, InstrInputs
+    InstrId, InstrStore, InstrInfo, InstrInputs, InstrIter,
+    OperandEncoding
+};
+use crate::defn::Defn;
+use crate::instr_index::InstrIndex;
+use crate::def_use::{ DefUses, Uses };
+use crate::changes::{ Change, ChangeLog, ChangeMark };
+use crate::undo::Undo;
+use crate::mut_session::GraphMutSession;
+use crate::encoding_stats::EncodingStats;
+use crate::pass_stats::{ Analysis, AnalysisCounters, AnalysisCounts };
+use crate::decode::{
+    DecodeError, DecodedInstr, InstrDecoder, StreamError
+};
+use crate::schedule::{ Schedule, LatencyTable };
+use crate::analysis::{
+    Cfg, DomTree, Liveness, LoopInfo, RegPressure, EscapeInfo,
+    MemorySsa, InductionVars, SlpPlan
 };
-use crate::defn::{ Defn, TypedDefn };
-use crate::leb128;
+use crate::regalloc::{
+    LiveIntervals, Coalescing, Allocation, CallConv, AbiLowering,
+    FrameLayout, RegHints
+};
+use crate::trace::{ Trace, BranchProfile };
+use crate::profile::{ Profile, ProfileError };
+use crate::snapshot::Snapshot;
+use crate::target::TargetDesc;
+use crate::verify::{ Verifier, VerifyError };
+use crate::invariants::{ self, InvariantError };
+use crate::typecheck::{ self, TypeError };
+
+/**
+ * What `Graph::freeze` keeps besides the graph itself.
+ * `Shrink` keeps everything; `Strip` also drops tables
+ * that are only there to speed up queries and can be
+ * rebuilt, such as the instruction side-index.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum FreezeMode { Shrink, Strip }
+
+/**
+ * The heap bytes held by a graph or builder: by the
+ * instruction stream, the block table, the RPO index,
+ * and side tables such as the constant pool and the
+ * instruction side-index.
+ * Bytes in use and spare capacity are both counted.
+ */
+#[derive(Clone, Copy, Debug, Default)]
+#[derive(PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub(crate) instr_stream: usize,
+    pub(crate) block_table: usize,
+    pub(crate) rpo_index: usize,
+    pub(crate) side_tables: usize
+}
+
+impl MemoryUsage {
+    pub fn instr_stream(&self) -> usize { self.instr_stream }
+    pub fn block_table(&self) -> usize { self.block_table }
+    pub fn rpo_index(&self) -> usize { self.rpo_index }
+    pub fn side_tables(&self) -> usize { self.side_tables }
+
+    pub fn total(&self) -> usize {
+        self.instr_stream + self.block_table
+          + self.rpo_index + self.side_tables
+    }
+}
+
+/**
+ * The memory a graph used before and after
+ * `Graph::freeze`.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct FreezeStats {
+    before: MemoryUsage,
+    after: MemoryUsage
+}
+
+impl FreezeStats {
+    pub fn before(&self) -> MemoryUsage { self.before }
+    pub fn after(&self) -> MemoryUsage { self.after }
+    pub fn reclaimed(&self) -> usize {
+        self.before.total() - self.after.total()
+    }
+}
 
 /**
  * A Graph represents a fully constructed graph.
@@ -18,23 +106,620 @@ pub struct Graph {
 
     // The block store.
     block_store: BlockStore,
+
+    // The instruction side-index, if it has been built.
+    instr_index: Option<InstrIndex>,
+
+    // Every instruction decoded, for indexing the graph
+    // by id: decoded on first use, and dropped when the
+    // stream is written.
+    decoded: OnceLock<Vec<DecodedInstr>>,
+
+    // The def-use index, built on first use and
+    // dropped when the stream is written.
+    def_uses: OnceLock<DefUses>,
+
+    // The analyses of the graph's control flow and
+    // liveness, computed on first use and dropped by
+    // the edits that stale them.
+    cfg: OnceLock<Cfg>,
+    dominators: OnceLock<DomTree>,
+    loops: OnceLock<LoopInfo>,
+    liveness: OnceLock<Liveness>,
+
+    // How many times each cached analysis was computed.
+    computed: AnalysisCounters,
+
+    // The edits made in place since the graph was last
+    // rebuilt.
+    changes: ChangeLog,
+
+    // What the edits since the undo log was begun
+    // overwrote, if one is kept.
+    undo: Option<Vec<Undo>>,
+
+    // The execution profile, if one has been attached.
+    profile: Option<Profile>,
+
+    // The deopt snapshots attached to guards and exits,
+    // by instruction id.
+    snapshots: Vec<(InstrId, Snapshot)>,
+
+    // The machine the graph is built for.
+    target: TargetDesc
 }
 
+// A graph can be shared between threads, and borrowed
+// within `catch_unwind`, as a `reduce` predicate may.
+const _: fn() = || {
+    fn shareable<T: Send + Sync + RefUnwindSafe>() {}
+    shareable::<Graph>();
+};
+
 impl Graph {
     pub(crate) fn new(
-        instr_store: InstrStore, block_store: BlockStore)
+        instr_store: InstrStore, mut block_store: BlockStore)
       -> Graph 
     {
-        Graph { instr_store, block_store }
+        block_store.build_rpo_index();
+        Graph {
+            instr_store, block_store, instr_index: None,
+            decoded: OnceLock::new(), def_uses: OnceLock::new(),
+            cfg: OnceLock::new(), dominators: OnceLock::new(),
+            loops: OnceLock::new(), liveness: OnceLock::new(),
+            computed: AnalysisCounters::default(),
+            changes: ChangeLog::default(), undo: None, profile: None,
+            snapshots: Vec::new(), target: TargetDesc::default()
+        }
+    }
+
+    pub(crate) fn instr_store(&self) -> &InstrStore {
+        &self.instr_store
+    }
+    pub(crate) fn block_store(&self) -> &BlockStore {
+        &self.block_store
+    }
+    pub(crate) fn block_store_mut(&mut self) -> &mut BlockStore {
+        &mut self.block_store
+    }
+    // The instruction store, to be written.  Every
+    // write that changes the graph's instructions must
+    // be recorded, to drop the analyses it stales.
+    pub(crate) fn instr_store_mut(&mut self) -> &mut InstrStore {
+        self.decoded.take();
+        &mut self.instr_store
+    }
+
+    // Log an edit about to be made in place, saving
+    // what it overwrites if an undo log is kept, and
+    // dropping or updating the cached analyses it
+    // stales.
+    pub(crate) fn record(&mut self, change: Change) {
+        if let Some(mut undo) = self.undo.take() {
+            undo.push(self.undo_entry(change));
+            self.undo = Some(undo);
+        }
+        match change {
+          Change::Operand { user, site, old, new } => {
+            if let Some(def_uses) = self.def_uses.get_mut() {
+                def_uses.move_use(user, site, old, new);
+            }
+            self.liveness.take();
+          }
+          Change::Edge { .. } => {
+            self.def_uses.take();
+            self.drop_flow_analyses();
+          }
+          Change::Payload { .. } | Change::Snapshot { .. }
+            | Change::Patch { .. } => {}
+        }
+        self.changes.push(change);
+    }
+
+    // Drop the cached analyses of the control flow.
+    fn drop_flow_analyses(&mut self) {
+        self.cfg.take();
+        self.dominators.take();
+        self.loops.take();
+        self.liveness.take();
+    }
+
+    // Replace the graph with its rebuilt form, whose ids
+    // are all new, beginning a new epoch of its log.  An
+    // undo log keeps the graph replaced.
+    pub(crate) fn rebuilt(&mut self, graph: Graph) {
+        let mut changes = mem::take(&mut self.changes);
+        changes.rebuilt();
+        let undo = self.undo.take();
+        let computed = mem::take(&mut self.computed);
+        let old = mem::replace(self, graph);
+        self.changes = changes;
+        self.computed = computed;
+        if let Some(mut undo) = undo {
+            undo.push(Undo::Graph(Box::new(old)));
+            self.undo = Some(undo);
+        }
+    }
+
+    // The undo log, begun if none is kept.
+    pub(crate) fn undo_log_mut(&mut self) -> &mut Vec<Undo> {
+        self.undo.get_or_insert_with(Vec::new)
+    }
+    pub(crate) fn take_undo_log(&mut self) -> Option<Vec<Undo>> {
+        self.undo.take()
+    }
+
+    // Keep the undo log once the graph has been rolled
+    // back, its caches and change log being stale.
+    pub(crate) fn rolled_back(&mut self, undo: Vec<Undo>) {
+        self.decoded.take();
+        self.def_uses.take();
+        self.drop_flow_analyses();
+        self.changes.rebuilt();
+        self.undo = Some(undo);
+    }
+
+    /**
+     * Mark the current point in the graph's change
+     * log, for `changes_since` to look back to.
+     */
+    pub fn change_mark(&self) -> ChangeMark {
+        self.changes.mark()
+    }
+
+    /**
+     * The edits made in place since the mark was taken,
+     * in order, or none if the graph has been rebuilt
+     * since, changing every id.  Analyses kept outside
+     * the graph can update themselves by the changes, or
+     * must be computed anew.
+     */
+    pub fn changes_since(&self, mark: ChangeMark)
+      -> Option<&[Change]>
+    {
+        self.changes.since(mark)
+    }
+
+    // Every instruction of the stream, decoded, in
+    // stream order.
+    pub(crate) fn decoded_instrs(&self) -> &[DecodedInstr] {
+        self.decoded.get_or_init(|| {
+            let store = &self.instr_store;
+            InstrDecoder::with_encoding(store.instr_bytes(),
+                                        store.encoding())
+              .with_pool(store.const_pool().values())
+              .collect::<Result<_, _>>()
+              .expect("graph stream fails to decode")
+        })
+    }
+    pub(crate) fn snapshot_table(&self) -> &[(InstrId, Snapshot)] {
+        &self.snapshots
+    }
+    pub(crate) fn snapshot_table_mut(&mut self)
+      -> &mut [(InstrId, Snapshot)]
+    {
+        &mut self.snapshots
+    }
+    pub(crate) fn set_snapshots(&mut self,
+                                snapshots: Vec<(InstrId, Snapshot)>)
+    {
+        debug_assert!(snapshots.windows(2).all(|w| w[0].0 < w[1].0));
+        self.snapshots = snapshots;
+    }
+
+    // The machine the graph is built for.
+    pub fn target(&self) -> TargetDesc { self.target }
+    pub(crate) fn set_target(&mut self, target: TargetDesc) {
+        self.target = target;
+    }
+
+    // Look up a block by id.  Block ids handed out by
+    // the graph's own stores are always in range.
+    pub(crate) fn block(&self, id: BlockId) -> &Block {
+        assert!((id.as_u32() as usize)
+                  < self.block_store.total_blocks());
+        unsafe { self.block_store.get_block(id) }
+    }
+
+    // Iterate the instructions of a finished block,
+    // from its first instruction to its end instruction.
+    pub(crate) fn block_instrs(&self, block: &Block)
+      -> InstrIter<'_>
+    {
+        debug_assert!(block.has_finished());
+        unsafe {
+            self.instr_store.iter_from(block.first_instr())
+        }
+    }
+
+    // Decode the instruction with the given id.  Ids
+    // handed out by the graph's own stores always
+    // name the start of an instruction.
+    pub(crate) fn instr(&self, id: InstrId) -> InstrInfo<'_> {
+        assert!(id.offset() < self.instr_store.instr_bytes_len());
+        unsafe { self.instr_store.read_instr_info(id) }
+    }
+
+    // Decode the end instruction of a finished block.
+    pub(crate) fn block_end(&self, block: &Block)
+      -> InstrInfo<'_>
+    {
+        debug_assert!(block.has_finished());
+        unsafe {
+            self.instr_store.read_instr_info(block.last_instr())
+        }
+    }
+
+    /** The raw bytes of the instruction stream. */
+    pub fn instr_bytes(&self) -> &[u8] {
+        self.instr_store.instr_bytes()
+    }
+
+    /**
+     * The constant pool of the instruction stream, in
+     * order of first use, for decoding the stream's
+     * pooled constants.
+     */
+    pub fn const_pool(&self) -> &[u64] {
+        self.instr_store.const_pool().values()
+    }
+
+    /** How the instruction stream writes operands. */
+    pub fn operand_encoding(&self) -> OperandEncoding {
+        self.instr_store.encoding()
+    }
+
+    /** How the block store holds its blocks. */
+    pub fn block_storage(&self) -> BlockStorage {
+        self.block_store.storage()
+    }
+
+    /**
+     * Decode the instruction with the given id, with
+     * the bounds checks of the safe decoder.
+     */
+    pub fn decode_instr(&self, id: InstrId)
+      -> Result<DecodedInstr, DecodeError>
+    {
+        self.instr_store.try_decode_instr(id)
+    }
+
+    /**
+     * Build the instruction side-index, which the graph
+     * keeps for `instr_index` to return.
+     */
+    pub fn build_instr_index(&mut self) {
+        self.instr_index = Some(InstrIndex::compute(self));
+    }
+
+    /** The instruction side-index, if it has been built. */
+    pub fn instr_index(&self) -> Option<&InstrIndex> {
+        self.instr_index.as_ref()
+    }
+
+    /**
+     * The def-use index of the graph, built on first
+     * use and kept until the stream is next written.
+     */
+    pub fn def_uses(&self) -> &DefUses {
+        self.def_uses.get_or_init(|| {
+            self.computed.computed(Analysis::DefUses);
+            DefUses::compute(self)
+        })
+    }
+
+    // The block whose instruction range holds `id`,
+    // which need not start an instruction.
+    pub(crate) fn block_of_instr(&self, id: InstrId)
+      -> Option<BlockId>
+    {
+        match &self.instr_index {
+          Some(index) if index.contains(id) => index.block_of(id),
+          _ => self.block_store.block_containing(id)
+        }
+    }
+
+    /**
+     * Attach an execution profile to the graph, for
+     * `profile` to return, once it is checked against
+     * the graph's blocks and edges.
+     */
+    pub fn attach_profile(&mut self, profile: Profile)
+      -> Result<(), ProfileError>
+    {
+        profile.check(self) ?;
+        let old = self.profile.replace(profile);
+        if let Some(ref mut undo) = self.undo {
+            undo.push(Undo::Profile(old));
+        }
+        Ok(())
+    }
+
+    /**
+     * Parse a profile from its text format, as written
+     * by an external profiler, and attach it.
+     */
+    pub fn import_profile(&mut self, text: &str)
+      -> Result<(), ProfileError>
+    {
+        self.attach_profile(Profile::parse(text) ?)
+    }
+
+    /** The attached execution profile, if any. */
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+    pub(crate) fn profile_mut(&mut self) -> &mut Option<Profile> {
+        &mut self.profile
+    }
+
+    /**
+     * Release the spare capacity the builder left in the
+     * graph's vectors, for graphs kept resident for a
+     * long time.  Returns the heap bytes the graph held
+     * before and after.
+     */
+    pub fn freeze(&mut self, mode: FreezeMode) -> FreezeStats {
+        let before = self.memory_usage();
+        self.instr_store.shrink_to_fit();
+        self.block_store.shrink_to_fit();
+        if mode == FreezeMode::Strip {
+            self.instr_index = None;
+            self.decoded.take();
+            self.def_uses.take();
+            self.drop_flow_analyses();
+        }
+        FreezeStats { before, after: self.memory_usage() }
+    }
+
+    /**
+     * The heap bytes the graph holds.  A stream loaded
+     * from shared bytes, such as a mapped file, counts
+     * for nothing until it is copied out.
+     */
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let index = self.instr_index.as_ref();
+        let pool = self.instr_store.const_pool();
+        MemoryUsage {
+            instr_stream: self.instr_store.heap_bytes(),
+            block_table: self.block_store.block_table_bytes(),
+            rpo_index: self.block_store.rpo_index_bytes(),
+            side_tables: index.map_or(0, |i| i.heap_bytes())
+                           + pool.heap_bytes()
+                           + self.decoded.get().map_or(0, |d| {
+                               d.iter().map(|i| i.heap_bytes()).sum()
+                             })
+                           + self.def_uses.get()
+                                 .map_or(0, |d| d.heap_bytes())
+        }
     }
 
     pub fn dump_stats(&self, name: &'static str) {
-        debug!("Graph {} instrs={} blocks={}",
+        debug!("Graph {} instrs={} bytes={} blocks={}",
                name,
+               self.instr_store.num_instrs(),
                self.instr_store.instr_bytes_len(),
                self.block_store.total_blocks());
     }
 
+    /**
+     * The control flow graph, computed on first use
+     * and kept until an edge is edited.
+     */
+    pub fn cfg(&self) -> &Cfg {
+        self.cfg.get_or_init(|| {
+            self.computed.computed(Analysis::Cfg);
+            Cfg::compute(self)
+        })
+    }
+
+    /** The dominator tree, kept as the `cfg` is. */
+    pub fn dominators(&self) -> &DomTree {
+        self.dominators.get_or_init(|| {
+            self.computed.computed(Analysis::Dominators);
+            DomTree::compute(self, self.cfg())
+        })
+    }
+
+    /**
+     * Block-level liveness of definitions, computed on
+     * first use and kept until an operand or an edge is
+     * edited.
+     */
+    pub fn liveness(&self) -> &Liveness {
+        self.liveness.get_or_init(|| {
+            self.computed.computed(Analysis::Liveness);
+            Liveness::compute(self, self.cfg())
+        })
+    }
+
+    /**
+     * Break down the bytes of the instruction stream by
+     * opcode, and into opcodes and payloads, operands
+     * and target lists.
+     */
+    pub fn encoding_stats(&self) -> EncodingStats {
+        EncodingStats::compute(self)
+    }
+
+    /**
+     * How many times each cached analysis of the graph
+     * has been computed, counting the graphs it was
+     * rebuilt from.
+     */
+    pub fn analysis_counts(&self) -> AnalysisCounts {
+        self.computed.counts()
+    }
+    pub(crate) fn analysis_counters(&self) -> &AnalysisCounters {
+        &self.computed
+    }
+
+    /** The natural loops of the graph, kept as the `cfg` is. */
+    pub fn loops(&self) -> &LoopInfo {
+        self.loops.get_or_init(|| {
+            self.computed.computed(Analysis::Loops);
+            LoopInfo::compute(self, self.cfg())
+        })
+    }
+
+    /**
+     * Compute how far each allocation-like value
+     * escapes the graph.
+     */
+    pub fn escapes(&self) -> EscapeInfo {
+        EscapeInfo::compute(self)
+    }
+
+    /**
+     * Compute the basic and derived induction
+     * variables of the graph's loops.
+     */
+    pub fn induction_vars(&self) -> InductionVars {
+        InductionVars::compute(self, self.cfg(), self.dominators(),
+                               self.loops())
+    }
+
+    /**
+     * Compute the MemorySSA form of the graph's loads,
+     * stores and patch points.
+     */
+    pub fn memory_ssa(&self) -> MemorySsa {
+        MemorySsa::compute(self, self.cfg(), self.dominators())
+    }
+
+    /**
+     * Plan the straight-line vectorization of the
+     * graph's blocks into packs of isomorphic scalar
+     * instructions.
+     */
+    pub fn slp_plan(&self) -> SlpPlan {
+        SlpPlan::compute(self, self.dominators())
+    }
+
+    /**
+     * Estimate register pressure per block and per
+     * loop from previously computed liveness.
+     */
+    pub fn reg_pressure(&self, liveness: &Liveness)
+      -> RegPressure
+    {
+        RegPressure::compute(self, liveness, self.loops())
+    }
+
+    /** Compute live intervals from block liveness. */
+    pub fn live_intervals(&self, liveness: &Liveness)
+      -> LiveIntervals
+    {
+        LiveIntervals::compute(self, liveness)
+    }
+
+    /**
+     * Coalesce the graph's phis with their arguments,
+     * and copies with their operands, where their live
+     * intervals allow, for register assignment.
+     */
+    pub fn coalesce_copies(&self, intervals: &LiveIntervals)
+      -> Coalescing
+    {
+        Coalescing::compute(self, intervals)
+    }
+
+    /**
+     * Lower the graph's params and returns to the moves
+     * the calling convention makes of them, given the
+     * allocation of its values.
+     */
+    pub fn lower_abi(&self, conv: &CallConv, alloc: &Allocation)
+      -> AbiLowering
+    {
+        AbiLowering::compute(self, conv, alloc)
+    }
+
+    /**
+     * Hint the graph's params passed in registers to
+     * be allocated to them, and its returned values to
+     * the return register, under the calling
+     * convention.
+     */
+    pub fn abi_reg_hints(&self, conv: &CallConv) -> RegHints {
+        RegHints::for_abi(self, conv)
+    }
+
+    /**
+     * Lay out the stack frame of the graph under the
+     * calling convention, given the allocation of its
+     * values, with the spill code the allocation needs.
+     */
+    pub fn frame_layout(&self, alloc: &Allocation, conv: &CallConv)
+      -> FrameLayout
+    {
+        FrameLayout::compute(self, alloc, conv)
+    }
+
+    /**
+     * Compute a list schedule for every block in the
+     * graph, using the given per-opcode latencies.
+     */
+    pub fn schedule(&self, latencies: &LatencyTable)
+      -> Schedule
+    {
+        Schedule::compute(self, latencies)
+    }
+
+    /**
+     * Check the structure of the graph: operands name
+     * dominating value definitions, phi arguments match
+     * their targets, blocks end in end instructions,
+     * back edges form natural loops, and pointer
+     * constants fit the pointers of the graph's target.
+     */
+    pub fn verify(&self) -> Result<(), Vec<VerifyError>> {
+        Verifier::new(self).run()
+    }
+
+    /**
+     * Check the structure of the graph's stores, much
+     * more cheaply than `verify`: the RPO index covers
+     * every block once, block instruction ranges are
+     * ordered without overlap, and every block's last
+     * instruction is an end instruction.
+     */
+    pub fn check_invariants(&self) -> Result<(), InvariantError> {
+        invariants::check_invariants(self)
+    }
+
+    /**
+     * Check the bytes of the graph's instruction stream:
+     * decoded from the start, its instructions exactly
+     * tile it, and every operand and phi argument names
+     * the start of an earlier instruction.  The error
+     * gives the first offset found inconsistent.
+     */
+    pub fn validate_stream(&self) -> Result<(), StreamError> {
+        self.instr_store().validate()
+    }
+
+    /**
+     * Check that every operand has the type expected by
+     * its instruction's operation.  Only meaningful for
+     * graphs that pass `verify`.
+     */
+    pub fn type_check(&self) -> Result<(), Vec<TypeError>> {
+        typecheck::type_check(self)
+    }
+
+    /**
+     * Extract the linear trace starting at `start`,
+     * following branches in the directions recorded
+     * by `profile`.
+     */
+    pub fn extract_trace(&self,
+        start: BlockId, profile: &BranchProfile)
+      -> Trace
+    {
+        Trace::extract(self, start, profile)
+    }
+
     pub fn enter_session<R, F>(&self, f: F) -> R
       where F: for <'gs> FnOnce (&mut GraphSession<'gs>)
                             -> R
@@ -48,6 +733,18 @@ impl Graph {
 
         f(&mut sess)
     }
+
+    /**
+     * Hold the graph for editing through a session,
+     * which checks the graph once `f` returns.
+     */
+    pub fn enter_mut_session<R, F>(&mut self, f: F) -> R
+      where F: for <'gs> FnOnce (&mut GraphMutSession<'gs>)
+                            -> R
+    {
+        let mut sess = GraphMutSession::new(self);
+        f(&mut sess)
+    }
 }
 
 /**
@@ -105,6 +802,26 @@ impl<'gs> GraphSession<'gs> {
         self.cur_instr.inputs_iter()
     }
 
+    /**
+     * The block defining a value: through the
+     * instruction side-index if it has been built, and
+     * otherwise by a binary search of the blocks'
+     * instruction ranges.
+     */
+    pub fn block_of(&self, defn: Defn<'gs>) -> BlockId {
+        self.graph.block_of_instr(defn.instr_id())
+          .expect("Defn outside every block.")
+    }
+
+    /**
+     * The uses of a value by operands and phi arguments,
+     * ordered by user and site, from the graph's
+     * def-use index.
+     */
+    pub fn uses(&self, defn: Defn<'gs>) -> Uses<'gs> {
+        self.graph.def_uses().uses(defn.instr_id())
+    }
+
     // Go to the next instruction, returning its Defn.
     // If at the last instruction, None is returned.
     pub fn next_defn(&mut self) -> Option<Defn<'gs>> {
@@ -120,7 +837,15 @@ impl<'gs> GraphSession<'gs> {
 
     pub fn debug_print_cur_instr(&self) {
         let bl = self.get_cur_block();
-        debug!("{} {} - {}",
-            bl.id(), self.cur_instr.defn(), self.cur_op());
+        let mut inputs_str = String::new();
+        for (i, def) in self.cur_inputs().enumerate() {
+            if i > 0 {
+                write!(inputs_str, ", ").unwrap();
+            }
+            write!(inputs_str, "{}", def.instr_id().offset()).unwrap();
+        }
+        debug!("{} {} - {}({})",
+            bl.id(), self.cur_instr.defn(), self.cur_op(),
+            inputs_str);
     }
 }
@@ -0,0 +1,380 @@
+
+use std::collections::{ HashMap, HashSet };
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::{ Op, ConstOp, BiniOp, BiniKind, CmpKind, Operation };
+use crate::analysis::{
+    Cfg, DomTree, LoopInfo, InductionVars, BasicIv, DerivedIv
+};
+use crate::analysis::wrap;
+use crate::ir_types::IrTypeId;
+use crate::text::Rebuild;
+
+/*
+ * Induction variable simplification.  A derived
+ * variable `i * k + base + offset`, with a scale other
+ * than 1, is strength-reduced to a new header phi that
+ * starts at its value for the entry value of `i`, and
+ * is stepped by `step * k` over each back edge, so an
+ * address computed from an index each iteration
+ * becomes a pointer incremented along with it.  A
+ * derived variable used only by other derived
+ * variables is left to them.
+ *
+ * An exit condition `eq` or `ne` of a basic variable
+ * against a loop-invariant bound is then rewritten to
+ * compare a reduced variable against the bound scaled
+ * in the same way, when the loop has one entry edge to
+ * compute the bound on.  This holds at any width only
+ * for odd scales, by which multiplication cannot wrap
+ * two values together.  A basic variable left used
+ * only by its own increments is removed.
+ *
+ * The graph is rebuilt with the new instructions, so
+ * the ids of its instructions and blocks change.
+ */
+
+/** What `Graph::simplify_induction_vars` did. */
+#[derive(Clone, Copy, Debug, Default)]
+#[derive(PartialEq, Eq)]
+pub struct IndVarStats {
+    reduced: u32,
+    exits: u32,
+    removed: u32
+}
+
+impl IndVarStats {
+    // The derived variables strength-reduced to phis.
+    pub fn reduced(&self) -> u32 { self.reduced }
+
+    // The exit conditions rewritten to reduced
+    // variables.
+    pub fn exits(&self) -> u32 { self.exits }
+
+    // The basic variables removed once unused.
+    pub fn removed(&self) -> u32 { self.removed }
+
+    pub fn changed(&self) -> bool {
+        self.reduced + self.exits + self.removed > 0
+    }
+}
+
+// The block, op and operands of each instruction.
+type OpTable = HashMap<InstrId, (BlockId, Op, Vec<InstrId>)>;
+
+fn const_op(ty: IrTypeId, v: u64) -> Op {
+    match ty {
+      IrTypeId::Int32 => ConstOp::new_int32(v as u32).op(),
+      _ => ConstOp::new_int64(v).op()
+    }
+}
+
+fn bini(kind: BiniKind, ty: IrTypeId) -> Op {
+    BiniOp::new(kind, ty).op()
+}
+
+// Emit `value * scale + offset + base` at the end of a
+// block, folding a constant value, and give its name.
+fn emit_affine(rb: &mut Rebuild, block: BlockId, ty: IrTypeId,
+               value: Result<u64, InstrId>, scale: u64, offset: u64,
+               base: Option<InstrId>)
+  -> String
+{
+    let mut v = match value {
+      Ok(c) => {
+        let c = wrap(ty, c.wrapping_mul(scale).wrapping_add(offset));
+        rb.insert_before_end(block, const_op(ty, c), &[]).unwrap()
+      }
+      Err(id) => {
+        let k = rb.insert_before_end(block, const_op(ty, scale), &[])
+                  .unwrap();
+        let mut v = rb.insert_before_end(block, bini(BiniKind::Mul, ty),
+                                         &[&rb.value(id), &k]).unwrap();
+        if offset != 0 {
+            let c = rb.insert_before_end(block, const_op(ty, offset),
+                                         &[]).unwrap();
+            v = rb.insert_before_end(block, bini(BiniKind::Add, ty),
+                                     &[&v, &c]).unwrap();
+        }
+        v
+      }
+    };
+    if let Some(base) = base {
+        v = rb.insert_before_end(block, bini(BiniKind::Add, ty),
+                                 &[&v, &rb.value(base)]).unwrap();
+    }
+    v
+}
+
+// Remove an unused value, and the values computing it
+// that become unused in turn.  Phis are kept.
+fn remove_dead(rb: &mut Rebuild, value: &str) {
+    let mut work = vec![value.to_string()];
+    while let Some(v) = work.pop() {
+        if rb.count_uses(&v) > 0 {
+            continue;
+        }
+        match rb.def(&v) {
+          Some((Op::Bini(_), operands))
+            | Some((Op::Const(_), operands)) => {
+            rb.remove_value(&v);
+            work.extend(operands);
+          }
+          _ => {}
+        }
+    }
+}
+
+impl Graph {
+    /**
+     * Strength-reduce the derived induction variables
+     * of the graph's loops to incremented phis, and
+     * rewrite `eq` and `ne` exit conditions to compare
+     * them.  When anything changes, the graph is
+     * rebuilt: its instruction and block ids change,
+     * and its profile, instruction index and patched
+     * patch point bytes are dropped.
+     */
+    pub fn simplify_induction_vars(&mut self) -> IndVarStats {
+        let cfg = Cfg::compute(self);
+        let dom = DomTree::compute(self, &cfg);
+        let loops = LoopInfo::compute(self, &cfg);
+        let ivs = InductionVars::compute(self, &cfg, &dom, &loops);
+
+        // The users of each value, counting the ends
+        // passing it to phis and the instructions whose
+        // snapshots hold it; and the loop blocks.
+        let mut users: HashMap<InstrId, Vec<InstrId>> = HashMap::new();
+        let mut ops: OpTable = HashMap::new();
+        for block in self.block_store().iter_rpo_blocks() {
+            for info in self.block_instrs(block) {
+                let id = info.defn().instr_id();
+                let mut inputs: Vec<InstrId> =
+                  info.inputs_iter().map(|d| d.instr_id()).collect();
+                ops.insert(id, (block.id(), info.op().clone(),
+                                inputs.clone()));
+                for target in info.targets_iter() {
+                    inputs.extend(target.phi_args()
+                                        .map(|d| d.instr_id()));
+                }
+                if let Some(snapshot) = self.snapshot(id) {
+                    inputs.extend(snapshot.entries().iter()
+                                          .map(|e| e.1));
+                }
+                for v in inputs {
+                    users.entry(v).or_default().push(id);
+                }
+            }
+        }
+
+        let is_candidate = |d: &DerivedIv| !matches!(d.scale(), 0 | 1);
+        let candidates: HashSet<InstrId> = ivs.derived().iter()
+          .filter(|d| is_candidate(d)).map(|d| d.value()).collect();
+        let reduce: Vec<&DerivedIv> = ivs.derived().iter()
+          .filter(|d| is_candidate(d))
+          .filter(|d| users.get(&d.value()).is_some_and(|u| {
+              u.iter().any(|u| !candidates.contains(u))
+          }))
+          .collect();
+
+        let mut stats = IndVarStats::default();
+        let mut rb = Rebuild::new(self);
+
+        // The reduced phis of each basic variable, with
+        // the scale of each.
+        let mut reduced: HashMap<InstrId, Vec<(String, &DerivedIv)>> =
+          HashMap::new();
+        for d in reduce {
+            let basic = ivs.basic_of(d.basic()).unwrap();
+            let ty = basic.ty();
+            let phi = rb.add_phi(basic.header(), ty);
+            for &(from, index, init) in basic.entries() {
+                let init = match ops[&init].1 {
+                  Op::Const(ConstOp::Int32(c)) => Ok(c as u64),
+                  Op::Const(ConstOp::Int64(c)) => Ok(c),
+                  _ => Err(init)
+                };
+                let v = emit_affine(&mut rb, from, ty, init, d.scale(),
+                                    d.offset(), d.base());
+                rb.add_edge_arg(from, index, &v);
+            }
+            let step = wrap(ty, basic.step().wrapping_mul(d.scale()));
+            for &(from, index, _) in basic.latches() {
+                let c = rb.insert_before_end(from, const_op(ty, step),
+                                             &[]).unwrap();
+                let v = rb.insert_before_end(from,
+                                             bini(BiniKind::Add, ty),
+                                             &[&phi, &c]).unwrap();
+                rb.add_edge_arg(from, index, &v);
+            }
+
+            let value = rb.value(d.value());
+            rb.replace_uses(&value, &phi);
+            remove_dead(&mut rb, &value);
+            reduced.entry(d.basic()).or_default().push((phi, d));
+            stats.reduced += 1;
+        }
+
+        for basic in ivs.basic() {
+            if let Some(reduced) = reduced.get(&basic.phi()) {
+                stats.exits += Self::rewrite_exits(
+                  &mut rb, &loops, &dom, basic, reduced, &ops);
+            }
+            if Self::remove_basic(&mut rb, basic) {
+                stats.removed += 1;
+            }
+        }
+
+        if stats.changed() {
+            self.rebuilt(rb.finish());
+        }
+        stats
+    }
+
+    // Rewrite the `eq` and `ne` exit conditions of a
+    // basic variable, giving the number rewritten.
+    fn rewrite_exits(rb: &mut Rebuild, loops: &LoopInfo,
+                     dom: &DomTree, basic: &BasicIv,
+                     reduced: &[(String, &DerivedIv)], ops: &OpTable)
+      -> u32
+    {
+        let odd = reduced.iter().find(|r| r.1.scale() & 1 == 1);
+        let (phi, d) = match odd {
+          Some(r) => r,
+          None => return 0
+        };
+        let (from, _, _) = match basic.entries() {
+          [entry] => *entry,
+          _ => return 0
+        };
+        let lp = loops.loop_of(basic.header()).unwrap();
+        let ty = basic.ty();
+        let konst = |v: InstrId| match ops[&v].1 {
+          Op::Const(ConstOp::Int32(c)) => Some(c as u64),
+          Op::Const(ConstOp::Int64(c)) => Some(c),
+          _ => None
+        };
+
+        let mut cmps: Vec<InstrId> = ops.keys().cloned().collect();
+        cmps.sort_unstable();
+        let mut exits = 0;
+        for id in cmps {
+            let (block, op, inputs) = &ops[&id];
+            match op {
+              Op::Cmp(cmp) if lp.contains(*block)
+                && matches!(cmp.kind(), CmpKind::Eq | CmpKind::Ne) => {}
+              _ => continue
+            }
+            let (i, n) = if inputs[0] == basic.phi() {
+                (0, inputs[1])
+            } else if inputs[1] == basic.phi() {
+                (1, inputs[0])
+            } else {
+                continue
+            };
+            let bound = match konst(n) {
+              Some(c) => Ok(c),
+              None => {
+                let b = ops[&n].0;
+                if lp.contains(b) || !dom.dominates(b, from) {
+                    continue;
+                }
+                Err(n)
+              }
+            };
+            let bound = emit_affine(rb, from, ty, bound, d.scale(),
+                                    d.offset(), d.base());
+            let mut operands = [phi.as_str(), bound.as_str()];
+            if i == 1 {
+                operands.swap(0, 1);
+            }
+            rb.set_operands(&rb.value(id), &operands);
+            exits += 1;
+        }
+        exits
+    }
+
+    // Remove a basic variable used only by its own
+    // increments, and the increments.
+    fn remove_basic(rb: &mut Rebuild, basic: &BasicIv) -> bool {
+        let phi = rb.value(basic.phi());
+        let mut incrs: Vec<String> =
+          basic.latches().iter().map(|l| rb.value(l.2)).collect();
+        incrs.sort_unstable();
+        incrs.dedup();
+        if rb.count_uses(&phi) != incrs.len() {
+            return false;
+        }
+        for incr in &incrs {
+            let passed = basic.latches().iter()
+                              .filter(|l| &rb.value(l.2) == incr)
+                              .count();
+            if rb.count_uses(incr) != passed {
+                return false;
+            }
+        }
+        rb.remove_value(&phi);
+        for incr in &incrs {
+            remove_dead(rb, incr);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::Graph;
+
+    fn counted_loop(scale: u32) -> Graph {
+        Graph::parse_ir(&format!("\
+block0:
+  %0 = const.i32 0
+  %1 = const.i32 10
+  %2 = const.i32 {}
+  %3 = param.i32 0
+  jump block1(%0)
+loop block1(%4: i32) {{
+  %5 = ne.i32 %4, %1
+  branch %5, block2, block3
+  block2:
+    %6 = mul.i32 %4, %2
+    %7 = add.i32 %6, %3
+    %8 = call.i32 0, %7
+    %9 = const.i32 1
+    %10 = add.i32 %4, %9
+    jump block1(%10)
+}}
+block3:
+  ret.i32 %1
+", scale)).unwrap()
+    }
+
+    #[test]
+    fn odd_scale_rewrites_exit() {
+        let mut graph = counted_loop(3);
+        let stats = graph.simplify_induction_vars();
+        assert_eq!((stats.reduced(), stats.exits(), stats.removed()),
+                   (1, 1, 1));
+        let text = graph.print_ir();
+        assert!(text.contains("%5 = const.i32 30"));
+        assert!(text.contains("%8 = ne.i32 %7, %6"));
+        assert!(graph.verify().is_ok());
+    }
+
+    #[test]
+    fn even_scale_keeps_exit() {
+        // Multiplying by 4 can wrap two indices to the
+        // same address, so the exit on `i != 10` does not
+        // become one on the address.
+        let mut graph = counted_loop(4);
+        let stats = graph.simplify_induction_vars();
+        assert_eq!((stats.reduced(), stats.exits(), stats.removed()),
+                   (1, 0, 0));
+        let text = graph.print_ir();
+        assert!(text.contains("%7 = ne.i32 %5, %1"));
+        assert!(text.contains("%12 = add.i32 %6, %11"));
+        assert!(graph.verify().is_ok());
+    }
+}
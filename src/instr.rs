@@ -1,26 +1,203 @@
 
 use std::fmt;
-use std::str::from_utf8;
 use std::fmt::Write;
+use std::mem;
+use std::ops::Deref;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
 
-use crate::ops::{ Operation, Op };
+use crate::ops::{
+    Operation, Opcode, Op, PatchPointOp, POOLED_CONST_TAG
+};
 use crate::block::BlockId;
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
 use crate::defn::Defn;
+use crate::const_pool::ConstPool;
+use crate::patch::PatchPoint;
+use crate::def_use::UseSite;
 
 use crate::leb128;
+use crate::decode::{
+    ByteReader, DecodeError, DecodedInstr, StreamError
+};
+
+/**
+ * The encoding interface shared by statically typed
+ * operations and dynamically decoded `Op` values, so
+ * either can be appended to an instruction stream.
+ */
+pub(crate) trait EncodeOp: fmt::Display {
+    fn encode_opcode(&self) -> Opcode;
+    fn encode_terminal(&self) -> bool;
+    fn encode_out_type(&self) -> Option<IrTypeId>;
+    fn encode_operand_type(&self, idx: u32) -> IrInputTypeId;
+    fn encode_payload(&self, vec: &mut Vec<u8>);
+    fn encode_pool_value(&self) -> Option<u64>;
+}
+
+impl<OP: Operation> EncodeOp for OP {
+    fn encode_opcode(&self) -> Opcode { OP::opcode() }
+    fn encode_terminal(&self) -> bool { OP::terminal() }
+    fn encode_out_type(&self) -> Option<IrTypeId> {
+        self.out_type()
+    }
+    fn encode_operand_type(&self, idx: u32) -> IrInputTypeId {
+        self.operand_type(idx)
+    }
+    fn encode_payload(&self, vec: &mut Vec<u8>) {
+        self.write_to(vec)
+    }
+    fn encode_pool_value(&self) -> Option<u64> {
+        self.pool_value()
+    }
+}
+
+impl EncodeOp for Op {
+    fn encode_opcode(&self) -> Opcode { self.opcode() }
+    fn encode_terminal(&self) -> bool { self.terminal() }
+    fn encode_out_type(&self) -> Option<IrTypeId> {
+        self.out_type()
+    }
+    fn encode_operand_type(&self, idx: u32) -> IrInputTypeId {
+        self.operand_type(idx)
+    }
+    fn encode_payload(&self, vec: &mut Vec<u8>) {
+        self.write_to(vec)
+    }
+    fn encode_pool_value(&self) -> Option<u64> {
+        self.pool_value()
+    }
+}
+
+/**
+ * How the operands of instructions are written: as the
+ * leb128 absolute offsets of their definitions, as
+ * leb128 backward deltas from the offset of the using
+ * instruction, which stay small however large the
+ * graph grows, or as fixed-width absolute offsets.
+ * Phi arguments are operands of the end instruction
+ * passing them.
+ *
+ * The fixed-width encoding also writes the block ids
+ * and argument counts of targets in little-endian
+ * slots as wide as offsets (4 bytes, or 8 with
+ * `wide-offsets`), so any operand of an instruction,
+ * and the end of its operands, is found without
+ * decoding the operands before it.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum OperandEncoding {
+    Absolute,
+    Delta,
+    Fixed
+}
+
+/**
+ * How the byte vector of a built instruction stream
+ * is allocated and grows.  A stream must be
+ * contiguous, so growing it moves it; a graph of
+ * known rough size avoids the moves with a large
+ * enough initial capacity.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum StreamGrowth {
+    // Start with `initial` bytes, doubling the capacity
+    // whenever it runs out.
+    Doubling { initial: usize },
+
+    // Start with `initial` bytes, growing the capacity
+    // by `chunk` bytes whenever it runs out.
+    Chunked { initial: usize, chunk: usize },
+
+    // Allocate `size` bytes once and never grow: a
+    // build whose stream outgrows them fails.
+    FixedSlab { size: usize }
+}
+
+impl StreamGrowth {
+    // The capacity allocated up front.
+    pub fn initial(&self) -> usize {
+        match *self {
+          StreamGrowth::Doubling { initial } => initial,
+          StreamGrowth::Chunked { initial, .. } => initial,
+          StreamGrowth::FixedSlab { size } => size
+        }
+    }
+}
+
+impl Default for StreamGrowth {
+    fn default() -> StreamGrowth {
+        StreamGrowth::Doubling { initial: InstrStore::INIT_INSTR_BYTES }
+    }
+}
+
+/**
+ * The bytes of an instruction stream: either owned,
+ * or a read-only range of a shared buffer (such as a
+ * mapped file) that is copied out only if the stream
+ * is written to.
+ */
+pub(crate) enum InstrBytes {
+    Owned(Vec<u8>),
+    Shared {
+        buf: Arc<dyn AsRef<[u8]> + Send + Sync + RefUnwindSafe>,
+        start: usize,
+        end: usize
+    }
+}
+
+impl InstrBytes {
+    // The owned bytes, first copying shared ones.
+    fn to_mut(&mut self) -> &mut Vec<u8> {
+        if let InstrBytes::Shared { .. } = *self {
+            *self = InstrBytes::Owned(self.to_vec());
+        }
+        match *self {
+          InstrBytes::Owned(ref mut vec) => vec,
+          InstrBytes::Shared { .. } => unreachable!()
+        }
+    }
+}
+
+impl Deref for InstrBytes {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        match *self {
+          InstrBytes::Owned(ref vec) => vec,
+          InstrBytes::Shared { ref buf, start, end } => {
+            &(**buf).as_ref()[start .. end]
+          }
+        }
+    }
+}
 
 /** Stores a writable instruction stream and presents
  * an API to write (append-only) instructions to it,
  * and to read from it. */
 pub(crate) struct InstrStore {
     /** The raw instruction bytes. */
-    instr_bytes: Vec<u8>,
+    instr_bytes: InstrBytes,
 
     /** Max len of vec. */
-    max_len: u32,
+    max_len: usize,
+
+    /** How the vec grows as instructions are emitted. */
+    growth: StreamGrowth,
 
     /** The number of instructions emitted. */
     num_instrs: u32,
+
+    /** How operands are written. */
+    encoding: OperandEncoding,
+
+    /** The large constants the stream refers to. */
+    const_pool: ConstPool,
+
+    /** The space reserved by patch points, in stream
+        order. */
+    patch_points: Vec<PatchPoint>,
 }
 
 /**
@@ -43,12 +220,16 @@ pub(crate) struct InstrInfo<'a> {
      * Also the offset to the targets list for
      * end instructions. */
     after_inputs_offset: u32,
+
+    /** How the operands are written. */
+    encoding: OperandEncoding,
 }
 
 /**
  * An InstrInputs iterates through the input
  * definitions for an instruction.
  */
+#[derive(Clone)]
 pub struct InstrInputs<'a> {
     // Remaining # of inputs to read.
     remaining: u32,
@@ -57,41 +238,148 @@ pub struct InstrInputs<'a> {
     bytes_read: u32,
 
     // The current bytes cursor.
-    bytes: &'a [u8]
+    bytes: &'a [u8],
+
+    // How the operands are written, and the
+    // instruction they belong to.
+    encoding: OperandEncoding,
+    user: InstrId
 }
+/**
+ * An InstrTargets iterates through the (block, phi-args)
+ * target list of an end instruction.
+ */
+pub(crate) struct InstrTargets<'a> {
+    // Remaining # of targets to read.
+    remaining: u32,
+
+    // The current bytes cursor.
+    bytes: &'a [u8],
+
+    // As for `InstrInputs`.
+    encoding: OperandEncoding,
+    user: InstrId
+}
+
+/**
+ * A single decoded target of an end instruction.
+ */
+pub(crate) struct InstrTarget<'a> {
+    block_id: BlockId,
+    phi_args: InstrInputs<'a>
+}
+
+/**
+ * An InstrIter walks the instructions of a block in
+ * stream order, starting from the first instruction
+ * and ending with the block's end instruction.
+ */
+pub(crate) struct InstrIter<'a> {
+    store: &'a InstrStore,
+    next: Option<InstrId>
+}
+
+/**
+ * The integer holding instruction offsets.  Offsets
+ * are 32 bits, limiting a stream to 16MB, unless the
+ * `wide-offsets` feature makes them 64 bits.
+ */
+#[cfg(not(feature = "wide-offsets"))]
+pub(crate) type OffsetRepr = u32;
+#[cfg(feature = "wide-offsets")]
+pub(crate) type OffsetRepr = u64;
+
 /**
  * The offset of an instruction in the instruction stream.
  * Serves as the canonical id for an instruction.
  */
 #[derive(Clone, Copy, Debug)]
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
-pub struct InstrPosn(u32);
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InstrPosn(OffsetRepr);
 
 /**
  * The id of an instruction is just its position.
  */
 #[derive(Clone, Copy, Debug)]
-#[derive(PartialEq, Eq, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct InstrId(InstrPosn);
 
 impl InstrStore {
     const INIT_INSTR_BYTES: usize = 256;
-    const MAX_INSTR_BYTES: usize = 0xff_ffff;
 
-    pub(crate) fn new() -> InstrStore {
-        let max_len = Self::MAX_INSTR_BYTES as u32;
-        let instr_bytes =
-          Vec::with_capacity(Self::INIT_INSTR_BYTES);
-        InstrStore { instr_bytes, max_len, num_instrs: 0 }
+    // Room for an opcode and its payload: a type or
+    // kind byte and leb128 immediates, leaving aside
+    // the space of a patch point.
+    const MAX_HEADER_BYTES: usize = 1 + 2 * leb128::MAX_LEB128_BYTES;
+    #[cfg(not(feature = "wide-offsets"))]
+    pub(crate) const MAX_INSTR_BYTES: usize = 0xff_ffff;
+    #[cfg(feature = "wide-offsets")]
+    pub(crate) const MAX_INSTR_BYTES: usize = 0xff_ffff_ffff;
+
+    pub(crate) fn new(encoding: OperandEncoding) -> InstrStore {
+        InstrStore::with_growth(encoding, StreamGrowth::default())
+    }
+
+    // A store allocating and growing its bytes as
+    // `growth` says.  A fixed slab bounds the stream.
+    pub(crate) fn with_growth(encoding: OperandEncoding,
+                              growth: StreamGrowth)
+      -> InstrStore
+    {
+        let max_len = match growth {
+          StreamGrowth::FixedSlab { size } => {
+            size.min(Self::MAX_INSTR_BYTES)
+          }
+          _ => Self::MAX_INSTR_BYTES
+        };
+        let capacity = growth.initial().min(max_len);
+        let instr_bytes = InstrBytes::Owned(
+          Vec::with_capacity(capacity));
+        InstrStore {
+            instr_bytes, max_len, growth, num_instrs: 0, encoding,
+            const_pool: ConstPool::new(),
+            patch_points: Vec::new()
+        }
+    }
+
+    // Adopt a stream of already-checked instruction
+    // bytes, with the constant pool it was checked
+    // against and the patch points found in it, or None
+    // if it is longer than a store may be.
+    pub(crate) fn from_bytes(instr_bytes: InstrBytes,
+                             num_instrs: u32,
+                             encoding: OperandEncoding,
+                             const_pool: ConstPool,
+                             patch_points: Vec<PatchPoint>)
+      -> Option<InstrStore>
+    {
+        let max_len = Self::MAX_INSTR_BYTES;
+        if instr_bytes.len() > max_len {
+            return None;
+        }
+        Some(InstrStore {
+            instr_bytes, max_len, growth: StreamGrowth::default(),
+            num_instrs, encoding, const_pool, patch_points
+        })
+    }
+
+    // The size of the fixed slab bounding the stream,
+    // if it has one.
+    pub(crate) fn slab_size(&self) -> Option<usize> {
+        match self.growth {
+          StreamGrowth::FixedSlab { .. } => Some(self.max_len),
+          _ => None
+        }
     }
 
     fn within_limits(&self) -> bool {
-        self.instr_bytes.len() <= (self.max_len as usize)
+        self.instr_bytes.len() <= self.max_len
+          && self.num_instrs < u32::MAX
     }
 
     fn front_instr_posn(&self) -> InstrPosn {
         debug_assert!(self.within_limits());
-        InstrPosn::new(self.instr_bytes.len() as u32)
+        InstrPosn::from_offset(self.instr_bytes.len())
     }
     pub(crate) fn front_instr_id(&self) -> InstrId {
         InstrId::new(self.front_instr_posn())
@@ -100,60 +388,296 @@ impl InstrStore {
     pub(crate) fn instr_bytes_len(&self) -> usize {
         self.instr_bytes.len()
     }
+    pub(crate) fn encoding(&self) -> OperandEncoding {
+        self.encoding
+    }
+    pub(crate) fn num_instrs(&self) -> u32 {
+        self.num_instrs
+    }
+    pub(crate) fn const_pool(&self) -> &ConstPool {
+        &self.const_pool
+    }
+    pub(crate) fn patch_points(&self) -> &[PatchPoint] {
+        &self.patch_points
+    }
+
+    // The space a patch point of the store reserves.
+    pub(crate) fn patch_space(&self, point: &PatchPoint) -> &[u8] {
+        let start = point.offset();
+        &self.instr_bytes[start .. start + point.size() as usize]
+    }
+    pub(crate) fn patch_space_mut(&mut self, point: &PatchPoint)
+      -> &mut [u8]
+    {
+        let start = point.offset();
+        let bytes = self.instr_bytes.to_mut();
+        &mut bytes[start .. start + point.size() as usize]
+    }
+
+    // Overwrite the payload of the instruction at `id`
+    // with that of an op of the same opcode and encoded
+    // length, leaving its operands as they were.
+    pub(crate) fn rewrite_payload(&mut self, id: InstrId, op: &Op) {
+        debug_assert!(op.pool_value().is_none());
+        let mut payload = Vec::new();
+        op.write_to(&mut payload);
+        let start = id.offset() + 1;
+        let bytes = self.instr_bytes.to_mut();
+        debug_assert!(bytes[id.offset()] == op.opcode().into_u8());
+        bytes[start .. start + payload.len()]
+          .copy_from_slice(&payload);
+    }
+
+    // The offset and length of the payload of the
+    // instruction at `id`, following its opcode.
+    pub(crate) fn payload_field(&self, id: InstrId) -> (usize, usize) {
+        let info = unsafe { self.read_instr_info(id) };
+        (id.offset() + 1, info.header_len() - 1)
+    }
+
+    // The offset and length of the fields of the target
+    // at index `target` of the end instruction `user`.
+    pub(crate) fn target_field(&self, user: InstrId, target: u32)
+      -> (usize, usize)
+    {
+        let offset = self.target_offset(user, target);
+        (offset, self.target_len(offset))
+    }
+
+    // Write `bytes` over the stream at `offset`, as they
+    // were before an edit made in place.
+    pub(crate) fn restore(&mut self, offset: usize, bytes: &[u8]) {
+        let stream = self.instr_bytes.to_mut();
+        stream[offset .. offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    // The offset and length of the field holding the
+    // operand or phi argument of `user` at `site`.
+    pub(crate) fn operand_field(&self, user: InstrId, site: UseSite)
+      -> (usize, usize)
+    {
+        let info = unsafe { self.read_instr_info(user) };
+        let mut offset = user.offset();
+        let skip = match site {
+          UseSite::Operand(index) => {
+            offset += info.header_len();
+            index
+          }
+          UseSite::PhiArg { target, index } => {
+            // Past the block id and argument count.
+            offset = self.target_offset(user, target);
+            offset += self.field_len(offset);
+            offset += self.field_len(offset);
+            index
+          }
+        };
+        for _ in 0 .. skip {
+            offset += self.field_len(offset);
+        }
+        (offset, self.field_len(offset))
+    }
+
+    fn field_len(&self, offset: usize) -> usize {
+        unsafe { self.encoding.read_field(&self.instr_bytes[offset ..]).0 }
+    }
+
+    // The offset of the target at index `target` of the
+    // end instruction `user`: of its block id field,
+    // which its argument count and arguments follow.
+    fn target_offset(&self, user: InstrId, target: u32) -> usize {
+        let info = unsafe { self.read_instr_info(user) };
+        let mut offset = user.offset() + info.operands_end();
+        for _ in 0 .. target {
+            offset += self.target_len(offset);
+        }
+        offset
+    }
+
+    fn target_len(&self, offset: usize) -> usize {
+        let mut end = offset + self.field_len(offset);
+        let (nb, nargs) = unsafe {
+            self.encoding.read_field(&self.instr_bytes[end ..])
+        };
+        end += nb;
+        for _ in 0 .. narrow_repr(nargs) {
+            end += self.field_len(end);
+        }
+        end - offset
+    }
+
+    // The fields of a target of `user` at `block`
+    // passing `args`.
+    fn target_fields(&self, user: InstrId, block: BlockId,
+                     args: &[InstrId])
+      -> Vec<u8>
+    {
+        let encoding = self.encoding;
+        let mut fields = Vec::new();
+        encoding.write_field(block.as_u32() as OffsetRepr, &mut fields);
+        encoding.write_field(args.len() as OffsetRepr, &mut fields);
+        for &arg in args {
+            encoding.write_field(encoding.encode(user, arg), &mut fields);
+        }
+        fields
+    }
+
+    // Whether the target of `user` at index `target`
+    // can be rewritten in place to go to `block`
+    // passing `args`: whether its fields take as many
+    // bytes as they do now.
+    pub(crate) fn can_retarget(&self, user: InstrId, target: u32,
+                               block: BlockId, args: &[InstrId])
+      -> bool
+    {
+        let offset = self.target_offset(user, target);
+        self.target_fields(user, block, args).len()
+          == self.target_len(offset)
+    }
+
+    // Overwrite the target of `user` at index `target`
+    // to go to `block` passing `args`, which it must be
+    // able to do in place.
+    pub(crate) fn retarget(&mut self, user: InstrId, target: u32,
+                           block: BlockId, args: &[InstrId])
+    {
+        let offset = self.target_offset(user, target);
+        let len = self.target_len(offset);
+        let fields = self.target_fields(user, block, args);
+        assert!(fields.len() == len, "target fields change length");
+        let bytes = self.instr_bytes.to_mut();
+        bytes[offset .. offset + len].copy_from_slice(&fields);
+    }
+
+    // Whether the operand or phi argument of `user` at
+    // `site` can be rewritten in place to name `def`:
+    // that is, whether its field encodes `def` in as
+    // many bytes as it has now.
+    pub(crate) fn can_rewrite_operand(&self, user: InstrId,
+                                      site: UseSite, def: InstrId)
+      -> bool
+    {
+        let (_, len) = self.operand_field(user, site);
+        let mut field = Vec::with_capacity(len);
+        self.encoding.write_field(self.encoding.encode(user, def),
+                                  &mut field);
+        field.len() == len
+    }
+
+    // Overwrite the operand or phi argument of `user` at
+    // `site` to name `def`, which it must be able to
+    // name in place.
+    pub(crate) fn rewrite_operand(&mut self, user: InstrId,
+                                  site: UseSite, def: InstrId)
+    {
+        let (offset, len) = self.operand_field(user, site);
+        let mut field = Vec::with_capacity(len);
+        self.encoding.write_field(self.encoding.encode(user, def),
+                                  &mut field);
+        assert!(field.len() == len, "operand field changes length");
+        let bytes = self.instr_bytes.to_mut();
+        bytes[offset .. offset + len].copy_from_slice(&field);
+    }
+
+    // The bytes of stream the store owns.  Shared bytes
+    // are owned by their buffer, not the store.
+    pub(crate) fn heap_bytes(&self) -> usize {
+        match self.instr_bytes {
+          InstrBytes::Owned(ref vec) => vec.capacity(),
+          InstrBytes::Shared { .. } => 0
+        }
+    }
+    pub(crate) fn shrink_to_fit(&mut self) {
+        if let InstrBytes::Owned(ref mut vec) = self.instr_bytes {
+            vec.shrink_to_fit();
+        }
+        self.const_pool.shrink_to_fit();
+        self.patch_points.shrink_to_fit();
+    }
+
+    // Make room, as the growth policy says, for an
+    // instruction of `fields` operand and target
+    // fields.  The room is reckoned generously, but for
+    // the space a patch point reserves, which the vec
+    // grows to fit on its own.  A fixed slab is never
+    // grown, so an instruction overrunning it fails.
+    fn reserve_for(&mut self, fields: usize) {
+        let needed = Self::MAX_HEADER_BYTES
+                       + fields * self.encoding.max_field_bytes();
+        let growth = self.growth;
+        let bytes = self.instr_bytes.to_mut();
+        let spare = bytes.capacity() - bytes.len();
+        if spare >= needed {
+            return;
+        }
+        let extra = match growth {
+          StreamGrowth::Doubling { .. } => bytes.capacity(),
+          StreamGrowth::Chunked { chunk, .. } => chunk,
+          StreamGrowth::FixedSlab { .. } => return
+        };
+        bytes.reserve_exact((spare + extra).max(needed));
+    }
 
     fn append_instr_impl<OP, DEF>(
-        &mut self, op: &OP, inputs: &[DEF])
-      where OP: Operation, DEF: Copy + Into<InstrId>
+        &mut self, id: InstrId, op: &OP, inputs: &[DEF])
+      where OP: EncodeOp, DEF: Copy + Into<InstrId>
     {
         debug_assert!(self.within_limits());
 
+        let encoding = self.encoding;
+        let bytes = self.instr_bytes.to_mut();
+
         // Encode the opcode for the instruction.
-        self.instr_bytes.push(
-          OP::opcode().into_u8());
+        bytes.push(op.encode_opcode().into_u8());
 
-        // Encode the operation payload.
-        op.write_to(&mut self.instr_bytes);
+        // Encode the operation payload, referring to the
+        // constant pool for large constants.
+        match op.encode_pool_value() {
+          Some(value) if ConstPool::worth_pooling(value) => {
+            let index = self.const_pool.intern(value);
+            bytes.push(POOLED_CONST_TAG);
+            leb128::write_leb128u(index, bytes);
+          }
+          _ => op.encode_payload(bytes)
+        }
 
         // Encode each operand.
         for inp in inputs {
-            leb128::write_leb128u(
-                (*inp).into().as_u32(),
-                &mut self.instr_bytes);
+            encoding.write_field(
+                encoding.encode(id, (*inp).into()), bytes);
         }
     }
 
     fn append_targets_impl<BLK, DEF>(
-        &mut self, targets: &[(BLK, &[DEF])])
+        &mut self, id: InstrId, targets: &[(BLK, &[DEF])])
       where BLK: Copy + Into<BlockId>,
             DEF: Copy + Into<InstrId>
     {
+        let encoding = self.encoding;
+        let bytes = self.instr_bytes.to_mut();
         for &(target_blk, phi_defs) in targets.iter() {
             Self::debug_print_target(target_blk, phi_defs);
 
             // Write the target block-id.
-            leb128::write_leb128u(
-              target_blk.into().as_u32(),
-              &mut self.instr_bytes);
+            encoding.write_field(
+              target_blk.into().as_u32() as OffsetRepr, bytes);
 
             // Write out # of phi-defs.
             debug_assert!(
               phi_defs.len() <= Self::MAX_INSTR_BYTES);
-            leb128::write_leb128u(
-                phi_defs.len() as u32,
-                &mut self.instr_bytes);
+            encoding.write_field(
+                phi_defs.len() as OffsetRepr, bytes);
 
             // Write out each phi def for the target.
             for def in phi_defs {
-                leb128::write_leb128u(
-                    (*def).into().as_u32(),
-                    &mut self.instr_bytes);
+                encoding.write_field(
+                    encoding.encode(id, (*def).into()), bytes);
             }
         }
     }
 
     fn debug_print_instr<OP, DEF>(
         id: InstrId, op: &OP, inputs: &[DEF])
-      where OP: Operation,
+      where OP: EncodeOp,
             DEF: Copy + Into<InstrId>
     {
         let mut inputs_str = String::new();
@@ -162,13 +686,13 @@ impl InstrStore {
                 write!(inputs_str, ", ").unwrap();
             }
             write!(inputs_str, "{}",
-                   (*def).into().as_u32()).unwrap();
+                   (*def).into().offset()).unwrap();
         }
-        if inputs_str.len() > 0 {
+        if !inputs_str.is_empty() {
             debug!("Emit {} - {}({})",
-                   id.as_u32(), op, inputs_str);
+                   id.offset(), op, inputs_str);
         } else {
-            debug!("Emit {} - {}", id.as_u32(), op);
+            debug!("Emit {} - {}", id.offset(), op);
         }
     }
 
@@ -183,9 +707,9 @@ impl InstrStore {
                 write!(phi_args_str, ", ").unwrap();
             }
             write!(phi_args_str, "{}:{}",
-                   i, (*def).into().as_u32()).unwrap();
+                   i, (*def).into().offset()).unwrap();
         }
-        if phi_args_str.len() > 0 {
+        if !phi_args_str.is_empty() {
             debug!("  Target {} - {}",
                    target.into().as_u32(), phi_args_str);
         } else {
@@ -194,10 +718,9 @@ impl InstrStore {
     }
 
     unsafe fn instr_data(&self, id: InstrId) -> &[u8] {
-        let offset = id.posn().as_u32();
-        debug_assert!((offset as usize )
-                        <= self.instr_bytes.len());
-        &self.instr_bytes[offset as usize ..]
+        let offset = id.offset();
+        debug_assert!(offset <= self.instr_bytes.len());
+        &self.instr_bytes[offset ..]
     }
     pub(crate) unsafe fn read_instr_info<'a>(
         &'a self, instr_id: InstrId)
@@ -205,31 +728,106 @@ impl InstrStore {
     {
         let instr_data = self.instr_data(instr_id);
         let defn = Defn::new(instr_id);
-        let (nb, op) = Op::read_from(instr_data);
+        let pool = self.const_pool.values();
+        let (nb, op) = Op::read_from(instr_data, pool);
         let inputs_offset = nb as u32;
         let after_inputs_offset = 0;
         let mut instr_info = InstrInfo {
             instr_data, defn, op,
             inputs_offset, after_inputs_offset,
+            encoding: self.encoding,
         };
 
         // Adjust after_inputs_offset to be correct.
         let mut inputs_iter = instr_info.inputs_iter();
-        loop {
-            if inputs_iter.next() == None { break; }
-        }
+        inputs_iter.skip_rest();
         instr_info.after_inputs_offset =
             inputs_offset + inputs_iter.bytes_read();
         instr_info
     }
 
+    pub(crate) fn instr_bytes(&self) -> &[u8] {
+        &self.instr_bytes
+    }
+
+    // Decode the instruction at an untrusted id with
+    // full bounds checks.
+    pub(crate) fn try_decode_instr(&self, instr_id: InstrId)
+      -> Result<DecodedInstr, DecodeError>
+    {
+        let offset = instr_id.offset();
+        if offset >= self.instr_bytes.len() {
+            return Err(DecodeError::Truncated { offset });
+        }
+        let mut reader = ByteReader::at(&self.instr_bytes, offset)
+                           .with_pool(self.const_pool.values());
+        DecodedInstr::decode(&mut reader, self.encoding)
+    }
+
+    // Read the info for an instruction at an untrusted
+    // id.  The instruction is decoded with full checks
+    // first, so the unchecked readers used by the
+    // returned info stay within its bytes.
+    pub(crate) fn try_read_instr_info(&self, instr_id: InstrId)
+      -> Result<InstrInfo<'_>, DecodeError>
+    {
+        self.try_decode_instr(instr_id) ?;
+        Ok(unsafe { self.read_instr_info(instr_id) })
+    }
+
+    // Decode the whole stream from its start, with full
+    // checks, as the unchecked readers depend on: the
+    // instructions must tile the bytes exactly, each
+    // operand and phi argument must name the start of an
+    // earlier instruction, and the instructions must be
+    // as many as were emitted.
+    pub(crate) fn validate(&self) -> Result<(), StreamError> {
+        let mut reader = ByteReader::new(&self.instr_bytes)
+                           .with_pool(self.const_pool.values());
+        let mut starts: Vec<InstrId> = Vec::new();
+        while !reader.at_end() {
+            let instr = DecodedInstr::decode(&mut reader, self.encoding)
+                          .map_err(StreamError::Decode) ?;
+            let offset = instr.id().offset();
+            let args = instr.targets().iter()
+                            .flat_map(|(_, args)| args.iter());
+            for &operand in instr.inputs().iter().chain(args) {
+                if operand >= instr.id() {
+                    return Err(StreamError::ForwardOperand {
+                        offset, operand
+                    });
+                }
+                if starts.binary_search(&operand).is_err() {
+                    return Err(StreamError::NotAnInstr {
+                        offset, operand
+                    });
+                }
+            }
+            starts.push(instr.id());
+        }
+        if starts.len() != self.num_instrs as usize {
+            return Err(StreamError::InstrCount {
+                expected: self.num_instrs, found: starts.len() as u32
+            });
+        }
+        Ok(())
+    }
+
+    // Iterate the instructions starting at `first`
+    // up to and including the next end instruction.
+    pub(crate) unsafe fn iter_from(&self, first: InstrId)
+      -> InstrIter<'_>
+    {
+        InstrIter { store: self, next: Some(first) }
+    }
+
     pub(crate) fn emit_instr<OP, DEF>(
         &mut self, op: &OP, inputs: &[DEF])
       -> Option<InstrId>
-      where OP: Operation,
+      where OP: EncodeOp,
             DEF: Copy + Into<InstrId>
     {
-        debug_assert!(! OP::terminal());
+        debug_assert!(! op.encode_terminal());
 
         if ! self.within_limits() { return None; }
 
@@ -240,24 +838,37 @@ impl InstrStore {
 
         // Append the instruction encoding, and
         // the list of input operands.
-        self.append_instr_impl(op, inputs);
+        self.reserve_for(inputs.len());
+        self.append_instr_impl(id, op, inputs);
 
         if ! self.within_limits() { return None; }
 
+        if op.encode_opcode() == Opcode::PatchPoint {
+            self.record_patch_point(id);
+        }
+        self.num_instrs += 1;
         Some(id)
     }
 
+    // Record the space reserved by the patch point just
+    // appended at `id`.
+    fn record_patch_point(&mut self, id: InstrId) {
+        let payload = &self.instr_bytes[id.offset() + 1 ..];
+        let (_, op) = unsafe { PatchPointOp::read_from(payload) };
+        self.patch_points.push(PatchPoint::in_stream(&op, id));
+    }
+
     pub(crate) fn emit_end<OP, DEF, BLK>(
         &mut self,
         op: &OP,
         inputs: &[DEF],
         targets: &[(BLK, &[DEF])])
       -> Option<InstrId>
-      where OP: Operation,
+      where OP: EncodeOp,
             DEF: Copy + Into<InstrId>,
             BLK: Copy + Into<BlockId>
     {
-        debug_assert!(OP::terminal());
+        debug_assert!(op.encode_terminal());
 
         if ! self.within_limits() { return None; }
 
@@ -268,13 +879,18 @@ impl InstrStore {
 
         // Append the instruction encoding, and
         // the list of input operands.
-        self.append_instr_impl(op, inputs);
+        let fields = targets.iter()
+                            .map(|&(_, args)| 2 + args.len())
+                            .sum::<usize>();
+        self.reserve_for(inputs.len() + fields);
+        self.append_instr_impl(id, op, inputs);
 
         // Append the (target, phi_defs) list.
-        self.append_targets_impl(targets);
+        self.append_targets_impl(id, targets);
 
         if ! self.within_limits() { return None; }
 
+        self.num_instrs += 1;
         Some(id)
     }
 }
@@ -282,6 +898,15 @@ impl InstrStore {
 impl<'a> InstrInfo<'a> {
     pub(crate) fn defn(&self) -> Defn<'a> { self.defn }
 
+    // The bytes of the opcode and payload, and the
+    // offset (from the instruction) past its operands.
+    pub(crate) fn header_len(&self) -> usize {
+        self.inputs_offset as usize
+    }
+    pub(crate) fn operands_end(&self) -> usize {
+        self.after_inputs_offset as usize
+    }
+
     fn inputs_data(&self) -> &'a [u8] {
         let offset = self.inputs_offset as usize;
         debug_assert!(self.instr_data.len() >= offset);
@@ -292,7 +917,24 @@ impl<'a> InstrInfo<'a> {
     pub(crate) fn inputs_iter(&self) -> InstrInputs<'a> {
         unsafe {
             InstrInputs::new(
-              self.op.num_inputs(), self.inputs_data())
+              self.op.num_inputs(), self.inputs_data(),
+              self.encoding, self.defn.instr_id())
+        }
+    }
+
+    // Iterate the target list of an end instruction.
+    // Non-terminal instructions have no targets.
+    pub(crate) fn targets_iter(&self) -> InstrTargets<'a> {
+        let remaining = self.op.num_targets().unwrap_or(0);
+        let offset = self.after_inputs_offset as usize;
+        debug_assert!(self.instr_data.len() >= offset);
+        let bytes = unsafe {
+            self.instr_data.get_unchecked(offset..)
+        };
+        InstrTargets {
+            remaining, bytes,
+            encoding: self.encoding,
+            user: self.defn.instr_id()
         }
     }
 
@@ -301,14 +943,13 @@ impl<'a> InstrInfo<'a> {
             return None;
         }
         // Use the after_inputs_offset.
-        let instr_offset = self.defn.instr_id().as_u32();
+        let instr_offset = self.defn.instr_id().offset();
         let next_instr_offset =
-          instr_offset + self.after_inputs_offset;
+          instr_offset + self.after_inputs_offset as usize;
         debug_assert!((self.after_inputs_offset as usize)
                         < self.instr_data.len());
 
-        let posn = InstrPosn::new(next_instr_offset);
-        Some(Defn::new(InstrId::new(posn)))
+        Some(Defn::new(InstrId::from_offset(next_instr_offset)))
     }
 }
 impl<'a> fmt::Display for InstrInfo<'a> {
@@ -323,13 +964,35 @@ impl<'a> fmt::Display for InstrInfo<'a> {
 impl<'a> InstrInputs<'a> {
     // Unsafe constructing this because the safe
     // iterator implementation uses unsafe code.
-    unsafe fn new(remaining: u32, bytes: &'a [u8])
+    unsafe fn new(remaining: u32, bytes: &'a [u8],
+                  encoding: OperandEncoding, user: InstrId)
       -> InstrInputs<'a>
     {
-        InstrInputs { remaining, bytes, bytes_read: 0 }
+        InstrInputs { remaining, bytes, bytes_read: 0, encoding, user }
     }
 
     fn bytes_read(&self) -> u32 { self.bytes_read }
+
+    // Skip `n` operands, without decoding them if they
+    // are fixed-width.
+    fn skip(&mut self, n: u32) {
+        debug_assert!(n <= self.remaining);
+        if self.encoding != OperandEncoding::Fixed {
+            for _ in 0 .. n {
+                self.next();
+            }
+            return;
+        }
+        let nb = (n as usize) * OperandEncoding::FIXED_BYTES;
+        debug_assert!(self.bytes.len() >= nb);
+        self.bytes = unsafe { self.bytes.get_unchecked(nb ..) };
+        self.bytes_read += nb as u32;
+        self.remaining -= n;
+    }
+
+    fn skip_rest(&mut self) {
+        self.skip(self.remaining);
+    }
 }
 impl<'a> Iterator for InstrInputs<'a> {
     type Item = Defn<'a>;
@@ -337,43 +1000,224 @@ impl<'a> Iterator for InstrInputs<'a> {
         if self.remaining == 0 {
             return None;
         }
-        let (nb, id64) = unsafe {
-            leb128::read_leb128u(self.bytes)
+        let (nb, v) = unsafe {
+            self.encoding.read_field(self.bytes)
         };
         self.bytes_read += nb as u32;
         self.remaining -= 1;
         self.bytes = unsafe {
           self.bytes.get_unchecked(nb ..)
         };
-        let posn = InstrPosn::new(id64 as u32);
+        let posn = InstrPosn::new(self.encoding.decode(self.user, v));
         Some(Defn::new(InstrId::new(posn)))
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.remaining as usize;
+        (n, Some(n))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= (self.remaining as usize) {
+            self.skip_rest();
+            return None;
+        }
+        self.skip(n as u32);
+        self.next()
+    }
+}
+
+impl<'a> InstrTarget<'a> {
+    pub(crate) fn block_id(&self) -> BlockId { self.block_id }
+    pub(crate) fn phi_args(&self) -> InstrInputs<'a> {
+        self.phi_args.clone()
+    }
+}
+impl<'a> Iterator for InstrTargets<'a> {
+    type Item = InstrTarget<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let (nb, block) = unsafe {
+            self.encoding.read_field(self.bytes)
+        };
+        let rest = unsafe { self.bytes.get_unchecked(nb ..) };
+        let (nb, nphis) = unsafe {
+            self.encoding.read_field(rest)
+        };
+        let rest = unsafe { rest.get_unchecked(nb ..) };
+        let phi_args = unsafe {
+            InstrInputs::new(narrow_repr(nphis), rest,
+                             self.encoding, self.user)
+        };
+
+        // Skip over the phi args to the next target.
+        let mut skip = phi_args.clone();
+        skip.skip_rest();
+        let skipped = skip.bytes_read() as usize;
+        self.bytes = unsafe { rest.get_unchecked(skipped ..) };
+        self.remaining -= 1;
+
+        let block_id = BlockId::new(narrow_repr(block));
+        Some(InstrTarget { block_id, phi_args })
+    }
+}
+
+impl<'a> Iterator for InstrIter<'a> {
+    type Item = InstrInfo<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.next ?;
+        let info = unsafe { self.store.read_instr_info(id) };
+        self.next = info.next_defn().map(|d| d.instr_id());
+        Some(info)
+    }
+}
+
+impl OperandEncoding {
+    // Fixed-width fields are as wide as offsets.
+    pub(crate) const FIXED_BYTES: usize =
+      mem::size_of::<OffsetRepr>();
+
+    // The most bytes an operand or target field takes.
+    pub(crate) fn max_field_bytes(self) -> usize {
+        match self {
+          OperandEncoding::Fixed => Self::FIXED_BYTES,
+          _ => leb128::MAX_LEB128_BYTES
+        }
+    }
+
+    // The value written for an operand `def` of the
+    // instruction `user`.  Operands are defined before
+    // their users, but deltas wrap so that any id can
+    // be written.
+    pub(crate) fn encode(self, user: InstrId, def: InstrId)
+      -> OffsetRepr
+    {
+        match self {
+          OperandEncoding::Absolute | OperandEncoding::Fixed => {
+            def.repr()
+          }
+          OperandEncoding::Delta => {
+            user.repr().wrapping_sub(def.repr())
+          }
+        }
+    }
+
+    // The operand id of the instruction `user` that
+    // `value` encodes.
+    pub(crate) fn decode(self, user: InstrId, value: OffsetRepr)
+      -> OffsetRepr
+    {
+        match self {
+          OperandEncoding::Absolute | OperandEncoding::Fixed => value,
+          OperandEncoding::Delta => user.repr().wrapping_sub(value)
+        }
+    }
+
+    // Write an operand or target field.
+    pub(crate) fn write_field(self, value: OffsetRepr,
+                              vec: &mut Vec<u8>)
+    {
+        match self {
+          OperandEncoding::Fixed => {
+            vec.extend_from_slice(&value.to_le_bytes())
+          }
+          _ => leb128::write_leb128u(value, vec)
+        }
+    }
+
+    // Read an operand or target field from trusted
+    // bytes, giving its length and value.
+    pub(crate) unsafe fn read_field(self, bytes: &[u8])
+      -> (usize, OffsetRepr)
+    {
+        match self {
+          OperandEncoding::Fixed => {
+            debug_assert!(bytes.len() >= Self::FIXED_BYTES);
+            let mut slot = [0_u8; Self::FIXED_BYTES];
+            slot.copy_from_slice(
+              bytes.get_unchecked(.. Self::FIXED_BYTES));
+            (Self::FIXED_BYTES, OffsetRepr::from_le_bytes(slot))
+          }
+          _ => {
+            let (nb, v) = leb128::read_leb128u(bytes);
+            (nb, v as OffsetRepr)
+          }
+        }
+    }
+
+    // The serialized code of the encoding.  Fixed-width
+    // fields of 64-bit offsets have a code of their own,
+    // so that builds of the other width reject them.
+    pub(crate) fn to_u8(self) -> u8 {
+        match self {
+          OperandEncoding::Absolute => 0,
+          OperandEncoding::Delta => 1,
+          OperandEncoding::Fixed => Self::FIXED_CODE
+        }
+    }
+
+    pub(crate) fn from_u8(code: u8) -> Option<OperandEncoding> {
+        match code {
+          0 => Some(OperandEncoding::Absolute),
+          1 => Some(OperandEncoding::Delta),
+          Self::FIXED_CODE => Some(OperandEncoding::Fixed),
+          _ => None
+        }
+    }
+
+    #[cfg(not(feature = "wide-offsets"))]
+    const FIXED_CODE: u8 = 2;
+    #[cfg(feature = "wide-offsets")]
+    const FIXED_CODE: u8 = 3;
+}
+
+// Narrow a trusted value that fits in 32 bits, such as
+// a target field, from the offset type.
+#[allow(clippy::unnecessary_cast)]
+fn narrow_repr(value: OffsetRepr) -> u32 {
+    debug_assert!(value <= (u32::MAX as OffsetRepr));
+    value as u32
 }
 
 impl InstrPosn {
-    const INVALID_VALUE: u32 = u32::max_value();
+    pub(crate) const INVALID_VALUE: OffsetRepr = OffsetRepr::MAX;
 
-    pub(crate) fn new(val: u32) -> InstrPosn {
+    pub(crate) fn new(val: OffsetRepr) -> InstrPosn {
         debug_assert!(val != Self::INVALID_VALUE);
         InstrPosn(val)
     }
+    pub(crate) fn from_offset(offset: usize) -> InstrPosn {
+        debug_assert!(offset < (Self::INVALID_VALUE as usize));
+        InstrPosn::new(offset as OffsetRepr)
+    }
     pub(crate) fn invalid() -> InstrPosn {
         InstrPosn(Self::INVALID_VALUE)
     }
-    pub(crate) fn as_u32(&self) -> u32 {
+    pub(crate) fn repr(&self) -> OffsetRepr {
         debug_assert!(self.0 != Self::INVALID_VALUE);
         self.0
     }
 }
 
 impl InstrId {
-    const INVALID_VALUE: u32 = u32::max_value();
-
     pub(crate) fn new(posn: InstrPosn) -> InstrId {
         InstrId(posn)
     }
-    fn posn(&self) -> InstrPosn { self.0 }
-    pub(crate) fn as_u32(&self) -> u32 { self.0.as_u32() }
+    pub(crate) fn from_offset(offset: usize) -> InstrId {
+        InstrId(InstrPosn::from_offset(offset))
+    }
+    pub(crate) fn repr(&self) -> OffsetRepr { self.0.repr() }
+
+    // The offset of the instruction in the stream.
+    pub fn offset(&self) -> usize { self.0.repr() as usize }
+
+    // The offset as a u32.  With `wide-offsets`, the
+    // offset must fit.
+    pub fn as_u32(&self) -> u32 {
+        narrow_repr(self.0.repr())
+    }
 
     pub(crate) fn invalid() -> InstrId {
         InstrId(InstrPosn::invalid())
@@ -383,7 +1227,7 @@ impl fmt::Display for InstrId {
     fn fmt(&self, f: &mut fmt::Formatter)
       -> Result<(), fmt::Error>
     {
-        write!(f, "[Ins@{}]", self.0.as_u32())
+        write!(f, "[Ins@{}]", self.offset())
     }
 }
 
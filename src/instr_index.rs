@@ -0,0 +1,75 @@
+
+use std::mem;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+
+/**
+ * A side-index of a finished graph's instruction
+ * stream: the sorted start offset of every instruction,
+ * and the first instruction of every block in stream
+ * order.  Checking that an id names an instruction,
+ * finding the nth instruction and finding the block
+ * holding an instruction each take a binary search
+ * rather than a walk of the stream.
+ */
+#[derive(Clone, Debug)]
+pub struct InstrIndex {
+    starts: Vec<InstrId>,
+    blocks: Vec<(InstrId, BlockId)>
+}
+
+impl InstrIndex {
+    pub(crate) fn compute(graph: &Graph) -> InstrIndex {
+        let store = graph.block_store();
+        let num_instrs = graph.instr_store().num_instrs() as usize;
+        let mut starts = Vec::with_capacity(num_instrs);
+        let mut blocks = Vec::with_capacity(store.total_blocks());
+
+        // Blocks are laid out in RPO, so both tables
+        // come out sorted.
+        for block in store.iter_rpo_blocks() {
+            blocks.push((block.first_instr(), block.id()));
+            for info in graph.block_instrs(block) {
+                starts.push(info.defn().instr_id());
+            }
+        }
+        debug_assert!(starts.windows(2).all(|w| w[0] < w[1]));
+        InstrIndex { starts, blocks }
+    }
+
+    pub(crate) fn heap_bytes(&self) -> usize {
+        self.starts.capacity() * mem::size_of::<InstrId>()
+          + self.blocks.capacity()
+              * mem::size_of::<(InstrId, BlockId)>()
+    }
+
+    pub fn len(&self) -> usize { self.starts.len() }
+    pub fn is_empty(&self) -> bool { self.starts.is_empty() }
+
+    // Whether `id` is the start of an instruction.
+    pub fn contains(&self, id: InstrId) -> bool {
+        self.position(id).is_some()
+    }
+
+    // The position of an instruction in stream order.
+    pub fn position(&self, id: InstrId) -> Option<u32> {
+        self.starts.binary_search(&id).ok().map(|i| i as u32)
+    }
+
+    // The instruction at position `n` in stream order.
+    pub fn nth(&self, n: u32) -> Option<InstrId> {
+        self.starts.get(n as usize).cloned()
+    }
+
+    // The block holding an instruction, or None if `id`
+    // does not start one.
+    pub fn block_of(&self, id: InstrId) -> Option<BlockId> {
+        if !self.contains(id) {
+            return None;
+        }
+        let i = self.blocks.partition_point(|&(first, _)| first <= id);
+        Some(self.blocks[i - 1].1)
+    }
+}
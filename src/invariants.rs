@@ -0,0 +1,134 @@
+
+use std::fmt;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+
+/**
+ * A broken structural invariant of a graph's stores,
+ * as found by `Graph::check_invariants`.
+ */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum InvariantError {
+    // The RPO index holds a different number of
+    // entries than there are declared blocks.
+    RpoLength { expected: u32, found: u32 },
+
+    // An RPO index entry names an undeclared block.
+    RpoBadBlock { index: u32, block: BlockId },
+
+    // A block appears more than once in the RPO index.
+    RpoDuplicate { block: BlockId },
+
+    // A block's recorded order differs from its
+    // position in the RPO index.
+    RpoOrder { block: BlockId, index: u32, order: u32 },
+
+    // The block was declared but never finished.
+    UnfinishedBlock { block: BlockId },
+
+    // The block's instruction range is reversed or runs
+    // past the end of the instruction stream.
+    BadRange { block: BlockId },
+
+    // The block's instruction range does not start
+    // after the range of the block before it in RPO.
+    OverlappingRange { block: BlockId, prev: BlockId },
+
+    // The block's last instruction is not an end
+    // instruction.
+    MissingTerminator { block: BlockId, instr: InstrId }
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match *self {
+          InvariantError::RpoLength { expected, found } =>
+            write!(f, "RPO index has {} entries, expected {}",
+                   found, expected),
+          InvariantError::RpoBadBlock { index, block } =>
+            write!(f, "RPO entry {} names undeclared {}",
+                   index, block),
+          InvariantError::RpoDuplicate { block } =>
+            write!(f, "{} appears twice in RPO index", block),
+          InvariantError::RpoOrder { block, index, order } =>
+            write!(f, "{} at RPO entry {} has order {}",
+                   block, index, order),
+          InvariantError::UnfinishedBlock { block } =>
+            write!(f, "{} never finished", block),
+          InvariantError::BadRange { block } =>
+            write!(f, "{} has a bad instruction range", block),
+          InvariantError::OverlappingRange { block, prev } =>
+            write!(f, "{} instructions overlap those of {}",
+                   block, prev),
+          InvariantError::MissingTerminator { block, instr } =>
+            write!(f, "{} last instruction {} is not an end",
+                   block, instr)
+        }
+    }
+}
+
+pub(crate) fn check_invariants(graph: &Graph)
+  -> Result<(), InvariantError>
+{
+    let store = graph.block_store();
+    let nblocks = store.total_blocks();
+    let rpo_len = store.rpo_len();
+    if rpo_len != nblocks {
+        return Err(InvariantError::RpoLength {
+            expected: nblocks as u32, found: rpo_len as u32
+        });
+    }
+
+    let num_bytes = graph.instr_bytes().len();
+    let mut seen = vec![false; nblocks];
+    let mut prev: Option<(BlockId, InstrId)> = None;
+    for (index, id) in store.iter_rpo_ids().enumerate() {
+        let index = index as u32;
+        let bi = id.as_u32() as usize;
+        if bi >= nblocks {
+            return Err(InvariantError::RpoBadBlock { index, block: id });
+        }
+        if seen[bi] {
+            return Err(InvariantError::RpoDuplicate { block: id });
+        }
+        seen[bi] = true;
+
+        let block = graph.block(id);
+        if !block.has_finished() {
+            return Err(InvariantError::UnfinishedBlock { block: id });
+        }
+        if block.order() != index {
+            return Err(InvariantError::RpoOrder {
+                block: id, index, order: block.order()
+            });
+        }
+
+        let (first, last) = (block.first_instr(), block.last_instr());
+        if first > last || last.offset() >= num_bytes {
+            return Err(InvariantError::BadRange { block: id });
+        }
+        if let Some((prev_id, prev_last)) = prev {
+            if first <= prev_last {
+                return Err(InvariantError::OverlappingRange {
+                    block: id, prev: prev_id
+                });
+            }
+        }
+        prev = Some((id, last));
+
+        let is_end = graph.instr_store().try_read_instr_info(last)
+                          .map(|info| info.op().terminal())
+                          .unwrap_or(false);
+        if !is_end {
+            return Err(InvariantError::MissingTerminator {
+                block: id, instr: last
+            });
+        }
+    }
+    Ok(())
+}
@@ -1,4 +1,6 @@
 
+use std::convert::TryFrom;
+use std::marker::PhantomData;
 use std::mem;
 use std::fmt::Debug;
 
@@ -32,6 +34,26 @@ impl IrTypeId {
         mem::transmute(v)
     }
     pub(crate) fn into_u8(self) -> u8 { self as u8 }
+
+    // The bytes a value of the type takes in memory.
+    pub(crate) fn mem_size(self) -> u32 {
+        match self {
+            IrTypeId::Bool => 1,
+            IrTypeId::Int32 => 4,
+            IrTypeId::Int64 | IrTypeId::PtrInt => 8
+        }
+    }
+}
+impl TryFrom<u8> for IrTypeId {
+    type Error = u8;
+
+    fn try_from(v: u8) -> Result<IrTypeId, u8> {
+        if Self::valid_u8(v) {
+            Ok(unsafe { Self::from_u8(v) })
+        } else {
+            Err(v)
+        }
+    }
 }
 
 /**
@@ -59,13 +81,29 @@ pub enum IrOutputTypeId {
 /**
  * A static trait that describes a closed set
  * of rust types that map to IR types.
+ *
+ * # Safety
+ * The `ID` must be the type id that the IR encodes
+ * for values of this type, as typed definitions are
+ * trusted to carry their declared type.
  */
 pub unsafe trait IrType: Clone + Sized + Debug {
     const ID: IrTypeId;
 }
+/**
+ * # Safety
+ * As for `IrType`, `INPUT_ID` must match the input
+ * type accepted by operations specialized on this type.
+ */
+#[allow(dead_code)]
 pub unsafe trait IrInputType: Clone + Sized + Debug {
     const INPUT_ID: IrInputTypeId;
 }
+/**
+ * # Safety
+ * As for `IrType`, `OUTPUT_ID` must match the output
+ * type produced by operations specialized on this type.
+ */
 pub unsafe trait IrOutputType: Clone + Sized + Debug {
     const OUTPUT_ID: IrOutputTypeId;
 }
@@ -74,6 +112,7 @@ pub unsafe trait IrOutputType: Clone + Sized + Debug {
 pub struct AnyTy;
 
 #[derive(Clone, Debug)]
+#[allow(dead_code)]
 pub struct VoidTy;
 
 #[derive(Clone, Debug)]
@@ -88,6 +127,29 @@ pub struct Int64Ty;
 #[derive(Clone, Debug)]
 pub struct PtrIntTy;
 
+/**
+ * A pointer to values of type `T`, for the builder
+ * only.  The graph holds it as a `PtrInt` like any
+ * other pointer: the pointee type is not part of the
+ * IR, so it is not printed or serialized, and neither
+ * `verify` nor `type_check` can reject a load or store
+ * of another type through the pointer.  The builder
+ * alone enforces it, loading and storing only values
+ * of type `T` through a `PtrTy<T>`.  `PtrIntTy`
+ * remains the untyped pointer, through which values of
+ * any type can be accessed.
+ */
+#[derive(Clone, Debug)]
+pub struct PtrTy<T: IrType>(PhantomData<T>);
+
+/**
+ * The pointer types: `PtrIntTy`, and `PtrTy<T>` for
+ * each type `T`.
+ *
+ * # Safety
+ * The `ID` of a pointer type must be `PtrInt`.
+ */
+pub unsafe trait IrPtrType: IrType {}
 
 unsafe impl<T: IrType> IrInputType for T {
     const INPUT_ID: IrInputTypeId =
@@ -116,3 +178,9 @@ unsafe impl IrType for Int64Ty {
 unsafe impl IrType for PtrIntTy {
     const ID: IrTypeId = IrTypeId::PtrInt;
 }
+unsafe impl<T: IrType> IrType for PtrTy<T> {
+    const ID: IrTypeId = IrTypeId::PtrInt;
+}
+
+unsafe impl IrPtrType for PtrIntTy {}
+unsafe impl<T: IrType> IrPtrType for PtrTy<T> {}
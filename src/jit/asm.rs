@@ -0,0 +1,280 @@
+
+/*
+ * An encoder for the handful of x86-64 instructions the
+ * template JIT emits.  Registers are given by their
+ * hardware numbers; memory operands are always a base
+ * register with a 32-bit displacement, and the base is
+ * never `rsp` or `r12`, which would need a SIB byte.
+ */
+
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub(crate) struct Reg(u8);
+
+pub(crate) const RAX: Reg = Reg(0);
+pub(crate) const RCX: Reg = Reg(1);
+pub(crate) const RDX: Reg = Reg(2);
+pub(crate) const RBX: Reg = Reg(3);
+pub(crate) const RBP: Reg = Reg(5);
+pub(crate) const RSI: Reg = Reg(6);
+pub(crate) const RDI: Reg = Reg(7);
+pub(crate) const R8: Reg = Reg(8);
+pub(crate) const R9: Reg = Reg(9);
+pub(crate) const R10: Reg = Reg(10);
+pub(crate) const R11: Reg = Reg(11);
+pub(crate) const R12: Reg = Reg(12);
+pub(crate) const R13: Reg = Reg(13);
+pub(crate) const R14: Reg = Reg(14);
+pub(crate) const R15: Reg = Reg(15);
+
+/** The condition of a `jcc` or `setcc`. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub(crate) enum Cond {
+    Ae = 0x3, E = 0x4, Ne = 0x5,
+    L = 0xC, Ge = 0xD, Le = 0xE, G = 0xF
+}
+
+impl Reg {
+    fn low(self) -> u8 { self.0 & 7 }
+    fn high(self) -> bool { self.0 >= 8 }
+}
+
+fn modrm(mode: u8, reg: u8, rm: u8) -> u8 {
+    (mode << 6) | ((reg & 7) << 3) | (rm & 7)
+}
+
+// The recommended nop of each length from 1 to 9.
+const NOPS: [&[u8]; 9] = [
+    &[0x90],
+    &[0x66, 0x90],
+    &[0x0F, 0x1F, 0x00],
+    &[0x0F, 0x1F, 0x40, 0x00],
+    &[0x0F, 0x1F, 0x44, 0x00, 0x00],
+    &[0x66, 0x0F, 0x1F, 0x44, 0x00, 0x00],
+    &[0x0F, 0x1F, 0x80, 0x00, 0x00, 0x00, 0x00],
+    &[0x0F, 0x1F, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00],
+    &[0x66, 0x0F, 0x1F, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00]
+];
+
+// Fill the code with as few nops as cover it.
+pub(crate) fn fill_nops(code: &mut [u8]) {
+    let mut rest = code;
+    while !rest.is_empty() {
+        let nop = NOPS[rest.len().min(NOPS.len()) - 1];
+        let (head, tail) = rest.split_at_mut(nop.len());
+        head.copy_from_slice(nop);
+        rest = tail;
+    }
+}
+
+pub(crate) struct Asm {
+    code: Vec<u8>
+}
+
+impl Asm {
+    pub(crate) fn new() -> Asm {
+        Asm { code: Vec::new() }
+    }
+
+    pub(crate) fn offset(&self) -> usize { self.code.len() }
+    pub(crate) fn into_code(self) -> Vec<u8> { self.code }
+
+    fn byte(&mut self, b: u8) { self.code.push(b); }
+
+    fn imm32(&mut self, v: u32) {
+        self.code.extend_from_slice(&v.to_le_bytes());
+    }
+
+    // A REX prefix, left out when it would be empty.
+    fn rex(&mut self, wide: bool, reg: Reg, rm: Reg) {
+        let rex = 0x40 | ((wide as u8) << 3)
+                       | ((reg.high() as u8) << 2)
+                       | (rm.high() as u8);
+        if rex != 0x40 {
+            self.byte(rex);
+        }
+    }
+
+    // An instruction taking a register and a register
+    // operand, `op rm, reg` in Intel order for the
+    // stores and ALU ops.
+    fn op_rr(&mut self, wide: bool, opcode: &[u8], reg: Reg, rm: Reg) {
+        self.rex(wide, reg, rm);
+        self.code.extend_from_slice(opcode);
+        self.byte(modrm(0b11, reg.0, rm.0));
+    }
+
+    // An instruction taking a register and a memory
+    // operand at `[base + disp]`.
+    fn op_rm(&mut self, wide: bool, opcode: &[u8], reg: Reg,
+             base: Reg, disp: i32)
+    {
+        debug_assert!(base.low() != 4);
+        self.rex(wide, reg, base);
+        self.code.extend_from_slice(opcode);
+        self.byte(modrm(0b10, reg.0, base.0));
+        self.imm32(disp as u32);
+    }
+
+    pub(crate) fn mov_rr(&mut self, dst: Reg, src: Reg) {
+        if dst != src {
+            self.op_rr(true, &[0x89], src, dst);
+        }
+    }
+
+    pub(crate) fn load(&mut self, dst: Reg, base: Reg, disp: i32) {
+        self.op_rm(true, &[0x8B], dst, base, disp);
+    }
+    pub(crate) fn store(&mut self, base: Reg, disp: i32, src: Reg) {
+        self.op_rm(true, &[0x89], src, base, disp);
+    }
+
+    // Loads and stores of 1, 4 or 8 bytes, with loads
+    // zero-extended.  Byte stores are only of `cl` or
+    // the other registers with a low byte that needs no
+    // REX prefix.
+    pub(crate) fn load_sized(&mut self, size: u32, dst: Reg,
+                             base: Reg, disp: i32)
+    {
+        match size {
+          1 => self.op_rm(false, &[0x0F, 0xB6], dst, base, disp),
+          4 => self.op_rm(false, &[0x8B], dst, base, disp),
+          _ => self.load(dst, base, disp)
+        }
+    }
+    pub(crate) fn store_sized(&mut self, size: u32, base: Reg,
+                              disp: i32, src: Reg)
+    {
+        match size {
+          1 => {
+            debug_assert!(src.0 < 4);
+            self.op_rm(false, &[0x88], src, base, disp)
+          }
+          4 => self.op_rm(false, &[0x89], src, base, disp),
+          _ => self.store(base, disp, src)
+        }
+    }
+
+    // Load a constant, zero-extending it from 32 bits
+    // where it fits.
+    pub(crate) fn mov_imm(&mut self, dst: Reg, v: u64) {
+        let wide = v > (u32::MAX as u64);
+        self.rex(wide, RAX, dst);
+        self.byte(0xB8 + dst.low());
+        if wide {
+            self.code.extend_from_slice(&v.to_le_bytes());
+        } else {
+            self.imm32(v as u32);
+        }
+    }
+
+    // Ops of `dst, src` on 32-bit registers zero the
+    // upper half of `dst`, which keeps Int32 values
+    // zero-extended.
+    pub(crate) fn add(&mut self, wide: bool, dst: Reg, src: Reg) {
+        self.op_rr(wide, &[0x01], src, dst);
+    }
+    pub(crate) fn sub(&mut self, wide: bool, dst: Reg, src: Reg) {
+        self.op_rr(wide, &[0x29], src, dst);
+    }
+    pub(crate) fn and(&mut self, wide: bool, dst: Reg, src: Reg) {
+        self.op_rr(wide, &[0x21], src, dst);
+    }
+    pub(crate) fn or(&mut self, wide: bool, dst: Reg, src: Reg) {
+        self.op_rr(wide, &[0x09], src, dst);
+    }
+    pub(crate) fn xor(&mut self, wide: bool, dst: Reg, src: Reg) {
+        self.op_rr(wide, &[0x31], src, dst);
+    }
+    pub(crate) fn imul(&mut self, wide: bool, dst: Reg, src: Reg) {
+        self.op_rr(wide, &[0x0F, 0xAF], dst, src);
+    }
+
+    // Set the flags from `lhs - rhs`.
+    pub(crate) fn cmp(&mut self, wide: bool, lhs: Reg, rhs: Reg) {
+        self.op_rr(wide, &[0x39], rhs, lhs);
+    }
+
+    // Set `eax` to 1 if the condition holds, else 0.
+    pub(crate) fn set_eax(&mut self, cond: Cond) {
+        self.code.extend_from_slice(&[0x0F, 0x90 + cond as u8, 0xC0]);
+        self.code.extend_from_slice(&[0x0F, 0xB6, 0xC0]);
+    }
+
+    pub(crate) fn and_eax_1(&mut self) {
+        self.code.extend_from_slice(&[0x83, 0xE0, 0x01]);
+    }
+    pub(crate) fn test_al(&mut self) {
+        self.code.extend_from_slice(&[0x84, 0xC0]);
+    }
+    pub(crate) fn test_rax(&mut self) {
+        self.code.extend_from_slice(&[0x48, 0x85, 0xC0]);
+    }
+
+    // Jumps are emitted with a zero displacement, giving
+    // the offset of it for `patch`.
+    pub(crate) fn jcc(&mut self, cond: Cond) -> usize {
+        self.code.extend_from_slice(&[0x0F, 0x80 + cond as u8]);
+        self.imm32(0);
+        self.offset() - 4
+    }
+    pub(crate) fn jmp(&mut self) -> usize {
+        self.byte(0xE9);
+        self.imm32(0);
+        self.offset() - 4
+    }
+
+    // Point the jump displacement at `at` to `target`.
+    pub(crate) fn patch(&mut self, at: usize, target: usize) {
+        let rel = (target as i64) - ((at + 4) as i64);
+        let rel = (rel as i32).to_le_bytes();
+        self.code[at .. at + 4].copy_from_slice(&rel);
+    }
+
+    pub(crate) fn push(&mut self, reg: Reg) {
+        self.rex(false, RAX, reg);
+        self.byte(0x50 + reg.low());
+    }
+    pub(crate) fn pop(&mut self, reg: Reg) {
+        self.rex(false, RAX, reg);
+        self.byte(0x58 + reg.low());
+    }
+    pub(crate) fn push_mem(&mut self, base: Reg, disp: i32) {
+        debug_assert!(base.low() != 4);
+        self.rex(false, RAX, base);
+        self.byte(0xFF);
+        self.byte(modrm(0b10, 6, base.0));
+        self.imm32(disp as u32);
+    }
+    pub(crate) fn pop_mem(&mut self, base: Reg, disp: i32) {
+        debug_assert!(base.low() != 4);
+        self.rex(false, RAX, base);
+        self.byte(0x8F);
+        self.byte(modrm(0b10, 0, base.0));
+        self.imm32(disp as u32);
+    }
+
+    // `mov rbp, rsp`
+    pub(crate) fn mov_rbp_rsp(&mut self) {
+        self.code.extend_from_slice(&[0x48, 0x89, 0xE5]);
+    }
+    // `sub rsp, bytes`
+    pub(crate) fn sub_rsp(&mut self, bytes: u32) {
+        self.code.extend_from_slice(&[0x48, 0x81, 0xEC]);
+        self.imm32(bytes);
+    }
+    // `lea rsp, [rbp + disp]`
+    pub(crate) fn lea_rsp_rbp(&mut self, disp: i32) {
+        self.code.extend_from_slice(&[0x48, 0x8D, 0xA5]);
+        self.imm32(disp as u32);
+    }
+    pub(crate) fn ret(&mut self) { self.byte(0xC3); }
+
+    // Nops covering `len` bytes.
+    pub(crate) fn nops(&mut self, len: usize) {
+        let start = self.offset();
+        self.code.resize(start + len, 0);
+        fill_nops(&mut self.code[start ..]);
+    }
+}
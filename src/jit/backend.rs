@@ -0,0 +1,443 @@
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::view::BlockView;
+use crate::ops::{ CmpKind, BiniKind };
+use crate::ir_types::IrTypeId;
+use crate::regalloc::{ Allocation, Location, TargetRegs, PhysReg };
+use crate::lower::{
+    LoweringBackend, LowerOp, LowerInstr, LowerTarget, Operand,
+    phi_locations, edge_moves
+};
+use crate::jit::asm::*;
+use crate::patch::PatchPoint;
+
+/*
+ * The generated function is called as
+ *
+ *   extern "C" fn(args: *const u64, result: *mut u64) -> u32
+ *
+ * and gives 0 after writing a returned value through
+ * `result`, or K + 1 on leaving through the Kth side
+ * exit.  Every value lives where the allocator put it:
+ * each instruction loads its operands into the scratch
+ * registers `rax` and `rcx`, computes into `rax`, and
+ * stores that to its own location.
+ *
+ * The frame below `rbp` holds the saved callee-saved
+ * registers, the two pointer arguments, and the spill
+ * slots:
+ *
+ *   rbp - 8 .. rbp - 40    rbx, r12 .. r15
+ *   rbp - 48               args
+ *   rbp - 56               result
+ *   rbp - 64 - 8K          spill slot K
+ *
+ * Phis are taken out of SSA form on each edge: the
+ * phi arguments are pushed and then popped into the
+ * target's phi locations, which moves them all at once
+ * whatever the overlap between sources and
+ * destinations.  Edges of a branch get their moves on
+ * their own paths, so no edge is ever critical.
+ */
+
+const SAVED_REGS: [Reg; 5] = [RBX, R12, R13, R14, R15];
+const ARGS_SLOT: i32 = -48;
+const RESULT_SLOT: i32 = -56;
+const FIRST_SPILL: i32 = -64;
+
+// The allocatable registers, in order of preference,
+// numbered as the `PhysReg`s of `target_regs`.
+const ALLOCATABLE: [Reg; 12] = [
+    RDX, RSI, RDI, R8, R9, R10, R11, RBX, R12, R13, R14, R15
+];
+const REG_NAMES: [&str; 12] = [
+    "rdx", "rsi", "rdi", "r8", "r9", "r10", "r11",
+    "rbx", "r12", "r13", "r14", "r15"
+];
+
+/**
+ * The registers the JIT allocates values to: one
+ * class of general purpose registers holding values
+ * of every type.
+ */
+pub fn target_regs() -> TargetRegs {
+    let mut target = TargetRegs::new();
+    let regs: Vec<PhysReg> = REG_NAMES.iter()
+                                      .map(|n| target.add_reg(n))
+                                      .collect();
+    let gpr = target.add_class("gpr", &regs);
+    for &ty in &[IrTypeId::Bool, IrTypeId::Int32,
+                 IrTypeId::Int64, IrTypeId::PtrInt]
+    {
+        target.set_type_class(ty, gpr);
+    }
+    target
+}
+
+#[derive(Clone, Copy)]
+enum Label {
+    Block(BlockId),
+    Epilogue
+}
+
+/** Generated code, not yet mapped executable. */
+pub(crate) struct X86Code {
+    pub(crate) code: Vec<u8>,
+    pub(crate) params: Vec<Option<IrTypeId>>,
+    pub(crate) exits: Vec<InstrId>,
+    pub(crate) patch_points: Vec<PatchPoint>
+}
+
+pub(crate) struct X86Backend<'a> {
+    alloc: &'a Allocation,
+    asm: Asm,
+
+    // Code offset of each block, and the locations of
+    // its phis, by block id.
+    block_offsets: Vec<Option<usize>>,
+    phi_locations: Vec<Vec<Option<Location>>>,
+
+    // Jumps to patch once every label is placed.
+    fixups: Vec<(usize, Label)>,
+
+    // Guard failures, as the jump to the stub and the
+    // side exit it leaves by.
+    guard_stubs: Vec<(usize, u32)>,
+
+    exits: Vec<InstrId>,
+    params: Vec<Option<IrTypeId>>,
+    patch_points: Vec<PatchPoint>
+}
+
+fn spill_disp(slot: u32) -> i32 {
+    FIRST_SPILL - 8 * (slot as i32)
+}
+
+fn konst_value(op: LowerOp) -> u64 {
+    op.const_bits().unwrap_or_else(|| {
+        panic!("Not a constant: {:?}", op)
+    })
+}
+
+fn cmp_cond(kind: CmpKind) -> Cond {
+    match kind {
+      CmpKind::Lt => Cond::L, CmpKind::Gt => Cond::G,
+      CmpKind::Le => Cond::Le, CmpKind::Ge => Cond::Ge,
+      CmpKind::Eq => Cond::E, CmpKind::Ne => Cond::Ne
+    }
+}
+
+impl<'a> X86Backend<'a> {
+    pub(crate) fn new(alloc: &'a Allocation) -> X86Backend<'a> {
+        X86Backend {
+            alloc,
+            asm: Asm::new(),
+            block_offsets: Vec::new(),
+            phi_locations: Vec::new(),
+            fixups: Vec::new(),
+            guard_stubs: Vec::new(),
+            exits: Vec::new(),
+            params: Vec::new(),
+            patch_points: Vec::new()
+        }
+    }
+
+    fn reg(&self, reg: PhysReg) -> Reg {
+        ALLOCATABLE[reg.as_u16() as usize]
+    }
+
+    fn load_operand(&mut self, dst: Reg, operand: &Operand) {
+        if let Some(op) = operand.konst() {
+            self.asm.mov_imm(dst, konst_value(op));
+            return;
+        }
+        match operand.location() {
+          Some(Location::Reg(r)) => {
+            let src = self.reg(r);
+            self.asm.mov_rr(dst, src);
+          }
+          Some(Location::Spill(s)) => {
+            self.asm.load(dst, RBP, spill_disp(s.as_u32()));
+          }
+          None => panic!("Operand {:?} has no location.",
+                         operand.value())
+        }
+    }
+
+    // Store `rax` to the location of a value.  Values
+    // the allocator gave no location are never used.
+    fn store_result(&mut self, location: Option<Location>) {
+        match location {
+          Some(Location::Reg(r)) => {
+            let dst = self.reg(r);
+            self.asm.mov_rr(dst, RAX);
+          }
+          Some(Location::Spill(s)) => {
+            self.asm.store(RBP, spill_disp(s.as_u32()), RAX);
+          }
+          None => {}
+        }
+    }
+
+    fn jump_to(&mut self, label: Label) {
+        let at = self.asm.jmp();
+        self.fixups.push((at, label));
+    }
+
+    // Move the phi arguments of an edge into the phis
+    // of its target.
+    fn emit_edge_moves(&mut self, target: &LowerTarget) {
+        let block = target.block().as_u32() as usize;
+        let moves = edge_moves(target, &self.phi_locations[block]);
+        for (arg, _) in moves.iter().rev() {
+            match (arg.konst(), arg.location()) {
+              (Some(op), _) => {
+                self.asm.mov_imm(RAX, konst_value(op));
+                self.asm.push(RAX);
+              }
+              (None, Some(Location::Reg(r))) => {
+                let src = self.reg(r);
+                self.asm.push(src);
+              }
+              (None, Some(Location::Spill(s))) => {
+                self.asm.push_mem(RBP, spill_disp(s.as_u32()));
+              }
+              (None, None) => {
+                panic!("Phi argument {:?} has no location.",
+                       arg.value())
+              }
+            }
+        }
+        for &(_, dst) in &moves {
+            match dst {
+              Location::Reg(r) => {
+                let dst = self.reg(r);
+                self.asm.pop(dst);
+              }
+              Location::Spill(s) => {
+                self.asm.pop_mem(RBP, spill_disp(s.as_u32()));
+              }
+            }
+        }
+    }
+
+    fn side_exit(&mut self, instr: InstrId) -> u32 {
+        self.exits.push(instr);
+        self.exits.len() as u32
+    }
+
+    fn lower_bini(&mut self, kind: BiniKind, ty: IrTypeId) {
+        let wide = ty != IrTypeId::Int32 && ty != IrTypeId::Bool;
+        match kind {
+          BiniKind::Add => self.asm.add(wide, RAX, RCX),
+          BiniKind::Sub => self.asm.sub(wide, RAX, RCX),
+          BiniKind::Mul => self.asm.imul(wide, RAX, RCX),
+          BiniKind::And => self.asm.and(wide, RAX, RCX),
+          BiniKind::Or => self.asm.or(wide, RAX, RCX),
+          BiniKind::Xor => self.asm.xor(wide, RAX, RCX)
+        }
+        if ty == IrTypeId::Bool {
+            self.asm.and_eax_1();
+        }
+    }
+
+    fn lower_branch(&mut self, instr: &LowerInstr) {
+        self.load_operand(RAX, &instr.operands()[0]);
+        self.asm.test_al();
+        let (t, f) = (&instr.targets()[0], &instr.targets()[1]);
+        if t.args().is_empty() && f.args().is_empty() {
+            let at = self.asm.jcc(Cond::Ne);
+            self.fixups.push((at, Label::Block(t.block())));
+            self.jump_to(Label::Block(f.block()));
+            return;
+        }
+        let skip = self.asm.jcc(Cond::E);
+        self.emit_edge_moves(t);
+        self.jump_to(Label::Block(t.block()));
+        let here = self.asm.offset();
+        self.asm.patch(skip, here);
+        self.emit_edge_moves(f);
+        self.jump_to(Label::Block(f.block()));
+    }
+}
+
+impl<'a> LoweringBackend for X86Backend<'a> {
+    type Output = X86Code;
+
+    fn begin(&mut self, graph: &Graph) {
+        let nblocks = graph.block_store().total_blocks();
+        self.block_offsets = vec![None; nblocks];
+        self.phi_locations = phi_locations(graph, self.alloc);
+
+        self.asm.push(RBP);
+        self.asm.mov_rbp_rsp();
+        for &reg in &SAVED_REGS {
+            self.asm.push(reg);
+        }
+        let frame = 16 + 8 * self.alloc.num_spill_slots();
+        self.asm.sub_rsp((frame + 15) & !15);
+        self.asm.store(RBP, ARGS_SLOT, RDI);
+        self.asm.store(RBP, RESULT_SLOT, RSI);
+    }
+
+    fn begin_block(&mut self, block: &BlockView) {
+        let offset = self.asm.offset();
+        self.block_offsets[block.id().as_u32() as usize] = Some(offset);
+    }
+
+    fn lower_instr(&mut self, instr: &LowerInstr) {
+        let operands = instr.operands();
+        match instr.op() {
+          LowerOp::Nop | LowerOp::Phi { .. } => {}
+          LowerOp::Param { index, ty } => {
+            let i = index as usize;
+            if self.params.len() <= i {
+                self.params.resize(i + 1, None);
+            }
+            self.params[i] = Some(ty);
+            self.asm.load(RAX, RBP, ARGS_SLOT);
+            self.asm.load(RAX, RAX, 8 * (index as i32));
+            self.store_result(instr.location());
+          }
+          op @ LowerOp::ConstBool(_)
+          | op @ LowerOp::ConstInt32(_)
+          | op @ LowerOp::ConstInt64(_)
+          | op @ LowerOp::ConstPtrInt(_) => {
+            if instr.location().is_some() {
+                self.asm.mov_imm(RAX, konst_value(op));
+                self.store_result(instr.location());
+            }
+          }
+          LowerOp::Cmp { kind, ty } => {
+            self.load_operand(RAX, &operands[0]);
+            self.load_operand(RCX, &operands[1]);
+            self.asm.cmp(ty != IrTypeId::Int32 && ty != IrTypeId::Bool,
+                         RAX, RCX);
+            self.asm.set_eax(cmp_cond(kind));
+            self.store_result(instr.location());
+          }
+          LowerOp::Bini { kind, ty } => {
+            self.load_operand(RAX, &operands[0]);
+            self.load_operand(RCX, &operands[1]);
+            self.lower_bini(kind, ty);
+            self.store_result(instr.location());
+          }
+          LowerOp::Guard { expected } => {
+            self.load_operand(RAX, &operands[0]);
+            self.asm.test_al();
+            let exit = self.side_exit(instr.id());
+            let fail = if expected { Cond::E } else { Cond::Ne };
+            let at = self.asm.jcc(fail);
+            self.guard_stubs.push((at, exit));
+          }
+          LowerOp::Ret { .. } => {
+            self.load_operand(RCX, &operands[0]);
+            self.asm.load(RAX, RBP, RESULT_SLOT);
+            self.asm.store(RAX, 0, RCX);
+            self.asm.mov_imm(RAX, 0);
+            self.jump_to(Label::Epilogue);
+          }
+          LowerOp::Branch => self.lower_branch(instr),
+          LowerOp::Jump => {
+            let target = &instr.targets()[0];
+            self.emit_edge_moves(target);
+            self.jump_to(Label::Block(target.block()));
+          }
+          LowerOp::Exit => {
+            let exit = self.side_exit(instr.id());
+            self.asm.mov_imm(RAX, exit as u64);
+            self.jump_to(Label::Epilogue);
+          }
+          LowerOp::PatchPoint { id, size } => {
+            let offset = self.asm.offset();
+            self.asm.nops(size as usize);
+            self.patch_points.push(
+              PatchPoint::new(id, instr.id(), offset, size));
+          }
+          LowerOp::BoundsCheck { ty, elided } => {
+            self.load_operand(RAX, &operands[0]);
+            if !elided {
+                self.load_operand(RCX, &operands[1]);
+                self.asm.cmp(ty != IrTypeId::Int32, RAX, RCX);
+                let exit = self.side_exit(instr.id());
+                let at = self.asm.jcc(Cond::Ae);
+                self.guard_stubs.push((at, exit));
+            }
+            self.store_result(instr.location());
+          }
+          LowerOp::NullCheck { elided } => {
+            self.load_operand(RAX, &operands[0]);
+            if !elided {
+                self.asm.test_rax();
+                let exit = self.side_exit(instr.id());
+                let at = self.asm.jcc(Cond::E);
+                self.guard_stubs.push((at, exit));
+            }
+            self.store_result(instr.location());
+          }
+          LowerOp::Load { ty } => {
+            self.load_operand(RAX, &operands[0]);
+            self.asm.load_sized(ty.mem_size(), RAX, RAX, 0);
+            self.store_result(instr.location());
+          }
+          LowerOp::Store { ty } => {
+            self.load_operand(RAX, &operands[0]);
+            self.load_operand(RCX, &operands[1]);
+            self.asm.store_sized(ty.mem_size(), RAX, 0, RCX);
+          }
+          LowerOp::PtrAdd { offset, stride } => {
+            self.load_operand(RAX, &operands[1]);
+            self.asm.mov_imm(RCX, stride as u64);
+            self.asm.imul(true, RAX, RCX);
+            self.load_operand(RCX, &operands[0]);
+            self.asm.add(true, RAX, RCX);
+            self.asm.mov_imm(RCX, offset as u64);
+            self.asm.add(true, RAX, RCX);
+            self.store_result(instr.location());
+          }
+          LowerOp::Copy { .. } => {
+            self.load_operand(RAX, &operands[0]);
+            self.store_result(instr.location());
+          }
+          LowerOp::Call { .. } => {
+            unreachable!("calls are rejected before compiling")
+          }
+        }
+    }
+
+    fn finish(mut self) -> X86Code {
+        for (at, exit) in std::mem::take(&mut self.guard_stubs) {
+            let here = self.asm.offset();
+            self.asm.patch(at, here);
+            self.asm.mov_imm(RAX, exit as u64);
+            self.jump_to(Label::Epilogue);
+        }
+
+        let epilogue = self.asm.offset();
+        self.asm.lea_rsp_rbp(-8 * (SAVED_REGS.len() as i32));
+        for &reg in SAVED_REGS.iter().rev() {
+            self.asm.pop(reg);
+        }
+        self.asm.pop(RBP);
+        self.asm.ret();
+
+        for &(at, label) in &self.fixups {
+            let target = match label {
+              Label::Block(b) => {
+                self.block_offsets[b.as_u32() as usize]
+                    .expect("Jump to a block with no code.")
+              }
+              Label::Epilogue => epilogue
+            };
+            self.asm.patch(at, target);
+        }
+        X86Code {
+            code: self.asm.into_code(),
+            params: self.params,
+            exits: self.exits,
+            patch_points: self.patch_points
+        }
+    }
+}
@@ -0,0 +1,232 @@
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::ptr;
+
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ir_types::IrTypeId;
+use crate::verify::VerifyError;
+use crate::target::TargetDesc;
+use crate::regalloc::{ linear_scan, assign_spill_slots };
+use crate::patch::{ PatchPoint, PatchError, points_to_patch };
+
+mod asm;
+use self::asm::fill_nops;
+
+mod backend;
+pub use self::backend::target_regs;
+use self::backend::{ X86Backend, X86Code };
+
+/** An error compiling a graph to machine code. */
+#[derive(Debug)]
+pub enum JitError {
+    // The graph does not pass `verify`.
+    Verify(Vec<VerifyError>),
+
+    // Executable memory could not be mapped.
+    Map(io::Error),
+
+    // A patch did not fit its patch point.
+    Patch(PatchError),
+
+    // The graph is built for a target other than a
+    // 64-bit little-endian one.
+    Target(TargetDesc),
+
+    // The graph calls another graph at the given
+    // instruction, which a function compiled on its
+    // own cannot.
+    Call { instr: InstrId }
+}
+
+impl fmt::Display for JitError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match self {
+          JitError::Verify(errs) => {
+            write!(f, "{} verify errors", errs.len())?;
+            if let Some(err) = errs.first() {
+                write!(f, ", first: {}", err)?;
+            }
+            Ok(())
+          }
+          JitError::Map(err) =>
+            write!(f, "mapping code: {}", err),
+          JitError::Patch(err) =>
+            write!(f, "patching code: {}", err),
+          JitError::Target(target) =>
+            write!(f, "cannot compile for target {}", target),
+          JitError::Call { instr } =>
+            write!(f, "cannot compile the call at {}", instr)
+        }
+    }
+}
+
+impl Error for JitError {}
+
+/** How a call of a compiled graph ended. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum JitOutcome {
+    // A `ret` returned the value, zero-extended.
+    Return(u64),
+
+    // A failed guard or an `exit` left through a side
+    // exit.
+    SideExit(InstrId)
+}
+
+type EntryFn = unsafe extern "C" fn(*const u64, *mut u64) -> u32;
+
+/**
+ * A graph compiled to x86-64 machine code, held in a
+ * private executable mapping.
+ */
+pub struct JitFunction {
+    ptr: *mut libc::c_void,
+    len: usize,
+    params: Vec<Option<IrTypeId>>,
+    exits: Vec<InstrId>,
+    patch_points: Vec<PatchPoint>
+}
+
+impl JitFunction {
+    fn map(code: X86Code) -> Result<JitFunction, JitError> {
+        let len = code.code.len();
+        let ptr = unsafe {
+            libc::mmap(ptr::null_mut(), len,
+                       libc::PROT_READ | libc::PROT_WRITE,
+                       libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0)
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(JitError::Map(io::Error::last_os_error()));
+        }
+        let func = JitFunction {
+            ptr, len, params: code.params, exits: code.exits,
+            patch_points: code.patch_points
+        };
+        unsafe {
+            ptr::copy_nonoverlapping(code.code.as_ptr(),
+                                     ptr as *mut u8, len);
+        }
+        func.protect(libc::PROT_READ | libc::PROT_EXEC) ?;
+        Ok(func)
+    }
+
+    fn protect(&self, prot: libc::c_int) -> Result<(), JitError> {
+        if unsafe { libc::mprotect(self.ptr, self.len, prot) } != 0 {
+            return Err(JitError::Map(io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    // The number of params the graph reads, counting
+    // indices up to the highest it reads.
+    pub fn num_params(&self) -> usize { self.params.len() }
+
+    pub fn code_size(&self) -> usize { self.len }
+
+    // The nop padding each patch point reserves, with
+    // offsets into the code.
+    pub fn patch_points(&self) -> &[PatchPoint] {
+        &self.patch_points
+    }
+
+    /**
+     * Write machine code into the padding of every
+     * patch point with the given id, filling the rest
+     * of the padding with nops.
+     *
+     * # Safety
+     * The code runs in the middle of the compiled
+     * graph, so it must preserve every register and the
+     * stack, and fall through or jump back to the end
+     * of the padding.
+     */
+    pub unsafe fn patch(&mut self, id: u32, code: &[u8])
+      -> Result<(), JitError>
+    {
+        let points = points_to_patch(&self.patch_points, id, code.len())
+          .map_err(JitError::Patch) ?;
+        self.protect(libc::PROT_READ | libc::PROT_WRITE) ?;
+        for point in &points {
+            let space = std::slice::from_raw_parts_mut(
+              (self.ptr as *mut u8).add(point.offset()),
+              point.size() as usize);
+            space[.. code.len()].copy_from_slice(code);
+            fill_nops(&mut space[code.len() ..]);
+        }
+        self.protect(libc::PROT_READ | libc::PROT_EXEC)
+    }
+
+    /**
+     * Run the compiled graph.  Each argument is
+     * truncated to the type of its param, with any
+     * nonzero value a true `Bool`.  Panics if fewer
+     * arguments are given than `num_params`.
+     */
+    pub fn call(&self, args: &[u64]) -> JitOutcome {
+        assert!(args.len() >= self.params.len(),
+                "Expected {} arguments, given {}.",
+                self.params.len(), args.len());
+        let args: Vec<u64> = args.iter().zip(&self.params)
+          .map(|(&v, ty)| match ty {
+            Some(IrTypeId::Bool) => (v != 0) as u64,
+            Some(IrTypeId::Int32) => v & 0xFFFF_FFFF,
+            _ => v
+          })
+          .collect();
+        let mut result = 0_u64;
+        let status = unsafe {
+            let entry: EntryFn = mem::transmute(self.ptr);
+            entry(args.as_ptr(), &mut result)
+        };
+        match status {
+          0 => JitOutcome::Return(result),
+          k => JitOutcome::SideExit(self.exits[(k - 1) as usize])
+        }
+    }
+}
+
+impl Drop for JitFunction {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len); }
+    }
+}
+
+impl Graph {
+    /**
+     * Compile the graph to x86-64 machine code: values
+     * coalesced by `coalesce_copies` are allocated to
+     * `target_regs` by `linear_scan`, spilled values
+     * share slots by `assign_spill_slots`, phis are
+     * resolved by moves on each edge, and each
+     * instruction is lowered from a fixed template.
+     * The graph must be built for a 64-bit
+     * little-endian target.
+     */
+    pub fn compile_x86_64(&self) -> Result<JitFunction, JitError> {
+        if !self.target().is_64_little() {
+            return Err(JitError::Target(self.target()));
+        }
+        self.verify().map_err(JitError::Verify) ?;
+        if let Some(instr) = self.first_call() {
+            return Err(JitError::Call { instr });
+        }
+        let liveness = self.liveness();
+        let intervals = self.live_intervals(liveness);
+        let classes = self.coalesce_copies(&intervals);
+        let alloc = linear_scan(classes.intervals(), &target_regs())
+                      .expect("target_regs has a class for every type");
+        let alloc = assign_spill_slots(classes.intervals(), &alloc);
+        let alloc = classes.expand(&alloc);
+        let code = self.lower(X86Backend::new(&alloc), Some(&alloc));
+        debug!("Compiled {} bytes of x86-64, {} spill slots",
+               code.code.len(), alloc.num_spill_slots());
+        JitFunction::map(code)
+    }
+}
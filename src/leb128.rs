@@ -14,13 +14,50 @@ pub fn write_leb128u<V: Into<u64>>(
     }
 }
 
+// The number of bytes `write_leb128u` takes for a
+// value.
+pub fn leb128u_len(v: u64) -> usize {
+    let bits = 64 - (v | 1).leading_zeros() as usize;
+    bits.div_ceil(7)
+}
+
+// Read a value from trusted bytes.  One and two byte
+// encodings, which most operands take, are picked out
+// of a word of bytes read at once; longer ones are
+// read a byte at a time.  Gathering the groups of
+// longer encodings from the word by shifts and masks
+// was measured slower than the byte loop: it puts the
+// length on a longer dependency chain ahead of the
+// next read, where the loop's branches predict well.
 pub unsafe fn read_leb128u(bytes: &[u8]) -> (usize, u64) {
+    if bytes.len() < WORD_BYTES {
+        return read_leb128u_bytewise(bytes);
+    }
+    let mut slot = [0_u8; WORD_BYTES];
+    slot.copy_from_slice(bytes.get_unchecked(.. WORD_BYTES));
+    let word = u64::from_le_bytes(slot);
+    if word & 0x80 == 0 {
+        return (1, word & 0x7F);
+    }
+    if word & 0x8000 == 0 {
+        return (2, (word & 0x7F) | ((word >> 1) & 0x3F80));
+    }
+    read_leb128u_bytewise(bytes)
+}
+
+const WORD_BYTES: usize = 8;
+
+// Read a value from trusted bytes a byte at a time.
+pub unsafe fn read_leb128u_bytewise(bytes: &[u8])
+  -> (usize, u64)
+{
     let mut accum: u64 = 0;
     let mut i: usize = 0;
     loop {
         debug_assert!(i < bytes.len());
+        debug_assert!(i < MAX_LEB128_BYTES);
         let b = *bytes.get_unchecked(i);
-        accum = (accum << 7) | ((b & 0x7F) as u64);
+        accum |= ((b & 0x7F) as u64) << (7 * i);
         i += 1;
         if b < 0x80 {
             break;
@@ -29,3 +66,114 @@ pub unsafe fn read_leb128u(bytes: &[u8]) -> (usize, u64) {
 
     (i, accum)
 }
+
+/** Why a leb128 encoding was rejected. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum Leb128Error {
+    // The bytes ended before the encoding did.
+    Truncated,
+
+    // The encoding has trailing zero groups, which the
+    // writer never emits.
+    Overlong,
+
+    // The value does not fit in 64 bits.
+    Overflow
+}
+
+// The longest encoding of a 64 bit value.
+pub const MAX_LEB128_BYTES: usize = 10;
+
+// Read a value, accepting only the canonical encoding
+// of a 64 bit value, as the writer produces it.
+pub fn read_leb128u_checked(bytes: &[u8])
+  -> Result<(usize, u64), Leb128Error>
+{
+    let mut accum: u64 = 0;
+    for (i, &b) in bytes.iter().take(MAX_LEB128_BYTES).enumerate() {
+        let group = (b & 0x7F) as u64;
+
+        // The last byte holds only the top bit.
+        if i == MAX_LEB128_BYTES - 1 && b > 0x01 {
+            return Err(Leb128Error::Overflow);
+        }
+        accum |= group << (7 * i);
+
+        if b < 0x80 {
+            if b == 0 && i > 0 {
+                return Err(Leb128Error::Overlong);
+            }
+            return Ok((i + 1, accum));
+        }
+    }
+    // A tenth byte always ends the encoding, so only
+    // running out of bytes gets here.
+    Err(Leb128Error::Truncated)
+}
+
+// Read an unsigned value of at most `bits` bits, in
+// the bounded encoding wasm uses: padding groups are
+// allowed, but not more bytes than `bits` needs, and
+// not set bits past `bits`.
+pub fn read_leb128u_bounded(bytes: &[u8], bits: u32)
+  -> Result<(usize, u64), Leb128Error>
+{
+    debug_assert!(bits > 0 && bits <= 64);
+    let max_bytes = bits.div_ceil(7) as usize;
+    let mut accum: u64 = 0;
+    for (i, &b) in bytes.iter().take(max_bytes).enumerate() {
+        let shift = 7 * i as u32;
+        let group = (b & 0x7F) as u64;
+        if bits - shift < 7 && (group >> (bits - shift)) != 0 {
+            return Err(Leb128Error::Overflow);
+        }
+        accum |= group << shift;
+        if b < 0x80 {
+            return Ok((i + 1, accum));
+        }
+    }
+    if bytes.len() < max_bytes {
+        Err(Leb128Error::Truncated)
+    } else {
+        Err(Leb128Error::Overflow)
+    }
+}
+
+// Read a signed value of at most `bits` bits, in the
+// bounded encoding wasm uses.  The unused bits of the
+// last byte must repeat the sign bit.
+pub fn read_leb128s_bounded(bytes: &[u8], bits: u32)
+  -> Result<(usize, i64), Leb128Error>
+{
+    debug_assert!(bits > 0 && bits <= 64);
+    let max_bytes = bits.div_ceil(7) as usize;
+    let mut accum: i64 = 0;
+    for (i, &b) in bytes.iter().take(max_bytes).enumerate() {
+        let shift = 7 * i as u32;
+        let group = (b & 0x7F) as i64;
+        if bits - shift < 7 {
+            // The group's bits from the sign bit up, which
+            // must be all clear or all set.
+            let high = group >> (bits - shift - 1);
+            let mask = 0x7F >> (bits - shift - 1);
+            let last = b < 0x80;
+            if last && high != 0 && high != mask {
+                return Err(Leb128Error::Overflow);
+            }
+        }
+        accum |= group << shift;
+        if b < 0x80 {
+            let used = shift + 7;
+            if used < 64 && (group & 0x40) != 0 {
+                accum |= -1_i64 << used;
+            }
+            return Ok((i + 1, accum));
+        }
+    }
+    if bytes.len() < max_bytes {
+        Err(Leb128Error::Truncated)
+    } else {
+        Err(Leb128Error::Overflow)
+    }
+}
@@ -7,17 +7,168 @@ extern crate env_logger;
 mod ir_types;
 mod ops;
 mod instr;
+mod instr_index;
+mod def_use;
+mod changes;
+mod undo;
+mod const_pool;
+mod const_eval;
+mod encoding_stats;
+mod pass_stats;
+mod disasm;
 mod leb128;
+mod crc32;
+mod decode;
+mod batch;
+mod arena;
 mod block;
 mod builder;
 mod defn;
 mod graph;
+mod mut_session;
+mod view;
+mod cursor;
+mod schedule;
+mod structured;
+mod analysis;
+mod regalloc;
+mod lower;
+mod ebpf;
+#[cfg(all(feature = "jit", target_arch = "x86_64", unix))]
+mod jit;
+mod trace;
+mod profile;
+mod patch;
+mod snapshot;
+mod target;
+mod bounds;
+mod null_check;
+mod indvars;
+mod two_address;
+mod rauw;
+mod outline;
+mod splice;
+mod split;
+mod retarget;
+mod verify;
+mod invariants;
+mod serialize;
+mod canonical;
+mod module;
+mod type_desc;
+#[cfg(feature = "compress")]
+mod compress;
+#[cfg(unix)]
+mod mmap;
+mod text;
+mod wasm;
+mod typecheck;
+mod lint;
+mod diff;
+mod testing;
 
 pub mod api {
-    pub use crate::graph::{ Graph, GraphSession };
-    pub use crate::builder::{ Builder, BuildSession };
+    pub use crate::graph::{
+        Graph, GraphSession, FreezeMode, FreezeStats, MemoryUsage
+    };
+    pub use crate::builder::{
+        Builder, BuildSession, BuildError, BuildLimit
+    };
+    pub use crate::block::{ BlockId, BlockStorage };
+    pub use crate::view::{
+        BlockView, InstrView, TargetView, InputIds, TargetViews
+    };
+    pub use crate::instr::{ InstrId, OperandEncoding, StreamGrowth };
+    pub use crate::instr_index::InstrIndex;
+    pub use crate::def_use::{ DefUses, Use, UseSite, Uses };
+    pub use crate::changes::{ Change, ChangeMark };
+    pub use crate::undo::UndoMark;
+    pub use crate::mut_session::GraphMutSession;
+    pub use crate::cursor::GraphCursor;
+    pub use crate::encoding_stats::{ EncodingStats, OpcodeBytes };
+    pub use crate::pass_stats::{ AnalysisCounts, PassStats, PassReport };
+    pub use crate::ops::{
+        Opcode, CmpKind, BiniKind, DynOperation, Operation, OpVisitor,
+        NopOp, PhiOp, ParamOp, ConstOp, CmpOp, BiniOp, GuardOp,
+        RetOp, BranchOp, JumpOp, ExitOp, PatchPointOp, BoundsCheckOp,
+        NullCheckOp, LoadOp, StoreOp, PtrAddOp, CopyOp, CallOp
+    };
+    pub use crate::const_eval::ConstValue;
+    pub use crate::schedule::{
+        Schedule, BlockSchedule, ScheduledInstr, LatencyTable
+    };
+    pub use crate::structured::{
+        StructuredCfg, Structured, Edge, StructureError
+    };
+    pub use crate::analysis::{
+        Cfg, DomTree, Liveness, LoopInfo, Loop, RegPressure,
+        EscapeInfo, Escape, MemorySsa, MemoryAccess,
+        InductionVars, BasicIv, DerivedIv, SlpPlan, SlpPack
+    };
+    pub use crate::batch::{ DecodeBatch, BatchInstr };
+    pub use crate::decode::{
+        DecodeError, DecodedInstr, InstrDecoder, StreamError,
+        decode_instrs, decode_instrs_with, decode_instrs_pooled
+    };
+    pub use crate::disasm::disasm;
+    pub use crate::verify::{ VerifyError, VerifyErrorKind };
+    pub use crate::invariants::InvariantError;
+    pub use crate::serialize::LoadError;
+    pub use crate::module::{ Module, DataId };
+    pub use crate::type_desc::{
+        TypeTable, TypeDesc, DescId, FieldType,
+        AccessError, AccessErrorKind
+    };
+    pub use crate::canonical::CanonicalError;
+    #[cfg(unix)]
+    pub use crate::mmap::MappedFile;
+    pub use crate::text::ParseError;
+    pub use crate::wasm::{ WasmError, WasmErrorKind };
+    pub use crate::typecheck::TypeError;
+    pub use crate::lint::BuildLint;
+    pub use crate::diff::{ GraphDiff, BlockDiff, InstrDiff, diff };
+    pub use crate::regalloc::{
+        LiveIntervals, LiveInterval, Coalescing,
+        TargetRegs, RegClass, RegClassId, PhysReg,
+        Allocation, Location, SpillSlot,
+        CallConv, StackOrder, ArgLocation,
+        AbiLowering, AbiMove, AbiLocation,
+        FrameLayout, SpillOp, SpillKind, RegHint, RegHints,
+        AllocError, linear_scan, linear_scan_with_hints,
+        assign_spill_slots
+    };
+    pub use crate::lower::{
+        LoweringBackend, LowerOp, LowerInstr, LowerTarget, Operand
+    };
+    pub use crate::ebpf::{
+        EbpfProgram, EbpfInsn, EbpfError,
+        target_regs as ebpf_target_regs
+    };
+    #[cfg(all(feature = "jit", target_arch = "x86_64", unix))]
+    pub use crate::jit::{
+        JitFunction, JitOutcome, JitError,
+        target_regs as jit_target_regs
+    };
+    pub use crate::trace::{ Trace, TraceEnd, BranchProfile };
+    pub use crate::profile::{ Profile, ProfileError };
+    pub use crate::patch::{ PatchPoint, PatchError };
+    pub use crate::snapshot::{ Snapshot, FrameSlot, FrameState };
+    pub use crate::target::{ TargetDesc, Endian };
+    pub use crate::bounds::BoundsCheckStats;
+    pub use crate::indvars::IndVarStats;
+    pub use crate::two_address::TwoAddressStats;
+    pub use crate::rauw::ReplaceStats;
+    pub use crate::outline::OutlineError;
+    pub use crate::splice::SpliceError;
+    pub use crate::testing::{
+        Rng, GraphGen, GraphGenConfig,
+        assert_ops_round_trip, assert_graph_round_trip,
+        CheckError, file_check, assert_file_check, reduce,
+        Mutation, Mutator
+    };
     pub use crate::ir_types::{
-        BoolTy, Int32Ty, Int64Ty, PtrIntTy
+        IrTypeId, IrInputTypeId, BoolTy, Int32Ty, Int64Ty, PtrIntTy,
+        PtrTy
     };
 
     pub fn build<F>(f: F) -> Builder
@@ -26,6 +177,69 @@ pub mod api {
         Builder::build(f)
     }
 
+    // Build, giving an error if the graph outgrows a
+    // limit of the stores rather than panicking.
+    pub fn try_build<F>(f: F) -> Result<Builder, BuildError>
+      where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        Builder::try_build(f)
+    }
+
+    // As `try_build`, with the given encoding and block
+    // storage.
+    pub fn try_build_with<F>(encoding: OperandEncoding,
+                             storage: BlockStorage, f: F)
+      -> Result<Builder, BuildError>
+      where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        Builder::try_build_with(encoding, storage, f)
+    }
+
+    // As `try_build`, with the given encoding, and the
+    // instruction stream allocated and grown as `growth`
+    // says rather than starting small and doubling.  A
+    // build outgrowing a fixed slab fails.
+    pub fn try_build_grown<F>(encoding: OperandEncoding,
+                              growth: StreamGrowth, f: F)
+      -> Result<Builder, BuildError>
+      where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        Builder::try_build_grown(encoding, growth, f)
+    }
+
+    // Build with operands written in the given encoding.
+    pub fn build_encoded<F>(encoding: OperandEncoding, f: F) -> Builder
+      where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        Builder::build_encoded(encoding, f)
+    }
+
+    // Build with operands written in the given encoding,
+    // and blocks held in the given storage.
+    pub fn build_with<F>(encoding: OperandEncoding,
+                         storage: BlockStorage, f: F)
+      -> Builder
+      where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        Builder::build_with(encoding, storage, f)
+    }
+
+    // Build for the given target rather than
+    // `TargetDesc::LP64`.
+    pub fn build_for<F>(target: TargetDesc, f: F) -> Builder
+      where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        Builder::build_for(target, f)
+    }
+
+    // Build with shadow type tracking: every operand's
+    // type is checked as its instruction is emitted.
+    pub fn build_checked<F>(f: F) -> Builder
+      where F: for<'x> FnOnce (&mut BuildSession<'x>)
+    {
+        Builder::build_checked(f)
+    }
+
     pub fn graph<R, F>(b: Builder, f: F) -> R
       where F: for <'x> FnOnce (&mut GraphSession<'x>)
                         -> R
@@ -0,0 +1,77 @@
+
+use std::fmt;
+
+use crate::block::BlockId;
+use crate::graph::Graph;
+
+/**
+ * A likely frontend bug in a finished build: legal to
+ * construct, but almost never intended.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum BuildLint {
+    // No edge into the block passes an argument for
+    // the phi at this index.
+    UnfedPhi { block: BlockId, index: u32 },
+
+    // The block was entered, but no end instruction
+    // targets it.
+    UntargetedBlock { block: BlockId }
+}
+
+impl BuildLint {
+    pub fn block(&self) -> BlockId {
+        match *self {
+          BuildLint::UnfedPhi { block, .. } => block,
+          BuildLint::UntargetedBlock { block } => block
+        }
+    }
+}
+
+impl fmt::Display for BuildLint {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match *self {
+          BuildLint::UnfedPhi { block, index } =>
+            write!(f, "{}: phi {} receives no arguments",
+                   block, index),
+          BuildLint::UntargetedBlock { block } =>
+            write!(f, "{}: block is never targeted", block)
+        }
+    }
+}
+
+pub(crate) fn build_lints(graph: &Graph) -> Vec<BuildLint> {
+    let nblocks = graph.block_store().total_blocks();
+
+    // The number of incoming edges of each block, and
+    // the most phi arguments passed along any of them.
+    let mut edges = vec![0_u32; nblocks];
+    let mut max_args = vec![0_u32; nblocks];
+    for block in graph.block_store().iter_rpo_blocks() {
+        for target in graph.block_end(block).targets_iter() {
+            let ti = target.block_id().as_u32() as usize;
+            edges[ti] += 1;
+            let nargs = target.phi_args().count() as u32;
+            max_args[ti] = max_args[ti].max(nargs);
+        }
+    }
+
+    let mut lints = Vec::new();
+    for block in graph.block_store().iter_rpo_blocks() {
+        let bi = block.id().as_u32() as usize;
+        if edges[bi] == 0 && !block.is_start() {
+            lints.push(BuildLint::UntargetedBlock {
+                block: block.id()
+            });
+        }
+        for index in max_args[bi] .. block.num_phis() {
+            lints.push(BuildLint::UnfedPhi {
+                block: block.id(), index
+            });
+        }
+    }
+    lints
+}
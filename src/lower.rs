@@ -0,0 +1,303 @@
+
+use crate::block::{ Block, BlockId };
+use crate::instr::{ InstrId, InstrInfo };
+use crate::graph::Graph;
+use crate::view::BlockView;
+use crate::ops::{ Op, ConstOp, CmpKind, BiniKind };
+use crate::regalloc::{ Allocation, Location };
+use crate::ir_types::IrTypeId;
+
+/**
+ * A decoded operation, as handed to a lowering
+ * backend.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum LowerOp {
+    Nop,
+    Phi { ty: IrTypeId },
+    Param { index: u32, ty: IrTypeId },
+    ConstBool(bool),
+    ConstInt32(u32),
+    ConstInt64(u64),
+    ConstPtrInt(u64),
+    Cmp { kind: CmpKind, ty: IrTypeId },
+    Bini { kind: BiniKind, ty: IrTypeId },
+    Guard { expected: bool },
+    Ret { ty: IrTypeId },
+    Branch,
+    Jump,
+    Exit,
+    PatchPoint { id: u32, size: u32 },
+    BoundsCheck { ty: IrTypeId, elided: bool },
+    NullCheck { elided: bool },
+    Load { ty: IrTypeId },
+    Store { ty: IrTypeId },
+    PtrAdd { offset: u32, stride: u32 },
+    Copy { ty: IrTypeId },
+    Call { callee: u32, ty: IrTypeId }
+}
+
+/**
+ * An operand resolved to its definition: the defining
+ * instruction, the type it defines, and where the
+ * register allocator placed it, if allocation results
+ * were given.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct Operand {
+    value: InstrId,
+    ty: IrTypeId,
+    location: Option<Location>,
+    konst: Option<LowerOp>
+}
+
+/** A target of an end instruction, with its phi arguments. */
+#[derive(Clone, Debug)]
+pub struct LowerTarget {
+    block: BlockId,
+    args: Vec<Operand>
+}
+
+/** An instruction handed to a lowering backend. */
+#[derive(Clone, Debug)]
+pub struct LowerInstr {
+    id: InstrId,
+    op: LowerOp,
+    location: Option<Location>,
+    operands: Vec<Operand>,
+    targets: Vec<LowerTarget>
+}
+
+/**
+ * A code generator driven by `Graph::lower`.  Blocks
+ * are visited in layout order, which is the order of
+ * the instruction stream (RPO) with cold blocks moved
+ * after the rest, and each instruction of a block is
+ * handed over in order between its `begin_block` and
+ * `end_block`.  The start block always comes first.
+ */
+pub trait LoweringBackend {
+    type Output;
+
+    // Called once, before the first block.
+    fn begin(&mut self, _graph: &Graph) {}
+
+    fn begin_block(&mut self, _block: &BlockView) {}
+    fn lower_instr(&mut self, instr: &LowerInstr);
+    fn end_block(&mut self, _block: &BlockView) {}
+
+    // Called once after the last block, giving the
+    // generated code.
+    fn finish(self) -> Self::Output;
+}
+
+impl Operand {
+    pub fn value(&self) -> InstrId { self.value }
+    pub fn ty(&self) -> IrTypeId { self.ty }
+    pub fn location(&self) -> Option<Location> { self.location }
+
+    // The constant operation defining the operand, if it
+    // is a constant, for backends that fold constants
+    // into immediates.
+    pub fn konst(&self) -> Option<LowerOp> { self.konst }
+}
+
+impl LowerTarget {
+    pub fn block(&self) -> BlockId { self.block }
+    pub fn args(&self) -> &[Operand] { &self.args }
+}
+
+impl LowerInstr {
+    pub fn id(&self) -> InstrId { self.id }
+    pub fn op(&self) -> LowerOp { self.op }
+
+    // The allocated location of the value the
+    // instruction defines.
+    pub fn location(&self) -> Option<Location> { self.location }
+
+    pub fn operands(&self) -> &[Operand] { &self.operands }
+    pub fn targets(&self) -> &[LowerTarget] { &self.targets }
+}
+
+impl LowerOp {
+    fn of(op: &Op) -> LowerOp {
+        match op {
+          Op::Nop(_) => LowerOp::Nop,
+          Op::Phi(op) => LowerOp::Phi { ty: op.tyid() },
+          Op::Param(op) => {
+            LowerOp::Param { index: op.index(), ty: op.tyid() }
+          }
+          Op::Const(ConstOp::Bool(b)) => LowerOp::ConstBool(*b),
+          Op::Const(ConstOp::Int32(i)) => LowerOp::ConstInt32(*i),
+          Op::Const(ConstOp::Int64(i)) => LowerOp::ConstInt64(*i),
+          Op::Const(ConstOp::PtrInt(p)) => LowerOp::ConstPtrInt(*p),
+          Op::Cmp(op) => {
+            LowerOp::Cmp { kind: op.kind(), ty: op.tyid() }
+          }
+          Op::Bini(op) => {
+            LowerOp::Bini { kind: op.kind(), ty: op.tyid() }
+          }
+          Op::Guard(op) => LowerOp::Guard { expected: op.expected() },
+          Op::Ret(op) => LowerOp::Ret { ty: op.tyid() },
+          Op::Branch(_) => LowerOp::Branch,
+          Op::Jump(_) => LowerOp::Jump,
+          Op::Exit(_) => LowerOp::Exit,
+          Op::PatchPoint(op) => {
+            LowerOp::PatchPoint { id: op.id(), size: op.size() }
+          }
+          Op::BoundsCheck(op) => {
+            LowerOp::BoundsCheck { ty: op.tyid(), elided: op.elided() }
+          }
+          Op::NullCheck(op) => {
+            LowerOp::NullCheck { elided: op.elided() }
+          }
+          Op::Load(op) => LowerOp::Load { ty: op.tyid() },
+          Op::Store(op) => LowerOp::Store { ty: op.tyid() },
+          Op::PtrAdd(op) => {
+            LowerOp::PtrAdd { offset: op.offset(), stride: op.stride() }
+          }
+          Op::Copy(op) => LowerOp::Copy { ty: op.tyid() },
+          Op::Call(op) => {
+            LowerOp::Call { callee: op.callee(), ty: op.tyid() }
+          }
+        }
+    }
+
+    // The bits of a constant, zero-extended.
+    pub fn const_bits(&self) -> Option<u64> {
+        match *self {
+          LowerOp::ConstBool(b) => Some(b as u64),
+          LowerOp::ConstInt32(i) => Some(i as u64),
+          LowerOp::ConstInt64(i) | LowerOp::ConstPtrInt(i) => Some(i),
+          _ => None
+        }
+    }
+
+    fn is_const(&self) -> bool {
+        matches!(self, LowerOp::ConstBool(_)
+                     | LowerOp::ConstInt32(_)
+                     | LowerOp::ConstInt64(_)
+                     | LowerOp::ConstPtrInt(_))
+    }
+}
+
+impl Graph {
+    /**
+     * Drive a lowering backend over the graph, handing it
+     * each instruction with its operands resolved, and
+     * with allocated locations if `alloc` is given.  The
+     * graph should pass `verify`.
+     */
+    pub fn lower<B>(&self, mut backend: B, alloc: Option<&Allocation>)
+      -> B::Output
+      where B: LoweringBackend
+    {
+        backend.begin(self);
+        for block in self.layout_order() {
+            let block = self.block_view(block).unwrap();
+            backend.begin_block(&block);
+            let b = self.block(block.id());
+            for info in self.block_instrs(b) {
+                let instr = self.lower_instr(&info, alloc);
+                backend.lower_instr(&instr);
+            }
+            backend.end_block(&block);
+        }
+        backend.finish()
+    }
+
+    // The first call of the graph, for backends that
+    // compile a graph on its own and cannot lower one.
+    pub(crate) fn first_call(&self) -> Option<InstrId> {
+        self.block_store().iter_rpo_blocks()
+            .flat_map(|b| self.block_instrs(b))
+            .find(|info| matches!(info.op(), Op::Call(_)))
+            .map(|info| info.defn().instr_id())
+    }
+
+    // The blocks in layout order: RPO, with the cold
+    // blocks other than the start block sunk to the
+    // end in their RPO order.
+    pub(crate) fn layout_order(&self) -> Vec<BlockId> {
+        let rpo = self.block_store().iter_rpo_blocks();
+        let (hot, cold): (Vec<&Block>, Vec<&Block>) =
+          rpo.partition(|b| b.order() == 0 || !b.is_cold());
+        hot.into_iter().chain(cold).map(|b| b.id()).collect()
+    }
+
+    fn lower_instr(&self, info: &InstrInfo, alloc: Option<&Allocation>)
+      -> LowerInstr
+    {
+        let id = info.defn().instr_id();
+        let location = |id| alloc.and_then(|a| a.location(id));
+        let operand = |value: InstrId| {
+            let def = self.instr(value);
+            let konst = Some(LowerOp::of(def.op()))
+                          .filter(|op| op.is_const());
+            Operand {
+                value,
+                ty: def.op().out_type().unwrap(),
+                location: location(value),
+                konst
+            }
+        };
+
+        let operands = info.inputs_iter()
+                           .map(|d| operand(d.instr_id()))
+                           .collect();
+        let targets = info.targets_iter().map(|t| LowerTarget {
+            block: t.block_id(),
+            args: t.phi_args().map(|d| operand(d.instr_id())).collect()
+        }).collect();
+        let location =
+          if info.op().defines_value() { location(id) } else { None };
+        LowerInstr {
+            id, op: LowerOp::of(info.op()), location, operands, targets
+        }
+    }
+}
+
+// The allocated locations of each block's phis, by
+// block id, for backends that resolve phis with moves
+// on each edge.
+pub(crate) fn phi_locations(graph: &Graph, alloc: &Allocation)
+  -> Vec<Vec<Option<Location>>>
+{
+    let store = graph.block_store();
+    let mut phis = vec![Vec::new(); store.total_blocks()];
+    for block in store.iter_rpo_blocks() {
+        phis[block.id().as_u32() as usize] =
+          graph.block_instrs(block)
+               .take_while(|info| matches!(info.op(), Op::Phi(_)))
+               .map(|info| alloc.location(info.defn().instr_id()))
+               .collect();
+    }
+    phis
+}
+
+// The moves an edge makes from its phi arguments into
+// the locations of its target's phis, `dsts`, all to
+// be made at once.  An unused phi may share its
+// location with a later phi, so only the last move
+// into each location is kept; after that, moves of a
+// value to where it already is are dropped.
+pub(crate) fn edge_moves(target: &LowerTarget,
+                         dsts: &[Option<Location>])
+  -> Vec<(Operand, Location)>
+{
+    let all: Vec<(Operand, Location)> =
+      target.args().iter().zip(dsts.iter())
+            .filter_map(|(arg, &dst)| Some((*arg, dst ?)))
+            .collect();
+    all.iter().enumerate()
+       .filter(|&(i, &(_, dst))| {
+           all[i + 1 ..].iter().all(|&(_, d)| d != dst)
+       })
+       .map(|(_, &m)| m)
+       .filter(|(arg, dst)| {
+           arg.konst().is_some() || arg.location() != Some(*dst)
+       })
+       .collect()
+}
@@ -0,0 +1,90 @@
+
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::slice;
+use std::sync::Arc;
+
+use crate::graph::Graph;
+
+/**
+ * A read-only, private mapping of a whole file, for
+ * loading graphs with `Graph::from_shared_bytes`
+ * without reading them into memory first.
+ *
+ * The file should not be truncated while it is mapped:
+ * touching pages past its new end raises `SIGBUS`.
+ */
+pub struct MappedFile {
+    ptr: *mut libc::c_void,
+    len: usize
+}
+
+// The mapping is read-only, and unmapped only on drop.
+unsafe impl Send for MappedFile {}
+unsafe impl Sync for MappedFile {}
+
+impl MappedFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<MappedFile> {
+        let file = File::open(path) ?;
+        let len = file.metadata() ?.len();
+        if len > (isize::MAX as u64) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "file too large to map"));
+        }
+
+        // Empty mappings are not allowed.
+        let len = len as usize;
+        if len == 0 {
+            return Ok(MappedFile { ptr: ptr::null_mut(), len });
+        }
+        let ptr = unsafe {
+            libc::mmap(ptr::null_mut(), len, libc::PROT_READ,
+                       libc::MAP_PRIVATE, file.as_raw_fd(), 0)
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(MappedFile { ptr, len })
+    }
+
+    pub fn len(&self) -> usize { self.len }
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+}
+
+impl AsRef<[u8]> for MappedFile {
+    fn as_ref(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe {
+            slice::from_raw_parts(self.ptr as *const u8, self.len)
+        }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            unsafe { libc::munmap(self.ptr, self.len); }
+        }
+    }
+}
+
+impl Graph {
+    /**
+     * Map the file at `path` and load the graph it holds,
+     * as written by `write_to`, reading the instruction
+     * stream in place from the mapping.  Files that do
+     * not load fail with `InvalidData`, carrying the
+     * `LoadError`.
+     */
+    pub fn load_mapped<P: AsRef<Path>>(path: P) -> io::Result<Graph> {
+        let file = Arc::new(MappedFile::open(path) ?);
+        Graph::from_shared_bytes(file).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        })
+    }
+}
@@ -0,0 +1,277 @@
+
+use std::collections::HashMap;
+
+use crate::graph::Graph;
+use crate::instr::InstrBytes;
+use crate::const_pool::ConstPool;
+use crate::decode::{ ByteReader, DecodeError };
+use crate::serialize::{
+    LoadError, FORMAT_VERSION,
+    append_checksum, checked_body, read_count, write_const_pool
+};
+use crate::type_desc::{ TypeTable, AccessError };
+use crate::leb128;
+
+/*
+ * The serialized form of a module is:
+ *
+ *   magic           4 bytes, "CGIM"
+ *   version         1 byte, as for graphs
+ *   const pool      leb128 num_consts, then each
+ *                     constant as leb128
+ *   data pool       leb128 num_data, then each item as
+ *                     leb128 length and its bytes
+ *   type table      3 bytes of target, as in a graph
+ *                     header, leb128 num_descs, then
+ *                     each as a kind byte, 0 for a
+ *                     struct with leb128 num_fields
+ *                     and each field type, 1 for an
+ *                     array with its element type and
+ *                     leb128 length;
+ *                     a member type is a byte 0 and a
+ *                     type byte, or 1 and a leb128
+ *                     descriptor id
+ *   graphs          leb128 num_graphs, then each as
+ *                     leb128 name length, UTF-8 name,
+ *                     leb128 image length, and image
+ *   checksum        4 bytes, little-endian CRC-32 of
+ *                     all the bytes before it
+ *
+ * A graph image is a graph serialized as by
+ * `Graph::to_bytes`, under the magic "CGIX", with its
+ * const pool section holding leb128 indices into the
+ * module's pool rather than the constants themselves.
+ */
+const MODULE_MAGIC: [u8; 4] = *b"CGIM";
+const IMAGE_MAGIC: [u8; 4] = *b"CGIX";
+
+/** Names an item of a module's data pool. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DataId(u32);
+
+impl DataId {
+    pub fn as_u32(&self) -> u32 { self.0 }
+}
+
+/**
+ * A set of named graphs, such as the functions of a
+ * program, sharing one pool of large constants, one
+ * pool of data, such as strings, and one table of
+ * aggregate types.  Each constant or data item the
+ * graphs use is held, and serialized, once for the
+ * whole module.
+ */
+#[derive(Default)]
+pub struct Module {
+    graphs: Vec<(String, Graph)>,
+    consts: ConstPool,
+    data: Vec<Box<[u8]>>,
+    data_index: HashMap<Box<[u8]>, u32>,
+    types: TypeTable
+}
+
+impl Module {
+    pub fn new() -> Module {
+        Module::default()
+    }
+
+    // Add a graph, giving its index.  Names need not be
+    // unique; `find_graph` finds the first of a name.
+    pub fn add_graph(&mut self, name: &str, graph: Graph) -> u32 {
+        for &value in graph.const_pool() {
+            self.consts.intern(value);
+        }
+        self.graphs.push((name.to_string(), graph));
+        (self.graphs.len() - 1) as u32
+    }
+
+    pub fn num_graphs(&self) -> u32 { self.graphs.len() as u32 }
+
+    pub fn graph(&self, index: u32) -> Option<&Graph> {
+        self.graphs.get(index as usize).map(|(_, g)| g)
+    }
+    pub fn graph_name(&self, index: u32) -> Option<&str> {
+        self.graphs.get(index as usize).map(|(n, _)| &n[..])
+    }
+    pub fn find_graph(&self, name: &str) -> Option<u32> {
+        self.graphs.iter().position(|(n, _)| n == name)
+                   .map(|i| i as u32)
+    }
+
+    pub fn graphs(&self) -> impl Iterator<Item=(&str, &Graph)> {
+        self.graphs.iter().map(|(n, g)| (&n[..], g))
+    }
+
+    // The constants pooled by the module's graphs, each
+    // once, in order of first use.
+    pub fn const_pool(&self) -> &[u64] {
+        self.consts.values()
+    }
+
+    // Add an item of data, giving the id of the
+    // identical item if there is one.
+    pub fn add_data(&mut self, bytes: &[u8]) -> DataId {
+        if let Some(&id) = self.data_index.get(bytes) {
+            return DataId(id);
+        }
+        let id = self.data.len() as u32;
+        self.data.push(bytes.into());
+        self.data_index.insert(bytes.into(), id);
+        DataId(id)
+    }
+
+    pub fn data(&self, id: DataId) -> Option<&[u8]> {
+        self.data.get(id.0 as usize).map(|d| &d[..])
+    }
+    pub fn num_data(&self) -> u32 { self.data.len() as u32 }
+
+    // The aggregate types the graphs' `ptradd`
+    // instructions lay out members by.
+    pub fn types(&self) -> &TypeTable { &self.types }
+    pub fn types_mut(&mut self) -> &mut TypeTable { &mut self.types }
+
+    /**
+     * Check the member accesses of every graph against
+     * the module's type table, as by
+     * `TypeTable::check_graph`, giving the index of the
+     * graph with each problem.
+     */
+    pub fn check_accesses(&self)
+      -> Result<(), Vec<(u32, AccessError)>>
+    {
+        let mut errors = Vec::new();
+        for (index, (_, graph)) in self.graphs.iter().enumerate() {
+            if let Err(errs) = self.types.check_graph(graph) {
+                errors.extend(errs.into_iter()
+                                  .map(|e| (index as u32, e)));
+            }
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    /** Serialize the module and its graphs. */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&MODULE_MAGIC);
+        out.push(FORMAT_VERSION);
+        write_const_pool(self.consts.values(), &mut out);
+
+        leb128::write_leb128u(self.data.len() as u64, &mut out);
+        for item in &self.data {
+            leb128::write_leb128u(item.len() as u64, &mut out);
+            out.extend_from_slice(item);
+        }
+        self.types.write_to(&mut out);
+
+        leb128::write_leb128u(self.graphs.len() as u64, &mut out);
+        for (name, graph) in &self.graphs {
+            let mut refs = Vec::new();
+            let pool = graph.const_pool();
+            leb128::write_leb128u(pool.len() as u64, &mut refs);
+            for &value in pool {
+                let index = self.consts.index_of(value).unwrap();
+                leb128::write_leb128u(index, &mut refs);
+            }
+            let image = graph.write_image(&IMAGE_MAGIC, &refs);
+
+            leb128::write_leb128u(name.len() as u64, &mut out);
+            out.extend_from_slice(name.as_bytes());
+            leb128::write_leb128u(image.len() as u64, &mut out);
+            out.extend_from_slice(&image);
+        }
+        append_checksum(&mut out);
+        out
+    }
+
+    /**
+     * Load a module written by `to_bytes`.  Each graph
+     * is loaded with the checks of `Graph::from_bytes`,
+     * and must refer only to constants the module holds.
+     */
+    pub fn from_bytes(bytes: &[u8]) -> Result<Module, LoadError> {
+        if !bytes.starts_with(&MODULE_MAGIC) {
+            return Err(LoadError::BadMagic);
+        }
+        let mut reader = ByteReader::at(bytes, MODULE_MAGIC.len());
+        let version = reader.read_u8() ?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::BadVersion { found: version });
+        }
+        let bytes = checked_body(bytes, reader.offset()) ?;
+        let mut reader = ByteReader::at(bytes, reader.offset());
+
+        let mut module = Module::new();
+        let num_consts = read_count(&mut reader) ?;
+        let mut consts = Vec::with_capacity(num_consts);
+        for _ in 0 .. num_consts {
+            consts.push(reader.read_leb128u() ?);
+        }
+        module.consts = ConstPool::from_values(consts);
+
+        let num_data = read_count(&mut reader) ?;
+        for _ in 0 .. num_data {
+            let item = read_bytes(&mut reader, bytes) ?;
+            module.data.push(item.into());
+        }
+        for (i, item) in module.data.iter().enumerate() {
+            module.data_index.entry(item.clone()).or_insert(i as u32);
+        }
+        module.types = TypeTable::read_from(&mut reader) ?;
+
+        let num_graphs = read_count(&mut reader) ?;
+        for index in 0 .. num_graphs as u32 {
+            let offset = reader.offset();
+            let name = read_bytes(&mut reader, bytes) ?;
+            let name = std::str::from_utf8(name)
+              .map_err(|_| LoadError::BadName { offset }) ?;
+            let image = read_bytes(&mut reader, bytes) ?;
+            let graph = module.load_graph(image).map_err(|error| {
+                LoadError::ModuleGraph { index, error: Box::new(error) }
+            }) ?;
+            module.graphs.push((name.to_string(), graph));
+        }
+        if !reader.at_end() {
+            return Err(LoadError::TrailingBytes {
+                offset: reader.offset()
+            });
+        }
+        Ok(module)
+    }
+
+    fn load_graph(&self, image: &[u8]) -> Result<Graph, LoadError> {
+        let module_pool = self.consts.values();
+        let read_refs = |reader: &mut ByteReader| {
+            let num_refs = read_count(reader) ?;
+            let mut pool = Vec::with_capacity(num_refs);
+            for _ in 0 .. num_refs {
+                let offset = reader.offset();
+                let index = reader.read_leb128u32() ?;
+                match module_pool.get(index as usize) {
+                  Some(&value) => pool.push(value),
+                  None => return Err(DecodeError::BadPoolIndex {
+                      offset, index
+                  }.into())
+                }
+            }
+            Ok(pool)
+        };
+        Graph::load_image(image, &IMAGE_MAGIC, read_refs, |start, end| {
+            InstrBytes::Owned(image[start .. end].to_vec())
+        })
+    }
+}
+
+// Read a leb128 length and that many bytes.
+fn read_bytes<'a>(reader: &mut ByteReader<'a>, bytes: &'a [u8])
+  -> Result<&'a [u8], LoadError>
+{
+    let offset = reader.offset();
+    let len = reader.read_leb128u32() ? as usize;
+    if len > reader.remaining() {
+        return Err(DecodeError::Truncated { offset }.into());
+    }
+    let start = reader.offset();
+    *reader = ByteReader::at(bytes, start + len);
+    Ok(&bytes[start .. start + len])
+}
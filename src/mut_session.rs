@@ -0,0 +1,104 @@
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::changes::{ Change, ChangeMark };
+use crate::patch::PatchError;
+use crate::rauw::ReplaceStats;
+
+/**
+ * A graph writer, holding the graph exclusively for
+ * the edits of a transform: patching, replacing uses,
+ * and retargeting edges.  The graph stays readable
+ * between edits.  When the session ends, the graph is
+ * checked: its stores must keep their invariants and
+ * its stream must decode, and in debug builds it must
+ * verify.
+ */
+pub struct GraphMutSession<'gs> {
+    // The underlying graph.
+    graph: &'gs mut Graph,
+
+    // The graph's change log at the start of the
+    // session.
+    start: ChangeMark
+}
+
+impl<'gs> GraphMutSession<'gs> {
+    pub(crate) fn new(graph: &'gs mut Graph) -> GraphMutSession<'gs> {
+        let start = graph.change_mark();
+        GraphMutSession { graph, start }
+    }
+
+    /** The graph as edited so far. */
+    pub fn graph(&self) -> &Graph { self.graph }
+
+    /**
+     * The edits made in place during the session, or
+     * none once one of them has rebuilt the graph,
+     * changing its ids.
+     */
+    pub fn changes(&self) -> Option<&[Change]> {
+        self.graph.changes_since(self.start)
+    }
+
+    /** Patch the space of patch points, by `Graph::patch`. */
+    pub fn patch(&mut self, id: u32, bytes: &[u8])
+      -> Result<(), PatchError>
+    {
+        self.graph.patch(id, bytes)
+    }
+
+    /**
+     * Make every use of `old` use `new`, by
+     * `Graph::replace_all_uses`.
+     */
+    pub fn replace_all_uses(&mut self, old: InstrId, new: InstrId)
+      -> ReplaceStats
+    {
+        self.graph.replace_all_uses(old, new)
+    }
+
+    /**
+     * Make an edge go to another block, by
+     * `Graph::retarget`, returning whether the graph was
+     * rebuilt.
+     */
+    pub fn retarget(&mut self, from: BlockId, index: u32, to: BlockId,
+                    args: &[InstrId])
+      -> bool
+    {
+        self.graph.retarget(from, index, to, args)
+    }
+
+    /**
+     * Make an edge pass another value to a phi, by
+     * `Graph::set_phi_arg`, returning whether the graph
+     * was rebuilt.
+     */
+    pub fn set_phi_arg(&mut self, from: BlockId, index: u32, phi: u32,
+                       value: InstrId)
+      -> bool
+    {
+        self.graph.set_phi_arg(from, index, phi, value)
+    }
+}
+
+impl<'gs> Drop for GraphMutSession<'gs> {
+    fn drop(&mut self) {
+        // Leave a panic in the session to unwind.
+        if std::thread::panicking() {
+            return;
+        }
+        if let Err(err) = self.graph.check_invariants() {
+            panic!("graph edits broke an invariant: {}", err);
+        }
+        if let Err(err) = self.graph.validate_stream() {
+            panic!("graph edits broke the stream: {}", err);
+        }
+        if cfg!(debug_assertions) {
+            if let Err(errs) = self.graph.verify() {
+                panic!("graph edits fail to verify: {}", errs[0]);
+            }
+        }
+    }
+}
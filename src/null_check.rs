@@ -0,0 +1,102 @@
+
+use std::collections::HashMap;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::changes::Change;
+use crate::ops::{ Op, NullCheckOp, Operation };
+
+/*
+ * Null check elimination.  A null check is redundant,
+ * and is elided in place, when a check of the same
+ * pointer dominates it: the value of a check is taken
+ * as the pointer it checks, so a check of a checked
+ * pointer is redundant too.
+ */
+
+impl Graph {
+    /**
+     * Elide the null checks dominated by a check of the
+     * same pointer, giving the number elided.  Checks
+     * in unreachable blocks are left alone.
+     */
+    pub fn eliminate_null_checks(&mut self) -> u32 {
+        let dom = self.dominators();
+
+        // The pointer each check's value stands for.
+        let mut roots: HashMap<InstrId, InstrId> = HashMap::new();
+        // The checks of each pointer so far.
+        let mut seen: HashMap<InstrId, Vec<(BlockId, InstrId)>> =
+          HashMap::new();
+        let mut redundant = Vec::new();
+
+        for block in self.block_store().iter_rpo_blocks() {
+            if !dom.is_reachable(block.id()) {
+                continue;
+            }
+            for info in self.block_instrs(block) {
+                let check = match info.op() {
+                  Op::NullCheck(check) => check,
+                  _ => continue
+                };
+                let id = info.defn().instr_id();
+                let ptr = info.inputs_iter().next().unwrap().instr_id();
+                let root = roots.get(&ptr).cloned().unwrap_or(ptr);
+                let earlier = seen.entry(root).or_default();
+                let dominated = earlier.iter().any(|&(b, c)| {
+                    dom.instr_dominates(b, c, block.id(), id)
+                });
+                if dominated && !check.elided() {
+                    redundant.push(id);
+                }
+                earlier.push((block.id(), id));
+                roots.insert(id, root);
+            }
+        }
+
+        let elided = NullCheckOp::new(true).op();
+        for &id in &redundant {
+            self.record(Change::Payload { instr: id });
+            self.instr_store_mut().rewrite_payload(id, &elided);
+        }
+        redundant.len() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::Graph;
+
+    #[test]
+    fn elides_only_dominated_checks() {
+        let mut graph = Graph::parse_ir("\
+block0:
+  %0 = param.ptrint 0
+  %1 = param.ptrint 1
+  %2 = nullcheck %0
+  %3 = param.bool 2
+  branch %3, block1, block2
+block1:
+  %4 = nullcheck %2
+  %5 = nullcheck %1
+  jump block3
+block2:
+  jump block3
+block3:
+  %6 = nullcheck %1
+  ret.ptrint %6
+").unwrap();
+        assert_eq!(graph.eliminate_null_checks(), 1);
+
+        // A check of a checked pointer is dominated by
+        // the first check; the check in block1 does not
+        // dominate block3.
+        let text = graph.print_ir();
+        assert!(text.contains("%4 = nullcheck.elided %2"));
+        assert!(text.contains("%5 = nullcheck %1"));
+        assert!(text.contains("%6 = nullcheck %1"));
+        assert!(graph.verify().is_ok());
+        assert_eq!(graph.eliminate_null_checks(), 0);
+    }
+}
@@ -1,16 +1,19 @@
 
 use std::fmt;
+use std::convert::TryFrom;
 use std::mem;
 
 use crate::ops::{ Operation, Opcode, Op };
-use crate::ir_types::IrTypeId;
+use crate::decode::{ ByteReader, DecodeError };
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
 
 /**
  * Integer binops are functions of the form `(T, T) -> T`.
  * Namely, they take two integer inputs of some type T,
  * and return some result taken from that same type T.
  */
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
 #[repr(u8)]
 pub enum BiniKind { Add=1, Sub, Mul, And, Or, Xor }
 impl BiniKind {
@@ -23,6 +26,12 @@ impl BiniKind {
         mem::transmute(code)
     }
     fn into_u8(self) -> u8 { self as u8 }
+
+    // Whether swapping the operands keeps the result.
+    pub fn is_commutative(self) -> bool {
+        !matches!(self, BiniKind::Sub)
+    }
+
     fn as_str(self) -> &'static str {
         match self {
           BiniKind::Add => "Add", BiniKind::Sub => "Sub",
@@ -31,6 +40,17 @@ impl BiniKind {
         }
     }
 }
+impl TryFrom<u8> for BiniKind {
+    type Error = u8;
+
+    fn try_from(code: u8) -> Result<BiniKind, u8> {
+        if Self::is_valid_code(code) {
+            Ok(unsafe { Self::from_u8(code) })
+        } else {
+            Err(code)
+        }
+    }
+}
 
 /** Binary operation on integers. */
 #[derive(Clone)]
@@ -46,8 +66,8 @@ impl BiniOp {
         BiniOp { kind, tyid }
     }
 
-    fn kind(&self) -> BiniKind { self.kind }
-    fn tyid(&self) -> IrTypeId { self.tyid }
+    pub fn kind(&self) -> BiniKind { self.kind }
+    pub fn tyid(&self) -> IrTypeId { self.tyid }
 }
 
 impl Operation for BiniOp {
@@ -57,6 +77,9 @@ impl Operation for BiniOp {
         Some(self.tyid)
     }
     fn num_operands(&self) -> u32 { 2 }
+    fn operand_type(&self, _idx: u32) -> IrInputTypeId {
+        IrInputTypeId::Specific(self.tyid)
+    }
 
     fn write_to(&self, vec: &mut Vec<u8>) {
         vec.extend_from_slice(&[
@@ -72,6 +95,18 @@ impl Operation for BiniOp {
           IrTypeId::from_u8(*bytes.get_unchecked(1));
         (2, BiniOp { kind, tyid })
     }
+
+    fn decode(reader: &mut ByteReader)
+      -> Result<Self, DecodeError>
+    {
+        let offset = reader.offset();
+        let kind = BiniKind::try_from(reader.read_u8() ?)
+                     .map_err(|code| {
+                         DecodeError::BadKind { offset, code }
+                     }) ?;
+        let tyid = reader.read_type_id() ?;
+        Ok(BiniOp::new(kind, tyid))
+    }
 }
 
 impl fmt::Display for BiniOp {
@@ -0,0 +1,22 @@
+
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
+
+define_op! {
+    /**
+     * The bounds check instruction checks that an index is
+     * below a length, both taken as unsigned integers of
+     * the op's type, and gives the index back as its
+     * value.  It leaves the graph through a side exit
+     * when the index is out of bounds.  An elided check,
+     * proven never to fail, only gives back the index.
+     */
+    BoundsCheckOp: BoundsCheck { tyid: IrTypeId, elided: bool }
+    operation {
+        fn effectful() -> bool { true }
+        fn out_type(&self) -> Option<IrTypeId> { Some(self.tyid) }
+        fn num_operands(&self) -> u32 { 2 }
+        fn operand_type(&self, _idx: u32) -> IrInputTypeId {
+            IrInputTypeId::Specific(self.tyid)
+        }
+    }
+}
@@ -2,7 +2,8 @@
 use std::fmt;
 
 use crate::ops::{ Opcode, Operation, Op };
-use crate::ir_types::IrTypeId;
+use crate::decode::{ ByteReader, DecodeError };
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
 
 /**
  * The branch instruction branches on a boolean
@@ -21,13 +22,22 @@ impl Operation for BranchOp {
     fn op(&self) -> Op { Op::Branch(self.clone()) }
     fn out_type(&self) -> Option<IrTypeId> { None }
     fn num_operands(&self) -> u32 { 1 }
+    fn operand_type(&self, _idx: u32) -> IrInputTypeId {
+        IrInputTypeId::Specific(IrTypeId::Bool)
+    }
     fn num_targets(&self) -> Option<u32> { Some(2) }
 
-    fn write_to(&self, vec: &mut Vec<u8>) {}
+    fn write_to(&self, _vec: &mut Vec<u8>) {}
 
     unsafe fn read_from(_bytes: &[u8]) -> (usize, Self) {
         (0, BranchOp::new())
     }
+
+    fn decode(_reader: &mut ByteReader)
+      -> Result<Self, DecodeError>
+    {
+        Ok(BranchOp::new())
+    }
 }
 
 impl fmt::Display for BranchOp {
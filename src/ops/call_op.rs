@@ -0,0 +1,18 @@
+
+use crate::ir_types::IrTypeId;
+
+define_op! {
+    /**
+     * The call instruction calls the graph at index
+     * `callee` of the caller's module with its `nargs`
+     * operands as params, and defines the value the
+     * callee returns, of the op's type.  A call may read
+     * and write memory, so it is effectful.
+     */
+    CallOp: Call { tyid: IrTypeId, callee: u32, nargs: u32 }
+    operation {
+        fn effectful() -> bool { true }
+        fn out_type(&self) -> Option<IrTypeId> { Some(self.tyid) }
+        fn num_operands(&self) -> u32 { self.nargs }
+    }
+}
@@ -1,12 +1,14 @@
 
 use std::fmt;
+use std::convert::TryFrom;
 use std::mem;
-use std::marker::PhantomData;
 
 use crate::ops::{ Operation, Opcode, Op };
-use crate::ir_types::IrTypeId;
+use crate::decode::{ ByteReader, DecodeError };
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
 #[repr(u8)]
 pub enum CmpKind { Lt = 1, Gt, Le, Ge, Eq, Ne }
 impl CmpKind {
@@ -19,6 +21,17 @@ impl CmpKind {
         mem::transmute(code)
     }
     fn into_u8(self) -> u8 { self as u8 }
+
+    // The kind comparing the same when the operands are
+    // swapped.
+    pub fn mirrored(self) -> CmpKind {
+        match self {
+          CmpKind::Lt => CmpKind::Gt, CmpKind::Gt => CmpKind::Lt,
+          CmpKind::Le => CmpKind::Ge, CmpKind::Ge => CmpKind::Le,
+          CmpKind::Eq => CmpKind::Eq, CmpKind::Ne => CmpKind::Ne
+        }
+    }
+
     fn as_str(&self) -> &'static str {
         match *self {
           CmpKind::Lt => "Lt", CmpKind::Gt => "Gt",
@@ -27,6 +40,17 @@ impl CmpKind {
         }
     }
 }
+impl TryFrom<u8> for CmpKind {
+    type Error = u8;
+
+    fn try_from(code: u8) -> Result<CmpKind, u8> {
+        if Self::is_valid_code(code) {
+            Ok(unsafe { Self::from_u8(code) })
+        } else {
+            Err(code)
+        }
+    }
+}
 
 /** Introduces a comparison instruction. */
 #[derive(Clone)]
@@ -41,6 +65,9 @@ impl CmpOp {
     {
         CmpOp { kind, tyid }
     }
+
+    pub fn kind(&self) -> CmpKind { self.kind }
+    pub fn tyid(&self) -> IrTypeId { self.tyid }
 }
 
 impl Operation for CmpOp {
@@ -50,6 +77,9 @@ impl Operation for CmpOp {
         Some(IrTypeId::Bool)
     }
     fn num_operands(&self) -> u32 { 2 }
+    fn operand_type(&self, _idx: u32) -> IrInputTypeId {
+        IrInputTypeId::Specific(self.tyid)
+    }
 
     fn write_to(&self, vec: &mut Vec<u8>) {
         vec.extend_from_slice(&[
@@ -65,6 +95,18 @@ impl Operation for CmpOp {
           IrTypeId::from_u8(*bytes.get_unchecked(1));
         (2, CmpOp::new(kind, tyid))
     }
+
+    fn decode(reader: &mut ByteReader)
+      -> Result<Self, DecodeError>
+    {
+        let offset = reader.offset();
+        let kind = CmpKind::try_from(reader.read_u8() ?)
+                     .map_err(|code| {
+                         DecodeError::BadKind { offset, code }
+                     }) ?;
+        let tyid = reader.read_type_id() ?;
+        Ok(CmpOp::new(kind, tyid))
+    }
 }
 
 impl fmt::Display for CmpOp {
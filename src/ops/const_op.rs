@@ -1,16 +1,24 @@
 
+use std::convert::TryFrom;
 use std::fmt;
 
 use crate::ops::{ Operation, Opcode, Op };
-use crate::ir_types::{ IrTypeId, BoolTy, Int32Ty, Int64Ty };
+use crate::decode::{ ByteReader, DecodeError };
+use crate::ir_types::IrTypeId;
 use crate::leb128;
 
+// The payload tag of an Int64 constant written to the
+// stream's constant pool, followed by its leb128 pool
+// index in place of its value.
+pub const POOLED_CONST_TAG: u8 = 0x80;
+
 /** Introduces a constant value. */
 #[derive(Clone)]
 pub enum ConstOp {
     Bool(bool),
     Int32(u32),
-    Int64(u64)
+    Int64(u64),
+    PtrInt(u64)
 }
 
 impl ConstOp {
@@ -23,14 +31,31 @@ impl ConstOp {
     pub(crate) fn new_int64(i: u64) -> ConstOp {
         ConstOp::Int64(i)
     }
+    pub(crate) fn new_ptr_int(p: u64) -> ConstOp {
+        ConstOp::PtrInt(p)
+    }
 
     fn tyid(&self) -> IrTypeId {
         match self {
-          &ConstOp::Bool(_) => IrTypeId::Bool,
-          &ConstOp::Int32(_) => IrTypeId::Int32,
-          &ConstOp::Int64(_) => IrTypeId::Int64,
+          ConstOp::Bool(_) => IrTypeId::Bool,
+          ConstOp::Int32(_) => IrTypeId::Int32,
+          ConstOp::Int64(_) => IrTypeId::Int64,
+          ConstOp::PtrInt(_) => IrTypeId::PtrInt
         }
     }
+
+    // Read the payload of a pooled constant, from its
+    // tag, resolving its index in `pool`.
+    pub(crate) unsafe fn read_pooled(bytes: &[u8], pool: &[u64])
+      -> (usize, ConstOp)
+    {
+        debug_assert!(bytes[0] == POOLED_CONST_TAG);
+        let rest = bytes.get_unchecked(1..);
+        let (nb, index) = leb128::read_leb128u(rest);
+        let index = index as usize;
+        debug_assert!(index < pool.len());
+        (1 + nb, ConstOp::Int64(*pool.get_unchecked(index)))
+    }
 }
 
 impl Operation for ConstOp {
@@ -41,26 +66,37 @@ impl Operation for ConstOp {
     }
     fn num_operands(&self) -> u32 { 0 }
 
+    fn pool_value(&self) -> Option<u64> {
+        match *self {
+          ConstOp::Int64(i) => Some(i),
+          _ => None
+        }
+    }
+
     fn write_to(&self, vec: &mut Vec<u8>) {
-        match self {
-          &ConstOp::Bool(b) => {
+        match *self {
+          ConstOp::Bool(b) => {
             vec.extend_from_slice(&[
                 IrTypeId::Bool.into_u8(),
                 b as u8
             ]);
           }
-          &ConstOp::Int32(i) => {
+          ConstOp::Int32(i) => {
             vec.push(IrTypeId::Int32.into_u8());
             leb128::write_leb128u(i, vec);
           }
-          &ConstOp::Int64(i) => {
+          ConstOp::Int64(i) => {
             vec.push(IrTypeId::Int64.into_u8());
             leb128::write_leb128u(i, vec);
           }
+          ConstOp::PtrInt(p) => {
+            vec.push(IrTypeId::PtrInt.into_u8());
+            leb128::write_leb128u(p, vec);
+          }
         }
     }
     unsafe fn read_from(bytes: &[u8]) -> (usize, Self) {
-        debug_assert!(bytes.len() >= 1);
+        debug_assert!(!bytes.is_empty());
         let tyid =
           IrTypeId::from_u8(*bytes.get_unchecked(0));
 
@@ -73,14 +109,44 @@ impl Operation for ConstOp {
           },
           IrTypeId::Int32 => {
             let (nb, v64) = leb128::read_leb128u(rest);
-            debug_assert!(v64 <= (u32::max_value() as u64));
+            debug_assert!(v64 <= (u32::MAX as u64));
             (1 + nb, ConstOp::Int32(v64 as u32))
           }
-          IrTypeId::Int32 => {
+          IrTypeId::Int64 => {
             let (nb, v) = leb128::read_leb128u(rest);
             (1 + nb, ConstOp::Int64(v))
           }
-          _ => { panic!("Unexpected const type."); }
+          IrTypeId::PtrInt => {
+            let (nb, v) = leb128::read_leb128u(rest);
+            (1 + nb, ConstOp::PtrInt(v))
+          }
+        }
+    }
+
+    fn decode(reader: &mut ByteReader)
+      -> Result<Self, DecodeError>
+    {
+        let offset = reader.offset();
+        let tag = reader.read_u8() ?;
+        if tag == POOLED_CONST_TAG {
+            return Ok(ConstOp::Int64(reader.read_pooled() ?));
+        }
+        let tyid = IrTypeId::try_from(tag).map_err(|code| {
+            DecodeError::BadType { offset, code }
+        }) ?;
+        match tyid {
+          IrTypeId::Bool => {
+            Ok(ConstOp::Bool(reader.read_u8() ? > 0_u8))
+          }
+          IrTypeId::Int32 => {
+            Ok(ConstOp::Int32(reader.read_leb128u32() ?))
+          }
+          IrTypeId::Int64 => {
+            Ok(ConstOp::Int64(reader.read_leb128u() ?))
+          }
+          IrTypeId::PtrInt => {
+            Ok(ConstOp::PtrInt(reader.read_leb128u() ?))
+          }
         }
     }
 }
@@ -89,13 +155,15 @@ impl fmt::Display for ConstOp {
     fn fmt(&self, f: &mut fmt::Formatter)
       -> Result<(), fmt::Error>
     {
-        match self {
-          &ConstOp::Bool(b) =>
+        match *self {
+          ConstOp::Bool(b) =>
             write!(f, "ConstBool({})", b),
-          &ConstOp::Int32(i) =>
+          ConstOp::Int32(i) =>
             write!(f, "ConstInt32({})", i),
-          &ConstOp::Int64(i) =>
+          ConstOp::Int64(i) =>
             write!(f, "ConstInt64({})", i),
+          ConstOp::PtrInt(p) =>
+            write!(f, "ConstPtrInt({:#x})", p),
         }
     }
 }
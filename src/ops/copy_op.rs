@@ -0,0 +1,21 @@
+
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
+
+define_op! {
+    /**
+     * The copy instruction defines a value equal to its
+     * operand, of the op's type.  Copies are inserted by
+     * lowerings that need a value in a register of its
+     * own, such as `Graph::to_two_address`, and a
+     * register allocator may give a copy the location of
+     * its operand.
+     */
+    CopyOp: Copy { tyid: IrTypeId }
+    operation {
+        fn out_type(&self) -> Option<IrTypeId> { Some(self.tyid) }
+        fn num_operands(&self) -> u32 { 1 }
+        fn operand_type(&self, _idx: u32) -> IrInputTypeId {
+            IrInputTypeId::Specific(self.tyid)
+        }
+    }
+}
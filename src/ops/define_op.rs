@@ -0,0 +1,183 @@
+
+use std::fmt;
+
+use crate::decode::{ ByteReader, DecodeError };
+use crate::ir_types::IrTypeId;
+use crate::leb128;
+
+/**
+ * A field of an operation's payload, as `define_op!`
+ * encodes it: each field is written in declaration
+ * order after the opcode.
+ */
+pub(crate) trait OpField: Sized + Copy {
+    fn write_to(self, vec: &mut Vec<u8>);
+
+    // Read the field from well formed bytes, giving the
+    // number of bytes read.
+    unsafe fn read_from(bytes: &[u8]) -> (usize, Self);
+
+    fn decode(reader: &mut ByteReader) -> Result<Self, DecodeError>;
+
+    fn fmt_field(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>;
+}
+
+impl OpField for bool {
+    fn write_to(self, vec: &mut Vec<u8>) { vec.push(self as u8); }
+
+    unsafe fn read_from(bytes: &[u8]) -> (usize, bool) {
+        debug_assert!(!bytes.is_empty());
+        (1, *bytes.get_unchecked(0) > 0_u8)
+    }
+
+    fn decode(reader: &mut ByteReader) -> Result<bool, DecodeError> {
+        Ok(reader.read_u8() ? > 0_u8)
+    }
+
+    fn fmt_field(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "{}", self)
+    }
+}
+
+impl OpField for u32 {
+    fn write_to(self, vec: &mut Vec<u8>) {
+        leb128::write_leb128u(self, vec);
+    }
+
+    unsafe fn read_from(bytes: &[u8]) -> (usize, u32) {
+        let (nb, value) = leb128::read_leb128u(bytes);
+        debug_assert!(value <= (u32::MAX as u64));
+        (nb, value as u32)
+    }
+
+    fn decode(reader: &mut ByteReader) -> Result<u32, DecodeError> {
+        reader.read_leb128u32()
+    }
+
+    fn fmt_field(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "{}", self)
+    }
+}
+
+impl OpField for IrTypeId {
+    fn write_to(self, vec: &mut Vec<u8>) { vec.push(self.into_u8()); }
+
+    unsafe fn read_from(bytes: &[u8]) -> (usize, IrTypeId) {
+        debug_assert!(!bytes.is_empty());
+        (1, IrTypeId::from_u8(*bytes.get_unchecked(0)))
+    }
+
+    fn decode(reader: &mut ByteReader)
+      -> Result<IrTypeId, DecodeError>
+    {
+        reader.read_type_id()
+    }
+
+    fn fmt_field(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/*
+ * Declare an operation: its payload struct, with a
+ * `new` constructor and a getter for each field, and
+ * its `Operation` impl, with the payload written and
+ * read field by field and a `Display` of the form
+ * `Variant(field, ...)`.  The items in the `operation`
+ * section are spliced into the `Operation` impl, and
+ * give at least `out_type` and `num_operands`.
+ *
+ * The opcode and the `Op` variant share the given
+ * name; both must be declared by hand, along with the
+ * opcode's arm in `Opcode::specialize` and the op's
+ * method on `OpVisitor`.
+ *
+ *   define_op! {
+ *       /** Checks that a boolean is as expected. */
+ *       GuardOp: Guard { expected: bool }
+ *       operation {
+ *           fn effectful() -> bool { true }
+ *           fn out_type(&self) -> Option<IrTypeId> { None }
+ *           fn num_operands(&self) -> u32 { 1 }
+ *       }
+ *   }
+ */
+macro_rules! define_op {
+    (
+        $(#[$attr:meta])*
+        $name:ident : $variant:ident { $($field:ident : $fty:ty),* }
+        operation { $($body:tt)* }
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone)]
+        pub struct $name { $($field: $fty),* }
+
+        impl $name {
+            pub(crate) fn new($($field: $fty),*) -> $name {
+                $name { $($field),* }
+            }
+
+            $(pub fn $field(&self) -> $fty { self.$field })*
+        }
+
+        impl $crate::ops::Operation for $name {
+            fn opcode() -> $crate::ops::Opcode {
+                $crate::ops::Opcode::$variant
+            }
+            fn op(&self) -> $crate::ops::Op {
+                $crate::ops::Op::$variant(self.clone())
+            }
+
+            $($body)*
+
+            fn write_to(&self, _vec: &mut Vec<u8>) {
+                $($crate::ops::OpField::write_to(self.$field, _vec);)*
+            }
+
+            unsafe fn read_from(_bytes: &[u8]) -> (usize, Self) {
+                let mut _nb = 0;
+                $(
+                    let (nb, $field) = <$fty as $crate::ops::OpField>
+                      ::read_from(_bytes.get_unchecked(_nb ..));
+                    _nb += nb;
+                )*
+                (_nb, $name::new($($field),*))
+            }
+
+            fn decode(_reader: &mut $crate::decode::ByteReader)
+              -> Result<Self, $crate::decode::DecodeError>
+            {
+                $(
+                    let $field = <$fty as $crate::ops::OpField>
+                      ::decode(_reader) ?;
+                )*
+                Ok($name::new($($field),*))
+            }
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter)
+              -> Result<(), ::std::fmt::Error>
+            {
+                write!(f, "{}", stringify!($variant))?;
+                let mut _sep = "(";
+                $(
+                    f.write_str(_sep)?;
+                    $crate::ops::OpField::fmt_field(&self.$field, f)?;
+                    _sep = ", ";
+                )*
+                if _sep != "(" {
+                    f.write_str(")")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
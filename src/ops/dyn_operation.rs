@@ -0,0 +1,85 @@
+
+use std::fmt;
+
+use crate::ops::{ Opcode, Operation, Op };
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
+
+/**
+ * The instance methods of `Operation`, in a form that
+ * can be used as a trait object.  Every `Operation`
+ * has it, so decoded ops of different kinds can be
+ * kept together as `Box<dyn DynOperation>`, as can
+ * operations defined outside the crate's own set.
+ */
+pub trait DynOperation: fmt::Display {
+    fn opcode(&self) -> Opcode;
+    fn terminal(&self) -> bool;
+    fn effectful(&self) -> bool;
+
+    fn out_type(&self) -> Option<IrTypeId>;
+    fn num_operands(&self) -> u32;
+    fn operand_type(&self, idx: u32) -> IrInputTypeId;
+    fn num_targets(&self) -> Option<u32>;
+    fn pool_value(&self) -> Option<u64>;
+
+    // Write the op's payload, without its opcode.
+    fn write_to(&self, vec: &mut Vec<u8>);
+
+    fn clone_dyn(&self) -> Box<dyn DynOperation>;
+}
+
+impl<OP: Operation + 'static> DynOperation for OP {
+    fn opcode(&self) -> Opcode { OP::opcode() }
+    fn terminal(&self) -> bool { OP::terminal() }
+    fn effectful(&self) -> bool { OP::effectful() }
+    fn out_type(&self) -> Option<IrTypeId> {
+        Operation::out_type(self)
+    }
+    fn num_operands(&self) -> u32 { Operation::num_operands(self) }
+    fn operand_type(&self, idx: u32) -> IrInputTypeId {
+        Operation::operand_type(self, idx)
+    }
+    fn num_targets(&self) -> Option<u32> {
+        Operation::num_targets(self)
+    }
+    fn pool_value(&self) -> Option<u64> {
+        Operation::pool_value(self)
+    }
+    fn write_to(&self, vec: &mut Vec<u8>) {
+        Operation::write_to(self, vec)
+    }
+    fn clone_dyn(&self) -> Box<dyn DynOperation> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn DynOperation> {
+    fn clone(&self) -> Box<dyn DynOperation> { self.clone_dyn() }
+}
+
+impl Op {
+    // The operation held by the op, as a trait object.
+    pub(crate) fn as_dyn(&self) -> &dyn DynOperation {
+        match self {
+          Op::Nop(op) => op,
+          Op::Phi(op) => op,
+          Op::Param(op) => op,
+          Op::Const(op) => op,
+          Op::Cmp(op) => op,
+          Op::Bini(op) => op,
+          Op::Guard(op) => op,
+          Op::Ret(op) => op,
+          Op::Branch(op) => op,
+          Op::Jump(op) => op,
+          Op::Exit(op) => op,
+          Op::PatchPoint(op) => op,
+          Op::BoundsCheck(op) => op,
+          Op::NullCheck(op) => op,
+          Op::Load(op) => op,
+          Op::Store(op) => op,
+          Op::PtrAdd(op) => op,
+          Op::Copy(op) => op,
+          Op::Call(op) => op
+        }
+    }
+}
@@ -0,0 +1,17 @@
+
+use crate::ir_types::IrTypeId;
+
+define_op! {
+    /**
+     * The exit instruction leaves the graph through a
+     * side exit, without returning a value.  It ends
+     * traces whose path could not be followed further.
+     */
+    ExitOp: Exit {}
+    operation {
+        fn terminal() -> bool { true }
+        fn out_type(&self) -> Option<IrTypeId> { None }
+        fn num_operands(&self) -> u32 { 0 }
+        fn num_targets(&self) -> Option<u32> { Some(0) }
+    }
+}
@@ -0,0 +1,19 @@
+
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
+
+define_op! {
+    /**
+     * The guard instruction checks that a boolean operand
+     * has an expected value, leaving the graph through a
+     * side exit when it does not.
+     */
+    GuardOp: Guard { expected: bool }
+    operation {
+        fn effectful() -> bool { true }
+        fn out_type(&self) -> Option<IrTypeId> { None }
+        fn num_operands(&self) -> u32 { 1 }
+        fn operand_type(&self, _idx: u32) -> IrInputTypeId {
+            IrInputTypeId::Specific(IrTypeId::Bool)
+        }
+    }
+}
@@ -0,0 +1,19 @@
+
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
+
+define_op! {
+    /**
+     * The load instruction reads a value of the op's type
+     * from the memory a pointer points to, in as many
+     * bytes as the type's `mem_size`.
+     */
+    LoadOp: Load { tyid: IrTypeId }
+    operation {
+        fn effectful() -> bool { true }
+        fn out_type(&self) -> Option<IrTypeId> { Some(self.tyid) }
+        fn num_operands(&self) -> u32 { 1 }
+        fn operand_type(&self, _idx: u32) -> IrInputTypeId {
+            IrInputTypeId::Specific(IrTypeId::PtrInt)
+        }
+    }
+}
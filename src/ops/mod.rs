@@ -1,33 +1,76 @@
 
+#[macro_use]
+mod define_op;
+pub(crate) use self::define_op::OpField;
+
 mod opcode;
 pub use self::opcode::{ Opcode, SpecializeOpcode };
 
 mod operation;
 pub use self::operation::Operation;
 
+mod dyn_operation;
+pub use self::dyn_operation::DynOperation;
+
 mod op;
 pub use self::op::Op;
 
+mod visitor;
+pub use self::visitor::OpVisitor;
+
 mod bini_op;
 pub use self::bini_op::{ BiniOp, BiniKind };
 
+mod bounds_check_op;
+pub use self::bounds_check_op::BoundsCheckOp;
+
 mod branch_op;
 pub use self::branch_op::BranchOp;
 
+mod call_op;
+pub use self::call_op::CallOp;
+
+mod copy_op;
+pub use self::copy_op::CopyOp;
+
+mod exit_op;
+pub use self::exit_op::ExitOp;
+
 mod cmp_op;
 pub use self::cmp_op::{ CmpOp, CmpKind };
 
 mod const_op;
-pub use self::const_op::ConstOp;
+pub use self::const_op::{ ConstOp, POOLED_CONST_TAG };
+
+mod guard_op;
+pub use self::guard_op::GuardOp;
 
 mod jump_op;
 pub use self::jump_op::JumpOp;
 
+mod load_op;
+pub use self::load_op::LoadOp;
+
 mod nop_op;
 pub use self::nop_op::NopOp;
 
+mod patch_point_op;
+pub use self::patch_point_op::PatchPointOp;
+
+mod null_check_op;
+pub use self::null_check_op::NullCheckOp;
+
+mod param_op;
+pub use self::param_op::ParamOp;
+
 mod phi_op;
 pub use self::phi_op::PhiOp;
 
+mod ptr_add_op;
+pub use self::ptr_add_op::PtrAddOp;
+
 mod ret_op;
 pub use self::ret_op::RetOp;
+
+mod store_op;
+pub use self::store_op::StoreOp;
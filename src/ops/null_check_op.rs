@@ -0,0 +1,23 @@
+
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
+
+define_op! {
+    /**
+     * The null check instruction checks that a pointer is
+     * not null, and gives it back as its value.  It
+     * leaves the graph through a side exit when the
+     * pointer is null.  An elided check, proven never to
+     * fail, only gives back the pointer.
+     */
+    NullCheckOp: NullCheck { elided: bool }
+    operation {
+        fn effectful() -> bool { true }
+        fn out_type(&self) -> Option<IrTypeId> {
+            Some(IrTypeId::PtrInt)
+        }
+        fn num_operands(&self) -> u32 { 1 }
+        fn operand_type(&self, _idx: u32) -> IrInputTypeId {
+            IrInputTypeId::Specific(IrTypeId::PtrInt)
+        }
+    }
+}
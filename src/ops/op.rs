@@ -1,59 +1,96 @@
 
 use std::fmt;
 
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
 use crate::ops::{
-    Opcode, SpecializeOpcode, Operation,
-    NopOp, PhiOp,
-    ConstOp, CmpOp, BiniOp,
-    RetOp, BranchOp, JumpOp
+    Opcode, SpecializeOpcode, Operation, OpVisitor,
+    NopOp, PhiOp, ParamOp,
+    ConstOp, CmpOp, BiniOp, GuardOp,
+    RetOp, BranchOp, JumpOp, ExitOp, PatchPointOp, BoundsCheckOp,
+    NullCheckOp, LoadOp, StoreOp, PtrAddOp, CopyOp, CallOp,
+    POOLED_CONST_TAG
 };
 
 #[derive(Clone)]
 pub enum Op {
     Nop(NopOp),
     Phi(PhiOp),
+    Param(ParamOp),
     Const(ConstOp),
     Cmp(CmpOp),
     Bini(BiniOp),
+    Guard(GuardOp),
     Ret(RetOp),
     Branch(BranchOp),
-    Jump(JumpOp)
+    Jump(JumpOp),
+    Exit(ExitOp),
+    PatchPoint(PatchPointOp),
+    BoundsCheck(BoundsCheckOp),
+    NullCheck(NullCheckOp),
+    Load(LoadOp),
+    Store(StoreOp),
+    PtrAdd(PtrAddOp),
+    Copy(CopyOp),
+    Call(CallOp)
 }
 
 impl Op {
+    pub(crate) fn opcode(&self) -> Opcode {
+        self.accept(&mut OpcodeOf)
+    }
     pub(crate) fn terminal(&self) -> bool {
-        match self {
-          &Op::Nop(ref op) => false,
-          &Op::Phi(ref op) => false,
-          &Op::Const(ref op) => false,
-          &Op::Cmp(ref op) => false,
-          &Op::Bini(ref op) => false,
-          &Op::Ret(ref op) => true,
-          &Op::Branch(ref op) => true,
-          &Op::Jump(ref op) => true,
-        }
+        self.accept(&mut Terminal)
+    }
+    pub(crate) fn effectful(&self) -> bool {
+        self.accept(&mut Effectful)
     }
     pub(crate) fn num_inputs(&self) -> u32 {
+        self.accept(&mut NumOperands)
+    }
+    pub(crate) fn operand_type(&self, idx: u32) -> IrInputTypeId {
+        debug_assert!(idx < self.num_inputs());
+        self.accept(&mut OperandType(idx))
+    }
+    pub(crate) fn num_targets(&self) -> Option<u32> {
+        self.accept(&mut NumTargets)
+    }
+    pub(crate) fn out_type(&self) -> Option<IrTypeId> {
+        self.accept(&mut OutType)
+    }
+    pub(crate) fn write_to(&self, vec: &mut Vec<u8>) {
+        self.accept(&mut WriteTo(vec))
+    }
+    // Whether instructions with this op define a value
+    // that can be used as an operand.  End instructions
+    // never do.
+    pub(crate) fn defines_value(&self) -> bool {
+        !self.terminal() && self.out_type().is_some()
+    }
+    // The value of a constant that may be written to
+    // the stream's constant pool.
+    pub(crate) fn pool_value(&self) -> Option<u64> {
         match self {
-          &Op::Nop(ref op) => op.num_operands(),
-          &Op::Phi(ref op) => op.num_operands(),
-          &Op::Const(ref op) => op.num_operands(),
-          &Op::Cmp(ref op) => op.num_operands(),
-          &Op::Bini(ref op) => op.num_operands(),
-          &Op::Ret(ref op) => op.num_operands(),
-          &Op::Branch(ref op) => op.num_operands(),
-          &Op::Jump(ref op) => op.num_operands(),
+          Op::Const(op) => op.pool_value(),
+          _ => None
         }
     }
-    pub(crate) unsafe fn read_from(bytes: &[u8])
+
+    // Read an op, resolving pooled constants in `pool`.
+    pub(crate) unsafe fn read_from(bytes: &[u8], pool: &[u64])
       -> (usize, Op)
     {
         // Read an opcode.
-        debug_assert!(bytes.len() >= 1);
+        debug_assert!(!bytes.is_empty());
         let opcode =
           Opcode::from_u8(*bytes.get_unchecked(0));
 
         let rest = bytes.get_unchecked(1..);
+        if opcode == Opcode::Const
+          && *rest.get_unchecked(0) == POOLED_CONST_TAG
+        {
+            let (nb, op) = ConstOp::read_pooled(rest, pool);
+            return (1 + nb, Op::Const(op));
+        }
         let (nb, op) =
           opcode.specialize(ReadOperation(rest));
         (1 + nb, op)
@@ -79,15 +116,84 @@ impl fmt::Display for Op {
     fn fmt(&self, f: &mut fmt::Formatter)
       -> Result<(), fmt::Error>
     {
-        match self {
-          &Op::Nop(ref op) => op.fmt(f),
-          &Op::Phi(ref op) => op.fmt(f),
-          &Op::Const(ref op) => op.fmt(f),
-          &Op::Cmp(ref op) => op.fmt(f),
-          &Op::Bini(ref op) => op.fmt(f),
-          &Op::Ret(ref op) => op.fmt(f),
-          &Op::Branch(ref op) => op.fmt(f),
-          &Op::Jump(ref op) => op.fmt(f),
-        }
+        self.accept(&mut FmtOp(f))
+    }
+}
+
+// Helper visitors answering a query of any op.
+struct OpcodeOf;
+struct Terminal;
+struct Effectful;
+struct NumOperands;
+struct OperandType(u32);
+struct NumTargets;
+struct OutType;
+struct WriteTo<'a>(&'a mut Vec<u8>);
+struct FmtOp<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+impl OpVisitor for OpcodeOf {
+    type Output = Opcode;
+    fn visit_any<OP: Operation>(&mut self, _op: &OP) -> Opcode {
+        OP::opcode()
+    }
+}
+
+impl OpVisitor for Terminal {
+    type Output = bool;
+    fn visit_any<OP: Operation>(&mut self, _op: &OP) -> bool {
+        OP::terminal()
+    }
+}
+
+impl OpVisitor for Effectful {
+    type Output = bool;
+    fn visit_any<OP: Operation>(&mut self, _op: &OP) -> bool {
+        OP::effectful()
+    }
+}
+
+impl OpVisitor for NumOperands {
+    type Output = u32;
+    fn visit_any<OP: Operation>(&mut self, op: &OP) -> u32 {
+        op.num_operands()
+    }
+}
+
+impl OpVisitor for OperandType {
+    type Output = IrInputTypeId;
+    fn visit_any<OP: Operation>(&mut self, op: &OP) -> IrInputTypeId {
+        op.operand_type(self.0)
+    }
+}
+
+impl OpVisitor for NumTargets {
+    type Output = Option<u32>;
+    fn visit_any<OP: Operation>(&mut self, op: &OP) -> Option<u32> {
+        op.num_targets()
+    }
+}
+
+impl OpVisitor for OutType {
+    type Output = Option<IrTypeId>;
+    fn visit_any<OP: Operation>(&mut self, op: &OP)
+      -> Option<IrTypeId>
+    {
+        op.out_type()
+    }
+}
+
+impl<'a> OpVisitor for WriteTo<'a> {
+    type Output = ();
+    fn visit_any<OP: Operation>(&mut self, op: &OP) {
+        op.write_to(self.0)
+    }
+}
+
+impl<'a, 'b> OpVisitor for FmtOp<'a, 'b> {
+    type Output = Result<(), fmt::Error>;
+    fn visit_any<OP: Operation>(&mut self, op: &OP)
+      -> Result<(), fmt::Error>
+    {
+        fmt::Display::fmt(op, self.0)
     }
 }
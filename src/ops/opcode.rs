@@ -1,20 +1,20 @@
 
+use std::convert::TryFrom;
 use std::mem;
 
 use crate::ops;
 use crate::ops::Operation;
-use crate::ir_types::{ BoolTy, Int32Ty, Int64Ty, PtrIntTy };
 
 /**
  * An Opcode defines the kind of operation an
  * instruction performs (e.g. a call, add, load, etc.)
  */
-#[derive(Clone, Copy, Hash)]
+#[derive(Clone, Copy, Debug, Hash)]
 #[derive(PartialEq, Eq)]
 #[repr(u8)]
 pub enum Opcode {
     // Special instructions.
-    Nop = 1, Phi,
+    Nop = 1, Phi, Param,
 
     // Introduce const values of various types.
     Const,
@@ -26,8 +26,44 @@ pub enum Opcode {
     // (Add, Sub, Mul, And, Or, Xor)
     Bini,
 
+    // Checks that leave through a side exit.
+    Guard,
+
     // Terminal instructions.
-    Ret, Branch, Jump,
+    Ret, Branch, Jump, Exit,
+
+    // Space reserved for patching.
+    PatchPoint,
+
+    // Checks of an index against a length, and of a
+    // pointer against null, leaving through a side exit.
+    BoundsCheck, NullCheck,
+
+    // Memory accesses through a pointer.
+    Load, Store,
+
+    // The address of a member of an aggregate.
+    PtrAdd,
+
+    // A copy of a value.
+    Copy,
+
+    // A call of another graph.
+    Call,
+}
+
+impl TryFrom<u8> for Opcode {
+    type Error = u8;
+
+    // Checked decoding of an opcode byte, giving back
+    // the byte if it names no opcode.
+    fn try_from(byte: u8) -> Result<Opcode, u8> {
+        if Self::valid_u8(byte) {
+            Ok(unsafe { Self::from_u8(byte) })
+        } else {
+            Err(byte)
+        }
+    }
 }
 
 pub trait SpecializeOpcode<R> {
@@ -36,7 +72,95 @@ pub trait SpecializeOpcode<R> {
 
 impl Opcode {
     pub const MIN: Opcode = Opcode::Nop;
-    pub const MAX: Opcode = Opcode::Jump;
+    pub const MAX: Opcode = Opcode::Call;
+
+    // Every opcode, in encoding order.
+    pub const ALL: [Opcode; 19] = [
+        Opcode::Nop, Opcode::Phi, Opcode::Param, Opcode::Const,
+        Opcode::Cmp, Opcode::Bini, Opcode::Guard, Opcode::Ret,
+        Opcode::Branch, Opcode::Jump, Opcode::Exit,
+        Opcode::PatchPoint, Opcode::BoundsCheck, Opcode::NullCheck,
+        Opcode::Load, Opcode::Store, Opcode::PtrAdd, Opcode::Copy,
+        Opcode::Call
+    ];
+
+    /** The opcode's name, as the text and JSON forms use. */
+    pub const fn name(self) -> &'static str {
+        match self {
+          Opcode::Nop => "nop", Opcode::Phi => "phi",
+          Opcode::Param => "param", Opcode::Const => "const",
+          Opcode::Cmp => "cmp", Opcode::Bini => "bini",
+          Opcode::Guard => "guard", Opcode::Ret => "ret",
+          Opcode::Branch => "branch", Opcode::Jump => "jump",
+          Opcode::Exit => "exit", Opcode::PatchPoint => "patchpoint",
+          Opcode::BoundsCheck => "boundscheck",
+          Opcode::NullCheck => "nullcheck",
+          Opcode::Load => "load", Opcode::Store => "store",
+          Opcode::PtrAdd => "ptradd", Opcode::Copy => "copy",
+          Opcode::Call => "call"
+        }
+    }
+
+    // The opcode with the given name.
+    pub fn from_name(name: &str) -> Option<Opcode> {
+        Self::ALL.iter().cloned().find(|op| op.name() == name)
+    }
+
+    /**
+     * The number of operands every instruction with
+     * the opcode takes, or None for calls, which give
+     * their number of arguments in their payload.  Phi
+     * arguments are carried by targets, and are not
+     * operands.
+     */
+    pub const fn num_operands(self) -> Option<u32> {
+        match self {
+          Opcode::Cmp | Opcode::Bini | Opcode::BoundsCheck
+            | Opcode::Store | Opcode::PtrAdd => Some(2),
+          Opcode::Guard | Opcode::Ret | Opcode::Branch
+            | Opcode::NullCheck | Opcode::Load
+            | Opcode::Copy => Some(1),
+          Opcode::Nop | Opcode::Phi | Opcode::Param
+            | Opcode::Const | Opcode::Jump | Opcode::Exit
+            | Opcode::PatchPoint => Some(0),
+          Opcode::Call => None
+        }
+    }
+
+    // The number of targets of a terminal opcode, and
+    // None for the others.
+    pub const fn num_targets(self) -> Option<u32> {
+        match self {
+          Opcode::Branch => Some(2),
+          Opcode::Jump => Some(1),
+          Opcode::Ret | Opcode::Exit => Some(0),
+          _ => None
+        }
+    }
+
+    // Whether the opcode ends a block.
+    pub const fn is_terminal(self) -> bool {
+        self.num_targets().is_some()
+    }
+
+    // Whether the opcode has effects that must not be
+    // reordered with other effectful instructions.
+    pub const fn is_effectful(self) -> bool {
+        matches!(self, Opcode::Guard | Opcode::PatchPoint
+                         | Opcode::BoundsCheck | Opcode::NullCheck
+                         | Opcode::Load | Opcode::Store
+                         | Opcode::Call)
+    }
+
+    // Whether instructions with the opcode define a
+    // value.
+    pub const fn defines_value(self) -> bool {
+        matches!(self, Opcode::Phi | Opcode::Param | Opcode::Const
+                         | Opcode::Cmp | Opcode::Bini
+                         | Opcode::BoundsCheck | Opcode::NullCheck
+                         | Opcode::Load | Opcode::PtrAdd
+                         | Opcode::Copy | Opcode::Call)
+    }
 
     fn valid_u8(byte: u8) -> bool {
         (byte >= (Self::MIN as u8))
@@ -56,12 +180,23 @@ impl Opcode {
         match self {
           Opcode::Nop => spec.op::<ops::NopOp>(),
           Opcode::Phi => spec.op::<ops::PhiOp>(),
+          Opcode::Param => spec.op::<ops::ParamOp>(),
           Opcode::Const => spec.op::<ops::ConstOp>(),
           Opcode::Cmp => spec.op::<ops::CmpOp>(),
           Opcode::Bini => spec.op::<ops::BiniOp>(),
+          Opcode::Guard => spec.op::<ops::GuardOp>(),
           Opcode::Ret => spec.op::<ops::RetOp>(),
           Opcode::Branch => spec.op::<ops::BranchOp>(),
-          Opcode::Jump => spec.op::<ops::JumpOp>()
+          Opcode::Jump => spec.op::<ops::JumpOp>(),
+          Opcode::Exit => spec.op::<ops::ExitOp>(),
+          Opcode::PatchPoint => spec.op::<ops::PatchPointOp>(),
+          Opcode::BoundsCheck => spec.op::<ops::BoundsCheckOp>(),
+          Opcode::NullCheck => spec.op::<ops::NullCheckOp>(),
+          Opcode::Load => spec.op::<ops::LoadOp>(),
+          Opcode::Store => spec.op::<ops::StoreOp>(),
+          Opcode::PtrAdd => spec.op::<ops::PtrAddOp>(),
+          Opcode::Copy => spec.op::<ops::CopyOp>(),
+          Opcode::Call => spec.op::<ops::CallOp>()
         }
     }
 }
@@ -3,7 +3,8 @@ use std::fmt;
 
 use crate::ops::Opcode;
 use crate::ops::Op;
-use crate::ir_types::IrTypeId;
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
+use crate::decode::{ ByteReader, DecodeError };
 
 /**
  * An operation embodies the full notion of an
@@ -17,6 +18,11 @@ pub trait Operation: Sized + Clone + fmt::Display {
     /** Check if the operation is terminal. */
     fn terminal() -> bool { false }
 
+    /** Check if the operation has side effects that
+        must not be reordered with respect to other
+        effectful operations. */
+    fn effectful() -> bool { false }
+
     /** Get the op for this operation. */
     fn op(&self) -> Op;
 
@@ -26,13 +32,35 @@ pub trait Operation: Sized + Clone + fmt::Display {
     /** Get the number of expected operands. */
     fn num_operands(&self) -> u32;
 
+    /** Get the expected type of the operand at the
+        given index. */
+    fn operand_type(&self, _idx: u32) -> IrInputTypeId {
+        IrInputTypeId::Any
+    }
+
     /** The number of target blocks for this operation,
         only valid for a terminal operation. */
     fn num_targets(&self) -> Option<u32> { None }
 
+    /** Get the value the operation may instead write
+        to the stream's constant pool, if it is a
+        constant that can be pooled. */
+    fn pool_value(&self) -> Option<u64> { None }
+
     /** Write to a vec. */
     fn write_to(&self, vec: &mut Vec<u8>);
 
-    /** Read from some bytes, unchecked. */
+    /**
+     * Read from some bytes, unchecked.
+     *
+     * # Safety
+     * The bytes must start with a payload of this
+     * operation, as `write_to` writes it.
+     */
     unsafe fn read_from(bytes: &[u8]) -> (usize, Self);
+
+    /** Read from untrusted bytes, checking that they
+        are well formed. */
+    fn decode(reader: &mut ByteReader)
+      -> Result<Self, DecodeError>;
 }
@@ -0,0 +1,64 @@
+
+use std::fmt;
+
+use crate::ops::{ Opcode, Operation, Op };
+use crate::decode::{ ByteReader, DecodeError };
+use crate::ir_types::IrTypeId;
+use crate::leb128;
+
+/**
+ * Introduces an incoming parameter value.  Params
+ * are emitted at the head of the start block, after
+ * any phis.
+ */
+#[derive(Clone)]
+pub struct ParamOp { index: u32, tyid: IrTypeId }
+
+impl ParamOp {
+    pub(crate) fn new(index: u32, tyid: IrTypeId) -> ParamOp {
+        ParamOp { index, tyid }
+    }
+
+    pub fn index(&self) -> u32 { self.index }
+    pub fn tyid(&self) -> IrTypeId { self.tyid }
+}
+
+impl Operation for ParamOp {
+    fn opcode() -> Opcode { Opcode::Param }
+    fn op(&self) -> Op { Op::Param(self.clone()) }
+    fn out_type(&self) -> Option<IrTypeId> {
+        Some(self.tyid)
+    }
+    fn num_operands(&self) -> u32 { 0 }
+
+    fn write_to(&self, vec: &mut Vec<u8>) {
+        vec.push(self.tyid.into_u8());
+        leb128::write_leb128u(self.index, vec);
+    }
+
+    unsafe fn read_from(bytes: &[u8]) -> (usize, Self) {
+        debug_assert!(!bytes.is_empty());
+        let tyid =
+          IrTypeId::from_u8(*bytes.get_unchecked(0));
+        let rest = bytes.get_unchecked(1..);
+        let (nb, index) = leb128::read_leb128u(rest);
+        debug_assert!(index <= (u32::MAX as u64));
+        (1 + nb, ParamOp::new(index as u32, tyid))
+    }
+
+    fn decode(reader: &mut ByteReader)
+      -> Result<Self, DecodeError>
+    {
+        let tyid = reader.read_type_id() ?;
+        let index = reader.read_leb128u32() ?;
+        Ok(ParamOp::new(index, tyid))
+    }
+}
+
+impl fmt::Display for ParamOp {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "Param<{}>({})", self.tyid.as_str(), self.index)
+    }
+}
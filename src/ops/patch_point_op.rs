@@ -0,0 +1,75 @@
+
+use std::fmt;
+
+use crate::ops::{ Opcode, Operation, Op };
+use crate::decode::{ ByteReader, DecodeError };
+use crate::ir_types::IrTypeId;
+use crate::leb128;
+
+/**
+ * The patch point instruction reserves `size` bytes
+ * of the instruction stream, after its `id` and
+ * `size`, for a client to patch later, and has the
+ * same space reserved in the machine code it lowers
+ * to.  It does nothing until patched.
+ */
+#[derive(Clone)]
+pub struct PatchPointOp { id: u32, size: u32 }
+
+impl PatchPointOp {
+    pub(crate) fn new(id: u32, size: u32) -> PatchPointOp {
+        PatchPointOp { id, size }
+    }
+
+    pub fn id(&self) -> u32 { self.id }
+    pub fn size(&self) -> u32 { self.size }
+
+    // The offset of the reserved space from the start
+    // of the instruction, past its opcode, id and size.
+    pub(crate) fn space_offset(&self) -> usize {
+        1 + leb128::leb128u_len(self.id as u64)
+          + leb128::leb128u_len(self.size as u64)
+    }
+}
+
+impl Operation for PatchPointOp {
+    fn opcode() -> Opcode { Opcode::PatchPoint }
+    fn effectful() -> bool { true }
+    fn op(&self) -> Op { Op::PatchPoint(self.clone()) }
+    fn out_type(&self) -> Option<IrTypeId> { None }
+    fn num_operands(&self) -> u32 { 0 }
+
+    fn write_to(&self, vec: &mut Vec<u8>) {
+        leb128::write_leb128u(self.id, vec);
+        leb128::write_leb128u(self.size, vec);
+        vec.resize(vec.len() + self.size as usize, 0_u8);
+    }
+
+    unsafe fn read_from(bytes: &[u8]) -> (usize, Self) {
+        let (nb_id, id) = leb128::read_leb128u(bytes);
+        let (nb_size, size) =
+          leb128::read_leb128u(bytes.get_unchecked(nb_id ..));
+        debug_assert!(id <= (u32::MAX as u64));
+        debug_assert!(size <= (u32::MAX as u64));
+        let nb = nb_id + nb_size + size as usize;
+        debug_assert!(nb <= bytes.len());
+        (nb, PatchPointOp::new(id as u32, size as u32))
+    }
+
+    fn decode(reader: &mut ByteReader)
+      -> Result<Self, DecodeError>
+    {
+        let id = reader.read_leb128u32() ?;
+        let size = reader.read_leb128u32() ?;
+        reader.read_bytes(size as usize) ?;
+        Ok(PatchPointOp::new(id, size))
+    }
+}
+
+impl fmt::Display for PatchPointOp {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "PatchPoint({}, {})", self.id, self.size)
+    }
+}
@@ -1,9 +1,9 @@
 
 use std::fmt;
-use std::marker::PhantomData;
 
 use crate::ops::{ Opcode, Operation, Op };
-use crate::ir_types::{ IrType, IrTypeId };
+use crate::decode::{ ByteReader, DecodeError };
+use crate::ir_types::IrTypeId;
 
 /** Introduces a phi value. */
 #[derive(Clone)]
@@ -13,6 +13,8 @@ impl PhiOp {
     pub(crate) fn new(tyid: IrTypeId) -> PhiOp {
         PhiOp { tyid }
     }
+
+    pub fn tyid(&self) -> IrTypeId { self.tyid }
 }
 
 impl Operation for PhiOp {
@@ -28,11 +30,17 @@ impl Operation for PhiOp {
     }
 
     unsafe fn read_from(bytes: &[u8]) -> (usize, Self) {
-        debug_assert!(bytes.len() >= 1);
+        debug_assert!(!bytes.is_empty());
         let tyid =
           IrTypeId::from_u8(*bytes.get_unchecked(0));
         (1, PhiOp::new(tyid))
     }
+
+    fn decode(reader: &mut ByteReader)
+      -> Result<Self, DecodeError>
+    {
+        Ok(PhiOp::new(reader.read_type_id() ?))
+    }
 }
 
 impl fmt::Display for PhiOp {
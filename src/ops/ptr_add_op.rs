@@ -0,0 +1,32 @@
+
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
+
+define_op! {
+    /**
+     * The pointer add instruction computes the address of
+     * a member of an aggregate: `ptr + index * stride +
+     * offset`, for a pointer to an array of aggregates of
+     * descriptor `desc` of its module's type table, and
+     * the offset of its member `member`.  The offset and
+     * stride are those of the descriptor's layout, held
+     * in the instruction so that it lowers without the
+     * table, and checked against it by
+     * `TypeTable::check_graph`.  The index is not checked.
+     */
+    PtrAddOp: PtrAdd {
+        desc: u32, member: u32, offset: u32, stride: u32
+    }
+    operation {
+        fn out_type(&self) -> Option<IrTypeId> {
+            Some(IrTypeId::PtrInt)
+        }
+        fn num_operands(&self) -> u32 { 2 }
+        fn operand_type(&self, idx: u32) -> IrInputTypeId {
+            if idx == 0 {
+                IrInputTypeId::Specific(IrTypeId::PtrInt)
+            } else {
+                IrInputTypeId::Specific(IrTypeId::Int64)
+            }
+        }
+    }
+}
@@ -1,9 +1,9 @@
 
 use std::fmt;
-use std::marker::PhantomData;
 
 use crate::ops::{ Opcode, Operation, Op };
-use crate::ir_types::{ IrType, IrTypeId, VoidTy };
+use crate::decode::{ ByteReader, DecodeError };
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
 
 #[derive(Clone)]
 pub struct RetOp { tyid: IrTypeId }
@@ -12,6 +12,8 @@ impl RetOp {
     pub(crate) fn new(tyid: IrTypeId) -> RetOp {
         RetOp { tyid }
     }
+
+    pub fn tyid(&self) -> IrTypeId { self.tyid }
 }
 impl Operation for RetOp {
     fn opcode() -> Opcode { Opcode::Ret }
@@ -21,6 +23,9 @@ impl Operation for RetOp {
       Some(self.tyid)
     }
     fn num_operands(&self) -> u32 { 1 }
+    fn operand_type(&self, _idx: u32) -> IrInputTypeId {
+        IrInputTypeId::Specific(self.tyid)
+    }
     fn num_targets(&self) -> Option<u32> { Some(0) }
 
     fn write_to(&self, vec: &mut Vec<u8>) {
@@ -28,11 +33,17 @@ impl Operation for RetOp {
     }
 
     unsafe fn read_from(bytes: &[u8]) -> (usize, Self) {
-        debug_assert!(bytes.len() >= 1);
+        debug_assert!(!bytes.is_empty());
         let tyid =
           IrTypeId::from_u8(*bytes.get_unchecked(0));
         (1, RetOp::new(tyid))
     }
+
+    fn decode(reader: &mut ByteReader)
+      -> Result<Self, DecodeError>
+    {
+        Ok(RetOp::new(reader.read_type_id() ?))
+    }
 }
 
 impl fmt::Display for RetOp {
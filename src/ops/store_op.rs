@@ -0,0 +1,24 @@
+
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
+
+define_op! {
+    /**
+     * The store instruction writes its second operand,
+     * a value of the op's type, to the memory its first
+     * operand points to, in as many bytes as the type's
+     * `mem_size`.
+     */
+    StoreOp: Store { tyid: IrTypeId }
+    operation {
+        fn effectful() -> bool { true }
+        fn out_type(&self) -> Option<IrTypeId> { None }
+        fn num_operands(&self) -> u32 { 2 }
+        fn operand_type(&self, idx: u32) -> IrInputTypeId {
+            if idx == 0 {
+                IrInputTypeId::Specific(IrTypeId::PtrInt)
+            } else {
+                IrInputTypeId::Specific(self.tyid)
+            }
+        }
+    }
+}
@@ -0,0 +1,242 @@
+
+use crate::block::BlockId;
+use crate::graph::Graph;
+use crate::view::{ BlockView, InstrView };
+use crate::ops::{
+    Op, Operation,
+    NopOp, PhiOp, ParamOp,
+    ConstOp, CmpOp, BiniOp, GuardOp,
+    RetOp, BranchOp, JumpOp, ExitOp, PatchPointOp, BoundsCheckOp,
+    NullCheckOp, LoadOp, StoreOp, PtrAddOp, CopyOp, CallOp
+};
+
+/**
+ * A visitor over the variants of `Op`, with a method
+ * for each.  The methods a visitor does not override
+ * fall back to `visit_any`, generic over the variant's
+ * operation, so a visitor need only handle the ops it
+ * is interested in.
+ *
+ * The walkers `Graph::visit_block` and
+ * `Graph::visit_ops` drive a visitor over the
+ * instructions of a block, or of a whole graph.
+ */
+pub trait OpVisitor {
+    type Output;
+
+    /** Visit an op with no method of its own. */
+    fn visit_any<OP: Operation>(&mut self, op: &OP) -> Self::Output;
+
+    fn visit_nop(&mut self, op: &NopOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_phi(&mut self, op: &PhiOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_param(&mut self, op: &ParamOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_const(&mut self, op: &ConstOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_cmp(&mut self, op: &CmpOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_bini(&mut self, op: &BiniOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_guard(&mut self, op: &GuardOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_ret(&mut self, op: &RetOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_branch(&mut self, op: &BranchOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_jump(&mut self, op: &JumpOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_exit(&mut self, op: &ExitOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_patch_point(&mut self, op: &PatchPointOp)
+      -> Self::Output
+    {
+        self.visit_any(op)
+    }
+    fn visit_bounds_check(&mut self, op: &BoundsCheckOp)
+      -> Self::Output
+    {
+        self.visit_any(op)
+    }
+    fn visit_null_check(&mut self, op: &NullCheckOp)
+      -> Self::Output
+    {
+        self.visit_any(op)
+    }
+    fn visit_load(&mut self, op: &LoadOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_store(&mut self, op: &StoreOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_ptr_add(&mut self, op: &PtrAddOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_copy(&mut self, op: &CopyOp) -> Self::Output {
+        self.visit_any(op)
+    }
+    fn visit_call(&mut self, op: &CallOp) -> Self::Output {
+        self.visit_any(op)
+    }
+
+    /** Called by the walkers before visiting the op of
+        each instruction, and each block's instructions. */
+    fn enter_instr(&mut self, _instr: &InstrView) {}
+    fn enter_block(&mut self, _block: &BlockView) {}
+}
+
+impl Op {
+    // Call the visitor's method for this op's variant.
+    pub(crate) fn accept<V: OpVisitor>(&self, visitor: &mut V)
+      -> V::Output
+    {
+        match self {
+          Op::Nop(op) => visitor.visit_nop(op),
+          Op::Phi(op) => visitor.visit_phi(op),
+          Op::Param(op) => visitor.visit_param(op),
+          Op::Const(op) => visitor.visit_const(op),
+          Op::Cmp(op) => visitor.visit_cmp(op),
+          Op::Bini(op) => visitor.visit_bini(op),
+          Op::Guard(op) => visitor.visit_guard(op),
+          Op::Ret(op) => visitor.visit_ret(op),
+          Op::Branch(op) => visitor.visit_branch(op),
+          Op::Jump(op) => visitor.visit_jump(op),
+          Op::Exit(op) => visitor.visit_exit(op),
+          Op::PatchPoint(op) => visitor.visit_patch_point(op),
+          Op::BoundsCheck(op) => visitor.visit_bounds_check(op),
+          Op::NullCheck(op) => visitor.visit_null_check(op),
+          Op::Load(op) => visitor.visit_load(op),
+          Op::Store(op) => visitor.visit_store(op),
+          Op::PtrAdd(op) => visitor.visit_ptr_add(op),
+          Op::Copy(op) => visitor.visit_copy(op),
+          Op::Call(op) => visitor.visit_call(op)
+        }
+    }
+}
+
+fn visit_view<V>(block: &BlockView, visitor: &mut V)
+  where V: OpVisitor<Output = ()>
+{
+    visitor.enter_block(block);
+    for instr in block.instrs() {
+        visitor.enter_instr(&instr);
+        instr.op().accept(visitor);
+    }
+}
+
+impl Graph {
+    /**
+     * Visit the ops of a block's instructions in order,
+     * through to its end instruction.  Panics if the
+     * block is out of range.
+     */
+    pub fn visit_block<V>(&self, id: BlockId, visitor: &mut V)
+      where V: OpVisitor<Output = ()>
+    {
+        let block = self.block_view(id).expect("No such block.");
+        visit_view(&block, visitor);
+    }
+
+    /** Visit the ops of every block, in RPO. */
+    pub fn visit_ops<V>(&self, visitor: &mut V)
+      where V: OpVisitor<Output = ()>
+    {
+        for block in self.rpo_block_views() {
+            visit_view(&block, visitor);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{
+        Graph, BlockView, InstrView, Opcode, Operation, OpVisitor,
+        BiniOp, BiniKind
+    };
+
+    // Counts the blocks and instructions entered, the
+    // ops of each opcode, and the additions.
+    #[derive(Default)]
+    struct Counter {
+        blocks: u32,
+        instrs: u32,
+        opcodes: Vec<Opcode>,
+        adds: u32
+    }
+
+    impl OpVisitor for Counter {
+        type Output = ();
+
+        fn visit_any<OP: Operation>(&mut self, _op: &OP) {
+            self.opcodes.push(OP::opcode());
+        }
+        fn visit_bini(&mut self, op: &BiniOp) {
+            if op.kind() == BiniKind::Add {
+                self.adds += 1;
+            }
+            self.visit_any(op);
+        }
+        fn enter_instr(&mut self, _instr: &InstrView) {
+            self.instrs += 1;
+        }
+        fn enter_block(&mut self, _block: &BlockView) {
+            self.blocks += 1;
+        }
+    }
+
+    const IR: &str = "\
+block0:
+  %0 = const.i32 0
+  %1 = const.i32 10
+  jump block1(%0)
+loop block1(%2: i32) {
+  %3 = lt.i32 %2, %1
+  branch %3, block2, block3
+  block2:
+    %4 = const.i32 1
+    %5 = add.i32 %2, %4
+    jump block1(%5)
+}
+block3:
+  ret.i32 %2
+";
+
+    #[test]
+    fn visits_every_op_in_rpo() {
+        let graph = Graph::parse_ir(IR).unwrap();
+        let mut counter = Counter::default();
+        graph.visit_ops(&mut counter);
+        assert_eq!(counter.blocks, 4);
+        assert_eq!(counter.instrs, 10);
+        assert_eq!(counter.adds, 1);
+        assert_eq!(counter.opcodes, vec![
+            Opcode::Const, Opcode::Const, Opcode::Jump,
+            Opcode::Phi, Opcode::Cmp, Opcode::Branch,
+            Opcode::Const, Opcode::Bini, Opcode::Jump,
+            Opcode::Ret
+        ]);
+    }
+
+    #[test]
+    fn visits_one_block() {
+        let graph = Graph::parse_ir(IR).unwrap();
+        let block = graph.rpo_block_views().nth(2).unwrap().id();
+        let mut counter = Counter::default();
+        graph.visit_block(block, &mut counter);
+        assert_eq!(counter.blocks, 1);
+        assert_eq!(counter.opcodes,
+                   vec![Opcode::Const, Opcode::Bini, Opcode::Jump]);
+    }
+}
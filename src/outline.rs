@@ -0,0 +1,386 @@
+use std::collections::{ HashMap, HashSet };
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::{
+    Opcode, Operation, ParamOp, JumpOp, RetOp, ConstOp, CallOp
+};
+use crate::ir_types::IrTypeId;
+use crate::text::{ Rebuild, ParsedBlock, ParsedInstr, BlockKind };
+
+/*
+ * Outlining.  A region of blocks entered only at its
+ * entry block, and left only to one block outside it,
+ * is moved into a graph of its own, and the entry is
+ * left holding a call of that graph and a jump to the
+ * block the region left to.
+ *
+ * The new graph's params are the arguments of the
+ * entry's phis, then the values from outside the
+ * region that it uses, in the order of their first
+ * use.  Its start block takes the params and jumps to
+ * the entry.  Each edge leaving the region goes to a
+ * new return block instead, passing the values that
+ * flow out of the region: the arguments of the phis of
+ * the block it left to, and any value of the region
+ * used outside it.  As a graph returns one value, at
+ * most one may flow out, and the call defines it in
+ * the caller.  A region with none returns a `false`.
+ *
+ * A side exit or return would leave the caller's
+ * frame, and a snapshot describes it, so a region
+ * holding either cannot be outlined.  Nor can a
+ * region holding a loop header without all the blocks
+ * within its loop.
+ *
+ * Both graphs are rebuilt, so the ids of the caller's
+ * instructions and blocks change.
+ */
+
+/** Why a region of blocks could not be outlined. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum OutlineError {
+    // The region is entered at a block other than its
+    // entry, by an edge or as a start block.
+    SecondEntry { block: BlockId },
+
+    // The region holds a loop header but not all the
+    // blocks of its loop.
+    SplitLoop { header: BlockId },
+
+    // The region leaves to a number of blocks other
+    // than one.
+    Exits { count: u32 },
+
+    // An instruction of the region returns, exits, or
+    // has a snapshot of the caller's frame.
+    LeavesFrame { instr: InstrId },
+
+    // More than one value flows out of the region.
+    Results { count: u32 }
+}
+
+impl fmt::Display for OutlineError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match *self {
+          OutlineError::SecondEntry { block } =>
+            write!(f, "region is also entered at {}", block),
+          OutlineError::SplitLoop { header } =>
+            write!(f, "region splits the loop of {}", header),
+          OutlineError::Exits { count } =>
+            write!(f, "region leaves to {} blocks, not one", count),
+          OutlineError::LeavesFrame { instr } =>
+            write!(f, "{} leaves the frame of the region", instr),
+          OutlineError::Results { count } =>
+            write!(f, "{} values flow out of the region", count)
+        }
+    }
+}
+
+impl Error for OutlineError {}
+
+// Call `f` on every block, in text order.
+fn walk<'a>(level: &'a [ParsedBlock],
+            f: &mut dyn FnMut(&'a ParsedBlock))
+{
+    for block in level {
+        f(block);
+        walk(&block.body, f);
+    }
+}
+
+fn all_within(level: &[ParsedBlock], names: &HashSet<String>)
+  -> bool
+{
+    level.iter().all(|b| {
+        names.contains(&b.name) && all_within(&b.body, names)
+    })
+}
+
+// The values an instruction uses, by operands, edges
+// and its snapshot.
+fn used_values(instr: &ParsedInstr) -> impl Iterator<Item=&String> {
+    let snapshot = instr.snapshot.iter()
+                        .flat_map(|s| s.entries.iter().map(|e| &e.1));
+    instr.operands.iter()
+         .chain(instr.targets.iter().flat_map(|t| t.1.iter()))
+         .chain(snapshot)
+}
+
+// Take the blocks of the region out of a level, in
+// text order, leaving the entry in place holding
+// `call`.
+fn take_region(level: &mut Vec<ParsedBlock>, names: &HashSet<String>,
+               entry: &str, call: &mut Vec<ParsedInstr>,
+               taken: &mut Vec<ParsedBlock>)
+{
+    let mut i = 0;
+    while i < level.len() {
+        let block = &mut level[i];
+        if block.name == entry {
+            let kind = match block.kind {
+              BlockKind::Start => BlockKind::Start,
+              _ => BlockKind::Plain
+            };
+            let caller = ParsedBlock {
+                line: block.line,
+                name: block.name.clone(),
+                kind,
+                cold: block.cold,
+                params: block.params.clone(),
+                instrs: mem::take(call),
+                body: Vec::new()
+            };
+            taken.push(mem::replace(block, caller));
+            i += 1;
+        } else if names.contains(&block.name) {
+            taken.push(level.remove(i));
+        } else {
+            take_region(&mut block.body, names, entry, call, taken);
+            i += 1;
+        }
+    }
+}
+
+impl Graph {
+    /**
+     * Outline the region of `blocks` and `entry` into a
+     * new graph, to be the graph at index `callee` of
+     * the module, and return it.  The region is left
+     * as a call of the new graph at its entry.  This
+     * graph is rebuilt, so the ids of its instructions
+     * and blocks change, and its profile, instruction
+     * index and patched patch point bytes are dropped.
+     */
+    pub fn outline(&mut self, entry: BlockId, blocks: &[BlockId],
+                   callee: u32)
+      -> Result<Graph, OutlineError>
+    {
+        let mut region: HashSet<BlockId> =
+          blocks.iter().cloned().collect();
+        region.insert(entry);
+
+        let start = self.start_block_id();
+        let mut within = Vec::new();
+        let mut exits = HashSet::new();
+        for view in self.rpo_block_views() {
+            let id = view.id();
+            let inside = region.contains(&id);
+            if inside && id != entry
+               && (id == start || view.is_start())
+            {
+                return Err(OutlineError::SecondEntry { block: id });
+            }
+            for succ in view.succs() {
+                match (inside, region.contains(&succ)) {
+                  (false, true) if succ != entry => {
+                    return Err(OutlineError::SecondEntry {
+                        block: succ
+                    });
+                  }
+                  (true, false) => { exits.insert(succ); }
+                  _ => {}
+                }
+            }
+            if !inside {
+                continue;
+            }
+            within.push(id);
+            for instr in view.instrs() {
+                if matches!(instr.opcode(), Opcode::Ret | Opcode::Exit)
+                   || self.snapshot(instr.id()).is_some()
+                {
+                    return Err(OutlineError::LeavesFrame {
+                        instr: instr.id()
+                    });
+                }
+            }
+        }
+        if exits.len() != 1 {
+            return Err(OutlineError::Exits {
+                count: exits.len() as u32
+            });
+        }
+        let exit = exits.into_iter().next().unwrap();
+
+        let mut rb = Rebuild::new(self);
+        let mut types = HashMap::new();
+        for view in self.rpo_block_views() {
+            for info in self.block_instrs(self.block(view.id())) {
+                if info.op().defines_value() {
+                    let ty = info.op().out_type().unwrap();
+                    types.insert(rb.value(info.defn().instr_id()), ty);
+                }
+            }
+        }
+        let ids: HashMap<String, BlockId> =
+          within.iter().map(|&b| (rb.block_name(b), b)).collect();
+        let names: HashSet<String> = ids.keys().cloned().collect();
+        let entry_name = rb.block_name(entry);
+        let exit_name = rb.block_name(exit);
+
+        // The values of the region, the values it uses
+        // from outside, and those it defines that are
+        // used outside.
+        let mut split = None;
+        let mut defined = HashSet::new();
+        let mut inputs: Vec<String> = Vec::new();
+        let mut results: Vec<String> = Vec::new();
+        let mut entry_params = Vec::new();
+        let mut exit_params = Vec::new();
+        walk(rb.blocks(), &mut |block| {
+            if block.name == entry_name {
+                entry_params = block.params.clone();
+            }
+            if block.name == exit_name {
+                exit_params = block.params.clone();
+            }
+            if !names.contains(&block.name) {
+                return;
+            }
+            if block.kind == BlockKind::Loop
+               && !all_within(&block.body, &names)
+            {
+                split.get_or_insert(ids[&block.name]);
+            }
+            for (name, ty) in &block.params {
+                defined.insert(name.clone());
+                types.insert(name.clone(), *ty);
+            }
+            for instr in &block.instrs {
+                defined.extend(instr.def.iter().cloned());
+            }
+        });
+        if let Some(header) = split {
+            return Err(OutlineError::SplitLoop { header });
+        }
+        walk(rb.blocks(), &mut |block| {
+            let inside = names.contains(&block.name);
+            for v in block.instrs.iter().flat_map(used_values) {
+                let list = match (inside, defined.contains(v)) {
+                  (true, false) => &mut inputs,
+                  (false, true) => &mut results,
+                  _ => continue
+                };
+                if !list.contains(v) {
+                    list.push(v.clone());
+                }
+            }
+        });
+        // The entry's phis stay with the call.
+        results.retain(|v| !entry_params.iter().any(|p| &p.0 == v));
+        let count = (exit_params.len() + results.len()) as u32;
+        if count > 1 {
+            return Err(OutlineError::Results { count });
+        }
+
+        // The call, defining the value that flows out
+        // of the region in its place.
+        let (def, ty, exit_args) = if let Some(v) = results.first() {
+            (v.clone(), types[v], Vec::new())
+        } else if let Some(&(_, ty)) = exit_params.first() {
+            let def = rb.fresh_name();
+            (def.clone(), ty, vec![def])
+        } else {
+            (rb.fresh_name(), IrTypeId::Bool, Vec::new())
+        };
+        let args: Vec<String> =
+          entry_params.iter().map(|p| p.0.clone())
+                      .chain(inputs.iter().cloned()).collect();
+        let line = 0;
+        let mut call = vec![
+            ParsedInstr::new(
+              line, Some(def),
+              CallOp::new(ty, callee, args.len() as u32).op(),
+              args, Vec::new()),
+            ParsedInstr::new(
+              line, None, JumpOp::new().op(), Vec::new(),
+              vec![(exit_name.clone(), exit_args)])
+        ];
+        let mut taken = Vec::new();
+        take_region(rb.blocks_mut(), &names, &entry_name, &mut call,
+                    &mut taken);
+
+        // The callee's start block, taking the params.
+        let mut start_instrs = Vec::new();
+        let mut entry_args = Vec::new();
+        for (i, &(_, ty)) in entry_params.iter().enumerate() {
+            let name = rb.fresh_name();
+            start_instrs.push(ParsedInstr::new(
+              line, Some(name.clone()), ParamOp::new(i as u32, ty).op(),
+              Vec::new(), Vec::new()));
+            entry_args.push(name);
+        }
+        for (i, v) in inputs.iter().enumerate() {
+            let index = (entry_params.len() + i) as u32;
+            start_instrs.push(ParsedInstr::new(
+              line, Some(v.clone()), ParamOp::new(index, types[v]).op(),
+              Vec::new(), Vec::new()));
+        }
+        start_instrs.push(ParsedInstr::new(
+          line, None, JumpOp::new().op(), Vec::new(),
+          vec![(entry_name, entry_args)]));
+
+        // The callee's return block, taking the values
+        // that flow out.
+        let ret_name = rb.fresh_name();
+        let ret_params: Vec<(String, IrTypeId)> =
+          exit_params.iter().map(|p| p.1)
+                     .chain(results.iter().map(|v| types[v]))
+                     .map(|ty| (rb.fresh_name(), ty)).collect();
+        let mut ret_instrs = Vec::new();
+        let ret_value = match ret_params.first() {
+          Some((name, _)) => name.clone(),
+          None => {
+            let name = rb.fresh_name();
+            ret_instrs.push(ParsedInstr::new(
+              line, Some(name.clone()), ConstOp::new_bool(false).op(),
+              Vec::new(), Vec::new()));
+            name
+          }
+        };
+        ret_instrs.push(ParsedInstr::new(
+          line, None, RetOp::new(ty).op(), vec![ret_value], Vec::new()));
+
+        fn retarget(level: &mut [ParsedBlock], exit: &str, ret: &str,
+                    results: &[String])
+        {
+            for block in level {
+                let end = block.instrs.last_mut().unwrap();
+                for (target, args) in &mut end.targets {
+                    if target == exit {
+                        *target = ret.to_string();
+                        args.extend(results.iter().cloned());
+                    }
+                }
+                retarget(&mut block.body, exit, ret, results);
+            }
+        }
+        retarget(&mut taken, &exit_name, &ret_name, &results);
+        if taken[0].kind == BlockKind::Start {
+            taken[0].kind = BlockKind::Plain;
+        }
+
+        let mut callee_blocks = vec![ParsedBlock {
+            line, name: rb.fresh_name(), kind: BlockKind::Plain,
+            cold: false, params: Vec::new(), instrs: start_instrs,
+            body: Vec::new()
+        }];
+        callee_blocks.append(&mut taken);
+        callee_blocks.push(ParsedBlock {
+            line, name: ret_name, kind: BlockKind::Plain, cold: false,
+            params: ret_params, instrs: ret_instrs, body: Vec::new()
+        });
+        let outlined =
+          Rebuild::from_blocks(callee_blocks, self).finish();
+        self.rebuilt(rb.finish());
+        Ok(outlined)
+    }
+}
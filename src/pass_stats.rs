@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::fmt::{ self, Write };
+use std::sync::atomic::{ AtomicU32, AtomicU64, Ordering };
+use std::time::{ Duration, Instant };
+
+use crate::ops::Opcode;
+use crate::graph::Graph;
+use crate::text::json_string;
+
+/*
+ * Pass statistics.  The crate has no pass manager: a
+ * pipeline is the passes its user calls on a graph, one
+ * after another.  A `PassReport` runs each of them,
+ * recording how long it took, the instructions it
+ * removed and added, and the analyses it computed,
+ * so that a pipeline can be profiled pass by pass.
+ *
+ * Instructions are counted by opcode, before and after
+ * the pass: a pass that turns an add into a constant
+ * removes one instruction and adds another.  Analyses
+ * are counted by the graph as its caches fill, and a
+ * rebuilt graph keeps the counts of the one it
+ * replaces.  A pass may instead assign the graph a new
+ * one, counted from zero, whose counts are reported
+ * as they are.
+ */
+
+/** How many times each cached analysis of a graph was computed. */
+#[derive(Clone, Copy, Debug, Default)]
+#[derive(PartialEq, Eq)]
+pub struct AnalysisCounts {
+    def_uses: u32,
+    cfg: u32,
+    dominators: u32,
+    loops: u32,
+    liveness: u32
+}
+
+impl AnalysisCounts {
+    pub fn def_uses(&self) -> u32 { self.def_uses }
+    pub fn cfg(&self) -> u32 { self.cfg }
+    pub fn dominators(&self) -> u32 { self.dominators }
+    pub fn loops(&self) -> u32 { self.loops }
+    pub fn liveness(&self) -> u32 { self.liveness }
+
+    pub fn total(&self) -> u32 {
+        self.def_uses + self.cfg + self.dominators + self.loops
+          + self.liveness
+    }
+
+    // The counts since `earlier`, taken of the same
+    // counters.
+    fn since(&self, earlier: &AnalysisCounts) -> AnalysisCounts {
+        AnalysisCounts {
+            def_uses: self.def_uses - earlier.def_uses,
+            cfg: self.cfg - earlier.cfg,
+            dominators: self.dominators - earlier.dominators,
+            loops: self.loops - earlier.loops,
+            liveness: self.liveness - earlier.liveness
+        }
+    }
+}
+
+// A cached analysis, as counted.
+#[derive(Clone, Copy)]
+pub(crate) enum Analysis { DefUses, Cfg, Dominators, Loops, Liveness }
+
+// The counts of a graph, bumped through a shared
+// reference as its caches fill.  Each set of counters
+// has its own id, which a rebuilt graph keeps with
+// them, so a graph put in place of another is told
+// apart.
+pub(crate) struct AnalysisCounters {
+    id: u64,
+    counts: [AtomicU32; 5]
+}
+
+static NEXT_COUNTERS_ID: AtomicU64 = AtomicU64::new(0);
+
+impl Default for AnalysisCounters {
+    fn default() -> AnalysisCounters {
+        let id = NEXT_COUNTERS_ID.fetch_add(1, Ordering::Relaxed);
+        AnalysisCounters { id, counts: Default::default() }
+    }
+}
+
+impl AnalysisCounters {
+    pub(crate) fn id(&self) -> u64 { self.id }
+
+    pub(crate) fn computed(&self, analysis: Analysis) {
+        self.counts[analysis as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn counts(&self) -> AnalysisCounts {
+        let count = |a: Analysis| {
+            self.counts[a as usize].load(Ordering::Relaxed)
+        };
+        AnalysisCounts {
+            def_uses: count(Analysis::DefUses),
+            cfg: count(Analysis::Cfg),
+            dominators: count(Analysis::Dominators),
+            loops: count(Analysis::Loops),
+            liveness: count(Analysis::Liveness)
+        }
+    }
+}
+
+/** What one pass of a `PassReport` did. */
+#[derive(Clone, Debug)]
+pub struct PassStats {
+    name: String,
+
+    // When the pass started, from the start of the
+    // report, and how long it ran.
+    start: Duration,
+    time: Duration,
+
+    instrs_before: u32,
+    instrs_after: u32,
+    removed: u32,
+    added: u32,
+
+    analyses: AnalysisCounts
+}
+
+impl PassStats {
+    pub fn name(&self) -> &str { &self.name }
+    pub fn start(&self) -> Duration { self.start }
+    pub fn time(&self) -> Duration { self.time }
+
+    // The instructions of the graph before and after the
+    // pass, and those it removed and added, by opcode.
+    pub fn instrs_before(&self) -> u32 { self.instrs_before }
+    pub fn instrs_after(&self) -> u32 { self.instrs_after }
+    pub fn removed(&self) -> u32 { self.removed }
+    pub fn added(&self) -> u32 { self.added }
+
+    // The analyses the pass computed, or recomputed
+    // after its edits dropped them.
+    pub fn analyses(&self) -> AnalysisCounts { self.analyses }
+}
+
+/**
+ * Statistics on the passes run over graphs, in the
+ * order they ran.  The report prints as a table, and
+ * exports as a Chrome trace for `chrome://tracing` or
+ * Perfetto.
+ */
+pub struct PassReport {
+    start: Instant,
+    passes: Vec<PassStats>
+}
+
+impl Default for PassReport {
+    fn default() -> PassReport { PassReport::new() }
+}
+
+// The instructions of a graph by opcode.
+fn opcode_counts(graph: &Graph) -> HashMap<Opcode, u32> {
+    let mut counts = HashMap::new();
+    for instr in graph.decoded_instrs() {
+        *counts.entry(instr.opcode()).or_insert(0) += 1;
+    }
+    counts
+}
+
+impl PassReport {
+    pub fn new() -> PassReport {
+        PassReport { start: Instant::now(), passes: Vec::new() }
+    }
+
+    pub fn passes(&self) -> &[PassStats] { &self.passes }
+
+    pub fn total_time(&self) -> Duration {
+        self.passes.iter().map(|p| p.time).sum()
+    }
+
+    /**
+     * Run a pass over a graph, recording its statistics
+     * under a name, and return what it returns.  Only
+     * the pass is timed, not the counting around it.
+     */
+    pub fn run<R, F>(&mut self, graph: &mut Graph, name: &str, pass: F)
+      -> R
+      where F: FnOnce(&mut Graph) -> R
+    {
+        let before = opcode_counts(graph);
+        let counters = graph.analysis_counters().id();
+        let analyses = graph.analysis_counts();
+
+        let start = Instant::now();
+        let result = pass(graph);
+        let time = start.elapsed();
+
+        // A graph the pass put in its place was counted
+        // from zero.
+        let analyses = if graph.analysis_counters().id() == counters {
+            graph.analysis_counts().since(&analyses)
+        } else {
+            graph.analysis_counts()
+        };
+        let after = opcode_counts(graph);
+        let (mut removed, mut added) = (0, 0);
+        for (opcode, &n) in &before {
+            let m = after.get(opcode).copied().unwrap_or(0);
+            removed += n.saturating_sub(m);
+        }
+        for (opcode, &m) in &after {
+            let n = before.get(opcode).copied().unwrap_or(0);
+            added += m.saturating_sub(n);
+        }
+        self.passes.push(PassStats {
+            name: name.to_string(),
+            start: start - self.start, time,
+            instrs_before: before.values().sum(),
+            instrs_after: after.values().sum(),
+            removed, added, analyses
+        });
+        result
+    }
+
+    /**
+     * Export the passes as Chrome trace events, one
+     * complete event per pass, with times in
+     * microseconds and the counts as its arguments.
+     */
+    pub fn to_chrome_trace(&self) -> String {
+        let mut out = String::from("{ \"traceEvents\": [");
+        for (i, pass) in self.passes.iter().enumerate() {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            out.push_str("  { \"name\": ");
+            json_string(&pass.name, &mut out);
+            write!(out,
+                   ", \"cat\": \"pass\", \"ph\": \"X\", \"pid\": 0, \
+                   \"tid\": 0,\n\
+                   \x20   \"ts\": {}, \"dur\": {},\n\
+                   \x20   \"args\": {{ \"instrs_before\": {}, \
+                   \"instrs_after\": {},\n\
+                   \x20     \"removed\": {}, \"added\": {}, \
+                   \"analyses\": {} }} }}",
+                   pass.start.as_micros(), pass.time.as_micros(),
+                   pass.instrs_before, pass.instrs_after,
+                   pass.removed, pass.added,
+                   pass.analyses.total()).unwrap();
+        }
+        out.push_str("\n] }\n");
+        out
+    }
+}
+
+impl fmt::Display for PassReport {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        writeln!(f, "{:<24} {:>12} {:>8} {:>8} {:>8} {:>9}",
+                 "pass", "time", "instrs", "removed", "added",
+                 "analyses") ?;
+        for pass in &self.passes {
+            writeln!(f, "{:<24} {:>12} {:>8} {:>8} {:>8} {:>9}",
+                     pass.name, format!("{:?}", pass.time),
+                     pass.instrs_after, pass.removed, pass.added,
+                     pass.analyses.total()) ?;
+        }
+        writeln!(f, "{:<24} {:>12}", "total",
+                 format!("{:?}", self.total_time()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::Graph;
+    use super::PassReport;
+
+    const IR: &str = "\
+block0:
+  %0 = const.i32 0
+  %1 = const.i32 10
+  jump block1(%0)
+loop block1(%2: i32) {
+  %3 = lt.i32 %2, %1
+  branch %3, block2, block3
+  block2:
+    %4 = const.i32 1
+    %5 = add.i32 %2, %4
+    jump block1(%5)
+}
+block3:
+  ret.i32 %2
+";
+
+    #[test]
+    fn counts_analyses_of_a_replaced_graph() {
+        let mut graph = Graph::parse_ir(IR).unwrap();
+        graph.dominators();
+        graph.liveness();
+        let mut report = PassReport::new();
+        report.run(&mut graph, "reparse", |g| {
+            *g = Graph::parse_ir(&g.print_ir()).unwrap();
+            g.cfg();
+        });
+        let analyses = report.passes()[0].analyses();
+        assert_eq!(analyses.cfg(), 1);
+        assert_eq!(analyses.dominators(), 0);
+        assert_eq!(analyses.liveness(), 0);
+        assert_eq!(analyses.total(), 1);
+    }
+
+    #[test]
+    fn counts_analyses_since_the_pass_started() {
+        let mut graph = Graph::parse_ir(IR).unwrap();
+        graph.cfg();
+        let mut report = PassReport::new();
+        report.run(&mut graph, "dominators", |g| { g.dominators(); });
+        let analyses = report.passes()[0].analyses();
+        assert_eq!(analyses.cfg(), 0);
+        assert_eq!(analyses.dominators(), 1);
+    }
+}
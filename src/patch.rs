@@ -0,0 +1,133 @@
+
+use std::error::Error;
+use std::fmt;
+
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::changes::Change;
+use crate::ops::PatchPointOp;
+
+/**
+ * A relocation record for a patch point: the `size`
+ * bytes reserved at `offset` by the patch point
+ * instruction `instr`, whose client-chosen id is `id`.
+ * The offset is into the bytes the record was made
+ * for: the graph's instruction stream, or the machine
+ * code it was compiled to.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct PatchPoint {
+    id: u32,
+    instr: InstrId,
+    offset: usize,
+    size: u32
+}
+
+impl PatchPoint {
+    pub(crate) fn new(id: u32, instr: InstrId, offset: usize,
+                      size: u32)
+      -> PatchPoint
+    {
+        PatchPoint { id, instr, offset, size }
+    }
+
+    // The record for a patch point instruction in the
+    // instruction stream.
+    pub(crate) fn in_stream(op: &PatchPointOp, instr: InstrId)
+      -> PatchPoint
+    {
+        let offset = instr.offset() + op.space_offset();
+        PatchPoint::new(op.id(), instr, offset, op.size())
+    }
+
+    pub fn id(&self) -> u32 { self.id }
+    pub fn instr(&self) -> InstrId { self.instr }
+    pub fn offset(&self) -> usize { self.offset }
+    pub fn size(&self) -> u32 { self.size }
+}
+
+/** An error patching the space of a patch point. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum PatchError {
+    // No patch point has the id.
+    NoSuchPoint { id: u32 },
+
+    // The patch is longer than the space reserved.
+    TooLarge { id: u32, size: u32, len: usize }
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match *self {
+          PatchError::NoSuchPoint { id } =>
+            write!(f, "no patch point with id {}", id),
+          PatchError::TooLarge { id, size, len } =>
+            write!(f, "patch of {} bytes over the {} reserved \
+                       by patch point {}", len, size, id)
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+// The patch points with the given id, when a patch of
+// `len` bytes fits each of them.
+pub(crate) fn points_to_patch(points: &[PatchPoint], id: u32,
+                              len: usize)
+  -> Result<Vec<PatchPoint>, PatchError>
+{
+    let found: Vec<PatchPoint> =
+      points.iter().filter(|p| p.id == id).cloned().collect();
+    if found.is_empty() {
+        return Err(PatchError::NoSuchPoint { id });
+    }
+    for p in &found {
+        if len > p.size as usize {
+            return Err(PatchError::TooLarge { id, size: p.size, len });
+        }
+    }
+    Ok(found)
+}
+
+impl Graph {
+    /**
+     * The patch points of the graph, in stream order,
+     * with the space each reserves in the instruction
+     * stream.
+     */
+    pub fn patch_points(&self) -> &[PatchPoint] {
+        self.instr_store().patch_points()
+    }
+
+    /**
+     * The bytes in the space of the first patch point
+     * with the given id, as last patched.
+     */
+    pub fn patch_space(&self, id: u32) -> Option<&[u8]> {
+        let point = self.patch_points().iter().find(|p| p.id == id) ?;
+        Some(self.instr_store().patch_space(point))
+    }
+
+    /**
+     * Write `bytes` into the space of every patch point
+     * with the given id, zeroing the rest of the space.
+     * The graph's instructions are left as they were.
+     */
+    pub fn patch(&mut self, id: u32, bytes: &[u8])
+      -> Result<(), PatchError>
+    {
+        let points = points_to_patch(self.patch_points(), id,
+                                     bytes.len()) ?;
+        for point in &points {
+            self.record(Change::Patch { instr: point.instr() });
+            let space = self.instr_store_mut().patch_space_mut(point);
+            space[.. bytes.len()].copy_from_slice(bytes);
+            space[bytes.len() ..].iter_mut().for_each(|b| *b = 0);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{ self, Write };
+
+use crate::block::BlockId;
+use crate::graph::Graph;
+use crate::ops::Op;
+use crate::trace::BranchProfile;
+
+/*
+ * The profile text format has one record per line,
+ * with blank lines and `#` comments ignored:
+ *
+ *   # block <block> <count>
+ *   block 0 1000
+ *   block 2 12
+ *   # edge <block> <target index> <count>
+ *   edge 0 0 988
+ *   edge 0 1 12
+ *
+ * Blocks are named by their declaration ids, as in the
+ * JSON export, and edges by their source block and the
+ * index of the target in its end instruction, so the
+ * true target of a branch is edge 0.  Blocks and edges
+ * without a record have no count.
+ */
+
+/** A problem with profile input, or with its graph. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum ProfileError {
+    // The 1-based line is not a well formed record.
+    Malformed { line: usize },
+
+    // The 1-based line repeats the block or edge of an
+    // earlier record.
+    Duplicate { line: usize },
+
+    // The block is not defined in the graph.
+    NoSuchBlock { block: BlockId },
+
+    // The block's end instruction has no target at
+    // the index.
+    NoSuchEdge { block: BlockId, index: u32 }
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match *self {
+          ProfileError::Malformed { line } =>
+            write!(f, "line {}: malformed profile record", line),
+          ProfileError::Duplicate { line } =>
+            write!(f, "line {}: duplicate profile record", line),
+          ProfileError::NoSuchBlock { block } =>
+            write!(f, "{}: no such block in the graph", block),
+          ProfileError::NoSuchEdge { block, index } =>
+            write!(f, "{}: no edge at target index {}",
+                   block, index)
+        }
+    }
+}
+
+impl Error for ProfileError {}
+
+/**
+ * Execution counts for the blocks and control flow
+ * edges of a graph, as recorded by a profiler, for
+ * PGO-style decisions such as trace selection.  Read
+ * from and written to a small text format with
+ * `parse` and `to_text`.
+ */
+#[derive(Clone, Debug, Default)]
+#[derive(PartialEq, Eq)]
+pub struct Profile {
+    // The count of each block with one, by block id.
+    blocks: BTreeMap<u32, u64>,
+
+    // The count of each edge with one, by source block
+    // id and target index.
+    edges: BTreeMap<(u32, u32), u64>
+}
+
+impl Profile {
+    pub fn new() -> Profile { Profile::default() }
+
+    pub fn set_block_count(&mut self, block: BlockId, count: u64) {
+        self.blocks.insert(block.as_u32(), count);
+    }
+
+    // Set the count of the edge to the target at
+    // `index` of the end instruction of `block`.
+    pub fn set_edge_count(&mut self, block: BlockId, index: u32,
+                          count: u64)
+    {
+        self.edges.insert((block.as_u32(), index), count);
+    }
+
+    pub fn block_count(&self, block: BlockId) -> Option<u64> {
+        self.blocks.get(&block.as_u32()).cloned()
+    }
+
+    pub fn edge_count(&self, block: BlockId, index: u32)
+      -> Option<u64>
+    {
+        self.edges.get(&(block.as_u32(), index)).cloned()
+    }
+
+    /** Parse a profile from its text format. */
+    pub fn parse(text: &str) -> Result<Profile, ProfileError> {
+        let mut profile = Profile::new();
+        for (i, src) in text.lines().enumerate() {
+            let line = i + 1;
+            let src = match src.find('#') {
+              Some(hash) => &src[.. hash],
+              None => src
+            };
+            let words: Vec<&str> = src.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+
+            let malformed = ProfileError::Malformed { line };
+            let num = |s: &str| s.parse::<u64>().ok();
+            let id = |s: &str| s.parse::<u32>().ok();
+            let fresh = match words[..] {
+              ["block", b, n] => {
+                let (b, n) = id(b).zip(num(n)).ok_or(malformed) ?;
+                profile.blocks.insert(b, n).is_none()
+              }
+              ["edge", b, t, n] => {
+                let (b, t) = id(b).zip(id(t)).ok_or(malformed) ?;
+                let n = num(n).ok_or(malformed) ?;
+                profile.edges.insert((b, t), n).is_none()
+              }
+              _ => return Err(malformed)
+            };
+            if !fresh {
+                return Err(ProfileError::Duplicate { line });
+            }
+        }
+        Ok(profile)
+    }
+
+    /**
+     * Write the profile in its text format, blocks
+     * before edges, each in id order.
+     */
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for (b, n) in &self.blocks {
+            writeln!(out, "block {} {}", b, n).unwrap();
+        }
+        for ((b, t), n) in &self.edges {
+            writeln!(out, "edge {} {} {}", b, t, n).unwrap();
+        }
+        out
+    }
+
+    // Check that every record names a block defined in
+    // the graph, and an edge its end instruction has.
+    pub(crate) fn check(&self, graph: &Graph)
+      -> Result<(), ProfileError>
+    {
+        let store = graph.block_store();
+        let mut num_targets = vec![None; store.total_blocks()];
+        for block in store.iter_rpo_blocks() {
+            let n = graph.block_end(block).targets_iter().count();
+            num_targets[block.id().as_u32() as usize] = Some(n as u32);
+        }
+        let targets = |b: u32| {
+            num_targets.get(b as usize).cloned().unwrap_or(None)
+              .ok_or(ProfileError::NoSuchBlock {
+                  block: BlockId::new(b)
+              })
+        };
+
+        for &b in self.blocks.keys() {
+            targets(b) ?;
+        }
+        for &(b, index) in self.edges.keys() {
+            if index >= targets(b) ? {
+                let block = BlockId::new(b);
+                return Err(ProfileError::NoSuchEdge { block, index });
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * The direction of each branch of the graph that
+     * has a count on either of its edges: to its true
+     * target unless its false edge counts more.  A
+     * missing count is taken as zero.
+     */
+    pub fn branch_profile(&self, graph: &Graph) -> BranchProfile {
+        let mut branches = BranchProfile::new();
+        for block in graph.block_store().iter_rpo_blocks() {
+            let id = block.id();
+            if let Op::Branch(_) = graph.block_end(block).op() {
+                let (t, f) = (self.edge_count(id, 0),
+                              self.edge_count(id, 1));
+                if t.is_some() || f.is_some() {
+                    let taken = t.unwrap_or(0) >= f.unwrap_or(0);
+                    branches.set(id, taken);
+                }
+            }
+        }
+        branches
+    }
+}
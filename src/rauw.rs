@@ -0,0 +1,99 @@
+
+use crate::instr::InstrId;
+use crate::changes::Change;
+use crate::graph::Graph;
+use crate::text::Rebuild;
+
+/*
+ * Replacing all uses of a value.  Every operand, phi
+ * argument and snapshot entry naming the old value is
+ * made to name the new one.  Where each operand field
+ * encodes the new value in as many bytes as the old,
+ * as fixed-width fields always do, the fields are
+ * patched in place and every id stays as it was.
+ * Otherwise the stream would have to grow or shrink,
+ * so the graph is rebuilt with the uses renamed, and
+ * the ids of its instructions and blocks change.
+ *
+ * The new value must have the old one's type, and
+ * dominate each of its uses.
+ */
+
+/** What `Graph::replace_all_uses` did. */
+#[derive(Clone, Copy, Debug, Default)]
+#[derive(PartialEq, Eq)]
+pub struct ReplaceStats {
+    uses: u32,
+    rebuilt: bool
+}
+
+impl ReplaceStats {
+    // The operands, phi arguments and snapshot entries
+    // replaced.
+    pub fn uses(&self) -> u32 { self.uses }
+
+    // Whether the graph was rebuilt, changing its ids,
+    // rather than patched in place.
+    pub fn rebuilt(&self) -> bool { self.rebuilt }
+}
+
+impl Graph {
+    /**
+     * Make every use of the value `old` use `new`
+     * instead, by operands, phi arguments and
+     * snapshots.  When an operand field cannot be
+     * patched in place the graph is rebuilt: its
+     * instruction and block ids change, and its
+     * profile, instruction index and patched patch
+     * point bytes are dropped.
+     */
+    pub fn replace_all_uses(&mut self, old: InstrId, new: InstrId)
+      -> ReplaceStats
+    {
+        let old_ty = self.instr(old).op().out_type();
+        assert!(self.instr(old).op().defines_value()
+                  && old_ty == self.instr(new).op().out_type(),
+                "replacing {} with a value of another type", old);
+
+        let mut stats = ReplaceStats::default();
+        if old == new {
+            return stats;
+        }
+        let uses: Vec<_> = self.def_uses().uses(old).collect();
+        let snapshot_uses = self.snapshot_table().iter()
+          .flat_map(|(_, s)| s.entries())
+          .filter(|e| e.1 == old)
+          .count() as u32;
+        stats.uses = uses.len() as u32 + snapshot_uses;
+
+        let store = self.instr_store();
+        let in_place = uses.iter().all(|u| {
+            store.can_rewrite_operand(u.user(), u.site(), new)
+        });
+        if !in_place {
+            let mut rb = Rebuild::new(self);
+            let (old, new) = (rb.value(old), rb.value(new));
+            rb.replace_uses(&old, &new);
+            self.rebuilt(rb.finish());
+            stats.rebuilt = true;
+            return stats;
+        }
+
+        for u in &uses {
+            let (user, site) = (u.user(), u.site());
+            self.record(Change::Operand { user, site, old, new });
+            self.instr_store_mut().rewrite_operand(user, site, new);
+        }
+        let touched: Vec<InstrId> = self.snapshot_table().iter()
+          .filter(|(_, s)| s.entries().iter().any(|e| e.1 == old))
+          .map(|&(instr, _)| instr)
+          .collect();
+        for instr in touched {
+            self.record(Change::Snapshot { instr });
+        }
+        for (_, snapshot) in self.snapshot_table_mut() {
+            snapshot.replace_value(old, new);
+        }
+        stats
+    }
+}
@@ -0,0 +1,62 @@
+
+use crate::instr::InstrId;
+use crate::regalloc::PhysReg;
+
+/** A stack slot holding a spilled value. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SpillSlot(u32);
+
+impl SpillSlot {
+    pub(crate) fn new(idx: u32) -> SpillSlot { SpillSlot(idx) }
+    pub fn as_u32(&self) -> u32 { self.0 }
+}
+
+/** Where an allocated value lives. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum Location {
+    Reg(PhysReg),
+    Spill(SpillSlot)
+}
+
+/**
+ * The result of register allocation: a side table
+ * mapping each value-defining instruction to its
+ * location, kept sorted by `InstrId` for lookup.
+ */
+pub struct Allocation {
+    locations: Vec<(InstrId, Location)>,
+    num_spill_slots: u32
+}
+
+impl Allocation {
+    pub(crate) fn new(mut locations: Vec<(InstrId, Location)>,
+        num_spill_slots: u32)
+      -> Allocation
+    {
+        locations.sort_unstable_by_key(|&(id, _)| id);
+        Allocation { locations, num_spill_slots }
+    }
+
+    pub fn location(&self, def: InstrId) -> Option<Location> {
+        let idx = self.locations
+                      .binary_search_by_key(&def, |&(id, _)| id)
+                      .ok() ?;
+        Some(self.locations[idx].1)
+    }
+
+    pub fn iter(&self)
+      -> impl Iterator<Item=(InstrId, Location)> + '_
+    {
+        self.locations.iter().cloned()
+    }
+
+    pub fn num_spill_slots(&self) -> u32 { self.num_spill_slots }
+
+    pub fn num_spilled(&self) -> usize {
+        self.locations.iter()
+            .filter(|&&(_, loc)| matches!(loc, Location::Spill(_)))
+            .count()
+    }
+}
@@ -0,0 +1,280 @@
+
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::Op;
+use crate::ir_types::IrTypeId;
+use crate::target::TargetDesc;
+use crate::regalloc::{ Allocation, Location, PhysReg, SpillSlot };
+
+/**
+ * The order arguments past the argument registers are
+ * pushed in: `RightToLeft` leaves the first of them
+ * nearest the stack pointer, `LeftToRight` the last.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum StackOrder { RightToLeft, LeftToRight }
+
+/**
+ * Where a calling convention passes a value: in a
+ * register, or at a byte offset into the arguments on
+ * the stack, from the stack pointer at the call.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum ArgLocation {
+    Reg(PhysReg),
+    Stack(u32)
+}
+
+/**
+ * A calling convention: the registers the leading
+ * arguments are passed in, in order, the order the
+ * rest are pushed in, and the register a value is
+ * returned in, with the registers a callee must
+ * preserve and the alignment of its stack frame, by
+ * default none and twice the pointer width.  Each
+ * stack argument takes a slot of the target's pointer
+ * width, or of its own size if larger, so slots stay
+ * aligned.  Registers are those
+ * of the `TargetRegs` the graph is allocated for,
+ * which may include registers of no class.
+ */
+#[derive(Clone, Debug)]
+pub struct CallConv {
+    name: String,
+    arg_regs: Vec<PhysReg>,
+    ret_reg: PhysReg,
+    order: StackOrder,
+    target: TargetDesc,
+    callee_saved: Vec<PhysReg>,
+    stack_align: u32
+}
+
+impl CallConv {
+    pub fn new(name: &str, arg_regs: &[PhysReg], ret_reg: PhysReg,
+               order: StackOrder, target: TargetDesc)
+      -> CallConv
+    {
+        CallConv {
+            name: name.to_string(), arg_regs: arg_regs.to_vec(),
+            ret_reg, order, target, callee_saved: Vec::new(),
+            stack_align: 2 * target.ptr_bytes()
+        }
+    }
+
+    // Set the registers a callee must preserve.
+    pub fn set_callee_saved(&mut self, regs: &[PhysReg]) {
+        self.callee_saved = regs.to_vec();
+    }
+
+    // Set the alignment of stack frames, a power of two.
+    pub fn set_stack_align(&mut self, align: u32) {
+        assert!(align.is_power_of_two());
+        self.stack_align = align;
+    }
+
+    pub fn name(&self) -> &str { &self.name }
+    pub fn arg_regs(&self) -> &[PhysReg] { &self.arg_regs }
+    pub fn ret_reg(&self) -> PhysReg { self.ret_reg }
+    pub fn order(&self) -> StackOrder { self.order }
+    pub fn target(&self) -> TargetDesc { self.target }
+    pub fn callee_saved(&self) -> &[PhysReg] { &self.callee_saved }
+    pub fn stack_align(&self) -> u32 { self.stack_align }
+
+    // The stack slot bytes an argument of the type
+    // takes.
+    fn slot_bytes(&self, ty: IrTypeId) -> u32 {
+        self.target.size_of(ty).max(self.target.ptr_bytes())
+    }
+
+    // Where each argument of the given types is passed.
+    pub fn arg_locations(&self, types: &[IrTypeId])
+      -> Vec<ArgLocation>
+    {
+        let num_regs = self.arg_regs.len().min(types.len());
+        let stack = &types[num_regs ..];
+        let mut offsets = Vec::with_capacity(stack.len());
+        let mut offset = 0;
+        match self.order {
+          StackOrder::RightToLeft => {
+            for &ty in stack {
+                offsets.push(offset);
+                offset += self.slot_bytes(ty);
+            }
+          }
+          StackOrder::LeftToRight => {
+            for &ty in stack.iter().rev() {
+                offsets.push(offset);
+                offset += self.slot_bytes(ty);
+            }
+            offsets.reverse();
+          }
+        }
+        self.arg_regs[.. num_regs].iter()
+            .map(|&r| ArgLocation::Reg(r))
+            .chain(offsets.into_iter().map(ArgLocation::Stack))
+            .collect()
+    }
+
+    // The bytes of stack the arguments of the given
+    // types take.
+    pub fn stack_bytes(&self, types: &[IrTypeId]) -> u32 {
+        let num_regs = self.arg_regs.len().min(types.len());
+        types[num_regs ..].iter().map(|&ty| self.slot_bytes(ty)).sum()
+    }
+}
+
+/** A place an ABI move reads or writes. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum AbiLocation {
+    Reg(PhysReg),
+    Spill(SpillSlot),
+
+    // A byte offset into the incoming arguments on the
+    // stack.
+    ArgStack(u32)
+}
+
+impl From<Location> for AbiLocation {
+    fn from(loc: Location) -> AbiLocation {
+        match loc {
+          Location::Reg(r) => AbiLocation::Reg(r),
+          Location::Spill(s) => AbiLocation::Spill(s)
+        }
+    }
+}
+
+impl From<ArgLocation> for AbiLocation {
+    fn from(loc: ArgLocation) -> AbiLocation {
+        match loc {
+          ArgLocation::Reg(r) => AbiLocation::Reg(r),
+          ArgLocation::Stack(offset) => AbiLocation::ArgStack(offset)
+        }
+    }
+}
+
+/**
+ * A move a backend makes for the calling convention
+ * at an instruction, of a value of the given type.
+ * A move between the stack and a spill slot is a load
+ * and a store through a scratch register.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct AbiMove {
+    from: AbiLocation,
+    to: AbiLocation,
+    ty: IrTypeId
+}
+
+impl AbiMove {
+    pub fn from(&self) -> AbiLocation { self.from }
+    pub fn to(&self) -> AbiLocation { self.to }
+    pub fn ty(&self) -> IrTypeId { self.ty }
+}
+
+/**
+ * The `param` and `ret` instructions of an allocated
+ * graph lowered to moves under a calling convention.
+ * On entry, the params move their arguments from
+ * where the convention passes them to their allocated
+ * locations, all at once as the moves of an edge are,
+ * since a param's location may be where another's
+ * argument is passed.  Each `ret` moves its value to
+ * the return register.  Param indices the graph never
+ * reads are passed as `Int64`.  Moves of a value to
+ * where it already is are dropped, and values given
+ * no location have no moves.
+ *
+ * The graph has no call instruction yet; a backend
+ * lowering calls would pass their arguments at the
+ * convention's `arg_locations` in the same way.
+ */
+pub struct AbiLowering {
+    entry_moves: Vec<AbiMove>,
+
+    // The move of each `ret` with one, sorted by
+    // instruction id.
+    ret_moves: Vec<(InstrId, AbiMove)>,
+
+    arg_types: Vec<IrTypeId>,
+    stack_bytes: u32
+}
+
+impl AbiLowering {
+    pub(crate) fn compute(graph: &Graph, conv: &CallConv,
+                          alloc: &Allocation)
+      -> AbiLowering
+    {
+        let mut params: Vec<(InstrId, u32, IrTypeId)> = Vec::new();
+        let mut rets: Vec<(InstrId, InstrId, IrTypeId)> = Vec::new();
+        for block in graph.block_store().iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                let id = info.defn().instr_id();
+                match info.op() {
+                  Op::Param(op) => {
+                    params.push((id, op.index(), op.tyid()));
+                  }
+                  Op::Ret(op) => {
+                    let value = info.inputs_iter().next().unwrap();
+                    rets.push((id, value.instr_id(), op.tyid()));
+                  }
+                  _ => {}
+                }
+            }
+        }
+
+        let num_args = params.iter().map(|p| p.1 as usize + 1).max()
+                             .unwrap_or(0);
+        let mut arg_types = vec![IrTypeId::Int64; num_args];
+        for &(_, index, ty) in &params {
+            arg_types[index as usize] = ty;
+        }
+        let args = conv.arg_locations(&arg_types);
+
+        let mut entry_moves = Vec::new();
+        for &(id, index, ty) in &params {
+            let from = args[index as usize].into();
+            match alloc.location(id).map(AbiLocation::from) {
+              Some(to) if to != from => {
+                entry_moves.push(AbiMove { from, to, ty });
+              }
+              _ => {}
+            }
+        }
+
+        let to = AbiLocation::Reg(conv.ret_reg());
+        let mut ret_moves = Vec::new();
+        for &(id, value, ty) in &rets {
+            match alloc.location(value).map(AbiLocation::from) {
+              Some(from) if from != to => {
+                ret_moves.push((id, AbiMove { from, to, ty }));
+              }
+              _ => {}
+            }
+        }
+        ret_moves.sort_unstable_by_key(|m| m.0);
+
+        let stack_bytes = conv.stack_bytes(&arg_types);
+        AbiLowering { entry_moves, ret_moves, arg_types, stack_bytes }
+    }
+
+    // The moves of the params, to be made at once before
+    // the start block.
+    pub fn entry_moves(&self) -> &[AbiMove] { &self.entry_moves }
+
+    // The move to make at a `ret`, if any.
+    pub fn ret_move(&self, id: InstrId) -> Option<AbiMove> {
+        let i = self.ret_moves.binary_search_by_key(&id, |m| m.0)
+                    .ok() ?;
+        Some(self.ret_moves[i].1)
+    }
+
+    // The types of the graph's arguments, by index.
+    pub fn arg_types(&self) -> &[IrTypeId] { &self.arg_types }
+
+    // The bytes of the incoming arguments on the stack.
+    pub fn stack_bytes(&self) -> u32 { self.stack_bytes }
+}
@@ -0,0 +1,223 @@
+
+use std::collections::HashMap;
+use std::mem;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::Op;
+use crate::regalloc::{
+    LiveIntervals, LiveInterval, Allocation, RegHints
+};
+
+/**
+ * Copy coalescing of a graph's live intervals, for
+ * register assignment.
+ *
+ * Values related by a copy are put in a class, to be
+ * given one location: each phi with each of its
+ * arguments, so that the edge moves out of SSA form
+ * become moves of a value to where it already is, and
+ * then the result of each `copy` with its operand.
+ * Two classes are merged when their values are of one
+ * type, no value of one is live where a value of the
+ * other is, except for a copy's operand ending where
+ * its result starts, and their hulls overlap or
+ * touch: since `linear_scan` allocates the hull of an
+ * interval, joining distant ranges would hold a
+ * register over the gap between them.  Constants,
+ * which edges move as immediates, are not merged.
+ *
+ * The classes' intervals are the unions of their
+ * values' ranges, for `linear_scan` to allocate in
+ * place of the values', and `expand` gives each value
+ * the location of its class.
+ */
+pub struct Coalescing {
+    // The leader of each value's class, sorted by
+    // instruction id.
+    leaders: Vec<(InstrId, InstrId)>,
+
+    intervals: LiveIntervals,
+    merged: u32
+}
+
+// Whether any value of one class is live where one of
+// the other is, but for the overlap of a copy pair at
+// the copy.
+fn interferes(ivs: &[LiveInterval], a: &[usize], b: &[usize],
+              copy: Option<(usize, usize, u32)>)
+  -> bool
+{
+    a.iter().any(|&x| b.iter().any(|&y| {
+        ivs[x].ranges().iter().any(|&(f, t)| {
+            ivs[y].ranges().iter().any(|&(of, ot)| {
+                let (from, to) = (f.max(of), t.min(ot));
+                let at_copy = copy.is_some_and(|(u, d, pos)| {
+                    let pair = (u, d) == (x, y) || (u, d) == (y, x);
+                    pair && (from, to) == (pos, pos + 1)
+                });
+                from < to && !at_copy
+            })
+        })
+    }))
+}
+
+// Merge sorted half-open ranges that touch.
+fn union_ranges(mut ranges: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(u32, u32)> = Vec::new();
+    for (f, t) in ranges {
+        match merged.last_mut() {
+          Some(last) if f <= last.1 => {
+            last.1 = last.1.max(t);
+          }
+          _ => merged.push((f, t))
+        }
+    }
+    merged
+}
+
+impl Coalescing {
+    pub(crate) fn compute(graph: &Graph, intervals: &LiveIntervals)
+      -> Coalescing
+    {
+        let ivs = intervals.intervals();
+        let index: HashMap<InstrId, usize> = ivs.iter().enumerate()
+          .map(|(i, iv)| (iv.def(), i))
+          .collect();
+
+        // The phis of each block, the phi arguments of
+        // each edge, and each copy with its position.
+        let mut is_const = vec![false; ivs.len()];
+        let mut phis: HashMap<BlockId, Vec<usize>> = HashMap::new();
+        let mut edges: Vec<(BlockId, Vec<usize>)> = Vec::new();
+        let mut copies: Vec<(usize, usize, u32)> = Vec::new();
+        for block in graph.block_store().iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                let id = info.defn().instr_id();
+                match info.op() {
+                  Op::Phi(_) => {
+                    phis.entry(block.id()).or_default()
+                        .push(index[&id]);
+                  }
+                  Op::Const(_) => is_const[index[&id]] = true,
+                  Op::Copy(_) => {
+                    let from = info.inputs_iter().next().unwrap();
+                    let pos = intervals.position_of(id).unwrap();
+                    copies.push((index[&from.instr_id()], index[&id],
+                                 pos));
+                  }
+                  _ => {}
+                }
+                for target in info.targets_iter() {
+                    let args = target.phi_args()
+                                     .map(|d| index[&d.instr_id()])
+                                     .collect();
+                    edges.push((target.block_id(), args));
+                }
+            }
+        }
+        let mut pairs: Vec<(usize, usize, Option<u32>)> = Vec::new();
+        for (block, args) in edges {
+            let block_phis = phis.get(&block).map_or(&[][..], |p| p);
+            for (&phi, arg) in block_phis.iter().zip(args) {
+                pairs.push((arg, phi, None));
+            }
+        }
+        pairs.extend(copies.into_iter()
+                           .map(|(u, d, p)| (u, d, Some(p))));
+
+        let mut leader: Vec<usize> = (0 .. ivs.len()).collect();
+        let mut members: Vec<Vec<usize>> =
+          (0 .. ivs.len()).map(|i| vec![i]).collect();
+        let mut hulls: Vec<(u32, u32)> =
+          ivs.iter().map(|iv| (iv.start(), iv.end())).collect();
+        let find = |leader: &[usize], mut i: usize| {
+            while leader[i] != i {
+                i = leader[i];
+            }
+            i
+        };
+        let mut merged = 0;
+        for (x, y, pos) in pairs {
+            let (rx, ry) = (find(&leader, x), find(&leader, y));
+            if rx == ry || is_const[x] || is_const[y]
+              || ivs[x].tyid() != ivs[y].tyid()
+            {
+                continue;
+            }
+            let (hx, hy) = (hulls[rx], hulls[ry]);
+            if hx.0.max(hy.0) > hx.1.min(hy.1) {
+                continue;
+            }
+            let copy = pos.map(|p| (x, y, p));
+            if interferes(ivs, &members[rx], &members[ry], copy) {
+                continue;
+            }
+            let moved = mem::take(&mut members[ry]);
+            members[rx].extend(moved);
+            hulls[rx] = (hx.0.min(hy.0), hx.1.max(hy.1));
+            leader[ry] = rx;
+            merged += 1;
+        }
+
+        let classes = members.iter().enumerate()
+          .filter(|(_, m)| !m.is_empty())
+          .map(|(r, m)| {
+              let ranges = m.iter()
+                .flat_map(|&i| ivs[i].ranges().iter().cloned())
+                .collect();
+              LiveInterval::new(ivs[r].def(), ivs[r].tyid(),
+                                union_ranges(ranges))
+          })
+          .collect();
+        let mut leaders: Vec<(InstrId, InstrId)> = (0 .. ivs.len())
+          .map(|i| (ivs[i].def(), ivs[find(&leader, i)].def()))
+          .collect();
+        leaders.sort_unstable();
+        debug!("Coalesced {} copy-related pairs", merged);
+        Coalescing {
+            leaders, intervals: intervals.with_intervals(classes),
+            merged
+        }
+    }
+
+    // The value whose class a value is in, by whose
+    // definition the class is allocated.
+    pub fn leader(&self, def: InstrId) -> Option<InstrId> {
+        let idx = self.leaders.binary_search_by_key(&def, |l| l.0)
+                      .ok() ?;
+        Some(self.leaders[idx].1)
+    }
+
+    // One interval for each class.
+    pub fn intervals(&self) -> &LiveIntervals { &self.intervals }
+
+    // The merges made, each removing an interval, and
+    // with a copy a move.
+    pub fn num_merged(&self) -> u32 { self.merged }
+
+    // The hints of the values of each class, as hints
+    // of its leader; a fixed register takes precedence
+    // over a preferred one, and a preferred one over a
+    // class.
+    pub fn class_hints(&self, hints: &RegHints) -> RegHints {
+        let mut class_hints = RegHints::new();
+        for (def, hint) in hints.iter() {
+            if let Some(leader) = self.leader(def) {
+                class_hints.merge(leader, hint);
+            }
+        }
+        class_hints
+    }
+
+    // Give each value the location its class was
+    // allocated.
+    pub fn expand(&self, alloc: &Allocation) -> Allocation {
+        let locations = self.leaders.iter()
+          .filter_map(|&(def, l)| Some((def, alloc.location(l) ?)))
+          .collect();
+        Allocation::new(locations, alloc.num_spill_slots())
+    }
+}
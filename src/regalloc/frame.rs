@@ -0,0 +1,222 @@
+
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::Op;
+use crate::ir_types::IrTypeId;
+use crate::regalloc::{
+    LiveIntervals, Allocation, Location, SpillSlot, PhysReg, CallConv
+};
+
+/**
+ * Give the spilled values of an allocation stack slots
+ * anew, sharing a slot among values whose intervals do
+ * not overlap.  Values are taken in order of their
+ * intervals' starts, each into the first slot free
+ * since the end of the last value in it, with
+ * intervals treated as the hulls of their ranges as
+ * `linear_scan` treats them.  Registers are kept.
+ */
+pub fn assign_spill_slots(intervals: &LiveIntervals,
+                          alloc: &Allocation)
+  -> Allocation
+{
+    let mut spilled: Vec<(u32, u32, InstrId)> = intervals.intervals()
+      .iter()
+      .filter(|iv| matches!(alloc.location(iv.def()),
+                            Some(Location::Spill(_))))
+      .map(|iv| (iv.start(), iv.end(), iv.def()))
+      .collect();
+    spilled.sort_unstable();
+
+    // The end of the last value in each slot.
+    let mut ends: Vec<u32> = Vec::new();
+    let mut slots: Vec<(InstrId, Location)> = Vec::new();
+    for (start, end, def) in spilled {
+        let slot = match ends.iter().position(|&e| e <= start) {
+          Some(s) => s,
+          None => {
+            ends.push(0);
+            ends.len() - 1
+          }
+        };
+        ends[slot] = end;
+        let slot = SpillSlot::new(slot as u32);
+        slots.push((def, Location::Spill(slot)));
+    }
+    slots.sort_unstable_by_key(|&(id, _)| id);
+
+    let locations = alloc.iter().map(|(id, loc)| match loc {
+      Location::Spill(_) => {
+        let i = slots.binary_search_by_key(&id, |s| s.0).unwrap();
+        (id, slots[i].1)
+      }
+      loc => (id, loc)
+    }).collect();
+    debug!("Assigned {} spill slots for {} spilled values",
+           ends.len(), slots.len());
+    Allocation::new(locations, ends.len() as u32)
+}
+
+/** Whether spill code stores a value or loads it. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum SpillKind {
+    // Store the value to its slot after the
+    // instruction defining it.
+    Spill,
+
+    // Load the value from its slot before an
+    // instruction using it.
+    Reload
+}
+
+/** A store or load of a spilled value, at an instruction. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct SpillOp {
+    kind: SpillKind,
+    value: InstrId,
+    slot: SpillSlot,
+    at: InstrId
+}
+
+impl SpillOp {
+    pub fn kind(&self) -> SpillKind { self.kind }
+    pub fn value(&self) -> InstrId { self.value }
+    pub fn slot(&self) -> SpillSlot { self.slot }
+    pub fn at(&self) -> InstrId { self.at }
+}
+
+/**
+ * The stack frame of an allocated graph under a calling
+ * convention, from the stack pointer after the
+ * prologue up:
+ *
+ *   spill slots        in slot order, each sized to
+ *                        its widest value or the
+ *                        target's pointer width if
+ *                        wider, and aligned to its
+ *                        size up to the target's
+ *                        largest alignment
+ *   saved registers    the callee-saved registers
+ *                        the allocation uses, one
+ *                        pointer-wide slot each
+ *
+ * padded to the convention's stack alignment.  The
+ * return address and any frame pointer the backend
+ * pushes are above the frame.
+ *
+ * Spill code stores each spilled value after its
+ * definition, other than a phi's or a param's, which
+ * their edges or ABI moves write to the slot, and
+ * reloads it before each instruction taking it as an
+ * operand or passing it to a phi.
+ */
+pub struct FrameLayout {
+    // The offset and size of each spill slot, in
+    // bytes.
+    slots: Vec<(u32, u32)>,
+
+    saved_regs: Vec<PhysReg>,
+    saved_offset: u32,
+    size: u32,
+    spill_code: Vec<SpillOp>
+}
+
+fn round_up(value: u32, align: u32) -> u32 {
+    value.div_ceil(align) * align
+}
+
+impl FrameLayout {
+    pub(crate) fn compute(graph: &Graph, alloc: &Allocation,
+                          conv: &CallConv)
+      -> FrameLayout
+    {
+        let target = conv.target();
+        let ptr_bytes = target.ptr_bytes();
+        let num_slots = alloc.num_spill_slots() as usize;
+        let mut sizes = vec![ptr_bytes; num_slots];
+        let mut spill_code = Vec::new();
+        let slot_of = |id: InstrId| match alloc.location(id) {
+          Some(Location::Spill(slot)) => Some(slot),
+          _ => None
+        };
+        let mut note_type = |slot: SpillSlot, ty: IrTypeId| {
+            let s = &mut sizes[slot.as_u32() as usize];
+            *s = (*s).max(target.size_of(ty));
+        };
+
+        for block in graph.block_store().iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                let id = info.defn().instr_id();
+                let uses = info.inputs_iter().chain(
+                  info.targets_iter().flat_map(|t| t.phi_args()));
+                let mut reloaded = Vec::new();
+                for value in uses.map(|d| d.instr_id()) {
+                    if let Some(slot) = slot_of(value) {
+                        if !reloaded.contains(&value) {
+                            reloaded.push(value);
+                            spill_code.push(SpillOp {
+                                kind: SpillKind::Reload,
+                                value, slot, at: id
+                            });
+                        }
+                    }
+                }
+                if !info.op().defines_value() {
+                    continue;
+                }
+                if let Some(slot) = slot_of(id) {
+                    note_type(slot, info.op().out_type().unwrap());
+                    let stored = !matches!(info.op(),
+                                           Op::Phi(_) | Op::Param(_));
+                    if stored {
+                        spill_code.push(SpillOp {
+                            kind: SpillKind::Spill,
+                            value: id, slot, at: id
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut slots = Vec::with_capacity(num_slots);
+        let mut end = 0;
+        for size in sizes {
+            let offset = round_up(end, size.min(target.max_align()));
+            slots.push((offset, size));
+            end = offset + size;
+        }
+
+        let saved_regs: Vec<PhysReg> = conv.callee_saved().iter()
+          .cloned()
+          .filter(|&r| alloc.iter().any(|(_, l)| l == Location::Reg(r)))
+          .collect();
+        let saved_offset = round_up(end, ptr_bytes);
+        end = saved_offset + ptr_bytes * saved_regs.len() as u32;
+        let size = round_up(end, conv.stack_align());
+        FrameLayout {
+            slots, saved_regs, saved_offset, size, spill_code
+        }
+    }
+
+    // The offset of a spill slot from the stack
+    // pointer, and its size.
+    pub fn slot(&self, slot: SpillSlot) -> (u32, u32) {
+        self.slots[slot.as_u32() as usize]
+    }
+    pub fn num_slots(&self) -> u32 { self.slots.len() as u32 }
+
+    // The callee-saved registers to save, in the
+    // convention's order, and the offset of the first.
+    pub fn saved_regs(&self) -> &[PhysReg] { &self.saved_regs }
+    pub fn saved_offset(&self) -> u32 { self.saved_offset }
+
+    // The bytes of the frame, padded to the stack
+    // alignment.
+    pub fn size(&self) -> u32 { self.size }
+
+    // The spill code, in stream order, with reloads at
+    // an instruction before the spill of its value.
+    pub fn spill_code(&self) -> &[SpillOp] { &self.spill_code }
+}
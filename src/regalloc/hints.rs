@@ -0,0 +1,119 @@
+
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::Op;
+use crate::regalloc::{ PhysReg, RegClassId, CallConv };
+
+/** A hint of where to allocate a value. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum RegHint {
+    // Allocate the value in the register if it is free
+    // when the value's interval starts.
+    Prefer(PhysReg),
+
+    // Allocate the value from the class in place of
+    // the class of its type.
+    Class(RegClassId),
+
+    // Allocate the value in the register, spilling any
+    // other value holding it.  The value is never
+    // spilled, and the register need not be of any
+    // class.
+    Fixed(PhysReg)
+}
+
+impl RegHint {
+    // The order in which the hints of coalesced values
+    // give way to one another.
+    fn strength(&self) -> u8 {
+        match self {
+          RegHint::Class(_) => 0,
+          RegHint::Prefer(_) => 1,
+          RegHint::Fixed(_) => 2
+        }
+    }
+}
+
+/**
+ * Register hints attached to a graph's definitions, as
+ * a side table kept sorted by `InstrId`, for
+ * `linear_scan_with_hints` to honor.  A definition has
+ * at most one hint.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct RegHints {
+    hints: Vec<(InstrId, RegHint)>
+}
+
+impl RegHints {
+    pub fn new() -> RegHints { RegHints { hints: Vec::new() } }
+
+    // Set the hint of a definition, replacing any it
+    // had.
+    pub fn set(&mut self, def: InstrId, hint: RegHint) {
+        match self.hints.binary_search_by_key(&def, |&(id, _)| id) {
+          Ok(idx) => self.hints[idx].1 = hint,
+          Err(idx) => self.hints.insert(idx, (def, hint))
+        }
+    }
+
+    pub fn remove(&mut self, def: InstrId) {
+        self.hints.retain(|&(id, _)| id != def);
+    }
+
+    pub fn get(&self, def: InstrId) -> Option<RegHint> {
+        let idx = self.hints
+                      .binary_search_by_key(&def, |&(id, _)| id)
+                      .ok() ?;
+        Some(self.hints[idx].1)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(InstrId, RegHint)> + '_ {
+        self.hints.iter().cloned()
+    }
+
+    pub fn len(&self) -> usize { self.hints.len() }
+    pub fn is_empty(&self) -> bool { self.hints.is_empty() }
+
+    // Set a hint unless the definition has one at least
+    // as strong.
+    pub(crate) fn merge(&mut self, def: InstrId, hint: RegHint) {
+        match self.get(def) {
+          Some(old) if old.strength() >= hint.strength() => {}
+          _ => self.set(def, hint)
+        }
+    }
+
+    // Hints giving each param passed in a register a
+    // preference for it, and each returned value a
+    // preference for the return register, so that
+    // their ABI moves are dropped where they are
+    // honored.  A param that is returned prefers its
+    // argument register.
+    pub(crate) fn for_abi(graph: &Graph, conv: &CallConv) -> RegHints {
+        let mut hints = RegHints::new();
+        let mut params = Vec::new();
+        for block in graph.block_store().iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                match info.op() {
+                  Op::Param(op) => {
+                    params.push((info.defn().instr_id(), op.index()));
+                  }
+                  Op::Ret(_) => {
+                    let value = info.inputs_iter().next().unwrap();
+                    hints.set(value.instr_id(),
+                              RegHint::Prefer(conv.ret_reg()));
+                  }
+                  _ => {}
+                }
+            }
+        }
+        for (id, index) in params {
+            if let Some(&reg) = conv.arg_regs().get(index as usize) {
+                hints.set(id, RegHint::Prefer(reg));
+            }
+        }
+        hints
+    }
+}
@@ -0,0 +1,177 @@
+
+use std::collections::HashMap;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::ir_types::IrTypeId;
+use crate::graph::Graph;
+use crate::analysis::Liveness;
+
+/**
+ * The live interval of a single value.
+ *
+ * Positions number the instructions of the graph in
+ * RPO block order and stream order within blocks,
+ * two apart so that moves can later be placed between
+ * instructions.  Ranges are half open, `[from, to)`.
+ */
+pub struct LiveInterval {
+    def: InstrId,
+    tyid: IrTypeId,
+    ranges: Vec<(u32, u32)>
+}
+
+impl LiveInterval {
+    pub(crate) fn new(def: InstrId, tyid: IrTypeId,
+                      ranges: Vec<(u32, u32)>)
+      -> LiveInterval
+    {
+        LiveInterval { def, tyid, ranges }
+    }
+
+    pub fn def(&self) -> InstrId { self.def }
+    pub fn tyid(&self) -> IrTypeId { self.tyid }
+    pub fn ranges(&self) -> &[(u32, u32)] { &self.ranges }
+
+    pub fn start(&self) -> u32 { self.ranges[0].0 }
+    pub fn end(&self) -> u32 {
+        self.ranges[self.ranges.len() - 1].1
+    }
+
+    pub fn covers(&self, pos: u32) -> bool {
+        self.ranges.iter().any(|&(f, t)| f <= pos && pos < t)
+    }
+    pub fn overlaps(&self, other: &LiveInterval) -> bool {
+        self.ranges.iter().any(|&(f, t)| {
+            other.ranges.iter().any(|&(of, ot)| f < ot && of < t)
+        })
+    }
+}
+
+/**
+ * The live intervals of every value in a graph,
+ * derived from block-level liveness.
+ */
+pub struct LiveIntervals {
+    intervals: Vec<LiveInterval>,
+
+    // Position of each instruction, sorted by InstrId.
+    positions: Vec<(InstrId, u32)>,
+
+    // Position range covered by each block, by block id.
+    block_ranges: Vec<(u32, u32)>
+}
+
+impl LiveIntervals {
+    pub(crate) fn compute(graph: &Graph, liveness: &Liveness)
+      -> LiveIntervals
+    {
+        let nblocks = graph.block_store().total_blocks();
+        let mut positions = Vec::new();
+        let mut block_ranges = vec![(0, 0); nblocks];
+        let mut types = vec![None; liveness.num_defs() as usize];
+        let mut ranges: Vec<Vec<(u32, u32)>> =
+          vec![Vec::new(); liveness.num_defs() as usize];
+
+        let mut pos = 0_u32;
+        for block in graph.block_store().iter_rpo_blocks() {
+            let block_start = pos;
+
+            // Decode the block once, recording each
+            // instruction's position, definition and uses.
+            let mut steps = Vec::new();
+            for info in graph.block_instrs(block) {
+                let def = if info.op().defines_value() {
+                    let idx = liveness.def_index(
+                                info.defn().instr_id()).unwrap();
+                    types[idx as usize] = info.op().out_type();
+                    Some(idx)
+                } else {
+                    None
+                };
+                let uses: Vec<u32> =
+                  info.inputs_iter()
+                      .filter_map(|d| liveness.def_index(d.instr_id()))
+                      .collect();
+                positions.push((info.defn().instr_id(), pos));
+                steps.push((pos, def, uses));
+                pos += 2;
+            }
+            let block_end = pos;
+            block_ranges[block.id().as_u32() as usize] =
+              (block_start, block_end);
+
+            // Walk backwards, starting from everything
+            // live out of the block covering all of it.
+            let mut local: HashMap<u32, (u32, u32)> = HashMap::new();
+            for v in liveness.live_out_set(block).iter() {
+                local.insert(v, (block_start, block_end));
+            }
+            for &(p, def, ref uses) in steps.iter().rev() {
+                if let Some(d) = def {
+                    let r = local.entry(d).or_insert((p, p + 1));
+                    r.0 = p;
+                }
+                for &u in uses {
+                    let r = local.entry(u)
+                                 .or_insert((block_start, p + 1));
+                    r.0 = block_start;
+                }
+            }
+            for (v, r) in local {
+                ranges[v as usize].push(r);
+            }
+        }
+
+        let mut intervals = Vec::with_capacity(ranges.len());
+        for (idx, mut rs) in ranges.into_iter().enumerate() {
+            rs.sort_unstable();
+            let mut merged: Vec<(u32, u32)> = Vec::new();
+            for (f, t) in rs {
+                match merged.last_mut() {
+                  Some(last) if f <= last.1 => {
+                    last.1 = last.1.max(t);
+                  }
+                  _ => merged.push((f, t))
+                }
+            }
+            intervals.push(LiveInterval {
+                def: liveness.def_at(idx as u32),
+                tyid: types[idx].expect("Value without type."),
+                ranges: merged
+            });
+        }
+
+        positions.sort_unstable_by_key(|&(id, _)| id);
+        LiveIntervals { intervals, positions, block_ranges }
+    }
+
+    pub fn intervals(&self) -> &[LiveInterval] { &self.intervals }
+
+    // The same positions with other intervals.
+    pub(crate) fn with_intervals(&self, intervals: Vec<LiveInterval>)
+      -> LiveIntervals
+    {
+        LiveIntervals {
+            intervals,
+            positions: self.positions.clone(),
+            block_ranges: self.block_ranges.clone()
+        }
+    }
+
+    pub fn interval(&self, def: InstrId) -> Option<&LiveInterval> {
+        self.intervals.iter().find(|iv| iv.def == def)
+    }
+
+    pub fn position_of(&self, instr: InstrId) -> Option<u32> {
+        let idx = self.positions
+                      .binary_search_by_key(&instr, |&(id, _)| id)
+                      .ok() ?;
+        Some(self.positions[idx].1)
+    }
+
+    // The half-open position range of a block.
+    pub fn block_range(&self, block: BlockId) -> (u32, u32) {
+        self.block_ranges[block.as_u32() as usize]
+    }
+}
@@ -0,0 +1,192 @@
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::ir_types::IrTypeId;
+use crate::regalloc::{
+    LiveIntervals, TargetRegs, PhysReg,
+    Allocation, Location, SpillSlot, RegHint, RegHints
+};
+
+/** An error allocating registers. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum AllocError {
+    // A value not hinted to a class has a type the
+    // target has no register class for.
+    NoClass(IrTypeId)
+}
+
+impl fmt::Display for AllocError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match *self {
+          AllocError::NoClass(ty) =>
+            write!(f, "no register class for type {}", ty.as_str())
+        }
+    }
+}
+
+impl Error for AllocError {}
+
+/**
+ * Allocate registers with the linear scan algorithm
+ * of Poletto and Sarkar.
+ *
+ * Each value's interval is treated as the hull of its
+ * ranges.  When a class runs out of registers, the
+ * interval ending furthest away is spilled to its own
+ * stack slot for its whole lifetime.  Fails if a value
+ * has a type the target has no register class for.
+ */
+pub fn linear_scan(intervals: &LiveIntervals, target: &TargetRegs)
+  -> Result<Allocation, AllocError>
+{
+    linear_scan_with_hints(intervals, target, &RegHints::new())
+}
+
+/**
+ * Allocate registers with linear scan, honoring the
+ * hints of the intervals' values.  A value preferring
+ * a register takes it if it is free when its interval
+ * starts, and a value given a class is allocated from
+ * it.  A value fixed to a register takes it, spilling
+ * the value holding it, if any; fixed values are
+ * never chosen to spill.
+ *
+ * Fails if a value given no class has a type the
+ * target has no register class for.  Panics if two
+ * values fixed to one register overlap.
+ */
+pub fn linear_scan_with_hints(intervals: &LiveIntervals,
+                              target: &TargetRegs, hints: &RegHints)
+  -> Result<Allocation, AllocError>
+{
+    let ivs = intervals.intervals();
+
+    let hint_of: Vec<Option<RegHint>> =
+      ivs.iter().map(|iv| hints.get(iv.def())).collect();
+    let class_of: Vec<usize> = ivs.iter().zip(&hint_of)
+      .map(|(iv, hint)| {
+        let class = match *hint {
+          Some(RegHint::Class(class)) => class,
+          _ => target.class_for(iv.tyid())
+                     .ok_or(AllocError::NoClass(iv.tyid())) ?
+        };
+        Ok(TargetRegs::class_index(class))
+      })
+      .collect::<Result<_, _>>() ?;
+    let fixed = |i: usize| {
+        matches!(hint_of[i], Some(RegHint::Fixed(_)))
+    };
+
+    // Free registers for each class, with the most
+    // preferred register at the end.
+    let mut free: Vec<Vec<PhysReg>> =
+      target.classes().iter()
+            .map(|c| c.regs().iter().rev().cloned().collect())
+            .collect();
+
+    let mut order: Vec<usize> = (0 .. ivs.len()).collect();
+    order.sort_by_key(|&i| (ivs[i].start(), i));
+
+    let mut locs: Vec<Option<Location>> = vec![None; ivs.len()];
+    let mut active: Vec<usize> = Vec::new();
+    let mut num_slots = 0_u32;
+    let mut new_slot = || {
+        num_slots += 1;
+        Location::Spill(SpillSlot::new(num_slots - 1))
+    };
+
+    // The classes whose free registers each fixed value
+    // took its register from, to give it back to.
+    let mut fixed_from: HashMap<usize, Vec<usize>> = HashMap::new();
+
+    for i in order {
+        let start = ivs[i].start();
+
+        // Expire intervals that ended before this one.
+        active.retain(|&a| {
+            if ivs[a].end() > start {
+                return true;
+            }
+            if let Some(Location::Reg(r)) = locs[a] {
+                match fixed_from.remove(&a) {
+                  Some(classes) => {
+                    classes.into_iter().for_each(|c| free[c].push(r))
+                  }
+                  None => free[class_of[a]].push(r)
+                }
+            }
+            false
+        });
+
+        let class = class_of[i];
+        if let Some(RegHint::Fixed(r)) = hint_of[i] {
+            let mut from: Vec<usize> = Vec::new();
+            for (c, regs) in free.iter_mut().enumerate() {
+                if let Some(idx) = regs.iter().position(|&f| f == r) {
+                    regs.remove(idx);
+                    from.push(c);
+                }
+            }
+            // Spill the value holding the register.
+            let held = Some(Location::Reg(r));
+            let holder = active.iter().cloned()
+                               .find(|&a| locs[a] == held);
+            if let Some(a) = holder {
+                assert!(!fixed(a),
+                        "Overlapping values fixed to {:?}.", r);
+                from.extend(fixed_from.remove(&a)
+                                      .unwrap_or(vec![class_of[a]]));
+                locs[a] = Some(new_slot());
+                active.retain(|&x| x != a);
+            }
+            fixed_from.insert(i, from);
+            locs[i] = Some(Location::Reg(r));
+            active.push(i);
+            continue;
+        }
+        if let Some(RegHint::Prefer(r)) = hint_of[i] {
+            let idx = free[class].iter().position(|&f| f == r);
+            if let Some(idx) = idx {
+                free[class].remove(idx);
+                locs[i] = Some(Location::Reg(r));
+                active.push(i);
+                continue;
+            }
+        }
+        if let Some(r) = free[class].pop() {
+            locs[i] = Some(Location::Reg(r));
+            active.push(i);
+            continue;
+        }
+
+        // Spill whichever of this interval and the active
+        // unfixed intervals in its class ends last.
+        let victim = active.iter().cloned()
+                           .filter(|&a| class_of[a] == class)
+                           .filter(|&a| !fixed(a))
+                           .max_by_key(|&a| (ivs[a].end(), a));
+        match victim {
+          Some(v) if ivs[v].end() > ivs[i].end() => {
+            locs[i] = locs[v];
+            locs[v] = Some(new_slot());
+            active.retain(|&a| a != v);
+            active.push(i);
+          }
+          _ => {
+            locs[i] = Some(new_slot());
+          }
+        }
+    }
+
+    let locations = ivs.iter().zip(locs)
+                       .map(|(iv, loc)| (iv.def(), loc.unwrap()))
+                       .collect();
+    debug!("Linear scan allocated {} values, {} spill slots",
+           ivs.len(), num_slots);
+    Ok(Allocation::new(locations, num_slots))
+}
@@ -0,0 +1,31 @@
+
+mod intervals;
+pub use self::intervals::{ LiveIntervals, LiveInterval };
+
+mod target;
+pub use self::target::{ TargetRegs, RegClass, RegClassId, PhysReg };
+
+mod allocation;
+pub use self::allocation::{ Allocation, Location, SpillSlot };
+
+mod coalesce;
+pub use self::coalesce::Coalescing;
+
+mod hints;
+pub use self::hints::{ RegHint, RegHints };
+
+mod linear_scan;
+pub use self::linear_scan::{
+    AllocError, linear_scan, linear_scan_with_hints
+};
+
+mod call_conv;
+pub use self::call_conv::{
+    CallConv, StackOrder, ArgLocation,
+    AbiLowering, AbiMove, AbiLocation
+};
+
+mod frame;
+pub use self::frame::{
+    FrameLayout, SpillOp, SpillKind, assign_spill_slots
+};
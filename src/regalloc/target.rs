@@ -0,0 +1,107 @@
+
+use crate::ir_types::IrTypeId;
+
+/** A physical register, numbered within its target. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PhysReg(u16);
+
+impl PhysReg {
+    pub fn as_u16(&self) -> u16 { self.0 }
+}
+
+/** Identifies a register class within a target. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct RegClassId(u16);
+
+/**
+ * A register class is a named set of allocatable
+ * registers that values of particular types may be
+ * assigned to.
+ */
+pub struct RegClass {
+    name: String,
+    regs: Vec<PhysReg>
+}
+
+impl RegClass {
+    pub fn name(&self) -> &str { &self.name }
+    pub fn regs(&self) -> &[PhysReg] { &self.regs }
+}
+
+/**
+ * The register description of a target: its physical
+ * registers, the classes they are grouped in, and the
+ * class used to hold values of each IR type.
+ */
+pub struct TargetRegs {
+    reg_names: Vec<String>,
+    classes: Vec<RegClass>,
+    type_classes: Vec<(IrTypeId, RegClassId)>
+}
+
+impl TargetRegs {
+    pub fn new() -> TargetRegs {
+        TargetRegs {
+            reg_names: Vec::new(),
+            classes: Vec::new(),
+            type_classes: Vec::new()
+        }
+    }
+
+    // Add a physical register to the target.
+    pub fn add_reg(&mut self, name: &str) -> PhysReg {
+        assert!(self.reg_names.len() < (u16::MAX as usize));
+        let reg = PhysReg(self.reg_names.len() as u16);
+        self.reg_names.push(name.to_string());
+        reg
+    }
+
+    // Add a register class with the given allocatable
+    // registers, in order of allocation preference.
+    pub fn add_class(&mut self, name: &str, regs: &[PhysReg])
+      -> RegClassId
+    {
+        assert!(self.classes.len() < (u16::MAX as usize));
+        let id = RegClassId(self.classes.len() as u16);
+        self.classes.push(RegClass {
+            name: name.to_string(),
+            regs: regs.to_vec()
+        });
+        id
+    }
+
+    // Set the class used to allocate values of a type.
+    pub fn set_type_class(&mut self, tyid: IrTypeId,
+        class: RegClassId)
+    {
+        self.type_classes.retain(|&(t, _)| t != tyid);
+        self.type_classes.push((tyid, class));
+    }
+
+    pub fn reg_name(&self, reg: PhysReg) -> &str {
+        &self.reg_names[reg.0 as usize]
+    }
+    pub fn num_regs(&self) -> usize { self.reg_names.len() }
+
+    pub fn class(&self, id: RegClassId) -> &RegClass {
+        &self.classes[id.0 as usize]
+    }
+    pub fn num_classes(&self) -> usize { self.classes.len() }
+
+    pub fn class_for(&self, tyid: IrTypeId) -> Option<RegClassId> {
+        self.type_classes.iter()
+            .find(|&&(t, _)| t == tyid)
+            .map(|&(_, c)| c)
+    }
+
+    pub(crate) fn classes(&self) -> &[RegClass] { &self.classes }
+    pub(crate) fn class_index(id: RegClassId) -> usize {
+        id.0 as usize
+    }
+}
+
+impl Default for TargetRegs {
+    fn default() -> TargetRegs { TargetRegs::new() }
+}
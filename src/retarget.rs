@@ -0,0 +1,236 @@
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::def_use::UseSite;
+use crate::changes::Change;
+use crate::text::Rebuild;
+
+/*
+ * Retargeting an edge.  The target at an index of a
+ * block's end instruction is made to go to another
+ * block, passing new phi arguments.  Where the new
+ * target's fields take as many bytes as the old ones,
+ * as fixed-width fields always do for as many
+ * arguments, they are patched in place: the edge is
+ * counted into its new target rather than its old
+ * one, and every id stays as it was.  Otherwise, or
+ * if the old target is left without edges into it,
+ * the graph is rebuilt with the edge changed, and the
+ * ids of its instructions and blocks change, though
+ * its target, operand encoding and block storage are
+ * kept.  The
+ * rebuilt graph drops the blocks no edge leads to any
+ * more, and makes a loop header left without back
+ * edges a plain block.
+ *
+ * The arguments must match the new target's phis in
+ * number and type.  The edge must stay a forward edge
+ * in the RPO, or be a back edge to a loop header that
+ * dominates its source, and its arguments must
+ * dominate the source, as `verify` checks.
+ *
+ * A single phi argument of an edge is replaced in the
+ * same way, patched in place where the new value's
+ * field takes as many bytes as the old one's, and
+ * otherwise by a rebuild.
+ */
+
+impl Graph {
+    /**
+     * Make the edge at `index` of the end of `from` go
+     * to `to`, passing `args` to its phis, and return
+     * whether the graph was rebuilt to do so.  A
+     * rebuild keeps and drops what `split_block` does:
+     * its ids change and its profile, instruction index
+     * and patched patch point bytes are dropped, but its
+     * target, operand encoding, block storage, cold
+     * blocks and snapshots are kept.
+     */
+    pub fn retarget(&mut self, from: BlockId, index: u32, to: BlockId,
+                    args: &[InstrId])
+      -> bool
+    {
+        let view = self.block_view(from)
+                       .unwrap_or_else(|| panic!("no block {}", from));
+        let end = view.end().id();
+        let old = view.end().targets().nth(index as usize)
+                      .unwrap_or_else(|| {
+                          panic!("no edge {} from {}", index, from)
+                      })
+                      .block();
+        let target = self.block_view(to)
+                         .unwrap_or_else(|| panic!("no block {}", to));
+        let phis: Vec<_> = target.instrs()
+                                 .take(target.num_phis() as usize)
+                                 .map(|phi| phi.out_type())
+                                 .collect();
+        let types: Vec<_> = args.iter().map(|&arg| {
+            self.instr_view(arg).and_then(|v| v.out_type())
+        }).collect();
+        assert!(phis == types,
+                "arguments do not match the phis of {}", to);
+
+        // A block left without edges must go, so the
+        // graph is rebuilt without it.
+        let keeps_old = old == to
+          || self.block_view(old).unwrap().input_edges() > 1;
+        let store = self.instr_store();
+        if keeps_old && store.can_retarget(end, index, to, args) {
+            self.record(Change::Edge { from, index });
+            self.instr_store_mut().retarget(end, index, to, args);
+            self.block_store_mut().move_input_edge(old, to);
+            return false;
+        }
+
+        let mut rb = Rebuild::new(self);
+        let args: Vec<String> =
+          args.iter().map(|&arg| rb.value(arg)).collect();
+        let name = rb.block_name(to);
+        let parsed = rb.block_mut(from);
+        let end = parsed.instrs.last_mut().unwrap();
+        end.targets[index as usize] = (name, args);
+        rb.remove_unreachable();
+        self.rebuilt(rb.finish());
+        true
+    }
+
+    /**
+     * Make the edge at `index` of the end of `from`
+     * pass `value` to the target's phi at `phi`, and
+     * return whether the graph was rebuilt to do so,
+     * keeping and dropping what `retarget` does.  The
+     * value must have the phi's type and dominate
+     * `from`.
+     */
+    pub fn set_phi_arg(&mut self, from: BlockId, index: u32, phi: u32,
+                       value: InstrId)
+      -> bool
+    {
+        let view = self.block_view(from)
+                       .unwrap_or_else(|| panic!("no block {}", from));
+        let end = view.end().id();
+        let edge = view.end().targets().nth(index as usize)
+                       .unwrap_or_else(|| {
+                           panic!("no edge {} from {}", index, from)
+                       });
+        let target = self.block_view(edge.block()).unwrap();
+        assert!(phi < target.num_phis(), "no phi {} of {}",
+                phi, target.id());
+        let old = edge.phi_args().nth(phi as usize).unwrap();
+        let ty = target.instrs().nth(phi as usize).unwrap().out_type();
+        assert!(self.instr_view(value).and_then(|v| v.out_type()) == ty,
+                "{} does not match phi {} of {}", value, phi, target.id());
+
+        let site = UseSite::PhiArg { target: index, index: phi };
+        if self.instr_store().can_rewrite_operand(end, site, value) {
+            self.record(Change::Operand {
+                user: end, site, old, new: value
+            });
+            self.instr_store_mut().rewrite_operand(end, site, value);
+            return false;
+        }
+
+        let mut rb = Rebuild::new(self);
+        let value = rb.value(value);
+        let parsed = rb.block_mut(from);
+        let end = parsed.instrs.last_mut().unwrap();
+        end.targets[index as usize].1[phi as usize] = value;
+        self.rebuilt(rb.finish());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::{ BlockId, BlockStorage };
+    use crate::instr::{ InstrId, OperandEncoding };
+    use crate::graph::Graph;
+    use crate::text::parse_with;
+
+    const DIAMOND: &str = "\
+block0:
+  %0 = const.i32 1
+  %1 = const.i32 2
+  %2 = lt.i32 %0, %1
+  branch %2, block1, block2
+block1:
+  jump block3(%0)
+block2:
+  jump block3(%1)
+block3(%3: i32):
+  ret.i32 %3
+";
+
+    fn ids(graph: &Graph) -> (Vec<BlockId>, Vec<InstrId>) {
+        let blocks = graph.rpo_block_views().map(|b| b.id()).collect();
+        let values = graph.rpo_block_views()
+                          .flat_map(|b| b.instrs().collect::<Vec<_>>())
+                          .map(|i| i.id())
+                          .collect();
+        (blocks, values)
+    }
+
+    #[test]
+    fn retarget_in_place() {
+        let mut graph = parse_with(DIAMOND, OperandEncoding::Fixed,
+                                   BlockStorage::Contiguous);
+        let (blocks, values) = ids(&graph);
+        assert!(!graph.retarget(blocks[1], 0, blocks[3], &[values[1]]));
+        assert_eq!(ids(&graph), (blocks, values));
+        assert!(graph.print_ir().contains("block1:\n  jump block3(%1)"));
+        assert!(graph.verify().is_ok());
+    }
+
+    #[test]
+    fn retarget_rebuilds_dropping_old_target() {
+        let mut graph = parse_with(DIAMOND, OperandEncoding::Delta,
+                                   BlockStorage::Chunked);
+        let (blocks, values) = ids(&graph);
+        assert!(graph.retarget(blocks[0], 0, blocks[3], &[values[0]]));
+        assert_eq!(graph.print_ir(), "\
+block0:
+  %0 = const.i32 1
+  %1 = const.i32 2
+  %2 = lt.i32 %0, %1
+  branch %2, block2(%0), block1
+block1:
+  jump block2(%1)
+block2(%3: i32):
+  ret.i32 %3
+");
+        assert_eq!(graph.operand_encoding(), OperandEncoding::Delta);
+        assert_eq!(graph.block_storage(), BlockStorage::Chunked);
+        assert!(graph.verify().is_ok());
+    }
+
+    #[test]
+    fn set_phi_arg_in_place() {
+        let mut graph = parse_with(DIAMOND, OperandEncoding::Fixed,
+                                   BlockStorage::Contiguous);
+        let (blocks, values) = ids(&graph);
+        assert!(!graph.set_phi_arg(blocks[2], 0, 0, values[0]));
+        assert_eq!(ids(&graph), (blocks, values));
+        assert!(graph.print_ir().contains("block2:\n  jump block3(%0)"));
+        assert!(graph.verify().is_ok());
+    }
+
+    #[test]
+    fn set_phi_arg_rebuilds_for_a_wider_field() {
+        // The first value is too far back from the jump
+        // for its delta to fit the last value's field.
+        let mut text = String::from("block0:\n");
+        for i in 0 .. 64 {
+            text.push_str(&format!("  %{} = const.i32 {}\n", i, i));
+        }
+        text.push_str("  jump block1(%63)\n");
+        text.push_str("block1(%64: i32):\n  ret.i32 %64\n");
+        let mut graph = parse_with(&text, OperandEncoding::Delta,
+                                   BlockStorage::Chunked);
+        let (blocks, values) = ids(&graph);
+        assert!(graph.set_phi_arg(blocks[0], 0, 0, values[0]));
+        assert!(graph.print_ir().contains("jump block1(%0)"));
+        assert_eq!(graph.operand_encoding(), OperandEncoding::Delta);
+        assert_eq!(graph.block_storage(), BlockStorage::Chunked);
+        assert!(graph.verify().is_ok());
+    }
+}
@@ -0,0 +1,296 @@
+
+use std::collections::HashMap;
+
+use crate::block::{ Block, BlockId };
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::Opcode;
+
+/**
+ * A per-opcode table of instruction latencies,
+ * measured in abstract cycles.  The latency of an
+ * instruction is the number of cycles after its
+ * issue before its result can be consumed.
+ */
+#[derive(Clone, Debug)]
+pub struct LatencyTable {
+    latencies: Vec<u32>
+}
+
+impl LatencyTable {
+    const NUM_OPCODES: usize = (Opcode::MAX as usize) + 1;
+
+    // Create a latency table with every opcode
+    // assigned the given default latency.
+    pub fn new(default_latency: u32) -> LatencyTable {
+        let latencies = vec![default_latency; Self::NUM_OPCODES];
+        LatencyTable { latencies }
+    }
+
+    pub fn set(&mut self, opcode: Opcode, latency: u32) {
+        self.latencies[opcode.into_u8() as usize] = latency;
+    }
+    pub fn get(&self, opcode: Opcode) -> u32 {
+        self.latencies[opcode.into_u8() as usize]
+    }
+}
+
+impl Default for LatencyTable {
+    fn default() -> LatencyTable { LatencyTable::new(1) }
+}
+
+/** An instruction together with its issue cycle. */
+#[derive(Clone, Copy, Debug)]
+pub struct ScheduledInstr {
+    instr: InstrId,
+    cycle: u32
+}
+
+impl ScheduledInstr {
+    pub fn instr(&self) -> InstrId { self.instr }
+    pub fn cycle(&self) -> u32 { self.cycle }
+}
+
+/**
+ * The schedule of a single block.  Phis lead the
+ * block at cycle 0 and the end instruction always
+ * comes last.
+ */
+pub struct BlockSchedule {
+    block: BlockId,
+    instrs: Vec<ScheduledInstr>,
+    length: u32
+}
+
+impl BlockSchedule {
+    pub fn block(&self) -> BlockId { self.block }
+    pub fn instrs(&self) -> &[ScheduledInstr] { &self.instrs }
+
+    // The number of cycles from the start of the
+    // block until the end instruction has issued.
+    pub fn length(&self) -> u32 { self.length }
+}
+
+/**
+ * A schedule for a whole graph, with one block
+ * schedule per block in RPO.
+ */
+pub struct Schedule {
+    blocks: Vec<BlockSchedule>
+}
+
+impl Schedule {
+    pub(crate) fn compute(graph: &Graph,
+        latencies: &LatencyTable)
+      -> Schedule
+    {
+        let blocks =
+          graph.block_store().iter_rpo_blocks()
+               .map(|bl| schedule_block(graph, bl, latencies))
+               .collect();
+        Schedule { blocks }
+    }
+
+    pub fn blocks(&self) -> &[BlockSchedule] { &self.blocks }
+
+    pub fn block(&self, id: BlockId) -> Option<&BlockSchedule> {
+        self.blocks.iter().find(|bs| bs.block == id)
+    }
+}
+
+// A node in the dependency dag for a block.
+struct Node {
+    instr: InstrId,
+    latency: u32,
+
+    // Number of unscheduled predecessors.
+    pending: u32,
+
+    // Earliest cycle at which all inputs are ready.
+    ready_at: u32,
+
+    // The latency-weighted height of the node
+    // (longest path to the end of the block).
+    height: u32,
+
+    succs: Vec<usize>
+}
+
+fn schedule_block(graph: &Graph, block: &Block,
+    latencies: &LatencyTable)
+  -> BlockSchedule
+{
+    let mut instrs = Vec::new();
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut index: HashMap<InstrId, usize> = HashMap::new();
+    let mut last_effect: Option<usize> = None;
+    let mut end_instr: Option<(InstrId, Vec<InstrId>)> = None;
+
+    for info in graph.block_instrs(block) {
+        let op = info.op();
+        let instr = info.defn().instr_id();
+
+        // Phis are pinned to the top of the block,
+        // and available from the first cycle.
+        if op.opcode() == Opcode::Phi {
+            instrs.push(ScheduledInstr { instr, cycle: 0 });
+            continue;
+        }
+
+        // The end instruction is pinned to the bottom,
+        // after its operands and the phi args it passes
+        // to its targets.
+        if op.terminal() {
+            let inputs = info.inputs_iter()
+                             .chain(info.targets_iter()
+                                        .flat_map(|t| t.phi_args()))
+                             .map(|d| d.instr_id())
+                             .collect();
+            end_instr = Some((instr, inputs));
+            continue;
+        }
+
+        let idx = nodes.len();
+        let latency = latencies.get(op.opcode());
+        nodes.push(Node {
+            instr, latency, pending: 0, ready_at: 0,
+            height: 0, succs: Vec::new()
+        });
+        index.insert(instr, idx);
+
+        // Data dependencies on earlier instructions
+        // within this block.
+        let mut preds: Vec<usize> =
+          info.inputs_iter()
+              .filter_map(|d| index.get(&d.instr_id()).cloned())
+              .filter(|&p| p != idx)
+              .collect();
+
+        // Effectful instructions keep their relative
+        // order.
+        if op.effectful() {
+            if let Some(prev) = last_effect {
+                preds.push(prev);
+            }
+            last_effect = Some(idx);
+        }
+
+        preds.sort_unstable();
+        preds.dedup();
+        for p in preds {
+            nodes[p].succs.push(idx);
+            nodes[idx].pending += 1;
+        }
+    }
+
+    // Compute heights in reverse order.  Dependencies
+    // always point backwards in stream order, so this
+    // visits every successor before its predecessors.
+    for i in (0 .. nodes.len()).rev() {
+        let max_succ = nodes[i].succs.iter()
+                               .map(|&s| nodes[s].height)
+                               .max()
+                               .unwrap_or(0);
+        nodes[i].height = nodes[i].latency + max_succ;
+    }
+
+    // Cycle-driven list scheduling with a single
+    // issue slot per cycle.
+    let mut ready: Vec<usize> =
+      (0 .. nodes.len()).filter(|&i| nodes[i].pending == 0)
+                        .collect();
+    let mut cycle: u32 = 0;
+    let mut finish: u32 = 0;
+    let mut issued_at: HashMap<InstrId, u32> = HashMap::new();
+
+    while !ready.is_empty() {
+        // Pick the highest available node, breaking ties
+        // by original order.
+        let pick = ready.iter().enumerate()
+          .filter(|&(_, &n)| nodes[n].ready_at <= cycle)
+          .max_by(|&(_, &a), &(_, &b)| {
+              nodes[a].height.cmp(&nodes[b].height)
+                .then(b.cmp(&a))
+          })
+          .map(|(pos, _)| pos);
+
+        let pos = match pick {
+          Some(pos) => pos,
+          None => {
+            // Nothing is available yet; stall until the
+            // earliest ready node.
+            cycle = ready.iter()
+                         .map(|&n| nodes[n].ready_at)
+                         .min()
+                         .unwrap();
+            continue;
+          }
+        };
+
+        let n = ready.swap_remove(pos);
+        let done = cycle + nodes[n].latency;
+        instrs.push(ScheduledInstr {
+            instr: nodes[n].instr, cycle
+        });
+        issued_at.insert(nodes[n].instr, done);
+        finish = finish.max(done);
+
+        for k in 0 .. nodes[n].succs.len() {
+            let s = nodes[n].succs[k];
+            nodes[s].ready_at = nodes[s].ready_at.max(done);
+            nodes[s].pending -= 1;
+            if nodes[s].pending == 0 {
+                ready.push(s);
+            }
+        }
+        cycle += 1;
+    }
+    debug_assert!(issued_at.len() == nodes.len());
+
+    // Place the end instruction once its inputs are
+    // ready.
+    let (end_id, end_inputs) =
+      end_instr.expect("Block without end instruction.");
+    let end_cycle = end_inputs.iter()
+                              .filter_map(|i| issued_at.get(i))
+                              .cloned()
+                              .fold(cycle, u32::max);
+    instrs.push(ScheduledInstr { instr: end_id, cycle: end_cycle });
+
+    debug!("Schedule block {} len={} crit={}",
+           block.id(), end_cycle + 1, finish);
+
+    BlockSchedule {
+        block: block.id(),
+        instrs,
+        length: end_cycle + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graph::Graph;
+    use crate::ops::Opcode;
+    use super::LatencyTable;
+
+    #[test]
+    fn end_waits_for_phi_args() {
+        let graph = Graph::parse_ir("\
+block0:
+  %0 = const.i32 3
+  %1 = const.i32 5
+  %2 = mul.i32 %0, %1
+  jump block1(%2)
+block1(%3: i32):
+  ret.i32 %3
+").unwrap();
+        let mut latencies = LatencyTable::new(1);
+        latencies.set(Opcode::Bini, 10);
+        let schedule = graph.schedule(&latencies);
+        let block = &schedule.blocks()[0];
+        let mul = block.instrs()[2];
+        let jump = *block.instrs().last().unwrap();
+        assert!(jump.cycle() >= mul.cycle() + 10);
+        assert_eq!(block.length(), jump.cycle() + 1);
+    }
+}
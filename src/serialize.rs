@@ -0,0 +1,680 @@
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+
+use crate::block::{ BlockId, BlockStore };
+use crate::instr::{
+    InstrId, InstrStore, InstrBytes, OperandEncoding
+};
+use crate::graph::Graph;
+use crate::decode::{ ByteReader, DecodeError, decode_instrs_pooled };
+use crate::const_pool::ConstPool;
+use crate::ops::Op;
+use crate::patch::PatchPoint;
+use crate::invariants::InvariantError;
+use crate::verify::VerifyError;
+use crate::target::TargetDesc;
+use crate::leb128;
+use crate::crc32::crc32;
+#[cfg(feature = "compress")]
+use crate::compress;
+
+/*
+ * The serialized form of a graph is:
+ *
+ *   magic           4 bytes, "CGIR"
+ *   version         1 byte
+ *   encoding        1 byte, how the stream writes
+ *                     operands: 0 absolute, 1 delta,
+ *                     2 fixed-width, 3 fixed-width
+ *                     with 64-bit offsets
+ *   target          3 bytes: pointer bytes, byte
+ *                     order, 0 little or 1 big, and
+ *                     largest alignment
+ *   num_blocks      leb128
+ *   num_instrs      leb128
+ *   num_bytes       leb128
+ *   block decls     num_blocks entries, in id order:
+ *                     kind byte, with the high bit set
+ *                     for cold blocks, then leb128
+ *                     num_phis for plain and loop
+ *                     blocks
+ *   block ranges    num_blocks entries, in RPO:
+ *                     leb128 block id, first instr,
+ *                     last instr
+ *   const pool      leb128 num_consts, then each
+ *                     pooled constant as leb128
+ *   instr stream    num_bytes bytes
+ *   checksum        4 bytes, little-endian CRC-32 of
+ *                     all the bytes before it
+ */
+const MAGIC: [u8; 4] = *b"CGIR";
+
+/*
+ * With the `compress` feature, a serialized graph can
+ * be written compressed, as:
+ *
+ *   magic           4 bytes, "CGIZ"
+ *   raw_len         leb128, the length expanded
+ *   payload         the serialized graph, compressed
+ *
+ * Loading expands compressed bytes transparently, and
+ * the checksum of the expanded graph is checked as
+ * ever.
+ */
+const COMPRESSED_MAGIC: [u8; 4] = *b"CGIZ";
+
+// Compressed graphs larger than this expanded are
+// rejected before expanding them.
+#[cfg(feature = "compress")]
+const MAX_EXPANDED_LEN: u64 = 1 << 28;
+pub(crate) const FORMAT_VERSION: u8 = 7;
+
+const CHECKSUM_LEN: usize = 4;
+
+const KIND_PLAIN: u8 = 0;
+const KIND_LOOP: u8 = 1;
+const KIND_START: u8 = 2;
+const KIND_COLD: u8 = 0x80;
+
+/** A reason serialized bytes could not be loaded. */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum LoadError {
+    // The bytes do not start with the format magic.
+    BadMagic,
+
+    // The bytes were written in an unsupported format
+    // version.
+    BadVersion { found: u8 },
+
+    // The bytes are compressed, and the crate was built
+    // without the `compress` feature.
+    Compressed,
+
+    // The compressed payload does not expand to the
+    // declared length.
+    BadCompression { offset: usize },
+
+    // The header names no operand encoding.
+    BadEncoding { found: u8 },
+
+    // The header describes no target.
+    BadTarget { found: [u8; 3] },
+
+    // The checksum does not match the bytes it covers.
+    Checksum { expected: u32, found: u32 },
+
+    // The header, block table or instruction stream
+    // could not be decoded.
+    Decode(DecodeError),
+
+    // An entry of the block table is malformed.
+    BadBlockTable { entry: u32 },
+
+    // A descriptor of a module's type table is
+    // malformed.
+    BadTypeTable { entry: u32 },
+
+    // The stream holds a different number of
+    // instructions than the header declares.
+    InstrCount { expected: u32, found: u32 },
+
+    // Bytes follow the instruction stream.
+    TrailingBytes { offset: usize },
+
+    // The rebuilt stores are inconsistent.
+    Invariant(InvariantError),
+
+    // The rebuilt graph is not well formed.
+    Verify(Vec<VerifyError>),
+
+    // A graph name of a module is not UTF-8.
+    BadName { offset: usize },
+
+    // A graph of a module could not be loaded.  Offsets
+    // in the error are relative to the graph's image.
+    ModuleGraph { index: u32, error: Box<LoadError> }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match self {
+          LoadError::BadMagic => write!(f, "bad magic"),
+          LoadError::BadVersion { found } =>
+            write!(f, "unsupported format version {}", found),
+          LoadError::Compressed =>
+            write!(f, "compressed, without compression support"),
+          LoadError::BadCompression { offset } =>
+            write!(f, "bad compressed data at offset {}", offset),
+          LoadError::BadEncoding { found } =>
+            write!(f, "unknown operand encoding {}", found),
+          LoadError::BadTarget { found } =>
+            write!(f, "unknown target {:?}", found),
+          LoadError::Checksum { expected, found } =>
+            write!(f, "checksum {:#010x}, expected {:#010x}",
+                   found, expected),
+          LoadError::Decode(err) => write!(f, "{}", err),
+          LoadError::BadBlockTable { entry } =>
+            write!(f, "bad block table entry {}", entry),
+          LoadError::BadTypeTable { entry } =>
+            write!(f, "bad type table entry {}", entry),
+          LoadError::InstrCount { expected, found } =>
+            write!(f, "stream holds {} instrs, expected {}",
+                   found, expected),
+          LoadError::TrailingBytes { offset } =>
+            write!(f, "trailing bytes at offset {}", offset),
+          LoadError::Invariant(err) => write!(f, "{}", err),
+          LoadError::Verify(errs) => {
+            write!(f, "{} verify errors", errs.len())?;
+            if let Some(err) = errs.first() {
+                write!(f, ", first: {}", err)?;
+            }
+            Ok(())
+          }
+          LoadError::BadName { offset } =>
+            write!(f, "graph name at offset {} is not UTF-8", offset),
+          LoadError::ModuleGraph { index, error } =>
+            write!(f, "module graph {}: {}", index, error)
+        }
+    }
+}
+
+impl LoadError {
+    // The offset of the problem in the loaded bytes,
+    // where it is known.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+          LoadError::BadMagic | LoadError::Compressed => Some(0),
+          LoadError::BadVersion { .. } => Some(MAGIC.len()),
+          LoadError::BadEncoding { .. } => Some(MAGIC.len() + 1),
+          LoadError::BadTarget { .. } => Some(MAGIC.len() + 2),
+          LoadError::BadCompression { offset } => Some(*offset),
+          LoadError::Decode(err) => Some(err.offset()),
+          LoadError::TrailingBytes { offset } => Some(*offset),
+          LoadError::BadName { offset } => Some(*offset),
+          _ => None
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+impl From<DecodeError> for LoadError {
+    fn from(err: DecodeError) -> LoadError {
+        LoadError::Decode(err)
+    }
+}
+
+// A block table range entry.
+struct BlockRange {
+    block: BlockId,
+    first: InstrId,
+    last: InstrId
+}
+
+impl Graph {
+    /**
+     * Serialize the graph with a versioned header, the
+     * block table and the instruction stream.
+     */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut pool = Vec::new();
+        write_const_pool(self.const_pool(), &mut pool);
+        self.write_image(&MAGIC, &pool)
+    }
+
+    // Write the graph in the serialized form, under the
+    // given magic and with the given const pool section.
+    pub(crate) fn write_image(&self, magic: &[u8; 4], pool: &[u8])
+      -> Vec<u8>
+    {
+        let store = self.block_store();
+        let stream = self.instr_bytes();
+
+        let mut out =
+          Vec::with_capacity(stream.len() + CHECKSUM_LEN + 64);
+        out.extend_from_slice(magic);
+        out.push(FORMAT_VERSION);
+        out.push(self.operand_encoding().to_u8());
+        out.extend_from_slice(&self.target().to_bytes());
+        let num_blocks = store.total_blocks() as u64;
+        let num_instrs = self.instr_store().num_instrs();
+        leb128::write_leb128u(num_blocks, &mut out);
+        leb128::write_leb128u(num_instrs, &mut out);
+        leb128::write_leb128u(stream.len() as u64, &mut out);
+
+        for block in store.iter_blocks() {
+            let cold = if block.is_cold() { KIND_COLD } else { 0 };
+            if block.is_start() {
+                out.push(KIND_START | cold);
+                continue;
+            }
+            let kind =
+              if block.is_loop() { KIND_LOOP } else { KIND_PLAIN };
+            out.push(kind | cold);
+            leb128::write_leb128u(block.num_phis(), &mut out);
+        }
+        for block in store.iter_rpo_blocks() {
+            for v in &[block.id().as_u32() as u64,
+                       block.first_instr().offset() as u64,
+                       block.last_instr().offset() as u64]
+            {
+                leb128::write_leb128u(*v, &mut out);
+            }
+        }
+        out.extend_from_slice(pool);
+
+        out.extend_from_slice(stream);
+        append_checksum(&mut out);
+        out
+    }
+
+    /**
+     * Load a graph written by `to_bytes`.  The bytes are
+     * untrusted: the checksum is checked first, then the
+     * stream is decoded with the safe decoder, and the
+     * rebuilt graph is checked with `check_invariants`
+     * and `verify` before being returned.
+     */
+    pub fn from_bytes(bytes: &[u8]) -> Result<Graph, LoadError> {
+        let expanded;
+        let bytes = if bytes.starts_with(&COMPRESSED_MAGIC) {
+            expanded = expand(bytes) ?;
+            &expanded[..]
+        } else {
+            bytes
+        };
+        Graph::load(bytes, |start, end| {
+            InstrBytes::Owned(bytes[start .. end].to_vec())
+        })
+    }
+
+    /**
+     * Load a graph written by `to_bytes` from a shared
+     * buffer, such as a `MappedFile`, as `from_bytes`
+     * does.  Rather than being copied, the instruction
+     * stream is read in place from the buffer, which the
+     * graph keeps alive; it is copied out only if the
+     * graph is later appended to.  Compressed bytes are
+     * expanded and copied, as by `from_bytes`.
+     */
+    pub fn from_shared_bytes<B>(buf: Arc<B>) -> Result<Graph, LoadError>
+      where B: AsRef<[u8]> + Send + Sync + RefUnwindSafe + 'static
+    {
+        if (*buf).as_ref().starts_with(&COMPRESSED_MAGIC) {
+            return Graph::from_bytes((*buf).as_ref());
+        }
+        let shared: Arc<dyn AsRef<[u8]> + Send + Sync + RefUnwindSafe> =
+          buf.clone();
+        Graph::load((*buf).as_ref(), move |start, end| {
+            InstrBytes::Shared { buf: shared, start, end }
+        })
+    }
+
+    // Load a serialized graph, with `adopt` giving the
+    // backing of the instruction stream found at the
+    // given range of `bytes`.
+    fn load<F>(bytes: &[u8], adopt: F) -> Result<Graph, LoadError>
+      where F: FnOnce(usize, usize) -> InstrBytes
+    {
+        Graph::load_image(bytes, &MAGIC, read_const_pool, adopt)
+    }
+
+    // Load a graph written by `write_image` under the
+    // given magic, with `read_pool` reading its const
+    // pool section.
+    pub(crate) fn load_image<P, F>(bytes: &[u8], magic: &[u8; 4],
+                                   read_pool: P, adopt: F)
+      -> Result<Graph, LoadError>
+      where P: FnOnce(&mut ByteReader) -> Result<Vec<u64>, LoadError>,
+            F: FnOnce(usize, usize) -> InstrBytes
+    {
+        if !bytes.starts_with(magic) {
+            return Err(LoadError::BadMagic);
+        }
+        let mut reader = ByteReader::at(bytes, magic.len());
+        let version = reader.read_u8() ?;
+        if version != FORMAT_VERSION {
+            return Err(LoadError::BadVersion { found: version });
+        }
+
+        // The checksum covers everything before it, and
+        // the rest of the format is read from within it.
+        let bytes = checked_body(bytes, reader.offset()) ?;
+        let mut reader = ByteReader::at(bytes, reader.offset());
+
+        let code = reader.read_u8() ?;
+        let encoding = OperandEncoding::from_u8(code)
+          .ok_or(LoadError::BadEncoding { found: code }) ?;
+        let found = [reader.read_u8() ?, reader.read_u8() ?,
+                     reader.read_u8() ?];
+        let target = TargetDesc::from_bytes(found)
+          .ok_or(LoadError::BadTarget { found }) ?;
+
+        let num_blocks = reader.read_leb128u32() ?;
+        let num_instrs = reader.read_leb128u32() ?;
+        let num_bytes = reader.read_leb128_offset() ? as usize;
+
+        // Each block takes at least a byte in each table,
+        // so counts beyond the remaining bytes can be
+        // rejected before allocating for them.
+        if (num_blocks as usize) > reader.remaining() {
+            return Err(DecodeError::Truncated {
+                offset: reader.offset()
+            }.into());
+        }
+
+        let mut kinds = Vec::with_capacity(num_blocks as usize);
+        let mut cold = Vec::new();
+        for entry in 0 .. num_blocks {
+            let byte = reader.read_u8() ?;
+            let kind = byte & !KIND_COLD;
+            let num_phis = match kind {
+              KIND_START => 0,
+              KIND_PLAIN | KIND_LOOP =>
+                reader.read_leb128u32() ?,
+              _ => return Err(LoadError::BadBlockTable { entry })
+            };
+            kinds.push((kind, num_phis));
+            if byte & KIND_COLD != 0 {
+                cold.push(BlockId::new(entry));
+            }
+        }
+
+        let mut ranges = Vec::with_capacity(num_blocks as usize);
+        for _ in 0 .. num_blocks {
+            let block = BlockId::new(reader.read_leb128u32() ?);
+            let first = reader.read_instr_id() ?;
+            let last = reader.read_instr_id() ?;
+            ranges.push(BlockRange { block, first, last });
+        }
+        let consts = read_pool(&mut reader) ?;
+
+        let stream_start = reader.offset();
+        if num_bytes > reader.remaining() {
+            return Err(DecodeError::Truncated {
+                offset: stream_start
+            }.into());
+        }
+        let stream_end = stream_start + num_bytes;
+        if stream_end < bytes.len() {
+            return Err(LoadError::TrailingBytes {
+                offset: stream_end
+            });
+        }
+        let stream = &bytes[stream_start .. stream_end];
+
+        // Report offsets relative to the whole input.
+        let decoded = decode_instrs_pooled(stream, encoding, &consts)
+          .map_err(|err| err.offset_by(stream_start)) ?;
+        if decoded.len() != (num_instrs as usize) {
+            return Err(LoadError::InstrCount {
+                expected: num_instrs,
+                found: decoded.len() as u32
+            });
+        }
+
+        // Block ranges must start and end at instruction
+        // boundaries for the graph's readers to stay
+        // within the stream.
+        let starts: Vec<InstrId> =
+          decoded.iter().map(|d| d.id()).collect();
+        let is_start =
+          |id: InstrId| starts.binary_search(&id).is_ok();
+        for (entry, range) in ranges.iter().enumerate() {
+            if !is_start(range.first) || !is_start(range.last) {
+                return Err(LoadError::BadBlockTable {
+                    entry: num_blocks + entry as u32
+                });
+            }
+        }
+
+        let mut block_store =
+          rebuild_blocks(&kinds, &ranges, num_instrs) ?;
+        for &block in &cold {
+            block_store.mark_cold(block);
+        }
+        let patch_points = decoded.iter().filter_map(|d| {
+            match d.op() {
+              Op::PatchPoint(op) => {
+                Some(PatchPoint::in_stream(op, d.id()))
+              }
+              _ => None
+            }
+        }).collect();
+        let instr_store =
+          InstrStore::from_bytes(adopt(stream_start, stream_end),
+                                 num_instrs, encoding,
+                                 ConstPool::from_values(consts),
+                                 patch_points)
+            .ok_or(LoadError::Decode(DecodeError::Overflow {
+                offset: stream_start
+            })) ?;
+
+        let mut graph = Graph::new(instr_store, block_store);
+        graph.set_target(target);
+        graph.check_invariants().map_err(LoadError::Invariant) ?;
+        graph.verify().map_err(LoadError::Verify) ?;
+
+        // The edges into each block are counted by the
+        // builder, and not serialized.
+        let targets: Vec<BlockId> = graph.rpo_block_views()
+          .flat_map(|b| b.succs())
+          .collect();
+        graph.block_store_mut().count_input_edges(&targets);
+        Ok(graph)
+    }
+
+    /**
+     * Serialize the graph as `to_bytes` does, then
+     * compress it.  `from_bytes` and the other loaders
+     * expand it again.
+     */
+    #[cfg(feature = "compress")]
+    pub fn to_compressed_bytes(&self) -> Vec<u8> {
+        let raw = self.to_bytes();
+        let mut out = Vec::with_capacity(raw.len() / 2 + 16);
+        out.extend_from_slice(&COMPRESSED_MAGIC);
+        leb128::write_leb128u(raw.len() as u64, &mut out);
+        compress::compress(&raw, &mut out);
+        out
+    }
+
+    /**
+     * Write the serialized graph to `w`, as by
+     * `to_bytes`.
+     */
+    pub fn write_to<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(&self.to_bytes())
+    }
+
+    /**
+     * Load a graph from everything `r` yields up to end
+     * of file; wrap a stream with `Read::take` to read a
+     * graph of known length from it.  Bytes that do not
+     * load fail with `InvalidData`, carrying the
+     * `LoadError`.
+     */
+    pub fn read_from<R: io::Read>(mut r: R) -> io::Result<Graph> {
+        let mut bytes = Vec::new();
+        r.read_to_end(&mut bytes) ?;
+        Graph::from_bytes(&bytes).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        })
+    }
+}
+
+// Expand a compressed graph, giving its serialized
+// bytes.  Offsets in errors are relative to the
+// compressed bytes.
+#[cfg(feature = "compress")]
+fn expand(bytes: &[u8]) -> Result<Vec<u8>, LoadError> {
+    let mut reader = ByteReader::at(bytes, COMPRESSED_MAGIC.len());
+    let offset = reader.offset();
+    let len = reader.read_leb128u() ?;
+    if len > MAX_EXPANDED_LEN {
+        return Err(LoadError::BadCompression { offset });
+    }
+    let start = reader.offset();
+    compress::expand(&bytes[start ..], len as usize).map_err(|err| {
+        match err {
+          LoadError::Decode(err) => {
+            LoadError::Decode(err.offset_by(start))
+          }
+          LoadError::BadCompression { offset } => {
+            LoadError::BadCompression { offset: start + offset }
+          }
+          LoadError::TrailingBytes { offset } => {
+            LoadError::TrailingBytes { offset: start + offset }
+          }
+          err => err
+        }
+    })
+}
+
+#[cfg(not(feature = "compress"))]
+fn expand(_bytes: &[u8]) -> Result<Vec<u8>, LoadError> {
+    Err(LoadError::Compressed)
+}
+
+pub(crate) fn append_checksum(out: &mut Vec<u8>) {
+    let checksum = crc32(out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+}
+
+// Check the checksum trailing `bytes`, giving the bytes
+// it covers.  The first `header_len` bytes are known
+// to be there.
+pub(crate) fn checked_body(bytes: &[u8], header_len: usize)
+  -> Result<&[u8], LoadError>
+{
+    if bytes.len() < header_len + CHECKSUM_LEN {
+        return Err(DecodeError::Truncated {
+            offset: bytes.len()
+        }.into());
+    }
+    let body_len = bytes.len() - CHECKSUM_LEN;
+    let mut trailer = [0_u8; CHECKSUM_LEN];
+    trailer.copy_from_slice(&bytes[body_len ..]);
+    let expected = u32::from_le_bytes(trailer);
+    let found = crc32(&bytes[.. body_len]);
+    if found != expected {
+        return Err(LoadError::Checksum { expected, found });
+    }
+    Ok(&bytes[.. body_len])
+}
+
+// Write the constant pool section.
+pub(crate) fn write_const_pool(pool: &[u64], out: &mut Vec<u8>) {
+    leb128::write_leb128u(pool.len() as u64, out);
+    for &value in pool {
+        leb128::write_leb128u(value, out);
+    }
+}
+
+// Read a count of items that each take at least a
+// byte, rejecting counts beyond the remaining bytes
+// before anything is allocated for them.
+pub(crate) fn read_count(reader: &mut ByteReader)
+  -> Result<usize, LoadError>
+{
+    let offset = reader.offset();
+    let count = reader.read_leb128u32() ? as usize;
+    if count > reader.remaining() {
+        return Err(DecodeError::Truncated { offset }.into());
+    }
+    Ok(count)
+}
+
+fn read_const_pool(reader: &mut ByteReader)
+  -> Result<Vec<u64>, LoadError>
+{
+    let num_consts = read_count(reader) ?;
+    let mut consts = Vec::with_capacity(num_consts);
+    for _ in 0 .. num_consts {
+        consts.push(reader.read_leb128u() ?);
+    }
+    Ok(consts)
+}
+
+// Rebuild the block store by replaying declarations in
+// id order and block entry in RPO, as the builder does.
+fn rebuild_blocks(kinds: &[(u8, u32)],
+                  ranges: &[BlockRange],
+                  num_instrs: u32)
+  -> Result<BlockStore, LoadError>
+{
+    let num_blocks = kinds.len() as u32;
+    if num_blocks > BlockStore::MAX_DECL_BLOCKS {
+        return Err(LoadError::BadBlockTable { entry: num_blocks });
+    }
+
+    // A new store has the start block declared and
+    // entered at the first instruction.
+    match (kinds.first(), ranges.first()) {
+      (Some(&(KIND_START, _)), Some(range))
+        if range.block.as_u32() == 0
+          && range.first.offset() == 0 => {}
+      _ => return Err(LoadError::BadBlockTable { entry: 0 })
+    }
+
+    // Every phi is an instruction, and loop and start
+    // numbers must fit their fields.
+    let (mut phis, mut loops, mut starts) = (0_u64, 0_u32, 0_u32);
+    for (entry, &(kind, num_phis)) in kinds.iter().enumerate() {
+        phis += num_phis as u64;
+        loops += (kind == KIND_LOOP) as u32;
+        starts += (kind == KIND_START) as u32;
+        if phis > (num_instrs as u64)
+          || loops >= (u16::MAX as u32)
+          || starts > (u16::MAX as u32)
+        {
+            return Err(LoadError::BadBlockTable {
+                entry: entry as u32
+            });
+        }
+    }
+
+    let mut store = BlockStore::new();
+    for &(kind, num_phis) in &kinds[1 ..] {
+        match kind {
+          KIND_START => { store.decl_start_block(); }
+          KIND_LOOP => { store.decl_loop_head(num_phis); }
+          _ => { store.decl_plain_block(num_phis); }
+        }
+    }
+
+    let mut entered = vec![false; kinds.len()];
+    entered[0] = true;
+    for (i, range) in ranges.iter().enumerate() {
+        let bi = range.block.as_u32();
+        let entry = num_blocks + i as u32;
+        if bi >= num_blocks || (i > 0 && entered[bi as usize]) {
+            return Err(LoadError::BadBlockTable { entry });
+        }
+
+        // The RPO index is rebuilt from the order of the
+        // blocks' first instructions, which the table
+        // must already be in.
+        if i > 0 && range.first <= ranges[i - 1].first {
+            return Err(LoadError::BadBlockTable { entry });
+        }
+        entered[bi as usize] = true;
+        unsafe {
+            if i > 0 {
+                store.enter_block(range.block, range.first);
+            }
+            store.finish_block(range.block, range.last);
+            if kinds[bi as usize].0 == KIND_LOOP {
+                store.finish_loop(range.block);
+            }
+        }
+    }
+    Ok(store)
+}
@@ -0,0 +1,206 @@
+
+use std::fmt;
+
+use crate::defn::Defn;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+
+/**
+ * An abstract slot of the frontend's interpreter frame:
+ * the receiver, a numbered local, or a position on the
+ * operand stack, counted from the bottom.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FrameSlot {
+    Receiver,
+    Local(u32),
+    Stack(u32)
+}
+
+impl fmt::Display for FrameSlot {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match *self {
+          FrameSlot::Receiver => write!(f, "this"),
+          FrameSlot::Local(i) => write!(f, "local{}", i),
+          FrameSlot::Stack(i) => write!(f, "stack{}", i)
+        }
+    }
+}
+
+/**
+ * The frame state at a side exit: the frontend's
+ * resume point `pc`, and the value held by each
+ * live slot of the frame when the exit is taken.
+ * Deoptimizing from the exit rebuilds the
+ * interpreter frame from these values.  Slots without
+ * an entry are dead at the resume point.
+ */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct Snapshot {
+    pc: u32,
+
+    // The live slots and their values, in slot order.
+    entries: Vec<(FrameSlot, InstrId)>,
+
+    // The depth of the operand stack.
+    stack_depth: u32
+}
+
+impl Snapshot {
+    pub(crate) fn new(pc: u32, entries: Vec<(FrameSlot, InstrId)>,
+                      stack_depth: u32)
+      -> Snapshot
+    {
+        Snapshot { pc, entries, stack_depth }
+    }
+
+    pub fn pc(&self) -> u32 { self.pc }
+    pub fn entries(&self) -> &[(FrameSlot, InstrId)] {
+        &self.entries
+    }
+    pub fn stack_depth(&self) -> u32 { self.stack_depth }
+
+    pub fn get(&self, slot: FrameSlot) -> Option<InstrId> {
+        self.entries.binary_search_by_key(&slot, |e| e.0)
+          .ok().map(|i| self.entries[i].1)
+    }
+    pub fn receiver(&self) -> Option<InstrId> {
+        self.get(FrameSlot::Receiver)
+    }
+    pub fn local(&self, index: u32) -> Option<InstrId> {
+        self.get(FrameSlot::Local(index))
+    }
+
+    // Make every slot holding `old` hold `new`, giving
+    // how many did.
+    pub(crate) fn replace_value(&mut self, old: InstrId, new: InstrId)
+      -> u32
+    {
+        let mut replaced = 0;
+        for entry in self.entries.iter_mut().filter(|e| e.1 == old) {
+            entry.1 = new;
+            replaced += 1;
+        }
+        replaced
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "snapshot @{} [", self.pc)?;
+        for (i, (slot, value)) in self.entries.iter().enumerate() {
+            let sep = if i == 0 { "" } else { ", " };
+            write!(f, "{}{} = {}", sep, slot, value)?;
+        }
+        write!(f, "]")
+    }
+}
+
+/**
+ * The abstract frame of the frontend, tracked while it
+ * lowers its bytecode into a build session: which
+ * definition each local, each stack position and the
+ * receiver currently hold.  The frontend updates it as
+ * it translates each bytecode, and takes a `Snapshot`
+ * of it for each guard or exit it emits.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct FrameState<'a> {
+    receiver: Option<Defn<'a>>,
+    locals: Vec<Option<Defn<'a>>>,
+    stack: Vec<Defn<'a>>
+}
+
+impl<'a> FrameState<'a> {
+    // A frame with the given number of locals, all
+    // dead, and an empty stack.
+    pub fn new(num_locals: u32) -> FrameState<'a> {
+        FrameState {
+            receiver: None,
+            locals: vec![None; num_locals as usize],
+            stack: Vec::new()
+        }
+    }
+
+    pub fn receiver(&self) -> Option<Defn<'a>> { self.receiver }
+    pub fn set_receiver(&mut self, defn: Defn<'a>) {
+        self.receiver = Some(defn);
+    }
+
+    pub fn num_locals(&self) -> u32 { self.locals.len() as u32 }
+    pub fn local(&self, index: u32) -> Option<Defn<'a>> {
+        self.locals.get(index as usize).cloned().unwrap_or(None)
+    }
+
+    // Set a local, growing the frame to hold it.
+    pub fn set_local(&mut self, index: u32, defn: Defn<'a>) {
+        let index = index as usize;
+        if index >= self.locals.len() {
+            self.locals.resize(index + 1, None);
+        }
+        self.locals[index] = Some(defn);
+    }
+
+    // Mark a local dead, so snapshots leave it out.
+    pub fn kill_local(&mut self, index: u32) {
+        if let Some(local) = self.locals.get_mut(index as usize) {
+            *local = None;
+        }
+    }
+
+    pub fn stack_depth(&self) -> u32 { self.stack.len() as u32 }
+    pub fn push(&mut self, defn: Defn<'a>) { self.stack.push(defn); }
+    pub fn pop(&mut self) -> Option<Defn<'a>> { self.stack.pop() }
+
+    // The value `depth` positions below the top of the
+    // stack.
+    pub fn peek(&self, depth: u32) -> Option<Defn<'a>> {
+        let len = self.stack.len();
+        len.checked_sub(depth as usize + 1).map(|i| self.stack[i])
+    }
+
+    /** Capture the frame as it stands, resuming at `pc`. */
+    pub fn snapshot(&self, pc: u32) -> Snapshot {
+        let receiver = self.receiver.map(|d| {
+            (FrameSlot::Receiver, d.instr_id())
+        });
+        let locals = self.locals.iter().enumerate()
+          .filter_map(|(i, d)| {
+              d.map(|d| (FrameSlot::Local(i as u32), d.instr_id()))
+          });
+        let stack = self.stack.iter().enumerate().map(|(i, d)| {
+            (FrameSlot::Stack(i as u32), d.instr_id())
+        });
+        Snapshot {
+            pc,
+            entries: receiver.into_iter().chain(locals)
+                             .chain(stack).collect(),
+            stack_depth: self.stack.len() as u32
+        }
+    }
+}
+
+impl Graph {
+    /**
+     * The snapshot attached to the guard or exit
+     * instruction, if it has one.
+     */
+    pub fn snapshot(&self, instr: InstrId) -> Option<&Snapshot> {
+        let table = self.snapshot_table();
+        table.binary_search_by_key(&instr, |e| e.0)
+          .ok().map(|i| &table[i].1)
+    }
+
+    /** The attached snapshots, in instruction order. */
+    pub fn snapshots(&self)
+      -> impl Iterator<Item = (InstrId, &Snapshot)> + '_
+    {
+        self.snapshot_table().iter().map(|(id, s)| (*id, s))
+    }
+}
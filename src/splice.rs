@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::{ Op, Operation, JumpOp };
+use crate::ir_types::IrTypeId;
+use crate::text::{ Rebuild, ParsedBlock, ParsedInstr, BlockKind };
+
+/*
+ * Splicing.  The blocks of a donor graph are copied
+ * into a host graph, either in place of a call, as
+ * inlining does, or along an edge.  The donor's params
+ * become the arguments of the call, or of the edge,
+ * and its returns jump on: from a call, to a new block
+ * holding the rest of the call's block, whose phi
+ * takes the returned value in place of the call's;
+ * along an edge, to the edge's target, with the edge's
+ * arguments, the returned value being dropped.
+ *
+ * The donor's values and blocks are renamed apart from
+ * the host's, and its side exits keep their snapshots.
+ * Its blocks go just after the block they are spliced
+ * into, within the same loop, or within that block's
+ * own loop where the code after them needs to be.
+ *
+ * The host is rebuilt, so the ids of its instructions
+ * and blocks change.
+ */
+
+/** Why a donor graph could not be spliced into a host. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum SpliceError {
+    // The instruction is not a call.
+    NotACall { instr: InstrId },
+
+    // The block has no edge at the index.
+    NoSuchEdge { block: BlockId, index: u32 },
+
+    // The donor has a param at an index with no
+    // argument, or of another type than its argument.
+    Param { index: u32 },
+
+    // The donor returns a value of another type than
+    // the call's.
+    Return { instr: InstrId },
+
+    // The donor has a second start block.
+    Entry { block: BlockId }
+}
+
+impl fmt::Display for SpliceError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match *self {
+          SpliceError::NotACall { instr } =>
+            write!(f, "{} is not a call", instr),
+          SpliceError::NoSuchEdge { block, index } =>
+            write!(f, "{} has no edge {}", block, index),
+          SpliceError::Param { index } =>
+            write!(f, "donor param {} does not match its argument",
+                   index),
+          SpliceError::Return { instr } =>
+            write!(f, "donor return {} does not match the call",
+                   instr),
+          SpliceError::Entry { block } =>
+            write!(f, "donor has a second start block {}", block)
+        }
+    }
+}
+
+impl Error for SpliceError {}
+
+// Rename every value and block of a level.
+fn rename(level: &mut [ParsedBlock], values: &dyn Fn(&mut String),
+          blocks: &dyn Fn(&mut String))
+{
+    for block in level {
+        blocks(&mut block.name);
+        block.params.iter_mut().for_each(|p| values(&mut p.0));
+        for instr in &mut block.instrs {
+            instr.def.iter_mut().for_each(values);
+            instr.operands.iter_mut().for_each(values);
+            for (target, args) in &mut instr.targets {
+                blocks(target);
+                args.iter_mut().for_each(values);
+            }
+            if let Some(ref mut snapshot) = instr.snapshot {
+                snapshot.entries.iter_mut().for_each(|e| values(&mut e.1));
+            }
+        }
+        rename(&mut block.body, values, blocks);
+    }
+}
+
+// Make each return of a level jump to `target` instead,
+// passing the returned value if `pass` is set, then the
+// given arguments.
+fn retarget_returns(level: &mut [ParsedBlock], target: &str,
+                    pass: bool, args: &[String])
+{
+    for block in level {
+        let end = block.instrs.last_mut().unwrap();
+        if let Op::Ret(_) = end.op {
+            let mut passed = mem::take(&mut end.operands);
+            passed.truncate(pass as usize);
+            passed.extend(args.iter().cloned());
+            end.op = JumpOp::new().op();
+            end.targets = vec![(target.to_string(), passed)];
+        }
+        retarget_returns(&mut block.body, target, pass, args);
+    }
+}
+
+impl Graph {
+    // The donor's blocks renamed apart from the host's,
+    // with its params replaced by the arguments, whose
+    // types are given.
+    fn donor_blocks(donor: &Graph, args: &[String],
+                    arg_types: &[IrTypeId])
+      -> Result<Vec<ParsedBlock>, SpliceError>
+    {
+        let start = donor.start_block_id();
+        for view in donor.rpo_block_views() {
+            if view.is_start() && view.id() != start {
+                return Err(SpliceError::Entry { block: view.id() });
+            }
+        }
+
+        let mut drb = Rebuild::new(donor);
+        let mut params = HashMap::new();
+        for view in donor.rpo_block_views() {
+            for instr in view.instrs() {
+                if let Op::Param(p) = donor.instr(instr.id()).op() {
+                    let index = p.index();
+                    match arg_types.get(index as usize) {
+                      Some(&ty) if ty == p.tyid() => {}
+                      _ => return Err(SpliceError::Param { index })
+                    }
+                    params.insert(drb.value(instr.id()),
+                                  args[index as usize].clone());
+                }
+            }
+        }
+
+        let mut blocks = mem::take(drb.blocks_mut());
+        for block in &mut blocks {
+            block.instrs.retain(|i| !matches!(i.op, Op::Param(_)));
+            if block.kind == BlockKind::Start {
+                block.kind = BlockKind::Plain;
+            }
+        }
+        rename(&mut blocks, &|v| match params.get(v.as_str()) {
+          Some(arg) => *v = arg.clone(),
+          None => v.insert(0, 'd')
+        }, &|b| b.insert(0, 'd'));
+        Ok(blocks)
+    }
+
+    /**
+     * Splice the donor graph in place of a call,
+     * passing the call's operands as its params, and
+     * continuing after the call with the value it
+     * returns.  This graph is rebuilt, so the ids of
+     * its instructions and blocks change, and its
+     * profile, instruction index and patched patch
+     * point bytes are dropped.
+     */
+    pub fn splice_call(&mut self, call: InstrId, donor: &Graph)
+      -> Result<(), SpliceError>
+    {
+        let info = self.instr(call);
+        let ty = match info.op() {
+          Op::Call(c) => c.out_type().unwrap(),
+          _ => return Err(SpliceError::NotACall { instr: call })
+        };
+        for view in donor.rpo_block_views() {
+            let end = view.end().id();
+            if let Op::Ret(r) = donor.instr(end).op() {
+                if r.tyid() != ty {
+                    return Err(SpliceError::Return { instr: end });
+                }
+            }
+        }
+
+        let mut rb = Rebuild::new(self);
+        let operands: Vec<InstrId> =
+          info.inputs_iter().map(|d| d.instr_id()).collect();
+        let args: Vec<String> =
+          operands.iter().map(|&v| rb.value(v)).collect();
+        let arg_types: Vec<IrTypeId> = operands.iter().map(|&v| {
+            self.instr(v).op().out_type().unwrap()
+        }).collect();
+        let mut blocks = Graph::donor_blocks(donor, &args, &arg_types) ?;
+
+        let def = rb.value(call);
+        let entry = blocks[0].name.clone();
+        let (block, index) = self.split_point(call);
+        let cont = rb.split_block(block, index + 1);
+        rb.named_block_mut(&cont).params.push((def, ty));
+        retarget_returns(&mut blocks, &cont, true, &[]);
+        let host = rb.block_mut(block);
+        host.instrs.truncate(index);
+        host.instrs.push(ParsedInstr::new(
+          host.line, None, JumpOp::new().op(), Vec::new(),
+          vec![(entry, Vec::new())]));
+        let (name, inner) =
+          (host.name.clone(), host.kind == BlockKind::Loop);
+        rb.place_after(&name, blocks, inner);
+        self.rebuilt(rb.finish());
+        Ok(())
+    }
+
+    /**
+     * Splice the donor graph along the edge at `index`
+     * of the end of `from`, passing the edge's
+     * arguments as its params, and continuing to the
+     * edge's target with the same arguments once it
+     * returns.  The returned value is dropped.  This
+     * graph is rebuilt, as by `splice_call`.
+     */
+    pub fn splice_edge(&mut self, from: BlockId, index: u32,
+                       donor: &Graph)
+      -> Result<(), SpliceError>
+    {
+        let no_edge = SpliceError::NoSuchEdge { block: from, index };
+        let view = self.block_view(from).ok_or(no_edge) ?;
+        let target = view.end().targets().nth(index as usize)
+                         .ok_or(no_edge) ?;
+        let operands: Vec<InstrId> = target.phi_args().collect();
+
+        let mut rb = Rebuild::new(self);
+        let args: Vec<String> =
+          operands.iter().map(|&v| rb.value(v)).collect();
+        let arg_types: Vec<IrTypeId> = operands.iter().map(|&v| {
+            self.instr(v).op().out_type().unwrap()
+        }).collect();
+        let mut blocks = Graph::donor_blocks(donor, &args, &arg_types) ?;
+
+        let target_name = rb.block_name(target.block());
+        retarget_returns(&mut blocks, &target_name, false, &args);
+        let entry = blocks[0].name.clone();
+        let host = rb.block_mut(from);
+        let end = host.instrs.last_mut().unwrap();
+        end.targets[index as usize] = (entry, Vec::new());
+        let inner = host.encloses(&target_name);
+        let name = host.name.clone();
+        rb.place_after(&name, blocks, inner);
+        self.rebuilt(rb.finish());
+        Ok(())
+    }
+}
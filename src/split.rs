@@ -0,0 +1,165 @@
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::text::Rebuild;
+
+/*
+ * Splitting blocks and edges.  When a block is split
+ * at an instruction, that instruction and the rest of
+ * the block, with its end, are moved into a new block,
+ * and the block ends in a jump to it instead.  The new
+ * block has no phis: the values of the block dominate
+ * it as they did the moved instructions, and snapshots
+ * move with their instructions.  Edges that left the
+ * block now leave the new one, so its successors' phis
+ * take the same arguments from it.
+ *
+ * When an edge is split, a new block holding only a
+ * jump to the edge's target is placed on it, and
+ * passes the edge's phi arguments on.  The edge's
+ * source now jumps to the new block with none.
+ *
+ * The graph is rebuilt with the new block, so the ids
+ * of its instructions and blocks change.  The new
+ * block takes its place in the RPO just after the
+ * block it was split from, or within that block's
+ * loop if its successors are.
+ */
+
+impl Graph {
+    // The block holding an instruction, and its index
+    // among the block's instructions after the phis.
+    pub(crate) fn split_point(&self, at: InstrId) -> (BlockId, usize) {
+        let block = self.block_of_instr(at)
+                        .unwrap_or_else(|| panic!("no instruction {}", at));
+        let phis = self.block(block).num_phis() as usize;
+        let index = self.block_instrs(self.block(block))
+                        .position(|i| i.defn().instr_id() == at)
+                        .unwrap_or_else(|| panic!("no instruction {}", at));
+        assert!(index >= phis, "splitting a block at phi {}", at);
+        (block, index - phis)
+    }
+
+    /**
+     * Split the block holding the instruction `at`
+     * before it, moving it and the rest of the block
+     * into a new block that the block now jumps to,
+     * and return the new block's id.  `at` may not be
+     * a phi.  The graph is rebuilt, so the ids of its
+     * instructions and blocks change and its change log
+     * begins a new epoch.  Its target, operand encoding,
+     * block storage, cold blocks and snapshots are kept;
+     * its profile, instruction index and patched patch
+     * point bytes are dropped.
+     */
+    pub fn split_block(&mut self, at: InstrId) -> BlockId {
+        let (block, index) = self.split_point(at);
+        let mut rb = Rebuild::new(self);
+        let name = rb.split_block(block, index);
+        let pos = rb.position(&name);
+        self.rebuilt(rb.finish());
+        self.rpo_block_views().nth(pos).unwrap().id()
+    }
+
+    /**
+     * Place a new block on the edge at `index` of the
+     * end of `from`, and return its id.  The graph is
+     * rebuilt as by `split_block`, keeping and dropping
+     * the same things.
+     */
+    pub fn split_edge(&mut self, from: BlockId, index: u32) -> BlockId {
+        let targets = self.block_view(from)
+                          .map_or(0, |b| b.end().targets().count());
+        assert!((index as usize) < targets,
+                "no edge {} from {}", index, from);
+        let mut rb = Rebuild::new(self);
+        let name = rb.split_edge(from, index as usize);
+        let pos = rb.position(&name);
+        self.rebuilt(rb.finish());
+        self.rpo_block_views().nth(pos).unwrap().id()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::BlockStorage;
+    use crate::instr::OperandEncoding;
+    use crate::text::parse_with;
+
+    const LOOP: &str = "\
+block0:
+  %0 = const.i32 0
+  %1 = const.i32 10
+  jump block1(%0)
+loop block1(%2: i32) {
+  %3 = lt.i32 %2, %1
+  branch %3, block2, block3
+  block2:
+    %4 = const.i32 1
+    %5 = add.i32 %2, %4
+    jump block1(%5)
+}
+block3:
+  ret.i32 %2
+";
+
+    #[test]
+    fn split_block_moves_the_rest() {
+        let mut graph = parse_with(LOOP, OperandEncoding::Fixed,
+                                   BlockStorage::Chunked);
+        let body = graph.rpo_block_views().nth(2).unwrap();
+        let add = body.instrs().nth(1).unwrap().id();
+        let new = graph.split_block(add);
+        assert_eq!(graph.rpo_block_views().nth(3).unwrap().id(), new);
+        assert_eq!(graph.print_ir(), "\
+block0:
+  %0 = const.i32 0
+  %1 = const.i32 10
+  jump block1(%0)
+loop block1(%2: i32) {
+  %3 = lt.i32 %2, %1
+  branch %3, block2, block4
+  block2:
+    %4 = const.i32 1
+    jump block3
+  block3:
+    %5 = add.i32 %2, %4
+    jump block1(%5)
+}
+block4:
+  ret.i32 %2
+");
+        assert_eq!(graph.operand_encoding(), OperandEncoding::Fixed);
+        assert_eq!(graph.block_storage(), BlockStorage::Chunked);
+        assert!(graph.verify().is_ok());
+    }
+
+    #[test]
+    fn split_edge_passes_phi_args_on() {
+        let mut graph = parse_with(LOOP, OperandEncoding::Delta,
+                                   BlockStorage::Contiguous);
+        let start = graph.start_block_id();
+        let new = graph.split_edge(start, 0);
+        assert_eq!(graph.rpo_block_views().nth(1).unwrap().id(), new);
+        assert_eq!(graph.print_ir(), "\
+block0:
+  %0 = const.i32 0
+  %1 = const.i32 10
+  jump block1
+block1:
+  jump block2(%0)
+loop block2(%2: i32) {
+  %3 = lt.i32 %2, %1
+  branch %3, block3, block4
+  block3:
+    %4 = const.i32 1
+    %5 = add.i32 %2, %4
+    jump block2(%5)
+}
+block4:
+  ret.i32 %2
+");
+        assert_eq!(graph.operand_encoding(), OperandEncoding::Delta);
+        assert!(graph.verify().is_ok());
+    }
+}
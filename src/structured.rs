@@ -0,0 +1,319 @@
+
+use std::error::Error;
+use std::fmt;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::Op;
+use crate::analysis::{ Cfg, DomTree };
+
+/** An error reconstructing structured control flow. */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum StructureError {
+    // An edge goes back to a block that does not
+    // dominate its source, so the graph is irreducible.
+    Irreducible { from: BlockId, to: BlockId }
+}
+
+impl fmt::Display for StructureError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match self {
+          StructureError::Irreducible { from, to } =>
+            write!(f, "irreducible edge from block {} to block {}",
+                   from.as_u32(), to.as_u32())
+        }
+    }
+}
+
+impl Error for StructureError {}
+
+/**
+ * A control flow edge: the target at `index` of the
+ * end instruction of block `from`.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct Edge {
+    from: BlockId,
+    index: u32,
+    to: BlockId
+}
+
+impl Edge {
+    pub fn from(&self) -> BlockId { self.from }
+    pub fn index(&self) -> u32 { self.index }
+    pub fn to(&self) -> BlockId { self.to }
+}
+
+/**
+ * A node of structured control flow, in the manner
+ * of wasm: control leaves a `Block` only by breaking
+ * out to its end, and a `Loop` only by breaking out of
+ * an enclosing `Block` or ending.  Control falls from
+ * each node into the next.
+ */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum Structured {
+    // The instructions of a block, up to but not
+    // including its end instruction.
+    Code(BlockId),
+
+    // Taking an edge into the code that follows, where
+    // the edge's phi arguments are passed.
+    Enter(Edge),
+
+    // A sequence that breaks to its end.
+    Block(Vec<Structured>),
+
+    // A sequence that breaks to its start.
+    Loop(Vec<Structured>),
+
+    // The two arms of a branch instruction, the first
+    // taken when its condition is true.
+    If {
+        branch: InstrId,
+        then_body: Vec<Structured>,
+        else_body: Vec<Structured>
+    },
+
+    // Taking an edge by breaking out to the end of a
+    // `Block`, or the start of a `Loop`, with `depth`
+    // counting the enclosing nodes to skip over, as in
+    // wasm's `br`: each `Block`, `Loop` and `If`.
+    Br { depth: u32, edge: Edge },
+
+    // A `ret` or `exit` instruction.
+    Ret(InstrId),
+    Exit(InstrId)
+}
+
+/**
+ * The control flow of a graph as a tree of structured
+ * nodes, such as a wasm backend emits.  Blocks not
+ * reachable from the start block are left out.
+ */
+#[derive(Clone, Debug)]
+pub struct StructuredCfg {
+    body: Vec<Structured>
+}
+
+impl StructuredCfg {
+    pub fn body(&self) -> &[Structured] { &self.body }
+}
+
+// An enclosing node, as a `Br` counts them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Frame {
+    If,
+    LoopHeadedBy(BlockId),
+    BlockFollowedBy(BlockId)
+}
+
+/*
+ * The stackifier of Ramsey's "Beyond Relooper": each
+ * block is placed with the subtree it dominates, the
+ * children that are joined by more than one forward
+ * edge following it, each after a `Block` that edges
+ * into it break out of.  Loop headers wrap their
+ * subtree in a `Loop` that back edges break to.
+ */
+struct Structurizer<'a> {
+    graph: &'a Graph,
+    cfg: &'a Cfg,
+    dom: &'a DomTree,
+
+    // By block id, whether a block is the target of a
+    // back edge.
+    is_header: Vec<bool>,
+
+    // By block id, whether more than one forward edge
+    // targets a block.
+    is_merge: Vec<bool>,
+
+    // By block id, the dominator tree children of a
+    // block that are merges, latest in RPO first.
+    merge_children: Vec<Vec<BlockId>>
+}
+
+impl<'a> Structurizer<'a> {
+    fn new(graph: &'a Graph)
+      -> Result<Structurizer<'a>, StructureError>
+    {
+        let cfg = graph.cfg();
+        let dom = graph.dominators();
+        let nblocks = cfg.num_blocks();
+        let mut is_header = vec![false; nblocks];
+        let mut forward_preds = vec![0_u32; nblocks];
+        let mut merge_children = vec![Vec::new(); nblocks];
+
+        let blocks = (0 .. nblocks as u32).map(BlockId::new);
+        for from in blocks.filter(|&b| dom.is_reachable(b)) {
+            let from_num = dom.rpo_number(from).unwrap();
+            for &to in cfg.succs(from) {
+                let ti = to.as_u32() as usize;
+                if dom.rpo_number(to).unwrap() > from_num {
+                    forward_preds[ti] += 1;
+                } else if dom.dominates(to, from) {
+                    is_header[ti] = true;
+                } else {
+                    let err = StructureError::Irreducible { from, to };
+                    return Err(err);
+                }
+            }
+        }
+
+        let is_merge: Vec<bool> =
+          forward_preds.iter().map(|&n| n > 1).collect();
+        for &b in cfg.rpo().iter().rev() {
+            if let Some(d) = dom.idom(b) {
+                if is_merge[b.as_u32() as usize] {
+                    merge_children[d.as_u32() as usize].push(b);
+                }
+            }
+        }
+        for children in &mut merge_children {
+            children.sort_by_key(|&b| {
+                std::cmp::Reverse(dom.rpo_number(b))
+            });
+        }
+
+        Ok(Structurizer {
+            graph, cfg, dom, is_header, is_merge, merge_children
+        })
+    }
+
+    fn run(&self) -> Vec<Structured> {
+        let start = self.graph.block_store().start_block_id();
+        let mut body = Vec::new();
+        self.do_tree(start, &mut Vec::new(), &mut body);
+        body
+    }
+
+    // Emit a block and the subtree it dominates.  A
+    // block that is entered by falling through is
+    // placed in the same list rather than by
+    // recursion, so long chains of blocks do not
+    // overflow the stack.
+    fn do_tree(&self, block: BlockId, ctx: &mut Vec<Frame>,
+               out: &mut Vec<Structured>)
+    {
+        let mut next = Some(block);
+        while let Some(b) = next {
+            if !self.is_header[b.as_u32() as usize] {
+                next = self.node_within(b, self.merges(b), ctx, out);
+                continue;
+            }
+            ctx.push(Frame::LoopHeadedBy(b));
+            let mut body = Vec::new();
+            let tail = self.node_within(b, self.merges(b), ctx,
+                                        &mut body);
+            if let Some(t) = tail {
+                self.do_tree(t, ctx, &mut body);
+            }
+            ctx.pop();
+            out.push(Structured::Loop(body));
+            next = None;
+        }
+    }
+
+    fn merges(&self, block: BlockId) -> &[BlockId] {
+        &self.merge_children[block.as_u32() as usize]
+    }
+
+    // Emit a block nested in a `Block` for each of the
+    // given merge children, giving the block to be
+    // placed next in the same list.
+    fn node_within(&self, block: BlockId, merges: &[BlockId],
+                   ctx: &mut Vec<Frame>, out: &mut Vec<Structured>)
+      -> Option<BlockId>
+    {
+        let (&merge, rest) = match merges.split_first() {
+          Some(split) => split,
+          None => {
+            out.push(Structured::Code(block));
+            return self.do_end(block, ctx, out);
+          }
+        };
+        ctx.push(Frame::BlockFollowedBy(merge));
+        let mut body = Vec::new();
+        if let Some(t) = self.node_within(block, rest, ctx, &mut body) {
+            self.do_tree(t, ctx, &mut body);
+        }
+        ctx.pop();
+        out.push(Structured::Block(body));
+        Some(merge)
+    }
+
+    fn do_end(&self, block: BlockId, ctx: &mut Vec<Frame>,
+              out: &mut Vec<Structured>)
+      -> Option<BlockId>
+    {
+        let end = self.graph.block_end(self.graph.block(block));
+        let id = end.defn().instr_id();
+        let edge = |index: u32| Edge {
+            from: block, index,
+            to: self.cfg.succs(block)[index as usize]
+        };
+        match end.op() {
+          Op::Ret(_) => { out.push(Structured::Ret(id)); None }
+          Op::Exit(_) => { out.push(Structured::Exit(id)); None }
+          Op::Jump(_) => self.do_branch(edge(0), ctx, out),
+          Op::Branch(_) => {
+            ctx.push(Frame::If);
+            let mut arms = [Vec::new(), Vec::new()];
+            for (i, arm) in arms.iter_mut().enumerate() {
+                let tail = self.do_branch(edge(i as u32), ctx, arm);
+                if let Some(t) = tail {
+                    self.do_tree(t, ctx, arm);
+                }
+            }
+            ctx.pop();
+            let [then_body, else_body] = arms;
+            out.push(Structured::If {
+                branch: id, then_body, else_body
+            });
+            None
+          }
+          _ => panic!("Block ends in a non-terminal.")
+        }
+    }
+
+    // Take an edge, giving its target if it is to be
+    // placed next.
+    fn do_branch(&self, edge: Edge, ctx: &[Frame],
+                 out: &mut Vec<Structured>)
+      -> Option<BlockId>
+    {
+        let num = |b: BlockId| self.dom.rpo_number(b).unwrap();
+        let frame = if num(edge.to) <= num(edge.from) {
+            Frame::LoopHeadedBy(edge.to)
+        } else if self.is_merge[edge.to.as_u32() as usize] {
+            Frame::BlockFollowedBy(edge.to)
+        } else {
+            out.push(Structured::Enter(edge));
+            return Some(edge.to);
+        };
+        let pos = ctx.iter().rposition(|&f| f == frame)
+                     .expect("Branch target not enclosing.");
+        let depth = (ctx.len() - 1 - pos) as u32;
+        out.push(Structured::Br { depth, edge });
+        None
+    }
+}
+
+impl Graph {
+    /**
+     * Reconstruct structured control flow, with blocks,
+     * loops and ifs, from the graph's control flow
+     * graph.  Fails if the graph is irreducible.
+     */
+    pub fn structurize(&self) -> Result<StructuredCfg, StructureError> {
+        let body = Structurizer::new(self)?.run();
+        Ok(StructuredCfg { body })
+    }
+}
@@ -0,0 +1,119 @@
+
+use std::fmt;
+
+use crate::ir_types::IrTypeId;
+
+/** The order of the bytes of values in memory. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash)]
+pub enum Endian { Little, Big }
+
+/**
+ * The machine a graph is built for: the width of its
+ * pointers, which is the width of `PtrInt` values, the
+ * byte order of memory, and the largest alignment a
+ * scalar needs, each scalar otherwise aligning to its
+ * size.  Graphs are built for `TargetDesc::LP64`
+ * unless given another.  The JIT and eBPF backends
+ * lower only graphs for 64-bit little-endian targets.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct TargetDesc {
+    ptr_bytes: u8,
+    endian: Endian,
+    max_align: u8
+}
+
+impl Default for TargetDesc {
+    fn default() -> TargetDesc { TargetDesc::LP64 }
+}
+
+impl TargetDesc {
+    // 64-bit pointers, little-endian, with 64-bit
+    // values aligned to 8 bytes.
+    pub const LP64: TargetDesc = TargetDesc {
+        ptr_bytes: 8, endian: Endian::Little, max_align: 8
+    };
+
+    // 32-bit pointers, little-endian, with 64-bit
+    // values aligned to 4 bytes.
+    pub const ILP32: TargetDesc = TargetDesc {
+        ptr_bytes: 4, endian: Endian::Little, max_align: 4
+    };
+
+    // A target of 4- or 8-byte pointers, and a largest
+    // alignment of 1, 2, 4 or 8 bytes; None for others.
+    pub fn new(ptr_bytes: u32, endian: Endian, max_align: u32)
+      -> Option<TargetDesc>
+    {
+        let ptr_ok = matches!(ptr_bytes, 4 | 8);
+        let align_ok = max_align.is_power_of_two() && max_align <= 8;
+        if !ptr_ok || !align_ok {
+            return None;
+        }
+        Some(TargetDesc {
+            ptr_bytes: ptr_bytes as u8, endian,
+            max_align: max_align as u8
+        })
+    }
+
+    pub fn ptr_bytes(&self) -> u32 { self.ptr_bytes as u32 }
+    pub fn endian(&self) -> Endian { self.endian }
+    pub fn max_align(&self) -> u32 { self.max_align as u32 }
+
+    // The bytes a value of the type takes in memory.
+    pub fn size_of(&self, ty: IrTypeId) -> u32 {
+        match ty {
+          IrTypeId::PtrInt => self.ptr_bytes(),
+          ty => ty.mem_size()
+        }
+    }
+
+    pub fn align_of(&self, ty: IrTypeId) -> u32 {
+        self.size_of(ty).min(self.max_align())
+    }
+
+    // The largest pointer value of the target.
+    pub fn max_ptr(&self) -> u64 {
+        u64::MAX >> (64 - 8 * self.ptr_bytes())
+    }
+
+    // Whether the JIT and eBPF backends can lower
+    // graphs built for the target.
+    pub(crate) fn is_64_little(&self) -> bool {
+        self.ptr_bytes == 8 && self.endian == Endian::Little
+    }
+
+    // The target as serialized: pointer bytes, byte
+    // order and largest alignment.
+    pub(crate) fn to_bytes(self) -> [u8; 3] {
+        let endian = match self.endian {
+          Endian::Little => 0,
+          Endian::Big => 1
+        };
+        [self.ptr_bytes, endian, self.max_align]
+    }
+
+    pub(crate) fn from_bytes(bytes: [u8; 3]) -> Option<TargetDesc> {
+        let endian = match bytes[1] {
+          0 => Endian::Little,
+          1 => Endian::Big,
+          _ => return None
+        };
+        TargetDesc::new(bytes[0] as u32, endian, bytes[2] as u32)
+    }
+}
+
+impl fmt::Display for TargetDesc {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        let endian = match self.endian {
+          Endian::Little => "little",
+          Endian::Big => "big"
+        };
+        write!(f, "ptr{} {}-endian align{}",
+               8 * self.ptr_bytes, endian, self.max_align)
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::{ HashMap, HashSet };
+use std::error::Error;
+use std::fmt;
+
+use crate::graph::Graph;
+
+/*
+ * FileCheck-style matching.  Text, such as printed IR,
+ * is checked against the check lines of a test, which
+ * are the lines holding a directive, whatever comes
+ * before it:
+ *
+ *   CHECK: pattern       matches a later line
+ *   CHECK-NEXT: pattern  matches the line just after
+ *                        the last line matched
+ *   CHECK-NOT: pattern   matches no line between the
+ *                        matches around it
+ *
+ * A pattern matches anywhere within a line.  A run of
+ * whitespace in it matches any run of whitespace,
+ * `{{...}}` matches any text, `[[NAME:]]` matches a
+ * word, such as `%4` or `block2`, and binds it to NAME,
+ * and `[[NAME]]` matches the word last bound to NAME,
+ * by its own check line or an earlier one.
+ */
+
+/** Why text failed its checks. */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum CheckError {
+    // The checks hold no directive.
+    NoChecks,
+
+    // The pattern of a check line is malformed.
+    Syntax { line: u32 },
+
+    // A pattern uses a name no earlier match binds.
+    Undefined { line: u32, name: String },
+
+    // No line after the last match matches a CHECK.
+    Unmatched { line: u32 },
+
+    // The line after the last match does not match a
+    // CHECK-NEXT.
+    NotNext { line: u32 },
+
+    // A CHECK-NOT matches a line of the text.
+    Forbidden { line: u32, text_line: u32 }
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match *self {
+          CheckError::NoChecks => write!(f, "no check lines"),
+          CheckError::Syntax { line } =>
+            write!(f, "check line {}: malformed pattern", line),
+          CheckError::Undefined { line, ref name } =>
+            write!(f, "check line {}: {} is not bound", line, name),
+          CheckError::Unmatched { line } =>
+            write!(f, "check line {}: no line matches", line),
+          CheckError::NotNext { line } =>
+            write!(f, "check line {}: the next line does not match",
+                   line),
+          CheckError::Forbidden { line, text_line } =>
+            write!(f, "check line {}: matches line {}",
+                   line, text_line)
+        }
+    }
+}
+
+impl Error for CheckError {}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Directive { Check, Next, Not }
+
+enum Piece {
+    Text(Vec<char>),
+    Space,
+    Any,
+    Def(String),
+    Use(String)
+}
+
+// A check line: its line number, directive and pattern.
+struct CheckLine {
+    line: u32,
+    directive: Directive,
+    pieces: Vec<Piece>
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || c == '%'
+}
+
+// The directive of a line and the pattern after it.
+fn split_directive(line: &str) -> Option<(Directive, &str)> {
+    let start = line.find("CHECK") ?;
+    let rest = &line[start + "CHECK".len() ..];
+    [(":", Directive::Check), ("-NEXT:", Directive::Next),
+     ("-NOT:", Directive::Not)].iter()
+      .find(|d| rest.starts_with(d.0))
+      .map(|d| (d.1, &rest[d.0.len() ..]))
+}
+
+fn parse_pattern(pattern: &str) -> Option<Vec<Piece>> {
+    let mut pieces = Vec::new();
+    let mut rest = pattern.trim();
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = rest.trim_start();
+            pieces.push(Piece::Space);
+        } else if let Some(after) = rest.strip_prefix("{{") {
+            let end = after.find("}}") ?;
+            rest = &after[end + 2 ..];
+            pieces.push(Piece::Any);
+        } else if let Some(after) = rest.strip_prefix("[[") {
+            let end = after.find("]]") ?;
+            let var = &after[.. end];
+            rest = &after[end + 2 ..];
+            let (name, def) = match var.strip_suffix(':') {
+              Some(name) => (name, true),
+              None => (var, false)
+            };
+            if name.is_empty()
+              || !name.chars().all(|c| c.is_alphanumeric() || c == '_')
+            {
+                return None;
+            }
+            pieces.push(if def { Piece::Def(name.to_string()) }
+                        else { Piece::Use(name.to_string()) });
+        } else {
+            rest = &rest[c.len_utf8() ..];
+            match pieces.last_mut() {
+              Some(Piece::Text(text)) => text.push(c),
+              _ => pieces.push(Piece::Text(vec![c]))
+            }
+        }
+    }
+    Some(pieces)
+}
+
+fn parse_checks(checks: &str) -> Result<Vec<CheckLine>, CheckError> {
+    let mut parsed = Vec::new();
+    let mut bound = HashSet::new();
+    for (n, text) in checks.lines().enumerate() {
+        let line = n as u32 + 1;
+        let (directive, pattern) = match split_directive(text) {
+          Some(split) => split,
+          None => continue
+        };
+        let pieces = parse_pattern(pattern)
+                       .ok_or(CheckError::Syntax { line }) ?;
+        let mut own = HashSet::new();
+        for piece in &pieces {
+            match *piece {
+              Piece::Def(ref name) => { own.insert(name.clone()); }
+              Piece::Use(ref name)
+                if !bound.contains(name) && !own.contains(name) =>
+              {
+                  let name = name.clone();
+                  return Err(CheckError::Undefined { line, name });
+              }
+              _ => {}
+            }
+        }
+        if directive != Directive::Not {
+            bound.extend(own);
+        }
+        parsed.push(CheckLine { line, directive, pieces });
+    }
+    Ok(parsed)
+}
+
+// Match pieces at `at` of a line, binding the names
+// they define in `vars`.
+fn match_at(pieces: &[Piece], text: &[char], at: usize,
+            vars: &mut HashMap<String, String>)
+  -> bool
+{
+    let (piece, rest) = match pieces.split_first() {
+      Some(split) => split,
+      None => return true
+    };
+    match *piece {
+      Piece::Text(ref chars) => {
+        text[at ..].starts_with(chars)
+          && match_at(rest, text, at + chars.len(), vars)
+      }
+      Piece::Space => {
+        let n = text[at ..].iter().take_while(|c| c.is_whitespace())
+                           .count();
+        n > 0 && match_at(rest, text, at + n, vars)
+      }
+      Piece::Any => {
+        (at ..= text.len()).any(|end| match_at(rest, text, end, vars))
+      }
+      Piece::Def(_) | Piece::Use(_)
+        if at > 0 && is_word_char(text[at - 1]) => false,
+      Piece::Def(ref name) => {
+        let n = text[at ..].iter().take_while(|&&c| is_word_char(c))
+                           .count();
+        let word: String = text[at .. at + n].iter().collect();
+        let old = vars.insert(name.clone(), word);
+        if n > 0 && match_at(rest, text, at + n, vars) {
+            return true;
+        }
+        match old {
+          Some(old) => vars.insert(name.clone(), old),
+          None => vars.remove(name)
+        };
+        false
+      }
+      Piece::Use(ref name) => {
+        let word: Vec<char> = vars[name].chars().collect();
+        let end = at + word.len();
+        text[at ..].starts_with(&word)
+          && (end == text.len() || !is_word_char(text[end]))
+          && match_at(rest, text, end, vars)
+      }
+    }
+}
+
+// Whether the pattern matches anywhere within a line,
+// binding its names in `vars` if it does.
+fn match_line(pieces: &[Piece], text: &[char],
+              vars: &mut HashMap<String, String>)
+  -> bool
+{
+    (0 ..= text.len()).any(|at| match_at(pieces, text, at, vars))
+}
+
+// Check that no CHECK-NOT matches a line of a range of
+// the text, starting at line index `first`.
+fn check_nots(nots: &[&CheckLine], lines: &[Vec<char>], first: usize,
+              vars: &HashMap<String, String>)
+  -> Result<(), CheckError>
+{
+    for not in nots {
+        for (i, text) in lines.iter().enumerate() {
+            if match_line(&not.pieces, text, &mut vars.clone()) {
+                let text_line = (first + i) as u32 + 1;
+                return Err(CheckError::Forbidden {
+                    line: not.line, text_line
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Match text against the check lines of `checks`, in
+ * the manner of LLVM's FileCheck, so that a test can
+ * check the shape of its output without comparing it
+ * exactly.
+ */
+pub fn file_check(text: &str, checks: &str) -> Result<(), CheckError> {
+    let checks = parse_checks(checks) ?;
+    if checks.is_empty() {
+        return Err(CheckError::NoChecks);
+    }
+    let lines: Vec<Vec<char>> =
+      text.lines().map(|l| l.chars().collect()).collect();
+    let mut vars = HashMap::new();
+    // The first line not yet passed by a match.
+    let mut next = 0;
+    let mut nots = Vec::new();
+    for check in &checks {
+        let line = check.line;
+        let found = match check.directive {
+          Directive::Not => {
+            nots.push(check);
+            continue;
+          }
+          Directive::Check => (next .. lines.len()).find(|&i| {
+            match_line(&check.pieces, &lines[i], &mut vars)
+          }).ok_or(CheckError::Unmatched { line }) ?,
+          Directive::Next => {
+            if next < lines.len()
+              && match_line(&check.pieces, &lines[next], &mut vars)
+            {
+                next
+            } else {
+                return Err(CheckError::NotNext { line });
+            }
+          }
+        };
+        check_nots(&nots, &lines[next .. found], next, &vars) ?;
+        nots.clear();
+        next = found + 1;
+    }
+    check_nots(&nots, &lines[next ..], next, &vars)
+}
+
+/**
+ * Assert that the printed IR of a graph passes the
+ * check lines of `checks`, as by `file_check`, showing
+ * the IR if it does not.
+ */
+pub fn assert_file_check(graph: &Graph, checks: &str) {
+    let ir = graph.print_ir();
+    if let Err(err) = file_check(&ir, checks) {
+        panic!("{}\n{}", err, ir);
+    }
+}
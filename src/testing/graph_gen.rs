@@ -0,0 +1,298 @@
+
+use crate::instr::{ InstrId, OperandEncoding };
+use crate::block::BlockStorage;
+use crate::defn::{ Defn, TypedDefn };
+use crate::graph::Graph;
+use crate::builder::{ Builder, BuildSession };
+use crate::ops::{
+    Operation, PhiOp, ConstOp,
+    CmpOp, CmpKind, BiniOp, BiniKind, RetOp
+};
+use crate::ir_types::IrTypeId;
+use crate::testing::Rng;
+
+const VALUE_TYPES: [IrTypeId; 3] =
+  [IrTypeId::Bool, IrTypeId::Int32, IrTypeId::Int64];
+const INT_TYPES: [IrTypeId; 2] =
+  [IrTypeId::Int32, IrTypeId::Int64];
+const CMP_KINDS: [CmpKind; 6] = [
+    CmpKind::Lt, CmpKind::Gt, CmpKind::Le,
+    CmpKind::Ge, CmpKind::Eq, CmpKind::Ne
+];
+const BINI_KINDS: [BiniKind; 6] = [
+    BiniKind::Add, BiniKind::Sub, BiniKind::Mul,
+    BiniKind::And, BiniKind::Or, BiniKind::Xor
+];
+
+/** Limits on the shape of generated graphs. */
+#[derive(Clone, Debug)]
+pub struct GraphGenConfig {
+    // Maximum nesting of diamonds and loops.
+    pub max_depth: u32,
+
+    // Maximum number of items (straight-line runs,
+    // diamonds and loops) in a sequence.
+    pub max_items: u32,
+
+    // Maximum instructions in a straight-line run.
+    pub max_run: u32,
+
+    // Maximum phis at a join or loop header, not
+    // counting a loop's trip counter.
+    pub max_phis: u32,
+
+    // Maximum trip count of generated loops.
+    pub max_trips: u32,
+
+    // How generated graphs write operands.
+    pub encoding: OperandEncoding,
+
+    // How generated graphs hold their blocks.
+    pub storage: BlockStorage
+}
+
+impl Default for GraphGenConfig {
+    fn default() -> GraphGenConfig {
+        GraphGenConfig {
+            max_depth: 3, max_items: 4, max_run: 6,
+            max_phis: 3, max_trips: 4,
+            encoding: OperandEncoding::Absolute,
+            storage: BlockStorage::Contiguous
+        }
+    }
+}
+
+// The values in scope at some point of generation,
+// with their types.
+type Scope = Vec<(InstrId, IrTypeId)>;
+
+/**
+ * Generates random valid graphs: nested sequences of
+ * straight-line runs, if/else diamonds joining through
+ * phis, and counted loops carrying values through
+ * header phis.
+ *
+ * Generated loops always run to a bounded trip count,
+ * so the graphs terminate when executed.
+ */
+pub struct GraphGen {
+    rng: Rng,
+    config: GraphGenConfig
+}
+
+impl GraphGen {
+    pub fn new(seed: u64) -> GraphGen {
+        GraphGen::with_config(seed, GraphGenConfig::default())
+    }
+    pub fn with_config(seed: u64, config: GraphGenConfig)
+      -> GraphGen
+    {
+        GraphGen { rng: Rng::new(seed), config }
+    }
+
+    pub fn rng(&mut self) -> &mut Rng { &mut self.rng }
+
+    /** Generate a builder holding a random graph. */
+    pub fn generate_builder(&mut self) -> Builder {
+        let config = &self.config;
+        let (encoding, storage) = (config.encoding, config.storage);
+        Builder::build_with(encoding, storage, |bs| {
+            let mut scope = Scope::new();
+            self.gen_sequence(bs, &mut scope, 0);
+            let ty = *self.rng.pick(&VALUE_TYPES);
+            let val = self.pick_value(bs, &mut scope, ty);
+            bs.emit_op_end(&RetOp::new(ty).op(), &[val], &[]);
+        })
+    }
+
+    /** Generate a random graph. */
+    pub fn generate(&mut self) -> Graph {
+        self.generate_builder().into_graph()
+    }
+
+    // Emit a sequence of items into the open current
+    // block, leaving a (possibly different) block open.
+    fn gen_sequence(&mut self, bs: &mut BuildSession,
+                    scope: &mut Scope, depth: u32)
+    {
+        let items = 1 + self.rng.below(self.config.max_items);
+        for _ in 0 .. items {
+            let nested = depth < self.config.max_depth;
+            match self.rng.below(4) {
+              0 if nested => self.gen_diamond(bs, scope, depth),
+              1 if nested => self.gen_loop(bs, scope, depth),
+              _ => self.gen_run(bs, scope)
+            }
+        }
+    }
+
+    fn gen_run(&mut self, bs: &mut BuildSession, scope: &mut Scope) {
+        let len = 1 + self.rng.below(self.config.max_run);
+        for _ in 0 .. len {
+            match self.rng.below(3) {
+              0 => {
+                let ty = *self.rng.pick(&VALUE_TYPES);
+                self.emit_const(bs, scope, ty);
+              }
+              1 => {
+                let ty = *self.rng.pick(&VALUE_TYPES);
+                let lhs = self.pick_value(bs, scope, ty);
+                let rhs = self.pick_value(bs, scope, ty);
+                let kind = *self.rng.pick(&CMP_KINDS);
+                let op = CmpOp::new(kind, ty).op();
+                let def = bs.emit_op(&op, &[lhs, rhs]);
+                scope.push((def.instr_id(), IrTypeId::Bool));
+              }
+              _ => {
+                let ty = *self.rng.pick(&INT_TYPES);
+                let lhs = self.pick_value(bs, scope, ty);
+                let rhs = self.pick_value(bs, scope, ty);
+                let kind = *self.rng.pick(&BINI_KINDS);
+                let op = BiniOp::new(kind, ty).op();
+                let def = bs.emit_op(&op, &[lhs, rhs]);
+                scope.push((def.instr_id(), ty));
+              }
+            }
+        }
+    }
+
+    fn gen_diamond(&mut self, bs: &mut BuildSession,
+                   scope: &mut Scope, depth: u32)
+    {
+        let cond = self.pick_value(bs, scope, IrTypeId::Bool);
+        let phi_types = self.gen_types();
+
+        let then_block = bs.decl_plain_block(0);
+        let else_block = bs.decl_plain_block(0);
+        let join = bs.decl_plain_block(phi_types.len() as u32);
+        bs.branch(TypedDefn::new(cond.instr_id()),
+                  then_block, &[], else_block, &[]);
+
+        for &arm in &[then_block, else_block] {
+            bs.def_block(arm);
+            bs.def_subgraph(|cs| {
+                let mut arm_scope = scope.clone();
+                self.gen_sequence(cs, &mut arm_scope, depth + 1);
+                let args: Vec<Defn> = phi_types.iter().map(|&ty| {
+                    self.pick_value(cs, &mut arm_scope, ty)
+                }).collect();
+                cs.jump(join, &args);
+            });
+        }
+
+        bs.def_block(join);
+        for &ty in &phi_types {
+            let phi = bs.emit_op(&PhiOp::new(ty).op(), &[]);
+            scope.push((phi.instr_id(), ty));
+        }
+    }
+
+    fn gen_loop(&mut self, bs: &mut BuildSession,
+                scope: &mut Scope, depth: u32)
+    {
+        let carried = self.gen_types();
+        let trips = 1 + self.rng.below(self.config.max_trips);
+
+        let mut init = vec![
+            self.emit_const_int32(bs, 0)
+        ];
+        for &ty in &carried {
+            init.push(self.pick_value(bs, scope, ty));
+        }
+
+        let head = bs.decl_loop_head(1 + carried.len() as u32);
+        let exit = bs.decl_plain_block(0);
+        bs.jump(head, &init);
+
+        let phis = bs.def_loop(head, |cs| {
+            let counter = cs.emit_op(
+              &PhiOp::new(IrTypeId::Int32).op(), &[]);
+            let mut phis = vec![(counter.instr_id(), IrTypeId::Int32)];
+            for &ty in &carried {
+                let phi = cs.emit_op(&PhiOp::new(ty).op(), &[]);
+                phis.push((phi.instr_id(), ty));
+            }
+
+            let limit = self.emit_const_int32(cs, trips);
+            let cmp = CmpOp::new(CmpKind::Lt, IrTypeId::Int32).op();
+            let cond = cs.emit_op(&cmp, &[counter, limit]);
+
+            let body = cs.decl_plain_block(0);
+            cs.branch(TypedDefn::new(cond.instr_id()),
+                      body, &[], exit, &[]);
+
+            cs.def_block(body);
+            cs.def_subgraph(|bs| {
+                let mut body_scope = scope.clone();
+                body_scope.extend_from_slice(&phis);
+                self.gen_sequence(bs, &mut body_scope, depth + 1);
+
+                let one = self.emit_const_int32(bs, 1);
+                let add = BiniOp::new(BiniKind::Add, IrTypeId::Int32);
+                let next = bs.emit_op(&add.op(), &[counter, one]);
+                let mut args = vec![next];
+                for &ty in &carried {
+                    args.push(self.pick_value(bs, &mut body_scope, ty));
+                }
+                bs.jump(head, &args);
+            });
+            phis
+        });
+
+        // Header phis dominate the loop exit.
+        bs.def_block(exit);
+        scope.extend_from_slice(&phis);
+    }
+
+    fn gen_types(&mut self) -> Vec<IrTypeId> {
+        let n = self.rng.below(self.config.max_phis + 1);
+        (0 .. n).map(|_| *self.rng.pick(&VALUE_TYPES)).collect()
+    }
+
+    // Pick a value of the given type from the scope,
+    // sometimes (or when there is none) emitting a new
+    // constant instead.
+    fn pick_value<'x>(&mut self, bs: &mut BuildSession<'x>,
+                      scope: &mut Scope, ty: IrTypeId)
+      -> Defn<'x>
+    {
+        let candidates: Vec<InstrId> =
+          scope.iter().filter(|&&(_, t)| t == ty)
+               .map(|&(id, _)| id).collect();
+        if candidates.is_empty() || self.rng.chance(1, 8) {
+            return self.emit_const(bs, scope, ty);
+        }
+        Defn::new(*self.rng.pick(&candidates))
+    }
+
+    fn emit_const<'x>(&mut self, bs: &mut BuildSession<'x>,
+                      scope: &mut Scope, ty: IrTypeId)
+      -> Defn<'x>
+    {
+        let op = match ty {
+          IrTypeId::Bool => ConstOp::new_bool(self.rng.chance(1, 2)),
+          IrTypeId::Int32 => ConstOp::new_int32(self.gen_int() as u32),
+          _ => ConstOp::new_int64(self.gen_int())
+        };
+        let def = bs.emit_op(&op.op(), &[]);
+        scope.push((def.instr_id(), ty));
+        def
+    }
+
+    fn emit_const_int32<'x>(&mut self, bs: &mut BuildSession<'x>,
+                            i: u32)
+      -> Defn<'x>
+    {
+        bs.emit_op(&ConstOp::new_int32(i).op(), &[])
+    }
+
+    // Mostly small integers, with occasional large
+    // ones to exercise multi-byte encodings.
+    fn gen_int(&mut self) -> u64 {
+        match self.rng.below(4) {
+          0 => self.rng.next_u64(),
+          1 => self.rng.below(1 << 16) as u64,
+          _ => self.rng.below(16) as u64
+        }
+    }
+}
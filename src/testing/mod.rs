@@ -0,0 +1,20 @@
+
+mod rng;
+pub use self::rng::Rng;
+
+mod graph_gen;
+pub use self::graph_gen::{ GraphGen, GraphGenConfig };
+
+mod roundtrip;
+pub use self::roundtrip::{
+    assert_ops_round_trip, assert_graph_round_trip
+};
+
+mod file_check;
+pub use self::file_check::{ CheckError, file_check, assert_file_check };
+
+mod reduce;
+pub use self::reduce::reduce;
+
+mod mutate;
+pub use self::mutate::{ Mutation, Mutator };
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+
+use crate::graph::Graph;
+use crate::ops::{ Op, Operation, ConstOp, CmpOp };
+use crate::ir_types::IrTypeId;
+use crate::text::{ Rebuild, each_block };
+use crate::testing::Rng;
+
+/*
+ * Mutation of graphs.  Where a generator makes graphs of
+ * the shapes it knows, a mutator edits a valid graph,
+ * however it was made, into a nearby one: some edits
+ * keep what the graph computes, and a pass should treat
+ * the result as it does the original, while others
+ * change it, or leave a graph that no longer verifies,
+ * for the verifier to catch.  Each edit is made through
+ * the graph's text, so the result can always be built.
+ *
+ * Bytes are mutated apart from any graph, so a decoder
+ * can be given streams no builder would write.
+ */
+
+/** An edit made by a `Mutator`. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum Mutation {
+    // Swap the operands of a commutative binop, or of a
+    // comparison whose kind is mirrored.  The graph
+    // computes the same.
+    CommuteOperands,
+
+    // Swap the operands of a subtraction, or of an
+    // ordered comparison keeping its kind.
+    SwapOperands,
+
+    // Give a constant another value.
+    ChangeConst,
+
+    // Make an operand use another value of its type,
+    // defined earlier in the text, which need not
+    // dominate the use.
+    ReplaceOperand,
+
+    // Swap the targets of a branch.
+    SwapTargets,
+
+    // Make an edge go to another block with phis of the
+    // same types, keeping its arguments.
+    Retarget
+}
+
+impl Mutation {
+    /** Whether the mutated graph computes the same. */
+    pub fn preserves_semantics(self) -> bool {
+        matches!(self, Mutation::CommuteOperands)
+    }
+}
+
+// Where a mutation is made, by the names of a rebuild:
+// the instruction at an index of a block, and for an
+// operand or edge, its index and the names it may be
+// given instead.
+struct Site {
+    mutation: Mutation,
+    block: String,
+    index: usize,
+    slot: usize,
+    choices: Vec<String>
+}
+
+/**
+ * Makes random edits to graphs, and to instruction
+ * bytes, for stress testing the verifier, decoder and
+ * passes beyond what a `GraphGen` generates.
+ */
+pub struct Mutator {
+    rng: Rng
+}
+
+impl Mutator {
+    pub fn new(seed: u64) -> Mutator {
+        Mutator { rng: Rng::new(seed) }
+    }
+
+    pub fn rng(&mut self) -> &mut Rng { &mut self.rng }
+
+    /**
+     * Make a random edit to a graph, returning it, or
+     * None if the graph has no place for any edit.  The
+     * graph must print as text that parses back, as a
+     * graph that verifies does; once an edit leaves it
+     * failing to verify, it may not.  The graph is
+     * rebuilt: its ids change, and it loses its profile,
+     * but keeps its target, operand encoding and block
+     * storage.
+     */
+    pub fn mutate(&mut self, graph: &mut Graph) -> Option<Mutation> {
+        let mut sites = sites(&mut Rebuild::new(graph));
+        while !sites.is_empty() {
+            let i = self.rng.below(sites.len() as u32) as usize;
+            let site = sites.swap_remove(i);
+            let mut rb = Rebuild::new(graph);
+            self.apply(&mut rb, &site);
+            // An edit may leave a block targeted from
+            // outside its loop; try another.
+            if let Ok(mutated) = rb.try_finish() {
+                graph.rebuilt(mutated);
+                return Some(site.mutation);
+            }
+        }
+        None
+    }
+
+    /**
+     * Make a random edit to instruction bytes: changing,
+     * inserting or removing a byte, or truncating them.
+     * A decoder given the result should fail with an
+     * error, or decode instructions, but not panic.
+     */
+    pub fn mutate_bytes(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let mut bytes = bytes.to_vec();
+        if bytes.is_empty() {
+            bytes.push(self.rng.next_u64() as u8);
+            return bytes;
+        }
+        let at = self.rng.below(bytes.len() as u32) as usize;
+        match self.rng.below(4) {
+          0 => bytes[at] ^= 1 << self.rng.below(8),
+          1 => bytes.insert(at, self.rng.next_u64() as u8),
+          2 => { bytes.remove(at); }
+          _ => bytes.truncate(at)
+        }
+        bytes
+    }
+
+    fn apply(&mut self, rb: &mut Rebuild, site: &Site) {
+        let pick = |rng: &mut Rng| rng.pick(&site.choices).clone();
+        let choice = match site.mutation {
+          Mutation::ReplaceOperand | Mutation::Retarget =>
+            pick(&mut self.rng),
+          _ => String::new()
+        };
+        let changed = self.change_const(rb, site);
+        let instr = &mut rb.named_block_mut(&site.block)
+                           .instrs[site.index];
+        match site.mutation {
+          Mutation::CommuteOperands | Mutation::SwapOperands => {
+            instr.operands.swap(0, 1);
+            if let (Op::Cmp(ref op), Mutation::CommuteOperands) =
+                   (&instr.op, site.mutation)
+            {
+                instr.op = CmpOp::new(op.kind().mirrored(), op.tyid())
+                             .op();
+            }
+          }
+          Mutation::ChangeConst => instr.op = changed.unwrap(),
+          Mutation::ReplaceOperand =>
+            instr.operands[site.slot] = choice,
+          Mutation::SwapTargets => instr.targets.swap(0, 1),
+          Mutation::Retarget => instr.targets[site.slot].0 = choice
+        }
+    }
+
+    // The constant a ChangeConst site is given: another
+    // value of its type.
+    fn change_const(&mut self, rb: &mut Rebuild, site: &Site)
+      -> Option<Op>
+    {
+        if site.mutation != Mutation::ChangeConst {
+            return None;
+        }
+        let instr = &rb.named_block_mut(&site.block).instrs[site.index];
+        let old = match instr.op {
+          Op::Const(ref op) => op.clone(),
+          _ => unreachable!()
+        };
+        let mut value = match self.rng.below(4) {
+          0 => self.rng.next_u64(),
+          1 => self.rng.below(1 << 16) as u64,
+          _ => self.rng.below(16) as u64
+        };
+        let op = match old {
+          ConstOp::Bool(b) => ConstOp::new_bool(!b),
+          ConstOp::Int32(i) => {
+            if value as u32 == i { value ^= 1; }
+            ConstOp::new_int32(value as u32)
+          }
+          ConstOp::Int64(i) => {
+            if value == i { value ^= 1; }
+            ConstOp::new_int64(value)
+          }
+          ConstOp::PtrInt(p) => {
+            if value == p { value ^= 1; }
+            ConstOp::new_ptr_int(value)
+          }
+        };
+        Some(op.op())
+    }
+}
+
+// The sites of every mutation of a graph, in text order.
+fn sites(rb: &mut Rebuild) -> Vec<Site> {
+    // The types of the phis of each block but the
+    // first, the start block, which no edge may enter.
+    let mut phis: Vec<(String, Vec<IrTypeId>)> = Vec::new();
+    each_block(rb.blocks_mut(), &mut |block| {
+        let types = block.params.iter().map(|p| p.1).collect();
+        phis.push((block.name.clone(), types));
+    });
+    phis.remove(0);
+
+    let mut sites = Vec::new();
+    // The values defined so far in text order.
+    let mut seen: Vec<(String, IrTypeId)> = Vec::new();
+    let mut types: HashMap<String, IrTypeId> = HashMap::new();
+    each_block(rb.blocks_mut(), &mut |block| {
+        for &(ref name, ty) in &block.params {
+            seen.push((name.clone(), ty));
+            types.insert(name.clone(), ty);
+        }
+        for (index, instr) in block.instrs.iter().enumerate() {
+            let mut site = |mutation, slot, choices| {
+                let block = block.name.clone();
+                sites.push(Site { mutation, block, index, slot, choices });
+            };
+            match instr.op {
+              Op::Bini(ref op) if op.kind().is_commutative() =>
+                site(Mutation::CommuteOperands, 0, Vec::new()),
+              Op::Bini(_) => site(Mutation::SwapOperands, 0, Vec::new()),
+              Op::Cmp(ref op) => {
+                site(Mutation::CommuteOperands, 0, Vec::new());
+                if op.kind().mirrored() != op.kind() {
+                    site(Mutation::SwapOperands, 0, Vec::new());
+                }
+              }
+              Op::Const(_) => site(Mutation::ChangeConst, 0, Vec::new()),
+              _ => {}
+            }
+            for (slot, operand) in instr.operands.iter().enumerate() {
+                let ty = types[operand];
+                let choices: Vec<String> =
+                  seen.iter().filter(|v| v.1 == ty && &v.0 != operand)
+                      .map(|v| v.0.clone()).collect();
+                if !choices.is_empty() {
+                    site(Mutation::ReplaceOperand, slot, choices);
+                }
+            }
+            if instr.targets.len() == 2 {
+                site(Mutation::SwapTargets, 0, Vec::new());
+            }
+            for (slot, target) in instr.targets.iter().enumerate() {
+                let want = &phis.iter().find(|p| p.0 == target.0)
+                                .unwrap().1;
+                let choices: Vec<String> =
+                  phis.iter().filter(|p| p.0 != target.0 && &p.1 == want)
+                      .map(|p| p.0.clone()).collect();
+                if !choices.is_empty() {
+                    site(Mutation::Retarget, slot, choices);
+                }
+            }
+            if let Some(ref def) = instr.def {
+                let ty = instr.op.out_type().unwrap();
+                seen.push((def.clone(), ty));
+                types.insert(def.clone(), ty);
+            }
+        }
+    });
+    sites
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::BlockStorage;
+    use crate::instr::OperandEncoding;
+    use crate::text::parse_with;
+    use super::Mutator;
+
+    #[test]
+    fn keeps_encoding_and_storage() {
+        let mut mutator = Mutator::new(7);
+        for _ in 0 .. 16 {
+            let mut graph = parse_with("\
+block0:
+  %0 = const.i32 0
+  %1 = const.i32 10
+  jump block1(%0)
+loop block1(%2: i32) {
+  %3 = lt.i32 %2, %1
+  branch %3, block2, block3
+  block2:
+    %4 = const.i32 1
+    %5 = add.i32 %2, %4
+    jump block1(%5)
+}
+block3:
+  ret.i32 %2
+", OperandEncoding::Fixed, BlockStorage::Chunked);
+            let before = graph.print_ir();
+            let mutation = mutator.mutate(&mut graph).unwrap();
+            assert_ne!(graph.print_ir(), before);
+            assert_eq!(graph.operand_encoding(), OperandEncoding::Fixed);
+            assert_eq!(graph.block_storage(), BlockStorage::Chunked);
+            if mutation.preserves_semantics() {
+                assert!(graph.verify().is_ok());
+            }
+        }
+    }
+}
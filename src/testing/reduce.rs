@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+
+use crate::graph::Graph;
+use crate::ops::{ Op, Operation, ConstOp, JumpOp };
+use crate::ir_types::IrTypeId;
+use crate::text::{ Rebuild, ParsedBlock, ParsedInstr, BlockKind,
+                   each_block };
+
+/*
+ * Test case reduction.  A graph showing a bug, such as
+ * one a pass crashes on, is cut down while a predicate
+ * holds of it, one edit at a time: a branch is folded
+ * into a jump along one of its edges, dropping the
+ * blocks no longer reached; a block is merged into
+ * the block that alone jumps to it; an unused value,
+ * or an instruction defining none, is removed; or the
+ * uses of a value are given a zero constant instead.
+ * Each edit lowers the number of non-constant
+ * instructions, but for a fold, which lowers the
+ * number of branches, and the removal of a constant,
+ * which lowers the number of constants; neither
+ * raises the first.  So reduction ends, once no edit
+ * keeps the predicate holding.
+ *
+ * Every graph tried can be built, and if the graph
+ * given verifies, verifies too, so the predicate only
+ * sees graphs a pass could be handed.
+ */
+
+// An edit to a graph, by the names of its rebuild.
+enum Edit {
+    // End a block with a jump along the edge at an
+    // index of its branch.
+    Fold { block: String, index: usize },
+
+    // Move the instructions of the block after one in
+    // the text, which only that one jumps to, to the
+    // end of that one.
+    Merge(String),
+
+    // Remove an unused value, or phi with its arguments.
+    RemoveValue(String),
+
+    // Remove the instruction at an index of a block,
+    // which defines no value and is not its end.
+    RemoveInstr { block: String, index: usize },
+
+    // Use a zero constant in place of a value.
+    Zero { value: String, ty: IrTypeId }
+}
+
+fn zero(ty: IrTypeId) -> Op {
+    match ty {
+      IrTypeId::Bool => ConstOp::new_bool(false).op(),
+      IrTypeId::Int32 => ConstOp::new_int32(0).op(),
+      IrTypeId::Int64 => ConstOp::new_int64(0).op(),
+      IrTypeId::PtrInt => ConstOp::new_ptr_int(0).op()
+    }
+}
+
+// Add the merges of the blocks of a level, and those
+// its loops enclose, by the number of edges into each
+// block.
+fn merges(level: &[ParsedBlock], preds: &HashMap<String, usize>,
+          edits: &mut Vec<Edit>)
+{
+    for pair in level.windows(2) {
+        let (first, next) = (&pair[0], &pair[1]);
+        let end = first.instrs.last().unwrap();
+        if first.kind != BlockKind::Loop && next.kind == BlockKind::Plain
+           && next.params.is_empty() && preds[&next.name] == 1
+           && matches!(end.op, Op::Jump(_))
+           && end.targets[0].0 == next.name
+        {
+            edits.push(Edit::Merge(first.name.clone()));
+        }
+    }
+    for block in level {
+        merges(&block.body, preds, edits);
+    }
+}
+
+fn merge(level: &mut Vec<ParsedBlock>, block: &str) -> bool {
+    match level.iter().position(|b| b.name == block) {
+      Some(i) => {
+        let next = level.remove(i + 1);
+        let first = &mut level[i];
+        first.instrs.pop();
+        first.instrs.extend(next.instrs);
+        true
+      }
+      None => level.iter_mut().any(|b| merge(&mut b.body, block))
+    }
+}
+
+// The edits to try, in text order: the folds first, as
+// they remove the most.
+fn edits(rb: &mut Rebuild) -> Vec<Edit> {
+    let mut folds = Vec::new();
+    let mut removes = Vec::new();
+    let mut values = Vec::new();
+    let mut preds = HashMap::new();
+    each_block(rb.blocks_mut(), &mut |block| {
+        let end = block.instrs.last().unwrap();
+        for target in &end.targets {
+            *preds.entry(target.0.clone()).or_insert(0) += 1;
+        }
+        if end.targets.len() > 1 {
+            for index in 0 .. end.targets.len() {
+                let block = block.name.clone();
+                folds.push(Edit::Fold { block, index });
+            }
+        }
+        for (name, ty) in &block.params {
+            values.push((name.clone(), *ty, false));
+        }
+        for (index, instr) in block.instrs.iter().enumerate() {
+            match instr.def {
+              Some(ref def) => {
+                let is_const = matches!(instr.op, Op::Const(_));
+                let ty = instr.op.out_type().unwrap();
+                values.push((def.clone(), ty, is_const));
+              }
+              None if !instr.op.terminal() => {
+                let block = block.name.clone();
+                removes.push(Edit::RemoveInstr { block, index });
+              }
+              None => {}
+            }
+        }
+    });
+    merges(rb.blocks(), &preds, &mut folds);
+
+    let mut zeros = Vec::new();
+    for (value, ty, is_const) in values {
+        if rb.count_uses(&value) == 0 {
+            removes.push(Edit::RemoveValue(value));
+        } else if !is_const {
+            zeros.push(Edit::Zero { value, ty });
+        }
+    }
+    folds.into_iter().chain(removes).chain(zeros).collect()
+}
+
+fn apply(rb: &mut Rebuild, edit: &Edit) {
+    match *edit {
+      Edit::Fold { ref block, index } => {
+        let end = rb.named_block_mut(block).instrs.last_mut().unwrap();
+        let target = end.targets.swap_remove(index);
+        end.op = JumpOp::new().op();
+        end.operands.clear();
+        end.targets = vec![target];
+        rb.remove_unreachable();
+      }
+      Edit::Merge(ref block) => {
+        merge(rb.blocks_mut(), block);
+      }
+      Edit::RemoveValue(ref value) => rb.remove_value(value),
+      Edit::RemoveInstr { ref block, index } => {
+        rb.named_block_mut(block).instrs.remove(index);
+      }
+      Edit::Zero { ref value, ty } => {
+        // At the top of the start block, after its
+        // params, the constant is defined before any
+        // use.
+        let name = rb.fresh_name();
+        let start = &mut rb.blocks_mut()[0];
+        let at = start.instrs.iter()
+                      .take_while(|i| matches!(i.op, Op::Param(_)))
+                      .count();
+        start.instrs.insert(at, ParsedInstr::new(
+          start.line, Some(name.clone()), zero(ty), Vec::new(),
+          Vec::new()));
+        rb.replace_uses(value, &name);
+        rb.remove_value(value);
+      }
+    }
+}
+
+/**
+ * Reduce a graph for which `interesting` holds, such as
+ * one a pass fails on, to a smaller graph for which it
+ * still holds, removing blocks and instructions until
+ * no single edit keeps it holding.  Every graph passed
+ * to `interesting` verifies if the given graph does;
+ * to reduce a crash, it should catch the panic.  The
+ * graph must print as text that parses back, as those
+ * built by a builder do.  The reduced graph is rebuilt:
+ * it has no profile, and its ids differ, but it keeps
+ * the given graph's target, operand encoding and block
+ * storage, as does every graph tried.
+ */
+pub fn reduce<F>(graph: Graph, mut interesting: F) -> Graph
+  where F: FnMut(&Graph) -> bool
+{
+    assert!(interesting(&graph), "the graph to reduce is not interesting");
+    let verifies = graph.verify().is_ok();
+    let mut current = graph;
+    loop {
+        let mut reduced = false;
+        let mut next = 0;
+        loop {
+            let mut rb = Rebuild::new(&current);
+            let edits = edits(&mut rb);
+            let edit = match edits.get(next) {
+              Some(edit) => edit,
+              None => break
+            };
+            apply(&mut rb, edit);
+            match rb.try_finish() {
+              Ok(candidate) if (!verifies || candidate.verify().is_ok())
+                                 && interesting(&candidate) =>
+              {
+                  current = candidate;
+                  reduced = true;
+              }
+              _ => next += 1
+            }
+        }
+        if !reduced {
+            return current;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::BlockStorage;
+    use crate::instr::OperandEncoding;
+    use crate::text::parse_with;
+    use super::reduce;
+
+    #[test]
+    fn keeps_encoding_and_storage() {
+        let graph = parse_with("\
+block0:
+  %0 = const.i32 0
+  %1 = const.i32 10
+  jump block1(%0)
+loop block1(%2: i32) {
+  %3 = lt.i32 %2, %1
+  branch %3, block2, block3
+  block2:
+    %4 = const.i32 1
+    %5 = add.i32 %2, %4
+    jump block1(%5)
+}
+block3:
+  %6 = mul.i32 %2, %1
+  ret.i32 %6
+", OperandEncoding::Delta, BlockStorage::Chunked);
+        let reduced = reduce(graph, |g| {
+            assert_eq!(g.operand_encoding(), OperandEncoding::Delta);
+            assert_eq!(g.block_storage(), BlockStorage::Chunked);
+            g.print_ir().contains("mul.i32")
+        });
+        assert!(reduced.print_ir().contains("mul.i32"));
+        assert!(reduced.num_blocks() < 4);
+        assert_eq!(reduced.operand_encoding(), OperandEncoding::Delta);
+        assert_eq!(reduced.block_storage(), BlockStorage::Chunked);
+        assert!(reduced.verify().is_ok());
+    }
+}
@@ -0,0 +1,40 @@
+
+/**
+ * A small deterministic pseudo-random generator
+ * (xorshift64*), so generated test inputs can be
+ * reproduced from their seed.
+ */
+#[derive(Clone)]
+pub struct Rng { state: u64 }
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // The state must never be zero.
+        let state = seed ^ 0x9E37_79B9_7F4A_7C15;
+        Rng { state: if state == 0 { 1 } else { state } }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // A value in `0 .. n`, for non-zero `n`.
+    pub fn below(&mut self, n: u32) -> u32 {
+        debug_assert!(n > 0);
+        ((self.next_u64() >> 32) % (n as u64)) as u32
+    }
+
+    // True with probability `num / den`.
+    pub fn chance(&mut self, num: u32, den: u32) -> bool {
+        self.below(den) < num
+    }
+
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u32) as usize]
+    }
+}
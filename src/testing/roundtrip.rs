@@ -0,0 +1,217 @@
+
+use std::convert::TryFrom;
+
+use crate::instr::InstrId;
+use crate::block::BlockId;
+use crate::graph::Graph;
+use crate::decode::{ ByteReader, decode_op, decode_instrs_pooled };
+use crate::ops::{
+    Opcode, Operation, Op,
+    NopOp, PhiOp, ParamOp, ConstOp, CmpOp, CmpKind,
+    BiniOp, BiniKind, GuardOp, RetOp, BranchOp, JumpOp, ExitOp,
+    PatchPointOp, BoundsCheckOp, NullCheckOp, LoadOp, StoreOp,
+    PtrAddOp, CopyOp, CallOp
+};
+use crate::ir_types::IrTypeId;
+use crate::testing::Rng;
+
+// Every value of a byte-coded enum.
+fn all_codes<T: TryFrom<u8>>() -> Vec<T> {
+    (0 ..= u8::MAX).filter_map(|c| T::try_from(c).ok()).collect()
+}
+
+// A leb128 payload value, biased towards the edges of
+// each encoded length.
+fn sample_u64(rng: &mut Rng) -> u64 {
+    let bits = rng.below(65);
+    let top = if bits == 64 { u64::MAX } else { (1 << bits) - 1 };
+    match rng.below(3) {
+      0 => top,
+      1 => top.wrapping_add(1),
+      _ => rng.next_u64() & top
+    }
+}
+
+/**
+ * Sample operations covering every opcode, type and
+ * kind, with random payloads for the ones carrying
+ * values.
+ */
+pub(crate) fn sample_ops(rng: &mut Rng) -> Vec<Op> {
+    let types: Vec<IrTypeId> = all_codes();
+    let mut ops = Vec::new();
+    for opcode in all_codes::<Opcode>() {
+        match opcode {
+          Opcode::Nop => ops.push(NopOp::new().op()),
+          Opcode::Phi => {
+            ops.extend(types.iter().map(|&ty| PhiOp::new(ty).op()));
+          }
+          Opcode::Param => {
+            for &ty in &types {
+                let index = sample_u64(rng) as u32;
+                ops.push(ParamOp::new(index, ty).op());
+            }
+          }
+          Opcode::Const => {
+            ops.push(ConstOp::new_bool(false).op());
+            ops.push(ConstOp::new_bool(true).op());
+            let i = sample_u64(rng);
+            ops.push(ConstOp::new_int32(i as u32).op());
+            ops.push(ConstOp::new_int64(i).op());
+            ops.push(ConstOp::new_ptr_int(i).op());
+          }
+          Opcode::Cmp => {
+            for kind in all_codes::<CmpKind>() {
+                for &ty in &types {
+                    ops.push(CmpOp::new(kind, ty).op());
+                }
+            }
+          }
+          Opcode::Bini => {
+            for kind in all_codes::<BiniKind>() {
+                for &ty in &types {
+                    ops.push(BiniOp::new(kind, ty).op());
+                }
+            }
+          }
+          Opcode::Guard => {
+            ops.push(GuardOp::new(false).op());
+            ops.push(GuardOp::new(true).op());
+          }
+          Opcode::Ret => {
+            ops.extend(types.iter().map(|&ty| RetOp::new(ty).op()));
+          }
+          Opcode::Branch => ops.push(BranchOp::new().op()),
+          Opcode::Jump => ops.push(JumpOp::new().op()),
+          Opcode::Exit => ops.push(ExitOp::new().op()),
+          Opcode::PatchPoint => {
+            let id = sample_u64(rng) as u32;
+            let size = rng.below(40);
+            ops.push(PatchPointOp::new(id, size).op());
+          }
+          Opcode::BoundsCheck => {
+            for &ty in &types {
+                ops.push(BoundsCheckOp::new(ty, false).op());
+                ops.push(BoundsCheckOp::new(ty, true).op());
+            }
+          }
+          Opcode::NullCheck => {
+            ops.push(NullCheckOp::new(false).op());
+            ops.push(NullCheckOp::new(true).op());
+          }
+          Opcode::Load => {
+            ops.extend(types.iter().map(|&ty| LoadOp::new(ty).op()));
+          }
+          Opcode::Store => {
+            ops.extend(types.iter().map(|&ty| StoreOp::new(ty).op()));
+          }
+          Opcode::PtrAdd => {
+            let field = |rng: &mut Rng| sample_u64(rng) as u32;
+            ops.push(PtrAddOp::new(field(rng), field(rng), field(rng),
+                                   field(rng)).op());
+            ops.push(PtrAddOp::new(0, 0, 0, 0).op());
+          }
+          Opcode::Copy => {
+            ops.extend(types.iter().map(|&ty| CopyOp::new(ty).op()));
+          }
+          Opcode::Call => {
+            for &ty in &types {
+                let callee = sample_u64(rng) as u32;
+                let nargs = rng.below(8);
+                ops.push(CallOp::new(ty, callee, nargs).op());
+            }
+          }
+        }
+    }
+    ops
+}
+
+fn encode_op(op: &Op) -> Vec<u8> {
+    let mut bytes = vec![op.opcode().into_u8()];
+    op.write_to(&mut bytes);
+    bytes
+}
+
+/**
+ * Assert that an operation reads back, through both
+ * the unchecked reader and the safe decoder, as an
+ * operation with the same encoding, consuming exactly
+ * the bytes written.
+ */
+pub(crate) fn assert_op_round_trip(op: &Op) {
+    let bytes = encode_op(op);
+
+    let (nb, read) = unsafe { Op::read_from(&bytes, &[]) };
+    assert!(nb == bytes.len(),
+            "{}: read {} of {} bytes", op, nb, bytes.len());
+    assert!(encode_op(&read) == bytes,
+            "{}: read back as {}", op, read);
+
+    let mut reader = ByteReader::new(&bytes);
+    let decoded = decode_op(&mut reader).unwrap_or_else(|err| {
+        panic!("{}: decode failed: {}", op, err)
+    });
+    assert!(reader.at_end(),
+            "{}: decoded {} of {} bytes",
+            op, reader.offset(), bytes.len());
+    assert!(encode_op(&decoded) == bytes,
+            "{}: decoded as {}", op, decoded);
+}
+
+/**
+ * Round-trip a sample of every operation through its
+ * encoding.
+ */
+pub fn assert_ops_round_trip(rng: &mut Rng) {
+    for op in sample_ops(rng) {
+        assert_op_round_trip(&op);
+    }
+}
+
+/**
+ * Assert that a graph's instruction stream decodes,
+ * with the safe decoder, to exactly the instructions
+ * its blocks hold: the same ids, operations, operands
+ * and targets.
+ */
+pub fn assert_graph_round_trip(graph: &Graph) {
+    let decoded = decode_instrs_pooled(graph.instr_bytes(),
+                                       graph.operand_encoding(),
+                                       graph.const_pool())
+      .unwrap_or_else(|err| panic!("decode failed: {}", err));
+
+    let mut ids: Vec<(InstrId, BlockId)> = Vec::new();
+    for block in graph.block_store().iter_blocks() {
+        for info in graph.block_instrs(block) {
+            ids.push((info.defn().instr_id(), block.id()));
+        }
+    }
+    ids.sort_by_key(|&(id, _)| id.offset());
+    assert!(ids.len() == decoded.len(),
+            "blocks hold {} instrs, stream decodes to {}",
+            ids.len(), decoded.len());
+
+    for (&(id, block), dec) in ids.iter().zip(&decoded) {
+        let at = format!("block {} instr {}", block.as_u32(), id);
+        assert!(dec.id() == id, "{}: decoded id {}", at, dec.id());
+
+        let info = graph.instr(id);
+        assert!(encode_op(info.op()) == encode_op(dec.op()),
+                "{}: op {} decoded as {}", at, info.op(), dec.op());
+
+        let inputs: Vec<InstrId> =
+          info.inputs_iter().map(|d| d.instr_id()).collect();
+        assert!(inputs == dec.inputs(),
+                "{}: inputs {:?} decoded as {:?}",
+                at, inputs, dec.inputs());
+
+        let targets: Vec<(BlockId, Vec<InstrId>)> =
+          info.targets_iter().map(|t| {
+              let args = t.phi_args().map(|d| d.instr_id());
+              (t.block_id(), args.collect())
+          }).collect();
+        assert!(targets == dec.targets(),
+                "{}: targets {:?} decoded as {:?}",
+                at, targets, dec.targets());
+    }
+}
@@ -0,0 +1,142 @@
+
+use std::fmt::Write;
+
+use crate::block::Block;
+use crate::instr::{ InstrInfo, InstrInputs };
+use crate::graph::Graph;
+use crate::text::type_name;
+use crate::text::printer::mnemonic;
+
+/*
+ * The JSON export of a graph is a single object:
+ *
+ *   {
+ *     "start": 0,
+ *     "blocks": [
+ *       { "id": 0, "order": 0, "kind": "start",
+ *         "num_phis": 0, "cold": false,
+ *         "instrs": [
+ *           { "id": 0, "opcode": "const",
+ *             "op": "const.i32 3", "type": "i32",
+ *             "inputs": [], "targets": [] },
+ *           { "id": 6, "opcode": "jump", "op": "jump",
+ *             "type": null, "inputs": [],
+ *             "targets": [{ "block": 1, "args": [0] }] }
+ *         ] },
+ *       ...
+ *     ],
+ *     "edges": [{ "from": 0, "to": 1, "instr": 6 }]
+ *   }
+ *
+ * Blocks are listed in RPO and named by their
+ * declaration ids; instructions, operands and phi
+ * arguments are named by their stream offsets.
+ */
+
+impl Graph {
+    /**
+     * Export the graph's blocks, instructions, operands
+     * and edges as JSON, for tools that read the graph
+     * without linking the crate.
+     */
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        write!(out, "{{\n  \"start\": {},\n  \"blocks\": [",
+               self.start_block_id().as_u32()).unwrap();
+
+        let mut edges = Vec::new();
+        for (i, block) in self.block_store().iter_rpo_blocks()
+                              .enumerate()
+        {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            self.json_block(block, &mut out);
+
+            let end = self.block_end(block);
+            for target in end.targets_iter() {
+                edges.push((block.id().as_u32(),
+                            target.block_id().as_u32(),
+                            end.defn().instr_id().offset()));
+            }
+        }
+        out.push_str("\n  ],\n  \"edges\": [");
+        for (i, (from, to, instr)) in edges.iter().enumerate() {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            write!(out,
+                   "    {{ \"from\": {}, \"to\": {}, \"instr\": {} }}",
+                   from, to, instr).unwrap();
+        }
+        out.push_str("\n  ]\n}\n");
+        out
+    }
+
+    fn json_block(&self, block: &Block, out: &mut String) {
+        let kind =
+          if block.is_loop() { "loop" }
+          else if block.is_start() { "start" }
+          else { "plain" };
+        write!(out,
+               "    {{ \"id\": {}, \"order\": {}, \"kind\": \"{}\",\n\
+               \x20     \"num_phis\": {}, \"cold\": {},\n\
+               \x20     \"instrs\": [",
+               block.id().as_u32(), block.order(), kind,
+               block.num_phis(), block.is_cold()).unwrap();
+        for (i, info) in self.block_instrs(block).enumerate() {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            json_instr(&info, out);
+        }
+        out.push_str("\n      ] }");
+    }
+}
+
+fn json_instr(info: &InstrInfo, out: &mut String) {
+    let op = info.op();
+    let ty = if op.defines_value() { op.out_type() } else { None };
+    write!(out, "        {{ \"id\": {}, \"opcode\": \"{}\", \"op\": ",
+           info.defn().instr_id().offset(),
+           op.opcode().name()).unwrap();
+    json_string(&mnemonic(op), out);
+    match ty {
+      Some(ty) => write!(out, ", \"type\": \"{}\"", type_name(ty)),
+      None => write!(out, ", \"type\": null")
+    }.unwrap();
+
+    out.push_str(",\n          \"inputs\": ");
+    json_ids(info.inputs_iter(), out);
+    out.push_str(", \"targets\": [");
+    for (i, target) in info.targets_iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write!(out, "{{ \"block\": {}, \"args\": ",
+               target.block_id().as_u32()).unwrap();
+        json_ids(target.phi_args(), out);
+        out.push_str(" }");
+    }
+    out.push_str("] }");
+}
+
+fn json_ids(ids: InstrInputs, out: &mut String) {
+    out.push('[');
+    for (i, d) in ids.enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write!(out, "{}", d.instr_id().offset()).unwrap();
+    }
+    out.push(']');
+}
+
+pub(crate) fn json_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+          '"' => out.push_str("\\\""),
+          '\\' => out.push_str("\\\\"),
+          c if (c as u32) < 0x20 => {
+            write!(out, "\\u{:04x}", c as u32).unwrap()
+          }
+          c => out.push(c)
+        }
+    }
+    out.push('"');
+}
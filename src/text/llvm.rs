@@ -0,0 +1,580 @@
+
+use std::collections::{ BTreeMap, HashMap };
+use std::fmt::Write;
+
+use crate::block::Block;
+use crate::instr::{ InstrId, InstrInfo };
+use crate::graph::Graph;
+use crate::ops::{ Op, ConstOp, CmpKind, BiniKind };
+use crate::ir_types::IrTypeId;
+use crate::target::Endian;
+
+/*
+ * The LLVM IR export of a graph is a single function,
+ * after the data layout of the graph's target.
+ * `PtrInt` values are integers of the target's
+ * pointer width.
+ * Blocks are labelled `bbN` by their RPO position, and
+ * values `%vN` in stream order.  Params become the
+ * function's arguments `%pN`, and constants are
+ * printed inline where they are used.
+ *
+ * Guards and exits call the declared `@cog.side_exit`,
+ * which does not return.  A guard splits its block:
+ * the code after it continues in `bbN.K` for the Kth
+ * guard of the block, with the exit path in `bbN.xK`.
+ * A bounds check is a guard on `%bbN.cK`, the unsigned
+ * comparison of its index and length, and its value
+ * is its index; an elided check is just its index.
+ * Null checks are guards on `%bbN.cK` in the same way,
+ * comparing their pointer with zero.
+ * A branch whose targets are the same block passes
+ * through `bbN.t` and `bbN.f`, so that each phi has
+ * one incoming value per predecessor.
+ *
+ * Loads and stores first turn their pointer into a
+ * `ptr` with `inttoptr`, named `%bbN.aK` for the Kth
+ * access of the block.
+ *
+ * Patch points become calls of LLVM's own patchpoint
+ * intrinsic, with no target, reserving the same
+ * number of bytes.
+ *
+ * A call of the graph at index N of the module calls
+ * the declared `@cog.graph.N`, typed after the first
+ * call of it.
+ *
+ * A branch to one cold target and one other carries
+ * `!prof` branch weights against the cold target:
+ * `!0` when the false target is cold, `!1` when the
+ * true target is.
+ */
+
+const SIDE_EXIT: &str = "@cog.side_exit";
+const PATCH_POINT: &str = "@llvm.experimental.patchpoint.void";
+const CALLEE: &str = "@cog.graph";
+
+struct LlvmPrinter<'a> {
+    graph: &'a Graph,
+
+    // The RPO position of each block, by block id.
+    rpo_pos: Vec<u32>,
+
+    // The operand text of each value.
+    values: HashMap<InstrId, String>,
+
+    // The label the last part of each block ends
+    // under, by RPO position.
+    end_labels: Vec<String>,
+
+    // The incoming edges of each block, by RPO
+    // position: the predecessor's label and the phi
+    // arguments it passes.
+    incoming: Vec<Vec<(String, Vec<InstrId>)>>,
+
+    // Whether a branch has used the weights against
+    // its false target, and its true target.
+    cold_weights: [bool; 2],
+
+    out: String
+}
+
+impl Graph {
+    /**
+     * Print the graph as an LLVM IR function of the
+     * given name, for running through `opt` and `llc`.
+     * Argument types are those of the graph's params,
+     * and the return type that of its first `ret`.
+     */
+    pub fn print_llvm(&self, name: &str) -> String {
+        LlvmPrinter::new(self).print(name)
+    }
+}
+
+// Whether the op leaves through a side exit in the
+// middle of its block.
+fn has_exit(op: &Op) -> bool {
+    match op {
+      Op::Guard(_) => true,
+      Op::BoundsCheck(check) => !check.elided(),
+      Op::NullCheck(check) => !check.elided(),
+      _ => false
+    }
+}
+
+fn icmp_name(kind: CmpKind) -> &'static str {
+    match kind {
+      CmpKind::Lt => "slt", CmpKind::Gt => "sgt",
+      CmpKind::Le => "sle", CmpKind::Ge => "sge",
+      CmpKind::Eq => "eq", CmpKind::Ne => "ne"
+    }
+}
+
+fn bini_name(kind: BiniKind) -> &'static str {
+    match kind {
+      BiniKind::Add => "add", BiniKind::Sub => "sub",
+      BiniKind::Mul => "mul", BiniKind::And => "and",
+      BiniKind::Or => "or", BiniKind::Xor => "xor"
+    }
+}
+
+// A global name, quoted unless it is a plain
+// identifier.
+fn global_name(name: &str) -> String {
+    let plain = |c: char| {
+        c.is_ascii_alphanumeric() || "._$-".contains(c)
+    };
+    let starts_ok = name.chars().next()
+                        .map(|c| !c.is_ascii_digit())
+                        .unwrap_or(false);
+    if starts_ok && name.chars().all(plain) {
+        return format!("@{}", name);
+    }
+    let mut s = String::from("@\"");
+    for b in name.bytes() {
+        if b == b'"' || b == b'\\' || !(0x20 .. 0x7F).contains(&b) {
+            write!(s, "\\{:02X}", b).unwrap();
+        } else {
+            s.push(b as char);
+        }
+    }
+    s.push('"');
+    s
+}
+
+impl<'a> LlvmPrinter<'a> {
+    fn new(graph: &'a Graph) -> LlvmPrinter<'a> {
+        let store = graph.block_store();
+        let nblocks = store.total_blocks();
+        let mut printer = LlvmPrinter {
+            graph,
+            rpo_pos: vec![u32::MAX; nblocks],
+            values: HashMap::new(),
+            end_labels: Vec::with_capacity(nblocks),
+            incoming: vec![Vec::new(); nblocks],
+            cold_weights: [false; 2],
+            out: String::new()
+        };
+
+        let mut next = 0;
+        for (pos, block) in store.iter_rpo_blocks().enumerate() {
+            printer.rpo_pos[block.id().as_u32() as usize] = pos as u32;
+            let mut guards = 0;
+            for info in graph.block_instrs(block) {
+                if has_exit(info.op()) {
+                    guards += 1;
+                }
+                printer.name_value(&info, &mut next);
+            }
+            printer.end_labels.push(match guards {
+              0 => format!("bb{}", pos),
+              n => format!("bb{}.{}", pos, n)
+            });
+        }
+
+        for (pos, block) in store.iter_rpo_blocks().enumerate() {
+            let end = graph.block_end(block);
+            let targets: Vec<_> = end.targets_iter().collect();
+            let split = targets.len() == 2
+              && targets[0].block_id() == targets[1].block_id();
+            for (i, target) in targets.iter().enumerate() {
+                let label = if split {
+                    let edge = if i == 0 { "t" } else { "f" };
+                    format!("bb{}.{}", pos, edge)
+                } else {
+                    printer.end_labels[pos].clone()
+                };
+                let args = target.phi_args()
+                                 .map(|d| d.instr_id()).collect();
+                let to = printer.pos(target.block_id().as_u32());
+                printer.incoming[to].push((label, args));
+            }
+        }
+        printer
+    }
+
+    fn pos(&self, block_id: u32) -> usize {
+        self.rpo_pos[block_id as usize] as usize
+    }
+
+    fn name_value(&mut self, info: &InstrInfo, next: &mut u32) {
+        let op = info.op();
+        if !op.defines_value() {
+            return;
+        }
+        let name = match op {
+          Op::BoundsCheck(_) | Op::NullCheck(_) | Op::Copy(_) => {
+            let checked = info.inputs_iter().next().unwrap();
+            self.value(checked.instr_id()).to_string()
+          }
+          Op::Param(op) => format!("%p{}", op.index()),
+          Op::Const(ConstOp::Bool(b)) => format!("{}", b),
+          Op::Const(ConstOp::Int32(i)) => format!("{}", *i as i32),
+          Op::Const(ConstOp::Int64(i)) => format!("{}", *i as i64),
+          Op::Const(ConstOp::PtrInt(p)) if self.ptr_type() == "i32" => {
+            format!("{}", *p as u32 as i32)
+          }
+          Op::Const(ConstOp::PtrInt(p)) => format!("{}", *p as i64),
+          _ => {
+            *next += 1;
+            format!("%v{}", *next - 1)
+          }
+        };
+        self.values.insert(info.defn().instr_id(), name);
+    }
+
+    fn ty(&self, ty: IrTypeId) -> &'static str {
+        match ty {
+          IrTypeId::Bool => "i1",
+          IrTypeId::Int32 => "i32",
+          IrTypeId::Int64 => "i64",
+          IrTypeId::PtrInt => self.ptr_type()
+        }
+    }
+
+    // The integer type of the target's pointers.
+    fn ptr_type(&self) -> &'static str {
+        match self.graph.target().ptr_bytes() {
+          4 => "i32",
+          _ => "i64"
+        }
+    }
+
+    // The operand text of a value.
+    fn value(&self, id: InstrId) -> &str {
+        match self.values.get(&id) {
+          Some(name) => name,
+          None => "undef"
+        }
+    }
+
+    fn print(mut self, name: &str) -> String {
+        let graph = self.graph;
+        let mut params: Vec<Option<IrTypeId>> = Vec::new();
+        let mut ret_type = None;
+        let mut exits = false;
+        let mut patches = false;
+        let mut callees = BTreeMap::new();
+        for block in graph.block_store().iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                match info.op() {
+                  Op::Param(op) => {
+                    let i = op.index() as usize;
+                    if params.len() <= i {
+                        params.resize(i + 1, None);
+                    }
+                    params[i] = Some(op.tyid());
+                  }
+                  Op::Ret(op) if ret_type.is_none() => {
+                    ret_type = Some(op.tyid());
+                  }
+                  op if has_exit(op) => exits = true,
+                  Op::Exit(_) => exits = true,
+                  Op::PatchPoint(_) => patches = true,
+                  Op::Call(op) => {
+                    let args = self.arg_types(&info);
+                    callees.entry(op.callee())
+                           .or_insert((op.tyid(), args));
+                  }
+                  _ => {}
+                }
+            }
+        }
+
+        // Param indices the graph never reads are given
+        // the widest type.
+        // The layout of the target: its byte order, and
+        // the size and alignment of pointers and 64-bit
+        // values in bits.
+        let target = graph.target();
+        let endian = match target.endian() {
+          Endian::Little => "e",
+          Endian::Big => "E"
+        };
+        let bits = |ty| 8 * target.align_of(ty);
+        writeln!(self.out,
+                 "target datalayout = \"{}-p:{}:{}-i64:{}\"\n",
+                 endian, 8 * target.ptr_bytes(),
+                 bits(IrTypeId::PtrInt), bits(IrTypeId::Int64))
+          .unwrap();
+
+        let ret = ret_type.map(|ty| self.ty(ty)).unwrap_or("void");
+        write!(self.out, "define {} {}(", ret, global_name(name))
+          .unwrap();
+        for (i, ty) in params.iter().enumerate() {
+            let ty = ty.unwrap_or(IrTypeId::Int64);
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            write!(self.out, "{} %p{}", self.ty(ty), i).unwrap();
+        }
+        self.out.push_str(") {\n");
+
+        for (pos, block) in graph.block_store().iter_rpo_blocks()
+                                 .enumerate()
+        {
+            if pos > 0 {
+                self.out.push('\n');
+            }
+            self.print_block(pos, block);
+        }
+        self.out.push_str("}\n");
+
+        if exits {
+            write!(self.out, "\ndeclare void {}() noreturn\n",
+                   SIDE_EXIT).unwrap();
+        }
+        if patches {
+            write!(self.out,
+                   "\ndeclare void {}(i64, i32, ptr, i32, ...)\n",
+                   PATCH_POINT).unwrap();
+        }
+        for (callee, (ret, args)) in callees {
+            write!(self.out, "\ndeclare {} {}.{}({})\n",
+                   self.ty(ret), CALLEE, callee, args.join(", "))
+              .unwrap();
+        }
+        if self.cold_weights.iter().any(|&w| w) {
+            self.out.push('\n');
+        }
+        let weights = [(2000, 1), (1, 2000)];
+        for (i, &(t, f)) in weights.iter().enumerate() {
+            if self.cold_weights[i] {
+                writeln!(self.out, "!{} = !{{!\"branch_weights\", \
+                                    i32 {}, i32 {}}}",
+                         i, t, f).unwrap();
+            }
+        }
+        self.out
+    }
+
+    fn print_block(&mut self, pos: usize, block: &'a Block) {
+        let graph = self.graph;
+        writeln!(self.out, "bb{}:", pos).unwrap();
+
+        let mut phi_index = 0;
+        let mut guards = 0;
+        let mut accesses = 0;
+        for info in graph.block_instrs(block) {
+            let op = info.op();
+            match op {
+              Op::Phi(phi) => {
+                self.print_phi(pos, phi_index, &info, phi.tyid());
+                phi_index += 1;
+              }
+              Op::Nop(_) | Op::Param(_) | Op::Const(_)
+                | Op::Copy(_) => {}
+              Op::PatchPoint(patch) => {
+                writeln!(self.out,
+                         "  call void (i64, i32, ptr, i32, ...) {}(\
+                          i64 {}, i32 {}, ptr null, i32 0)",
+                         PATCH_POINT, patch.id(), patch.size())
+                  .unwrap();
+              }
+              Op::Cmp(cmp) => {
+                self.print_binary(&info, "icmp ", icmp_name(cmp.kind()),
+                                  cmp.tyid());
+              }
+              Op::Bini(bini) => {
+                self.print_binary(&info, "", bini_name(bini.kind()),
+                                  bini.tyid());
+              }
+              Op::Guard(guard) => {
+                guards += 1;
+                let cond = self.input(&info, 0);
+                self.print_guard(pos, guards, &cond, guard.expected());
+              }
+              Op::BoundsCheck(check) if !check.elided() => {
+                guards += 1;
+                let cond = format!("%bb{}.c{}", pos, guards);
+                let (index, len) = (self.input(&info, 0),
+                                    self.input(&info, 1));
+                writeln!(self.out, "  {} = icmp ult {} {}, {}",
+                         cond, self.ty(check.tyid()), index, len)
+                  .unwrap();
+                self.print_guard(pos, guards, &cond, true);
+              }
+              Op::NullCheck(check) if !check.elided() => {
+                guards += 1;
+                let cond = format!("%bb{}.c{}", pos, guards);
+                writeln!(self.out, "  {} = icmp ne {} {}, 0",
+                         cond, self.ptr_type(), self.input(&info, 0))
+                  .unwrap();
+                self.print_guard(pos, guards, &cond, true);
+              }
+              Op::BoundsCheck(_) | Op::NullCheck(_) => {}
+              Op::Load(load) => {
+                accesses += 1;
+                let addr = self.print_address(pos, accesses, &info);
+                let def = self.value(info.defn().instr_id())
+                              .to_string();
+                writeln!(self.out, "  {} = load {}, ptr {}",
+                         def, self.ty(load.tyid()), addr).unwrap();
+              }
+              Op::Store(store) => {
+                accesses += 1;
+                let addr = self.print_address(pos, accesses, &info);
+                writeln!(self.out, "  store {} {}, ptr {}",
+                         self.ty(store.tyid()), self.input(&info, 1),
+                         addr).unwrap();
+              }
+              Op::PtrAdd(add) => {
+                let def = self.value(info.defn().instr_id())
+                              .to_string();
+                let ptr = self.ptr_type();
+                let mut index = self.input(&info, 1);
+                if ptr != "i64" {
+                    writeln!(self.out, "  {}.i = trunc i64 {} to {}",
+                             def, index, ptr).unwrap();
+                    index = format!("{}.i", def);
+                }
+                writeln!(self.out, "  {}.s = mul {} {}, {}",
+                         def, ptr, index, add.stride()).unwrap();
+                writeln!(self.out, "  {}.p = add {} {}, {}.s",
+                         def, ptr, self.input(&info, 0), def).unwrap();
+                writeln!(self.out, "  {} = add {} {}.p, {}",
+                         def, ptr, def, add.offset()).unwrap();
+              }
+              Op::Call(call) => {
+                let def = self.value(info.defn().instr_id())
+                              .to_string();
+                let args: Vec<String> = self.arg_types(&info).iter()
+                  .zip(info.inputs_iter())
+                  .map(|(ty, a)| {
+                      format!("{} {}", ty, self.value(a.instr_id()))
+                  })
+                  .collect();
+                writeln!(self.out, "  {} = call {} {}.{}({})",
+                         def, self.ty(call.tyid()), CALLEE,
+                         call.callee(), args.join(", ")).unwrap();
+              }
+              Op::Ret(ret) => {
+                writeln!(self.out, "  ret {} {}",
+                         self.ty(ret.tyid()),
+                         self.input(&info, 0)).unwrap();
+              }
+              Op::Jump(_) => {
+                let target = info.targets_iter().next().unwrap();
+                let to = self.pos(target.block_id().as_u32());
+                writeln!(self.out, "  br label %bb{}", to).unwrap();
+              }
+              Op::Branch(_) => self.print_branch(pos, &info),
+              Op::Exit(_) => self.print_side_exit()
+            }
+        }
+    }
+
+    // The types of the operands of an instruction.
+    fn arg_types(&self, info: &InstrInfo) -> Vec<&'static str> {
+        info.inputs_iter().map(|a| {
+            let info = self.graph.instr(a.instr_id());
+            self.ty(info.op().out_type().unwrap())
+        }).collect()
+    }
+
+    fn input(&self, info: &InstrInfo, i: usize) -> String {
+        let id = info.inputs_iter().nth(i).unwrap().instr_id();
+        self.value(id).to_string()
+    }
+
+    // Turn the pointer operand of the block's `k`th
+    // memory access into a `ptr`, giving its name.
+    fn print_address(&mut self, pos: usize, k: u32,
+                     info: &InstrInfo)
+      -> String
+    {
+        let addr = format!("%bb{}.a{}", pos, k);
+        writeln!(self.out, "  {} = inttoptr {} {} to ptr",
+                 addr, self.ptr_type(), self.input(info, 0)).unwrap();
+        addr
+    }
+
+    fn print_phi(&mut self, pos: usize, index: usize,
+                 info: &InstrInfo, ty: IrTypeId)
+    {
+        let name = self.value(info.defn().instr_id()).to_string();
+        let ty = self.ty(ty);
+
+        // A block no edge reaches still needs its phis
+        // defined.
+        if self.incoming[pos].is_empty() {
+            writeln!(self.out, "  {} = freeze {} undef", name, ty)
+              .unwrap();
+            return;
+        }
+        let mut line = format!("  {} = phi {} ", name, ty);
+        let incoming = &self.incoming[pos];
+        for (i, (label, args)) in incoming.iter().enumerate() {
+            if i > 0 {
+                line.push_str(", ");
+            }
+            let arg = args.get(index).map(|&a| self.value(a))
+                          .unwrap_or("undef");
+            write!(line, "[ {}, %{} ]", arg, label).unwrap();
+        }
+        self.out.push_str(&line);
+        self.out.push('\n');
+    }
+
+    fn print_binary(&mut self, info: &InstrInfo, prefix: &str,
+                    name: &str, ty: IrTypeId)
+    {
+        let def = self.value(info.defn().instr_id()).to_string();
+        let (lhs, rhs) = (self.input(info, 0), self.input(info, 1));
+        writeln!(self.out, "  {} = {}{} {} {}, {}",
+                 def, prefix, name, self.ty(ty), lhs, rhs).unwrap();
+    }
+
+    fn print_branch(&mut self, pos: usize, info: &InstrInfo) {
+        let cond = self.input(info, 0);
+        let targets: Vec<usize> = info.targets_iter().map(|t| {
+            self.pos(t.block_id().as_u32())
+        }).collect();
+        if targets[0] != targets[1] {
+            write!(self.out, "  br i1 {}, label %bb{}, label %bb{}",
+                   cond, targets[0], targets[1]).unwrap();
+            let graph = self.graph;
+            let cold: Vec<bool> = info.targets_iter()
+              .map(|t| graph.block(t.block_id()).is_cold())
+              .collect();
+            if cold[0] != cold[1] {
+                let weights = cold[0] as usize;
+                self.cold_weights[weights] = true;
+                write!(self.out, ", !prof !{}", weights).unwrap();
+            }
+            self.out.push('\n');
+            return;
+        }
+        writeln!(self.out,
+                 "  br i1 {}, label %bb{}.t, label %bb{}.f",
+                 cond, pos, pos).unwrap();
+        for edge in &["t", "f"] {
+            writeln!(self.out, "bb{}.{}:\n  br label %bb{}",
+                     pos, edge, targets[0]).unwrap();
+        }
+    }
+
+    // Split the block at its `k`th guard, leaving
+    // through a side exit unless `cond` is as expected.
+    fn print_guard(&mut self, pos: usize, k: u32, cond: &str,
+                   expected: bool)
+    {
+        let cont = format!("bb{}.{}", pos, k);
+        let exit = format!("bb{}.x{}", pos, k);
+        let (t, f) = if expected {
+            (&cont, &exit)
+        } else {
+            (&exit, &cont)
+        };
+        writeln!(self.out, "  br i1 {}, label %{}, label %{}",
+                 cond, t, f).unwrap();
+        writeln!(self.out, "{}:", exit).unwrap();
+        self.print_side_exit();
+        writeln!(self.out, "{}:", cont).unwrap();
+    }
+
+    fn print_side_exit(&mut self) {
+        writeln!(self.out, "  call void {}()\n  unreachable",
+                 SIDE_EXIT).unwrap();
+    }
+}
@@ -0,0 +1,69 @@
+
+/*
+ * The canonical text form of a graph.  Blocks are
+ * printed in specification order (RPO) and named by
+ * their position in it, and values are numbered in
+ * stream order, so printing a graph is independent of
+ * block declaration ids and instruction offsets:
+ *
+ *   block0:
+ *     %0 = const.i32 0
+ *     %1 = const.i32 10
+ *     jump block1(%0)
+ *   loop block1(%2: i32) {
+ *     %3 = lt.i32 %2, %1
+ *     branch %3, block2, block3
+ *     block2:
+ *       %4 = const.i32 1
+ *       %5 = add.i32 %2, %4
+ *       jump block1(%5)
+ *   }
+ *   block3:
+ *     ret.i32 %2
+ *
+ * Header phis are listed as block parameters.  The
+ * braces of a loop enclose the blocks built within
+ * its loop subgraph.  Cold blocks have their header
+ * prefixed with `cold`.
+ */
+
+mod printer;
+mod parser;
+mod json;
+pub(crate) use self::json::json_string;
+mod llvm;
+mod rebuild;
+pub use self::parser::ParseError;
+pub(crate) use self::printer::{ mnemonic, instr_lines };
+pub(crate) use self::rebuild::{ Rebuild, each_block };
+pub(crate) use self::parser::{ ParsedBlock, ParsedInstr, BlockKind };
+#[cfg(test)]
+pub(crate) use self::parser::parse_with;
+
+use crate::ops::{ CmpKind, BiniKind };
+use crate::ir_types::IrTypeId;
+
+pub(crate) fn type_name(ty: IrTypeId) -> &'static str {
+    match ty {
+      IrTypeId::Bool => "bool",
+      IrTypeId::Int32 => "i32",
+      IrTypeId::Int64 => "i64",
+      IrTypeId::PtrInt => "ptrint"
+    }
+}
+
+pub(crate) fn cmp_name(kind: CmpKind) -> &'static str {
+    match kind {
+      CmpKind::Lt => "lt", CmpKind::Gt => "gt",
+      CmpKind::Le => "le", CmpKind::Ge => "ge",
+      CmpKind::Eq => "eq", CmpKind::Ne => "ne"
+    }
+}
+
+pub(crate) fn bini_name(kind: BiniKind) -> &'static str {
+    match kind {
+      BiniKind::Add => "add", BiniKind::Sub => "sub",
+      BiniKind::Mul => "mul", BiniKind::And => "and",
+      BiniKind::Or => "or", BiniKind::Xor => "xor"
+    }
+}
@@ -0,0 +1,740 @@
+
+use std::collections::{ HashMap, HashSet };
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use crate::block::{ BlockId, BlockRef, BlockStorage };
+use crate::instr::{ InstrId, OperandEncoding };
+use crate::defn::Defn;
+use crate::graph::Graph;
+use crate::builder::{ Builder, BuildSession };
+use crate::ops::{
+    Operation, Op, CmpKind, BiniKind,
+    NopOp, PhiOp, ParamOp, ConstOp, CmpOp, BiniOp, PatchPointOp,
+    GuardOp, BoundsCheckOp, NullCheckOp, LoadOp, StoreOp, PtrAddOp,
+    CopyOp, CallOp, RetOp, BranchOp, JumpOp, ExitOp
+};
+use crate::ir_types::IrTypeId;
+use crate::snapshot::{ Snapshot, FrameSlot };
+use crate::text::{ type_name, cmp_name, bini_name };
+
+/** A problem with text IR input, and its line. */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct ParseError {
+    line: usize,
+    message: String
+}
+
+impl ParseError {
+    fn new<S: Into<String>>(line: usize, message: S) -> ParseError {
+        ParseError { line, message: message.into() }
+    }
+
+    // The 1-based line of the problem.
+    pub fn line(&self) -> usize { self.line }
+    pub fn message(&self) -> &str { &self.message }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+type ParseResult<T> = Result<T, ParseError>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BlockKind { Plain, Loop, Start }
+
+pub(crate) struct ParsedInstr {
+    pub(crate) line: usize,
+    pub(crate) def: Option<String>,
+    pub(crate) op: Op,
+    pub(crate) operands: Vec<String>,
+    pub(crate) targets: Vec<(String, Vec<String>)>,
+
+    // The snapshot of a guard or exit, with its values
+    // by name.  The text form has none; graphs rebuilt
+    // through it carry theirs over.
+    pub(crate) snapshot: Option<ParsedSnapshot>
+}
+
+impl ParsedBlock {
+    // Whether a block within the braces of this one
+    // has the name.
+    pub(crate) fn encloses(&self, name: &str) -> bool {
+        self.body.iter().any(|b| b.name == name || b.encloses(name))
+    }
+}
+
+impl ParsedInstr {
+    // An instruction made by a transform, with no
+    // snapshot, reported at the line of its block.
+    pub(crate) fn new(line: usize, def: Option<String>, op: Op,
+                      operands: Vec<String>,
+                      targets: Vec<(String, Vec<String>)>)
+      -> ParsedInstr
+    {
+        ParsedInstr { line, def, op, operands, targets, snapshot: None }
+    }
+}
+
+pub(crate) struct ParsedSnapshot {
+    pub(crate) pc: u32,
+    pub(crate) entries: Vec<(FrameSlot, String)>,
+    pub(crate) stack_depth: u32
+}
+
+pub(crate) struct ParsedBlock {
+    pub(crate) line: usize,
+    pub(crate) name: String,
+    pub(crate) kind: BlockKind,
+    pub(crate) cold: bool,
+    pub(crate) params: Vec<(String, IrTypeId)>,
+    pub(crate) instrs: Vec<ParsedInstr>,
+
+    // The blocks within a loop's braces.
+    pub(crate) body: Vec<ParsedBlock>
+}
+
+impl Graph {
+    /**
+     * Parse a graph from the canonical text format, as
+     * printed by `print_ir`, by driving a builder.
+     *
+     * The first block is the start block.  Blocks and
+     * values may have any names, but values must be
+     * defined before they are used, and a block within
+     * a loop's braces may only be targeted from that
+     * loop.  The text does not record a target, so the
+     * graph is built for `TargetDesc::LP64`.  The
+     * parsed graph is not verified.
+     */
+    pub fn parse_ir(text: &str) -> Result<Graph, ParseError> {
+        let blocks = parse_blocks(text) ?;
+        check_blocks(&blocks) ?;
+        Ok(build_blocks(&blocks, OperandEncoding::Absolute,
+                        BlockStorage::Contiguous))
+    }
+}
+
+// Parse a graph with the given operand encoding and
+// block storage, for tests of transforms that must keep
+// them.
+#[cfg(test)]
+pub(crate) fn parse_with(text: &str, encoding: OperandEncoding,
+                         storage: BlockStorage)
+  -> Graph
+{
+    let blocks = parse_blocks(text).unwrap();
+    check_blocks(&blocks).unwrap();
+    build_blocks(&blocks, encoding, storage)
+}
+
+// Drive a builder over checked blocks, writing
+// operands in the given encoding and holding blocks in
+// the given storage.
+pub(crate) fn build_blocks(blocks: &[ParsedBlock],
+                           encoding: OperandEncoding,
+                           storage: BlockStorage)
+  -> Graph
+{
+    let mut state = BuildState {
+        blocks: HashMap::new(),
+        values: HashMap::new()
+    };
+    let builder = Builder::build_with(encoding, storage, |bs| {
+        state.build_level(bs, blocks, true);
+    });
+    builder.into_graph()
+}
+
+//
+// Parsing lines into blocks.
+//
+
+pub(crate) fn parse_blocks(text: &str)
+  -> ParseResult<Vec<ParsedBlock>>
+{
+    // Each frame holds the loop header whose braces are
+    // open (none for the top level) and the blocks
+    // parsed within them so far.
+    let mut frames: Vec<(Option<ParsedBlock>, Vec<ParsedBlock>)> =
+      vec![(None, Vec::new())];
+    let mut in_block = false;
+
+    for (i, raw) in text.lines().enumerate() {
+        let line = i + 1;
+        let src = match raw.find(';') {
+          Some(c) => &raw[.. c],
+          None => raw
+        }.trim();
+        if src.is_empty() {
+            continue;
+        }
+
+        if src == "}" {
+            if frames.len() == 1 {
+                return Err(ParseError::new(line, "unmatched '}'"));
+            }
+            let (header, body) = frames.pop().unwrap();
+            let mut header = header.unwrap();
+            header.body = body;
+            frames.last_mut().unwrap().1.push(header);
+            in_block = false;
+            continue;
+        }
+
+        if src.ends_with(':') || src.ends_with('{') {
+            let block = parse_header(line, src) ?;
+            if block.kind == BlockKind::Loop {
+                frames.push((Some(block), Vec::new()));
+            } else {
+                frames.last_mut().unwrap().1.push(block);
+            }
+            in_block = true;
+            continue;
+        }
+
+        if !in_block {
+            return Err(ParseError::new(line,
+              "instruction outside of a block"));
+        }
+        let instr = parse_instr(line, src) ?;
+        let frame = frames.last_mut().unwrap();
+        let block = match frame.1.last_mut() {
+          Some(block) => block,
+          None => frame.0.as_mut().unwrap()
+        };
+        block.instrs.push(instr);
+    }
+
+    if frames.len() > 1 {
+        let header = frames.pop().unwrap().0.unwrap();
+        return Err(ParseError::new(header.line,
+          format!("loop {} is not closed", header.name)));
+    }
+    Ok(frames.pop().unwrap().1)
+}
+
+fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+      Some(c) if c.is_ascii_alphanumeric() || c == '_' => {}
+      _ => return false
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_value_name(line: usize, s: &str) -> ParseResult<String> {
+    let s = s.trim();
+    match s.strip_prefix('%') {
+      Some(name) if is_name(name) => Ok(name.to_string()),
+      _ => Err(ParseError::new(line,
+             format!("expected a value, found '{}'", s)))
+    }
+}
+
+fn parse_type(line: usize, s: &str) -> ParseResult<IrTypeId> {
+    let s = s.trim();
+    (0 ..= u8::MAX).filter_map(|c| IrTypeId::try_from(c).ok())
+      .find(|&ty| type_name(ty) == s)
+      .ok_or_else(|| ParseError::new(line,
+                       format!("unknown type '{}'", s)))
+}
+
+// Split a list at commas outside of parentheses.
+fn split_list(s: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+          '(' => depth += 1,
+          ')' => depth -= 1,
+          ',' if depth == 0 => {
+            items.push(s[start .. i].trim());
+            start = i + 1;
+          }
+          _ => {}
+        }
+    }
+    let last = s[start ..].trim();
+    if !last.is_empty() || !items.is_empty() {
+        items.push(last);
+    }
+    items
+}
+
+// Split `name(list)` into the name and the list items.
+fn split_call(line: usize, s: &str)
+  -> ParseResult<(&str, Vec<&str>)>
+{
+    let s = s.trim();
+    let open = match s.find('(') {
+      Some(open) => open,
+      None => return Ok((s, Vec::new()))
+    };
+    if !s.ends_with(')') {
+        return Err(ParseError::new(line,
+          format!("expected ')' in '{}'", s)));
+    }
+    Ok((s[.. open].trim(), split_list(&s[open + 1 .. s.len() - 1])))
+}
+
+fn parse_header(line: usize, src: &str)
+  -> ParseResult<ParsedBlock>
+{
+    let opens = src.ends_with('{');
+    let rest = src[.. src.len() - 1].trim();
+    let (cold, rest) = match rest.strip_prefix("cold ") {
+      Some(r) => (true, r),
+      None => (false, rest)
+    };
+    let (kind, rest) = if let Some(r) = rest.strip_prefix("loop ") {
+        (BlockKind::Loop, r)
+    } else if let Some(r) = rest.strip_prefix("start ") {
+        (BlockKind::Start, r)
+    } else {
+        (BlockKind::Plain, rest)
+    };
+    if opens != (kind == BlockKind::Loop) {
+        return Err(ParseError::new(line, if opens {
+            "only loop headers open braces"
+        } else {
+            "loop headers must open braces"
+        }));
+    }
+
+    let (name, items) = split_call(line, rest) ?;
+    if !is_name(name) {
+        return Err(ParseError::new(line,
+          format!("bad block name '{}'", name)));
+    }
+    if kind == BlockKind::Start && !items.is_empty() {
+        return Err(ParseError::new(line,
+          "start blocks take no phis"));
+    }
+
+    let mut params = Vec::new();
+    for item in items {
+        let colon = item.find(':').ok_or_else(|| {
+            ParseError::new(line, format!("expected '%name: type', \
+                                           found '{}'", item))
+        }) ?;
+        let value = parse_value_name(line, &item[.. colon]) ?;
+        let ty = parse_type(line, &item[colon + 1 ..]) ?;
+        params.push((value, ty));
+    }
+
+    Ok(ParsedBlock {
+        line, name: name.to_string(), kind, cold, params,
+        instrs: Vec::new(), body: Vec::new()
+    })
+}
+
+fn parse_instr(line: usize, src: &str) -> ParseResult<ParsedInstr> {
+    let (def, rest) = match src.find('=') {
+      Some(eq) => {
+        let def = parse_value_name(line, &src[.. eq]) ?;
+        (Some(def), src[eq + 1 ..].trim())
+      }
+      None => (None, src)
+    };
+    let (mnemonic, args) = match rest.find(char::is_whitespace) {
+      Some(sp) => (&rest[.. sp], rest[sp ..].trim()),
+      None => (rest, "")
+    };
+    let args = split_list(args);
+
+    let bad_arg = |arg: &str| {
+        ParseError::new(line, format!("bad argument '{}' to {}",
+                                      arg, mnemonic))
+    };
+    let mut parts = mnemonic.splitn(2, '.');
+    let head = parts.next().unwrap();
+    let suffix = parts.next();
+    let ty = || parse_type(line, suffix.unwrap_or(""));
+
+    // Ops that take a literal rather than operands
+    // consume it here.
+    let literal = || -> ParseResult<&str> {
+        match args.first() {
+          Some(a) => Ok(a),
+          None => Err(ParseError::new(line,
+                    format!("{} needs a value", mnemonic)))
+        }
+    };
+    let (op, num_literals) = match head {
+      "nop" => (NopOp::new().op(), 0),
+      "branch" => (BranchOp::new().op(), 0),
+      "jump" => (JumpOp::new().op(), 0),
+      "exit" => (ExitOp::new().op(), 0),
+      "phi" => {
+        return Err(ParseError::new(line,
+          "phis must be declared as block parameters"));
+      }
+      "param" => {
+        let lit = literal() ?;
+        let index = lit.parse::<u32>().map_err(|_| bad_arg(lit)) ?;
+        (ParamOp::new(index, ty() ?).op(), 1)
+      }
+      "const" => {
+        let lit = literal() ?;
+        let op = match ty() ? {
+          IrTypeId::Bool => ConstOp::new_bool(
+            lit.parse::<bool>().map_err(|_| bad_arg(lit)) ?),
+          IrTypeId::Int32 => ConstOp::new_int32(
+            lit.parse::<u32>().map_err(|_| bad_arg(lit)) ?),
+          IrTypeId::Int64 => ConstOp::new_int64(
+            lit.parse::<u64>().map_err(|_| bad_arg(lit)) ?),
+          IrTypeId::PtrInt => ConstOp::new_ptr_int(
+            lit.parse::<u64>().map_err(|_| bad_arg(lit)) ?)
+        };
+        (op.op(), 1)
+      }
+      "guard" => {
+        let expected = match suffix {
+          Some("true") => true,
+          Some("false") => false,
+          _ => return Err(ParseError::new(line,
+                 "expected guard.true or guard.false"))
+        };
+        (GuardOp::new(expected).op(), 0)
+      }
+      "ret" => (RetOp::new(ty() ?).op(), 0),
+      "patchpoint" => {
+        let num = |i: usize| -> ParseResult<u32> {
+            let lit = args.get(i).ok_or_else(|| {
+                ParseError::new(line,
+                                "patchpoint needs an id and a size")
+            }) ?;
+            lit.parse::<u32>().map_err(|_| bad_arg(lit))
+        };
+        (PatchPointOp::new(num(0) ?, num(1) ?).op(), 2)
+      }
+      "boundscheck" => {
+        let (ty, elided) = match suffix {
+          Some(s) => match s.strip_suffix(".elided") {
+            Some(ty) => (ty, true),
+            None => (s, false)
+          },
+          None => ("", false)
+        };
+        let ty = match parse_type(line, ty) ? {
+          IrTypeId::Bool => return Err(ParseError::new(line,
+            "bounds checks take integer types")),
+          ty => ty
+        };
+        (BoundsCheckOp::new(ty, elided).op(), 0)
+      }
+      "nullcheck" => {
+        let elided = match suffix {
+          None => false,
+          Some("elided") => true,
+          Some(_) => return Err(ParseError::new(line,
+                       "expected nullcheck or nullcheck.elided"))
+        };
+        (NullCheckOp::new(elided).op(), 0)
+      }
+      "load" => (LoadOp::new(ty() ?).op(), 0),
+      "store" => (StoreOp::new(ty() ?).op(), 0),
+      "copy" => (CopyOp::new(ty() ?).op(), 0),
+      "call" => {
+        let lit = literal() ?;
+        let callee = lit.parse::<u32>().map_err(|_| bad_arg(lit)) ?;
+        let nargs = (args.len() - 1) as u32;
+        (CallOp::new(ty() ?, callee, nargs).op(), 1)
+      }
+      "ptradd" => {
+        let num = |i: usize| -> ParseResult<u32> {
+            let lit = args.get(i).ok_or_else(|| {
+                ParseError::new(line, "ptradd needs a descriptor, \
+                                       member, offset and stride")
+            }) ?;
+            lit.parse::<u32>().map_err(|_| bad_arg(lit))
+        };
+        let op = PtrAddOp::new(num(0) ?, num(1) ?, num(2) ?, num(3) ?);
+        (op.op(), 4)
+      }
+      _ => {
+        let cmp = (0 ..= u8::MAX)
+          .filter_map(|c| CmpKind::try_from(c).ok())
+          .find(|&k| cmp_name(k) == head);
+        let bini = (0 ..= u8::MAX)
+          .filter_map(|c| BiniKind::try_from(c).ok())
+          .find(|&k| bini_name(k) == head);
+        match (cmp, bini) {
+          (Some(kind), _) => (CmpOp::new(kind, ty() ?).op(), 0),
+          (_, Some(kind)) => (BiniOp::new(kind, ty() ?).op(), 0),
+          _ => return Err(ParseError::new(line,
+                 format!("unknown instruction '{}'", mnemonic)))
+        }
+      }
+    };
+
+    if op.defines_value() != def.is_some() {
+        return Err(ParseError::new(line, if def.is_some() {
+            format!("{} defines no value", mnemonic)
+        } else {
+            format!("{} must define a value", mnemonic)
+        }));
+    }
+
+    let args = &args[num_literals ..];
+    let num_inputs = op.num_inputs() as usize;
+    let num_targets = op.num_targets().unwrap_or(0) as usize;
+    if args.len() != num_inputs + num_targets {
+        return Err(ParseError::new(line,
+          format!("{} takes {} arguments, found {}",
+                  mnemonic, num_inputs + num_targets, args.len())));
+    }
+
+    let mut operands = Vec::new();
+    for arg in &args[.. num_inputs] {
+        operands.push(parse_value_name(line, arg) ?);
+    }
+    let mut targets = Vec::new();
+    for arg in &args[num_inputs ..] {
+        let (name, items) = split_call(line, arg) ?;
+        if !is_name(name) {
+            return Err(bad_arg(arg));
+        }
+        let mut phi_args = Vec::new();
+        for item in items {
+            phi_args.push(parse_value_name(line, item) ?);
+        }
+        targets.push((name.to_string(), phi_args));
+    }
+
+    Ok(ParsedInstr { line, def, op, operands, targets, snapshot: None })
+}
+
+//
+// Checking what the builder requires before driving it.
+//
+
+struct Checker {
+    // The position in emission order from which each
+    // block is declared, and may be targeted.
+    blocks: HashMap<String, usize>,
+    values: HashSet<String>,
+    pos: usize
+}
+
+pub(crate) fn check_blocks(blocks: &[ParsedBlock])
+  -> ParseResult<()>
+{
+    let start = match blocks.first() {
+      Some(start) => start,
+      None => return Err(ParseError::new(1, "no blocks"))
+    };
+    if start.kind != BlockKind::Plain || !start.params.is_empty() {
+        return Err(ParseError::new(start.line,
+          "the first block must be a plain block without phis"));
+    }
+
+    let mut checker = Checker {
+        blocks: HashMap::new(), values: HashSet::new(), pos: 0
+    };
+    checker.declare(blocks, 0) ?;
+    checker.check_level(blocks)
+}
+
+impl Checker {
+    fn declare(&mut self, level: &[ParsedBlock], from: usize)
+      -> ParseResult<()>
+    {
+        for block in level {
+            if self.blocks.insert(block.name.clone(), from).is_some() {
+                return Err(ParseError::new(block.line,
+                  format!("block {} defined twice", block.name)));
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, line: usize, name: &str) -> ParseResult<()> {
+        if !self.values.insert(name.to_string()) {
+            return Err(ParseError::new(line,
+              format!("value %{} defined twice", name)));
+        }
+        Ok(())
+    }
+
+    fn use_value(&self, line: usize, name: &str) -> ParseResult<()> {
+        if !self.values.contains(name) {
+            return Err(ParseError::new(line,
+              format!("value %{} used before definition", name)));
+        }
+        Ok(())
+    }
+
+    fn check_level(&mut self, level: &[ParsedBlock])
+      -> ParseResult<()>
+    {
+        for block in level {
+            let pos = self.pos;
+            self.pos += 1;
+            if block.kind == BlockKind::Loop {
+                self.declare(&block.body, pos) ?;
+            }
+            self.check_block(block) ?;
+            self.check_level(&block.body) ?;
+        }
+        Ok(())
+    }
+
+    fn check_block(&mut self, block: &ParsedBlock) -> ParseResult<()> {
+        for (name, _) in &block.params {
+            self.define(block.line, name) ?;
+        }
+
+        let last = block.instrs.len().checked_sub(1).ok_or_else(|| {
+            ParseError::new(block.line,
+              format!("block {} is empty", block.name))
+        }) ?;
+        for (i, instr) in block.instrs.iter().enumerate() {
+            if instr.op.terminal() != (i == last) {
+                return Err(ParseError::new(instr.line, if i == last {
+                    format!("block {} does not end in an end \
+                             instruction", block.name)
+                } else {
+                    "end instruction before end of block".to_string()
+                }));
+            }
+            for operand in &instr.operands {
+                self.use_value(instr.line, operand) ?;
+            }
+            for (target, args) in &instr.targets {
+                match self.blocks.get(target) {
+                  Some(&from) if from < self.pos => {}
+                  Some(_) => return Err(ParseError::new(instr.line,
+                    format!("block {} targeted from outside its loop",
+                            target))),
+                  None => return Err(ParseError::new(instr.line,
+                    format!("no block {}", target)))
+                }
+                for arg in args {
+                    self.use_value(instr.line, arg) ?;
+                }
+            }
+            if let Some(ref def) = instr.def {
+                self.define(instr.line, def) ?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//
+// Driving the builder.
+//
+
+struct BuildState {
+    blocks: HashMap<String, BlockId>,
+    values: HashMap<String, InstrId>
+}
+
+impl BuildState {
+    fn block<'x>(&self, name: &str) -> BlockRef<'x> {
+        BlockRef::new(self.blocks[name])
+    }
+    fn value<'x>(&self, name: &str) -> Defn<'x> {
+        Defn::new(self.values[name])
+    }
+
+    fn declare<'x>(&mut self, bs: &mut BuildSession<'x>,
+                   level: &[ParsedBlock])
+    {
+        for block in level {
+            let num_phis = block.params.len() as u32;
+            let b = match block.kind {
+              BlockKind::Plain => bs.decl_plain_block(num_phis),
+              BlockKind::Loop => bs.decl_loop_head(num_phis),
+              BlockKind::Start => bs.decl_start_block()
+            };
+            if block.cold {
+                bs.mark_cold(b);
+            }
+            self.blocks.insert(block.name.clone(), b.id());
+        }
+    }
+
+    // Build the blocks of a level.  At the top level,
+    // the first block is the start block the builder
+    // has already entered.
+    fn build_level<'x>(&mut self, bs: &mut BuildSession<'x>,
+                       level: &[ParsedBlock], top: bool)
+    {
+        if top {
+            let start = bs.cur_block_id();
+            if level[0].cold {
+                bs.mark_cold(BlockRef::new(start));
+            }
+            self.blocks.insert(level[0].name.clone(), start);
+            self.declare(bs, &level[1 ..]);
+        }
+        for (i, block) in level.iter().enumerate() {
+            if top && i == 0 {
+                self.build_block(bs, block);
+                continue;
+            }
+            let b = self.block(&block.name);
+            if block.kind == BlockKind::Loop {
+                bs.def_loop(b, |cs| {
+                    self.declare(cs, &block.body);
+                    self.build_block(cs, block);
+                    self.build_level(cs, &block.body, false);
+                });
+            } else {
+                bs.def_block(b);
+                self.build_block(bs, block);
+            }
+        }
+    }
+
+    fn build_block<'x>(&mut self, bs: &mut BuildSession<'x>,
+                       block: &ParsedBlock)
+    {
+        for (name, ty) in &block.params {
+            let phi = bs.emit_op(&PhiOp::new(*ty).op(), &[]);
+            self.values.insert(name.clone(), phi.instr_id());
+        }
+
+        for instr in &block.instrs {
+            let operands: Vec<Defn> =
+              instr.operands.iter().map(|v| self.value(v)).collect();
+            let id = if !instr.op.terminal() {
+                let def = bs.emit_op(&instr.op, &operands);
+                if let Some(ref name) = instr.def {
+                    self.values.insert(name.clone(), def.instr_id());
+                }
+                def.instr_id()
+            } else {
+                let args: Vec<Vec<Defn>> =
+                  instr.targets.iter().map(|(_, a)| {
+                      a.iter().map(|v| self.value(v)).collect()
+                  }).collect();
+                let targets: Vec<(BlockRef, &[Defn])> =
+                  instr.targets.iter().zip(&args).map(|((t, _), a)| {
+                      (self.block(t), &a[..])
+                  }).collect();
+                bs.emit_op_end(&instr.op, &operands, &targets)
+            };
+            if let Some(ref snapshot) = instr.snapshot {
+                let entries = snapshot.entries.iter().map(|(slot, v)| {
+                    (*slot, self.values[v])
+                }).collect();
+                bs.attach_snapshot(id, Snapshot::new(
+                  snapshot.pc, entries, snapshot.stack_depth));
+            }
+        }
+    }
+}
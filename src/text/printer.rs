@@ -0,0 +1,412 @@
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::block::{ Block, BlockId };
+use crate::instr::{ InstrId, InstrInfo, InstrInputs };
+use crate::graph::Graph;
+use crate::ops::{ Op, ConstOp };
+use crate::text::{ type_name, cmp_name, bini_name };
+use crate::structured::{ Structured, StructuredCfg, Edge };
+
+struct Printer<'a> {
+    graph: &'a Graph,
+
+    // The RPO position of each block, by block id.
+    rpo_pos: Vec<u32>,
+
+    // The number of each value-defining instruction.
+    values: HashMap<InstrId, u32>,
+
+    // Whether to name values and blocks as `dump_stable`
+    // does, rather than as `print_ir` does.
+    stable: bool,
+
+    out: String
+}
+
+impl Graph {
+    /**
+     * Print the graph in the canonical text format.
+     * The graph should pass `verify`; blocks whose phis
+     * do not lead are still printed, but will not parse
+     * back.
+     */
+    pub fn print_ir(&self) -> String {
+        Printer::new(self).print()
+    }
+
+    /**
+     * Print structured control flow from `structurize`
+     * as pseudo-code, naming blocks and values as
+     * `print_ir` does.  Each edge is printed with its
+     * phi arguments, as `fall` into the code that
+     * follows or as a `br` out to an enclosing node.
+     */
+    pub fn print_structured(&self, structured: &StructuredCfg)
+      -> String
+    {
+        let mut printer = Printer::new(self);
+        printer.print_nodes(structured.body(), 0);
+        printer.out
+    }
+
+    /**
+     * Dump the graph for snapshot tests, as `print_ir`
+     * prints it but with values named `v0`, `v1`, ...
+     * and blocks `b0`, `b1`, ..., and with the deopt
+     * snapshot of each guard and exit below it.  Values
+     * are numbered in order of definition and blocks in
+     * specification order, so the dump stays the same
+     * whatever the operand encoding, block ids or
+     * instruction offsets.
+     */
+    pub fn dump_stable(&self) -> String {
+        let mut printer = Printer::new(self);
+        printer.stable = true;
+        printer.print()
+    }
+}
+
+impl<'a> Printer<'a> {
+    fn new(graph: &'a Graph) -> Printer<'a> {
+        let store = graph.block_store();
+        let mut rpo_pos = vec![u32::MAX; store.total_blocks()];
+        let mut values = HashMap::new();
+        for (pos, block) in store.iter_rpo_blocks().enumerate() {
+            rpo_pos[block.id().as_u32() as usize] = pos as u32;
+            for info in graph.block_instrs(block) {
+                if info.op().defines_value() {
+                    let n = values.len() as u32;
+                    values.insert(info.defn().instr_id(), n);
+                }
+            }
+        }
+        Printer {
+            graph, rpo_pos, values, stable: false, out: String::new()
+        }
+    }
+
+    fn print(mut self) -> String {
+        let graph = self.graph;
+        let loop_ends = self.loop_ends();
+
+        // The RPO positions at which open loops end.
+        let mut open: Vec<u32> = Vec::new();
+        for (pos, block) in graph.block_store().iter_rpo_blocks()
+                                 .enumerate()
+        {
+            let pos = pos as u32;
+            let depth = open.len();
+            self.print_block(block, depth);
+            if block.is_loop() {
+                open.push(loop_ends[pos as usize]);
+            }
+            while open.last() == Some(&pos) {
+                open.pop();
+                self.indent(open.len());
+                self.out.push_str("}\n");
+            }
+        }
+        self.out
+    }
+
+    // The RPO position of the last block within each
+    // loop's braces, by header position: the last block
+    // of its natural loop, extended to enclose any loop
+    // whose header falls inside.
+    fn loop_ends(&self) -> Vec<u32> {
+        let graph = self.graph;
+        let nblocks = graph.block_store().total_blocks();
+        let mut ends: Vec<u32> = (0 .. nblocks as u32).collect();
+        for lp in graph.loops().loops() {
+            let h = self.pos(lp.header()) as usize;
+            for &b in lp.blocks() {
+                ends[h] = ends[h].max(self.pos(b));
+            }
+        }
+
+        let is_loop: Vec<bool> = graph.block_store().iter_rpo_blocks()
+                                      .map(|b| b.is_loop()).collect();
+        for h in (0 .. nblocks).rev() {
+            if !is_loop[h] {
+                continue;
+            }
+            let mut p = h + 1;
+            while p <= ends[h] as usize {
+                if is_loop[p] {
+                    ends[h] = ends[h].max(ends[p]);
+                }
+                p += 1;
+            }
+        }
+        ends
+    }
+
+    fn pos(&self, block: BlockId) -> u32 {
+        self.rpo_pos[block.as_u32() as usize]
+    }
+
+    fn indent(&mut self, depth: usize) {
+        for _ in 0 .. depth {
+            self.out.push_str("  ");
+        }
+    }
+
+    fn value(&mut self, id: InstrId) {
+        match (self.values.get(&id), self.stable) {
+          (Some(n), false) => write!(self.out, "%{}", n).unwrap(),
+          (Some(n), true) => write!(self.out, "v{}", n).unwrap(),
+          (None, false) => write!(self.out, "%?{}", id.offset()).unwrap(),
+          (None, true) => self.out.push_str("v?")
+        }
+    }
+
+    fn block_name(&mut self, id: BlockId) {
+        let prefix = if self.stable { "b" } else { "block" };
+        write!(self.out, "{}{}", prefix, self.pos(id)).unwrap();
+    }
+
+    // The deopt snapshot of an instruction, if it has
+    // one.
+    fn print_snapshot(&mut self, id: InstrId, depth: usize) {
+        let snapshot = match self.graph.snapshot(id) {
+          Some(snapshot) => snapshot,
+          None => return
+        };
+        self.indent(depth);
+        write!(self.out, "snapshot @{} [", snapshot.pc()).unwrap();
+        for (i, &(slot, value)) in snapshot.entries().iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            write!(self.out, "{} = ", slot).unwrap();
+            self.value(value);
+        }
+        writeln!(self.out, "] stack {}", snapshot.stack_depth()).unwrap();
+    }
+
+    fn values(&mut self, inputs: InstrInputs) {
+        for (i, d) in inputs.enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.value(d.instr_id());
+        }
+    }
+
+    // A block's name, with its phis as parameters.
+    fn print_block_label(&mut self, block: &Block) {
+        let graph = self.graph;
+        let mut instrs = graph.block_instrs(block).peekable();
+        self.block_name(block.id());
+
+        let mut phis = 0;
+        while let Some(Op::Phi(phi)) = instrs.peek().map(|i| i.op()) {
+            let ty = phi.tyid();
+            let id = instrs.next().unwrap().defn().instr_id();
+            self.out.push_str(if phis == 0 { "(" } else { ", " });
+            self.value(id);
+            write!(self.out, ": {}", type_name(ty)).unwrap();
+            phis += 1;
+        }
+        if phis > 0 {
+            self.out.push(')');
+        }
+    }
+
+    fn print_block(&mut self, block: &Block, depth: usize) {
+        let graph = self.graph;
+
+        self.indent(depth);
+        if block.is_cold() {
+            self.out.push_str("cold ");
+        }
+        if block.is_loop() {
+            self.out.push_str("loop ");
+        } else if block.is_start() && self.pos(block.id()) > 0 {
+            self.out.push_str("start ");
+        }
+        self.print_block_label(block);
+        self.out.push_str(if block.is_loop() { " {\n" } else { ":\n" });
+
+        let instrs = graph.block_instrs(block)
+                          .skip_while(|i| matches!(i.op(), Op::Phi(_)));
+        for info in instrs {
+            self.indent(depth + 1);
+            self.print_instr(&info);
+            self.out.push('\n');
+            if self.stable {
+                self.print_snapshot(info.defn().instr_id(), depth + 2);
+            }
+        }
+    }
+
+    fn print_nodes(&mut self, nodes: &[Structured], depth: usize) {
+        let graph = self.graph;
+        for node in nodes {
+            match node {
+              Structured::Code(b) => {
+                let block = graph.block(*b);
+                self.indent(depth);
+                self.print_block_label(block);
+                self.out.push_str(":\n");
+                for info in graph.block_instrs(block) {
+                    if info.op().terminal() {
+                        break;
+                    }
+                    if let Op::Phi(_) = info.op() {
+                        continue;
+                    }
+                    self.indent(depth + 1);
+                    self.print_instr(&info);
+                    self.out.push('\n');
+                }
+              }
+              Structured::Enter(edge) => {
+                self.indent(depth + 1);
+                self.out.push_str("fall ");
+                self.print_edge(edge);
+              }
+              Structured::Br { depth: d, edge } => {
+                self.indent(depth + 1);
+                write!(self.out, "br {} ", d).unwrap();
+                self.print_edge(edge);
+              }
+              Structured::Block(body) | Structured::Loop(body) => {
+                self.indent(depth);
+                let is_loop = matches!(node, Structured::Loop(_));
+                self.out.push_str(if is_loop { "loop {\n" }
+                                  else { "block {\n" });
+                self.print_nodes(body, depth + 1);
+                self.indent(depth);
+                self.out.push_str("}\n");
+              }
+              Structured::If { branch, then_body, else_body } => {
+                let cond = graph.instr(*branch).inputs_iter().next()
+                                .unwrap().instr_id();
+                self.indent(depth + 1);
+                self.out.push_str("if ");
+                self.value(cond);
+                self.out.push_str(" {\n");
+                self.print_nodes(then_body, depth + 2);
+                self.indent(depth + 1);
+                self.out.push_str("} else {\n");
+                self.print_nodes(else_body, depth + 2);
+                self.indent(depth + 1);
+                self.out.push_str("}\n");
+              }
+              Structured::Ret(id) | Structured::Exit(id) => {
+                self.indent(depth + 1);
+                self.print_instr(&graph.instr(*id));
+                self.out.push('\n');
+              }
+            }
+        }
+    }
+
+    // An edge's target and its phi arguments.
+    fn print_edge(&mut self, edge: &Edge) {
+        let graph = self.graph;
+        let end = graph.block_end(graph.block(edge.from()));
+        let target = end.targets_iter().nth(edge.index() as usize)
+                        .unwrap();
+        self.block_name(edge.to());
+        if target.phi_args().next().is_some() {
+            self.out.push('(');
+            self.values(target.phi_args());
+            self.out.push(')');
+        }
+        self.out.push('\n');
+    }
+
+    fn print_instr(&mut self, info: &InstrInfo) {
+        if info.op().defines_value() {
+            self.value(info.defn().instr_id());
+            self.out.push_str(" = ");
+        }
+        let mnemonic = mnemonic(info.op());
+        self.out.push_str(&mnemonic);
+
+        // Operands follow an op's literals in the same
+        // list.
+        let mut sep = if mnemonic.contains(' ') { ", " } else { " " };
+        if info.op().num_inputs() > 0 {
+            self.out.push_str(sep);
+            self.values(info.inputs_iter());
+            sep = ", ";
+        }
+        for target in info.targets_iter() {
+            self.out.push_str(sep);
+            self.block_name(target.block_id());
+            if target.phi_args().next().is_some() {
+                self.out.push('(');
+                self.values(target.phi_args());
+                self.out.push(')');
+            }
+            sep = ", ";
+        }
+    }
+}
+
+// The text of each instruction as `print_ir` prints
+// it, with phis printed as `%N = phi.T`.
+pub(crate) fn instr_lines(graph: &Graph) -> HashMap<InstrId, String> {
+    let mut printer = Printer::new(graph);
+    let mut lines = HashMap::new();
+    for block in graph.block_store().iter_rpo_blocks() {
+        for info in graph.block_instrs(block) {
+            printer.print_instr(&info);
+            let line = std::mem::take(&mut printer.out);
+            lines.insert(info.defn().instr_id(), line);
+        }
+    }
+    lines
+}
+
+// The mnemonic of an operation, with its immediates.
+pub(crate) fn mnemonic(op: &Op) -> String {
+    match op {
+      Op::Nop(_) => "nop".to_string(),
+      Op::Phi(op) => format!("phi.{}", type_name(op.tyid())),
+      Op::Param(op) => {
+        format!("param.{} {}", type_name(op.tyid()), op.index())
+      }
+      Op::Const(ConstOp::Bool(b)) => format!("const.bool {}", b),
+      Op::Const(ConstOp::Int32(i)) => format!("const.i32 {}", i),
+      Op::Const(ConstOp::Int64(i)) => format!("const.i64 {}", i),
+      Op::Const(ConstOp::PtrInt(p)) => format!("const.ptrint {}", p),
+      Op::Cmp(op) => {
+        format!("{}.{}", cmp_name(op.kind()), type_name(op.tyid()))
+      }
+      Op::Bini(op) => {
+        format!("{}.{}", bini_name(op.kind()), type_name(op.tyid()))
+      }
+      Op::Guard(op) => format!("guard.{}", op.expected()),
+      Op::Ret(op) => format!("ret.{}", type_name(op.tyid())),
+      Op::Branch(_) => "branch".to_string(),
+      Op::Jump(_) => "jump".to_string(),
+      Op::Exit(_) => "exit".to_string(),
+      Op::PatchPoint(op) => {
+        format!("patchpoint {}, {}", op.id(), op.size())
+      }
+      Op::BoundsCheck(op) => {
+        let elided = if op.elided() { ".elided" } else { "" };
+        format!("boundscheck.{}{}", type_name(op.tyid()), elided)
+      }
+      Op::NullCheck(op) => {
+        let elided = if op.elided() { ".elided" } else { "" };
+        format!("nullcheck{}", elided)
+      }
+      Op::Load(op) => format!("load.{}", type_name(op.tyid())),
+      Op::Store(op) => format!("store.{}", type_name(op.tyid())),
+      Op::PtrAdd(op) => {
+        format!("ptradd {}, {}, {}, {}", op.desc(), op.member(),
+                op.offset(), op.stride())
+      }
+      Op::Copy(op) => format!("copy.{}", type_name(op.tyid())),
+      Op::Call(op) => {
+        format!("call.{} {}", type_name(op.tyid()), op.callee())
+      }
+    }
+}
@@ -0,0 +1,550 @@
+
+use std::collections::{ HashMap, HashSet };
+use std::mem;
+
+use crate::block::{ BlockId, BlockStorage };
+use crate::instr::{ InstrId, OperandEncoding };
+use crate::graph::Graph;
+use crate::ops::{ Op, Operation, JumpOp };
+use crate::ir_types::IrTypeId;
+use crate::target::TargetDesc;
+use crate::text::parser::{
+    ParseError, ParsedBlock, ParsedInstr, ParsedSnapshot, BlockKind,
+    parse_blocks, check_blocks, build_blocks
+};
+
+/*
+ * Rebuilding a graph with instructions added and
+ * removed.  The graph's instruction stream only allows
+ * payloads to be rewritten in place, so a transform
+ * that needs more edits the parsed form of the graph's
+ * text instead, by value and block name, and builds a
+ * new graph from it.  Snapshots are carried over with
+ * their values renamed, and the new graph is built
+ * for the old graph's target, with its operand
+ * encoding and block storage.  The new graph is
+ * encoded by the builder afresh: its ids differ from
+ * the old graph's, and profiles, instruction indexes
+ * and the bytes of patched patch points are not
+ * carried over.
+ */
+
+pub(crate) struct Rebuild {
+    blocks: Vec<ParsedBlock>,
+
+    // The names of the old graph's values and blocks.
+    values: HashMap<InstrId, String>,
+    block_names: HashMap<BlockId, String>,
+
+    target: TargetDesc,
+    encoding: OperandEncoding,
+    storage: BlockStorage,
+    next_name: u32
+}
+
+// Call `f` on every block, in text order, which is
+// the order of the graph's RPO.
+pub(crate) fn each_block(level: &mut [ParsedBlock],
+                         f: &mut dyn FnMut(&mut ParsedBlock))
+{
+    for block in level {
+        f(block);
+        each_block(&mut block.body, f);
+    }
+}
+
+fn any_block(level: &[ParsedBlock], f: &dyn Fn(&ParsedBlock) -> bool)
+  -> bool
+{
+    level.iter().any(|b| f(b) || any_block(&b.body, f))
+}
+
+fn find_block<'a>(level: &'a mut [ParsedBlock], name: &str)
+  -> Option<&'a mut ParsedBlock>
+{
+    for block in level {
+        if block.name == name {
+            return Some(block);
+        }
+        if let Some(found) = find_block(&mut block.body, name) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+impl Rebuild {
+    pub(crate) fn new(graph: &Graph) -> Rebuild {
+        let mut blocks = parse_blocks(&graph.print_ir())
+          .expect("printed graph does not parse");
+
+        let mut values = HashMap::new();
+        let mut block_names = HashMap::new();
+        let mut rpo = graph.block_store().iter_rpo_blocks();
+        each_block(&mut blocks, &mut |parsed| {
+            let block = rpo.next().unwrap();
+            block_names.insert(block.id(), parsed.name.clone());
+            let mut instrs = graph.block_instrs(block);
+            for (name, _) in &parsed.params {
+                let phi = instrs.next().unwrap();
+                values.insert(phi.defn().instr_id(), name.clone());
+            }
+            for (info, instr) in instrs.zip(&mut parsed.instrs) {
+                let id = info.defn().instr_id();
+                if let Some(ref def) = instr.def {
+                    values.insert(id, def.clone());
+                }
+                instr.snapshot = graph.snapshot(id).map(|snapshot| {
+                    let entries = snapshot.entries().iter()
+                      .map(|&(slot, v)| (slot, values[&v].clone()))
+                      .collect();
+                    ParsedSnapshot {
+                        pc: snapshot.pc(),
+                        entries,
+                        stack_depth: snapshot.stack_depth()
+                    }
+                });
+            }
+        });
+        Rebuild {
+            blocks, values, block_names, target: graph.target(),
+            encoding: graph.operand_encoding(),
+            storage: graph.block_storage(),
+            next_name: 0
+        }
+    }
+
+    // Rebuild from parsed blocks taken from another
+    // rebuild, for a graph built as `like` was.  There
+    // is no old graph to name values and blocks of.
+    pub(crate) fn from_blocks(blocks: Vec<ParsedBlock>, like: &Graph)
+      -> Rebuild
+    {
+        Rebuild {
+            blocks, values: HashMap::new(),
+            block_names: HashMap::new(), target: like.target(),
+            encoding: like.operand_encoding(),
+            storage: like.block_storage(),
+            next_name: 0
+        }
+    }
+
+    // The name of a value of the old graph.
+    pub(crate) fn value(&self, id: InstrId) -> String {
+        self.values[&id].clone()
+    }
+
+    // The name of a block of the old graph.
+    pub(crate) fn block_name(&self, block: BlockId) -> String {
+        self.block_names[&block].clone()
+    }
+
+    // The parsed blocks, for edits beyond those below.
+    pub(crate) fn blocks(&self) -> &[ParsedBlock] {
+        &self.blocks
+    }
+    pub(crate) fn blocks_mut(&mut self) -> &mut Vec<ParsedBlock> {
+        &mut self.blocks
+    }
+
+    pub(crate) fn fresh_name(&mut self) -> String {
+        self.next_name += 1;
+        format!("r{}", self.next_name)
+    }
+
+    pub(crate) fn block_mut(&mut self, block: BlockId)
+      -> &mut ParsedBlock
+    {
+        let name = &self.block_names[&block];
+        find_block(&mut self.blocks, name).unwrap()
+    }
+
+    // A block by name, such as one added by a split.
+    pub(crate) fn named_block_mut(&mut self, name: &str)
+      -> &mut ParsedBlock
+    {
+        find_block(&mut self.blocks, name).expect("no such block")
+    }
+
+    // The position of a block in text order, which is
+    // its position in the RPO of the rebuilt graph.
+    pub(crate) fn position(&self, name: &str) -> usize {
+        fn find(level: &[ParsedBlock], name: &str, pos: &mut usize)
+          -> bool
+        {
+            for block in level {
+                if block.name == name {
+                    return true;
+                }
+                *pos += 1;
+                if find(&block.body, name, pos) {
+                    return true;
+                }
+            }
+            false
+        }
+        let mut pos = 0;
+        assert!(find(&self.blocks, name, &mut pos), "no block {}", name);
+        pos
+    }
+
+    // Place blocks after the named block: at the start
+    // of its loop if `inner` is set, or else just after
+    // it in the same loop.
+    pub(crate) fn place_after(&mut self, name: &str,
+                              blocks: Vec<ParsedBlock>, inner: bool)
+    {
+        fn place(level: &mut Vec<ParsedBlock>, name: &str,
+                 blocks: &mut Vec<ParsedBlock>, inner: bool)
+          -> bool
+        {
+            for i in 0 .. level.len() {
+                if level[i].name == name {
+                    let (at, pos) = if inner {
+                        (&mut level[i].body, 0)
+                    } else {
+                        (&mut *level, i + 1)
+                    };
+                    at.splice(pos .. pos, mem::take(blocks));
+                    return true;
+                }
+                if place(&mut level[i].body, name, blocks, inner) {
+                    return true;
+                }
+            }
+            false
+        }
+        let mut blocks = blocks;
+        assert!(place(&mut self.blocks, name, &mut blocks, inner),
+                "no block {}", name);
+    }
+
+    // Split a block before the instruction at `index`
+    // of those after its phis, moving that instruction
+    // and the rest into a new block that the first now
+    // jumps to, and give the new block's name.  The new
+    // block is placed at the start of the first's loop
+    // if it heads one, where the moved end may lead,
+    // or else just after it.
+    pub(crate) fn split_block(&mut self, block: BlockId, index: usize)
+      -> String
+    {
+        let name = self.fresh_name();
+        let parsed = self.block_mut(block);
+        let instrs = parsed.instrs.split_off(index);
+        let line = instrs[0].line;
+        parsed.instrs.push(ParsedInstr::new(
+          line, None, JumpOp::new().op(), Vec::new(),
+          vec![(name.clone(), Vec::new())]));
+        let split = ParsedBlock {
+            line,
+            name: name.clone(),
+            kind: BlockKind::Plain,
+            cold: parsed.cold,
+            params: Vec::new(),
+            instrs,
+            body: Vec::new()
+        };
+        let (from, inner) =
+          (parsed.name.clone(), parsed.kind == BlockKind::Loop);
+        self.place_after(&from, vec![split], inner);
+        name
+    }
+
+    // Split the edge at `index` of the end of a block
+    // by a new block holding only a jump to its target,
+    // which takes the edge's arguments, and give the new
+    // block's name.  The new block is placed within the
+    // block's loop if the target is, or else just after
+    // the block.
+    pub(crate) fn split_edge(&mut self, from: BlockId, index: usize)
+      -> String
+    {
+        let name = self.fresh_name();
+        let parsed = self.block_mut(from);
+        let end = parsed.instrs.last_mut().unwrap();
+        let line = end.line;
+        let edge = mem::replace(&mut end.targets[index],
+                                (name.clone(), Vec::new()));
+        let inner = parsed.encloses(&edge.0);
+        let split = ParsedBlock {
+            line,
+            name: name.clone(),
+            kind: BlockKind::Plain,
+            cold: parsed.cold,
+            params: Vec::new(),
+            instrs: vec![ParsedInstr::new(
+              line, None, JumpOp::new().op(), Vec::new(), vec![edge])],
+            body: Vec::new()
+        };
+        let from = parsed.name.clone();
+        self.place_after(&from, vec![split], inner);
+        name
+    }
+
+    // Remove the blocks that no edges lead to from the
+    // entries, with the values they define, which only
+    // such blocks can use.  A loop header left without
+    // back edges becomes a plain block.
+    pub(crate) fn remove_unreachable(&mut self) {
+        let mut succs: HashMap<String, Vec<String>> = HashMap::new();
+        let mut work = Vec::new();
+        each_block(&mut self.blocks, &mut |block| {
+            if work.is_empty() || block.kind == BlockKind::Start {
+                work.push(block.name.clone());
+            }
+            let end = block.instrs.last().unwrap();
+            succs.insert(block.name.clone(),
+                         end.targets.iter().map(|t| t.0.clone())
+                                    .collect());
+        });
+        let mut reached = HashSet::new();
+        while let Some(name) = work.pop() {
+            if reached.insert(name.clone()) {
+                work.extend(succs[&name].iter().cloned());
+            }
+        }
+
+        // A loop header left without back edges becomes
+        // a plain block, followed by its loop's blocks.
+        fn retain(level: &mut Vec<ParsedBlock>,
+                  reached: &HashSet<String>)
+        {
+            level.retain(|b| reached.contains(&b.name));
+            let mut i = 0;
+            while i < level.len() {
+                retain(&mut level[i].body, reached);
+                let block = &mut level[i];
+                let name = block.name.clone();
+                let back = |b: &ParsedBlock| {
+                    b.instrs.last().unwrap().targets.iter()
+                     .any(|t| t.0 == name)
+                };
+                if block.kind == BlockKind::Loop
+                   && !back(block) && !any_block(&block.body, &back)
+                {
+                    block.kind = BlockKind::Plain;
+                    let body = mem::take(&mut block.body);
+                    level.splice(i + 1 .. i + 1, body);
+                }
+                i += 1;
+            }
+        }
+        retain(&mut self.blocks, &reached);
+    }
+
+    // Add a phi to a block, giving its name.  Each
+    // edge into the block needs an argument for it.
+    pub(crate) fn add_phi(&mut self, block: BlockId, ty: IrTypeId)
+      -> String
+    {
+        let name = self.fresh_name();
+        self.block_mut(block).params.push((name.clone(), ty));
+        name
+    }
+
+    // Pass a value for the last phi of the target
+    // of an edge.
+    pub(crate) fn add_edge_arg(&mut self, from: BlockId, index: u32,
+                               value: &str)
+    {
+        let end = self.block_mut(from).instrs.last_mut().unwrap();
+        end.targets[index as usize].1.push(value.to_string());
+    }
+
+    // Insert an instruction before the end of a block,
+    // giving the name of its value, if it has one.
+    pub(crate) fn insert_before_end(&mut self, block: BlockId, op: Op,
+                                    operands: &[&str])
+      -> Option<String>
+    {
+        let def = if op.defines_value() {
+            Some(self.fresh_name())
+        } else {
+            None
+        };
+        let instrs = &mut self.block_mut(block).instrs;
+        let line = instrs.last().unwrap().line;
+        instrs.insert(instrs.len() - 1, ParsedInstr {
+            line,
+            def: def.clone(),
+            op,
+            operands: operands.iter().map(|s| s.to_string()).collect(),
+            targets: Vec::new(),
+            snapshot: None
+        });
+        def
+    }
+
+    // Insert an instruction before the one defining a
+    // value, giving the name of its value, if it has
+    // one.
+    pub(crate) fn insert_before(&mut self, value: &str, op: Op,
+                                operands: &[&str])
+      -> Option<String>
+    {
+        let def = if op.defines_value() {
+            Some(self.fresh_name())
+        } else {
+            None
+        };
+        let operands: Vec<String> =
+          operands.iter().map(|s| s.to_string()).collect();
+        each_block(&mut self.blocks, &mut |block| {
+            let instrs = &mut block.instrs;
+            let found = instrs.iter().position(|i| {
+                i.def.as_deref() == Some(value)
+            });
+            if let Some(i) = found {
+                let line = instrs[i].line;
+                instrs.insert(i, ParsedInstr {
+                    line,
+                    def: def.clone(),
+                    op: op.clone(),
+                    operands: operands.clone(),
+                    targets: Vec::new(),
+                    snapshot: None
+                });
+            }
+        });
+        def
+    }
+
+    fn each_use(&mut self, mut f: impl FnMut(&mut String)) {
+        each_block(&mut self.blocks, &mut |block| {
+            for instr in &mut block.instrs {
+                instr.operands.iter_mut().for_each(&mut f);
+                for (_, args) in &mut instr.targets {
+                    args.iter_mut().for_each(&mut f);
+                }
+                if let Some(ref mut snapshot) = instr.snapshot {
+                    snapshot.entries.iter_mut()
+                            .for_each(|(_, v)| f(v));
+                }
+            }
+        });
+    }
+
+    // Replace every use of a value, by instructions,
+    // edges and snapshots.
+    pub(crate) fn replace_uses(&mut self, old: &str, new: &str) {
+        self.each_use(|v| if v == old { *v = new.to_string() });
+    }
+
+    pub(crate) fn count_uses(&mut self, value: &str) -> usize {
+        let mut uses = 0;
+        self.each_use(|v| if v == value { uses += 1 });
+        uses
+    }
+
+    // The op and operands of the instruction defining
+    // a value, or None for a phi.
+    pub(crate) fn def(&mut self, value: &str)
+      -> Option<(Op, Vec<String>)>
+    {
+        let mut found = None;
+        each_block(&mut self.blocks, &mut |block| {
+            for instr in &block.instrs {
+                if instr.def.as_deref() == Some(value) {
+                    found = Some((instr.op.clone(),
+                                  instr.operands.clone()));
+                }
+            }
+        });
+        found
+    }
+
+    pub(crate) fn set_operands(&mut self, value: &str,
+                               operands: &[&str])
+    {
+        each_block(&mut self.blocks, &mut |block| {
+            for instr in &mut block.instrs {
+                if instr.def.as_deref() == Some(value) {
+                    instr.operands =
+                      operands.iter().map(|s| s.to_string()).collect();
+                }
+            }
+        });
+    }
+
+    // Remove the definition of an unused value.  A phi
+    // is removed with its argument on each edge.
+    pub(crate) fn remove_value(&mut self, value: &str) {
+        let mut phi = None;
+        each_block(&mut self.blocks, &mut |block| {
+            if let Some(i) = block.params.iter()
+                                  .position(|p| p.0 == value)
+            {
+                block.params.remove(i);
+                phi = Some((block.name.clone(), i));
+            }
+            block.instrs.retain(|i| i.def.as_deref() != Some(value));
+        });
+        if let Some((target, i)) = phi {
+            each_block(&mut self.blocks, &mut |block| {
+                let end = block.instrs.last_mut().unwrap();
+                for (t, args) in &mut end.targets {
+                    if *t == target {
+                        args.remove(i);
+                    }
+                }
+            });
+        }
+    }
+
+    // Build the edited graph.
+    pub(crate) fn finish(self) -> Graph {
+        self.try_finish().expect("rebuilt graph is invalid")
+    }
+
+    // Build the edited graph, or give why the edits
+    // left it unable to be built.
+    pub(crate) fn try_finish(self) -> Result<Graph, ParseError> {
+        check_blocks(&self.blocks) ?;
+        let mut graph = build_blocks(&self.blocks, self.encoding,
+                                     self.storage);
+        graph.set_target(self.target);
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::BlockStorage;
+    use crate::instr::OperandEncoding;
+    use crate::text::parse_with;
+    use super::Rebuild;
+
+    const LOOP: &str = "\
+block0:
+  %0 = const.i32 0
+  %1 = const.i32 10
+  jump block1(%0)
+loop block1(%2: i32) {
+  %3 = lt.i32 %2, %1
+  branch %3, block2, block3
+  block2:
+    %4 = const.i32 1
+    %5 = add.i32 %2, %4
+    jump block1(%5)
+}
+block3:
+  ret.i32 %2
+";
+
+    #[test]
+    fn keeps_encoding_and_storage() {
+        let cases = [
+            (OperandEncoding::Fixed, BlockStorage::Chunked),
+            (OperandEncoding::Delta, BlockStorage::Contiguous)
+        ];
+        for &(encoding, storage) in &cases {
+            let graph = parse_with(LOOP, encoding, storage);
+            let rebuilt = Rebuild::new(&graph).finish();
+            assert_eq!(rebuilt.operand_encoding(), encoding);
+            assert_eq!(rebuilt.block_storage(), storage);
+            assert_eq!(rebuilt.print_ir(), graph.print_ir());
+            assert!(rebuilt.verify().is_ok());
+        }
+    }
+}
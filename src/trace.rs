@@ -0,0 +1,322 @@
+
+use std::collections::HashMap;
+
+use crate::block::BlockId;
+use crate::instr::{ InstrId, InstrInfo };
+use crate::defn::Defn;
+use crate::graph::Graph;
+use crate::builder::{ Builder, BuildSession };
+use crate::ops::{ Operation, Op, PhiOp, ParamOp, GuardOp };
+use crate::ir_types::IrTypeId;
+
+/**
+ * The recorded direction of conditional branches,
+ * keyed by the block ending in the branch.
+ */
+pub struct BranchProfile {
+    taken: Vec<Option<bool>>
+}
+
+impl BranchProfile {
+    pub fn new() -> BranchProfile {
+        BranchProfile { taken: Vec::new() }
+    }
+
+    // Record that the branch ending `block` goes to its
+    // true target when `taken` is set, and to its false
+    // target otherwise.
+    pub fn set(&mut self, block: BlockId, taken: bool) {
+        let idx = block.as_u32() as usize;
+        if idx >= self.taken.len() {
+            self.taken.resize(idx + 1, None);
+        }
+        self.taken[idx] = Some(taken);
+    }
+
+    pub fn get(&self, block: BlockId) -> Option<bool> {
+        self.taken.get(block.as_u32() as usize)
+            .cloned()
+            .unwrap_or(None)
+    }
+}
+
+impl Default for BranchProfile {
+    fn default() -> BranchProfile { BranchProfile::new() }
+}
+
+/** The way a trace path ends. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum TraceEnd {
+    // The path reached a return.
+    Return,
+
+    // The path reached an unprofiled branch, a cold
+    // block, a block already on the path, or an exit,
+    // and leaves through a side exit.
+    Exit,
+
+    // The path came back around to its start block,
+    // and the trace is a loop.
+    Loop
+}
+
+/**
+ * A linear trace extracted from a graph.
+ *
+ * The trace graph is a single path through the
+ * original graph, with every branch along it replaced
+ * by a guard on the profiled direction.  Values live
+ * into the start block (including its phis) become
+ * params of the trace graph.
+ *
+ * A looping trace has a start block that only jumps
+ * to a loop header, with one loop phi per param, and
+ * the path as the body of the loop.
+ */
+pub struct Trace {
+    graph: Graph,
+
+    // The original blocks along the path, in order.
+    path: Vec<BlockId>,
+
+    // The original value bound to each param index.
+    params: Vec<InstrId>,
+
+    end: TraceEnd
+}
+
+impl Trace {
+    pub(crate) fn extract(graph: &Graph,
+        start: BlockId, profile: &BranchProfile)
+      -> Trace
+    {
+        let (steps, end) = follow_path(graph, start, profile);
+        let params = trace_params(graph, start);
+
+        let builder = Builder::build_for(graph.target(), |bs| {
+            let mut map: HashMap<InstrId, InstrId> =
+              HashMap::new();
+            let param_defs: Vec<Defn> =
+              params.iter().enumerate().map(|(i, &(_, ty))| {
+                  let op = ParamOp::new(i as u32, ty).op();
+                  bs.emit_op(&op, &[])
+              }).collect();
+
+            if end == TraceEnd::Loop {
+                let nparams = params.len() as u32;
+                let head = bs.decl_loop_head(nparams);
+                bs.jump(head, &param_defs);
+                bs.def_loop(head, |cs| {
+                    for &(v, ty) in &params {
+                        let op = PhiOp::new(ty).op();
+                        let phi = cs.emit_op(&op, &[]);
+                        map.insert(v, phi.instr_id());
+                    }
+                    emit_path(graph, &steps, &mut map, cs);
+                    let args = closing_args(
+                      graph, start, &steps, &params, &map);
+                    cs.jump(head, &args);
+                });
+                return;
+            }
+
+            for (&(v, _), d) in params.iter().zip(&param_defs) {
+                map.insert(v, d.instr_id());
+            }
+            emit_path(graph, &steps, &mut map, bs);
+
+            if end == TraceEnd::Return {
+                let &(last, _) = steps.last().unwrap();
+                let ret = graph.block_end(graph.block(last));
+                let inputs = mapped_inputs(
+                  &map, ret.inputs_iter().map(|d| d.instr_id()));
+                bs.emit_op_end(ret.op(), &inputs, &[]);
+            } else {
+                bs.exit();
+            }
+        });
+
+        let path = steps.iter().map(|&(b, _)| b).collect();
+        let params = params.iter().map(|&(v, _)| v).collect();
+        Trace { graph: builder.into_graph(), path, params, end }
+    }
+
+    pub fn graph(&self) -> &Graph { &self.graph }
+    pub fn into_graph(self) -> Graph { self.graph }
+    pub fn path(&self) -> &[BlockId] { &self.path }
+    pub fn end(&self) -> TraceEnd { self.end }
+
+    // The original values bound to the trace params,
+    // by param index.
+    pub fn params(&self) -> &[InstrId] { &self.params }
+}
+
+// A step on the path: a block, and the index of the
+// target followed out of it (if the path continues).
+type Step = (BlockId, Option<usize>);
+
+fn follow_path(graph: &Graph,
+    start: BlockId, profile: &BranchProfile)
+  -> (Vec<Step>, TraceEnd)
+{
+    let mut steps = Vec::new();
+    let mut visited =
+      vec![false; graph.block_store().total_blocks()];
+    let mut cur = start;
+    loop {
+        visited[cur.as_u32() as usize] = true;
+        let end = graph.block_end(graph.block(cur));
+        let taken = match end.op() {
+          Op::Ret(_) => {
+            steps.push((cur, None));
+            return (steps, TraceEnd::Return);
+          }
+          Op::Jump(_) => Some(0),
+          Op::Branch(_) => {
+            profile.get(cur).map(|t| if t { 0 } else { 1 })
+              .or_else(|| away_from_cold(graph, &end))
+          }
+          _ => None
+        };
+        let next = taken.map(|i| {
+            end.targets_iter().nth(i).unwrap().block_id()
+        });
+        match next {
+          Some(n) if n == start => {
+            steps.push((cur, taken));
+            return (steps, TraceEnd::Loop);
+          }
+          Some(n) if !visited[n.as_u32() as usize]
+                       && !graph.block(n).is_cold() => {
+            steps.push((cur, taken));
+            cur = n;
+          }
+          _ => {
+            steps.push((cur, None));
+            return (steps, TraceEnd::Exit);
+          }
+        }
+    }
+}
+
+// The target an unprofiled branch is predicted to
+// take: the other one, when just one of its targets is
+// cold.
+fn away_from_cold(graph: &Graph, end: &InstrInfo) -> Option<usize> {
+    let cold: Vec<bool> = end.targets_iter()
+      .map(|t| graph.block(t.block_id()).is_cold())
+      .collect();
+    match cold[..] {
+      [false, true] => Some(0),
+      [true, false] => Some(1),
+      _ => None
+    }
+}
+
+// The values that become trace params: the phis and
+// params of the start block in order, followed by its
+// live-in values.
+fn trace_params(graph: &Graph, start: BlockId)
+  -> Vec<(InstrId, IrTypeId)>
+{
+    let mut params = Vec::new();
+    for info in graph.block_instrs(graph.block(start)) {
+        match info.op() {
+          Op::Phi(_) | Op::Param(_) => {
+            let ty = info.op().out_type().unwrap();
+            params.push((info.defn().instr_id(), ty));
+          }
+          _ => {}
+        }
+    }
+    for v in graph.liveness().live_in(start) {
+        let ty = graph.instr(v).op().out_type().unwrap();
+        params.push((v, ty));
+    }
+    params
+}
+
+fn mapped_inputs<'a, I>(
+    map: &HashMap<InstrId, InstrId>, inputs: I)
+  -> Vec<Defn<'a>>
+  where I: Iterator<Item=InstrId>
+{
+    inputs.map(|v| Defn::new(map[&v])).collect()
+}
+
+// The phi arguments passed along the path edge
+// leaving the given step.
+fn edge_args(graph: &Graph, step: Step) -> Vec<InstrId> {
+    let (block, taken) = step;
+    let end = graph.block_end(graph.block(block));
+    let target = end.targets_iter().nth(taken.unwrap()).unwrap();
+    target.phi_args().map(|d| d.instr_id()).collect()
+}
+
+// Copy the instructions along the path into the
+// session, replacing the branches followed with guards.
+// Phis and params of the start block must already be
+// mapped.
+fn emit_path<'x>(graph: &Graph,
+    steps: &[Step],
+    map: &mut HashMap<InstrId, InstrId>,
+    bs: &mut BuildSession<'x>)
+{
+    for (i, &(block, taken)) in steps.iter().enumerate() {
+        let incoming = if i > 0 {
+            edge_args(graph, steps[i - 1])
+        } else {
+            Vec::new()
+        };
+        let mut phi_idx = 0;
+
+        for info in graph.block_instrs(graph.block(block)) {
+            let id = info.defn().instr_id();
+            let op = info.op();
+            let inputs = info.inputs_iter().map(|d| d.instr_id());
+            match op {
+              Op::Phi(_) | Op::Param(_) if i == 0 => {}
+              Op::Phi(_) => {
+                let arg = map[&incoming[phi_idx]];
+                map.insert(id, arg);
+                phi_idx += 1;
+              }
+              Op::Branch(_) => {
+                if let Some(t) = taken {
+                    let cond = mapped_inputs(map, inputs);
+                    let guard = GuardOp::new(t == 0).op();
+                    bs.emit_op(&guard, &cond);
+                }
+              }
+              _ if op.terminal() => {}
+              _ => {
+                let inputs = mapped_inputs(map, inputs);
+                let def = bs.emit_op(op, &inputs);
+                map.insert(id, def.instr_id());
+              }
+            }
+        }
+    }
+}
+
+// The arguments passed back to the loop header at the
+// end of a looping trace: the start block's phi
+// arguments along the closing edge, then the current
+// values of the other params.
+fn closing_args<'a>(graph: &Graph,
+    start: BlockId,
+    steps: &[Step],
+    params: &[(InstrId, IrTypeId)],
+    map: &HashMap<InstrId, InstrId>)
+  -> Vec<Defn<'a>>
+{
+    let num_phis = graph.block(start).num_phis() as usize;
+    let phi_args = edge_args(graph, *steps.last().unwrap());
+    debug_assert!(phi_args.len() == num_phis);
+
+    let carried = params.iter().enumerate().map(|(i, &(v, _))| {
+        if i < num_phis { phi_args[i] } else { v }
+    });
+    mapped_inputs(map, carried)
+}
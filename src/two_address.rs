@@ -0,0 +1,135 @@
+
+use std::collections::HashMap;
+
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::{ Op, CopyOp, Operation };
+use crate::ir_types::IrTypeId;
+use crate::text::Rebuild;
+
+/*
+ * Two-address form.  An x86-style binary instruction
+ * writes its result over its first operand, so a
+ * lowering can give the result the first operand's
+ * register only where that operand dies at the
+ * instruction.  Each `bini` whose first operand is
+ * still live after it, by a later instruction or edge
+ * of its block or by being live out of the block, is
+ * rewritten: a commutative one whose second operand
+ * dies there has its operands swapped, and any other
+ * takes a copy of its first operand, inserted just
+ * before it, in its place.  A value used as both
+ * operands dies at the instruction if it is not used
+ * after it.
+ *
+ * Liveness counts uses by instructions and edges, as
+ * the register allocator does; snapshots do not keep
+ * a value live.
+ *
+ * The graph is rebuilt with the new instructions, so
+ * the ids of its instructions and blocks change.
+ */
+
+/** What `Graph::to_two_address` did. */
+#[derive(Clone, Copy, Debug, Default)]
+#[derive(PartialEq, Eq)]
+pub struct TwoAddressStats {
+    swapped: u32,
+    copies: u32
+}
+
+impl TwoAddressStats {
+    // The instructions whose operands were swapped.
+    pub fn swapped(&self) -> u32 { self.swapped }
+
+    // The copies inserted.
+    pub fn copies(&self) -> u32 { self.copies }
+
+    pub fn changed(&self) -> bool {
+        self.swapped + self.copies > 0
+    }
+}
+
+// How an instruction is brought to two-address form.
+enum Fix {
+    Swap,
+    Copy(IrTypeId)
+}
+
+impl Graph {
+    /**
+     * Rewrite the graph's binary integer instructions
+     * into two-address form, where the first operand of
+     * each dies at it, by swapping commutative operands
+     * or copying the first operand.  When anything
+     * changes, the graph is rebuilt: its instruction and
+     * block ids change, and its profile, instruction
+     * index and patched patch point bytes are dropped.
+     */
+    pub fn to_two_address(&mut self) -> TwoAddressStats {
+        let liveness = self.liveness();
+
+        let mut fixes: Vec<(InstrId, Fix, [InstrId; 2])> = Vec::new();
+        for block in self.block_store().iter_rpo_blocks() {
+            // The position of the last use of each value
+            // by the block.
+            let mut last_use: HashMap<InstrId, usize> = HashMap::new();
+            for (pos, info) in self.block_instrs(block).enumerate() {
+                let uses = info.inputs_iter().chain(
+                  info.targets_iter().flat_map(|t| t.phi_args()));
+                for value in uses {
+                    last_use.insert(value.instr_id(), pos);
+                }
+            }
+            let live_after = |value: InstrId, pos: usize| {
+                last_use.get(&value).is_some_and(|&p| p > pos)
+                  || liveness.is_live_out(block.id(), value)
+            };
+
+            for (pos, info) in self.block_instrs(block).enumerate() {
+                let op = match info.op() {
+                  Op::Bini(op) => op,
+                  _ => continue
+                };
+                let mut inputs = info.inputs_iter();
+                let a = inputs.next().unwrap().instr_id();
+                let b = inputs.next().unwrap().instr_id();
+                if !live_after(a, pos) {
+                    continue;
+                }
+                let fix = if op.kind().is_commutative()
+                               && !live_after(b, pos)
+                {
+                    Fix::Swap
+                } else {
+                    Fix::Copy(op.tyid())
+                };
+                fixes.push((info.defn().instr_id(), fix, [a, b]));
+            }
+        }
+
+        let mut stats = TwoAddressStats::default();
+        if fixes.is_empty() {
+            return stats;
+        }
+        let mut rb = Rebuild::new(self);
+        for (id, fix, [a, b]) in fixes {
+            let value = rb.value(id);
+            let (a, b) = (rb.value(a), rb.value(b));
+            match fix {
+              Fix::Swap => {
+                rb.set_operands(&value, &[&b, &a]);
+                stats.swapped += 1;
+              }
+              Fix::Copy(ty) => {
+                let op = CopyOp::new(ty).op();
+                let copy = rb.insert_before(&value, op, &[&a]).unwrap();
+                rb.set_operands(&value, &[&copy, &b]);
+                stats.copies += 1;
+              }
+            }
+        }
+        self.rebuilt(rb.finish());
+        stats
+    }
+}
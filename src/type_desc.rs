@@ -0,0 +1,459 @@
+
+use std::fmt;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ops::{ Op, PtrAddOp };
+use crate::ir_types::IrTypeId;
+use crate::target::TargetDesc;
+use crate::decode::ByteReader;
+use crate::serialize::{ LoadError, read_count };
+use crate::leb128;
+
+/** Names a descriptor of a module's type table. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DescId(u32);
+
+impl DescId {
+    pub fn as_u32(&self) -> u32 { self.0 }
+}
+
+/**
+ * The type of a member of an aggregate: a scalar of
+ * one of the IR's types, laid out as the table's
+ * target lays it out, or an aggregate of the table.
+ */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum FieldType { Scalar(IrTypeId), Desc(DescId) }
+
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+enum DescKind {
+    // The offset and type of each field.
+    Struct(Vec<(u32, FieldType)>),
+
+    // The element type and number of elements.
+    Array(FieldType, u32)
+}
+
+/**
+ * The layout of an aggregate type.  The members of a
+ * struct are its fields, and those of an array its
+ * elements.  Every member is aligned to its own size,
+ * or for an aggregate to the largest alignment of its
+ * members, and the size of an aggregate is rounded up
+ * to its alignment, so that arrays of it align.
+ */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct TypeDesc {
+    kind: DescKind,
+    size: u32,
+    align: u32
+}
+
+impl TypeDesc {
+    pub fn size(&self) -> u32 { self.size }
+    pub fn align(&self) -> u32 { self.align }
+    pub fn is_array(&self) -> bool {
+        matches!(self.kind, DescKind::Array(..))
+    }
+
+    pub fn num_members(&self) -> u32 {
+        match self.kind {
+          DescKind::Struct(ref fields) => fields.len() as u32,
+          DescKind::Array(_, len) => len
+        }
+    }
+
+    // The offset and type of a member, if there is one.
+    pub fn member(&self, index: u32) -> Option<(u32, FieldType)> {
+        match self.kind {
+          DescKind::Struct(ref fields) => {
+            fields.get(index as usize).cloned()
+          }
+          DescKind::Array(elem, len) if index < len => {
+            Some((index * self.stride(), elem))
+          }
+          DescKind::Array(..) => None
+        }
+    }
+
+    // The distance between consecutive values indexed
+    // through a pointer to the aggregate: the size of a
+    // struct, as in an array of them, or of an element
+    // of an array.
+    pub fn stride(&self) -> u32 {
+        match self.kind {
+          DescKind::Struct(_) => self.size,
+          DescKind::Array(_, 0) => 0,
+          DescKind::Array(_, len) => self.size / len
+        }
+    }
+}
+
+/**
+ * A table of aggregate type descriptors, held by a
+ * module, which the `ptradd` instructions of its
+ * graphs compute the addresses of members through.
+ * A descriptor can only refer to those added before
+ * it, so aggregates cannot contain themselves.
+ * Descriptors are laid out for the table's target,
+ * `TargetDesc::LP64` unless given another.
+ */
+#[derive(Clone, Debug, Default)]
+#[derive(PartialEq, Eq)]
+pub struct TypeTable {
+    descs: Vec<TypeDesc>,
+    target: TargetDesc
+}
+
+const DESC_STRUCT: u8 = 0;
+const DESC_ARRAY: u8 = 1;
+
+const FIELD_SCALAR: u8 = 0;
+const FIELD_DESC: u8 = 1;
+
+fn write_field_type(ty: FieldType, out: &mut Vec<u8>) {
+    match ty {
+      FieldType::Scalar(ty) => {
+        out.push(FIELD_SCALAR);
+        out.push(ty.into_u8());
+      }
+      FieldType::Desc(id) => {
+        out.push(FIELD_DESC);
+        leb128::write_leb128u(id.0, out);
+      }
+    }
+}
+
+fn read_field_type(reader: &mut ByteReader, entry: u32)
+  -> Result<FieldType, LoadError>
+{
+    match reader.read_u8() ? {
+      FIELD_SCALAR => Ok(FieldType::Scalar(reader.read_type_id() ?)),
+      FIELD_DESC => {
+        Ok(FieldType::Desc(DescId(reader.read_leb128u32() ?)))
+      }
+      _ => Err(LoadError::BadTypeTable { entry })
+    }
+}
+
+fn round_up(value: u32, align: u32) -> Option<u32> {
+    value.checked_add(align - 1).map(|v| v / align * align)
+}
+
+impl TypeTable {
+    pub fn new() -> TypeTable {
+        TypeTable::default()
+    }
+
+    // An empty table laid out for the given target.
+    pub fn with_target(target: TargetDesc) -> TypeTable {
+        TypeTable { descs: Vec::new(), target }
+    }
+
+    pub fn target(&self) -> TargetDesc { self.target }
+
+    pub fn num_descs(&self) -> u32 { self.descs.len() as u32 }
+
+    pub fn desc(&self, id: DescId) -> Option<&TypeDesc> {
+        self.descs.get(id.0 as usize)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=(DescId, &TypeDesc)> {
+        self.descs.iter().enumerate()
+            .map(|(i, d)| (DescId(i as u32), d))
+    }
+
+    // The size and alignment of a member type, if it
+    // names a scalar or a descriptor of the table.
+    pub fn layout(&self, ty: FieldType) -> Option<(u32, u32)> {
+        match ty {
+          FieldType::Scalar(ty) => {
+            Some((self.target.size_of(ty), self.target.align_of(ty)))
+          }
+          FieldType::Desc(id) => {
+            self.desc(id).map(|d| (d.size, d.align))
+          }
+        }
+    }
+
+    // Add a struct of the given fields, laid out in
+    // order.  None if a field names no descriptor, or
+    // the struct outgrows 32 bits.
+    pub fn add_struct(&mut self, fields: &[FieldType])
+      -> Option<DescId>
+    {
+        let mut laid = Vec::with_capacity(fields.len());
+        let (mut end, mut align) = (0_u32, 1_u32);
+        for &field in fields {
+            let (size, field_align) = self.layout(field) ?;
+            let offset = round_up(end, field_align) ?;
+            laid.push((offset, field));
+            end = offset.checked_add(size) ?;
+            align = align.max(field_align);
+        }
+        let size = round_up(end, align) ?;
+        Some(self.push(TypeDesc {
+            kind: DescKind::Struct(laid), size, align
+        }))
+    }
+
+    // Add an array of `len` elements.  None if the
+    // element type names no descriptor, or the array
+    // outgrows 32 bits.
+    pub fn add_array(&mut self, elem: FieldType, len: u32)
+      -> Option<DescId>
+    {
+        let (size, align) = self.layout(elem) ?;
+        let size = size.checked_mul(len) ?;
+        Some(self.push(TypeDesc {
+            kind: DescKind::Array(elem, len), size, align
+        }))
+    }
+
+    fn push(&mut self, desc: TypeDesc) -> DescId {
+        self.descs.push(desc);
+        DescId((self.descs.len() - 1) as u32)
+    }
+
+    // Write the table as a module's types section.
+    pub(crate) fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.target.to_bytes());
+        leb128::write_leb128u(self.descs.len() as u64, out);
+        for desc in &self.descs {
+            match desc.kind {
+              DescKind::Struct(ref fields) => {
+                out.push(DESC_STRUCT);
+                leb128::write_leb128u(fields.len() as u64, out);
+                for &(_, field) in fields {
+                    write_field_type(field, out);
+                }
+              }
+              DescKind::Array(elem, len) => {
+                out.push(DESC_ARRAY);
+                write_field_type(elem, out);
+                leb128::write_leb128u(len, out);
+              }
+            }
+        }
+    }
+
+    // Read a types section, laying out each descriptor
+    // anew.
+    pub(crate) fn read_from(reader: &mut ByteReader)
+      -> Result<TypeTable, LoadError>
+    {
+        let found = [reader.read_u8() ?, reader.read_u8() ?,
+                     reader.read_u8() ?];
+        let target = TargetDesc::from_bytes(found)
+          .ok_or(LoadError::BadTarget { found }) ?;
+        let mut table = TypeTable::with_target(target);
+        let num_descs = read_count(reader) ?;
+        for entry in 0 .. num_descs as u32 {
+            let bad = LoadError::BadTypeTable { entry };
+            let id = match reader.read_u8() ? {
+              DESC_STRUCT => {
+                let num_fields = read_count(reader) ?;
+                let mut fields = Vec::with_capacity(num_fields);
+                for _ in 0 .. num_fields {
+                    fields.push(read_field_type(reader, entry) ?);
+                }
+                table.add_struct(&fields)
+              }
+              DESC_ARRAY => {
+                let elem = read_field_type(reader, entry) ?;
+                let len = reader.read_leb128u32() ?;
+                table.add_array(elem, len)
+              }
+              _ => return Err(bad)
+            };
+            id.ok_or(bad) ?;
+        }
+        Ok(table)
+    }
+
+    /**
+     * Check the member accesses of a graph against the
+     * table: each `ptradd` must name a member of a
+     * descriptor, with the offset and stride of its
+     * layout, and through a pointer to that aggregate
+     * when the pointer is itself a `ptradd`.  A load or
+     * store through a `ptradd` must access a scalar
+     * member of its type.  Pointers from elsewhere are
+     * taken on trust.  A graph built for another target
+     * than the table's has each `ptradd` reported, as
+     * the layouts it was built with are not the
+     * table's.
+     */
+    pub fn check_graph(&self, graph: &Graph)
+      -> Result<(), Vec<AccessError>>
+    {
+        let mut errors = Vec::new();
+        for block in graph.block_store().iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                let ptr = match info.inputs_iter().next() {
+                  Some(ptr) => ptr.instr_id(),
+                  None => continue
+                };
+                let base = match *graph.instr(ptr).op() {
+                  Op::PtrAdd(ref add) => self.member_of(add),
+                  _ => None
+                };
+                let error = |kind| AccessError {
+                    block: block.id(),
+                    instr: info.defn().instr_id(),
+                    kind
+                };
+                let access = match *info.op() {
+                  Op::Load(ref op) => op.tyid(),
+                  Op::Store(ref op) => op.tyid(),
+                  Op::PtrAdd(ref add) => {
+                    let desc = DescId(add.desc());
+                    if graph.target() != self.target {
+                        errors.push(error(AccessErrorKind::Target {
+                            found: graph.target()
+                        }));
+                        continue;
+                    }
+                    if let Some(kind) = self.check_ptr_add(add) {
+                        errors.push(error(kind));
+                    }
+                    match base {
+                      Some(FieldType::Desc(d)) if d == desc => {}
+                      Some(found) => errors.push(error(
+                        AccessErrorKind::BaseType {
+                            expected: desc, found
+                        })),
+                      None => {}
+                    }
+                    continue;
+                  }
+                  _ => continue
+                };
+                match base {
+                  Some(found) if found != FieldType::Scalar(access) => {
+                    errors.push(error(AccessErrorKind::AccessType {
+                        access, found
+                    }));
+                  }
+                  _ => {}
+                }
+            }
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    // The type of the member a `ptradd` addresses, if
+    // it names one.
+    fn member_of(&self, add: &PtrAddOp)
+      -> Option<FieldType>
+    {
+        let desc = self.desc(DescId(add.desc())) ?;
+        desc.member(add.member()).map(|m| m.1)
+    }
+
+    fn check_ptr_add(&self, add: &PtrAddOp)
+      -> Option<AccessErrorKind>
+    {
+        let id = DescId(add.desc());
+        let desc = match self.desc(id) {
+          Some(desc) => desc,
+          None => return Some(AccessErrorKind::BadDesc { desc: id })
+        };
+        let member = add.member();
+        match desc.member(member) {
+          None => Some(AccessErrorKind::BadMember { desc: id, member }),
+          Some((offset, _)) if offset != add.offset()
+                               || desc.stride() != add.stride() => {
+            Some(AccessErrorKind::BadLayout { desc: id, member })
+          }
+          Some(_) => None
+        }
+    }
+}
+
+/**
+ * A member access of a graph that does not agree with
+ * its module's type table, with the block and
+ * instruction at which it was found.
+ */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct AccessError {
+    block: BlockId,
+    instr: InstrId,
+    kind: AccessErrorKind
+}
+
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum AccessErrorKind {
+    // A `ptradd` names no descriptor of the table.
+    BadDesc { desc: DescId },
+
+    // A `ptradd` names no member of its descriptor.
+    BadMember { desc: DescId, member: u32 },
+
+    // The offset or stride of a `ptradd` differs from
+    // the layout of its descriptor.
+    BadLayout { desc: DescId, member: u32 },
+
+    // A `ptradd` indexes through the address of a
+    // member that is not its aggregate.
+    BaseType { expected: DescId, found: FieldType },
+
+    // A load or store of one type accesses a member of
+    // another.
+    AccessType { access: IrTypeId, found: FieldType },
+
+    // A `ptradd` of a graph built for another target
+    // than the table's.
+    Target { found: TargetDesc }
+}
+
+impl AccessError {
+    pub fn block(&self) -> BlockId { self.block }
+    pub fn instr(&self) -> InstrId { self.instr }
+    pub fn kind(&self) -> &AccessErrorKind { &self.kind }
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match self {
+          FieldType::Scalar(ty) => write!(f, "{}", ty.as_str()),
+          FieldType::Desc(id) => write!(f, "desc {}", id.0)
+        }
+    }
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "{} {}: ", self.block, self.instr)?;
+        match self.kind {
+          AccessErrorKind::BadDesc { desc } =>
+            write!(f, "no descriptor {}", desc.0),
+          AccessErrorKind::BadMember { desc, member } =>
+            write!(f, "descriptor {} has no member {}", desc.0, member),
+          AccessErrorKind::BadLayout { desc, member } =>
+            write!(f, "offset or stride of member {} of \
+                       descriptor {} differs from its layout",
+                   member, desc.0),
+          AccessErrorKind::BaseType { expected, found } =>
+            write!(f, "indexes descriptor {} through a pointer \
+                       to {}", expected.0, found),
+          AccessErrorKind::AccessType { access, found } =>
+            write!(f, "accesses {} as {}", found, access.as_str()),
+          AccessErrorKind::Target { found } =>
+            write!(f, "graph built for target {}", found)
+        }
+    }
+}
@@ -0,0 +1,83 @@
+
+use std::fmt;
+
+use crate::block::BlockId;
+use crate::instr::InstrId;
+use crate::graph::Graph;
+use crate::ir_types::{ IrTypeId, IrInputTypeId };
+
+/**
+ * An operand whose definition's output type does not
+ * match the type its operation expects.
+ */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct TypeError {
+    block: BlockId,
+    instr: InstrId,
+
+    // The index of the offending operand.
+    operand: u32,
+
+    expected: IrTypeId,
+
+    // The output type of the operand's definition, or
+    // None if it defines no value.
+    found: Option<IrTypeId>
+}
+
+impl TypeError {
+    pub fn block(&self) -> BlockId { self.block }
+    pub fn instr(&self) -> InstrId { self.instr }
+    pub fn operand(&self) -> u32 { self.operand }
+    pub fn expected(&self) -> IrTypeId { self.expected }
+    pub fn found(&self) -> Option<IrTypeId> { self.found }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "{} {}: operand {} expects {}, got {}",
+               self.block, self.instr, self.operand,
+               self.expected.as_str(),
+               self.found.map_or("no value", |t| t.as_str()))
+    }
+}
+
+// Check the operands of every instruction against the
+// types expected by its operation.  The graph must
+// already have passed verification, so that every
+// operand names an instruction.
+pub(crate) fn type_check(graph: &Graph)
+  -> Result<(), Vec<TypeError>>
+{
+    let mut errors = Vec::new();
+    for block in graph.block_store().iter_rpo_blocks() {
+        for info in graph.block_instrs(block) {
+            let op = info.op();
+            for (i, operand) in info.inputs_iter().enumerate() {
+                let expected = match op.operand_type(i as u32) {
+                  IrInputTypeId::Specific(ty) => ty,
+                  IrInputTypeId::Any => continue
+                };
+                let def = graph.instr(operand.instr_id());
+                let found = if def.op().defines_value() {
+                    def.op().out_type()
+                } else {
+                    None
+                };
+                if found != Some(expected) {
+                    errors.push(TypeError {
+                        block: block.id(),
+                        instr: info.defn().instr_id(),
+                        operand: i as u32,
+                        expected, found
+                    });
+                }
+            }
+        }
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
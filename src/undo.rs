@@ -0,0 +1,187 @@
+use crate::block::BlockId;
+use crate::graph::Graph;
+use crate::changes::Change;
+use crate::profile::Profile;
+use crate::snapshot::Snapshot;
+
+/*
+ * The undo log.  While a graph keeps one, each edit
+ * saves what it overwrites before making it: the bytes
+ * of an operand, payload, target or patch space, the
+ * target an edge went to, a snapshot, or the profile
+ * it replaces.  Rolling back to a mark restores them
+ * in reverse, so a transform that fails or is given up
+ * leaves the graph as it found it without the graph
+ * having been copied.
+ *
+ * An edit that rebuilds the graph saves the graph it
+ * replaces, which it would otherwise drop.  Rolling
+ * back changes the graph's ids, and begins a new
+ * epoch of its change log.
+ */
+
+/** A point in a graph's undo log, to roll back to. */
+#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct UndoMark {
+    len: usize
+}
+
+// What an edit overwrote.
+pub(crate) enum Undo {
+    // The stream's bytes at an offset.
+    Bytes { offset: usize, bytes: Vec<u8> },
+
+    // The fields, at an offset, of the edge at an index
+    // of the end of a block, which went to `old`.
+    Edge {
+        from: BlockId, index: u32, old: BlockId,
+        offset: usize, bytes: Vec<u8>
+    },
+
+    // The snapshot at an index of the snapshot table.
+    Snapshot { index: usize, snapshot: Snapshot },
+
+    // The attached profile.
+    Profile(Option<Profile>),
+
+    // The graph before it was rebuilt.
+    Graph(Box<Graph>)
+}
+
+impl Graph {
+    // What the edit `change`, about to be made, will
+    // overwrite.
+    pub(crate) fn undo_entry(&self, change: Change) -> Undo {
+        let store = self.instr_store();
+        let saved = |(offset, len): (usize, usize)| {
+            (offset, store.instr_bytes()[offset .. offset + len].to_vec())
+        };
+        let (offset, bytes) = match change {
+          Change::Operand { user, site, .. } =>
+            saved(store.operand_field(user, site)),
+          Change::Payload { instr } =>
+            saved(store.payload_field(instr)),
+          Change::Edge { from, index } => {
+            let end = self.block_view(from).unwrap().end();
+            let old = end.targets().nth(index as usize).unwrap().block();
+            let (offset, bytes) =
+              saved(store.target_field(end.id(), index));
+            return Undo::Edge { from, index, old, offset, bytes };
+          }
+          Change::Snapshot { instr } => {
+            let index = self.snapshot_table().iter()
+                            .position(|s| s.0 == instr).unwrap();
+            let snapshot = self.snapshot_table()[index].1.clone();
+            return Undo::Snapshot { index, snapshot };
+          }
+          Change::Patch { instr } => {
+            let point = self.patch_points().iter()
+                            .find(|p| p.instr() == instr).unwrap();
+            saved((point.offset(), point.size() as usize))
+          }
+        };
+        Undo::Bytes { offset, bytes }
+    }
+
+    /**
+     * Begin keeping an undo log, if one is not already
+     * kept, and mark its current point.  Marks nest:
+     * rolling back to an inner mark keeps the edits
+     * before it.
+     */
+    pub fn begin_undo(&mut self) -> UndoMark {
+        UndoMark { len: self.undo_log_mut().len() }
+    }
+
+    /**
+     * Undo every edit made since the mark, in reverse,
+     * keeping the log.  The graph's ids are those it
+     * had at the mark, and its cached analyses and
+     * change log start anew.
+     */
+    pub fn rollback(&mut self, mark: UndoMark) {
+        let mut log = self.take_undo_log().expect("no undo log is kept");
+        assert!(mark.len <= log.len(), "undo mark is past the log");
+        for undo in log.drain(mark.len ..).rev() {
+            match undo {
+              Undo::Bytes { offset, bytes } =>
+                self.instr_store_mut().restore(offset, &bytes),
+              Undo::Edge { from, index, old, offset, bytes } => {
+                let new = self.block_view(from).unwrap().end()
+                              .targets().nth(index as usize).unwrap()
+                              .block();
+                self.instr_store_mut().restore(offset, &bytes);
+                self.block_store_mut().move_input_edge(new, old);
+              }
+              Undo::Snapshot { index, snapshot } =>
+                self.snapshot_table_mut()[index].1 = snapshot,
+              Undo::Profile(profile) => *self.profile_mut() = profile,
+              Undo::Graph(graph) => self.rebuilt(*graph)
+            }
+        }
+        self.rolled_back(log);
+    }
+
+    /**
+     * Stop keeping the undo log, dropping it along with
+     * every mark, and keep the edits made.
+     */
+    pub fn end_undo(&mut self) {
+        self.take_undo_log();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::block::BlockStorage;
+    use crate::instr::OperandEncoding;
+    use crate::text::parse_with;
+
+    const DIAMOND: &str = "\
+block0:
+  %0 = const.i32 1
+  %1 = const.i32 2
+  %2 = lt.i32 %0, %1
+  branch %2, block1, block2
+block1:
+  jump block3(%0)
+block2:
+  jump block3(%1)
+block3(%3: i32):
+  ret.i32 %3
+";
+
+    #[test]
+    fn rollback_restores_the_graph() {
+        let mut graph = parse_with(DIAMOND, OperandEncoding::Fixed,
+                                   BlockStorage::Contiguous);
+        let before = graph.print_ir();
+        let bytes = graph.instr_bytes().to_vec();
+        let blocks: Vec<_> =
+          graph.rpo_block_views().map(|b| b.id()).collect();
+        let one = graph.block_view(blocks[0]).unwrap()
+                       .instrs().next().unwrap().id();
+
+        let outer = graph.begin_undo();
+        assert!(!graph.set_phi_arg(blocks[2], 0, 0, one));
+        let edited = graph.print_ir();
+        let inner = graph.begin_undo();
+        assert!(graph.retarget(blocks[0], 0, blocks[3], &[one]));
+        assert_eq!(graph.num_blocks(), 3);
+
+        // Rolling back to the inner mark keeps the edit
+        // made before it.
+        graph.rollback(inner);
+        assert_eq!(graph.print_ir(), edited);
+        assert!(graph.verify().is_ok());
+
+        graph.rollback(outer);
+        graph.end_undo();
+        assert_eq!(graph.print_ir(), before);
+        assert_eq!(graph.instr_bytes(), &bytes[..]);
+        assert_eq!(graph.rpo_block_views().map(|b| b.id())
+                        .collect::<Vec<_>>(), blocks);
+        assert!(graph.verify().is_ok());
+    }
+}
@@ -0,0 +1,461 @@
+
+use std::fmt;
+
+use crate::block::{ Block, BlockId };
+use crate::instr::{ InstrId, InstrInfo };
+use crate::graph::Graph;
+use crate::ops::{ Op, ConstOp };
+use crate::analysis::{ Cfg, DomTree };
+use crate::ir_types::IrTypeId;
+
+/**
+ * A problem found by the graph verifier, with the
+ * block (and instruction, where there is one) at
+ * which it was found.
+ */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct VerifyError {
+    block: BlockId,
+    instr: Option<InstrId>,
+    kind: VerifyErrorKind
+}
+
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum VerifyErrorKind {
+    // The block was declared but never finished.
+    UnfinishedBlock,
+
+    // The block's last instruction is not an end
+    // instruction.
+    MissingTerminator,
+
+    // An end instruction appears before the block's
+    // last instruction.
+    EarlyTerminator,
+
+    // An operand does not name the start of an
+    // instruction.
+    BadOperand { operand: InstrId },
+
+    // An operand names an instruction that does not
+    // define a value.
+    NonValueOperand { operand: InstrId },
+
+    // An operand's definition does not dominate its use.
+    UndominatedOperand { operand: InstrId },
+
+    // A phi follows a non-phi instruction in its block.
+    MisplacedPhi,
+
+    // The block's leading phis differ in number from
+    // its declared phis.
+    PhiCount { expected: u32, found: u32 },
+
+    // An end instruction targets an undeclared block.
+    BadTarget { target: BlockId },
+
+    // An end instruction passes a different number of
+    // phi arguments than its target declares.
+    PhiArgCount { target: BlockId, expected: u32, found: u32 },
+
+    // A phi argument's type differs from the type of
+    // the target phi it is passed to.
+    PhiArgType {
+        target: BlockId, index: u32,
+        expected: IrTypeId, found: IrTypeId
+    },
+
+    // A back edge targets a block that is not a loop
+    // header.
+    BackEdgeToNonLoop { target: BlockId },
+
+    // A back edge targets a loop header that does not
+    // dominate the edge's source.
+    IrreducibleLoop { target: BlockId },
+
+    // A loop header was not completed as a loop, or
+    // has no back edge.
+    MalformedLoop,
+
+    // A pointer constant does not fit the pointers of
+    // the graph's target.
+    PtrWidth { value: u64, ptr_bytes: u32 }
+}
+
+impl VerifyError {
+    fn new(block: BlockId, instr: Option<InstrId>,
+           kind: VerifyErrorKind)
+      -> VerifyError
+    {
+        VerifyError { block, instr, kind }
+    }
+
+    pub fn block(&self) -> BlockId { self.block }
+    pub fn instr(&self) -> Option<InstrId> { self.instr }
+    pub fn kind(&self) -> &VerifyErrorKind { &self.kind }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "{}", self.block)?;
+        if let Some(instr) = self.instr {
+            write!(f, " {}", instr)?;
+        }
+        write!(f, ": {}", self.kind)
+    }
+}
+
+impl fmt::Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match self {
+          VerifyErrorKind::UnfinishedBlock =>
+            write!(f, "block never finished"),
+          VerifyErrorKind::MissingTerminator =>
+            write!(f, "block does not end in an end instruction"),
+          VerifyErrorKind::EarlyTerminator =>
+            write!(f, "end instruction before end of block"),
+          VerifyErrorKind::BadOperand { operand } =>
+            write!(f, "operand {} is not an instruction", operand),
+          VerifyErrorKind::NonValueOperand { operand } =>
+            write!(f, "operand {} defines no value", operand),
+          VerifyErrorKind::UndominatedOperand { operand } =>
+            write!(f, "operand {} does not dominate its use", operand),
+          VerifyErrorKind::MisplacedPhi =>
+            write!(f, "phi after non-phi instruction"),
+          VerifyErrorKind::PhiCount { expected, found } =>
+            write!(f, "block declares {} phis, has {}",
+                   expected, found),
+          VerifyErrorKind::BadTarget { target } =>
+            write!(f, "target {} is not declared", target),
+          VerifyErrorKind::PhiArgCount { target, expected, found } =>
+            write!(f, "target {} expects {} phi args, got {}",
+                   target, expected, found),
+          VerifyErrorKind::PhiArgType {
+            target, index, expected, found
+          } =>
+            write!(f, "target {} phi {} expects {}, got {}",
+                   target, index, expected.as_str(), found.as_str()),
+          VerifyErrorKind::BackEdgeToNonLoop { target } =>
+            write!(f, "back edge to non-loop block {}", target),
+          VerifyErrorKind::IrreducibleLoop { target } =>
+            write!(f, "back edge to non-dominating header {}", target),
+          VerifyErrorKind::MalformedLoop =>
+            write!(f, "loop header without a completed back edge"),
+          VerifyErrorKind::PtrWidth { value, ptr_bytes } =>
+            write!(f, "pointer {:#x} wider than {} bytes",
+                   value, ptr_bytes)
+        }
+    }
+}
+
+/**
+ * Checks the structure of a finished graph, collecting
+ * every problem found rather than stopping at the
+ * first.
+ */
+pub(crate) struct Verifier<'a> {
+    graph: &'a Graph,
+
+    // Every instruction in the graph with its block
+    // and the type of value it defines, sorted by
+    // instruction id.
+    instrs: Vec<(InstrId, BlockId, Option<IrTypeId>)>,
+
+    // The types of each block's leading phis, by
+    // block id.
+    phi_types: Vec<Vec<IrTypeId>>,
+
+    errors: Vec<VerifyError>
+}
+
+impl<'a> Verifier<'a> {
+    pub(crate) fn new(graph: &'a Graph) -> Verifier<'a> {
+        let phi_types =
+          vec![Vec::new(); graph.block_store().total_blocks()];
+        Verifier {
+            graph, instrs: Vec::new(), phi_types,
+            errors: Vec::new()
+        }
+    }
+
+    pub(crate) fn run(mut self) -> Result<(), Vec<VerifyError>> {
+        let graph = self.graph;
+
+        // Blocks must be finished before any of their
+        // contents can be looked at.
+        let mut complete = true;
+        for block in graph.block_store().iter_blocks() {
+            if !block.has_finished() {
+                self.error(block.id(), None,
+                           VerifyErrorKind::UnfinishedBlock);
+                complete = false;
+            }
+        }
+        if !complete {
+            return Err(self.errors);
+        }
+
+        for block in graph.block_store().iter_rpo_blocks() {
+            self.check_terminator(block);
+        }
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+        self.instrs.sort_unstable_by_key(|&(id, _, _)| id);
+
+        let cfg = Cfg::compute(graph);
+        let dom = DomTree::compute(graph, &cfg);
+        for block in graph.block_store().iter_rpo_blocks() {
+            for info in graph.block_instrs(block) {
+                self.check_operands(block, &info, &dom);
+                self.check_targets(block, &info, &dom);
+                self.check_ptr_width(block, &info);
+            }
+        }
+        self.check_loops(&cfg, &dom);
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn error(&mut self, block: BlockId, instr: Option<InstrId>,
+             kind: VerifyErrorKind)
+    {
+        self.errors.push(VerifyError::new(block, instr, kind));
+    }
+
+    // Walk the block's instructions up to its recorded
+    // last instruction, checking that only that one is
+    // an end instruction, and that phis lead the block.
+    fn check_terminator(&mut self, block: &Block) {
+        let graph = self.graph;
+        let bi = block.id().as_u32() as usize;
+        let last = block.last_instr();
+        let mut leading = true;
+        for info in graph.block_instrs(block) {
+            let id = info.defn().instr_id();
+            let ty = if info.op().defines_value() {
+                info.op().out_type()
+            } else {
+                None
+            };
+            self.instrs.push((id, block.id(), ty));
+
+            match info.op() {
+              Op::Phi(_) if leading => {
+                self.phi_types[bi].push(ty.unwrap());
+              }
+              Op::Phi(_) => {
+                self.error(block.id(), Some(id),
+                           VerifyErrorKind::MisplacedPhi);
+              }
+              _ => leading = false
+            }
+
+            if id > last {
+                self.error(block.id(), Some(last),
+                           VerifyErrorKind::MissingTerminator);
+                break;
+            }
+            if id == last {
+                if !info.op().terminal() {
+                    self.error(block.id(), Some(id),
+                               VerifyErrorKind::MissingTerminator);
+                }
+                self.check_target_blocks(block, &info);
+                break;
+            }
+            if info.op().terminal() {
+                self.error(block.id(), Some(id),
+                           VerifyErrorKind::EarlyTerminator);
+                break;
+            }
+        }
+
+        let expected = block.num_phis();
+        let found = self.phi_types[bi].len() as u32;
+        if expected != found {
+            self.error(block.id(), None,
+              VerifyErrorKind::PhiCount { expected, found });
+        }
+    }
+
+    fn check_target_blocks(&mut self, block: &Block,
+                           info: &InstrInfo)
+    {
+        let nblocks = self.graph.block_store().total_blocks();
+        for target in info.targets_iter() {
+            let to = target.block_id();
+            if (to.as_u32() as usize) >= nblocks {
+                self.error(block.id(), Some(info.defn().instr_id()),
+                  VerifyErrorKind::BadTarget { target: to });
+            }
+        }
+    }
+
+    // Find the block defining an operand and the type
+    // it defines, reporting an error if it is not a
+    // value-defining instruction.
+    fn operand_def(&mut self, block: &Block, instr: InstrId,
+                   operand: InstrId)
+      -> Option<(BlockId, IrTypeId)>
+    {
+        let found = self.instrs
+                        .binary_search_by_key(&operand, |e| e.0);
+        let kind = match found {
+          Ok(i) if self.instrs[i].2.is_some() => {
+            let (_, def_block, ty) = self.instrs[i];
+            return Some((def_block, ty.unwrap()));
+          }
+          Ok(_) => VerifyErrorKind::NonValueOperand { operand },
+          Err(_) => VerifyErrorKind::BadOperand { operand }
+        };
+        self.error(block.id(), Some(instr), kind);
+        None
+    }
+
+    // Check the instruction's operands, and the values
+    // of its snapshot, which are used where it is.
+    fn check_operands(&mut self, block: &Block,
+                      info: &InstrInfo, dom: &DomTree)
+    {
+        let id = info.defn().instr_id();
+        for operand in info.inputs_iter() {
+            self.check_use(block, id, operand.instr_id(), dom);
+        }
+        if let Some(snapshot) = self.graph.snapshot(id) {
+            for &(_, value) in snapshot.entries() {
+                self.check_use(block, id, value, dom);
+            }
+        }
+    }
+
+    // Check that a pointer constant fits the target's
+    // pointers.
+    fn check_ptr_width(&mut self, block: &Block, info: &InstrInfo) {
+        let target = self.graph.target();
+        if let Op::Const(ConstOp::PtrInt(value)) = *info.op() {
+            if value > target.max_ptr() {
+                let ptr_bytes = target.ptr_bytes();
+                self.error(block.id(), Some(info.defn().instr_id()),
+                  VerifyErrorKind::PtrWidth { value, ptr_bytes });
+            }
+        }
+    }
+
+    fn check_use(&mut self, block: &Block, id: InstrId,
+                 operand: InstrId, dom: &DomTree)
+    {
+        let def_block = match self.operand_def(block, id, operand) {
+          Some((b, _)) => b,
+          None => return
+        };
+        if !dom.is_reachable(block.id()) {
+            return;
+        }
+        if operand == id
+           || !dom.instr_dominates(def_block, operand,
+                                   block.id(), id)
+        {
+            self.error(block.id(), Some(id),
+              VerifyErrorKind::UndominatedOperand { operand });
+        }
+    }
+
+    // Check the targets of an end instruction, and the
+    // phi arguments passed along them.  Phi arguments
+    // are used at the end of the passing block.
+    fn check_targets(&mut self, block: &Block,
+                     info: &InstrInfo, dom: &DomTree)
+    {
+        let graph = self.graph;
+        let id = info.defn().instr_id();
+        for target in info.targets_iter() {
+            let to = target.block_id();
+            let expected = graph.block(to).num_phis();
+            let found = target.phi_args().count() as u32;
+            if expected != found {
+                self.error(block.id(), Some(id),
+                  VerifyErrorKind::PhiArgCount {
+                    target: to, expected, found
+                  });
+            }
+
+            for (index, arg) in target.phi_args().enumerate() {
+                let arg = arg.instr_id();
+                let (def_block, found) =
+                  match self.operand_def(block, id, arg) {
+                    Some(def) => def,
+                    None => continue
+                  };
+                let phi_types = &self.phi_types[to.as_u32() as usize];
+                if let Some(&expected) = phi_types.get(index) {
+                    if expected != found {
+                        self.error(block.id(), Some(id),
+                          VerifyErrorKind::PhiArgType {
+                            target: to, index: index as u32,
+                            expected, found
+                          });
+                    }
+                }
+                if dom.is_reachable(block.id())
+                   && !dom.instr_dominates(def_block, arg,
+                                           block.id(), id)
+                {
+                    self.error(block.id(), Some(id),
+                      VerifyErrorKind::UndominatedOperand {
+                        operand: arg
+                      });
+                }
+            }
+        }
+    }
+
+    // Back edges (edges to a block entered no later than
+    // their source) must target loop headers dominating
+    // their source, and every loop header needs one.
+    fn check_loops(&mut self, cfg: &Cfg, dom: &DomTree) {
+        let graph = self.graph;
+        let mut has_back_edge =
+          vec![false; graph.block_store().total_blocks()];
+
+        for &b in cfg.rpo() {
+            let block = graph.block(b);
+            let end = block.last_instr();
+            for &to in cfg.succs(b) {
+                let target = graph.block(to);
+                if target.order() > block.order() {
+                    continue;
+                }
+                has_back_edge[to.as_u32() as usize] = true;
+                if !target.is_loop() {
+                    self.error(b, Some(end),
+                      VerifyErrorKind::BackEdgeToNonLoop { target: to });
+                } else if !dom.dominates(to, b) {
+                    self.error(b, Some(end),
+                      VerifyErrorKind::IrreducibleLoop { target: to });
+                }
+            }
+        }
+
+        for block in graph.block_store().iter_rpo_blocks() {
+            if !block.is_loop() {
+                continue;
+            }
+            let bi = block.id().as_u32() as usize;
+            if !block.has_loop_complete() || !has_back_edge[bi] {
+                self.error(block.id(), None,
+                           VerifyErrorKind::MalformedLoop);
+            }
+        }
+    }
+}
@@ -0,0 +1,264 @@
+
+use std::fmt;
+use std::ops::Index;
+
+use crate::block::{ Block, BlockId };
+use crate::instr::{ InstrId, InstrInfo, InstrInputs, InstrTargets };
+use crate::graph::Graph;
+use crate::defn::Defn;
+use crate::decode::DecodedInstr;
+use crate::ops::{ Op, Opcode };
+use crate::ir_types::IrTypeId;
+
+/**
+ * A safe, read-only view of a block of a graph.
+ *
+ * Views can only be obtained through bounds-checked
+ * lookups on the graph, so everything reachable from
+ * them names real blocks and instructions.
+ */
+#[derive(Clone, Copy)]
+pub struct BlockView<'g> {
+    graph: &'g Graph,
+    block: &'g Block
+}
+
+impl<'g> BlockView<'g> {
+    pub(crate) fn new(graph: &'g Graph, block: &'g Block)
+      -> BlockView<'g>
+    {
+        debug_assert!(block.has_finished());
+        BlockView { graph, block }
+    }
+
+    pub fn id(&self) -> BlockId { self.block.id() }
+    pub fn num_phis(&self) -> u32 { self.block.num_phis() }
+    pub fn is_start(&self) -> bool { self.block.is_start() }
+    pub fn is_loop(&self) -> bool { self.block.is_loop() }
+    pub fn is_cold(&self) -> bool { self.block.is_cold() }
+
+    // The number of edges into the block.
+    pub fn input_edges(&self) -> u32 { self.block.input_edges() }
+
+    // The position of the block in specification
+    // order (RPO).
+    pub fn order(&self) -> u32 { self.block.order() }
+
+    pub fn first_instr(&self) -> InstrId {
+        self.block.first_instr()
+    }
+    pub fn last_instr(&self) -> InstrId {
+        self.block.last_instr()
+    }
+
+    // Iterate the instructions of the block, ending
+    // with its end instruction.
+    pub fn instrs(&self) -> impl Iterator<Item=InstrView<'g>> {
+        self.graph.block_instrs(self.block).map(InstrView::new)
+    }
+
+    // The end instruction of the block.
+    pub fn end(&self) -> InstrView<'g> {
+        InstrView::new(self.graph.block_end(self.block))
+    }
+
+    // The successor blocks, in target order, with
+    // repeats for multiple edges to the same block.
+    pub fn succs(&self) -> Vec<BlockId> {
+        self.end().targets().map(|t| t.block()).collect()
+    }
+}
+
+/** A safe, read-only view of an instruction. */
+pub struct InstrView<'g> {
+    info: InstrInfo<'g>
+}
+
+impl<'g> InstrView<'g> {
+    pub(crate) fn new(info: InstrInfo<'g>) -> InstrView<'g> {
+        InstrView { info }
+    }
+
+    pub fn id(&self) -> InstrId { self.info.defn().instr_id() }
+    pub(crate) fn op(&self) -> &Op { self.info.op() }
+    pub fn opcode(&self) -> Opcode { self.info.op().opcode() }
+    pub fn is_terminal(&self) -> bool { self.info.op().terminal() }
+    pub fn is_effectful(&self) -> bool {
+        self.info.op().effectful()
+    }
+
+    // The type of value the instruction defines, if it
+    // defines one.
+    pub fn out_type(&self) -> Option<IrTypeId> {
+        if self.info.op().defines_value() {
+            self.info.op().out_type()
+        } else {
+            None
+        }
+    }
+
+    pub fn num_inputs(&self) -> u32 { self.info.op().num_inputs() }
+    pub fn inputs(&self) -> InputIds<'g> {
+        InputIds { inputs: self.info.inputs_iter() }
+    }
+
+    // The operand at `index`, found in constant time in
+    // a graph with fixed-width operands.
+    pub fn input(&self, index: u32) -> Option<InstrId> {
+        self.inputs().nth(index as usize)
+    }
+
+    // The targets of an end instruction.  Other
+    // instructions have none.
+    pub fn targets(&self) -> TargetViews<'g> {
+        TargetViews { targets: self.info.targets_iter() }
+    }
+}
+
+impl<'g> fmt::Display for InstrView<'g> {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "{} {}(", self.id(), self.info.op())?;
+        for (i, inp) in self.inputs().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", inp.offset())?;
+        }
+        write!(f, ")")
+    }
+}
+
+/** A target of an end instruction. */
+pub struct TargetView<'g> {
+    block: BlockId,
+    phi_args: InstrInputs<'g>
+}
+
+impl<'g> TargetView<'g> {
+    pub fn block(&self) -> BlockId { self.block }
+
+    // The arguments passed to the target's phis.
+    pub fn phi_args(&self) -> InputIds<'g> {
+        InputIds { inputs: self.phi_args.clone() }
+    }
+}
+
+/** Iterates the operand ids of an instruction. */
+#[derive(Clone)]
+pub struct InputIds<'g> {
+    inputs: InstrInputs<'g>
+}
+
+impl<'g> Iterator for InputIds<'g> {
+    type Item = InstrId;
+    fn next(&mut self) -> Option<InstrId> {
+        self.inputs.next().map(|d| d.instr_id())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inputs.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<InstrId> {
+        self.inputs.nth(n).map(|d| d.instr_id())
+    }
+}
+
+impl<'g> ExactSizeIterator for InputIds<'g> {}
+
+/** Iterates the targets of an end instruction. */
+pub struct TargetViews<'g> {
+    targets: InstrTargets<'g>
+}
+
+impl<'g> Iterator for TargetViews<'g> {
+    type Item = TargetView<'g>;
+    fn next(&mut self) -> Option<TargetView<'g>> {
+        self.targets.next().map(|t| TargetView {
+            block: t.block_id(),
+            phi_args: t.phi_args()
+        })
+    }
+}
+
+impl Graph {
+    pub fn num_blocks(&self) -> u32 {
+        self.block_store().total_blocks() as u32
+    }
+    pub fn start_block_id(&self) -> BlockId {
+        self.block_store().start_block_id()
+    }
+
+    // Look up a block, or None if the id is out of
+    // range.
+    pub fn block_view(&self, id: BlockId) -> Option<BlockView<'_>> {
+        if id.as_u32() >= self.num_blocks() {
+            return None;
+        }
+        Some(BlockView::new(self, self.block(id)))
+    }
+
+    // Iterate the blocks in declaration (id) order.
+    pub fn block_views(&self) -> impl Iterator<Item=BlockView<'_>> {
+        self.block_store().iter_blocks()
+            .map(move |b| BlockView::new(self, b))
+    }
+
+    // Iterate the blocks in specification order (RPO).
+    pub fn rpo_block_views(&self)
+      -> impl Iterator<Item=BlockView<'_>>
+    {
+        self.block_store().iter_rpo_blocks()
+            .map(move |b| BlockView::new(self, b))
+    }
+
+    // Look up an instruction, or None if the id does
+    // not name the start of an instruction.  Unless the
+    // instruction side-index is built, this walks the
+    // block holding the offset, so prefer iterating
+    // blocks where possible.
+    pub fn instr_view(&self, id: InstrId) -> Option<InstrView<'_>> {
+        if let Some(index) = self.instr_index() {
+            if !index.contains(id) {
+                return None;
+            }
+            return Some(InstrView::new(self.instr(id)));
+        }
+        let block = self.block_store().iter_blocks().find(|b| {
+            b.first_instr() <= id && id <= b.last_instr()
+        }) ?;
+        self.block_instrs(block)
+            .find(|info| info.defn().instr_id() == id)
+            .map(InstrView::new)
+    }
+}
+
+/**
+ * Index a graph by instruction id, for tools that
+ * inspect a few instructions.  The first lookup
+ * decodes the whole stream into a table the graph
+ * keeps until its stream is next written, so lookups
+ * are binary searches after it.
+ *
+ * Panics if the id does not name the start of an
+ * instruction; `instr_view` is the checked lookup.
+ */
+impl Index<InstrId> for Graph {
+    type Output = DecodedInstr;
+    fn index(&self, id: InstrId) -> &DecodedInstr {
+        let instrs = self.decoded_instrs();
+        match instrs.binary_search_by_key(&id, |i| i.id()) {
+          Ok(idx) => &instrs[idx],
+          Err(_) => panic!("no instruction at {}", id)
+        }
+    }
+}
+
+impl<'a> Index<Defn<'a>> for Graph {
+    type Output = DecodedInstr;
+    fn index(&self, defn: Defn<'a>) -> &DecodedInstr {
+        &self[defn.instr_id()]
+    }
+}
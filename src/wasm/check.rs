@@ -0,0 +1,245 @@
+
+use std::collections::BTreeSet;
+
+use crate::ir_types::IrTypeId;
+use crate::wasm::{ WasmError, WasmErrorKind };
+use crate::wasm::reader::{ FuncBody, Item, Construct, WasmOp };
+
+// A label being checked: the type of value a branch
+// to it carries, whether a branch targets it, and the
+// locals assigned within its construct so far.
+struct Label {
+    branch_type: Option<IrTypeId>,
+    targeted: bool,
+    assigned: BTreeSet<u32>
+}
+
+struct Checker<'a> {
+    locals: &'a [IrTypeId],
+    result: IrTypeId,
+    stack: Vec<IrTypeId>,
+    labels: Vec<Label>
+}
+
+// Check the body, filling in each construct's assigned
+// locals and continuation reachability.
+pub(crate) fn check_body(func: &mut FuncBody, result: IrTypeId)
+  -> Result<(), WasmError>
+{
+    let mut checker = Checker {
+        locals: &func.locals,
+        result,
+        stack: Vec::new(),
+        labels: Vec::new()
+    };
+    checker.labels.push(Label::new(Some(result)));
+    if checker.seq(&mut func.code) ? {
+        checker.check_end(func.end, 0, Some(result)) ?;
+    }
+    Ok(())
+}
+
+impl Label {
+    fn new(branch_type: Option<IrTypeId>) -> Label {
+        Label {
+            branch_type,
+            targeted: false,
+            assigned: BTreeSet::new()
+        }
+    }
+}
+
+impl<'a> Checker<'a> {
+    fn pop(&mut self, offset: usize) -> Result<IrTypeId, WasmError> {
+        self.stack.pop().ok_or(
+          WasmError::new(offset, WasmErrorKind::StackHeight))
+    }
+
+    fn pop_expect(&mut self, offset: usize, ty: IrTypeId)
+      -> Result<(), WasmError>
+    {
+        if self.pop(offset) ? != ty {
+            return Err(WasmError::new(offset,
+                                      WasmErrorKind::TypeMismatch));
+        }
+        Ok(())
+    }
+
+    // Check the values a branch to the label at `depth`
+    // carries, and mark the label as targeted.
+    fn check_branch(&mut self, offset: usize, depth: u32)
+      -> Result<(), WasmError>
+    {
+        let n = self.labels.len();
+        if (depth as usize) >= n {
+            let kind = WasmErrorKind::BadLabel { depth };
+            return Err(WasmError::new(offset, kind));
+        }
+        let label = &mut self.labels[n - 1 - depth as usize];
+        label.targeted = true;
+        if let Some(ty) = label.branch_type {
+            match self.stack.last() {
+              Some(&top) if top == ty => {}
+              Some(_) => return Err(WasmError::new(offset,
+                                      WasmErrorKind::TypeMismatch)),
+              None => return Err(WasmError::new(offset,
+                                   WasmErrorKind::StackHeight))
+            }
+        }
+        Ok(())
+    }
+
+    // Check that the stack holds exactly the construct's
+    // result above `height`.
+    fn check_end(&mut self, offset: usize, height: usize,
+                 result: Option<IrTypeId>)
+      -> Result<(), WasmError>
+    {
+        let expected = height + result.iter().count();
+        if self.stack.len() != expected {
+            return Err(WasmError::new(offset,
+                                      WasmErrorKind::StackHeight));
+        }
+        if let Some(ty) = result {
+            if self.stack[height] != ty {
+                return Err(WasmError::new(offset,
+                                          WasmErrorKind::TypeMismatch));
+            }
+        }
+        Ok(())
+    }
+
+    fn local(&self, offset: usize, index: u32)
+      -> Result<IrTypeId, WasmError>
+    {
+        self.locals.get(index as usize).cloned().ok_or(
+          WasmError::new(offset, WasmErrorKind::BadLocal { index }))
+    }
+
+    // Check a sequence, returning whether its end is
+    // reachable.
+    fn seq(&mut self, items: &mut [Item]) -> Result<bool, WasmError> {
+        for item in items {
+            let reachable = match item {
+              Item::Op { offset, op } => self.op(*offset, op) ?,
+              Item::Block(c) => self.block(c, false) ?,
+              Item::Loop(c) => self.block(c, true) ?,
+              Item::If(c) => self.if_else(c) ?
+            };
+            if !reachable {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Check a `block` or `loop`.
+    fn block(&mut self, c: &mut Construct, is_loop: bool)
+      -> Result<bool, WasmError>
+    {
+        let height = self.stack.len();
+        let branch_type = if is_loop { None } else { c.result };
+        self.labels.push(Label::new(branch_type));
+        let falls = self.seq(&mut c.body) ?;
+        if falls {
+            self.check_end(c.end, height, c.result) ?;
+        }
+        self.finish(c, height, falls, is_loop);
+        Ok(c.live_cont)
+    }
+
+    fn if_else(&mut self, c: &mut Construct)
+      -> Result<bool, WasmError>
+    {
+        self.pop_expect(c.offset, IrTypeId::Int32) ?;
+        let height = self.stack.len();
+        self.labels.push(Label::new(c.result));
+        let then_falls = self.seq(&mut c.body) ?;
+        if then_falls {
+            self.check_end(c.end, height, c.result) ?;
+        }
+
+        self.stack.truncate(height);
+        let else_falls = self.seq(&mut c.else_body) ?;
+        if else_falls {
+            self.check_end(c.end, height, c.result) ?;
+        }
+        self.finish(c, height, then_falls || else_falls, false);
+        Ok(c.live_cont)
+    }
+
+    // Pop a construct's label, recording what was found
+    // for it, and leave its result on the stack.  The
+    // code after a loop is reached only by falling out
+    // of its body, as branches to its label go to its
+    // header.
+    fn finish(&mut self, c: &mut Construct, height: usize,
+              falls: bool, is_loop: bool)
+    {
+        let label = self.labels.pop().unwrap();
+        c.assigned = label.assigned.into_iter().collect();
+        c.targeted = label.targeted;
+        c.live_cont = falls || (label.targeted && !is_loop);
+        self.stack.truncate(height);
+        self.stack.extend(c.result);
+    }
+
+    // Check an instruction, returning whether the code
+    // after it is reachable.
+    fn op(&mut self, offset: usize, op: &WasmOp)
+      -> Result<bool, WasmError>
+    {
+        use crate::ir_types::IrTypeId::{ Int32, Int64 };
+        match *op {
+          WasmOp::Unreachable => return Ok(false),
+          WasmOp::Br(depth) => {
+            self.check_branch(offset, depth) ?;
+            return Ok(false);
+          }
+          WasmOp::BrIf(depth) => {
+            self.pop_expect(offset, Int32) ?;
+            self.check_branch(offset, depth) ?;
+          }
+          WasmOp::BrTable(ref depths, default) => {
+            self.pop_expect(offset, Int32) ?;
+            for &depth in depths.iter().chain(Some(&default)) {
+                self.check_branch(offset, depth) ?;
+            }
+            return Ok(false);
+          }
+          WasmOp::Return => {
+            let result = self.result;
+            self.pop_expect(offset, result) ?;
+            return Ok(false);
+          }
+          WasmOp::Drop => { self.pop(offset) ?; }
+          WasmOp::LocalGet(index) => {
+            let ty = self.local(offset, index) ?;
+            self.stack.push(ty);
+          }
+          WasmOp::LocalSet(index) | WasmOp::LocalTee(index) => {
+            let ty = self.local(offset, index) ?;
+            self.pop_expect(offset, ty) ?;
+            if let WasmOp::LocalTee(_) = *op {
+                self.stack.push(ty);
+            }
+            for label in &mut self.labels[1 ..] {
+                label.assigned.insert(index);
+            }
+          }
+          WasmOp::Const32(_) => self.stack.push(Int32),
+          WasmOp::Const64(_) => self.stack.push(Int64),
+          WasmOp::Eqz(ty) => {
+            self.pop_expect(offset, ty) ?;
+            self.stack.push(Int32);
+          }
+          WasmOp::Cmp(_, ty) | WasmOp::Bini(_, ty) => {
+            self.pop_expect(offset, ty) ?;
+            self.pop_expect(offset, ty) ?;
+            let is_cmp = matches!(*op, WasmOp::Cmp(..));
+            self.stack.push(if is_cmp { Int32 } else { ty });
+          }
+        }
+        Ok(true)
+    }
+}
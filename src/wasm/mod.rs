@@ -0,0 +1,184 @@
+
+/*
+ * Import of WebAssembly function bodies.
+ *
+ * A body (its local declarations and code, as found in
+ * an entry of the code section) is imported in three
+ * passes: the reader decodes it into a tree of
+ * structured control constructs, the checker validates
+ * operand types and branch labels and works out which
+ * locals each construct assigns and whether its
+ * continuation is reachable, and the translator drives
+ * a `Builder` over the checked tree.
+ *
+ * The translation keeps locals in SSA form.  The
+ * continuation of each `block`, `loop` and `if` takes
+ * block parameters for the locals assigned within the
+ * construct and for its result, and a loop header
+ * takes parameters for the locals its body assigns.
+ *
+ * Only i32 and i64 values are supported, with the
+ * control instructions, locals, constants, and the
+ * eqz, signed comparison, add, sub, mul, and, or and
+ * xor instructions.  Code that follows an unconditional
+ * branch within its block is decoded but neither
+ * checked nor translated.
+ */
+
+mod reader;
+mod check;
+mod translate;
+
+use std::error::Error;
+use std::fmt;
+
+use crate::graph::Graph;
+use crate::ir_types::IrTypeId;
+
+/**
+ * A reason a wasm function body could not be
+ * imported, with the offset into the body at which it
+ * was found.
+ */
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub struct WasmError {
+    offset: usize,
+    kind: WasmErrorKind
+}
+
+#[derive(Clone, Debug)]
+#[derive(PartialEq, Eq)]
+pub enum WasmErrorKind {
+    // The function signature has a type other than
+    // i32 or i64.
+    BadSignature,
+
+    // The body ended in the middle of an item.
+    Truncated,
+
+    // A leb128 value does not fit the field it encodes.
+    BadLeb128,
+
+    // A byte names no value type, or an unsupported one.
+    BadValType { code: u8 },
+
+    // A block type is a type index or an unsupported
+    // value type.
+    BadBlockType,
+
+    // An instruction outside the supported subset.
+    UnsupportedOp { code: u8 },
+
+    // An `else` outside an `if`.
+    UnexpectedElse,
+
+    // Control constructs nest too deeply.
+    TooDeep,
+
+    // The body declares too many locals.
+    TooManyLocals,
+
+    // Bytes follow the body's final `end`.
+    TrailingBytes,
+
+    // A local index names no local.
+    BadLocal { index: u32 },
+
+    // A branch names no enclosing label.
+    BadLabel { depth: u32 },
+
+    // An operand has the wrong type.
+    TypeMismatch,
+
+    // The operand stack holds too few values for an
+    // instruction, or the wrong number at the end of a
+    // construct.
+    StackHeight
+}
+
+// Control constructs nesting deeper than this are
+// rejected, bounding the recursion of each pass.
+pub(crate) const MAX_NESTING: u32 = 256;
+
+// At most this many locals, parameters included.
+pub(crate) const MAX_LOCALS: u32 = 50_000;
+
+impl WasmError {
+    pub(crate) fn new(offset: usize, kind: WasmErrorKind)
+      -> WasmError
+    {
+        WasmError { offset, kind }
+    }
+
+    pub fn offset(&self) -> usize { self.offset }
+    pub fn kind(&self) -> &WasmErrorKind { &self.kind }
+}
+
+impl fmt::Display for WasmError {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        write!(f, "{} at offset {}", self.kind, self.offset)
+    }
+}
+
+impl fmt::Display for WasmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter)
+      -> Result<(), fmt::Error>
+    {
+        match *self {
+          WasmErrorKind::BadSignature =>
+            write!(f, "unsupported signature type"),
+          WasmErrorKind::Truncated => write!(f, "truncated body"),
+          WasmErrorKind::BadLeb128 => write!(f, "bad leb128 value"),
+          WasmErrorKind::BadValType { code } =>
+            write!(f, "unsupported value type {:#04x}", code),
+          WasmErrorKind::BadBlockType =>
+            write!(f, "unsupported block type"),
+          WasmErrorKind::UnsupportedOp { code } =>
+            write!(f, "unsupported instruction {:#04x}", code),
+          WasmErrorKind::UnexpectedElse =>
+            write!(f, "else outside of if"),
+          WasmErrorKind::TooDeep =>
+            write!(f, "constructs nest too deeply"),
+          WasmErrorKind::TooManyLocals =>
+            write!(f, "too many locals"),
+          WasmErrorKind::TrailingBytes =>
+            write!(f, "trailing bytes"),
+          WasmErrorKind::BadLocal { index } =>
+            write!(f, "no local {}", index),
+          WasmErrorKind::BadLabel { depth } =>
+            write!(f, "no label at depth {}", depth),
+          WasmErrorKind::TypeMismatch => write!(f, "type mismatch"),
+          WasmErrorKind::StackHeight =>
+            write!(f, "wrong number of operands")
+        }
+    }
+}
+
+impl Error for WasmError {}
+
+impl Graph {
+    /**
+     * Import a wasm function body, given the function's
+     * parameter and result types, which must be
+     * `Int32` or `Int64`.  One result is required.
+     */
+    pub fn from_wasm_body(params: &[IrTypeId],
+                          result: IrTypeId,
+                          body: &[u8])
+      -> Result<Graph, WasmError>
+    {
+        let wasm_type = |ty: &IrTypeId| {
+            matches!(ty, IrTypeId::Int32 | IrTypeId::Int64)
+        };
+        if !params.iter().all(wasm_type) || !wasm_type(&result) {
+            return Err(WasmError::new(0, WasmErrorKind::BadSignature));
+        }
+
+        let mut func = reader::read_body(params, body) ?;
+        check::check_body(&mut func, result) ?;
+        Ok(translate::translate_body(&func, params.len(), result))
+    }
+}
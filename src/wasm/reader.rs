@@ -0,0 +1,295 @@
+
+use crate::leb128::{ self, Leb128Error };
+use crate::ops::{ CmpKind, BiniKind };
+use crate::ir_types::IrTypeId;
+use crate::wasm::{
+    WasmError, WasmErrorKind, MAX_NESTING, MAX_LOCALS
+};
+
+/** A decoded function body. */
+pub(crate) struct FuncBody {
+    // The type of each local, parameters first.
+    pub(crate) locals: Vec<IrTypeId>,
+    pub(crate) code: Vec<Item>,
+
+    // The offset of the body's final `end`.
+    pub(crate) end: usize
+}
+
+pub(crate) enum Item {
+    Op { offset: usize, op: WasmOp },
+    Block(Construct),
+    Loop(Construct),
+    If(Construct)
+}
+
+/** A `block`, `loop` or `if` and its contents. */
+pub(crate) struct Construct {
+    pub(crate) offset: usize,
+    pub(crate) result: Option<IrTypeId>,
+    pub(crate) body: Vec<Item>,
+
+    // The arm after an `if`'s `else`, empty where
+    // there is none.
+    pub(crate) else_body: Vec<Item>,
+
+    // The offset of the construct's `end`.
+    pub(crate) end: usize,
+
+    // Filled in by the checker: the locals assigned
+    // within the construct, in index order, whether a
+    // branch targets its label, and whether the code
+    // after it is reachable.
+    pub(crate) assigned: Vec<u32>,
+    pub(crate) targeted: bool,
+    pub(crate) live_cont: bool
+}
+
+/** A supported non-structured instruction. */
+#[derive(Clone)]
+pub(crate) enum WasmOp {
+    Unreachable,
+    Br(u32),
+    BrIf(u32),
+    BrTable(Vec<u32>, u32),
+    Return,
+    Drop,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    Const32(u32),
+    Const64(u64),
+    Eqz(IrTypeId),
+    Cmp(CmpKind, IrTypeId),
+    Bini(BiniKind, IrTypeId)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize
+}
+
+// How a sequence of instructions ended.
+#[derive(PartialEq, Eq)]
+enum SeqEnd { End, Else }
+
+pub(crate) fn read_body(params: &[IrTypeId], bytes: &[u8])
+  -> Result<FuncBody, WasmError>
+{
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut locals = params.to_vec();
+    let num_decls = reader.read_u32() ?;
+    for _ in 0 .. num_decls {
+        let offset = reader.pos;
+        let count = reader.read_u32() ?;
+        let ty = reader.read_val_type() ?;
+        if (locals.len() as u64) + (count as u64)
+             > (MAX_LOCALS as u64)
+        {
+            return Err(WasmError::new(offset,
+                                      WasmErrorKind::TooManyLocals));
+        }
+        locals.extend((0 .. count).map(|_| ty));
+    }
+
+    let (code, seq_end) = reader.read_seq(0) ?;
+    let end = reader.pos - 1;
+    if seq_end == SeqEnd::Else {
+        return Err(WasmError::new(end, WasmErrorKind::UnexpectedElse));
+    }
+    if reader.pos < bytes.len() {
+        return Err(WasmError::new(reader.pos,
+                                  WasmErrorKind::TrailingBytes));
+    }
+    Ok(FuncBody { locals, code, end })
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, offset: usize, kind: WasmErrorKind)
+      -> WasmError
+    {
+        WasmError::new(offset, kind)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, WasmError> {
+        let b = *self.bytes.get(self.pos).ok_or_else(|| {
+            self.error(self.pos, WasmErrorKind::Truncated)
+        }) ?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn leb_error(&self, err: Leb128Error) -> WasmError {
+        match err {
+          Leb128Error::Truncated =>
+            self.error(self.pos, WasmErrorKind::Truncated),
+          _ => self.error(self.pos, WasmErrorKind::BadLeb128)
+        }
+    }
+
+    fn read_u32(&mut self) -> Result<u32, WasmError> {
+        let rest = &self.bytes[self.pos ..];
+        let (nb, v) = leb128::read_leb128u_bounded(rest, 32)
+          .map_err(|err| self.leb_error(err)) ?;
+        self.pos += nb;
+        Ok(v as u32)
+    }
+
+    fn read_s64(&mut self, bits: u32) -> Result<i64, WasmError> {
+        let rest = &self.bytes[self.pos ..];
+        let (nb, v) = leb128::read_leb128s_bounded(rest, bits)
+          .map_err(|err| self.leb_error(err)) ?;
+        self.pos += nb;
+        Ok(v)
+    }
+
+    fn read_val_type(&mut self) -> Result<IrTypeId, WasmError> {
+        let offset = self.pos;
+        match self.read_u8() ? {
+          0x7F => Ok(IrTypeId::Int32),
+          0x7E => Ok(IrTypeId::Int64),
+          code => Err(self.error(offset,
+                                 WasmErrorKind::BadValType { code }))
+        }
+    }
+
+    fn read_block_type(&mut self)
+      -> Result<Option<IrTypeId>, WasmError>
+    {
+        let offset = self.pos;
+        match self.read_u8() ? {
+          0x40 => Ok(None),
+          0x7F => Ok(Some(IrTypeId::Int32)),
+          0x7E => Ok(Some(IrTypeId::Int64)),
+          _ => Err(self.error(offset, WasmErrorKind::BadBlockType))
+        }
+    }
+
+    // Read instructions up to the `end` or `else` that
+    // closes the enclosing construct.
+    fn read_seq(&mut self, depth: u32)
+      -> Result<(Vec<Item>, SeqEnd), WasmError>
+    {
+        let mut items = Vec::new();
+        loop {
+            let offset = self.pos;
+            let code = self.read_u8() ?;
+            let op = match code {
+              0x0B => return Ok((items, SeqEnd::End)),
+              0x05 => return Ok((items, SeqEnd::Else)),
+              0x02 ..= 0x04 => {
+                if depth + 1 >= MAX_NESTING {
+                    return Err(self.error(offset,
+                                          WasmErrorKind::TooDeep));
+                }
+                let c = self.read_construct(offset, code == 0x04,
+                                            depth + 1) ?;
+                items.push(match code {
+                  0x02 => Item::Block(c),
+                  0x03 => Item::Loop(c),
+                  _ => Item::If(c)
+                });
+                continue;
+              }
+              0x01 => continue,
+              0x00 => WasmOp::Unreachable,
+              0x0C => WasmOp::Br(self.read_u32() ?),
+              0x0D => WasmOp::BrIf(self.read_u32() ?),
+              0x0E => {
+                let count = self.read_u32() ?;
+                // Each label takes at least a byte.
+                if (count as usize) > self.bytes.len() - self.pos {
+                    return Err(self.error(self.pos,
+                                          WasmErrorKind::Truncated));
+                }
+                let mut labels = Vec::with_capacity(count as usize);
+                for _ in 0 .. count {
+                    labels.push(self.read_u32() ?);
+                }
+                WasmOp::BrTable(labels, self.read_u32() ?)
+              }
+              0x0F => WasmOp::Return,
+              0x1A => WasmOp::Drop,
+              0x20 => WasmOp::LocalGet(self.read_u32() ?),
+              0x21 => WasmOp::LocalSet(self.read_u32() ?),
+              0x22 => WasmOp::LocalTee(self.read_u32() ?),
+              0x41 => WasmOp::Const32(self.read_s64(32) ? as u32),
+              0x42 => WasmOp::Const64(self.read_s64(64) ? as u64),
+              _ => match numeric_op(code) {
+                Some(op) => op,
+                None => return Err(self.error(offset,
+                  WasmErrorKind::UnsupportedOp { code }))
+              }
+            };
+            items.push(Item::Op { offset, op });
+        }
+    }
+
+    fn read_construct(&mut self, offset: usize, is_if: bool,
+                      depth: u32)
+      -> Result<Construct, WasmError>
+    {
+        let result = self.read_block_type() ?;
+        let (body, seq_end) = self.read_seq(depth) ?;
+        let else_body = match seq_end {
+          SeqEnd::Else if is_if => self.read_else(depth) ?,
+          SeqEnd::Else => {
+            return Err(self.error(self.pos - 1,
+                                  WasmErrorKind::UnexpectedElse));
+          }
+          SeqEnd::End => Vec::new()
+        };
+        Ok(Construct {
+            offset, result, body, else_body,
+            end: self.pos - 1,
+            assigned: Vec::new(),
+            targeted: false,
+            live_cont: false
+        })
+    }
+
+    fn read_else(&mut self, depth: u32)
+      -> Result<Vec<Item>, WasmError>
+    {
+        let (body, seq_end) = self.read_seq(depth) ?;
+        if seq_end == SeqEnd::Else {
+            return Err(self.error(self.pos - 1,
+                                  WasmErrorKind::UnexpectedElse));
+        }
+        Ok(body)
+    }
+}
+
+fn numeric_op(code: u8) -> Option<WasmOp> {
+    use crate::ir_types::IrTypeId::{ Int32, Int64 };
+    let op = match code {
+      0x45 => WasmOp::Eqz(Int32),
+      0x46 => WasmOp::Cmp(CmpKind::Eq, Int32),
+      0x47 => WasmOp::Cmp(CmpKind::Ne, Int32),
+      0x48 => WasmOp::Cmp(CmpKind::Lt, Int32),
+      0x4A => WasmOp::Cmp(CmpKind::Gt, Int32),
+      0x4C => WasmOp::Cmp(CmpKind::Le, Int32),
+      0x4E => WasmOp::Cmp(CmpKind::Ge, Int32),
+      0x50 => WasmOp::Eqz(Int64),
+      0x51 => WasmOp::Cmp(CmpKind::Eq, Int64),
+      0x52 => WasmOp::Cmp(CmpKind::Ne, Int64),
+      0x53 => WasmOp::Cmp(CmpKind::Lt, Int64),
+      0x55 => WasmOp::Cmp(CmpKind::Gt, Int64),
+      0x57 => WasmOp::Cmp(CmpKind::Le, Int64),
+      0x59 => WasmOp::Cmp(CmpKind::Ge, Int64),
+      0x6A => WasmOp::Bini(BiniKind::Add, Int32),
+      0x6B => WasmOp::Bini(BiniKind::Sub, Int32),
+      0x6C => WasmOp::Bini(BiniKind::Mul, Int32),
+      0x71 => WasmOp::Bini(BiniKind::And, Int32),
+      0x72 => WasmOp::Bini(BiniKind::Or, Int32),
+      0x73 => WasmOp::Bini(BiniKind::Xor, Int32),
+      0x7C => WasmOp::Bini(BiniKind::Add, Int64),
+      0x7D => WasmOp::Bini(BiniKind::Sub, Int64),
+      0x7E => WasmOp::Bini(BiniKind::Mul, Int64),
+      0x83 => WasmOp::Bini(BiniKind::And, Int64),
+      0x84 => WasmOp::Bini(BiniKind::Or, Int64),
+      0x85 => WasmOp::Bini(BiniKind::Xor, Int64),
+      _ => return None
+    };
+    Some(op)
+}
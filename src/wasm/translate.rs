@@ -0,0 +1,505 @@
+
+use crate::block::{ BlockId, BlockRef };
+use crate::instr::InstrId;
+use crate::defn::Defn;
+use crate::graph::Graph;
+use crate::builder::{ Builder, BuildSession };
+use crate::ops::{
+    Operation, Op, CmpKind,
+    PhiOp, ParamOp, ConstOp, CmpOp, BiniOp,
+    RetOp, BranchOp, JumpOp, ExitOp
+};
+use crate::ir_types::IrTypeId;
+use crate::wasm::reader::{ FuncBody, Item, Construct, WasmOp };
+
+// A value on the operand stack or in a local.  Wasm
+// comparisons give i32 values, but are translated to
+// `Bool` ones, which are widened to `Int32` only where
+// used as integers.
+#[derive(Clone, Copy)]
+struct Value {
+    id: InstrId,
+    ty: IrTypeId
+}
+
+#[derive(Clone, Copy)]
+enum LabelKind {
+    // The function body, branches to which return.
+    Return,
+
+    // A construct's continuation, if it is reachable.
+    Cont(Option<BlockId>),
+
+    // A loop's header.
+    Loop(BlockId)
+}
+
+#[derive(Clone, Copy)]
+struct Label<'a> {
+    kind: LabelKind,
+
+    // The locals the target block takes as parameters,
+    // followed by the construct's result if a branch
+    // carries it.
+    assigned: &'a [u32],
+    result: Option<IrTypeId>
+}
+
+struct Translator<'a> {
+    local_types: &'a [IrTypeId],
+    locals: Vec<Value>,
+    stack: Vec<Value>,
+    labels: Vec<Label<'a>>,
+    result: IrTypeId
+}
+
+// Translate a checked body.
+pub(crate) fn translate_body(func: &FuncBody, num_params: usize,
+                             result: IrTypeId)
+  -> Graph
+{
+    let mut tr = Translator {
+        local_types: &func.locals,
+        locals: Vec::with_capacity(func.locals.len()),
+        stack: Vec::new(),
+        labels: vec![Label {
+            kind: LabelKind::Return, assigned: &[], result: Some(result)
+        }],
+        result
+    };
+    let builder = Builder::build(|bs| {
+        tr.start(bs, num_params);
+        if tr.seq(bs, &func.code) {
+            let label = tr.labels[0];
+            tr.jump_to(bs, label);
+        }
+    });
+    builder.into_graph()
+}
+
+fn cont_phis(c: &Construct) -> u32 {
+    (c.assigned.len() + c.result.iter().count()) as u32
+}
+
+impl<'a> Translator<'a> {
+    fn emit<'x>(&mut self, bs: &mut BuildSession<'x>, op: Op,
+                inputs: &[Value])
+      -> Value
+    {
+        let defns: Vec<Defn> =
+          inputs.iter().map(|v| Defn::new(v.id)).collect();
+        let ty = op.out_type().unwrap();
+        let def = bs.emit_op(&op, &defns);
+        Value { id: def.instr_id(), ty }
+    }
+
+    fn emit_const<'x>(&mut self, bs: &mut BuildSession<'x>,
+                      ty: IrTypeId, v: u64)
+      -> Value
+    {
+        let op = match ty {
+          IrTypeId::Int64 => ConstOp::new_int64(v),
+          _ => ConstOp::new_int32(v as u32)
+        };
+        self.emit(bs, op.op(), &[])
+    }
+
+    fn emit_end<'x>(&mut self, bs: &mut BuildSession<'x>, op: Op,
+                    inputs: &[Value],
+                    targets: &[(BlockId, Vec<InstrId>)])
+    {
+        let defns: Vec<Defn> =
+          inputs.iter().map(|v| Defn::new(v.id)).collect();
+        let args: Vec<Vec<Defn>> = targets.iter().map(|(_, a)| {
+            a.iter().map(|&id| Defn::new(id)).collect()
+        }).collect();
+        let targets: Vec<(BlockRef, &[Defn])> =
+          targets.iter().zip(&args).map(|((b, _), a)| {
+              (BlockRef::new(*b), &a[..])
+          }).collect();
+        bs.emit_op_end(&op, &defns, &targets);
+    }
+
+    // Emit the parameters and zero the declared locals.
+    fn start<'x>(&mut self, bs: &mut BuildSession<'x>,
+                 num_params: usize)
+    {
+        let types = self.local_types;
+        for (i, &ty) in types[.. num_params].iter().enumerate() {
+            let v = self.emit(bs, ParamOp::new(i as u32, ty).op(), &[]);
+            self.locals.push(v);
+        }
+        let mut zeros: Vec<Value> = Vec::new();
+        for &ty in &types[num_params ..] {
+            let zero = match zeros.iter().find(|z| z.ty == ty) {
+              Some(&zero) => zero,
+              None => {
+                let zero = self.emit_const(bs, ty, 0);
+                zeros.push(zero);
+                zero
+              }
+            };
+            self.locals.push(zero);
+        }
+    }
+
+    // Widen a `Bool` to an `Int32` of 0 or 1.
+    fn as_int<'x>(&mut self, bs: &mut BuildSession<'x>, v: Value)
+      -> Value
+    {
+        if v.ty != IrTypeId::Bool {
+            return v;
+        }
+        bs.def_subgraph(|cs| {
+            let t = cs.decl_plain_block(0).id();
+            let f = cs.decl_plain_block(0).id();
+            let join = cs.decl_plain_block(1).id();
+            self.emit_end(cs, BranchOp::new().op(), &[v],
+                          &[(t, vec![]), (f, vec![])]);
+            for &(b, n) in &[(t, 1), (f, 0)] {
+                cs.def_block(BlockRef::new(b));
+                let c = self.emit_const(cs, IrTypeId::Int32, n);
+                self.emit_end(cs, JumpOp::new().op(), &[],
+                              &[(join, vec![c.id])]);
+            }
+            cs.def_block(BlockRef::new(join));
+            self.emit(cs, PhiOp::new(IrTypeId::Int32).op(), &[])
+        })
+    }
+
+    // Narrow an `Int32` to a `Bool` true when non-zero.
+    fn as_cond<'x>(&mut self, bs: &mut BuildSession<'x>, v: Value)
+      -> Value
+    {
+        if v.ty == IrTypeId::Bool {
+            return v;
+        }
+        let zero = self.emit_const(bs, v.ty, 0);
+        let op = CmpOp::new(CmpKind::Ne, v.ty).op();
+        self.emit(bs, op, &[v, zero])
+    }
+
+    fn pop_int<'x>(&mut self, bs: &mut BuildSession<'x>) -> Value {
+        let v = self.stack.pop().unwrap();
+        self.as_int(bs, v)
+    }
+
+    // The arguments a branch to the label passes.  A
+    // carried result is widened in place on the stack.
+    fn branch_args<'x>(&mut self, bs: &mut BuildSession<'x>,
+                       label: Label<'a>)
+      -> Vec<InstrId>
+    {
+        let mut args: Vec<InstrId> = label.assigned.iter().map(|&i| {
+            self.locals[i as usize].id
+        }).collect();
+        if label.result.is_some() {
+            let top = self.stack.pop().unwrap();
+            let top = self.as_int(bs, top);
+            self.stack.push(top);
+            args.push(top.id);
+        }
+        args
+    }
+
+    fn label(&self, depth: u32) -> Label<'a> {
+        self.labels[self.labels.len() - 1 - depth as usize]
+    }
+
+    fn target(label: Label) -> BlockId {
+        match label.kind {
+          LabelKind::Cont(Some(b)) | LabelKind::Loop(b) => b,
+          _ => panic!("branch to an unreachable label")
+        }
+    }
+
+    // End the current block with a branch to the label.
+    fn jump_to<'x>(&mut self, bs: &mut BuildSession<'x>,
+                   label: Label<'a>)
+    {
+        let args = self.branch_args(bs, label);
+        if let LabelKind::Return = label.kind {
+            let ret = RetOp::new(self.result).op();
+            let v = Value { id: args[0], ty: self.result };
+            self.emit_end(bs, ret, &[v], &[]);
+            return;
+        }
+        let target = Translator::target(label);
+        self.emit_end(bs, JumpOp::new().op(), &[], &[(target, args)]);
+    }
+
+    // Branch to the label when `cond` holds, leaving the
+    // session in a new block otherwise.
+    fn branch_if<'x>(&mut self, bs: &mut BuildSession<'x>,
+                     cond: Value, label: Label<'a>)
+    {
+        bs.def_subgraph(|cs| {
+            let args = self.branch_args(cs, label);
+            let ret = match label.kind {
+              LabelKind::Return => Some(cs.decl_plain_block(0).id()),
+              _ => None
+            };
+            let next = cs.decl_plain_block(0).id();
+            let taken = match ret {
+              Some(b) => (b, vec![]),
+              None => (Translator::target(label), args.clone())
+            };
+            self.emit_end(cs, BranchOp::new().op(), &[cond],
+                          &[taken, (next, vec![])]);
+            if let Some(b) = ret {
+                cs.def_block(BlockRef::new(b));
+                let v = Value { id: args[0], ty: self.result };
+                let ret = RetOp::new(self.result).op();
+                self.emit_end(cs, ret, &[v], &[]);
+            }
+            cs.def_block(BlockRef::new(next));
+        })
+    }
+
+    // Emit the parameters of a continuation or loop
+    // header, which take the place of the assigned
+    // locals and the construct's result.
+    fn enter_params<'x>(&mut self, bs: &mut BuildSession<'x>,
+                        assigned: &[u32], result: Option<IrTypeId>)
+    {
+        for &i in assigned {
+            let ty = self.local_types[i as usize];
+            let phi = self.emit(bs, PhiOp::new(ty).op(), &[]);
+            self.locals[i as usize] = phi;
+        }
+        if let Some(ty) = result {
+            let phi = self.emit(bs, PhiOp::new(ty).op(), &[]);
+            self.stack.push(phi);
+        }
+    }
+
+    // Translate a sequence, returning whether its end is
+    // reachable, with the current block unfinished.
+    fn seq<'x>(&mut self, bs: &mut BuildSession<'x>,
+               items: &'a [Item])
+      -> bool
+    {
+        for item in items {
+            let reachable = match item {
+              Item::Op { op, .. } => self.op(bs, op),
+              Item::Block(c) => self.block(bs, c),
+              // A loop nothing branches back to is built
+              // as a plain block, as the graph's loops
+              // must have back edges.
+              Item::Loop(c) if !c.targeted => self.block(bs, c),
+              Item::Loop(c) => self.do_loop(bs, c),
+              Item::If(c) => self.if_else(bs, c)
+            };
+            if !reachable {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn cont_label(c: &'a Construct, block: Option<BlockId>)
+      -> Label<'a>
+    {
+        Label {
+            kind: LabelKind::Cont(block),
+            assigned: &c.assigned,
+            result: c.result
+        }
+    }
+
+    // Enter a construct's continuation, if it is
+    // reachable.
+    fn enter_cont<'x>(&mut self, bs: &mut BuildSession<'x>,
+                      c: &'a Construct, cont: Option<BlockId>,
+                      height: usize)
+      -> bool
+    {
+        self.stack.truncate(height);
+        match cont {
+          Some(b) => {
+            bs.def_block(BlockRef::new(b));
+            self.enter_params(bs, &c.assigned, c.result);
+            true
+          }
+          None => false
+        }
+    }
+
+    fn block<'x>(&mut self, bs: &mut BuildSession<'x>,
+                 c: &'a Construct)
+      -> bool
+    {
+        let height = self.stack.len();
+        let cont = if c.live_cont {
+            Some(bs.decl_plain_block(cont_phis(c)).id())
+        } else {
+            None
+        };
+        let label = Translator::cont_label(c, cont);
+        self.labels.push(label);
+        bs.def_subgraph(|cs| {
+            if self.seq(cs, &c.body) {
+                self.jump_to(cs, label);
+            }
+        });
+        self.labels.pop();
+        self.enter_cont(bs, c, cont, height)
+    }
+
+    fn do_loop<'x>(&mut self, bs: &mut BuildSession<'x>,
+                   c: &'a Construct)
+      -> bool
+    {
+        let height = self.stack.len();
+        let header = bs.decl_loop_head(c.assigned.len() as u32);
+        let after = if c.live_cont {
+            Some(bs.decl_plain_block(cont_phis(c)).id())
+        } else {
+            None
+        };
+        let entry = Label {
+            kind: LabelKind::Loop(header.id()),
+            assigned: &c.assigned,
+            result: None
+        };
+        self.jump_to(bs, entry);
+
+        bs.def_loop(header, |cs| {
+            self.enter_params(cs, &c.assigned, None);
+            self.labels.push(entry);
+            let falls = self.seq(cs, &c.body);
+            self.labels.pop();
+            if falls {
+                self.jump_to(cs, Translator::cont_label(c, after));
+            }
+        });
+        self.enter_cont(bs, c, after, height)
+    }
+
+    fn if_else<'x>(&mut self, bs: &mut BuildSession<'x>,
+                   c: &'a Construct)
+      -> bool
+    {
+        let cond = self.stack.pop().unwrap();
+        let cond = self.as_cond(bs, cond);
+        let height = self.stack.len();
+        let then_block = bs.decl_plain_block(0).id();
+        let else_block = bs.decl_plain_block(0).id();
+        let cont = if c.live_cont {
+            Some(bs.decl_plain_block(cont_phis(c)).id())
+        } else {
+            None
+        };
+        self.emit_end(bs, BranchOp::new().op(), &[cond],
+                      &[(then_block, vec![]), (else_block, vec![])]);
+
+        let label = Translator::cont_label(c, cont);
+        self.labels.push(label);
+        let entry_locals = self.locals.clone();
+        for &(block, body) in &[(then_block, &c.body),
+                                (else_block, &c.else_body)]
+        {
+            self.locals.clone_from(&entry_locals);
+            self.stack.truncate(height);
+            bs.def_block(BlockRef::new(block));
+            bs.def_subgraph(|cs| {
+                if self.seq(cs, body) {
+                    self.jump_to(cs, label);
+                }
+            });
+        }
+        self.labels.pop();
+        self.enter_cont(bs, c, cont, height)
+    }
+
+    // Translate an instruction, returning whether the
+    // code after it is reachable.
+    fn op<'x>(&mut self, bs: &mut BuildSession<'x>, op: &WasmOp)
+      -> bool
+    {
+        match *op {
+          WasmOp::Unreachable => {
+            self.emit_end(bs, ExitOp::new().op(), &[], &[]);
+            return false;
+          }
+          WasmOp::Br(depth) => {
+            let label = self.label(depth);
+            self.jump_to(bs, label);
+            return false;
+          }
+          WasmOp::BrIf(depth) => {
+            let cond = self.stack.pop().unwrap();
+            let cond = self.as_cond(bs, cond);
+            let label = self.label(depth);
+            self.branch_if(bs, cond, label);
+          }
+          WasmOp::BrTable(ref depths, default) => {
+            // A chain of comparisons against each index.
+            let index = self.pop_int(bs);
+            for (i, &depth) in depths.iter().enumerate() {
+                let k = self.emit_const(bs, IrTypeId::Int32, i as u64);
+                let op = CmpOp::new(CmpKind::Eq, IrTypeId::Int32).op();
+                let cond = self.emit(bs, op, &[index, k]);
+                let label = self.label(depth);
+                self.branch_if(bs, cond, label);
+            }
+            let label = self.label(default);
+            self.jump_to(bs, label);
+            return false;
+          }
+          WasmOp::Return => {
+            let label = self.labels[0];
+            self.jump_to(bs, label);
+            return false;
+          }
+          WasmOp::Drop => { self.stack.pop(); }
+          WasmOp::LocalGet(index) => {
+            self.stack.push(self.locals[index as usize]);
+          }
+          WasmOp::LocalSet(index) | WasmOp::LocalTee(index) => {
+            let v = self.pop_int(bs);
+            self.locals[index as usize] = v;
+            if let WasmOp::LocalTee(_) = *op {
+                self.stack.push(v);
+            }
+          }
+          WasmOp::Const32(i) => {
+            let v = self.emit_const(bs, IrTypeId::Int32, i as u64);
+            self.stack.push(v);
+          }
+          WasmOp::Const64(i) => {
+            let v = self.emit_const(bs, IrTypeId::Int64, i);
+            self.stack.push(v);
+          }
+          WasmOp::Eqz(_) => {
+            // A `Bool` is compared against false rather
+            // than widened.
+            let v = self.stack.pop().unwrap();
+            let zero = match v.ty {
+              IrTypeId::Bool => {
+                self.emit(bs, ConstOp::new_bool(false).op(), &[])
+              }
+              ty => self.emit_const(bs, ty, 0)
+            };
+            let op = CmpOp::new(CmpKind::Eq, v.ty).op();
+            let r = self.emit(bs, op, &[v, zero]);
+            self.stack.push(r);
+          }
+          WasmOp::Cmp(kind, ty) => {
+            let rhs = self.pop_int(bs);
+            let lhs = self.pop_int(bs);
+            let op = CmpOp::new(kind, ty).op();
+            let r = self.emit(bs, op, &[lhs, rhs]);
+            self.stack.push(r);
+          }
+          WasmOp::Bini(kind, ty) => {
+            let rhs = self.pop_int(bs);
+            let lhs = self.pop_int(bs);
+            let op = BiniOp::new(kind, ty).op();
+            let r = self.emit(bs, op, &[lhs, rhs]);
+            self.stack.push(r);
+          }
+        }
+        true
+    }
+}